@@ -517,6 +517,105 @@ impl Packet for ClientboundPacket {
 
         }
     }
+    fn id_by_name(state: &ClientState, name: &str) -> Option<i32> {
+        match (state, name) {
+        (&ClientState::Status, "StatusResponse") => Some(0),
+        (&ClientState::Status, "StatusPong") => Some(1),
+        (&ClientState::Login, "LoginDisconnect") => Some(0),
+        (&ClientState::Login, "EncryptionRequest") => Some(1),
+        (&ClientState::Login, "LoginSuccess") => Some(2),
+        (&ClientState::Login, "SetCompression") => Some(3),
+        (&ClientState::Login, "LoginPluginRequest") => Some(4),
+        (&ClientState::Play, "SpawnObject") => Some(0),
+        (&ClientState::Play, "SpawnExperienceOrb") => Some(1),
+        (&ClientState::Play, "SpawnGlobalEntity") => Some(2),
+        (&ClientState::Play, "SpawnMob") => Some(3),
+        (&ClientState::Play, "SpawnPainting") => Some(4),
+        (&ClientState::Play, "SpawnPlayer") => Some(5),
+        (&ClientState::Play, "ClientboundAnimation") => Some(6),
+        (&ClientState::Play, "Statistics") => Some(7),
+        (&ClientState::Play, "BlockBreakAnimation") => Some(8),
+        (&ClientState::Play, "UpdateBlockEntity") => Some(9),
+        (&ClientState::Play, "BlockAction") => Some(10),
+        (&ClientState::Play, "BlockChange") => Some(11),
+        (&ClientState::Play, "BossBar") => Some(12),
+        (&ClientState::Play, "ServerDifficulty") => Some(13),
+        (&ClientState::Play, "ChatMessage") => Some(14),
+        (&ClientState::Play, "MultiBlockChange") => Some(15),
+        (&ClientState::Play, "ClientboundTabComplete") => Some(16),
+        (&ClientState::Play, "DeclareCommands") => Some(17),
+        (&ClientState::Play, "ClientboundConfirmTransaction") => Some(18),
+        (&ClientState::Play, "ClientboundCloseWindow") => Some(19),
+        (&ClientState::Play, "OpenWindow") => Some(20),
+        (&ClientState::Play, "WindowItems") => Some(21),
+        (&ClientState::Play, "WindowProperty") => Some(22),
+        (&ClientState::Play, "SetSlot") => Some(23),
+        (&ClientState::Play, "SetCooldown") => Some(24),
+        (&ClientState::Play, "ClientboundPluginMessage") => Some(25),
+        (&ClientState::Play, "NamedSoundEffect") => Some(26),
+        (&ClientState::Play, "PlayDisconnect") => Some(27),
+        (&ClientState::Play, "EntityStatus") => Some(28),
+        (&ClientState::Play, "NBTQueryResponse") => Some(29),
+        (&ClientState::Play, "Explosion") => Some(30),
+        (&ClientState::Play, "UnloadChunk") => Some(31),
+        (&ClientState::Play, "ChangeGameState") => Some(32),
+        (&ClientState::Play, "KeepAlive") => Some(33),
+        (&ClientState::Play, "ChunkData") => Some(34),
+        (&ClientState::Play, "Effect") => Some(35),
+        (&ClientState::Play, "Particle") => Some(36),
+        (&ClientState::Play, "JoinGame") => Some(37),
+        (&ClientState::Play, "Map") => Some(38),
+        (&ClientState::Play, "Entity") => Some(39),
+        (&ClientState::Play, "EntityRelativeMove") => Some(40),
+        (&ClientState::Play, "EntityLookRelativeMove") => Some(41),
+        (&ClientState::Play, "EntityLook") => Some(42),
+        (&ClientState::Play, "ClientboundVehicleMove") => Some(43),
+        (&ClientState::Play, "OpenSignEditor") => Some(44),
+        (&ClientState::Play, "CraftRecipeResponse") => Some(45),
+        (&ClientState::Play, "PlayerAbilities") => Some(46),
+        (&ClientState::Play, "CombatEvent") => Some(47),
+        (&ClientState::Play, "PlayerListItem") => Some(48),
+        (&ClientState::Play, "FacePlayer") => Some(49),
+        (&ClientState::Play, "PlayerPositionAndLook") => Some(50),
+        (&ClientState::Play, "UseBed") => Some(51),
+        (&ClientState::Play, "UnlockRecipes") => Some(52),
+        (&ClientState::Play, "DestroyEntities") => Some(53),
+        (&ClientState::Play, "RemoveEntityEffect") => Some(54),
+        (&ClientState::Play, "ResourcePackSend") => Some(55),
+        (&ClientState::Play, "Respawn") => Some(56),
+        (&ClientState::Play, "EntityHeadLook") => Some(57),
+        (&ClientState::Play, "SelectAdvancementTab") => Some(58),
+        (&ClientState::Play, "WorldBorder") => Some(59),
+        (&ClientState::Play, "Camera") => Some(60),
+        (&ClientState::Play, "ClientboundHeldItemChange") => Some(61),
+        (&ClientState::Play, "DisplayScoreboard") => Some(62),
+        (&ClientState::Play, "EntityMetadata") => Some(63),
+        (&ClientState::Play, "AttachEntity") => Some(64),
+        (&ClientState::Play, "EntityVelocity") => Some(65),
+        (&ClientState::Play, "EntityEquipment") => Some(66),
+        (&ClientState::Play, "SetExperience") => Some(67),
+        (&ClientState::Play, "UpdateHealth") => Some(68),
+        (&ClientState::Play, "ScoreboardObjective") => Some(69),
+        (&ClientState::Play, "SetPassengers") => Some(70),
+        (&ClientState::Play, "Teams") => Some(71),
+        (&ClientState::Play, "UpdateScore") => Some(72),
+        (&ClientState::Play, "SpawnPosition") => Some(73),
+        (&ClientState::Play, "TimeUpdate") => Some(74),
+        (&ClientState::Play, "Title") => Some(75),
+        (&ClientState::Play, "StopSound") => Some(76),
+        (&ClientState::Play, "SoundEffect") => Some(77),
+        (&ClientState::Play, "PlayerListHeaderFooter") => Some(78),
+        (&ClientState::Play, "CollectItem") => Some(79),
+        (&ClientState::Play, "EntityTeleport") => Some(80),
+        (&ClientState::Play, "Advancements") => Some(81),
+        (&ClientState::Play, "EntityProperties") => Some(82),
+        (&ClientState::Play, "EntityEffect") => Some(83),
+        (&ClientState::Play, "DeclareRecipes") => Some(84),
+        (&ClientState::Play, "Tags") => Some(85),
+
+        _ => None,
+        }
+    }
     fn to_u8(&self) -> Result<Vec<u8>> {
         match self {
         &ClientboundPacket::StatusResponse(ref x) => x.to_u8(),
@@ -1508,7 +1508,7 @@ impl SetCooldown {
 
 #[derive(Debug, PartialEq, Clone)]
 pub struct ClientboundPluginMessage {
-    channel: String,
+    channel: SmallString,
     data: Vec<u8>,
 }
 
@@ -1517,7 +1517,7 @@ impl ClientboundPluginMessage {
     /// Deserializes a Read type into a packet. You usually won't need to use this.
     pub fn deserialize<R: Read>(r: &mut R) -> Result<ClientboundPacket> {
         Ok(ClientboundPacket::ClientboundPluginMessage(ClientboundPluginMessage {
-            channel: read_String(r)?,
+            channel: read_SmallString(r)?,
             data: read_bytearray_to_end(r)?,
 
         }))
@@ -1526,19 +1526,19 @@ impl ClientboundPluginMessage {
     pub fn to_u8(&self) -> Result<Vec<u8>> {
         let mut ret = Vec::new();
         write_varint(&Self::PACKET_ID, &mut ret)?;
-        write_String(&self.channel, &mut ret)?;
+        write_SmallString(&self.channel, &mut ret)?;
         write_bytearray_to_end(&self.data, &mut ret)?;
 
         Ok(ret)
     }
-    pub fn new(channel: String, data: Vec<u8>) -> ClientboundPacket {
+    pub fn new(channel: SmallString, data: Vec<u8>) -> ClientboundPacket {
         ClientboundPacket::ClientboundPluginMessage(ClientboundPluginMessage {
             channel: channel,
             data: data,
         })
     }
     /// Get the plugin channel
-    pub fn get_channel(&self) -> &String {
+    pub fn get_channel(&self) -> &SmallString {
         &self.channel
     }    /// Get the raw data
     pub fn get_data(&self) -> &Vec<u8> {
@@ -1548,7 +1548,7 @@ impl ClientboundPluginMessage {
 
 #[derive(Debug, PartialEq, Clone)]
 pub struct NamedSoundEffect {
-    sound_name: String,
+    sound_name: SmallString,
     sound_category: i32,
     x: i32,
     y: i32,
@@ -1562,7 +1562,7 @@ impl NamedSoundEffect {
     /// Deserializes a Read type into a packet. You usually won't need to use this.
     pub fn deserialize<R: Read>(r: &mut R) -> Result<ClientboundPacket> {
         Ok(ClientboundPacket::NamedSoundEffect(NamedSoundEffect {
-            sound_name: read_String(r)?,
+            sound_name: read_SmallString(r)?,
             sound_category: read_varint(r)?,
             x: read_i32(r)?,
             y: read_i32(r)?,
@@ -1576,7 +1576,7 @@ impl NamedSoundEffect {
     pub fn to_u8(&self) -> Result<Vec<u8>> {
         let mut ret = Vec::new();
         write_varint(&Self::PACKET_ID, &mut ret)?;
-        write_String(&self.sound_name, &mut ret)?;
+        write_SmallString(&self.sound_name, &mut ret)?;
         write_varint(&self.sound_category, &mut ret)?;
         write_i32(&self.x, &mut ret)?;
         write_i32(&self.y, &mut ret)?;
@@ -1586,7 +1586,7 @@ impl NamedSoundEffect {
 
         Ok(ret)
     }
-    pub fn new(sound_name: String, sound_category: i32, x: i32, y: i32, z: i32, volume: f32, pitch: f32) -> ClientboundPacket {
+    pub fn new(sound_name: SmallString, sound_category: i32, x: i32, y: i32, z: i32, volume: f32, pitch: f32) -> ClientboundPacket {
         ClientboundPacket::NamedSoundEffect(NamedSoundEffect {
             sound_name: sound_name,
             sound_category: sound_category,
@@ -1598,7 +1598,7 @@ impl NamedSoundEffect {
         })
     }
     /// Get the name of the sound
-    pub fn get_sound_name(&self) -> &String {
+    pub fn get_sound_name(&self) -> &SmallString {
         &self.sound_name
     }    /// Get the category of the sound
     pub fn get_sound_category(&self) -> &i32 {
@@ -2066,7 +2066,7 @@ pub struct JoinGame {
     dimension: i32,
     difficulty: u8,
     max_players: u8,
-    level_type: String,
+    level_type: SmallString,
     reduced_debug: bool,
 }
 
@@ -2080,7 +2080,7 @@ impl JoinGame {
             dimension: read_i32(r)?,
             difficulty: read_u8(r)?,
             max_players: read_u8(r)?,
-            level_type: read_String(r)?,
+            level_type: read_SmallString(r)?,
             reduced_debug: read_bool(r)?,
 
         }))
@@ -2094,12 +2094,12 @@ impl JoinGame {
         write_i32(&self.dimension, &mut ret)?;
         write_u8(&self.difficulty, &mut ret)?;
         write_u8(&self.max_players, &mut ret)?;
-        write_String(&self.level_type, &mut ret)?;
+        write_SmallString(&self.level_type, &mut ret)?;
         write_bool(&self.reduced_debug, &mut ret)?;
 
         Ok(ret)
     }
-    pub fn new(entity_id: i32, gamemode: u8, dimension: i32, difficulty: u8, max_players: u8, level_type: String, reduced_debug: bool) -> ClientboundPacket {
+    pub fn new(entity_id: i32, gamemode: u8, dimension: i32, difficulty: u8, max_players: u8, level_type: SmallString, reduced_debug: bool) -> ClientboundPacket {
         ClientboundPacket::JoinGame(JoinGame {
             entity_id: entity_id,
             gamemode: gamemode,
@@ -2126,7 +2126,7 @@ impl JoinGame {
     pub fn get_max_players(&self) -> &u8 {
         &self.max_players
     }    /// Get the level type of the world the player joined in
-    pub fn get_level_type(&self) -> &String {
+    pub fn get_level_type(&self) -> &SmallString {
         &self.level_type
     }    /// Get whether to show reduced debug info
     pub fn get_reduced_debug(&self) -> &bool {
@@ -2395,8 +2395,8 @@ pub struct ClientboundVehicleMove {
     x: f64,
     y: f64,
     z: f64,
-    yaw: i8,
-    pitch: i8,
+    yaw: f32,
+    pitch: f32,
 }
 
 impl ClientboundVehicleMove {
@@ -2407,8 +2407,8 @@ impl ClientboundVehicleMove {
             x: read_f64(r)?,
             y: read_f64(r)?,
             z: read_f64(r)?,
-            yaw: read_i8(r)?,
-            pitch: read_i8(r)?,
+            yaw: read_f32(r)?,
+            pitch: read_f32(r)?,
 
         }))
     }
@@ -2419,12 +2419,12 @@ impl ClientboundVehicleMove {
         write_f64(&self.x, &mut ret)?;
         write_f64(&self.y, &mut ret)?;
         write_f64(&self.z, &mut ret)?;
-        write_i8(&self.yaw, &mut ret)?;
-        write_i8(&self.pitch, &mut ret)?;
+        write_f32(&self.yaw, &mut ret)?;
+        write_f32(&self.pitch, &mut ret)?;
 
         Ok(ret)
     }
-    pub fn new(x: f64, y: f64, z: f64, yaw: i8, pitch: i8) -> ClientboundPacket {
+    pub fn new(x: f64, y: f64, z: f64, yaw: f32, pitch: f32) -> ClientboundPacket {
         ClientboundPacket::ClientboundVehicleMove(ClientboundVehicleMove {
             x: x,
             y: y,
@@ -2443,10 +2443,10 @@ impl ClientboundVehicleMove {
     pub fn get_z(&self) -> &f64 {
         &self.z
     }    /// Get the (absolute) angle
-    pub fn get_yaw(&self) -> &i8 {
+    pub fn get_yaw(&self) -> &f32 {
         &self.yaw
     }    /// Get the (absolute) pitch
-    pub fn get_pitch(&self) -> &i8 {
+    pub fn get_pitch(&self) -> &f32 {
         &self.pitch
     }
 }
@@ -2967,7 +2967,7 @@ pub struct Respawn {
     dimension: i32,
     difficulty: u8,
     gamemode: u8,
-    level_type: String,
+    level_type: SmallString,
 }
 
 impl Respawn {
@@ -2978,7 +2978,7 @@ impl Respawn {
             dimension: read_i32(r)?,
             difficulty: read_u8(r)?,
             gamemode: read_u8(r)?,
-            level_type: read_String(r)?,
+            level_type: read_SmallString(r)?,
 
         }))
     }
@@ -2989,11 +2989,11 @@ impl Respawn {
         write_i32(&self.dimension, &mut ret)?;
         write_u8(&self.difficulty, &mut ret)?;
         write_u8(&self.gamemode, &mut ret)?;
-        write_String(&self.level_type, &mut ret)?;
+        write_SmallString(&self.level_type, &mut ret)?;
 
         Ok(ret)
     }
-    pub fn new(dimension: i32, difficulty: u8, gamemode: u8, level_type: String) -> ClientboundPacket {
+    pub fn new(dimension: i32, difficulty: u8, gamemode: u8, level_type: SmallString) -> ClientboundPacket {
         ClientboundPacket::Respawn(Respawn {
             dimension: dimension,
             difficulty: difficulty,
@@ -3011,7 +3011,7 @@ impl Respawn {
     pub fn get_gamemode(&self) -> &u8 {
         &self.gamemode
     }    /// Get the level type
-    pub fn get_level_type(&self) -> &String {
+    pub fn get_level_type(&self) -> &SmallString {
         &self.level_type
     }
 }
@@ -3178,7 +3178,7 @@ impl ClientboundHeldItemChange {
 #[derive(Debug, PartialEq, Clone)]
 pub struct DisplayScoreboard {
     position: u8,
-    name: String,
+    name: SmallString,
 }
 
 impl DisplayScoreboard {
@@ -3187,7 +3187,7 @@ impl DisplayScoreboard {
     pub fn deserialize<R: Read>(r: &mut R) -> Result<ClientboundPacket> {
         Ok(ClientboundPacket::DisplayScoreboard(DisplayScoreboard {
             position: read_u8(r)?,
-            name: read_String(r)?,
+            name: read_SmallString(r)?,
 
         }))
     }
@@ -3196,11 +3196,11 @@ impl DisplayScoreboard {
         let mut ret = Vec::new();
         write_varint(&Self::PACKET_ID, &mut ret)?;
         write_u8(&self.position, &mut ret)?;
-        write_String(&self.name, &mut ret)?;
+        write_SmallString(&self.name, &mut ret)?;
 
         Ok(ret)
     }
-    pub fn new(position: u8, name: String) -> ClientboundPacket {
+    pub fn new(position: u8, name: SmallString) -> ClientboundPacket {
         ClientboundPacket::DisplayScoreboard(DisplayScoreboard {
             position: position,
             name: name,
@@ -3210,7 +3210,7 @@ impl DisplayScoreboard {
     pub fn get_position(&self) -> &u8 {
         &self.position
     }    /// Get the name of the scoreboard
-    pub fn get_name(&self) -> &String {
+    pub fn get_name(&self) -> &SmallString {
         &self.name
     }
 }
@@ -3600,9 +3600,9 @@ impl Teams {
 
 #[derive(Debug, PartialEq, Clone)]
 pub struct UpdateScore {
-    name: String,
+    name: SmallString,
     action: u8,
-    objective_name: String,
+    objective_name: SmallString,
     value: Option<i32>,
 }
 
@@ -3610,7 +3610,7 @@ impl UpdateScore {
     const PACKET_ID: i32 = 72;
 
 
-    pub fn new_raw(name: String, action: u8, objective_name: String, value: Option<i32>) -> ClientboundPacket {
+    pub fn new_raw(name: SmallString, action: u8, objective_name: SmallString, value: Option<i32>) -> ClientboundPacket {
         ClientboundPacket::UpdateScore(UpdateScore {
             name: name,
             action: action,
@@ -3619,13 +3619,13 @@ impl UpdateScore {
         })
     }
     /// Get the name of the score to be updated
-    pub fn get_name(&self) -> &String {
+    pub fn get_name(&self) -> &SmallString {
         &self.name
     }    /// Get the action being performed
     pub fn get_action(&self) -> &u8 {
         &self.action
     }    /// Get the name of the objective the score belongs to
-    pub fn get_objective_name(&self) -> &String {
+    pub fn get_objective_name(&self) -> &SmallString {
         &self.objective_name
     }    /// Get the score to be displayed if this packet is updating a score, else `None`
     pub fn get_value(&self) -> &Option<i32> {
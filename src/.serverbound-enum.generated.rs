@@ -299,6 +299,61 @@ impl Packet for ServerboundPacket {
 
         }
     }
+    fn id_by_name(state: &ClientState, name: &str) -> Option<i32> {
+        match (state, name) {
+        (&ClientState::Handshake, "Handshake") => Some(0),
+        (&ClientState::Status, "StatusRequest") => Some(0),
+        (&ClientState::Status, "StatusPing") => Some(1),
+        (&ClientState::Login, "LoginStart") => Some(0),
+        (&ClientState::Login, "EncryptionResponse") => Some(1),
+        (&ClientState::Login, "LoginPluginResponse") => Some(2),
+        (&ClientState::Play, "TeleportConfirm") => Some(0),
+        (&ClientState::Play, "QueryBlockNBT") => Some(1),
+        (&ClientState::Play, "ChatMessage") => Some(2),
+        (&ClientState::Play, "ClientStatus") => Some(3),
+        (&ClientState::Play, "ClientSettings") => Some(4),
+        (&ClientState::Play, "TabComplete") => Some(5),
+        (&ClientState::Play, "ConfirmTransaction") => Some(6),
+        (&ClientState::Play, "EnchantItem") => Some(7),
+        (&ClientState::Play, "ClickWindow") => Some(8),
+        (&ClientState::Play, "CloseWindow") => Some(9),
+        (&ClientState::Play, "PluginMessage") => Some(10),
+        (&ClientState::Play, "EditBook") => Some(11),
+        (&ClientState::Play, "QueryEntityNBT") => Some(12),
+        (&ClientState::Play, "UseEntity") => Some(13),
+        (&ClientState::Play, "KeepAlive") => Some(14),
+        (&ClientState::Play, "Player") => Some(15),
+        (&ClientState::Play, "PlayerPosition") => Some(16),
+        (&ClientState::Play, "PlayerPositionAndLook") => Some(17),
+        (&ClientState::Play, "PlayerLook") => Some(18),
+        (&ClientState::Play, "VehicleMove") => Some(19),
+        (&ClientState::Play, "SteerBoat") => Some(20),
+        (&ClientState::Play, "PickItem") => Some(21),
+        (&ClientState::Play, "CraftRecipeRequest") => Some(22),
+        (&ClientState::Play, "PlayerAbilities") => Some(23),
+        (&ClientState::Play, "PlayerDigging") => Some(24),
+        (&ClientState::Play, "EntityAction") => Some(25),
+        (&ClientState::Play, "SteerVehicle") => Some(26),
+        (&ClientState::Play, "RecipeBookData") => Some(27),
+        (&ClientState::Play, "NameItem") => Some(28),
+        (&ClientState::Play, "ResourcePackStatus") => Some(29),
+        (&ClientState::Play, "AdvancementTab") => Some(30),
+        (&ClientState::Play, "SelectTrade") => Some(31),
+        (&ClientState::Play, "SetBeaconEffect") => Some(32),
+        (&ClientState::Play, "HeldItemChange") => Some(33),
+        (&ClientState::Play, "UpdateCommandBlock") => Some(34),
+        (&ClientState::Play, "UpdateCommandBlockMinecart") => Some(35),
+        (&ClientState::Play, "CreativeInventoryAction") => Some(36),
+        (&ClientState::Play, "UpdateStructureBlock") => Some(37),
+        (&ClientState::Play, "UpdateSign") => Some(38),
+        (&ClientState::Play, "Animation") => Some(39),
+        (&ClientState::Play, "Spectate") => Some(40),
+        (&ClientState::Play, "PlayerBlockPlacement") => Some(41),
+        (&ClientState::Play, "UseItem") => Some(42),
+
+        _ => None,
+        }
+    }
     fn to_u8(&self) -> Result<Vec<u8>> {
         match self {
         &ServerboundPacket::Handshake(ref x) => x.to_u8(),
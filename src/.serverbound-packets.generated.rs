@@ -365,7 +365,7 @@ impl ClientStatus {
 
 #[derive(Debug, PartialEq, Clone)]
 pub struct ClientSettings {
-    locale: String,
+    locale: SmallString,
     view_distance: u8,
     chat_mode: i32,
     chat_colors: bool,
@@ -378,7 +378,7 @@ impl ClientSettings {
     /// Deserializes a Read type into a packet. You usually won't need to use this.
     pub fn deserialize<R: Read>(r: &mut R) -> Result<ServerboundPacket> {
         Ok(ServerboundPacket::ClientSettings(ClientSettings {
-            locale: read_String(r)?,
+            locale: read_SmallString(r)?,
             view_distance: read_u8(r)?,
             chat_mode: read_varint(r)?,
             chat_colors: read_bool(r)?,
@@ -391,7 +391,7 @@ impl ClientSettings {
     pub fn to_u8(&self) -> Result<Vec<u8>> {
         let mut ret = Vec::new();
         write_varint(&Self::PACKET_ID, &mut ret)?;
-        write_String(&self.locale, &mut ret)?;
+        write_SmallString(&self.locale, &mut ret)?;
         write_u8(&self.view_distance, &mut ret)?;
         write_varint(&self.chat_mode, &mut ret)?;
         write_bool(&self.chat_colors, &mut ret)?;
@@ -400,7 +400,7 @@ impl ClientSettings {
 
         Ok(ret)
     }
-    pub fn new(locale: String, view_distance: u8, chat_mode: i32, chat_colors: bool, displayed_skin_parts: u8, main_hand: i32) -> ServerboundPacket {
+    pub fn new(locale: SmallString, view_distance: u8, chat_mode: i32, chat_colors: bool, displayed_skin_parts: u8, main_hand: i32) -> ServerboundPacket {
         ServerboundPacket::ClientSettings(ClientSettings {
             locale: locale,
             view_distance: view_distance,
@@ -411,7 +411,7 @@ impl ClientSettings {
         })
     }
     /// Get the client's locale
-    pub fn get_locale(&self) -> &String {
+    pub fn get_locale(&self) -> &SmallString {
         &self.locale
     }    /// Get the client's view distance in chunks
     pub fn get_view_distance(&self) -> &u8 {
@@ -661,7 +661,7 @@ impl CloseWindow {
 
 #[derive(Debug, PartialEq, Clone)]
 pub struct PluginMessage {
-    channel: String,
+    channel: SmallString,
     data: Vec<u8>,
 }
 
@@ -670,7 +670,7 @@ impl PluginMessage {
     /// Deserializes a Read type into a packet. You usually won't need to use this.
     pub fn deserialize<R: Read>(r: &mut R) -> Result<ServerboundPacket> {
         Ok(ServerboundPacket::PluginMessage(PluginMessage {
-            channel: read_String(r)?,
+            channel: read_SmallString(r)?,
             data: read_bytearray(r)?,
 
         }))
@@ -679,19 +679,19 @@ impl PluginMessage {
     pub fn to_u8(&self) -> Result<Vec<u8>> {
         let mut ret = Vec::new();
         write_varint(&Self::PACKET_ID, &mut ret)?;
-        write_String(&self.channel, &mut ret)?;
+        write_SmallString(&self.channel, &mut ret)?;
         write_bytearray(&self.data, &mut ret)?;
 
         Ok(ret)
     }
-    pub fn new(channel: String, data: Vec<u8>) -> ServerboundPacket {
+    pub fn new(channel: SmallString, data: Vec<u8>) -> ServerboundPacket {
         ServerboundPacket::PluginMessage(PluginMessage {
             channel: channel,
             data: data,
         })
     }
     /// Get the channel
-    pub fn get_channel(&self) -> &String {
+    pub fn get_channel(&self) -> &SmallString {
         &self.channel
     }    /// Get the data
     pub fn get_data(&self) -> &Vec<u8> {
@@ -0,0 +1,348 @@
+//! Managing the sessions of several accounts
+//!
+//! Tools running several bots need to keep the tokens of each account fresh
+//! and stored somewhere. An AccountManager holds a Session per account, be
+//! it a Mojang or a Microsoft account, refreshes them on demand and saves
+//! them all to a single file, which like the CredentialStore can be
+//! encrypted with a passphrase.
+//!
+//! Accounts are keyed by any name you like, and can also be looked up by the
+//! player name or UUID of their profile.
+//!
+//! # Examples
+//!
+//! ```rust,no_run
+//! use ozelot::accounts::AccountManager;
+//! use ozelot::Client;
+//!
+//! let mut accounts = AccountManager::open_encrypted("bots.json",
+//!                                                   "my passphrase").unwrap();
+//! for (account, e) in accounts.refresh_all() {
+//!     println!("Unable to refresh {}: {}", account, e);
+//! }
+//! accounts.save().unwrap();
+//!
+//! let account = accounts.find("Notch").unwrap().to_string();
+//! let auth = accounts.get(&account).unwrap()
+//!     .to_authentication_response().unwrap();
+//! let mut client = Client::connect_authenticated("minecraft.example.com",
+//!                                                25565,
+//!                                                &auth).unwrap();
+//! ```
+
+use credentials::{self, EncryptedAccounts};
+use errors::{Error, Result, ResultExt};
+use json::{AuthenticationResponse, NameUUID};
+use msa::MsaSession;
+use uuid;
+
+use serde_json;
+
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io::{self, Read};
+use std::path::{Path, PathBuf};
+
+/// The session of a single account
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum Session {
+    /// A Mojang account, as returned by Authenticate
+    Mojang(AuthenticationResponse),
+    /// A Microsoft account
+    Msa(MsaSession),
+}
+impl Session {
+    /// Get the profile (UUID and name) of the account, if known
+    pub fn profile(&self) -> Option<&NameUUID> {
+        match *self {
+            Session::Mojang(ref auth) => Some(&auth.selectedProfile),
+            Session::Msa(ref session) => session.token().map(|x| &x.profile),
+        }
+    }
+
+    /// Refresh the tokens if needed, returning whether they were refreshed.
+    ///
+    /// Mojang tokens are validated with the authserver and refreshed if
+    /// invalid, which requires the client token. Microsoft tokens are
+    /// refreshed when they're about to expire.
+    pub fn ensure_fresh(&mut self) -> Result<bool> {
+        match *self {
+            Session::Mojang(ref mut auth) => credentials::ensure_valid(auth),
+            Session::Msa(ref mut session) => session.ensure_fresh(),
+        }
+    }
+
+    /// Unconditionally refresh the tokens
+    pub fn refresh(&mut self) -> Result<()> {
+        match *self {
+            Session::Mojang(ref mut auth) => {
+                *auth = credentials::refresh(auth)?;
+                Ok(())
+            },
+            Session::Msa(ref mut session) => session.refresh(),
+        }
+    }
+
+    /// Get the tokens as an AuthenticationResponse, for use with
+    /// Client::connect_authenticated()
+    pub fn to_authentication_response(&self) -> Option<AuthenticationResponse> {
+        match *self {
+            Session::Mojang(ref auth) => Some(auth.clone()),
+            Session::Msa(ref session) => session.to_authentication_response(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+struct Accounts {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    selected: Option<String>,
+    sessions: BTreeMap<String, Session>,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct AccountsFile {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    accounts: Option<Accounts>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    encrypted: Option<EncryptedAccounts>,
+}
+
+/// The sessions of several accounts, see the module documentation
+#[derive(Debug)]
+pub struct AccountManager {
+    path: Option<PathBuf>,
+    passphrase: Option<String>,
+    accounts: Accounts,
+}
+impl AccountManager {
+    /// Create an empty manager that isn't saved to disk
+    pub fn new() -> Self {
+        AccountManager {
+            path: None,
+            passphrase: None,
+            accounts: Accounts::default(),
+        }
+    }
+
+    /// Open the unencrypted accounts file at the given path. If the file
+    /// doesn't exist, the manager starts out empty.
+    ///
+    /// Returns an error if the file is encrypted.
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Self> {
+        AccountManager::open_inner(path.as_ref(), None)
+    }
+
+    /// Open the accounts file at the given path, encrypted with the given
+    /// passphrase. If the file doesn't exist, the manager starts out empty.
+    pub fn open_encrypted<P: AsRef<Path>>(path: P, passphrase: &str) -> Result<Self> {
+        AccountManager::open_inner(path.as_ref(), Some(passphrase))
+    }
+
+    fn open_inner(path: &Path, passphrase: Option<&str>) -> Result<Self> {
+        let file = match File::open(path) {
+            Ok(mut f) => {
+                let mut contents = String::new();
+                let _: usize = f.read_to_string(&mut contents)?;
+                serde_json::from_str(&contents)
+                    .chain_err(|| "Unable to parse the accounts file")?
+            },
+            Err(ref e) if e.kind() == io::ErrorKind::NotFound => {
+                AccountsFile::default()
+            },
+            Err(e) => return Err(e.into()),
+        };
+
+        let accounts = match (file.encrypted, passphrase) {
+            (Some(ref encrypted), Some(passphrase)) => {
+                credentials::decrypt(encrypted, passphrase)?
+            },
+            (Some(_), None) => {
+                bail!("The accounts file is encrypted, but no passphrase was given");
+            },
+            (None, _) => file.accounts.unwrap_or_default(),
+        };
+
+        Ok(AccountManager {
+            path: Some(path.to_path_buf()),
+            passphrase: passphrase.map(|x| x.to_string()),
+            accounts,
+        })
+    }
+
+    /// Add an account, returning its old session if any
+    pub fn add(&mut self, account: &str, session: Session) -> Option<Session> {
+        self.accounts.sessions.insert(account.to_string(), session)
+    }
+
+    /// Remove an account, returning its session if it existed. If it was
+    /// selected, no account is selected afterwards.
+    pub fn remove(&mut self, account: &str) -> Option<Session> {
+        if self.accounts.selected.as_ref().is_some_and(|x| x == account) {
+            self.accounts.selected = None;
+        }
+        self.accounts.sessions.remove(account)
+    }
+
+    /// Get the names of all the accounts
+    pub fn list(&self) -> Vec<&str> {
+        self.accounts.sessions.keys().map(|x| x.as_str()).collect()
+    }
+
+    /// Get the session of the given account
+    pub fn get(&self, account: &str) -> Option<&Session> {
+        self.accounts.sessions.get(account)
+    }
+
+    /// Get the session of the given account mutably
+    pub fn get_mut(&mut self, account: &str) -> Option<&mut Session> {
+        self.accounts.sessions.get_mut(account)
+    }
+
+    /// Find an account by its name, or the player name (case insensitive) or
+    /// UUID (in any format) of its profile. Returns the account name.
+    pub fn find(&self, query: &str) -> Option<&str> {
+        if let Some((k, _)) = self.accounts.sessions.get_key_value(query) {
+            return Some(k);
+        }
+        self.accounts
+            .sessions
+            .iter()
+            .find(|x| match x.1.profile() {
+                      Some(profile) => {
+                          profile.name.eq_ignore_ascii_case(query) ||
+                          uuid::same(&profile.id, query)
+                      },
+                      None => false,
+                  })
+            .map(|x| x.0.as_str())
+    }
+
+    /// Select the account found by find(), e.g. the one a tool should use by
+    /// default. The selection is saved along with the accounts.
+    pub fn select(&mut self, query: &str) -> Result<()> {
+        let account = match self.find(query) {
+            Some(x) => x.to_string(),
+            None => bail!("No account matching {}", query),
+        };
+        self.accounts.selected = Some(account);
+        Ok(())
+    }
+
+    /// Get the name of the selected account, if any
+    pub fn selected(&self) -> Option<&str> {
+        self.accounts.selected.as_deref()
+    }
+
+    /// Refresh the tokens of the given account if needed, see
+    /// Session::ensure_fresh()
+    pub fn refresh(&mut self, account: &str) -> Result<bool> {
+        match self.accounts.sessions.get_mut(account) {
+            Some(session) => session.ensure_fresh(),
+            None => bail!("No account named {}", account),
+        }
+    }
+
+    /// Refresh the tokens of all accounts if needed, returning the accounts
+    /// that failed to refresh along with the errors
+    pub fn refresh_all(&mut self) -> Vec<(String, Error)> {
+        let mut ret = Vec::new();
+        for (account, session) in &mut self.accounts.sessions {
+            if let Err(e) = session.ensure_fresh() {
+                ret.push((account.clone(), e));
+            }
+        }
+        ret
+    }
+
+    /// Change the passphrase, or with None stop encrypting the file. Takes
+    /// effect the next time the accounts are saved.
+    pub fn set_passphrase(&mut self, passphrase: Option<&str>) {
+        self.passphrase = passphrase.map(|x| x.to_string());
+    }
+
+    /// Write the accounts to the file they were opened from, the same way
+    /// as CredentialStore::save(). Returns an error if the manager was
+    /// created with new().
+    pub fn save(&self) -> Result<()> {
+        let path = match self.path {
+            Some(ref x) => x,
+            None => bail!("The account manager has no file to save to"),
+        };
+        let file = match self.passphrase {
+            Some(ref passphrase) => AccountsFile {
+                accounts: None,
+                encrypted: Some(credentials::encrypt(&self.accounts, passphrase)?),
+            },
+            None => AccountsFile {
+                accounts: Some(self.accounts.clone()),
+                encrypted: None,
+            },
+        };
+        let contents = serde_json::to_string_pretty(&file)?;
+        credentials::write_private(path, &contents)
+    }
+}
+impl Default for AccountManager {
+    fn default() -> Self {
+        AccountManager::new()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::{env, fs};
+
+    fn mojang(name: &str, id: &str) -> Session {
+        Session::Mojang(AuthenticationResponse {
+                            accessToken: format!("{} access token", name),
+                            clientToken: None,
+                            availableProfiles: None,
+                            selectedProfile: NameUUID {
+                                id: id.to_string(),
+                                name: name.to_string(),
+                                legacy: false,
+                                demo: false,
+                            },
+                        })
+    }
+
+    #[test]
+    fn account_manager() {
+        let path = env::temp_dir().join(format!("ozelot-accounts-{}.json",
+                                                ::std::process::id()));
+
+        let mut accounts = AccountManager::open_encrypted(&path, "hunter2").unwrap();
+        assert!(accounts.add("notch@example.com",
+                             mojang("Notch", "069a79f444e94726a5befca90e38aaf5"))
+                        .is_none());
+        assert!(accounts.add("jeb@example.com",
+                             mojang("jeb_", "853c80ef3c3749fdaa49938b674adae6"))
+                        .is_none());
+        let _ = accounts.add("bot",
+                             Session::Msa(MsaSession::new("client id".to_string(),
+                                                          "refresh token".to_string())));
+        assert_eq!(accounts.list(), vec!["bot", "jeb@example.com", "notch@example.com"]);
+
+        assert_eq!(accounts.find("notch"), Some("notch@example.com"));
+        assert_eq!(accounts.find("853C80EF-3C37-49FD-AA49-938B674ADAE6"),
+                   Some("jeb@example.com"));
+        assert_eq!(accounts.find("bot"), Some("bot"));
+        assert_eq!(accounts.find("Dinnerbone"), None);
+        assert!(accounts.select("Dinnerbone").is_err());
+        accounts.select("jeb_").unwrap();
+        accounts.save().unwrap();
+
+        assert!(AccountManager::open(&path).is_err());
+        let mut accounts = AccountManager::open_encrypted(&path, "hunter2").unwrap();
+        assert_eq!(accounts.selected(), Some("jeb@example.com"));
+        assert_eq!(accounts.list().len(), 3);
+        assert!(accounts.refresh("nobody").is_err());
+        assert!(accounts.remove("jeb@example.com").is_some());
+        assert_eq!(accounts.selected(), None);
+
+        assert!(AccountManager::new().save().is_err());
+        fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,140 @@
+//! Structured audit logging of client connections
+//!
+//! Give a Server an AuditSink with server.set_audit_sink(), and it records
+//! login attempts, logins, kicks, chat messages and commands as they pass
+//! through. Other events, e.g. failed authentication, can be recorded with
+//! server.audit().
+//!
+//! # Examples
+//!
+//! ```rust,no_run
+//! use ozelot::Server;
+//! use ozelot::audit::JsonLinesSink;
+//! use std::net::TcpListener;
+//! use std::sync::Arc;
+//!
+//! let sink = Arc::new(JsonLinesSink::open("audit.jsonl").unwrap());
+//! let listener = TcpListener::bind("0.0.0.0:25565").unwrap();
+//! for stream in listener.incoming() {
+//!     let mut server = Server::from_tcpstream(stream.unwrap()).unwrap();
+//!     server.set_audit_sink(Some(sink.clone()));
+//!     /* ... */
+//! }
+//! ```
+
+use errors::Result;
+
+use serde_json;
+
+use std::fs::{File, OpenOptions};
+use std::io::{BufWriter, Write};
+use std::net::SocketAddr;
+use std::path::Path;
+use std::sync::Mutex;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Something that happened on a connection
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum AuditEvent {
+    /// The client sent LoginStart with the given username
+    LoginAttempt { username: String },
+    /// The client was authenticated (or not), e.g. LoginSuccess was sent
+    AuthResult {
+        success: bool,
+        reason: Option<String>,
+    },
+    /// The client was disconnected by the server, with the given raw chat
+    /// json reason
+    Kick { reason: String },
+    /// The client sent a chat message
+    Chat { message: String },
+    /// The client sent a command (a chat message starting with /)
+    Command { command: String },
+    /// The login attempt was rejected for being over the throttle limit of
+    /// the given key, see the throttle module
+    ThrottleRejected { key: String, attempts: usize },
+}
+
+/// An AuditEvent with information about when and where it happened
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct AuditRecord {
+    /// Milliseconds since the unix epoch
+    pub timestamp: u64,
+    /// The address of the client, if known
+    pub peer: Option<SocketAddr>,
+    /// The username given by the client in LoginStart, if known yet
+    pub username: Option<String>,
+    #[serde(flatten)]
+    pub event: AuditEvent,
+}
+impl AuditRecord {
+    /// Create a new record of an event that happened now
+    pub fn new(peer: Option<SocketAddr>,
+               username: Option<String>,
+               event: AuditEvent)
+               -> Self {
+        let timestamp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_millis() as u64)
+            .unwrap_or(0);
+        AuditRecord {
+            timestamp,
+            peer,
+            username,
+            event,
+        }
+    }
+}
+
+/// Receives audit records. Sinks are shared between connections, and thus
+/// between threads.
+pub trait AuditSink: Send + Sync {
+    fn record(&self, record: &AuditRecord);
+}
+
+/// An AuditSink that appends each record as a line of json to a file
+#[derive(Debug)]
+pub struct JsonLinesSink {
+    writer: Mutex<BufWriter<File>>,
+}
+impl JsonLinesSink {
+    /// Open the given file for appending, creating it if needed
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let file = OpenOptions::new().append(true).create(true).open(path)?;
+        Ok(JsonLinesSink {
+            writer: Mutex::new(BufWriter::new(file)),
+        })
+    }
+}
+impl AuditSink for JsonLinesSink {
+    fn record(&self, record: &AuditRecord) {
+        let line = match serde_json::to_string(record) {
+            Ok(x) => x,
+            Err(_) => return,
+        };
+        if let Ok(mut writer) = self.writer.lock() {
+            /* Audit logging must never take down the connection, so errors
+             * are ignored */
+            let _ = writeln!(writer, "{}", line);
+            let _ = writer.flush();
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn record_json() {
+        let record = AuditRecord {
+            timestamp: 1000,
+            peer: Some("127.0.0.1:12345".parse().unwrap()),
+            username: Some("Notch".to_string()),
+            event: AuditEvent::Command { command: "/help".to_string() },
+        };
+        assert_eq!(serde_json::to_string(&record).unwrap(),
+                   r#"{"timestamp":1000,"peer":"127.0.0.1:12345","username":"Notch","event":"command","command":"/help"}"#);
+    }
+}
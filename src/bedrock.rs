@@ -0,0 +1,231 @@
+//! Pinging Bedrock edition servers
+//!
+//! Bedrock servers use RakNet over UDP, and answer an "unconnected ping" with
+//! an "unconnected pong" containing the server's MOTD, version and player
+//! count. This is all that's implemented here, not the rest of the Bedrock
+//! protocol.
+//!
+//! # Examples
+//!
+//! ```rust,no_run
+//! use ozelot::bedrock;
+//! use std::time::Duration;
+//!
+//! let status = bedrock::ping("bedrock.example.com", 19132,
+//!                            Duration::from_secs(5)).unwrap();
+//! println!("{} ({}/{} players)", status.motd, status.players, status.max_players);
+//! ```
+
+use errors::{Result, ResultExt};
+use read::{read_i64, read_u16, read_u8};
+use write::{write_i64, write_u8};
+
+use std::io::{Cursor, Read};
+use std::net::{Ipv4Addr, Ipv6Addr, SocketAddr, ToSocketAddrs, UdpSocket};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+/// The default port of Bedrock servers
+pub const DEFAULT_PORT: u16 = 19132;
+
+const UNCONNECTED_PING: u8 = 0x01;
+const UNCONNECTED_PONG: u8 = 0x1c;
+/// The "offline message data ID" included in unconnected RakNet packets
+const MAGIC: [u8; 16] = [0x00, 0xff, 0xff, 0x00, 0xfe, 0xfe, 0xfe, 0xfe,
+                         0xfd, 0xfd, 0xfd, 0xfd, 0x12, 0x34, 0x56, 0x78];
+
+/// The status of a Bedrock server, as given in the unconnected pong
+#[derive(Debug, Clone, PartialEq)]
+pub struct BedrockStatus {
+    /// The edition, "MCPE" or "MCEE" (education edition)
+    pub edition: String,
+    /// The first line of the MOTD
+    pub motd: String,
+    pub protocol_version: i32,
+    /// The version name, e.g. "1.16.201"
+    pub version: String,
+    pub players: i32,
+    pub max_players: i32,
+    /// The RakNet GUID of the server
+    pub server_guid: i64,
+    /// The second line of the MOTD (usually the world name), if given
+    pub sub_motd: Option<String>,
+    /// The default gamemode, e.g. "Survival", if given
+    pub gamemode: Option<String>,
+    /// The IPv4 port, if given
+    pub port_v4: Option<u16>,
+    /// The IPv6 port, if given
+    pub port_v6: Option<u16>,
+    /// The time between sending the ping and receiving the pong
+    pub latency: Duration,
+}
+
+/// Ping the Bedrock server at the given host and port, waiting at most
+/// timeout for the answer.
+pub fn ping(host: &str, port: u16, timeout: Duration) -> Result<BedrockStatus> {
+    let socket = connect(host, port)?;
+
+    let start = Instant::now();
+    /* Servers echo the time back, so use it to recognize our pong */
+    let time = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_millis() as i64)
+        .unwrap_or(0);
+    let _: usize = socket.send(&ping_packet(time, 0)?)?;
+
+    let mut buf = [0; 2048];
+    loop {
+        let remaining = match timeout.checked_sub(start.elapsed()) {
+            Some(x) if x > Duration::from_secs(0) => x,
+            _ => bail!("Timed out waiting for the unconnected pong"),
+        };
+        socket.set_read_timeout(Some(remaining))?;
+        let len = socket.recv(&mut buf)
+            .chain_err(|| "Timed out waiting for the unconnected pong")?;
+        /* Ignore anything that isn't our pong */
+        if let Ok((pong_time, mut status)) = parse_pong(&buf[..len]) {
+            if pong_time == time {
+                status.latency = start.elapsed();
+                return Ok(status);
+            }
+        }
+    }
+}
+
+/* Connect a socket to the first address of the host that works, bound to
+ * the unspecified address of the same family */
+fn connect(host: &str, port: u16) -> Result<UdpSocket> {
+    let mut last = None;
+    for addr in (host, port).to_socket_addrs()? {
+        let local: SocketAddr = match addr {
+            SocketAddr::V4(_) => (Ipv4Addr::UNSPECIFIED, 0).into(),
+            SocketAddr::V6(_) => (Ipv6Addr::UNSPECIFIED, 0).into(),
+        };
+        match UdpSocket::bind(local).and_then(|x| x.connect(addr).map(|_| x)) {
+            Ok(x) => return Ok(x),
+            Err(e) => last = Some(e),
+        }
+    }
+    match last {
+        Some(e) => Err(e.into()),
+        None => bail!("{} has no addresses", host),
+    }
+}
+
+/// Create an unconnected ping packet with the given time and client GUID
+pub fn ping_packet(time: i64, client_guid: i64) -> Result<Vec<u8>> {
+    let mut ret = Vec::with_capacity(33);
+    write_u8(&UNCONNECTED_PING, &mut ret)?;
+    write_i64(&time, &mut ret)?;
+    ret.extend_from_slice(&MAGIC);
+    write_i64(&client_guid, &mut ret)?;
+    Ok(ret)
+}
+
+/// Parse an unconnected pong packet, returning the time from the ping and
+/// the status. The latency of the status is set to 0.
+pub fn parse_pong(data: &[u8]) -> Result<(i64, BedrockStatus)> {
+    let mut r = Cursor::new(data);
+    if read_u8(&mut r)? != UNCONNECTED_PONG {
+        bail!("Not an unconnected pong packet");
+    }
+    let time = read_i64(&mut r)?;
+    let server_guid = read_i64(&mut r)?;
+    let mut magic = [0; 16];
+    r.read_exact(&mut magic)?;
+    if magic != MAGIC {
+        bail!("Unconnected pong has the wrong magic");
+    }
+    let len = read_u16(&mut r)? as usize;
+    let mut s = vec![0; len];
+    r.read_exact(&mut s)?;
+    let s = String::from_utf8(s)?;
+
+    let fields: Vec<&str> = s.split(';').collect();
+    if fields.len() < 6 {
+        bail!("Unconnected pong has too few fields: {:?}", s);
+    }
+    let optional = |i: usize| match fields.get(i) {
+        Some(x) if !x.is_empty() => Some(x.to_string()),
+        _ => None,
+    };
+    let number = |i: usize| -> Result<i32> {
+        fields[i].parse()
+            .chain_err(|| format!("Invalid number in unconnected pong: {:?}", fields[i]))
+    };
+
+    let status = BedrockStatus {
+        edition: fields[0].to_string(),
+        motd: fields[1].to_string(),
+        protocol_version: number(2)?,
+        version: fields[3].to_string(),
+        players: number(4)?,
+        max_players: number(5)?,
+        server_guid,
+        sub_motd: optional(7),
+        gamemode: optional(8),
+        port_v4: optional(10).and_then(|x| x.parse().ok()),
+        port_v6: optional(11).and_then(|x| x.parse().ok()),
+        latency: Duration::from_secs(0),
+    };
+    Ok((time, status))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use write::write_u16;
+    use std::thread;
+
+    #[test]
+    fn pong() {
+        let motd = "MCPE;Dedicated Server;422;1.16.201;3;10;13253860892328930865;\
+                    Bedrock level;Survival;1;19132;19133;";
+        let mut data = vec![UNCONNECTED_PONG];
+        write_i64(&1234, &mut data).unwrap();
+        write_i64(&-42, &mut data).unwrap();
+        data.extend_from_slice(&MAGIC);
+        write_u16(&(motd.len() as u16), &mut data).unwrap();
+        data.extend_from_slice(motd.as_bytes());
+
+        let (time, status) = parse_pong(&data).unwrap();
+        assert_eq!(time, 1234);
+        assert_eq!(status.edition, "MCPE");
+        assert_eq!(status.motd, "Dedicated Server");
+        assert_eq!(status.protocol_version, 422);
+        assert_eq!(status.version, "1.16.201");
+        assert_eq!((status.players, status.max_players), (3, 10));
+        assert_eq!(status.server_guid, -42);
+        assert_eq!(status.sub_motd, Some("Bedrock level".to_string()));
+        assert_eq!(status.gamemode, Some("Survival".to_string()));
+        assert_eq!((status.port_v4, status.port_v6), (Some(19132), Some(19133)));
+
+        assert_eq!(ping_packet(1234, 0).unwrap().len(), 33);
+        assert!(parse_pong(&ping_packet(1234, 0).unwrap()).is_err());
+    }
+
+    #[test]
+    fn ping_ipv6() {
+        /* Not every machine running the tests has IPv6 */
+        let server = match UdpSocket::bind("[::1]:0") {
+            Ok(x) => x,
+            Err(_) => return,
+        };
+        let port = server.local_addr().unwrap().port();
+        let thread = thread::spawn(move || {
+            let mut buf = [0; 64];
+            let (len, from) = server.recv_from(&mut buf).unwrap();
+            assert_eq!(len, 33);
+            let motd = "MCPE;Dedicated Server;422;1.16.201;3;10";
+            let mut pong = vec![UNCONNECTED_PONG];
+            pong.extend_from_slice(&buf[1..9]);
+            write_i64(&-42, &mut pong).unwrap();
+            pong.extend_from_slice(&MAGIC);
+            write_u16(&(motd.len() as u16), &mut pong).unwrap();
+            pong.extend_from_slice(motd.as_bytes());
+            let _: usize = server.send_to(&pong, from).unwrap();
+        });
+        let status = ping("::1", port, Duration::from_secs(5)).unwrap();
+        thread.join().unwrap();
+        assert_eq!(status.server_guid, -42);
+    }
+}
@@ -0,0 +1,316 @@
+//! Generates packet definitions and registries from PrismarineJS'
+//! minecraft-data, to ease updating ozelot to a new protocol version.
+//!
+//! Usage: minecraft-data <data dir> [output dir]
+//!
+//! The data dir is a version folder of minecraft-data, e.g.
+//! minecraft-data/data/pc/1.13.2, containing protocol.json and optionally
+//! blocks.json, items.json, entities.json and biomes.json. (If the version's
+//! files are spread over several folders, see dataPaths.json, copy them
+//! into one first.) Two files are written to the output dir, which defaults
+//! to the current directory:
+//!
+//! - packet_definitions.generated.clj, the packet IDs and fields in the
+//!   format of packet_definitions.clj. Fields whose type has no counterpart
+//!   in ozelot end the packet in a raw data field.
+//! - .registries.generated.rs, the IDs and names of each registry as consts.
+//!
+//! The generated definitions are a starting point, the packet names and
+//! docs still need to be merged into packet_definitions.clj by hand, after
+//! which packets.clj generates the code as usual.
+//!
+//! Requires the codegen feature, i.e. run it with
+//! cargo run --features codegen --bin minecraft-data -- <data dir>
+extern crate serde_json;
+
+use std::env;
+use std::fs::File;
+use std::io::{Read, Write};
+use std::path::Path;
+use std::process::exit;
+
+use serde_json::Value;
+
+const STATES: [(&str, &str); 4] = [("handshaking", "Handshake"),
+                                   ("status", "Status"),
+                                   ("login", "Login"),
+                                   ("play", "Play")];
+const DIRECTIONS: [(&str, &str); 2] = [("toServer", "serverbound"),
+                                       ("toClient", "clientbound")];
+const REGISTRIES: [(&str, &str); 4] = [("blocks.json", "BLOCKS"),
+                                       ("items.json", "ITEMS"),
+                                       ("entities.json", "ENTITIES"),
+                                       ("biomes.json", "BIOMES")];
+
+fn main() {
+    let args: Vec<String> = env::args().collect();
+    if args.len() < 2 || args.len() > 3 {
+        eprintln!("Usage: {} <minecraft-data version dir> [output dir]", args[0]);
+        exit(1);
+    }
+    let dir = Path::new(&args[1]);
+    let out = Path::new(args.get(2).map(|x| x.as_str()).unwrap_or("."));
+
+    let protocol = match read_json(&dir.join("protocol.json")) {
+        Ok(Some(x)) => x,
+        Ok(None) => fail(&format!("No protocol.json in {}", dir.display())),
+        Err(e) => fail(&e),
+    };
+    let definitions = match packet_definitions(&protocol) {
+        Ok(x) => x,
+        Err(e) => fail(&e),
+    };
+    write_file(&out.join("packet_definitions.generated.clj"), &definitions);
+
+    let mut registries = String::from("/* This file is automatically generated by the minecraft-data\nbinary, do not manually edit this file */\n\n");
+    for &(file, name) in REGISTRIES.iter() {
+        match read_json(&dir.join(file)) {
+            Ok(Some(x)) => match registry(name, &x) {
+                Ok(x) => registries.push_str(&x),
+                Err(e) => fail(&format!("{}: {}", file, e)),
+            },
+            Ok(None) => (),
+            Err(e) => fail(&e),
+        }
+    }
+    write_file(&out.join(".registries.generated.rs"), &registries);
+}
+
+fn fail(msg: &str) -> ! {
+    eprintln!("{}", msg);
+    exit(1);
+}
+
+/// Read and parse a json file, or None if it doesn't exist
+fn read_json(path: &Path) -> Result<Option<Value>, String> {
+    if !path.exists() {
+        return Ok(None);
+    }
+    let mut s = String::new();
+    File::open(path)
+        .and_then(|mut f| f.read_to_string(&mut s))
+        .map_err(|e| format!("Error reading {}: {}", path.display(), e))?;
+    serde_json::from_str(&s)
+        .map(Some)
+        .map_err(|e| format!("Error parsing {}: {}", path.display(), e))
+}
+
+fn write_file(path: &Path, contents: &str) {
+    if let Err(e) = File::create(path).and_then(|mut f| f.write_all(contents.as_bytes())) {
+        fail(&format!("Error writing {}: {}", path.display(), e));
+    }
+    println!("Wrote {}", path.display());
+}
+
+/// Convert protocol.json into the format of packet_definitions.clj
+fn packet_definitions(protocol: &Value) -> Result<String, String> {
+    let mut ret = String::from(";;;;;; Generated from minecraft-data by the minecraft-data binary.\n\n(def packets\n  {");
+    for (i, &(direction, direction_name)) in DIRECTIONS.iter().enumerate() {
+        if i != 0 {
+            ret.push_str("\n   ");
+        }
+        ret.push_str(&format!(":{}\n   {{", direction_name));
+        for (j, &(state, state_name)) in STATES.iter().enumerate() {
+            if j != 0 {
+                ret.push_str("\n    ");
+            }
+            ret.push_str(&format!("\"{}\"\n    [", state_name));
+            let types = &protocol[state][direction]["types"];
+            for (k, (id, name)) in packet_ids(types)?.into_iter().enumerate() {
+                if k != 0 {
+                    ret.push_str("\n     ");
+                }
+                let fields = match types.get(format!("packet_{}", name)) {
+                    Some(x) => fields(x),
+                    None => Vec::new(),
+                };
+                ret.push_str(&format!("{{:name \"{}\"\n      :id {}\n      :fields [",
+                                      camel_case(&name),
+                                      id));
+                for (l, field) in fields.iter().enumerate() {
+                    if l != 0 {
+                        ret.push_str("\n               ");
+                    }
+                    ret.push_str(field);
+                }
+                ret.push_str("]}");
+            }
+            ret.push(']');
+        }
+        ret.push('}');
+    }
+    ret.push_str("})\n");
+    Ok(ret)
+}
+
+/// Get the packet IDs and names of a state/direction, sorted by ID
+fn packet_ids(types: &Value) -> Result<Vec<(i32, String)>, String> {
+    let packet = match types.get("packet") {
+        Some(x) => x,
+        None => return Ok(Vec::new()),
+    };
+    let mappings = packet[1]
+        .as_array()
+        .and_then(|x| x.iter().find(|x| x["name"] == "name"))
+        .and_then(|x| x["type"][1]["mappings"].as_object())
+        .ok_or_else(|| "Packet type without an ID mapping".to_string())?;
+    let mut ret = Vec::new();
+    for (id, name) in mappings {
+        let id = i32::from_str_radix(id.trim_start_matches("0x"), 16)
+            .map_err(|_| format!("Invalid packet ID {}", id))?;
+        let name = name.as_str()
+            .ok_or_else(|| format!("Invalid name of packet {}", id))?;
+        ret.push((id, name.to_string()));
+    }
+    ret.sort();
+    Ok(ret)
+}
+
+/// Get the fields of a packet as packet_definitions.clj maps. If a field
+/// has a type ozelot has no counterpart for, it and the following fields are
+/// replaced by a raw data field.
+fn fields(packet: &Value) -> Vec<String> {
+    let mut ret = Vec::new();
+    let fields = match packet[1].as_array() {
+        Some(x) => x,
+        None => return ret,
+    };
+    for field in fields {
+        let name = match field["name"].as_str() {
+            Some(x) => snake_case(x),
+            None => break,
+        };
+        match field_type(&field["type"]) {
+            Some((ty, read)) => {
+                let read = match read {
+                    Some(x) => format!(" :read \"{}\"", x),
+                    None => String::new(),
+                };
+                ret.push(format!("{{:name \"{}\" :type \"{}\"{} :getter \"Get the {}\"}}",
+                                 name,
+                                 ty,
+                                 read,
+                                 name.replace('_', " ")));
+            },
+            None => {
+                ret.push("{:name \"data\" :type \"Vec<u8>\" :read \"bytearray_to_end\" :getter \"Get the raw unprocessed data\"}".to_string());
+                break;
+            },
+        }
+    }
+    ret
+}
+
+/// Map a minecraft-data type to the Rust type and read function
+fn field_type(ty: &Value) -> Option<(&'static str, Option<&'static str>)> {
+    if let Some(s) = ty.as_str() {
+        return Some(match s {
+                        "bool" => ("bool", None),
+                        "u8" => ("u8", None),
+                        "i8" => ("i8", None),
+                        "u16" => ("u16", None),
+                        "i16" => ("i16", None),
+                        "i32" => ("i32", None),
+                        "i64" => ("i64", None),
+                        "u64" => ("u64", None),
+                        "f32" => ("f32", None),
+                        "f64" => ("f64", None),
+                        "string" => ("String", None),
+                        "varint" => ("i32", Some("varint")),
+                        "varlong" => ("i64", Some("varlong")),
+                        "UUID" => ("u128", None),
+                        "position" => ("(i32, i32, i32)", Some("position")),
+                        "restBuffer" => ("Vec<u8>", Some("bytearray_to_end")),
+                        _ => return None,
+                    });
+    }
+    if ty[0] == "buffer" && ty[1]["countType"] == "varint" {
+        return Some(("Vec<u8>", Some("prefixed_bytearray")));
+    }
+    None
+}
+
+/// Convert a registry json array into a const of IDs and names
+fn registry(name: &str, entries: &Value) -> Result<String, String> {
+    let entries = entries.as_array()
+        .ok_or_else(|| "Expected an array".to_string())?;
+    let mut ret = format!("/// The {} registry, as IDs and names\npub const {}: &[(i32, &str)] = &[\n",
+                          name.to_lowercase(),
+                          name);
+    for entry in entries {
+        let id = entry["id"]
+            .as_i64()
+            .ok_or_else(|| "Entry without an ID".to_string())?;
+        let entry_name = entry["name"]
+            .as_str()
+            .ok_or_else(|| format!("Entry {} without a name", id))?;
+        ret.push_str(&format!("    ({}, \"{}\"),\n", id, entry_name));
+    }
+    ret.push_str("];\n\n");
+    Ok(ret)
+}
+
+/// e.g. spawn_entity_living -> SpawnEntityLiving
+fn camel_case(s: &str) -> String {
+    s.split('_')
+        .map(|x| {
+                 let mut chars = x.chars();
+                 match chars.next() {
+                     Some(c) => c.to_uppercase().chain(chars).collect(),
+                     None => String::new(),
+                 }
+             })
+        .collect()
+}
+
+/// e.g. protocolVersion -> protocol_version
+fn snake_case(s: &str) -> String {
+    let mut ret = String::new();
+    for c in s.chars() {
+        if c.is_uppercase() {
+            if !ret.is_empty() {
+                ret.push('_');
+            }
+            ret.extend(c.to_lowercase());
+        } else {
+            ret.push(c);
+        }
+    }
+    ret
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn generate() {
+        let protocol: Value = serde_json::from_str(r#"{
+            "handshaking": {"toServer": {"types": {
+                "packet_set_protocol": ["container", [
+                    {"name": "protocolVersion", "type": "varint"},
+                    {"name": "serverHost", "type": "string"},
+                    {"name": "serverPort", "type": "u16"},
+                    {"name": "nextState", "type": "varint"}]],
+                "packet_legacy_server_list_ping": ["container", [
+                    {"name": "payload", "type": "u8"},
+                    {"name": "extra", "type": ["option", "string"]},
+                    {"name": "skipped", "type": "u8"}]],
+                "packet": ["container", [
+                    {"name": "name", "type": ["mapper", {"type": "varint",
+                        "mappings": {"0xfe": "legacy_server_list_ping", "0x00": "set_protocol"}}]},
+                    {"name": "params", "type": ["switch", {"compareTo": "name", "fields": {}}]}]]
+            }}}
+        }"#).unwrap();
+        let ret = packet_definitions(&protocol).unwrap();
+        assert!(ret.contains("{:name \"SetProtocol\"\n      :id 0\n      :fields [{:name \"protocol_version\" :type \"i32\" :read \"varint\" :getter \"Get the protocol version\"}"));
+        assert!(ret.contains("{:name \"LegacyServerListPing\"\n      :id 254\n"));
+        assert!(ret.contains(":type \"u8\" :getter \"Get the payload\"}\n               {:name \"data\""));
+        assert!(!ret.contains("skipped"));
+        assert!(ret.find("SetProtocol") < ret.find("LegacyServerListPing"));
+
+        let blocks: Value = serde_json::from_str(r#"[{"id": 0, "name": "air"}, {"id": 1, "name": "stone"}]"#).unwrap();
+        assert_eq!(registry("BLOCKS", &blocks).unwrap(),
+                   "/// The blocks registry, as IDs and names\npub const BLOCKS: &[(i32, &str)] = &[\n    (0, \"air\"),\n    (1, \"stone\"),\n];\n\n");
+    }
+}
@@ -0,0 +1,364 @@
+//! Block related helpers, e.g. for digging and placing blocks
+//!
+//! BlockTracker keeps the block states the server sent in BlockChange and
+//! MultiBlockChange packets. Chunk data isn't parsed, so only blocks that
+//! changed since joining are known. With prediction enabled, the client's
+//! own digging and placing is applied right away, and rolled back if the
+//! server sends a different state or doesn't confirm it in time.
+
+use clientbound::ClientboundPacket;
+
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+/// The block state ID of air
+pub const AIR: i32 = 0;
+
+/// A face of a block, e.g. the one being dug or placed against
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum BlockFace {
+    Bottom,
+    Top,
+    North,
+    South,
+    West,
+    East,
+}
+impl BlockFace {
+    /// Get the protocol ID of this face
+    pub fn to_id(&self) -> i32 {
+        match *self {
+            BlockFace::Bottom => 0,
+            BlockFace::Top => 1,
+            BlockFace::North => 2,
+            BlockFace::South => 3,
+            BlockFace::West => 4,
+            BlockFace::East => 5,
+        }
+    }
+
+    /// Get the position of the block adjacent to the given block on this
+    /// face, e.g. where a block placed against this face ends up.
+    pub fn offset(&self, (x, y, z): (i32, i32, i32)) -> (i32, i32, i32) {
+        match *self {
+            BlockFace::Bottom => (x, y - 1, z),
+            BlockFace::Top => (x, y + 1, z),
+            BlockFace::North => (x, y, z - 1),
+            BlockFace::South => (x, y, z + 1),
+            BlockFace::West => (x - 1, y, z),
+            BlockFace::East => (x + 1, y, z),
+        }
+    }
+}
+
+/// A hand of the player
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Hand {
+    Main,
+    Off,
+}
+impl Hand {
+    /// Get the protocol ID of this hand
+    pub fn to_id(&self) -> i32 {
+        match *self {
+            Hand::Main => 0,
+            Hand::Off => 1,
+        }
+    }
+}
+
+/// A block state the client predicted, awaiting confirmation
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Prediction {
+    pub state: i32,
+    /// The state before the prediction, if known
+    pub previous: Option<i32>,
+    pub made_at: Instant,
+}
+
+/// The outcome of a prediction, see ClientEvent::Prediction
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PredictionEvent {
+    /// The server sent the predicted state
+    Confirmed((i32, i32, i32), i32),
+    /// The server sent another state, or none in time, so the block was set
+    /// to the actual state, or the state before the prediction. actual is
+    /// None if neither is known, e.g. when the chunk was unloaded.
+    RolledBack {
+        position: (i32, i32, i32),
+        predicted: i32,
+        actual: Option<i32>,
+    },
+}
+
+/// The default time the server has to confirm a prediction
+pub const DEFAULT_PREDICTION_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// Tracks block states, see the module documentation
+#[derive(Debug, Clone)]
+pub struct BlockTracker {
+    known: HashMap<(i32, i32, i32), i32>,
+    pending: HashMap<(i32, i32, i32), Prediction>,
+    predict: bool,
+    timeout: Duration,
+}
+impl Default for BlockTracker {
+    fn default() -> Self {
+        BlockTracker {
+            known: HashMap::new(),
+            pending: HashMap::new(),
+            predict: false,
+            timeout: DEFAULT_PREDICTION_TIMEOUT,
+        }
+    }
+}
+impl BlockTracker {
+    /// Enable or disable prediction, which is disabled by default.
+    /// Disabling it rolls back any pending predictions.
+    pub fn set_prediction(&mut self, enabled: bool) -> Vec<PredictionEvent> {
+        self.predict = enabled;
+        if enabled {
+            return Vec::new();
+        }
+        let positions: Vec<_> = self.pending.keys().cloned().collect();
+        positions.into_iter().filter_map(|x| self.roll_back(x, None)).collect()
+    }
+
+    pub fn is_predicting(&self) -> bool {
+        self.predict
+    }
+
+    /// Set how long the server has to confirm a prediction
+    pub fn set_timeout(&mut self, timeout: Duration) {
+        self.timeout = timeout;
+    }
+
+    /// Get the state of a block, including predictions, or None if unknown
+    pub fn get(&self, position: (i32, i32, i32)) -> Option<i32> {
+        self.pending
+            .get(&position)
+            .map(|x| x.state)
+            .or_else(|| self.known.get(&position).cloned())
+    }
+
+    /// Get the state of a block as last sent by the server
+    pub fn confirmed(&self, position: (i32, i32, i32)) -> Option<i32> {
+        self.known.get(&position).cloned()
+    }
+
+    /// Get the prediction for a block awaiting confirmation
+    pub fn prediction(&self, position: (i32, i32, i32)) -> Option<&Prediction> {
+        self.pending.get(&position)
+    }
+
+    /// Predict that the block will change to the given state, if prediction
+    /// is enabled
+    pub fn predict(&mut self, position: (i32, i32, i32), state: i32) {
+        if !self.predict {
+            return;
+        }
+        let previous = self.confirmed(position);
+        let _ = self.pending.insert(position,
+                                    Prediction {
+                                        state,
+                                        previous,
+                                        made_at: Instant::now(),
+                                    });
+    }
+
+    /// Set a block to the state sent by the server
+    pub fn server_update(&mut self,
+                         position: (i32, i32, i32),
+                         state: i32)
+                         -> Option<PredictionEvent> {
+        let _: Option<i32> = self.known.insert(position, state);
+        match self.pending.remove(&position) {
+            Some(x) if x.state == state => Some(PredictionEvent::Confirmed(position, state)),
+            Some(x) => {
+                Some(PredictionEvent::RolledBack {
+                         position,
+                         predicted: x.state,
+                         actual: Some(state),
+                     })
+            },
+            None => None,
+        }
+    }
+
+    /// Roll back the predictions the server hasn't confirmed in time
+    pub fn expire(&mut self, now: Instant) -> Vec<PredictionEvent> {
+        let timeout = self.timeout;
+        let expired: Vec<_> = self.pending
+            .iter()
+            .filter(|&(_, x)| now.saturating_duration_since(x.made_at) >= timeout)
+            .map(|(&position, _)| position)
+            .collect();
+        expired.into_iter().filter_map(|x| self.roll_back(x, None)).collect()
+    }
+
+    /// Forget all blocks, e.g. when changing worlds
+    pub fn clear(&mut self) {
+        self.known.clear();
+        self.pending.clear();
+    }
+
+    /// Update the states from a BlockChange, MultiBlockChange or
+    /// UnloadChunk packet
+    pub fn handle(&mut self, packet: &ClientboundPacket) -> Vec<PredictionEvent> {
+        match *packet {
+            ClientboundPacket::BlockChange(ref p) => {
+                self.server_update(*p.get_position(), *p.get_new_block())
+                    .into_iter()
+                    .collect()
+            },
+            ClientboundPacket::MultiBlockChange(ref p) => {
+                let (cx, cz) = (*p.get_chunk_x(), *p.get_chunk_z());
+                p.get_changes()
+                    .iter()
+                    .filter_map(|&(x, y, z, state)| {
+                                    let position = (cx * 16 + x as i32,
+                                                    y as i32,
+                                                    cz * 16 + z as i32);
+                                    self.server_update(position, state)
+                                })
+                    .collect()
+            },
+            ClientboundPacket::UnloadChunk(ref p) => {
+                let chunk = (*p.get_chunk_x(), *p.get_chunk_z());
+                let in_chunk = |&(x, _, z): &(i32, i32, i32)| (x >> 4, z >> 4) == chunk;
+                self.known.retain(|k, _| !in_chunk(k));
+                /* The blocks are unknown now, so the predictions can't be
+                 * confirmed anymore */
+                let unloaded: Vec<_> = self.pending.keys().cloned().filter(in_chunk).collect();
+                unloaded.into_iter()
+                    .filter_map(|x| self.pending.remove(&x).map(|p| (x, p)))
+                    .map(|(position, x)| {
+                             PredictionEvent::RolledBack {
+                                 position,
+                                 predicted: x.state,
+                                 actual: None,
+                             }
+                         })
+                    .collect()
+            },
+            _ => Vec::new(),
+        }
+    }
+
+    fn roll_back(&mut self,
+                 position: (i32, i32, i32),
+                 actual: Option<i32>)
+                 -> Option<PredictionEvent> {
+        self.pending
+            .remove(&position)
+            .map(|x| {
+                     PredictionEvent::RolledBack {
+                         position,
+                         predicted: x.state,
+                         actual: actual.or(x.previous),
+                     }
+                 })
+    }
+}
+
+/// Calculate how many ticks it takes to break a block, the way the vanilla
+/// client does.
+///
+/// hardness is the hardness of the block (e.g. 1.5 for stone, -1 for
+/// bedrock), tool_speed the mining speed of the held item against the
+/// block (1 for hands or the wrong tool, 2 for wooden tools, 4 stone,
+/// 6 iron, 8 diamond, 12 gold, plus efficiency/haste bonuses), and
+/// can_harvest whether the held item can harvest the block (e.g. false when
+/// mining stone by hand.) If the player is under water or not on the ground,
+/// the vanilla client divides the tool speed by 5 for each.
+///
+/// Returns None if the block is unbreakable, and Some(0) if it's broken
+/// instantly.
+pub fn break_ticks(hardness: f32, tool_speed: f32, can_harvest: bool) -> Option<u32> {
+    if hardness < 0.0 {
+        return None;
+    }
+    if hardness == 0.0 {
+        return Some(0);
+    }
+    let damage = tool_speed / hardness / if can_harvest { 30.0 } else { 100.0 };
+    if damage > 1.0 {
+        Some(0)
+    } else {
+        Some((1.0 / damage).ceil() as u32)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use clientbound::UnloadChunk;
+
+    #[test]
+    fn break_ticks() {
+        /* Bedrock */
+        assert_eq!(super::break_ticks(-1.0, 8.0, true), None);
+        /* Grass (tall grass) */
+        assert_eq!(super::break_ticks(0.0, 1.0, true), Some(0));
+        /* Stone by hand, 7.5 seconds */
+        assert_eq!(super::break_ticks(1.5, 1.0, false), Some(150));
+        /* Stone with a wooden pickaxe, 1.15 seconds */
+        assert_eq!(super::break_ticks(1.5, 2.0, true), Some(23));
+        /* Stone with an efficiency V diamond pickaxe (8 + 5^2 + 1) */
+        assert_eq!(super::break_ticks(1.5, 34.0, true), Some(2));
+    }
+
+    #[test]
+    fn block_face() {
+        assert_eq!(BlockFace::Top.offset((1, 2, 3)), (1, 3, 3));
+        assert_eq!(BlockFace::West.offset((1, 2, 3)), (0, 2, 3));
+        assert_eq!(BlockFace::East.to_id(), 5);
+    }
+
+    #[test]
+    fn prediction() {
+        let mut blocks = BlockTracker::default();
+        blocks.predict((0, 64, 0), AIR);
+        assert_eq!(blocks.get((0, 64, 0)), None);
+
+        let _ = blocks.set_prediction(true);
+        assert_eq!(blocks.server_update((0, 64, 0), 1), None);
+        blocks.predict((0, 64, 0), AIR);
+        assert_eq!(blocks.get((0, 64, 0)), Some(AIR));
+        assert_eq!(blocks.confirmed((0, 64, 0)), Some(1));
+        assert_eq!(blocks.server_update((0, 64, 0), AIR),
+                   Some(PredictionEvent::Confirmed((0, 64, 0), AIR)));
+
+        blocks.predict((1, 64, 0), 9);
+        assert_eq!(blocks.server_update((1, 64, 0), AIR),
+                   Some(PredictionEvent::RolledBack {
+                            position: (1, 64, 0),
+                            predicted: 9,
+                            actual: Some(AIR),
+                        }));
+        assert_eq!(blocks.get((1, 64, 0)), Some(AIR));
+
+        blocks.predict((0, 64, 0), 9);
+        assert_eq!(blocks.expire(Instant::now()), vec![]);
+        let later = Instant::now() + DEFAULT_PREDICTION_TIMEOUT;
+        assert_eq!(blocks.expire(later),
+                   vec![PredictionEvent::RolledBack {
+                            position: (0, 64, 0),
+                            predicted: 9,
+                            actual: Some(AIR),
+                        }]);
+        assert_eq!(blocks.get((0, 64, 0)), Some(AIR));
+
+        /* Unloading the chunk rolls back its predictions */
+        blocks.predict((17, 64, 0), 9);
+        blocks.predict((1, 64, 0), 9);
+        assert_eq!(blocks.handle(&UnloadChunk::new(1, 0)),
+                   vec![PredictionEvent::RolledBack {
+                            position: (17, 64, 0),
+                            predicted: 9,
+                            actual: None,
+                        }]);
+        assert_eq!(blocks.get((17, 64, 0)), None);
+        assert_eq!(blocks.prediction((1, 64, 0)).map(|x| x.state), Some(9));
+    }
+}
@@ -0,0 +1,144 @@
+//! Sending chat messages to many connections at once
+//!
+//! Messages are formatted for each connection separately, given the locale
+//! the client sent in its ClientSettings, and the clients' chat visibility
+//! settings are respected like the vanilla server does.
+//!
+//! Protocol 404 has no signed chat, so player messages are sent as plain
+//! chat components.
+//!
+//! # Examples
+//!
+//! ```rust,no_run
+//! use ozelot::Server;
+//! use ozelot::broadcast::{self, ChatPosition};
+//!
+//! # let mut servers: Vec<Server> = Vec::new();
+//! let message = broadcast::player_message("Notch", "hello");
+//! let failed = broadcast::broadcast(servers.iter_mut(),
+//!                                   ChatPosition::Chat,
+//!                                   |_| message.clone(),
+//!                                   |_| false);
+//! ```
+
+use {ClientState, Server};
+use clientbound::ChatMessage;
+use errors::Error;
+
+/// Where a chat message is shown, and thus who will see it
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ChatPosition {
+    /// A player message, hidden from clients that only want system messages
+    Chat,
+    /// A system message, e.g. command feedback
+    System,
+    /// Above the hotbar, always shown
+    ActionBar,
+}
+impl ChatPosition {
+    /// Get the protocol ID of this position
+    pub fn to_id(&self) -> u8 {
+        match *self {
+            ChatPosition::Chat => 0,
+            ChatPosition::System => 1,
+            ChatPosition::ActionBar => 2,
+        }
+    }
+
+    /// Whether a client with the given chat mode (from ClientSettings)
+    /// wants messages in this position
+    pub fn is_visible(&self, chat_mode: i32) -> bool {
+        match *self {
+            ChatPosition::Chat => chat_mode == 0,
+            ChatPosition::System => chat_mode != 2,
+            ChatPosition::ActionBar => true,
+        }
+    }
+}
+
+/// Send a chat message to each of the given connections.
+///
+/// format is called with the locale of each connection ("en_us" if the
+/// client hasn't sent its ClientSettings yet) and returns the raw chat json
+/// to send. Connections for which exclude returns true, those whose chat
+/// settings hide messages in this position, and those that aren't in the
+/// Play state yet, are skipped.
+///
+/// Sending to a connection failing doesn't stop the broadcast. Returns the
+/// index (in the iteration order) and error of each connection that failed,
+/// so that they can be closed.
+pub fn broadcast<'a, I, F, E>(servers: I,
+                              position: ChatPosition,
+                              format: F,
+                              exclude: E)
+                              -> Vec<(usize, Error)>
+    where I: IntoIterator<Item = &'a mut Server>,
+          F: Fn(&str) -> String,
+          E: Fn(&Server) -> bool
+{
+    let mut failed = Vec::new();
+    for (i, server) in servers.into_iter().enumerate() {
+        if *server.clientstate() != ClientState::Play || exclude(server) ||
+           !position.is_visible(server.chat_mode()) {
+            continue;
+        }
+        let chat = format(server.locale().unwrap_or("en_us"));
+        if let Err(e) = server.send(ChatMessage::new(chat, position.to_id())) {
+            failed.push((i, e));
+        }
+    }
+    failed
+}
+
+/// Create a chat component of plain text
+pub fn text(text: &str) -> String {
+    json!({ "text": text }).to_string()
+}
+
+/// Create a chat component of the given translation key and arguments,
+/// which clients translate into their own language
+pub fn translate(key: &str, with: &[&str]) -> String {
+    json!({ "translate": key, "with": with }).to_string()
+}
+
+/// Create the chat component of a player's chat message, formatted like
+/// vanilla ("<player> message")
+pub fn player_message(player: &str, message: &str) -> String {
+    translate("chat.type.text", &[player, message])
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use Client;
+    use transport::MemoryStream;
+
+    #[test]
+    fn components() {
+        assert_eq!(text("a \"b\""), r#"{"text":"a \"b\""}"#);
+        assert_eq!(player_message("Notch", "hi"),
+                   r#"{"translate":"chat.type.text","with":["Notch","hi"]}"#);
+        assert!(ChatPosition::System.is_visible(1));
+        assert!(!ChatPosition::Chat.is_visible(1));
+        assert!(ChatPosition::ActionBar.is_visible(2));
+    }
+
+    #[test]
+    fn skips_before_play() {
+        let mut clients = Vec::new();
+        let mut servers = Vec::new();
+        for state in &[ClientState::Play, ClientState::Login] {
+            let (a, b) = MemoryStream::pair();
+            let mut client = Client::from_transport(a).unwrap();
+            let mut server = Server::from_transport(b).unwrap();
+            client.set_clientstate(state.clone());
+            server.set_clientstate(state.clone());
+            clients.push(client);
+            servers.push(server);
+        }
+        let failed = broadcast(servers.iter_mut(), ChatPosition::System, text, |_| false);
+        assert!(failed.is_empty());
+        assert_eq!(clients[0].read().unwrap().len(), 1);
+        assert!(clients[1].read().unwrap().is_empty());
+    }
+}
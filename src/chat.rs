@@ -0,0 +1,469 @@
+//! Validation of chat messages and commands sent by clients
+//!
+//! Like the vanilla server, the Server checks incoming ChatMessage packets
+//! before they are returned by server.read(): messages must not be longer
+//! than 256 characters, and must not contain control characters or the
+//! section sign (used for formatting codes). What happens to messages that
+//! don't pass is configured with server.set_chat_policy(). By default the
+//! client is kicked, as vanilla does.
+//!
+//! The other way around, chat components received from a server can be
+//! parsed into a ChatComponent, and shown in a terminal with the colors and
+//! formatting players see using to_ansi().
+//!
+//! # Examples
+//!
+//! ```rust,no_run
+//! use ozelot::{Client, ClientEvent};
+//! use ozelot::chat::ChatComponent;
+//! use ozelot::clientbound::ClientboundPacket;
+//!
+//! let mut client = Client::connect_unauthenticated("localhost", 25565, "Bob").unwrap();
+//! loop {
+//!     for packet in client.read().unwrap() {
+//!         if let ClientboundPacket::ChatMessage(ref p) = packet {
+//!             println!("{}", ChatComponent::parse(p.get_chat()).unwrap().to_terminal());
+//!         }
+//!     }
+//! }
+//! ```
+
+use consts;
+use errors::Result;
+use font;
+
+use serde_json::{self, Map, Value};
+
+use std::env;
+
+/// The maximum length of a chat message accepted by the vanilla server
+pub const MAX_LENGTH: usize = consts::MAX_CHAT_LENGTH;
+
+/// The reason a client is kicked with for a chat message with disallowed
+/// characters, the same as vanilla's
+pub const ILLEGAL_CHARACTERS_REASON: &str =
+    r#"{"translate":"multiplayer.disconnect.illegal_characters"}"#;
+/// The reason a client is kicked with for a chat message over the maximum
+/// length. The vanilla client cuts messages to the length itself, so there's
+/// no translation for this.
+pub const TOO_LONG_REASON: &str = r#"{"text":"Chat message too long"}"#;
+
+/// What to do with a chat message that fails validation
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ChatViolation {
+    /// Remove the disallowed characters and cut the message to the maximum
+    /// length, dropping it if nothing is left
+    Truncate,
+    /// Silently drop the message
+    Reject,
+    /// Disconnect the client
+    Kick,
+}
+
+/// How chat messages from clients are validated
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ChatPolicy {
+    /// The maximum length in characters
+    pub max_length: usize,
+    pub on_violation: ChatViolation,
+}
+impl Default for ChatPolicy {
+    fn default() -> Self {
+        ChatPolicy {
+            max_length: MAX_LENGTH,
+            on_violation: ChatViolation::Kick,
+        }
+    }
+}
+impl ChatPolicy {
+    /// Check the given message, returning it unchanged if it is valid.
+    ///
+    /// Otherwise returns the sanitized message if the policy is Truncate and
+    /// anything is left of it, or None.
+    pub fn apply(&self, message: &str) -> Option<String> {
+        if self.is_valid(message) {
+            return Some(message.to_string());
+        }
+        match self.on_violation {
+            ChatViolation::Truncate => {
+                let ret: String = message.chars()
+                    .filter(|&c| is_allowed_character(c))
+                    .take(self.max_length)
+                    .collect();
+                let ret = ret.trim();
+                if ret.is_empty() {
+                    None
+                } else {
+                    Some(ret.to_string())
+                }
+            },
+            ChatViolation::Reject | ChatViolation::Kick => None,
+        }
+    }
+
+    /// Whether the given message is within the maximum length and only
+    /// contains allowed characters
+    pub fn is_valid(&self, message: &str) -> bool {
+        self.kick_reason(message).is_none()
+    }
+
+    /// Get the reason to kick the client with for the given message, or
+    /// None if it is valid, i.e. TOO_LONG_REASON or
+    /// ILLEGAL_CHARACTERS_REASON
+    pub fn kick_reason(&self, message: &str) -> Option<&'static str> {
+        if message.chars().count() > self.max_length {
+            Some(TOO_LONG_REASON)
+        } else if !message.chars().all(is_allowed_character) {
+            Some(ILLEGAL_CHARACTERS_REASON)
+        } else {
+            None
+        }
+    }
+}
+
+/// Whether the vanilla server allows the given character in chat
+pub fn is_allowed_character(c: char) -> bool {
+    c != '\u{a7}' && c >= ' ' && c != '\u{7f}'
+}
+
+/// A parsed chat component, e.g. from a ChatMessage
+///
+/// Unset style fields are inherited from the parent component.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct ChatComponent {
+    /// The text, empty for translate components
+    pub text: String,
+    /// The translation key, if this is a translate component
+    pub translate: Option<String>,
+    /// The arguments of the translation
+    pub with: Vec<ChatComponent>,
+    /// The color name, e.g. "dark_red"
+    pub color: Option<String>,
+    pub bold: Option<bool>,
+    pub italic: Option<bool>,
+    pub underlined: Option<bool>,
+    pub strikethrough: Option<bool>,
+    pub obfuscated: Option<bool>,
+    /// The child components, shown after the text
+    pub extra: Vec<ChatComponent>,
+}
+
+/* The translations the vanilla server uses for chat, everything else is
+ * shown as the key followed by the arguments */
+const TRANSLATIONS: [(&str, &str); 8] = [("chat.type.text", "<%s> %s"),
+                                         ("chat.type.announcement", "[%s] %s"),
+                                         ("chat.type.emote", "* %s %s"),
+                                         ("chat.type.admin", "[%s: %s]"),
+                                         ("commands.message.display.incoming", "%s whispers to you: %s"),
+                                         ("commands.message.display.outgoing", "You whisper to %s: %s"),
+                                         ("multiplayer.player.joined", "%s joined the game"),
+                                         ("multiplayer.player.left", "%s left the game")];
+
+/* The formatting of a piece of text, as ANSI SGR codes */
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+struct Style {
+    color: Option<u8>,
+    bold: bool,
+    italic: bool,
+    underlined: bool,
+    strikethrough: bool,
+}
+impl Style {
+    fn to_ansi(self) -> String {
+        let mut codes = vec!["0".to_string()];
+        if let Some(color) = self.color {
+            codes.push(color.to_string());
+        }
+        for &(set, code) in &[(self.bold, "1"),
+                              (self.italic, "3"),
+                              (self.underlined, "4"),
+                              (self.strikethrough, "9")] {
+            if set {
+                codes.push(code.to_string());
+            }
+        }
+        format!("\x1b[{}m", codes.join(";"))
+    }
+}
+
+impl ChatComponent {
+    /// Parse the json of a chat component
+    pub fn parse(json: &str) -> Result<Self> {
+        let value: Value = serde_json::from_str(json)?;
+        Ok(ChatComponent::from_value(&value))
+    }
+
+    fn from_value(value: &Value) -> Self {
+        let mut ret = ChatComponent::default();
+        match *value {
+            Value::Object(ref x) => {
+                let flag = |name: &str| x.get(name).and_then(|x| x.as_bool());
+                ret.text = x.get("text").and_then(|x| x.as_str()).unwrap_or_default().to_string();
+                ret.translate = x.get("translate").and_then(|x| x.as_str()).map(|x| x.to_string());
+                ret.color = x.get("color").and_then(|x| x.as_str()).map(|x| x.to_string());
+                ret.bold = flag("bold");
+                ret.italic = flag("italic");
+                ret.underlined = flag("underlined");
+                ret.strikethrough = flag("strikethrough");
+                ret.obfuscated = flag("obfuscated");
+                if let Some(Value::Array(with)) = x.get("with") {
+                    ret.with = with.iter().map(ChatComponent::from_value).collect();
+                }
+                match x.get("extra") {
+                    Some(Value::Array(extra)) => {
+                        ret.extra = extra.iter().map(ChatComponent::from_value).collect();
+                    },
+                    Some(extra) => ret.extra.push(ChatComponent::from_value(extra)),
+                    None => (),
+                }
+            },
+            /* An array is the first component with the rest as its extra */
+            Value::Array(ref x) => {
+                if let Some((first, rest)) = x.split_first() {
+                    ret = ChatComponent::from_value(first);
+                    ret.extra.extend(rest.iter().map(ChatComponent::from_value));
+                }
+            },
+            Value::String(ref x) => ret.text = x.clone(),
+            Value::Null => (),
+            ref x => ret.text = x.to_string(),
+        }
+        ret
+    }
+
+    /// Serialize the component to json, leaving out unset fields
+    pub fn to_json(&self) -> String {
+        self.to_value().to_string()
+    }
+
+    fn to_value(&self) -> Value {
+        let mut ret = Map::new();
+        if self.translate.is_none() || !self.text.is_empty() {
+            let _ = ret.insert("text".to_string(), Value::from(self.text.clone()));
+        }
+        if let Some(ref x) = self.translate {
+            let _ = ret.insert("translate".to_string(), Value::from(x.clone()));
+        }
+        if !self.with.is_empty() {
+            let with = self.with.iter().map(ChatComponent::to_value).collect();
+            let _ = ret.insert("with".to_string(), Value::Array(with));
+        }
+        if let Some(ref x) = self.color {
+            let _ = ret.insert("color".to_string(), Value::from(x.clone()));
+        }
+        let flags = [("bold", self.bold),
+                     ("italic", self.italic),
+                     ("underlined", self.underlined),
+                     ("strikethrough", self.strikethrough),
+                     ("obfuscated", self.obfuscated)];
+        for &(name, flag) in flags.iter() {
+            if let Some(x) = flag {
+                let _ = ret.insert(name.to_string(), Value::Bool(x));
+            }
+        }
+        if !self.extra.is_empty() {
+            let extra = self.extra.iter().map(ChatComponent::to_value).collect();
+            let _ = ret.insert("extra".to_string(), Value::Array(extra));
+        }
+        Value::Object(ret)
+    }
+
+    /// Get the visible text without any formatting, including legacy
+    /// formatting codes
+    pub fn to_plain(&self) -> String {
+        self.runs().into_iter().map(|x| x.1).collect()
+    }
+
+    /// Get the text with the colors and formatting as ANSI escape codes.
+    ///
+    /// Colors are mapped to the 16 standard terminal colors, and obfuscated
+    /// text is shown as is. Legacy formatting codes (section sign codes) in
+    /// the text are converted too.
+    pub fn to_ansi(&self) -> String {
+        let mut ret = String::new();
+        let mut current = Style::default();
+        for (style, text) in self.runs() {
+            if style != current {
+                ret.push_str(&style.to_ansi());
+                current = style;
+            }
+            ret.push_str(&text);
+        }
+        if current != Style::default() {
+            ret.push_str("\x1b[0m");
+        }
+        ret
+    }
+
+    /// Estimate the rendered width in pixels, see the font module
+    pub fn width(&self) -> u32 {
+        self.runs()
+            .iter()
+            .map(|x| x.1.chars().map(|c| font::char_width(c, x.0.bold)).sum::<u32>())
+            .sum()
+    }
+
+    /// Get to_ansi(), or to_plain() if the NO_COLOR environment variable is
+    /// set or TERM is dumb
+    pub fn to_terminal(&self) -> String {
+        let no_color = env::var_os("NO_COLOR").is_some_and(|x| !x.is_empty()) ||
+                       env::var("TERM").is_ok_and(|x| x == "dumb");
+        if no_color {
+            self.to_plain()
+        } else {
+            self.to_ansi()
+        }
+    }
+
+    /* The component as pieces of text with their style */
+    fn runs(&self) -> Vec<(Style, String)> {
+        let mut ret = Vec::new();
+        self.push_runs(Style::default(), &mut ret);
+        ret.retain(|x| !x.1.is_empty());
+        ret
+    }
+
+    fn push_runs(&self, parent: Style, ret: &mut Vec<(Style, String)>) {
+        let style = Style {
+            color: match self.color {
+                Some(ref x) => color_code(x).or(parent.color),
+                None => parent.color,
+            },
+            bold: self.bold.unwrap_or(parent.bold),
+            italic: self.italic.unwrap_or(parent.italic),
+            underlined: self.underlined.unwrap_or(parent.underlined),
+            strikethrough: self.strikethrough.unwrap_or(parent.strikethrough),
+        };
+        match self.translate {
+            Some(ref key) => self.push_translation(key, style, ret),
+            None => push_legacy(&self.text, style, ret),
+        }
+        for extra in &self.extra {
+            extra.push_runs(style, ret);
+        }
+    }
+
+    fn push_translation(&self, key: &str, style: Style, ret: &mut Vec<(Style, String)>) {
+        let format = match TRANSLATIONS.iter().find(|x| x.0 == key) {
+            Some(x) => x.1,
+            None => {
+                ret.push((style, key.to_string()));
+                for arg in &self.with {
+                    ret.push((style, " ".to_string()));
+                    arg.push_runs(style, ret);
+                }
+                return;
+            },
+        };
+        let mut args = self.with.iter();
+        for (i, part) in format.split("%s").enumerate() {
+            if i != 0 {
+                if let Some(arg) = args.next() {
+                    arg.push_runs(style, ret);
+                }
+            }
+            ret.push((style, part.to_string()));
+        }
+    }
+}
+
+/* Push the text, applying the legacy formatting codes in it */
+fn push_legacy(text: &str, mut style: Style, ret: &mut Vec<(Style, String)>) {
+    let base = style;
+    let mut chars = text.chars();
+    let mut current = String::new();
+    while let Some(c) = chars.next() {
+        if c != '\u{a7}' {
+            current.push(c);
+            continue;
+        }
+        let code = match chars.next() {
+            Some(x) => x.to_ascii_lowercase(),
+            None => break,
+        };
+        ret.push((style, ::std::mem::take(&mut current)));
+        match code {
+            'l' => style.bold = true,
+            'm' => style.strikethrough = true,
+            'n' => style.underlined = true,
+            'o' => style.italic = true,
+            'k' => (),
+            'r' => style = base,
+            /* A color code also resets the formatting */
+            x => if let Some(color) = LEGACY_COLORS.iter().position(|&y| y == x) {
+                style = Style {
+                    color: color_code(COLORS[color].0),
+                    ..Style::default()
+                };
+            },
+        }
+    }
+    ret.push((style, current));
+}
+
+/* The colors by name and ANSI code, in the order of the legacy codes */
+const COLORS: [(&str, u8); 16] = [("black", 30),
+                                  ("dark_blue", 34),
+                                  ("dark_green", 32),
+                                  ("dark_aqua", 36),
+                                  ("dark_red", 31),
+                                  ("dark_purple", 35),
+                                  ("gold", 33),
+                                  ("gray", 37),
+                                  ("dark_gray", 90),
+                                  ("blue", 94),
+                                  ("green", 92),
+                                  ("aqua", 96),
+                                  ("red", 91),
+                                  ("light_purple", 95),
+                                  ("yellow", 93),
+                                  ("white", 97)];
+const LEGACY_COLORS: [char; 16] = ['0', '1', '2', '3', '4', '5', '6', '7', '8', '9', 'a',
+                                   'b', 'c', 'd', 'e', 'f'];
+
+fn color_code(name: &str) -> Option<u8> {
+    COLORS.iter().find(|x| x.0 == name).map(|x| x.1)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn policy() {
+        let mut policy = ChatPolicy::default();
+        assert_eq!(policy.apply("hello"), Some("hello".to_string()));
+        assert_eq!(policy.apply("\u{a7}chello"), None);
+        assert_eq!(policy.apply(&"a".repeat(257)), None);
+        assert_eq!(policy.kick_reason("hello"), None);
+        assert_eq!(policy.kick_reason("\u{a7}chello"), Some(ILLEGAL_CHARACTERS_REASON));
+        assert_eq!(policy.kick_reason(&"a".repeat(257)), Some(TOO_LONG_REASON));
+
+        policy.on_violation = ChatViolation::Truncate;
+        policy.max_length = 5;
+        assert_eq!(policy.apply("\u{a7}chello world"), Some("chell".to_string()));
+        assert_eq!(policy.apply("\n\u{7f}"), None);
+    }
+
+    #[test]
+    fn ansi() {
+        let chat = ChatComponent::parse(r#"{"text": "", "extra": [
+            {"text": "Hello ", "color": "red", "bold": true,
+             "extra": [{"text": "world", "bold": false}]},
+            "!"]}"#).unwrap();
+        assert_eq!(chat.to_plain(), "Hello world!");
+        assert_eq!(chat.to_ansi(),
+                   "\x1b[0;91;1mHello \x1b[0;91mworld\x1b[0m!");
+
+        let chat = ChatComponent::parse(r#"{"translate": "chat.type.text",
+            "with": [{"text": "Notch", "color": "yellow"}, "\u00a7chi \u00a7lthere"]}"#).unwrap();
+        assert_eq!(chat.to_plain(), "<Notch> hi there");
+        assert_eq!(chat.to_ansi(),
+                   "<\x1b[0;93mNotch\x1b[0m> \x1b[0;91mhi \x1b[0;91;1mthere\x1b[0m");
+        assert_eq!(ChatComponent::parse(&chat.to_json()).unwrap(), chat);
+
+        let chat = ChatComponent::parse(r#"{"translate": "unknown.key", "with": ["a"]}"#).unwrap();
+        assert_eq!(chat.to_plain(), "unknown.key a");
+        assert_eq!(chat.width(), font::text_width("unknown.key a"));
+    }
+}
@@ -0,0 +1,528 @@
+//! Helpers for servers that keep their own world model and need to keep
+//! connected clients in sync with it.
+//!
+//! Block states are given as the global palette block state IDs, the same
+//! values used in the BlockChange and MultiBlockChange packets. A full chunk
+//! column is represented as a slice of `CHUNK_VOLUME` block states, indexed
+//! with `block_index`.
+
+use clientbound::{BlockChange, ClientboundPacket, MultiBlockChange,
+                  UnloadChunk};
+use consts::MAX_VIEW_DISTANCE;
+use errors::Result;
+use prepared::PreparedPacket;
+
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::hash::Hash;
+
+/* The changed block states in a chunk, keyed by chunk-relative (x, y, z) */
+type ChunkChanges = BTreeMap<(u8, u8, u8), i32>;
+
+/// The amount of blocks in a single 16x256x16 chunk column
+pub const CHUNK_VOLUME: usize = 16 * 256 * 16;
+
+/// Get the index of the block at the given chunk-relative coordinates, in a
+/// block state slice as used by `diff`.
+///
+/// # Panics
+///
+/// Panics if x or z is not in the range 0-15.
+pub fn block_index(x: u8, y: u8, z: u8) -> usize {
+    if x > 15 || z > 15 {
+        panic!("block_index: x and z must be in the range 0-15");
+    }
+    ((y as usize) << 8) | ((z as usize) << 4) | (x as usize)
+}
+
+/// Compute the packets required to update a client that has the old state of
+/// the given chunk column, to the new state.
+///
+/// Returns nothing if there are no differences, a single BlockChange if only
+/// a single block differs, and else a single MultiBlockChange.
+///
+/// Both old and new must be exactly `CHUNK_VOLUME` long.
+pub fn diff(chunk_x: i32,
+            chunk_z: i32,
+            old: &[i32],
+            new: &[i32])
+            -> Result<Vec<ClientboundPacket>> {
+    if old.len() != CHUNK_VOLUME || new.len() != CHUNK_VOLUME {
+        bail!("chunk::diff expected {} block states, got {} and {}",
+              CHUNK_VOLUME,
+              old.len(),
+              new.len());
+    }
+
+    let mut changes = Vec::new();
+    for (i, (a, b)) in old.iter().zip(new.iter()).enumerate() {
+        if a != b {
+            let x = (i & 0xf) as u8;
+            let z = ((i >> 4) & 0xf) as u8;
+            let y = (i >> 8) as u8;
+            changes.push((x, y, z, *b));
+        }
+    }
+
+    Ok(chunk_packet(chunk_x, chunk_z, changes).into_iter().collect())
+}
+
+/// Records block changes as they happen to the world, so they can be sent to
+/// clients in as few packets as possible.
+///
+/// If the same block is changed several times, only the last change is kept.
+#[derive(Debug, Clone, Default)]
+pub struct ChangeLog {
+    /* Keyed by (chunk_x, chunk_z) */
+    chunks: BTreeMap<(i32, i32), ChunkChanges>,
+}
+impl ChangeLog {
+    pub fn new() -> Self {
+        ChangeLog {
+            chunks: BTreeMap::new(),
+        }
+    }
+
+    /// Record that the block at the given world position was changed to the
+    /// given block state.
+    ///
+    /// Returns an error if y is not in the range 0-255.
+    pub fn set_block(&mut self, pos: (i32, i32, i32), state: i32) -> Result<()> {
+        let (x, y, z) = pos;
+        if !(0..=255).contains(&y) {
+            bail!("ChangeLog::set_block y must be in the range 0-255, was {}",
+                  y);
+        }
+        let chunk = self.chunks.entry((x >> 4, z >> 4)).or_default();
+        let _: Option<i32> =
+            chunk.insert(((x & 0xf) as u8, y as u8, (z & 0xf) as u8), state);
+        Ok(())
+    }
+
+    /// Whether there are no recorded changes
+    pub fn is_empty(&self) -> bool {
+        self.chunks.is_empty()
+    }
+
+    /// Get the chunks that have recorded changes, as (chunk_x, chunk_z)
+    pub fn changed_chunks(&self) -> Vec<(i32, i32)> {
+        self.chunks.keys().cloned().collect()
+    }
+
+    /// Forget all recorded changes
+    pub fn clear(&mut self) {
+        self.chunks.clear()
+    }
+
+    /// Get the packets needed to send all recorded changes, with at most one
+    /// packet per chunk.
+    pub fn to_packets(&self) -> Vec<ClientboundPacket> {
+        self.chunks
+            .iter()
+            .filter_map(|(&(chunk_x, chunk_z), changes)| {
+                let changes = changes.iter()
+                    .map(|(&(x, y, z), &state)| (x, y, z, state))
+                    .collect();
+                chunk_packet(chunk_x, chunk_z, changes)
+            })
+            .collect()
+    }
+
+    /// Get the packets needed to send all the changes in the given chunk
+    pub fn chunk_packets(&self,
+                         chunk_x: i32,
+                         chunk_z: i32)
+                         -> Vec<ClientboundPacket> {
+        match self.chunks.get(&(chunk_x, chunk_z)) {
+            Some(changes) => {
+                let changes = changes.iter()
+                    .map(|(&(x, y, z), &state)| (x, y, z, state))
+                    .collect();
+                chunk_packet(chunk_x, chunk_z, changes).into_iter().collect()
+            },
+            None => Vec::new(),
+        }
+    }
+
+    /// Like to_packets, but also clears the recorded changes
+    pub fn drain_packets(&mut self) -> Vec<ClientboundPacket> {
+        let ret = self.to_packets();
+        self.clear();
+        ret
+    }
+}
+
+/// Prepared ChunkData packets, so that each chunk is serialized and
+/// compressed once and shared by all the players it's sent to, see the
+/// prepared module.
+///
+/// A prepared chunk is out of date once a block in it changes, so the
+/// chunk must be invalidated then, e.g. with `invalidate_changes` before
+/// draining a ChangeLog, to be prepared again when it's next sent.
+#[derive(Debug, Clone, Default)]
+pub struct ChunkCache {
+    chunks: HashMap<(i32, i32), PreparedPacket>,
+}
+impl ChunkCache {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// Get the prepared chunk, if it's cached
+    pub fn get(&self, chunk_x: i32, chunk_z: i32) -> Option<&PreparedPacket> {
+        self.chunks.get(&(chunk_x, chunk_z))
+    }
+
+    /// Get the prepared chunk, preparing the ChunkData packet returned by
+    /// the given function if it isn't cached. Returns None if the function
+    /// does.
+    pub fn get_or_prepare<F>(&mut self,
+                             chunk_x: i32,
+                             chunk_z: i32,
+                             f: F)
+                             -> Result<Option<PreparedPacket>>
+        where F: FnOnce() -> Option<ClientboundPacket>
+    {
+        if let Some(x) = self.get(chunk_x, chunk_z) {
+            return Ok(Some(x.clone()));
+        }
+        match f() {
+            Some(packet) => self.insert(chunk_x, chunk_z, &packet).map(Some),
+            None => Ok(None),
+        }
+    }
+
+    /// Prepare and cache the ChunkData packet of the chunk
+    pub fn insert(&mut self,
+                  chunk_x: i32,
+                  chunk_z: i32,
+                  packet: &ClientboundPacket)
+                  -> Result<PreparedPacket> {
+        let prepared = PreparedPacket::new(packet)?;
+        let _: Option<PreparedPacket> = self.chunks.insert((chunk_x, chunk_z), prepared.clone());
+        Ok(prepared)
+    }
+
+    /// Forget the prepared chunk, returning whether it was cached
+    pub fn invalidate(&mut self, chunk_x: i32, chunk_z: i32) -> bool {
+        self.chunks.remove(&(chunk_x, chunk_z)).is_some()
+    }
+
+    /// Forget the prepared chunk containing the block at the given world
+    /// position
+    pub fn invalidate_block(&mut self, pos: (i32, i32, i32)) -> bool {
+        self.invalidate(pos.0 >> 4, pos.2 >> 4)
+    }
+
+    /// Forget the prepared chunks with changes recorded in the ChangeLog
+    pub fn invalidate_changes(&mut self, log: &ChangeLog) {
+        for (x, z) in log.changed_chunks() {
+            let _: bool = self.invalidate(x, z);
+        }
+    }
+
+    /// The amount of cached chunks
+    pub fn len(&self) -> usize {
+        self.chunks.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.chunks.is_empty()
+    }
+
+    /// Forget all prepared chunks
+    pub fn clear(&mut self) {
+        self.chunks.clear()
+    }
+}
+
+/// Something that can provide ChunkData packets to a ChunkStreamer
+pub trait ChunkSource {
+    /// Get the ChunkData packet for the given chunk.
+    ///
+    /// If the chunk isn't available yet (e.g. it's still being generated),
+    /// return None, and it will be requested again on a later tick.
+    fn chunk_data(&mut self,
+                  chunk_x: i32,
+                  chunk_z: i32)
+                  -> Option<ClientboundPacket>;
+}
+
+/// Decides which chunks to send to and unload from each player, based on
+/// their position.
+///
+/// Call `update_player` whenever a player moves, and `tick` once every tick
+/// to get the packets that should be sent to each player. Chunks are sent
+/// closest first, and at most `send_budget` chunks are sent to each player
+/// per tick, so that players joining or teleporting don't saturate the
+/// connection. Unloading is not limited by the budget.
+///
+/// Players are identified by any key type, e.g. their entity ID or UUID.
+///
+/// Note that protocol 404 has no UpdateViewPosition packet (it was added in
+/// Minecraft 1.14), so no such packets are emitted.
+#[derive(Debug, Clone)]
+pub struct ChunkStreamer<P: Hash + Eq + Clone> {
+    view_distance: i32,
+    send_budget: usize,
+    players: HashMap<P, StreamedPlayer>,
+}
+#[derive(Debug, Clone)]
+struct StreamedPlayer {
+    center: (i32, i32),
+    loaded: HashSet<(i32, i32)>,
+}
+impl<P: Hash + Eq + Clone> ChunkStreamer<P> {
+    /// Create a new ChunkStreamer with the given view distance (in chunks,
+    /// as in the ClientSettings packet) and per-player send budget. The view
+    /// distance is capped at consts::MAX_VIEW_DISTANCE.
+    pub fn new(view_distance: u8, send_budget: usize) -> Self {
+        ChunkStreamer {
+            view_distance: view_distance.min(MAX_VIEW_DISTANCE) as i32,
+            send_budget,
+            players: HashMap::new(),
+        }
+    }
+
+    /// Change the view distance. Takes effect on the next tick.
+    pub fn set_view_distance(&mut self, view_distance: u8) {
+        self.view_distance = view_distance.min(MAX_VIEW_DISTANCE) as i32;
+    }
+
+    /// Change the maximum amount of chunks sent to each player per tick
+    pub fn set_send_budget(&mut self, send_budget: usize) {
+        self.send_budget = send_budget;
+    }
+
+    /// Set the position of the given player, in block coordinates. If the
+    /// player isn't known yet, they're added with no chunks loaded.
+    pub fn update_player(&mut self, player: P, x: f64, z: f64) {
+        let center = ((x.floor() as i32) >> 4, (z.floor() as i32) >> 4);
+        self.players
+            .entry(player)
+            .or_insert_with(|| {
+                StreamedPlayer {
+                    center,
+                    loaded: HashSet::new(),
+                }
+            })
+            .center = center;
+    }
+
+    /// Stop tracking the given player, e.g. because they disconnected.
+    ///
+    /// No UnloadChunk packets are generated.
+    pub fn remove_player(&mut self, player: &P) {
+        let _: Option<StreamedPlayer> = self.players.remove(player);
+    }
+
+    /// Forget that the given player has any chunks loaded, e.g. because they
+    /// changed dimension. The chunks will be sent again on the next tick.
+    pub fn reset_player(&mut self, player: &P) {
+        if let Some(x) = self.players.get_mut(player) {
+            x.loaded.clear();
+        }
+    }
+
+    /// Whether the given player has been sent the given chunk
+    pub fn is_loaded(&self, player: &P, chunk_x: i32, chunk_z: i32) -> bool {
+        self.players
+            .get(player)
+            .map(|x| x.loaded.contains(&(chunk_x, chunk_z)))
+            .unwrap_or(false)
+    }
+
+    /// Get the chunks currently loaded by the given player
+    pub fn loaded_chunks(&self, player: &P) -> Vec<(i32, i32)> {
+        self.players
+            .get(player)
+            .map(|x| x.loaded.iter().cloned().collect())
+            .unwrap_or_default()
+    }
+
+    /// Get the players that have the given chunk loaded, e.g. for sending
+    /// block changes in it.
+    pub fn viewers(&self, chunk_x: i32, chunk_z: i32) -> Vec<P> {
+        self.players
+            .iter()
+            .filter(|&(_, x)| x.loaded.contains(&(chunk_x, chunk_z)))
+            .map(|(p, _)| p.clone())
+            .collect()
+    }
+
+    /// Compute the packets to send to each player this tick.
+    ///
+    /// For each player, this unloads the chunks that are now outside of the
+    /// view distance, and sends the closest missing chunks within it.
+    pub fn tick<S: ChunkSource>(&mut self,
+                                source: &mut S)
+                                -> Vec<(P, ClientboundPacket)> {
+        let mut ret = Vec::new();
+        let distance = self.view_distance;
+
+        for (player, state) in &mut self.players {
+            let (cx, cz) = state.center;
+            let in_range = |&(x, z): &(i32, i32)| {
+                (x - cx).abs() <= distance && (z - cz).abs() <= distance
+            };
+
+            let unload: Vec<(i32, i32)> = state.loaded
+                .iter()
+                .filter(|x| !in_range(x))
+                .cloned()
+                .collect();
+            for (x, z) in unload {
+                let _: bool = state.loaded.remove(&(x, z));
+                ret.push((player.clone(), UnloadChunk::new(x, z)));
+            }
+
+            let mut missing = Vec::new();
+            for x in (cx - distance)..(cx + distance + 1) {
+                for z in (cz - distance)..(cz + distance + 1) {
+                    if !state.loaded.contains(&(x, z)) {
+                        missing.push((x, z));
+                    }
+                }
+            }
+            missing.sort_by_key(|&(x, z)| (x - cx).pow(2) + (z - cz).pow(2));
+
+            let mut sent = 0;
+            for (x, z) in missing {
+                if sent >= self.send_budget {
+                    break;
+                }
+                if let Some(packet) = source.chunk_data(x, z) {
+                    let _: bool = state.loaded.insert((x, z));
+                    ret.push((player.clone(), packet));
+                    sent += 1;
+                }
+            }
+        }
+
+        ret
+    }
+}
+
+/// Create the smallest packet for the given changes within a single chunk
+fn chunk_packet(chunk_x: i32,
+                chunk_z: i32,
+                changes: Vec<(u8, u8, u8, i32)>)
+                -> Option<ClientboundPacket> {
+    match changes.len() {
+        0 => None,
+        1 => {
+            let (x, y, z, state) = changes[0];
+            let pos = (chunk_x * 16 + x as i32,
+                       y as i32,
+                       chunk_z * 16 + z as i32);
+            Some(BlockChange::new(pos, state))
+        },
+        _ => Some(MultiBlockChange::new_raw(chunk_x, chunk_z, changes)),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn diff() {
+        let old = vec![0; CHUNK_VOLUME];
+        let mut new = old.clone();
+        assert!(super::diff(0, 0, &old, &new).unwrap().is_empty());
+
+        new[block_index(1, 64, 2)] = 9;
+        assert_eq!(super::diff(-1, 3, &old, &new).unwrap(),
+                   vec![BlockChange::new((-15, 64, 50), 9)]);
+
+        new[block_index(15, 255, 15)] = 1;
+        assert_eq!(super::diff(-1, 3, &old, &new).unwrap(),
+                   vec![MultiBlockChange::new_raw(-1,
+                                                  3,
+                                                  vec![(1, 64, 2, 9),
+                                                       (15, 255, 15, 1)])]);
+
+        assert!(super::diff(0, 0, &old, &new[1..]).is_err());
+    }
+
+    #[test]
+    fn change_log() {
+        let mut log = ChangeLog::new();
+        assert!(log.is_empty());
+        log.set_block((-1, 10, 17), 5).unwrap();
+        log.set_block((-1, 10, 17), 6).unwrap();
+        log.set_block((3, 11, 4), 1).unwrap();
+        log.set_block((4, 11, 4), 2).unwrap();
+        assert!(log.set_block((0, 256, 0), 1).is_err());
+
+        assert_eq!(log.changed_chunks(), vec![(-1, 1), (0, 0)]);
+        assert_eq!(log.drain_packets(),
+                   vec![BlockChange::new((-1, 10, 17), 6),
+                        MultiBlockChange::new_raw(0,
+                                                  0,
+                                                  vec![(3, 11, 4, 1),
+                                                       (4, 11, 4, 2)])]);
+        assert!(log.is_empty());
+    }
+
+    #[test]
+    fn chunk_cache() {
+        use clientbound::ChunkData;
+
+        let mut cache = ChunkCache::new();
+        let a = cache.get_or_prepare(0, -1, || Some(ChunkData::new(vec![1]))).unwrap().unwrap();
+        let b = cache.get_or_prepare(0, -1, || Some(ChunkData::new(vec![2]))).unwrap().unwrap();
+        assert_eq!(a.data(), b.data());
+        assert!(cache.get_or_prepare(1, 1, || None).unwrap().is_none());
+        assert_eq!(cache.len(), 1);
+
+        let mut log = ChangeLog::new();
+        log.set_block((3, 64, -2), 1).unwrap();
+        cache.invalidate_changes(&log);
+        assert!(cache.is_empty());
+        let _ = cache.insert(0, -1, &ChunkData::new(vec![2])).unwrap();
+        assert!(!cache.invalidate_block((16, 0, 0)));
+        assert!(cache.invalidate_block((15, 0, -16)));
+    }
+
+    #[test]
+    fn chunk_streamer() {
+        use clientbound::ChunkData;
+
+        struct Source;
+        impl ChunkSource for Source {
+            fn chunk_data(&mut self,
+                          chunk_x: i32,
+                          chunk_z: i32)
+                          -> Option<ClientboundPacket> {
+                /* Pretend the chunk at 1, 1 hasn't been generated yet */
+                if (chunk_x, chunk_z) == (1, 1) {
+                    None
+                } else {
+                    Some(ChunkData::new(vec![chunk_x as u8, chunk_z as u8]))
+                }
+            }
+        }
+
+        let mut streamer = ChunkStreamer::new(1, 4);
+        streamer.update_player("a", 8.0, 8.0);
+
+        let first = streamer.tick(&mut Source);
+        assert_eq!(first.len(), 4);
+        assert_eq!(first[0], ("a", ChunkData::new(vec![0, 0])));
+        let second = streamer.tick(&mut Source);
+        assert_eq!(second.len(), 4);
+        assert!(!streamer.is_loaded(&"a", 1, 1));
+        assert!(streamer.tick(&mut Source).is_empty());
+        assert_eq!(streamer.viewers(-1, -1), vec!["a"]);
+
+        /* Moving one chunk in the X direction unloads the 3 chunks at
+         * X = -1, and sends the 3 new chunks at X = 2 */
+        streamer.update_player("a", 20.0, 8.0);
+        let packets = streamer.tick(&mut Source);
+        let unloads = packets.iter()
+            .filter(|x| matches!(x.1, ClientboundPacket::UnloadChunk(_)))
+            .count();
+        assert_eq!(unloads, 3);
+        assert_eq!(packets.len(), 6);
+    }
+}
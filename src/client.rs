@@ -1,39 +1,261 @@
-use clientbound::ClientboundPacket;
-use connection::Connection;
+use block::{self, BlockFace, BlockTracker, Hand, PredictionEvent};
+use clientbound::{ClientboundPacket, LoginPluginRequest};
+use connection::{Connection, Packet, ReadStatus};
+use custom::{CustomPacket, RawPacket};
+use deferred::{self, DeferredPacket};
+use effect::{EffectChange, Effects};
+use entity::Location;
 use errors::Result;
+use extensions::Extensions;
+use forwarding::{self, Forwarding};
 use json::AuthenticationResponse;
+use lifecycle::LifecycleRecord;
+use protocol_data::ProtocolData;
+use read::SmallString;
 use serverbound::ServerboundPacket;
-use {ClientState, PROTOCOL_VERSION, mojang, serverbound, utils};
+use recorder::Recorder;
+use spectator::{GameMode, SpectatorChange, SpectatorState};
+use stats::PacketStats;
+use transition::TransitionLog;
+use transport::Transport;
+use slot::Slot;
+use status::{self, ServerStatus};
+use trade::TradeList;
+use uuid::Uuid;
+use vehicle::{Riding, Vehicle, VehicleChange};
+use watchdog::{self, AntiIdleAction, Watchdog, WatchdogAction, WatchdogOptions};
+use window::{Click, WindowEvent, WindowTransactions};
+use world::{WorldBorder, WorldState};
+use {ClientState, PROTOCOL_VERSION, http, mojang, serverbound, text, utils, vehicle, write};
 
-use std::{thread, time};
+use std::{mem, thread, time};
 use std::net::TcpStream;
 use std::borrow::Borrow;
+use std::sync::Arc;
+use std::sync::mpsc::Sender;
+use std::sync::atomic::{AtomicBool, Ordering};
+#[cfg(unix)]
+use std::os::unix::io::RawFd;
+
+/// Events noticed by the Client while handling packets, see
+/// client.poll_events()
+#[derive(Debug, Clone, PartialEq)]
+pub enum ClientEvent {
+    /// The player's health dropped to 0. Call client.respawn() to respawn.
+    Died,
+    /// The server confirmed that the block being dug at the given position
+    /// was broken
+    BlockBroken((i32, i32, i32)),
+    /// The server rejected breaking the block at the given position, and reset
+    /// it to the given block state
+    DigFailed((i32, i32, i32), i32),
+    /// A window click sent with client.click_window() was confirmed,
+    /// rejected, retried or cancelled
+    Window(WindowEvent),
+    /// Nothing has been received from the server for the given duration, see
+    /// client.set_watchdog()
+    ConnectionUnhealthy(time::Duration),
+    /// An effect of the player was added or removed, see client.effects()
+    Effect(EffectChange),
+    /// A totem of undying saved the player from dying
+    TotemUsed,
+    /// The player's food level dropped to the given level, at or below the
+    /// threshold set with client.set_hunger_threshold(). Call client.eat()
+    /// with food in hand to eat.
+    Hungry(i32),
+    /// The player mounted or dismounted a vehicle, see client.vehicle()
+    Vehicle(VehicleChange),
+    /// The game mode or spectator camera changed, see client.game_mode()
+    /// and client.camera()
+    Spectator(SpectatorChange),
+    /// A predicted block change was confirmed or rolled back, see
+    /// client.set_block_prediction()
+    Prediction(PredictionEvent),
+}
+
+/// The client settings and brand sent to the server when joining, see
+/// client.set_settings()
+#[derive(Debug, Clone, PartialEq)]
+pub struct ClientSettings {
+    /// The client brand sent on the minecraft:brand channel, e.g. "vanilla"
+    pub brand: String,
+    /// The locale, e.g. "en_us"
+    pub locale: String,
+    /// The view distance in chunks
+    pub view_distance: u8,
+    /// 0 for all chat, 1 for only system messages, 2 for hidden
+    pub chat_mode: i32,
+    pub chat_colors: bool,
+    /// Bit mask of the displayed skin parts: 0x01 cape, 0x02 jacket, 0x04
+    /// left sleeve, 0x08 right sleeve, 0x10 left pants leg, 0x20 right pants
+    /// leg and 0x40 hat
+    pub skin_parts: u8,
+    /// 0 for left, 1 for right
+    pub main_hand: i32,
+}
+impl Default for ClientSettings {
+    /// The settings of a vanilla client with default options
+    fn default() -> Self {
+        ClientSettings {
+            brand: "vanilla".to_string(),
+            locale: "en_us".to_string(),
+            view_distance: 10,
+            chat_mode: 0,
+            chat_colors: true,
+            skin_parts: 0x7f,
+            main_hand: 1,
+        }
+    }
+}
+
+/// The most packets a paused client holds on to, see client.pause()
+pub const MAX_PAUSED_PACKETS: usize = 16384;
+
+/// A Client whose connection is being kept alive in the background, see
+/// client.pause()
+pub struct PausedClient {
+    stop: Arc<AtomicBool>,
+    thread: thread::JoinHandle<Result<(Client, Vec<ClientboundPacket>)>>,
+}
+impl PausedClient {
+    /// Resume processing, returning the client and the packets received
+    /// while paused.
+    ///
+    /// Fails if the connection failed while paused, or was closed because
+    /// too many packets were received, in which case the client is gone.
+    pub fn resume(self) -> Result<(Client, Vec<ClientboundPacket>)> {
+        self.stop.store(true, Ordering::SeqCst);
+        match self.thread.join() {
+            Ok(x) => x,
+            Err(_) => bail!("The paused client's thread panicked"),
+        }
+    }
+}
+
+/// Options for connecting with client.connect_unauthenticated_with() and
+/// client.connect_authenticated_with()
+#[derive(Debug, Clone, PartialEq)]
+pub struct ConnectOptions {
+    /// Ping the server for its status before logging in, failing early if
+    /// it uses a different protocol version than PROTOCOL_VERSION, or that
+    /// of protocol_data. The status is available from client.server_status().
+    pub ping_first: bool,
+    /// How long to wait for the status when pinging first
+    pub ping_timeout: time::Duration,
+    /// The host to declare in the Handshake instead of the one connected
+    /// to, e.g. when connecting through a proxy or testing virtual host
+    /// routing
+    pub handshake_host: Option<String>,
+    /// The port to declare in the Handshake instead of the one connected to
+    pub handshake_port: Option<u16>,
+    /// The User-Agent for the requests made while connecting, i.e. joining
+    /// the session, instead of http::user_agent()
+    pub user_agent: Option<String>,
+    /// Connect with another protocol version than PROTOCOL_VERSION, see the
+    /// protocol_data module
+    pub protocol_data: Option<Arc<ProtocolData>>,
+    /// Forward the given player information like a proxy does, to connect
+    /// directly to a backend server, see the forwarding module
+    pub forwarding: Option<Forwarding>,
+}
+impl Default for ConnectOptions {
+    fn default() -> Self {
+        ConnectOptions {
+            ping_first: false,
+            ping_timeout: time::Duration::from_secs(10),
+            handshake_host: None,
+            handshake_port: None,
+            user_agent: None,
+            protocol_data: None,
+            forwarding: None,
+        }
+    }
+}
+
+/* A block being dug with client.dig_block() */
+struct Digging {
+    position: (i32, i32, i32),
+    face: BlockFace,
+    /* When to send the "finished digging" packet, None once it's been sent */
+    finish_at: Option<time::Instant>,
+}
 
 /// Represents a single client connection to a Server.
 pub struct Client {
     conn: Connection<ClientboundPacket, ServerboundPacket>,
     auto_handle: bool,
     hide_handled: bool,
+    position: Option<Location>,
+    health: f32,
+    food: i32,
+    saturation: f32,
+    experience: (f32, i32, i32),
+    events: Vec<ClientEvent>,
+    digging: Option<Digging>,
+    transactions: WindowTransactions,
+    watchdog: Option<Watchdog>,
+    /* Which way to turn the next time AntiIdleAction::Rotate is done */
+    anti_idle_flip: bool,
+    settings: Option<ClientSettings>,
+    /* Whether JoinGame has been received */
+    joined: bool,
+    status: Option<ServerStatus>,
+    world: WorldState,
+    /* The player's entity ID, from JoinGame */
+    entity_id: Option<i32>,
+    effects: Effects,
+    hunger_threshold: Option<i32>,
+    riding: Riding,
+    spectator: SpectatorState,
+    blocks: BlockTracker,
+    extensions: Extensions,
 }
 impl Client {
     /// Attempt open the tcp connection to the given host and port, and
     /// nothing more. If you use this you must then send all subsequent
     /// packets manually to authenticate and so on.
     pub fn connect_tcp(host: &str, port: u16) -> Result<Self> {
-        Ok(Client {
-               conn: Connection::connect_tcp(host, port)?,
-               auto_handle: false,
-               hide_handled: false,
-           })
+        Ok(Client::from_connection(Connection::connect_tcp(host, port)?))
     }
 
     /// Create a new connection from an existing TcpStream
     pub fn from_tcpstream(stream: TcpStream) -> Result<Self> {
-        Ok(Client {
-            conn: Connection::from_tcpstream(stream)?,
+        Ok(Client::from_connection(Connection::from_tcpstream(stream)?))
+    }
+
+    /// Create a new connection over a nonblocking transport other than a
+    /// TcpStream, e.g. a unix socket, see the transport module
+    pub fn from_transport<T: Transport + 'static>(transport: T) -> Result<Self> {
+        Ok(Client::from_connection(Connection::from_transport(Box::new(transport))))
+    }
+
+    fn from_connection(conn: Connection<ClientboundPacket, ServerboundPacket>) -> Self {
+        Client {
+            conn,
             auto_handle: false,
             hide_handled: false,
-        })
+            position: None,
+            health: 20.0,
+            food: 20,
+            saturation: 5.0,
+            experience: (0.0, 0, 0),
+            events: Vec::new(),
+            digging: None,
+            transactions: WindowTransactions::default(),
+            watchdog: None,
+            anti_idle_flip: false,
+            settings: Some(ClientSettings::default()),
+            joined: false,
+            status: None,
+            world: WorldState::default(),
+            entity_id: None,
+            effects: Effects::default(),
+            hunger_threshold: None,
+            riding: Riding::default(),
+            spectator: SpectatorState::default(),
+            blocks: BlockTracker::default(),
+            extensions: Extensions::new(),
+        }
     }
 
     /// Attempt to connect to the server at the given host and port,
@@ -59,19 +281,21 @@ impl Client {
                                    port: u16,
                                    username: &str)
                                    -> Result<Self> {
+        Client::connect_unauthenticated_with(host,
+                                             port,
+                                             username,
+                                             &ConnectOptions::default())
+    }
+
+    /// Like connect_unauthenticated(), with the given options
+    pub fn connect_unauthenticated_with(host: &str,
+                                        port: u16,
+                                        username: &str,
+                                        options: &ConnectOptions)
+                                        -> Result<Self> {
 
         let timeout = time::Instant::now();
-        let mut client = Client::connect_tcp(host, port)?;
-        client.set_auto_handle(true);
-        client.set_hide_handled(true);
-        let handshake = serverbound::Handshake::new(PROTOCOL_VERSION,
-                                                    host.to_string(),
-                                                    port,
-                                                    2);
-        let loginstart = serverbound::LoginStart::new(username.to_string());
-        let _: usize = client.send(handshake)?;
-        client.set_clientstate(ClientState::Login);
-        let _: usize = client.send(loginstart)?;
+        let mut client = Client::start_login(host, port, username, options)?;
 
         /* Now we wait for the PlayerAbilities packet from the server */
         'wait: loop {
@@ -87,6 +311,9 @@ impl Client {
                 Some(ClientboundPacket::EncryptionRequest(..)) => {
                     bail!("connect_unauthenticated got EncryptionRequest");
                 },
+                Some(ClientboundPacket::LoginPluginRequest(ref p)) => {
+                    client.answer_login_plugin(p, options)?;
+                },
                 Some(_) => (),
                 None => thread::sleep(time::Duration::from_millis(10)),
             }
@@ -129,20 +356,21 @@ impl Client {
                                  port: u16,
                                  auth: &AuthenticationResponse)
                                  -> Result<Self> {
+        Client::connect_authenticated_with(host, port, auth, &ConnectOptions::default())
+    }
+
+    /// Like connect_authenticated(), with the given options
+    pub fn connect_authenticated_with(host: &str,
+                                      port: u16,
+                                      auth: &AuthenticationResponse,
+                                      options: &ConnectOptions)
+                                      -> Result<Self> {
 
         let timeout = time::Instant::now();
-        let mut client = Client::connect_tcp(host, port)?;
-        client.set_auto_handle(true);
-        client.set_hide_handled(true);
-        let handshake = serverbound::Handshake::new(PROTOCOL_VERSION,
-                                                    host.to_string(),
-                                                    port,
-                                                    2);
-        let loginstart =
-            serverbound::LoginStart::new(auth.selectedProfile.name.clone());
-        let _: usize = client.send(handshake)?;
-        client.set_clientstate(ClientState::Login);
-        let _: usize = client.send(loginstart)?;
+        let mut client = Client::start_login(host,
+                                             port,
+                                             &auth.selectedProfile.name,
+                                             options)?;
 
         /* Here we wait for a LoginSuccess/EncryptionRequest packet */
         'wait: loop {
@@ -160,12 +388,15 @@ impl Client {
                 Some(ClientboundPacket::EncryptionRequest(ref p)) => {
                     let shared_secret = utils::create_shared_secret();
 
-                    mojang::SessionJoin::new(auth.accessToken.clone(),
-                                             auth.selectedProfile.id.clone(),
-                                             p.get_server_id(),
-                                             &shared_secret,
-                                             p.get_public_key())
-                            .perform()?;
+                    let join = mojang::SessionJoin::new(auth.accessToken.clone(),
+                                                        auth.selectedProfile.id.clone(),
+                                                        p.get_server_id(),
+                                                        &shared_secret,
+                                                        p.get_public_key());
+                    match options.user_agent {
+                        Some(ref x) => http::with_user_agent(x, || join.perform())?,
+                        None => join.perform()?,
+                    }
 
                     let encryptionresponse
                             = serverbound::EncryptionResponse::new_unencrypted(
@@ -177,6 +408,9 @@ impl Client {
 
                     break 'wait;
                 },
+                Some(ClientboundPacket::LoginPluginRequest(ref p)) => {
+                    client.answer_login_plugin(p, options)?;
+                },
                 Some(_) => (),
                 None => thread::sleep(time::Duration::from_millis(10)),
             }
@@ -193,6 +427,9 @@ impl Client {
                     bail!("Got LoginDisconnect, reason: {}", p.get_raw_chat());
                 },
                 Some(ClientboundPacket::LoginSuccess(..)) => break 'wait2,
+                Some(ClientboundPacket::LoginPluginRequest(ref p)) => {
+                    client.answer_login_plugin(p, options)?;
+                },
                 Some(_) => (),
                 None => thread::sleep(time::Duration::from_millis(10)),
             }
@@ -201,10 +438,82 @@ impl Client {
         Ok(client)
     }
 
+    /* Connect and send the Handshake and LoginStart packets, pinging the
+     * server first if requested */
+    fn start_login(host: &str,
+                   port: u16,
+                   username: &str,
+                   options: &ConnectOptions)
+                   -> Result<Self> {
+        let handshake_host = options.handshake_host.as_deref().unwrap_or(host);
+        let forwarded_host = match options.forwarding {
+            Some(ref x) => Some(x.handshake_host(handshake_host)?),
+            None => None,
+        };
+        let handshake_port = options.handshake_port.unwrap_or(port);
+        let protocol = options.protocol_data
+            .as_ref()
+            .map_or(PROTOCOL_VERSION, |x| x.protocol());
+        let status = if options.ping_first {
+            let status = status::ping_as(host,
+                                         port,
+                                         handshake_host,
+                                         handshake_port,
+                                         options.ping_timeout)?;
+            if status.protocol != protocol {
+                bail!("The server runs {} (protocol {}), but only protocol {} is supported",
+                      status.version_name,
+                      status.protocol,
+                      protocol);
+            }
+            Some(status)
+        } else {
+            None
+        };
+
+        let mut client = Client::connect_tcp(host, port)?;
+        client.status = status;
+        client.set_auto_handle(true);
+        client.set_hide_handled(true);
+        client.set_protocol_data(options.protocol_data.clone());
+        let handshake = serverbound::Handshake::new(protocol,
+                                                    forwarded_host.unwrap_or_else(|| handshake_host.to_string()),
+                                                    handshake_port,
+                                                    2);
+        let loginstart = serverbound::LoginStart::new(username.to_string());
+        let _: usize = client.send(handshake)?;
+        client.set_clientstate(ClientState::Login);
+        let _: usize = client.send(loginstart)?;
+        Ok(client)
+    }
+
+    /* Answer a LoginPluginRequest, with the forwarded player information if
+     * it's for Velocity's modern forwarding */
+    fn answer_login_plugin(&mut self,
+                           request: &LoginPluginRequest,
+                           options: &ConnectOptions)
+                           -> Result<()> {
+        let answer = match options.forwarding {
+            Some(ref x) => x.answer(request)?,
+            None => forwarding::not_understood(request),
+        };
+        let _: usize = self.send(answer)?;
+        Ok(())
+    }
+
+    /// Get the status of the server from the ping done before connecting,
+    /// if ConnectOptions::ping_first was set
+    pub fn server_status(&self) -> Option<&ServerStatus> {
+        self.status.as_ref()
+    }
+
     /// Try to read some packets from the server.
     ///
     /// This function is nonblocking.
     pub fn read(&mut self) -> Result<Vec<ClientboundPacket>> {
+        self.update_digging()?;
+        let expired = self.blocks.expire(time::Instant::now());
+        self.events.extend(expired.into_iter().map(ClientEvent::Prediction));
         self.update_inbuf()?;
 
         let mut ret = Vec::new();
@@ -227,6 +536,7 @@ impl Client {
                          .expect("unreachable packet = None in client.read()"));
             }
         }
+        self.check_watchdog()?;
 
         Ok(ret)
     }
@@ -236,24 +546,704 @@ impl Client {
     /// This adds the packet to the outgoing buffer, and sends as much as is
     /// possible. Returns the length of the outgoing buffer. If this is greater
     /// than 0, you will need to call write() to send the remaining data.
+    ///
+    /// Sending any of the player movement packets updates the position
+    /// returned by client.position().
     pub fn send<T: Borrow<ServerboundPacket>>(&mut self, packet: T) -> Result<usize> {
-        self.conn.send(packet.borrow())
+        let packet = packet.borrow();
+        if let Some(ref mut pos) = self.position {
+            match *packet {
+                ServerboundPacket::Player(ref p) => {
+                    pos.on_ground = *p.get_on_ground();
+                },
+                ServerboundPacket::PlayerPosition(ref p) => {
+                    pos.x = *p.get_x();
+                    pos.y = *p.get_y();
+                    pos.z = *p.get_z();
+                    pos.on_ground = *p.get_on_ground();
+                },
+                ServerboundPacket::PlayerPositionAndLook(ref p) => {
+                    pos.x = *p.get_x();
+                    pos.y = *p.get_y();
+                    pos.z = *p.get_z();
+                    pos.yaw = *p.get_yaw();
+                    pos.pitch = *p.get_pitch();
+                    pos.on_ground = *p.get_on_ground();
+                },
+                ServerboundPacket::PlayerLook(ref p) => {
+                    pos.yaw = *p.get_yaw();
+                    pos.pitch = *p.get_pitch();
+                    pos.on_ground = *p.get_on_ground();
+                },
+                _ => (),
+            }
+        }
+        if let Some(ref mut watchdog) = self.watchdog {
+            if watchdog::is_player_action(packet) {
+                watchdog.sent(time::Instant::now());
+            }
+        }
+        self.conn.send(packet)
     }
 
     /// Write from the outgoing buffer to the TcpStream
     ///
-    /// Returns the amount of bytes written.
+    /// Returns the amount of bytes written, which is 0 if the socket can't
+    /// take any more data right now.
     pub fn write(&mut self) -> Result<usize> {
         self.conn.write()
     }
 
-    /// Whether to automatically handle: KeepAlive, LoginSuccess and
-    /// SetCompression packets. Most clients won't need to manually deal with
-    /// these.
+    /// Get the amount of bytes in the outgoing buffer not yet written to the
+    /// TcpStream
+    pub fn pending_write(&self) -> usize {
+        self.conn.pending_write()
+    }
+
+    /// Set how long to wait for data before the connection is closed with a
+    /// "Read timeout" error, or None to never time out. Defaults to 30
+    /// seconds.
+    pub fn set_read_timeout(&mut self, timeout: Option<time::Duration>) {
+        self.conn.set_read_timeout(timeout)
+    }
+
+    /// Get the ID of this connection, as used in lifecycle events
+    pub fn connection_id(&self) -> u64 {
+        self.conn.connection_id()
+    }
+
+    /// Set the channel lifecycle events of this connection are sent to, see
+    /// the lifecycle module
+    pub fn set_lifecycle_sender(&mut self, sender: Option<Sender<LifecycleRecord>>) {
+        self.conn.set_lifecycle_sender(sender)
+    }
+
+    /// Translate the packet IDs to and from those of another protocol
+    /// version, or None to use those of PROTOCOL_VERSION, see the
+    /// protocol_data module
+    pub fn set_protocol_data(&mut self, data: Option<Arc<ProtocolData>>) {
+        match data {
+            Some(data) => self.conn.set_packet_ids(Some(data.clientbound_ids()),
+                                                   Some(data.serverbound_ids())),
+            None => self.conn.set_packet_ids(None, None),
+        }
+    }
+
+    /// Register a custom packet, so that received packets with its ID are
+    /// kept for read_custom() instead of being decoded, see the custom module
+    pub fn register_custom<P: CustomPacket>(&mut self) {
+        self.conn.register_custom(P::STATE, P::ID, P::NAME)
+    }
+
+    /// Send a custom packet, see the custom module
+    pub fn send_custom<P: CustomPacket>(&mut self, packet: &P) -> Result<usize> {
+        self.conn.send_raw(packet.to_u8()?, P::NAME)
+    }
+
+    /// Get the next received packet with a registered custom ID, if any
+    pub fn read_custom(&mut self) -> Option<RawPacket> {
+        self.conn.read_custom()
+    }
+
+    /// Set whether to keep received ChunkData and DeclareRecipes packets
+    /// compressed for read_deferred(), instead of decoding them and returning
+    /// them from read(), see the deferred module. Defaults to false.
+    pub fn set_deferred_decode(&mut self, enable: bool) {
+        if !enable {
+            return self.conn.clear_deferred();
+        }
+        for name in deferred::DEFERRED_PACKETS {
+            if let Some(id) = ClientboundPacket::id_by_name(&ClientState::Play, name) {
+                self.conn.register_deferred(ClientState::Play, id, name);
+            }
+        }
+    }
+
+    /// Get the next received packet kept compressed, if any, see
+    /// set_deferred_decode()
+    pub fn read_deferred(&mut self) -> Option<DeferredPacket> {
+        self.conn.read_deferred()
+    }
+
+    /// Get the player's current position, as last set by the server or sent
+    /// by us.
+    ///
+    /// Returns None until the server has sent the first PlayerPositionAndLook
+    /// packet or client.set_position() has been called. Positions sent by
+    /// the server are only tracked if auto_handle is true, but those set
+    /// with client.set_position() always are.
+    pub fn position(&self) -> Option<Location> {
+        self.position
+    }
+
+    /// Move the player to the given location, by sending a
+    /// PlayerPositionAndLook packet.
+    ///
+    /// Returns the length of the outgoing buffer, like client.send()
+    pub fn set_position(&mut self, location: Location) -> Result<usize> {
+        self.position = Some(location);
+        let packet =
+            serverbound::PlayerPositionAndLook::new(location.x,
+                                                    location.y,
+                                                    location.z,
+                                                    location.yaw,
+                                                    location.pitch,
+                                                    location.on_ground);
+        self.send(packet)
+    }
+
+    /// Get the player's health, from 0 to 20 (inclusive.) 0 means dead.
+    ///
+    /// This, like the other health, food and experience getters, is only
+    /// updated if auto_handle is true.
+    pub fn health(&self) -> f32 {
+        self.health
+    }
+
+    /// Get the player's food level, from 0 to 20 (inclusive)
+    pub fn food(&self) -> i32 {
+        self.food
+    }
+
+    /// Get the player's food saturation level
+    pub fn saturation(&self) -> f32 {
+        self.saturation
+    }
+
+    /// Get the player's experience as (experience bar from 0 to 1, level,
+    /// total experience)
+    pub fn experience(&self) -> (f32, i32, i32) {
+        self.experience
+    }
+
+    /// Whether the player is dead and needs to respawn
+    pub fn is_dead(&self) -> bool {
+        self.health <= 0.0
+    }
+
+    /// Get the time, weather and world border as last sent by the server.
+    ///
+    /// Like the health, this is only updated if auto_handle is true.
+    pub fn world(&self) -> &WorldState {
+        &self.world
+    }
+
+    /// Get the time of day in ticks, see the world module
+    pub fn time_of_day(&self) -> i64 {
+        self.world.time_of_day()
+    }
+
+    /// Get the age of the world in ticks
+    pub fn world_age(&self) -> i64 {
+        self.world.world_age()
+    }
+
+    /// Whether it's raining
+    pub fn is_raining(&self) -> bool {
+        self.world.is_raining()
+    }
+
+    /// Get the world border, or None if the server hasn't sent it yet
+    pub fn world_border(&self) -> Option<&WorldBorder> {
+        self.world.world_border()
+    }
+
+    /// Get the player's entity ID, or None before joining the game
+    pub fn entity_id(&self) -> Option<i32> {
+        self.entity_id
+    }
+
+    /// Get the state attached to this connection, see the extensions module
+    pub fn extensions(&self) -> &Extensions {
+        &self.extensions
+    }
+
+    /// Get the state attached to this connection mutably
+    pub fn extensions_mut(&mut self) -> &mut Extensions {
+        &mut self.extensions
+    }
+
+    /// Get the player's effects, e.g. from potions.
+    ///
+    /// Like the health, this is only updated if auto_handle is true.
+    pub fn effects(&self) -> &Effects {
+        &self.effects
+    }
+
+    /// Generate a ClientEvent::Hungry when the food level drops to or below
+    /// the given level, or never if None, which is the default. Vanilla
+    /// players can't sprint at 6 or below, and stop healing below 18.
+    pub fn set_hunger_threshold(&mut self, threshold: Option<i32>) {
+        self.hunger_threshold = threshold;
+    }
+
+    /// Start eating or drinking the item in the given hand, by sending a
+    /// UseItem packet. The server finishes eating after 32 ticks, as long as
+    /// the player keeps holding the item.
+    ///
+    /// Returns the length of the outgoing buffer, like client.send()
+    pub fn eat(&mut self, hand: Hand) -> Result<usize> {
+        self.send(serverbound::UseItem::new(hand.to_id()))
+    }
+
+    /// Set the text of the sign at the given position, see
+    /// text::update_sign()
+    ///
+    /// Returns the length of the outgoing buffer, like client.send()
+    pub fn update_sign(&mut self, position: (i32, i32, i32), lines: &[&str]) -> Result<usize> {
+        self.send(text::update_sign(position, lines)?)
+    }
+
+    /// Write the pages into the book and quill in the given hand, and sign
+    /// it if there is a title, see text::edit_book()
+    ///
+    /// Returns the length of the outgoing buffer, like client.send()
+    pub fn edit_book(&mut self,
+                     book: &Slot,
+                     pages: &[&str],
+                     title: Option<&str>,
+                     hand: Hand)
+                     -> Result<usize> {
+        self.send(text::edit_book(book, pages, title, hand)?)
+    }
+
+    /// Set the name of the item in the open anvil
+    ///
+    /// Returns the length of the outgoing buffer, like client.send()
+    pub fn rename_item(&mut self, name: &str) -> Result<usize> {
+        self.send(text::rename_item(name)?)
+    }
+
+    /// Get the vehicle the player is riding, or None if it isn't riding
+    /// anything. Only updated if auto_handle is true.
+    pub fn vehicle(&self) -> Option<&Vehicle> {
+        self.riding.vehicle()
+    }
+
+    /// Mount the vehicle with the given entity ID, by interacting with it.
+    /// A ClientEvent::Vehicle is generated once the server confirms it.
+    ///
+    /// Returns the length of the outgoing buffer, like client.send()
+    pub fn mount(&mut self, entity_id: i32) -> Result<usize> {
+        self.send(vehicle::mount(entity_id, Hand::Main))
+    }
+
+    /// Dismount the vehicle
+    ///
+    /// Returns the length of the outgoing buffer, like client.send()
+    pub fn dismount(&mut self) -> Result<usize> {
+        self.send(vehicle::dismount())
+    }
+
+    /// Steer the vehicle, see vehicle::steer(). Like the movement keys of
+    /// the vanilla client, this must be sent every tick while steering.
+    ///
+    /// Returns the length of the outgoing buffer, like client.send()
+    pub fn steer(&mut self, forward: f32, sideways: f32, jump: bool) -> Result<usize> {
+        self.send(vehicle::steer(forward, sideways, jump, false))
+    }
+
+    /// Turn the paddles of the boat being ridden
+    ///
+    /// Returns the length of the outgoing buffer, like client.send()
+    pub fn steer_boat(&mut self, left: bool, right: bool) -> Result<usize> {
+        self.send(vehicle::steer_boat(left, right))
+    }
+
+    /// Move the vehicle being ridden, if it's one the client controls, such
+    /// as a boat or a horse
+    ///
+    /// Returns the length of the outgoing buffer, like client.send()
+    pub fn move_vehicle(&mut self, location: Location) -> Result<usize> {
+        self.riding.set_location(location);
+        self.send(vehicle::move_vehicle(&location))
+    }
+
+    /// Make the horse being ridden jump, with a strength from 0 to 100
+    ///
+    /// Returns the length of the outgoing buffer, like client.send()
+    pub fn horse_jump(&mut self, strength: i32) -> Result<usize> {
+        match self.entity_id {
+            Some(entity_id) => self.send(vehicle::horse_jump(entity_id, strength)),
+            None => bail!("Not in game yet"),
+        }
+    }
+
+    /// Get the player's game mode, or None before joining the game
+    pub fn game_mode(&self) -> Option<GameMode> {
+        self.spectator.game_mode()
+    }
+
+    /// Get the entity whose view the player is spectating, or None if it's
+    /// the player's own. Only updated if auto_handle is true.
+    pub fn camera(&self) -> Option<i32> {
+        self.spectator.camera()
+    }
+
+    /// Teleport to the player with the given UUID, which only works in
+    /// spectator mode
+    ///
+    /// Returns the length of the outgoing buffer, like client.send()
+    pub fn spectate(&mut self, uuid: &Uuid) -> Result<usize> {
+        match self.game_mode() {
+            None | Some(GameMode::Spectator) => self.send(serverbound::Spectate::new(uuid.0)),
+            Some(x) => bail!("Can't spectate in {:?} mode", x),
+        }
+    }
+
+    /// Stop looking through another entity, by sneaking like the vanilla
+    /// client
+    ///
+    /// Returns the length of the outgoing buffer, like client.send()
+    pub fn stop_spectating(&mut self) -> Result<usize> {
+        let entity_id = match self.entity_id {
+            Some(x) => x,
+            None => bail!("Not in game yet"),
+        };
+        let _: usize = self.send(serverbound::EntityAction::new(entity_id, 0, 0))?;
+        self.send(serverbound::EntityAction::new(entity_id, 1, 0))
+    }
+
+    /// Respawn after dying, by sending a ClientStatus packet with the
+    /// "perform respawn" action.
+    ///
+    /// Returns the length of the outgoing buffer, like client.send()
+    pub fn respawn(&mut self) -> Result<usize> {
+        self.send(serverbound::ClientStatus::new(0))
+    }
+
+    /// Start digging the block at the given position, finishing after the
+    /// given amount of ticks (see block::break_ticks().)
+    ///
+    /// The "finished digging" packet is sent by client.read() once the time
+    /// is up, so keep calling that as usual. When the server acknowledges the
+    /// block change, a BlockBroken or DigFailed event is generated. Starting
+    /// to dig another block cancels the current one.
+    ///
+    /// Returns the length of the outgoing buffer, like client.send()
+    pub fn dig_block(&mut self,
+                     position: (i32, i32, i32),
+                     face: BlockFace,
+                     ticks: u32)
+                     -> Result<usize> {
+        self.cancel_digging()?;
+        let start = serverbound::PlayerDigging::new(0,
+                                                    position,
+                                                    face.to_id() as u8);
+        let _: usize = self.send(start)?;
+        /* Blocks that break instantly only need the start packet */
+        let finish_at = if ticks == 0 {
+            self.blocks.predict(position, block::AIR);
+            None
+        } else {
+            Some(time::Instant::now() + time::Duration::from_millis(50) * ticks)
+        };
+        self.digging = Some(Digging {
+                                position,
+                                face,
+                                finish_at,
+                            });
+        self.send(serverbound::Animation::new(Hand::Main.to_id()))
+    }
+
+    /// Stop digging the block currently being dug, if any
+    pub fn cancel_digging(&mut self) -> Result<()> {
+        if let Some(Digging { position, face, finish_at: Some(_) }) =
+            self.digging.take() {
+            let cancel = serverbound::PlayerDigging::new(1,
+                                                         position,
+                                                         face.to_id() as u8);
+            let _: usize = self.send(cancel)?;
+        }
+        Ok(())
+    }
+
+    /// Place the block held in the given hand against the given face of the
+    /// block at the given position.
+    ///
+    /// The server answers by sending BlockChange packets for both the clicked
+    /// block and the block placed (see BlockFace::offset()), regardless of
+    /// whether placing succeeded.
+    ///
+    /// Returns the length of the outgoing buffer, like client.send()
+    pub fn place_block(&mut self,
+                       position: (i32, i32, i32),
+                       face: BlockFace,
+                       hand: Hand)
+                       -> Result<usize> {
+        let place = serverbound::PlayerBlockPlacement::new(position,
+                                                           face.to_id(),
+                                                           hand.to_id(),
+                                                           0.5,
+                                                           0.5,
+                                                           0.5);
+        let _: usize = self.send(place)?;
+        self.send(serverbound::Animation::new(hand.to_id()))
+    }
+
+    /// Like client.place_block(), but with prediction enabled, the placed
+    /// block is set to the given state until the server confirms it
+    ///
+    /// Returns the length of the outgoing buffer, like client.send()
+    pub fn place_block_predicted(&mut self,
+                                 position: (i32, i32, i32),
+                                 face: BlockFace,
+                                 hand: Hand,
+                                 state: i32)
+                                 -> Result<usize> {
+        self.blocks.predict(face.offset(position), state);
+        self.place_block(position, face, hand)
+    }
+
+    /// Enable or disable applying the client's own digging and placing to
+    /// the tracked blocks before the server confirms it, see the block
+    /// module. It's disabled by default. The outcome of each prediction is
+    /// reported as a ClientEvent::Prediction.
+    pub fn set_block_prediction(&mut self, enabled: bool) {
+        let events = self.blocks.set_prediction(enabled);
+        self.events.extend(events.into_iter().map(ClientEvent::Prediction));
+    }
+
+    /// Get the tracked block states, see the block module. Only updated if
+    /// auto_handle is true.
+    pub fn blocks(&self) -> &BlockTracker {
+        &self.blocks
+    }
+
+    /// Get the state of the block at the given position, including
+    /// predictions, or None if it's unknown
+    pub fn block_at(&self, position: (i32, i32, i32)) -> Option<i32> {
+        self.blocks.get(position)
+    }
+
+    /// Click in an inventory window.
+    ///
+    /// Clicks are sent one at a time: if an earlier click hasn't been
+    /// confirmed by the server yet, this one is queued until it has. Rejected
+    /// clicks are retried once. The outcome is reported as a
+    /// ClientEvent::Window event. This requires auto_handle to be true.
+    pub fn click_window(&mut self, click: Click) -> Result<()> {
+        for packet in self.transactions.click(click) {
+            let _: usize = self.send(packet)?;
+        }
+        Ok(())
+    }
+
+    /// Do the trade with the given index in an open trading window, using
+    /// the inputs in the given window slots, see TradeList::clicks().
+    ///
+    /// The clicks are queued like with client.click_window(), so watch for
+    /// the WindowEvents to know whether the trade went through.
+    pub fn trade(&mut self, list: &TradeList, index: usize, inputs: &[(i16, Slot)]) -> Result<()> {
+        let clicks = list.clicks(index, inputs)?;
+        let _: usize = self.send(list.select(index)?)?;
+        for click in clicks {
+            self.click_window(click)?;
+        }
+        Ok(())
+    }
+
+    /// Set how many times rejected window clicks are retried before giving
+    /// up. The default is 1. This applies to the clicks already sent or
+    /// queued as well.
+    pub fn set_click_retries(&mut self, max_retries: u32) {
+        self.transactions.set_max_retries(max_retries);
+    }
+
+    /* Send the "finished digging" packet if it's time to */
+    fn update_digging(&mut self) -> Result<()> {
+        let finish = match self.digging {
+            Some(Digging { position, face, finish_at: Some(at) })
+                if at <= time::Instant::now() => Some((position, face)),
+            _ => None,
+        };
+        if let Some((position, face)) = finish {
+            if let Some(ref mut digging) = self.digging {
+                digging.finish_at = None;
+            }
+            let finish = serverbound::PlayerDigging::new(2,
+                                                         position,
+                                                         face.to_id() as u8);
+            let _: usize = self.send(finish)?;
+            self.blocks.predict(position, block::AIR);
+        }
+        Ok(())
+    }
+
+    /* Check whether a block change acknowledges the block being dug or a
+     * prediction */
+    fn block_changed(&mut self, position: (i32, i32, i32), state: i32) {
+        if let Some(event) = self.blocks.server_update(position, state) {
+            self.events.push(ClientEvent::Prediction(event));
+        }
+        let done = match self.digging {
+            Some(ref d) => d.position == position && d.finish_at.is_none(),
+            None => false,
+        };
+        if done {
+            self.digging = None;
+            self.events.push(if state == block::AIR {
+                ClientEvent::BlockBroken(position)
+            } else {
+                ClientEvent::DigFailed(position, state)
+            });
+        }
+    }
+
+    /// Get all the events that have happened since the last call to this
+    /// function.
+    ///
+    /// Events are only generated if auto_handle is true.
+    pub fn poll_events(&mut self) -> Vec<ClientEvent> {
+        mem::take(&mut self.events)
+    }
+
+    /// Pause processing of the connection, e.g. while a GUI is frozen.
+    ///
+    /// The client is moved to a background thread that keeps reading from
+    /// the connection with auto_handle enabled, so that KeepAlives are
+    /// answered and teleports are confirmed and the server doesn't
+    /// disconnect the client. Nothing is delivered to the consumer until
+    /// paused.resume() is called, which returns the client along with the
+    /// packets received in the meantime, as client.read() would have
+    /// returned them. Events are kept for client.poll_events().
+    ///
+    /// If more than MAX_PAUSED_PACKETS packets are received while paused,
+    /// the connection is closed rather than holding on to ever more of
+    /// them, and paused.resume() fails.
+    pub fn pause(self) -> PausedClient {
+        let stop = Arc::new(AtomicBool::new(false));
+        let thread_stop = stop.clone();
+        let thread = thread::spawn(move || {
+            let mut client = self;
+            let auto_handle = client.auto_handle;
+            client.auto_handle = true;
+            let mut packets = Vec::new();
+            while !thread_stop.load(Ordering::SeqCst) {
+                packets.extend(client.read()?);
+                if packets.len() > MAX_PAUSED_PACKETS {
+                    let _: Result<()> = client.close();
+                    bail!("Received more than {} packets while paused", MAX_PAUSED_PACKETS);
+                }
+                thread::sleep(time::Duration::from_millis(10));
+            }
+            client.auto_handle = auto_handle;
+            Ok((client, packets))
+        });
+        PausedClient { stop, thread }
+    }
+
+    /// Enable or disable (with None) the connection watchdog.
+    ///
+    /// When enabled, client.read() generates a ConnectionUnhealthy event if
+    /// nothing has been received for a while, and performs anti-idle actions
+    /// if configured to. See the watchdog module for details.
+    pub fn set_watchdog(&mut self, options: Option<WatchdogOptions>) {
+        self.watchdog = options.map(Watchdog::new);
+    }
+
+    /* Run the watchdog check, if enabled */
+    fn check_watchdog(&mut self) -> Result<()> {
+        let actions = match self.watchdog {
+            Some(ref mut watchdog) => watchdog.check(time::Instant::now()),
+            None => return Ok(()),
+        };
+        for action in actions {
+            match action {
+                WatchdogAction::Unhealthy(duration) => {
+                    self.events.push(ClientEvent::ConnectionUnhealthy(duration));
+                },
+                WatchdogAction::AntiIdle(actions) => {
+                    for action in actions {
+                        self.anti_idle(action)?;
+                    }
+                },
+            }
+        }
+        Ok(())
+    }
+
+    fn anti_idle(&mut self, action: AntiIdleAction) -> Result<()> {
+        match action {
+            AntiIdleAction::SwingArm => {
+                let swing = serverbound::Animation::new(Hand::Main.to_id());
+                let _: usize = self.send(swing)?;
+            },
+            AntiIdleAction::Rotate => {
+                if let Some(position) = self.position {
+                    self.anti_idle_flip = !self.anti_idle_flip;
+                    let turn = if self.anti_idle_flip { 1.0 } else { -1.0 };
+                    let look = serverbound::PlayerLook::new(position.yaw + turn,
+                                                            position.pitch,
+                                                            position.on_ground);
+                    let _: usize = self.send(look)?;
+                }
+            },
+        }
+        Ok(())
+    }
+
+    /// Whether to automatically handle: KeepAlive, LoginSuccess,
+    /// SetCompression, JoinGame and PlayerPositionAndLook packets. Most
+    /// clients won't need to manually deal with these.
+    ///
+    /// JoinGame is handled by sending the brand and client settings, see
+    /// client.set_settings().
+    ///
+    /// PlayerPositionAndLook packets are handled by confirming the teleport
+    /// and sending back the new position, as the vanilla client does. They
+    /// are never hidden, since consumers most likely want to know about them.
     pub fn set_auto_handle(&mut self, new: bool) {
         self.auto_handle = new;
     }
 
+    /// Set the brand and client settings to send to the server.
+    ///
+    /// If auto_handle is true, they are sent when JoinGame is received, like
+    /// the vanilla client does. Servers often expect them, so they default to
+    /// those of a vanilla client. If the client has already joined, the new
+    /// settings are sent right away, along with the brand if it changed.
+    /// None disables sending them.
+    pub fn set_settings(&mut self, settings: Option<ClientSettings>) -> Result<()> {
+        let old_brand = self.settings.as_ref().map(|x| x.brand.clone());
+        self.settings = settings;
+        if self.joined {
+            if let Some(ref settings) = self.settings {
+                let brand = old_brand.as_ref() != Some(&settings.brand);
+                self.send_settings(brand)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Get the client settings that are sent to the server, if any
+    pub fn settings(&self) -> Option<&ClientSettings> {
+        self.settings.as_ref()
+    }
+
+    /* Send the client settings, and the brand if requested */
+    fn send_settings(&mut self, brand: bool) -> Result<()> {
+        let settings = match self.settings {
+            Some(ref x) => x.clone(),
+            None => return Ok(()),
+        };
+        if brand {
+            let mut data = Vec::new();
+            write::write_String(&settings.brand, &mut data)?;
+            let message = serverbound::PluginMessage::new(SmallString::from("minecraft:brand"),
+                                                          data);
+            let _: usize = self.send(message)?;
+        }
+        let packet = serverbound::ClientSettings::new(SmallString::from(settings.locale.as_str()),
+                                                      settings.view_distance,
+                                                      settings.chat_mode,
+                                                      settings.chat_colors,
+                                                      settings.skin_parts,
+                                                      settings.main_hand);
+        let _: usize = self.send(packet)?;
+        Ok(())
+    }
+
     /// Whether or not to hide packets that have been handled by ozelot from the
     /// consumer of the library.
     ///
@@ -269,6 +1259,34 @@ impl Client {
         self.conn.close()
     }
 
+    /// Enable or disable collecting statistics about the packets sent and
+    /// received, see the stats module. Disabling clears the statistics.
+    pub fn enable_packet_stats(&mut self, enable: bool) {
+        self.conn.enable_packet_stats(enable)
+    }
+
+    /// Get the packet statistics, if enabled
+    pub fn packet_stats(&self) -> Option<&PacketStats> {
+        self.conn.packet_stats()
+    }
+
+    /// Record the packets sent and received with the given recorder, see
+    /// the recorder module. Returns the previous recorder, if any.
+    pub fn set_recorder(&mut self, recorder: Option<Recorder>) -> Option<Recorder> {
+        self.conn.set_recorder(recorder)
+    }
+
+    /// Get the current client state of this connection
+    pub fn clientstate(&self) -> &ClientState {
+        self.conn.clientstate()
+    }
+
+    /// Get the log of the state transitions of this connection, see the
+    /// transition module
+    pub fn transition_log(&mut self) -> &mut TransitionLog {
+        self.conn.transitions()
+    }
+
     /// Change the client state of this connection
     pub fn set_clientstate(&mut self, new_state: ClientState) {
         self.conn.set_clientstate(new_state)
@@ -290,6 +1308,16 @@ impl Client {
         self.conn.enable_compression(threshold)
     }
 
+    /// Set the zlib compression level used for outgoing packets once
+    /// compression is enabled, from 0 (fastest) to 9 (smallest). Defaults
+    /// to 6.
+    ///
+    /// Lower levels trade bandwidth for CPU time, which is usually worth it
+    /// when sending a lot of chunk data.
+    pub fn set_compression_level(&mut self, level: u32) -> Result<()> {
+        self.conn.set_compression_level(level)
+    }
+
     /// Read from the TcpStream and update the incoming buffer.
     ///
     /// This is the only way to actually read from the TcpStream. Unless you
@@ -302,6 +1330,24 @@ impl Client {
         self.conn.update_inbuf()
     }
 
+    /// Read a single packet, reading from the TcpStream only if the internal
+    /// buffer doesn't contain a complete one.
+    ///
+    /// This is meant for poll loops (e.g. mio) with nonblocking sockets: call
+    /// it whenever the socket is readable until it returns NeedMore. Partial
+    /// packets are kept in the internal buffer until the rest arrives. An
+    /// error is returned once the server has closed the connection.
+    pub fn try_read_packet(&mut self) -> Result<ReadStatus<ClientboundPacket>> {
+        if let Some(packet) = self.read_packet()? {
+            return Ok(ReadStatus::Ready(packet));
+        }
+        self.update_inbuf()?;
+        Ok(match self.read_packet()? {
+               Some(packet) => ReadStatus::Ready(packet),
+               None => ReadStatus::NeedMore,
+           })
+    }
+
     /// Read a single packet from the internal buffer.
     ///
     /// This is only really useful if you want finegrained control over the
@@ -314,6 +1360,12 @@ impl Client {
     pub fn read_packet(&mut self) -> Result<Option<ClientboundPacket>> {
         let packet = self.conn.read_packet()?;
 
+        if packet.is_some() {
+            if let Some(ref mut watchdog) = self.watchdog {
+                watchdog.received(time::Instant::now());
+            }
+        }
+
         if self.auto_handle {
             match &packet {
                 &Some(ClientboundPacket::LoginSuccess(..)) => {
@@ -322,14 +1374,229 @@ impl Client {
                 &Some(ClientboundPacket::SetCompression(ref p)) => {
                     self.enable_compression(*p.get_threshold() as usize);
                 },
+                &Some(ClientboundPacket::JoinGame(ref p)) => {
+                    self.joined = true;
+                    self.entity_id = Some(*p.get_entity_id());
+                    self.send_settings(true)?;
+                },
                 &Some(ClientboundPacket::KeepAlive(ref p)) => {
                     let keepalive = serverbound::KeepAlive::new(*p.get_id());
                     let _: usize = self.send(keepalive)?;
                 },
+                &Some(ClientboundPacket::PlayerPositionAndLook(ref p)) => {
+                    let old = self.position.unwrap_or_default();
+                    let new = old.teleport(*p.get_x(),
+                                           *p.get_y(),
+                                           *p.get_z(),
+                                           *p.get_yaw(),
+                                           *p.get_pitch(),
+                                           *p.get_flags());
+                    let confirm =
+                        serverbound::TeleportConfirm::new(*p.get_teleport_id());
+                    let _: usize = self.send(confirm)?;
+                    let _: usize = self.set_position(new)?;
+                },
+                &Some(ClientboundPacket::UpdateHealth(ref p)) => {
+                    let health = *p.get_health();
+                    if health <= 0.0 && self.health > 0.0 {
+                        self.events.push(ClientEvent::Died);
+                    }
+                    self.health = health;
+                    let food = *p.get_food();
+                    if let Some(threshold) = self.hunger_threshold {
+                        if food <= threshold && food < self.food {
+                            self.events.push(ClientEvent::Hungry(food));
+                        }
+                    }
+                    self.food = food;
+                    self.saturation = *p.get_saturation();
+                },
+                &Some(ClientboundPacket::BlockChange(ref p)) => {
+                    self.block_changed(*p.get_position(), *p.get_new_block());
+                },
+                &Some(ClientboundPacket::MultiBlockChange(ref p)) => {
+                    let (cx, cz) = (*p.get_chunk_x(), *p.get_chunk_z());
+                    for &(x, y, z, state) in p.get_changes() {
+                        let position = (cx * 16 + x as i32,
+                                        y as i32,
+                                        cz * 16 + z as i32);
+                        self.block_changed(position, state);
+                    }
+                },
+                &Some(ref p @ ClientboundPacket::UnloadChunk(_)) => {
+                    let rolled_back = self.blocks.handle(p);
+                    self.events.extend(rolled_back.into_iter().map(ClientEvent::Prediction));
+                },
+                &Some(ref p @ ClientboundPacket::ClientboundConfirmTransaction(_)) |
+                &Some(ref p @ ClientboundPacket::ClientboundCloseWindow(_)) => {
+                    for packet in self.transactions.handle(p) {
+                        let _: usize = self.send(packet)?;
+                    }
+                    let events = self.transactions.poll_events();
+                    self.events.extend(events.into_iter().map(ClientEvent::Window));
+                },
+                &Some(ref p @ ClientboundPacket::TimeUpdate(_)) |
+                &Some(ref p @ ClientboundPacket::ChangeGameState(_)) |
+                &Some(ref p @ ClientboundPacket::WorldBorder(_)) => {
+                    self.world.handle(p)?;
+                },
+                &Some(ref p @ ClientboundPacket::Respawn(_)) => {
+                    self.world.handle(p)?;
+                    self.effects.clear();
+                    self.blocks.clear();
+                    if let Some(change) = self.riding.clear() {
+                        self.events.push(ClientEvent::Vehicle(change));
+                    }
+                },
+                &Some(ref p @ ClientboundPacket::SetPassengers(_)) |
+                &Some(ref p @ ClientboundPacket::DestroyEntities(_)) |
+                &Some(ref p @ ClientboundPacket::ClientboundVehicleMove(_)) => {
+                    if let Some(entity_id) = self.entity_id {
+                        if let Some(change) = self.riding.handle(p, entity_id) {
+                            self.events.push(ClientEvent::Vehicle(change));
+                        }
+                    }
+                },
+                &Some(ref p @ ClientboundPacket::EntityEffect(_)) |
+                &Some(ref p @ ClientboundPacket::RemoveEntityEffect(_)) => {
+                    if let Some(entity_id) = self.entity_id {
+                        if let Some(change) = self.effects.handle(p, entity_id) {
+                            self.events.push(ClientEvent::Effect(change));
+                        }
+                    }
+                },
+                /* Status 35 plays the totem of undying animation */
+                &Some(ClientboundPacket::EntityStatus(ref p))
+                    if Some(*p.get_entity_id()) == self.entity_id && *p.get_status() == 35 => {
+                    self.events.push(ClientEvent::TotemUsed);
+                },
+                &Some(ClientboundPacket::SetExperience(ref p)) => {
+                    self.experience = (*p.get_experience(),
+                                       *p.get_level(),
+                                       *p.get_total_experience());
+                },
                 _ => (),
             }
+
+            if let (Some(p), Some(entity_id)) = (packet.as_ref(), self.entity_id) {
+                for change in self.spectator.handle(p, entity_id) {
+                    self.events.push(ClientEvent::Spectator(change));
+                }
+            }
         }
 
         Ok(packet)
     }
 }
+
+#[cfg(unix)]
+impl Client {
+    /// Get the file descriptor of the connection to poll for readiness, or
+    /// None if its transport has none, see the transport module
+    pub fn raw_fd(&self) -> Option<RawFd> {
+        self.conn.raw_fd()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use {chat, clientbound};
+    use transport::MemoryStream;
+    use Server;
+
+    /* A client and a server connected in memory, in the Play state */
+    fn connected() -> (Client, Server) {
+        let (a, b) = MemoryStream::pair();
+        let mut client = Client::from_transport(a).unwrap();
+        let mut server = Server::from_transport(b).unwrap();
+        client.set_clientstate(ClientState::Play);
+        server.set_clientstate(ClientState::Play);
+        (client, server)
+    }
+
+    #[test]
+    fn anti_idle_despite_keepalives() {
+        let (mut client, mut server) = connected();
+        client.set_auto_handle(true);
+        client.set_watchdog(Some(WatchdogOptions {
+                                     anti_idle_interval: Some(time::Duration::from_millis(50)),
+                                     ..WatchdogOptions::default()
+                                 }));
+
+        /* The KeepAlive replies don't count as the player doing anything */
+        let mut received = Vec::new();
+        for i in 0..20 {
+            let _: usize = server.send(clientbound::KeepAlive::new(i)).unwrap();
+            let _ = client.read().unwrap();
+            received.extend(server.read().unwrap());
+            thread::sleep(time::Duration::from_millis(10));
+        }
+        assert!(received.iter().any(|x| matches!(*x, ServerboundPacket::KeepAlive(..))));
+        assert!(received.iter().any(|x| matches!(*x, ServerboundPacket::Animation(..))));
+    }
+
+    #[test]
+    fn health_and_death() {
+        let (mut client, mut server) = connected();
+        client.set_auto_handle(true);
+        let mut update = |client: &mut Client, health, food| {
+            let _: usize = server.send(clientbound::UpdateHealth::new(health, food, 2.5)).unwrap();
+            let _ = client.read().unwrap();
+            client.poll_events()
+        };
+
+        assert_eq!(update(&mut client, 15.0, 18), vec![]);
+        assert_eq!(client.health(), 15.0);
+        assert_eq!(client.food(), 18);
+        assert_eq!(client.saturation(), 2.5);
+        assert!(!client.is_dead());
+
+        assert_eq!(update(&mut client, 0.0, 18), vec![ClientEvent::Died]);
+        assert!(client.is_dead());
+        /* Only dying is an event, not each update while dead */
+        assert_eq!(update(&mut client, 0.0, 18), vec![]);
+        assert_eq!(update(&mut client, 20.0, 20), vec![]);
+        assert!(!client.is_dead());
+
+        /* Nothing is tracked without auto_handle */
+        client.set_auto_handle(false);
+        assert_eq!(update(&mut client, 0.0, 20), vec![]);
+        assert_eq!(client.health(), 20.0);
+    }
+
+    #[test]
+    fn chat_kick_reason() {
+        let (mut client, mut server) = connected();
+        let _: usize = client.send(serverbound::ChatMessage::new("a".repeat(300))).unwrap();
+        assert!(server.read().is_err());
+        /* Reading all packets fails, since the server closed the connection
+         * after the disconnect */
+        client.update_inbuf().unwrap();
+        match client.read_packet().unwrap() {
+            Some(ClientboundPacket::PlayDisconnect(ref p)) => {
+                assert_eq!(p.get_reason(), chat::TOO_LONG_REASON);
+            },
+            x => panic!("Expected a PlayDisconnect, got {:?}", x),
+        }
+    }
+
+    #[test]
+    fn pause_overflow() {
+        let (client, mut server) = connected();
+        let paused = client.pause();
+        for i in 0..100 {
+            let _: usize = server.send(clientbound::TimeUpdate::new(i, 0)).unwrap();
+        }
+        thread::sleep(time::Duration::from_millis(100));
+        let (client, packets) = paused.resume().unwrap();
+        assert_eq!(packets.len(), 100);
+
+        let paused = client.pause();
+        for i in 0..MAX_PAUSED_PACKETS as i64 + 1 {
+            let _: usize = server.send(clientbound::TimeUpdate::new(i, 0)).unwrap();
+        }
+        thread::sleep(time::Duration::from_millis(500));
+        assert!(paused.resume().is_err());
+    }
+}
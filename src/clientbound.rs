@@ -7,6 +7,7 @@
 //! if you feel such a function is missing, open an issue.
 
 use connection::Packet;
+use consts::MAX_IDENTIFIER_LENGTH;
 use errors::Result;
 use read::*;
 use write::*;
@@ -331,18 +332,18 @@ impl UpdateScore {
     fn to_u8(&self) -> Result<Vec<u8>> {
         let mut ret = Vec::new();
         write_varint(&UpdateScore::PACKET_ID, &mut ret)?;
-        write_String(&self.name, &mut ret)?;
+        write_SmallString(&self.name, &mut ret)?;
         write_u8(&self.action, &mut ret)?;
-        write_String(&self.objective_name, &mut ret)?;
+        write_SmallString(&self.objective_name, &mut ret)?;
         if let Some(x) = self.value {
             write_varint(&x, &mut ret)?;
         }
         Ok(ret)
     }
     fn deserialize<R: Read>(r: &mut R) -> Result<ClientboundPacket> {
-        let name = read_String(r)?;
+        let name = read_SmallString(r)?;
         let action = read_u8(r)?;
-        let objective_name = read_String(r)?;
+        let objective_name = read_SmallString(r)?;
         let value = match action {
             1 => Some(read_varint(r)?),
             _ => None,
@@ -514,7 +515,7 @@ impl SelectAdvancementTab {
         let mut ret = Vec::new();
         write_varint(&Self::PACKET_ID, &mut ret)?;
         if let Some(ref identifier) = self.identifier {
-            if identifier.len() > 32767 {
+            if identifier.len() > MAX_IDENTIFIER_LENGTH {
                 bail!("SelectAdvancementTab identifier is too long, is {} bytes long",
                       identifier.len());
             }
@@ -529,7 +530,7 @@ impl SelectAdvancementTab {
         let has_id = read_bool(r)?;
         let identifier = if has_id {
             let tmp = read_String(r)?;
-            if tmp.len() > 32767 {
+            if tmp.len() > MAX_IDENTIFIER_LENGTH {
                 bail!("SelectAdvancementTab identifier is too long, is {} bytes long",
                       tmp.len());
             }
@@ -0,0 +1,602 @@
+//! Server-side command dispatching
+//!
+//! Commands are registered with a CommandDispatcher as a chain of literals
+//! followed by typed arguments. The dispatcher parses chat commands sent by
+//! clients against the registered commands, checks permissions, and calls the
+//! handler with the parsed arguments. It also creates the DeclareCommands
+//! packet describing the commands (the Brigadier command graph), so that
+//! clients can suggest and highlight them.
+//!
+//! # Examples
+//!
+//! ```rust
+//! use ozelot::command::{ArgumentType, CommandDispatcher};
+//!
+//! struct Player {
+//!     name: String,
+//!     op: bool,
+//! }
+//!
+//! let mut dispatcher = CommandDispatcher::new();
+//! dispatcher.set_permission_check(|player: &Player, _| player.op);
+//! dispatcher.register("say",
+//!                     vec![("message", ArgumentType::GreedyString)],
+//!                     None,
+//!                     |player: &mut Player, args| {
+//!                         println!("<{}> {}", player.name,
+//!                                  args.get_str("message").unwrap());
+//!                         Ok(())
+//!                     }).unwrap();
+//! dispatcher.register("kick",
+//!                     vec![("player", ArgumentType::Player)],
+//!                     Some("kick"),
+//!                     |_: &mut Player, args| {
+//!                         println!("Kicking {}", args.get_str("player").unwrap());
+//!                         Ok(())
+//!                     }).unwrap();
+//!
+//! let mut player = Player { name: "Notch".to_string(), op: false };
+//! assert!(dispatcher.dispatch(&mut player, "/say hello world").is_ok());
+//! assert!(dispatcher.dispatch(&mut player, "/kick jeb_").is_err());
+//! ```
+
+use clientbound::{ClientboundPacket, DeclareCommands};
+use errors::{self, Result};
+use write::{write_String, write_f64, write_i32, write_u8, write_varint};
+
+use std::fmt;
+
+/// The type of a command argument
+#[derive(Debug, Clone, PartialEq)]
+pub enum ArgumentType {
+    /// true or false
+    Bool,
+    /// An i32, optionally within the given (inclusive) bounds
+    Integer { min: Option<i32>, max: Option<i32> },
+    /// An f64, optionally within the given (inclusive) bounds
+    Double { min: Option<f64>, max: Option<f64> },
+    /// A single word
+    Word,
+    /// A single word, or a "quoted string"
+    QuotableString,
+    /// All the remaining text
+    GreedyString,
+    /// The name of a player
+    Player,
+}
+impl ArgumentType {
+    /// Parse an argument of this type from the start of the input, returning
+    /// the argument and the remaining input.
+    fn parse<'a>(&self, input: &'a str) -> Option<(Argument, &'a str)> {
+        if let ArgumentType::GreedyString = *self {
+            return if input.is_empty() {
+                None
+            } else {
+                Some((Argument::String(input.to_string()), ""))
+            };
+        }
+        if let ArgumentType::QuotableString = *self {
+            if input.starts_with('"') {
+                return parse_quoted(input);
+            }
+        }
+
+        let end = input.find(' ').unwrap_or(input.len());
+        let (word, rest) = input.split_at(end);
+        if word.is_empty() {
+            return None;
+        }
+        let arg = match *self {
+            ArgumentType::Bool => {
+                match word {
+                    "true" => Argument::Bool(true),
+                    "false" => Argument::Bool(false),
+                    _ => return None,
+                }
+            },
+            ArgumentType::Integer { min, max } => {
+                let x: i32 = word.parse().ok()?;
+                if min.is_some_and(|min| x < min) || max.is_some_and(|max| x > max) {
+                    return None;
+                }
+                Argument::Integer(x)
+            },
+            ArgumentType::Double { min, max } => {
+                let x: f64 = word.parse().ok()?;
+                if min.is_some_and(|min| x < min) || max.is_some_and(|max| x > max) {
+                    return None;
+                }
+                Argument::Double(x)
+            },
+            _ => Argument::String(word.to_string()),
+        };
+        Some((arg, rest))
+    }
+
+    /* Write the parser identifier and properties, as in DeclareCommands */
+    fn write(&self, out: &mut Vec<u8>) -> Result<()> {
+        match *self {
+            ArgumentType::Bool => write_String("brigadier:bool", out)?,
+            ArgumentType::Integer { min, max } => {
+                write_String("brigadier:integer", out)?;
+                write_u8(&bounds_flags(min.is_some(), max.is_some()), out)?;
+                if let Some(min) = min {
+                    write_i32(&min, out)?;
+                }
+                if let Some(max) = max {
+                    write_i32(&max, out)?;
+                }
+            },
+            ArgumentType::Double { min, max } => {
+                write_String("brigadier:double", out)?;
+                write_u8(&bounds_flags(min.is_some(), max.is_some()), out)?;
+                if let Some(min) = min {
+                    write_f64(&min, out)?;
+                }
+                if let Some(max) = max {
+                    write_f64(&max, out)?;
+                }
+            },
+            ArgumentType::Word => {
+                write_String("brigadier:string", out)?;
+                write_varint(&0, out)?;
+            },
+            ArgumentType::QuotableString => {
+                write_String("brigadier:string", out)?;
+                write_varint(&1, out)?;
+            },
+            ArgumentType::GreedyString => {
+                write_String("brigadier:string", out)?;
+                write_varint(&2, out)?;
+            },
+            ArgumentType::Player => write_String("minecraft:game_profile", out)?,
+        }
+        Ok(())
+    }
+}
+
+fn bounds_flags(min: bool, max: bool) -> u8 {
+    (if min { 0x01 } else { 0 }) | (if max { 0x02 } else { 0 })
+}
+
+/* Parse a "quoted string" with backslash escapes */
+fn parse_quoted(input: &str) -> Option<(Argument, &str)> {
+    let mut ret = String::new();
+    let mut escaped = false;
+    for (i, c) in input.char_indices().skip(1) {
+        match c {
+            _ if escaped => {
+                ret.push(c);
+                escaped = false;
+            },
+            '\\' => escaped = true,
+            '"' => return Some((Argument::String(ret), &input[i + 1..])),
+            _ => ret.push(c),
+        }
+    }
+    None
+}
+
+/// A parsed argument value
+#[derive(Debug, Clone, PartialEq)]
+pub enum Argument {
+    Bool(bool),
+    Integer(i32),
+    Double(f64),
+    /// Any of the string types, and player names
+    String(String),
+}
+
+/// The arguments given to a command, by name
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct Arguments(Vec<(String, Argument)>);
+impl Arguments {
+    /// Get the argument with the given name
+    pub fn get(&self, name: &str) -> Option<&Argument> {
+        self.0.iter().find(|x| x.0 == name).map(|x| &x.1)
+    }
+
+    pub fn get_bool(&self, name: &str) -> Option<bool> {
+        match self.get(name) {
+            Some(&Argument::Bool(x)) => Some(x),
+            _ => None,
+        }
+    }
+
+    pub fn get_int(&self, name: &str) -> Option<i32> {
+        match self.get(name) {
+            Some(&Argument::Integer(x)) => Some(x),
+            _ => None,
+        }
+    }
+
+    pub fn get_double(&self, name: &str) -> Option<f64> {
+        match self.get(name) {
+            Some(&Argument::Double(x)) => Some(x),
+            _ => None,
+        }
+    }
+
+    pub fn get_str(&self, name: &str) -> Option<&str> {
+        match self.get(name) {
+            Some(Argument::String(x)) => Some(x),
+            _ => None,
+        }
+    }
+}
+
+/// Why a command couldn't be run
+#[derive(Debug)]
+pub enum CommandError {
+    /// There's no command with the given name
+    UnknownCommand(String),
+    /// The command exists, but the source isn't allowed to run it
+    NoPermission,
+    /// The arguments didn't match any of the command's registered arguments
+    InvalidArguments,
+    /// The handler returned an error
+    Failed(errors::Error),
+}
+impl fmt::Display for CommandError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            CommandError::UnknownCommand(ref x) => write!(f, "Unknown command: {}", x),
+            CommandError::NoPermission => {
+                write!(f, "You do not have permission to use this command")
+            },
+            CommandError::InvalidArguments => write!(f, "Invalid command arguments"),
+            CommandError::Failed(ref e) => write!(f, "{}", e),
+        }
+    }
+}
+
+type Handler<S> = Box<dyn Fn(&mut S, &Arguments) -> Result<()>>;
+type PermissionCheck<S> = Box<dyn Fn(&S, &str) -> bool>;
+
+#[derive(Debug, PartialEq)]
+enum NodeKind {
+    Root,
+    Literal(String),
+    Argument(String, ArgumentType),
+}
+
+struct Node {
+    kind: NodeKind,
+    children: Vec<usize>,
+    /* The index of the handler, if this node is executable */
+    handler: Option<usize>,
+    permission: Option<String>,
+}
+
+/// Parses and runs commands for sources of type S (e.g. your player type),
+/// see the module documentation.
+pub struct CommandDispatcher<S> {
+    /* The root node is nodes[0] */
+    nodes: Vec<Node>,
+    handlers: Vec<Handler<S>>,
+    permission_check: PermissionCheck<S>,
+}
+impl<S> Default for CommandDispatcher<S> {
+    fn default() -> Self {
+        CommandDispatcher::new()
+    }
+}
+impl<S> fmt::Debug for CommandDispatcher<S> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("CommandDispatcher")
+            .field("nodes", &self.nodes.len())
+            .field("handlers", &self.handlers.len())
+            .finish()
+    }
+}
+impl<S> CommandDispatcher<S> {
+    /// Create a new dispatcher with no commands, and where every permission
+    /// is granted.
+    pub fn new() -> Self {
+        CommandDispatcher {
+            nodes: vec![Node {
+                            kind: NodeKind::Root,
+                            children: Vec::new(),
+                            handler: None,
+                            permission: None,
+                        }],
+            handlers: Vec::new(),
+            permission_check: Box::new(|_, _| true),
+        }
+    }
+
+    /// Set the callback deciding whether a source has the given permission
+    pub fn set_permission_check<F>(&mut self, check: F)
+        where F: Fn(&S, &str) -> bool + 'static
+    {
+        self.permission_check = Box::new(check);
+    }
+
+    /// Register a command.
+    ///
+    /// literals is the command name, optionally followed by subcommands,
+    /// separated by spaces (e.g. "gamemode creative".) They're followed by
+    /// the given arguments. For optional arguments, register the command
+    /// once with and once without them.
+    ///
+    /// If permission is given, only sources with that permission can run
+    /// the command or see it in DeclareCommands.
+    ///
+    /// Returns an error if the exact same command has already been
+    /// registered.
+    pub fn register<F>(&mut self,
+                       literals: &str,
+                       arguments: Vec<(&str, ArgumentType)>,
+                       permission: Option<&str>,
+                       handler: F)
+                       -> Result<()>
+        where F: Fn(&mut S, &Arguments) -> Result<()> + 'static
+    {
+        if literals.split_whitespace().next().is_none() {
+            bail!("Commands must have a name");
+        }
+        let mut node = 0;
+        for literal in literals.split_whitespace() {
+            node = self.child(node, NodeKind::Literal(literal.to_string()));
+        }
+        for (name, kind) in arguments {
+            node = self.child(node, NodeKind::Argument(name.to_string(), kind));
+        }
+        if self.nodes[node].handler.is_some() {
+            bail!("The command {:?} has already been registered", literals);
+        }
+        self.handlers.push(Box::new(handler));
+        self.nodes[node].handler = Some(self.handlers.len() - 1);
+        self.nodes[node].permission = permission.map(|x| x.to_string());
+        Ok(())
+    }
+
+    /* Get the child of the given node with the given kind, creating it if
+     * it doesn't exist */
+    fn child(&mut self, parent: usize, kind: NodeKind) -> usize {
+        let existing = self.nodes[parent]
+            .children
+            .iter()
+            .find(|&&x| self.nodes[x].kind == kind)
+            .cloned();
+        match existing {
+            Some(x) => x,
+            None => {
+                self.nodes.push(Node {
+                                    kind,
+                                    children: Vec::new(),
+                                    handler: None,
+                                    permission: None,
+                                });
+                let index = self.nodes.len() - 1;
+                self.nodes[parent].children.push(index);
+                index
+            },
+        }
+    }
+
+    /// Parse and run the given command (with or without the leading slash)
+    pub fn dispatch(&self, source: &mut S, command: &str) -> ::std::result::Result<(), CommandError> {
+        let command = command.trim_start_matches('/');
+        let name = command.split(' ').next().unwrap_or("");
+        let known = self.nodes[0].children.iter().any(|&x| {
+            self.nodes[x].kind == NodeKind::Literal(name.to_string())
+        });
+        if !known {
+            return Err(CommandError::UnknownCommand(name.to_string()));
+        }
+
+        let mut arguments = Arguments::default();
+        let node = match self.parse(0, command, &mut arguments) {
+            Some(x) => x,
+            None => return Err(CommandError::InvalidArguments),
+        };
+        if !self.is_permitted(source, node) {
+            return Err(CommandError::NoPermission);
+        }
+        let handler = self.nodes[node]
+            .handler
+            .expect("unreachable parsed command without handler");
+        self.handlers[handler](source, &arguments).map_err(CommandError::Failed)
+    }
+
+    /* Parse the input against the children of the given node, returning the
+     * executable node reached */
+    fn parse(&self, node: usize, input: &str, arguments: &mut Arguments) -> Option<usize> {
+        for &child in &self.nodes[node].children {
+            let rest = match self.nodes[child].kind {
+                NodeKind::Literal(ref literal) => {
+                    if input == literal {
+                        ""
+                    } else if input.starts_with(literal.as_str()) &&
+                              input[literal.len()..].starts_with(' ') {
+                        &input[literal.len()..]
+                    } else {
+                        continue;
+                    }
+                },
+                NodeKind::Argument(ref name, ref kind) => {
+                    match kind.parse(input) {
+                        Some((arg, rest)) if rest.is_empty() || rest.starts_with(' ') => {
+                            arguments.0.push((name.clone(), arg));
+                            rest
+                        },
+                        _ => continue,
+                    }
+                },
+                NodeKind::Root => continue,
+            };
+
+            if rest.is_empty() {
+                if self.nodes[child].handler.is_some() {
+                    return Some(child);
+                }
+            } else if let Some(x) = self.parse(child, &rest[1..], arguments) {
+                return Some(x);
+            }
+            if let NodeKind::Argument(..) = self.nodes[child].kind {
+                let _: Option<(String, Argument)> = arguments.0.pop();
+            }
+        }
+        None
+    }
+
+    /* Whether the source may run the command at the given node */
+    fn is_permitted(&self, source: &S, node: usize) -> bool {
+        match self.nodes[node].permission {
+            Some(ref permission) => (self.permission_check)(source, permission),
+            None => true,
+        }
+    }
+
+    /* Whether the source may run any command at or below the given node */
+    fn is_visible(&self, source: &S, node: usize) -> bool {
+        (self.nodes[node].handler.is_some() && self.is_permitted(source, node)) ||
+        self.nodes[node].children.iter().any(|&x| self.is_visible(source, x))
+    }
+
+    /// Create the DeclareCommands packet describing the commands available
+    /// to the given source
+    pub fn declare_commands(&self, source: &S) -> Result<ClientboundPacket> {
+        /* Map from our node indices to the indices in the packet */
+        let mut indices = vec![None; self.nodes.len()];
+        let mut count = 0;
+        for (i, index) in indices.iter_mut().enumerate() {
+            if i == 0 || self.is_visible(source, i) {
+                *index = Some(count);
+                count += 1;
+            }
+        }
+
+        let mut out = Vec::new();
+        write_varint(&count, &mut out)?;
+        for (i, node) in self.nodes.iter().enumerate() {
+            if indices[i].is_none() {
+                continue;
+            }
+            let mut flags = match node.kind {
+                NodeKind::Root => 0,
+                NodeKind::Literal(_) => 1,
+                NodeKind::Argument(..) => 2,
+            };
+            if node.handler.is_some() && self.is_permitted(source, i) {
+                flags |= 0x04;
+            }
+            write_u8(&flags, &mut out)?;
+            let children: Vec<i32> = node.children
+                .iter()
+                .filter_map(|&x| indices[x])
+                .collect();
+            write_varint(&(children.len() as i32), &mut out)?;
+            for child in &children {
+                write_varint(child, &mut out)?;
+            }
+            match node.kind {
+                NodeKind::Root => (),
+                NodeKind::Literal(ref name) => write_String(name, &mut out)?,
+                NodeKind::Argument(ref name, ref kind) => {
+                    write_String(name, &mut out)?;
+                    kind.write(&mut out)?;
+                },
+            }
+        }
+        /* The root node */
+        write_varint(&0, &mut out)?;
+
+        Ok(DeclareCommands::new(out))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn dispatch() {
+        let mut d = CommandDispatcher::new();
+        d.set_permission_check(|source: &Vec<String>, permission| {
+            source.iter().any(|x| x == permission)
+        });
+        d.register("tp",
+                   vec![("x", ArgumentType::Double { min: None, max: None }),
+                        ("y", ArgumentType::Double { min: Some(0.0), max: Some(255.0) }),
+                        ("z", ArgumentType::Double { min: None, max: None })],
+                   Some("tp"),
+                   |s: &mut Vec<String>, args| {
+                       s.push(format!("tp {} {} {}",
+                                      args.get_double("x").unwrap(),
+                                      args.get_double("y").unwrap(),
+                                      args.get_double("z").unwrap()));
+                       Ok(())
+                   })
+            .unwrap();
+        d.register("msg",
+                   vec![("player", ArgumentType::Player),
+                        ("message", ArgumentType::GreedyString)],
+                   None,
+                   |s: &mut Vec<String>, args| {
+                       s.push(format!("{}: {}",
+                                      args.get_str("player").unwrap(),
+                                      args.get_str("message").unwrap()));
+                       Ok(())
+                   })
+            .unwrap();
+        d.register("name set",
+                   vec![("name", ArgumentType::QuotableString)],
+                   None,
+                   |s: &mut Vec<String>, args| {
+                       s.push(args.get_str("name").unwrap().to_string());
+                       Ok(())
+                   })
+            .unwrap();
+        assert!(d.register("msg",
+                           vec![("player", ArgumentType::Player),
+                                ("message", ArgumentType::GreedyString)],
+                           None,
+                           |_, _| Ok(()))
+                    .is_err());
+
+        let mut source = Vec::new();
+        d.dispatch(&mut source, "/msg Notch hello there").unwrap();
+        d.dispatch(&mut source, "name set \"a \\\"b\\\"\"").unwrap();
+        match d.dispatch(&mut source, "/tp 1 2 3") {
+            Err(CommandError::NoPermission) => (),
+            x => panic!("Expected NoPermission, got {:?}", x),
+        }
+        source.push("tp".to_string());
+        d.dispatch(&mut source, "/tp 1.5 64 -3").unwrap();
+        match d.dispatch(&mut source, "/tp 1 300 3") {
+            Err(CommandError::InvalidArguments) => (),
+            x => panic!("Expected InvalidArguments, got {:?}", x),
+        }
+        match d.dispatch(&mut source, "/nope") {
+            Err(CommandError::UnknownCommand(ref x)) if x == "nope" => (),
+            x => panic!("Expected UnknownCommand, got {:?}", x),
+        }
+        assert_eq!(source,
+                   vec!["Notch: hello there", "a \"b\"", "tp", "tp 1.5 64 -3"]);
+    }
+
+    #[test]
+    fn declare_commands() {
+        let mut d = CommandDispatcher::new();
+        d.set_permission_check(|_: &(), _| false);
+        d.register("a",
+                   vec![("n", ArgumentType::Integer { min: Some(0), max: None })],
+                   None,
+                   |_, _| Ok(()))
+            .unwrap();
+        d.register("b", vec![], Some("b"), |_, _| Ok(())).unwrap();
+
+        let expected = vec![3, /* node count */
+                            0, 1, 1, /* root, with child 1 */
+                            1, 1, 2, 1, b'a', /* literal "a", with child 2 */
+                            6, 0, 1, b'n', /* executable argument "n" */
+                            17, b'b', b'r', b'i', b'g', b'a', b'd', b'i', b'e', b'r',
+                            b':', b'i', b'n', b't', b'e', b'g', b'e', b'r',
+                            1, 0, 0, 0, 0, /* min 0 */
+                            0 /* root index */];
+        assert_eq!(d.declare_commands(&()).unwrap(),
+                   DeclareCommands::new(expected));
+    }
+}
@@ -0,0 +1,136 @@
+//! Shims for packets and channels renamed or merged between versions
+//!
+//! When a packet is renamed in a new protocol version, ozelot renames it too,
+//! but keeps the old name as a deprecated type alias in the clientbound or
+//! serverbound module, so code written against the previous ozelot still
+//! compiles, with a warning pointing to the new name. The renames are also
+//! listed in RENAMES, which is used e.g. to accept the old names in
+//! protocol_data files.
+//!
+//! Minecraft 1.13 also turned several plugin channels into packets of their
+//! own, and renamed the remaining vanilla channels to namespaced ones.
+//! from_plugin_message() converts a plugin message sent by a 1.12 client to
+//! the packet that replaced it, and channel_name() gives the current name of
+//! a channel.
+
+use consts::PROTOCOL_1_13;
+use errors::Result;
+use read::{read_String, read_i32, read_varint};
+use serverbound::{EditBook, NameItem, PickItem, PluginMessage, SelectTrade,
+                  ServerboundPacket, SetBeaconEffect};
+use write::{write_bool, write_varint};
+
+use std::io::Cursor;
+
+/// A packet renamed in a protocol version
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Rename {
+    /// The name before the rename
+    pub old: &'static str,
+    /// The name from then on
+    pub new: &'static str,
+    /// The protocol version the packet was renamed in
+    pub protocol: i32,
+}
+
+/// The packets renamed since the previous release of ozelot, which are
+/// available under their old names as deprecated aliases
+pub const RENAMES: &[Rename] = &[Rename {
+                                     old: "CraftingBookData",
+                                     new: "RecipeBookData",
+                                     protocol: PROTOCOL_1_13,
+                                 }];
+
+/// The plugin channels renamed in Minecraft 1.13, as old and new name
+pub const CHANNEL_RENAMES: &[(&str, &str)] = &[("MC|Brand", "minecraft:brand"),
+                                               ("REGISTER", "minecraft:register"),
+                                               ("UNREGISTER", "minecraft:unregister"),
+                                               ("MC|BOpen", "minecraft:book_open"),
+                                               ("MC|TrList", "minecraft:trader_list"),
+                                               ("MC|DebugPath", "minecraft:debug/paths"),
+                                               ("MC|DebugNeighborsUpdate",
+                                                "minecraft:debug/neighbors_update")];
+
+/// Get the current name of a packet, given a possibly old one
+pub fn packet_name(name: &str) -> &str {
+    match RENAMES.iter().find(|x| x.old == name) {
+        Some(x) => x.new,
+        None => name,
+    }
+}
+
+/// Get the current name of a plugin channel, given a possibly old one
+pub fn channel_name(name: &str) -> &str {
+    match CHANNEL_RENAMES.iter().find(|x| x.0 == name) {
+        Some(x) => x.1,
+        None => name,
+    }
+}
+
+/// Convert a plugin message sent by a client of protocol 340 (Minecraft
+/// 1.12.2) to the packet that replaced it in Minecraft 1.13, if it was
+/// replaced by one. Returns None for other channels.
+///
+/// The command block and structure block channels aren't converted, since
+/// the packets replacing them take fields the channels didn't send.
+pub fn from_plugin_message(packet: &PluginMessage) -> Result<Option<ServerboundPacket>> {
+    let mut r = Cursor::new(packet.get_data());
+    Ok(Some(match packet.get_channel().as_str() {
+        "MC|ItemName" => NameItem::new(read_String(&mut r)?),
+        "MC|TrSel" => SelectTrade::new(read_i32(&mut r)?),
+        "MC|Beacon" => {
+            let primary = read_i32(&mut r)?;
+            SetBeaconEffect::new(primary, read_i32(&mut r)?)
+        },
+        "MC|PickItem" => PickItem::new(read_varint(&mut r)?),
+        "MC|BEdit" => EditBook::new(edit_book(packet.get_data(), false)?),
+        "MC|BSign" => EditBook::new(edit_book(packet.get_data(), true)?),
+        _ => return Ok(None),
+    }))
+}
+
+/* The data of an EditBook packet for the given slot data. 1.12 books could
+ * only be edited in the main hand. */
+fn edit_book(slot: &[u8], signing: bool) -> Result<Vec<u8>> {
+    let mut ret = slot.to_vec();
+    write_bool(&signing, &mut ret)?;
+    write_varint(&0, &mut ret)?;
+    Ok(ret)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use read::SmallString;
+    use serverbound;
+
+    #[test]
+    #[allow(deprecated)]
+    fn renames() {
+        let _: Option<serverbound::CraftingBookData> = None;
+        assert_eq!(packet_name("CraftingBookData"), "RecipeBookData");
+        assert_eq!(packet_name("ChatMessage"), "ChatMessage");
+        assert_eq!(channel_name("MC|Brand"), "minecraft:brand");
+
+        let message = |channel: &str, data: Vec<u8>| {
+            match PluginMessage::new(SmallString::from(channel), data) {
+                ServerboundPacket::PluginMessage(x) => from_plugin_message(&x).unwrap(),
+                _ => unreachable!(),
+            }
+        };
+        match message("MC|Beacon", vec![0, 0, 0, 1, 0, 0, 0, 10]) {
+            Some(ServerboundPacket::SetBeaconEffect(ref p)) => {
+                assert_eq!(*p.get_primary_effect(), 1);
+                assert_eq!(*p.get_secondary_effect(), 10);
+            },
+            x => panic!("Got {:?}", x),
+        }
+        match message("MC|BSign", vec![0xff, 0xff]) {
+            Some(ServerboundPacket::EditBook(ref p)) => {
+                assert_eq!(p.get_data(), &vec![0xff, 0xff, 1, 0])
+            },
+            x => panic!("Got {:?}", x),
+        }
+        assert!(message("minecraft:brand", vec![]).is_none());
+    }
+}
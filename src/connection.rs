@@ -1,12 +1,26 @@
 use ClientState;
+use consts::{MAX_PACKET_SIZE, MAX_UNCOMPRESSED_SIZE};
+use custom::RawPacket;
+use deferred::DeferredPacket;
 use errors::{Result, ResultExt};
+use lifecycle::{Lifecycle, LifecycleEvent, LifecycleRecord};
+use prepared::PreparedPacket;
+use protocol_data::IdMap;
 use read::read_varint;
+use recorder::Recorder;
+use stats::{Direction, PacketStats};
+use transition::TransitionLog;
+use transport::Transport;
 use write::write_varint;
 
+use std::collections::VecDeque;
 use std::io::{Cursor, Read, Write};
 use std::marker::PhantomData;
-use std::net::Shutdown;
-use std::net::TcpStream;
+use std::net::{SocketAddr, TcpStream};
+use std::sync::Arc;
+use std::sync::mpsc::Sender;
+#[cfg(unix)]
+use std::os::unix::io::RawFd;
 use std::{io, time};
 
 use netbuf::Buf;
@@ -27,13 +41,32 @@ pub trait Packet: Sized {
     fn get_clientstate(&self) -> ClientState;
     /// Returns the ID of the packet
     fn get_id(&self) -> i32;
+    /// Returns the ID of the packet with the given name in the given state,
+    /// if there is one
+    fn id_by_name(state: &ClientState, name: &str) -> Option<i32>;
     /// Serializes the packet into Vec<u8>. You usually won't need to use this.
     fn to_u8(&self) -> Result<Vec<u8>>;
 }
 
+/// The result of trying to read a packet from a nonblocking connection
+#[derive(Debug, Clone, PartialEq)]
+pub enum ReadStatus<P> {
+    /// A complete packet was read
+    Ready(P),
+    /// No complete packet is available yet. Any partial packet is kept in the
+    /// internal buffer, so try again when the socket is readable.
+    NeedMore,
+}
+
+/* Stop reading from the socket once this much is buffered, so that a peer
+ * sending faster than the packets are handled doesn't grow the buffer
+ * forever. The rest is read on the next call. This must be more than the
+ * largest packet, so that it can be buffered completely. */
+const MAX_BUFFERED: usize = MAX_PACKET_SIZE + 5;
+
 /// Represents a single MC connection, either as client or server
 pub(crate) struct Connection<I: Packet, O: Packet> {
-    stream: TcpStream,
+    stream: Box<dyn Transport>,
     clientstate: ClientState,
     /* The buffer for incoming packets */
     buf: Buf,
@@ -47,6 +80,7 @@ pub(crate) struct Connection<I: Packet, O: Packet> {
      * packet id header) */
     packet_len: Option<usize>,
     compression: Option<usize>,
+    compression_level: Compression,
     /// Buffer for outgoing data
     out_buf: Buf,
     /* Incoming encryption cipher */
@@ -56,30 +90,71 @@ pub(crate) struct Connection<I: Packet, O: Packet> {
     /* When we last read something from the server. Use this to timeout the
      * connection if the connection is lost */
     last_read: time::Instant,
+    /* How long to wait for data before timing out, if at all */
+    read_timeout: Option<time::Duration>,
+    /* Whether the peer has closed its side of the connection */
+    eof: bool,
+    /* Per packet type statistics, if enabled */
+    stats: Option<PacketStats>,
+    /* Records the packets to a file, if set */
+    recorder: Option<Recorder>,
+    transitions: TransitionLog,
+    /* The registered custom packets, as state, ID and name */
+    custom_ids: Vec<(ClientState, i32, &'static str)>,
+    /* Received custom packets not yet read */
+    custom_queue: VecDeque<RawPacket>,
+    /* The packets to keep compressed, as state, ID and name */
+    deferred_ids: Vec<(ClientState, i32, &'static str)>,
+    /* Received deferred packets not yet read */
+    deferred_queue: VecDeque<DeferredPacket>,
+    lifecycle: Lifecycle,
+    /* The packet IDs to translate to and from, if the peer uses another
+     * protocol version, see the protocol_data module */
+    in_ids: Option<Arc<IdMap>>,
+    out_ids: Option<Arc<IdMap>>,
     in_type: PhantomData<I>,
     out_type: PhantomData<O>,
 }
 impl<I: Packet, O: Packet> Connection<I, O> {
     pub(crate) fn from_tcpstream(stream: TcpStream) -> Result<Self> {
-        let conn = Connection {
+        /* Set 30 second timeout */
+        stream.set_read_timeout(Some(time::Duration::new(30, 0)))?;
+        stream.set_write_timeout(Some(time::Duration::new(30, 0)))?;
+        stream.set_nonblocking(true)?;
+        stream.set_nodelay(true)?;
+        Ok(Connection::from_transport(Box::new(stream)))
+    }
+
+    /// Create a connection over a nonblocking transport, see the transport
+    /// module
+    pub(crate) fn from_transport(stream: Box<dyn Transport>) -> Self {
+        let lifecycle = Lifecycle::new(stream.peer_addr(), &ClientState::Handshake);
+        Connection {
             stream: stream,
             clientstate: ClientState::Handshake,
             buf: Buf::new(),
             packet_len: None,
             compression: None,
+            compression_level: Compression::default(),
             out_buf: Buf::new(),
             in_encryption: None,
             out_encryption: None,
             last_read: time::Instant::now(),
+            read_timeout: Some(time::Duration::new(30, 0)),
+            eof: false,
+            stats: None,
+            recorder: None,
+            transitions: TransitionLog::new(),
+            custom_ids: Vec::new(),
+            custom_queue: VecDeque::new(),
+            deferred_ids: Vec::new(),
+            deferred_queue: VecDeque::new(),
+            lifecycle,
+            in_ids: None,
+            out_ids: None,
             in_type: PhantomData,
             out_type: PhantomData,
-        };
-        /* Set 30 second timeout */
-        conn.stream.set_read_timeout(Some(time::Duration::new(30, 0)))?;
-        conn.stream.set_write_timeout(Some(time::Duration::new(30, 0)))?;
-        conn.stream.set_nonblocking(true)?;
-        conn.stream.set_nodelay(true)?;
-        Ok(conn)
+        }
     }
 
     pub(crate) fn connect_tcp(host: &str, port: u16) -> Result<Self> {
@@ -93,44 +168,64 @@ impl<I: Packet, O: Packet> Connection<I, O> {
     /// possible. Returns the length of the outgoing buffer. If this is greater
     /// than 0, you will need to call write() to send the remaining data.
     pub(crate) fn send(&mut self, packet: &O) -> Result<usize> {
-        let tmp = packet.to_u8()?;
-        let uncompressed_length = tmp.len();
-        let mut out = Vec::with_capacity(uncompressed_length);
-
-        match self.compression {
-            /* Compression is enabled, and the packet length is over the
-             * threshold, thus compress the packet */
-            Some(threshold) if uncompressed_length >= threshold => {
-                /* We have to copy all the data again, because we need
-                 * to prefix the packet with length of the compressed data */
-                let mut compressed = Vec::new();
-                write_varint(&(uncompressed_length as i32), &mut compressed)?;
-                let mut compressor = ZlibEncoder::new(compressed, Compression::default());
-                compressor.write_all(&tmp)?;
-                let compressed = compressor.finish()?;
-
-                write_varint(&(compressed.len() as i32), &mut out)?;
-                out.write_all(&compressed)?;
-            },
-            /* Compression is enabled, but the packet length is not over the
-             * threshold, thus we don't compress the packet */
-            Some(_) => {
-                /* Add 1 to the uncompressed length for the 1 byte it takes
-                 * to specify no compression */
-                write_varint(&((uncompressed_length + 1) as i32), &mut out)?;
-                write_varint(&0, &mut out)?;
-                out.write_all(&tmp)?;
-            },
-            /* Compression is not enabled */
-            None => {
-                write_varint(&(uncompressed_length as i32), &mut out)?;
-                out.write_all(&tmp)?;
-            },
+        let started = time::Instant::now();
+        let mut tmp = packet.to_u8()?;
+        if let Some(ref ids) = self.out_ids {
+            tmp = ids.outbound(&self.clientstate, tmp)?;
+        }
+        self.send_data(tmp, packet.get_packet_name(), started)
+    }
+
+    /// Send an already serialized packet, including the packet ID, e.g. a
+    /// custom packet
+    pub(crate) fn send_raw(&mut self, data: Vec<u8>, name: &str) -> Result<usize> {
+        self.send_data(data, name, time::Instant::now())
+    }
+
+    /// Send a packet prepared with PreparedPacket, sharing its serialized
+    /// and compressed data with the other connections it's sent to
+    pub(crate) fn send_prepared(&mut self, packet: &PreparedPacket) -> Result<usize> {
+        let started = time::Instant::now();
+        if let Some(ref ids) = self.out_ids {
+            let tmp = ids.outbound(&self.clientstate, packet.data().to_vec())?;
+            return self.send_data(tmp, packet.name(), started);
+        }
+        let out = packet.framed(self.compression, self.compression_level)?;
+        self.send_frame(&out, packet.data(), packet.name(), started)
+    }
+
+    fn send_data(&mut self, tmp: Vec<u8>, name: &str, started: time::Instant) -> Result<usize> {
+        let out = frame(&tmp, self.compression, self.compression_level)?;
+        self.send_frame(&out, &tmp, name, started)
+    }
+
+    /* Send a framed packet, i.e. with its length and compression, given the
+     * serialized packet for the recorder */
+    fn send_frame(&mut self,
+                  out: &[u8],
+                  tmp: &[u8],
+                  name: &str,
+                  started: time::Instant)
+                  -> Result<usize> {
+        self.transitions.packet(Direction::Outbound, name);
+
+        if let Some(ref mut recorder) = self.recorder {
+            let _ = recorder.record(Direction::Outbound,
+                                    &self.clientstate,
+                                    name,
+                                    tmp.to_vec());
+        }
+
+        if let Some(ref mut stats) = self.stats {
+            stats.record(Direction::Outbound,
+                         name,
+                         out.len(),
+                         started.elapsed());
         }
 
         if let Some(ref mut enc) = self.out_encryption {
             let mut tmp = vec![0; out.len() + 16];
-            let n = enc.update(&out, &mut tmp).chain_err(|| "connection::send error writing encrypted data")?;
+            let n = enc.update(out, &mut tmp).chain_err(|| "connection::send error writing encrypted data")?;
             let mut i = 0;
             while i < n {
                 i += self.out_buf.write(&tmp[i..n])?;
@@ -145,30 +240,120 @@ impl<I: Packet, O: Packet> Connection<I, O> {
         match self.out_buf.write_to(&mut self.stream) {
             Ok(_) => (),
             Err(ref e) if e.kind() == io::ErrorKind::WouldBlock => (),
-            Err(e) => bail!(e),
+            Err(e) => {
+                self.lifecycle.closed(&e.to_string());
+                bail!(e)
+            },
         }
         Ok(self.out_buf.len())
     }
 
-    /// Write from the outgoing buffer to the TcpStream
+    /// Write from the outgoing buffer to the transport
     ///
-    /// Returns the amount of bytes written.
+    /// Returns the amount of bytes written, which is 0 if the socket can't
+    /// take any more data right now.
     pub(crate) fn write(&mut self) -> Result<usize> {
-        return Ok(self.out_buf.write_to(&mut self.stream)?);
+        match self.out_buf.write_to(&mut self.stream) {
+            Ok(x) => Ok(x),
+            Err(ref e) if e.kind() == io::ErrorKind::WouldBlock => Ok(0),
+            Err(e) => {
+                self.lifecycle.closed(&e.to_string());
+                bail!(e)
+            },
+        }
+    }
+
+    /// Get the length of the outgoing buffer
+    pub(crate) fn pending_write(&self) -> usize {
+        self.out_buf.len()
+    }
+
+    /// Set how long read_packet waits for data before closing the connection
+    /// with a timeout error, or None to never time out
+    pub(crate) fn set_read_timeout(&mut self, timeout: Option<time::Duration>) {
+        self.read_timeout = timeout;
     }
 
     /// Attempt to close this connection.
     ///
     /// All future sends and reads to this connection will fail
     pub(crate) fn close(&mut self) -> Result<()> {
-        Ok(self.stream.shutdown(Shutdown::Both)?)
+        self.lifecycle.closed("Closed locally");
+        Ok(self.stream.close()?)
+    }
+
+    /// Enable or disable collecting packet statistics. Disabling clears them.
+    pub(crate) fn enable_packet_stats(&mut self, enable: bool) {
+        if !enable {
+            self.stats = None;
+        } else if self.stats.is_none() {
+            self.stats = Some(PacketStats::new());
+        }
+    }
+
+    /// Get the packet statistics, if enabled
+    pub(crate) fn packet_stats(&self) -> Option<&PacketStats> {
+        self.stats.as_ref()
+    }
+
+    /// Get the current client state
+    pub(crate) fn clientstate(&self) -> &ClientState {
+        &self.clientstate
+    }
+
+    /// Get the log of state transitions
+    pub(crate) fn transitions(&mut self) -> &mut TransitionLog {
+        &mut self.transitions
+    }
+
+    /// Set the recorder, returning the previous one
+    pub(crate) fn set_recorder(&mut self, recorder: Option<Recorder>) -> Option<Recorder> {
+        ::std::mem::replace(&mut self.recorder, recorder)
     }
 
     /// Change the client state of this connection
     pub(crate) fn set_clientstate(&mut self, new_state: ClientState) {
+        self.transitions.transition(&self.clientstate, &new_state);
+        if new_state != self.clientstate {
+            self.lifecycle.emit(LifecycleEvent::StateChanged {
+                                    from: self.clientstate.clone(),
+                                    to: new_state.clone(),
+                                });
+        }
         self.clientstate = new_state;
     }
 
+    /// Set the packet IDs to translate the incoming and outgoing packets
+    /// to and from, see the protocol_data module
+    pub(crate) fn set_packet_ids(&mut self,
+                                 in_ids: Option<Arc<IdMap>>,
+                                 out_ids: Option<Arc<IdMap>>) {
+        self.in_ids = in_ids;
+        self.out_ids = out_ids;
+    }
+
+    /// Get the address of the peer, if the transport has one
+    pub(crate) fn peer_addr(&self) -> Option<SocketAddr> {
+        self.stream.peer_addr()
+    }
+
+    /// Get the file descriptor to poll for readiness, if the transport has
+    /// one
+    #[cfg(unix)]
+    pub(crate) fn raw_fd(&self) -> Option<RawFd> {
+        self.stream.raw_fd()
+    }
+
+    /// Get the ID of the connection used in lifecycle events
+    pub(crate) fn connection_id(&self) -> u64 {
+        self.lifecycle.id()
+    }
+
+    /// Set the channel lifecycle events are sent to
+    pub(crate) fn set_lifecycle_sender(&mut self, sender: Option<Sender<LifecycleRecord>>) {
+        self.lifecycle.set_sender(sender, &self.clientstate);
+    }
+
     /// Enable encryption with the given key.
     ///
     /// It is an error to enable encryption if encryption has already been
@@ -189,6 +374,7 @@ impl<I: Packet, O: Packet> Connection<I, O> {
 
         self.out_encryption = Some(out_cipher);
         self.in_encryption = Some(in_cipher);
+        self.lifecycle.emit(LifecycleEvent::EncryptionEnabled);
     }
 
     /// Enable compression.
@@ -197,6 +383,22 @@ impl<I: Packet, O: Packet> Connection<I, O> {
     /// already been enabled.
     pub(crate) fn enable_compression(&mut self, threshold: usize) {
         self.compression = Some(threshold);
+        self.lifecycle.emit(LifecycleEvent::CompressionEnabled { threshold });
+    }
+
+    /// Set the zlib compression level used for outgoing packets, from 0
+    /// (fastest) to 9 (smallest)
+    pub(crate) fn set_compression_level(&mut self, level: u32) -> Result<()> {
+        if level > 9 {
+            bail!("Invalid compression level {}, must be between 0 and 9", level);
+        }
+        self.compression_level = Compression::new(level);
+        Ok(())
+    }
+
+    /// Get the compression threshold, if compression is enabled
+    pub(crate) fn compression(&self) -> Option<usize> {
+        self.compression
     }
 
     /// Read from the TcpStream and update the incoming buffer.
@@ -206,22 +408,38 @@ impl<I: Packet, O: Packet> Connection<I, O> {
     /// I.e. if you're just using client.read(), then you do not need to call
     /// this function.
     pub(crate) fn update_inbuf(&mut self) -> Result<()> {
-        if let Some(ref mut enc) = self.in_encryption {
-            let mut enc_buf = Buf::new();
-            let n = match enc_buf.read_from(&mut self.stream) {
+        /* Read until the socket would block, so that edge triggered poll
+         * loops don't miss data */
+        let mut chunk = [0; 4096];
+        while !self.eof && self.buf.len() < MAX_BUFFERED {
+            let n = match self.stream.read(&mut chunk) {
+                Ok(0) => {
+                    self.eof = true;
+                    break;
+                },
                 Ok(n) => n,
-                Err(ref e) if e.kind() == io::ErrorKind::WouldBlock => 0,
-                Err(e) => bail!(e),
+                Err(ref e) if e.kind() == io::ErrorKind::WouldBlock => break,
+                Err(ref e) if e.kind() == io::ErrorKind::Interrupted => continue,
+                Err(e) => {
+                    self.lifecycle.closed(&e.to_string());
+                    bail!(e)
+                },
             };
-            let mut tmp = vec![0; n + 16];
-            let n = enc.update(&enc_buf[..], &mut tmp).chain_err(|| "connection::update_inbuf error reading encrypted data")?;
+            self.receive(&chunk[..n])?;
+        }
+        Ok(())
+    }
+
+    /* Add data as read from the socket to the incoming buffer, decrypting it
+     * first if encryption is enabled. The data may end anywhere in a packet,
+     * read_packet() waits for the rest. */
+    fn receive(&mut self, data: &[u8]) -> Result<()> {
+        if let Some(ref mut enc) = self.in_encryption {
+            let mut tmp = vec![0; data.len() + 16];
+            let n = enc.update(data, &mut tmp).chain_err(|| "connection::update_inbuf error reading encrypted data")?;
             self.buf.extend(&tmp[..n]);
         } else {
-            match self.buf.read_from(&mut self.stream) {
-                Ok(_) => (),
-                Err(ref e) if e.kind() == io::ErrorKind::WouldBlock => (),
-                Err(e) => bail!(e),
-            };
+            self.buf.extend(data);
         }
         Ok(())
     }
@@ -236,63 +454,259 @@ impl<I: Packet, O: Packet> Connection<I, O> {
     /// this function will not attempt to read from the TcpStream, only from the
     /// internal buffer.
     pub(crate) fn read_packet(&mut self) -> Result<Option<I>> {
-        if let None = self.packet_len {
-            self.read_length()?;
+        loop {
+            if let None = self.packet_len {
+                self.read_length()?;
+            }
+
+            let len = match self.packet_len {
+                Some(x) => x,
+                None => return self.need_more(),
+            };
+
+            if self.buf.len() < len {
+                /* We haven't received enough yet to read the whole packet */
+                return self.need_more();
+            } else {
+                self.last_read = time::Instant::now();
+            }
+
+            if !self.custom_ids.is_empty() && self.read_custom_frame(len)? {
+                continue;
+            }
+            if !self.deferred_ids.is_empty() && self.read_deferred_frame(len)? {
+                continue;
+            }
+
+            let started = time::Instant::now();
+            let packet: I = {
+                let data = &self.buf[..len];
+                let mut r = Cursor::new(data);
+                let ids = self.in_ids.as_deref();
+
+                match self.compression {
+                    Some(_) => {
+                        let compressed_length = read_varint(&mut r)?;
+                        if compressed_length < 0 ||
+                           compressed_length as usize > MAX_UNCOMPRESSED_SIZE {
+                            bail!("Received packet with invalid uncompressed length {}",
+                                  compressed_length);
+                        }
+                        if compressed_length == 0 {
+                            /* Compression is enabled, but the given packet
+                             * is not compressed */
+                            deserialize(&mut r, &self.clientstate, ids)?
+                        } else {
+                            /* Compression is enabled, and the given packet
+                             * is compressed */
+                            let mut r = ZlibDecoder::new(r);
+                            deserialize(&mut r, &self.clientstate, ids)?
+                        }
+                    },
+                    /* Compression is not enabled */
+                    None => deserialize(&mut r, &self.clientstate, ids)?,
+                }
+            };
+
+            self.buf.consume(len);
+            self.packet_len = None;
+
+            if let Some(ref mut stats) = self.stats {
+                /* Include the length header in the size */
+                stats.record(Direction::Inbound,
+                             packet.get_packet_name(),
+                             len + length_header_size(len),
+                             started.elapsed());
+            }
+
+            self.transitions.packet(Direction::Inbound, packet.get_packet_name());
+
+            if let Some(ref mut recorder) = self.recorder {
+                if let Ok(data) = packet.to_u8() {
+                    let _ = recorder.record(Direction::Inbound,
+                                            &self.clientstate,
+                                            packet.get_packet_name(),
+                                            data);
+                }
+            }
+
+            return Ok(Some(packet));
         }
+    }
 
-        let len = match self.packet_len {
+    /* If the complete packet of the given length at the start of the buffer
+     * has a registered custom ID, move it to the custom queue */
+    fn read_custom_frame(&mut self, len: usize) -> Result<bool> {
+        let started = time::Instant::now();
+        let (id, header, uncompressed_len) = match self.peek_id(len)? {
             Some(x) => x,
-            None => {
-                if self.last_read.elapsed() > time::Duration::new(30, 0) {
-                    /* If we haven't read anything for 30 seconds, timeout */
-                    self.close()?;
-                    bail!("Read timeout");
-                } else {
-                    return Ok(None);
-                }
-            },
+            None => return Ok(false),
         };
-
-        if self.buf.len() < len {
-            /* We haven't received enough yet to read the whole packet */
-            if self.last_read.elapsed() > time::Duration::new(30, 0) {
-                /* If we haven't read anything for 30 seconds, timeout */
-                self.close()?;
-                bail!("Read timeout");
+        let name = match self.custom_ids.iter().find(|x| x.0 == self.clientstate && x.1 == id) {
+            Some(x) => x.2,
+            None => return Ok(false),
+        };
+        let data = {
+            let mut packet = Vec::new();
+            if uncompressed_len == 0 {
+                packet.extend_from_slice(&self.buf[header..len]);
             } else {
-                return Ok(None);
-            }
-        } else {
-            self.last_read = time::Instant::now();
-        }
-
-        let packet = {
-            let data = &self.buf[..len];
-            let mut r = Cursor::new(data);
-
-            match self.compression {
-                Some(_) => {
-                    let compressed_length = read_varint(&mut r)?;
-                    if compressed_length == 0 {
-                        /* Compression is enabled, but the given packet
-                         * is not compressed */
-                        I::deserialize(&mut r, &self.clientstate)?
-                    } else {
-                        /* Compression is enabled, and the given packet
-                         * is compressed */
-                        let mut r = ZlibDecoder::new(r);
-                        I::deserialize(&mut r, &self.clientstate)?
-                    }
-                },
-                /* Compression is not enabled */
-                None => I::deserialize(&mut r, &self.clientstate)?,
+                /* Don't inflate more than the uncompressed length, so that
+                 * a small packet can't take up lots of memory */
+                let mut r = ZlibDecoder::new(&self.buf[header..len]);
+                let _: usize = (&mut r).take(uncompressed_len as u64).read_to_end(&mut packet)?;
+                if packet.len() != uncompressed_len || r.read(&mut [0])? != 0 {
+                    bail!("Received packet not of its uncompressed length {}", uncompressed_len);
+                }
             }
+            /* Replace the ID sent over the wire with the translated one */
+            let mut r = Cursor::new(&packet);
+            let _: i32 = read_varint(&mut r)?;
+            let mut data = Vec::with_capacity(packet.len());
+            write_varint(&id, &mut data)?;
+            data.extend_from_slice(&packet[r.position() as usize..]);
+            data
         };
 
         self.buf.consume(len);
         self.packet_len = None;
 
-        Ok(Some(packet))
+        if let Some(ref mut stats) = self.stats {
+            let size = len + length_header_size(len);
+            stats.record(Direction::Inbound, name, size, started.elapsed());
+        }
+        self.transitions.packet(Direction::Inbound, name);
+        if let Some(ref mut recorder) = self.recorder {
+            let _ = recorder.record(Direction::Inbound, &self.clientstate, name, data.clone());
+        }
+
+        self.custom_queue.push_back(RawPacket {
+                                        state: self.clientstate.clone(),
+                                        id,
+                                        data,
+                                    });
+        Ok(true)
+    }
+
+    /// Register a custom packet ID, so that incoming packets with it are kept
+    /// for read_custom() instead of being decoded
+    pub(crate) fn register_custom(&mut self, state: ClientState, id: i32, name: &'static str) {
+        if !self.custom_ids.iter().any(|x| x.0 == state && x.1 == id) {
+            self.custom_ids.push((state, id, name));
+        }
+    }
+
+    /// Get the next received custom packet
+    pub(crate) fn read_custom(&mut self) -> Option<RawPacket> {
+        self.custom_queue.pop_front()
+    }
+
+    /* If the complete packet of the given length at the start of the buffer
+     * is compressed and has a deferred ID, move it to the deferred queue
+     * without decompressing more than the packet ID */
+    fn read_deferred_frame(&mut self, len: usize) -> Result<bool> {
+        if self.compression.is_none() {
+            return Ok(false);
+        }
+        let started = time::Instant::now();
+        let (id, header, uncompressed_len) = match self.peek_id(len)? {
+            /* Uncompressed packets are decoded right away */
+            Some(x) if x.2 != 0 => x,
+            _ => return Ok(false),
+        };
+        let name = match self.deferred_ids.iter().find(|x| x.0 == self.clientstate && x.1 == id) {
+            Some(x) => x.2,
+            None => return Ok(false),
+        };
+
+        let data = self.buf[header..len].to_vec();
+        self.buf.consume(len);
+        self.packet_len = None;
+
+        if let Some(ref mut stats) = self.stats {
+            let size = len + length_header_size(len);
+            stats.record(Direction::Inbound, name, size, started.elapsed());
+        }
+        self.transitions.packet(Direction::Inbound, name);
+        if let Some(ref mut recorder) = self.recorder {
+            let mut uncompressed = Vec::new();
+            if ZlibDecoder::new(&data[..]).read_to_end(&mut uncompressed).is_ok() {
+                let _ = recorder.record(Direction::Inbound, &self.clientstate, name, uncompressed);
+            }
+        }
+
+        self.deferred_queue.push_back(DeferredPacket::new(self.clientstate.clone(),
+                                                          name,
+                                                          data,
+                                                          uncompressed_len,
+                                                          self.in_ids.clone()));
+        Ok(true)
+    }
+
+    /* Read the ID of the complete packet of the given length at the start of
+     * the buffer, translated through in_ids, decompressing no more than the
+     * ID. Also returns the length of the header before the packet, and the
+     * uncompressed length, 0 if the packet isn't compressed. Returns None if
+     * the uncompressed length is invalid, leaving it for read_packet to
+     * reject. */
+    fn peek_id(&self, len: usize) -> Result<Option<(i32, usize, usize)>> {
+        let mut r = Cursor::new(&self.buf[..len]);
+        let uncompressed_len = match self.compression {
+            Some(_) => read_varint(&mut r)?,
+            None => 0,
+        };
+        if uncompressed_len < 0 || uncompressed_len as usize > MAX_UNCOMPRESSED_SIZE {
+            return Ok(None);
+        }
+        let header = r.position() as usize;
+        let mut r: Box<dyn Read> = if uncompressed_len == 0 {
+            Box::new(r)
+        } else {
+            Box::new(ZlibDecoder::new(r))
+        };
+        let id = match self.in_ids {
+            Some(ref ids) => {
+                let id = ids.inbound(&self.clientstate, &mut r)?;
+                read_varint(&mut Cursor::new(id))?
+            },
+            None => read_varint(&mut r)?,
+        };
+        Ok(Some((id, header, uncompressed_len as usize)))
+    }
+
+    /// Keep incoming compressed packets with the given ID compressed, for
+    /// read_deferred()
+    pub(crate) fn register_deferred(&mut self, state: ClientState, id: i32, name: &'static str) {
+        if !self.deferred_ids.iter().any(|x| x.0 == state && x.1 == id) {
+            self.deferred_ids.push((state, id, name));
+        }
+    }
+
+    /// Decode all incoming packets again as they are read. Packets already
+    /// deferred are still returned by read_deferred().
+    pub(crate) fn clear_deferred(&mut self) {
+        self.deferred_ids.clear();
+    }
+
+    /// Get the next received deferred packet
+    pub(crate) fn read_deferred(&mut self) -> Option<DeferredPacket> {
+        self.deferred_queue.pop_front()
+    }
+
+    /* Called when the buffer doesn't contain a complete packet, returns an
+     * error if no more data can be expected */
+    fn need_more(&mut self) -> Result<Option<I>> {
+        if self.eof {
+            self.lifecycle.closed("Connection closed by peer");
+            bail!("Connection closed by peer");
+        }
+        if self.read_timeout.is_some_and(|x| self.last_read.elapsed() > x) {
+            /* If we haven't read anything for a while, timeout */
+            self.lifecycle.closed("Read timeout");
+            self.close()?;
+            bail!("Read timeout");
+        }
+        Ok(None)
     }
 
     /** Tries to read the length of the next packet in the buf, and sets
@@ -333,9 +747,653 @@ impl<I: Packet, O: Packet> Connection<I, O> {
             res
         };
 
+        if res > MAX_PACKET_SIZE {
+            bail!("Received packet that was too long, is {} bytes long", res);
+        }
+
         self.packet_len = Some(res);
         /* Consume the length header as we no longer need it */
         self.buf.consume(i + 1);
         Ok(())
     }
 }
+
+/* The size of the length prefix of a packet of the given length */
+fn length_header_size(len: usize) -> usize {
+    (1..5).find(|&i| len < 1 << (7 * i)).unwrap_or(5)
+}
+
+/* Deserialize a packet, translating its ID first if the peer uses another
+ * protocol version */
+pub(crate) fn deserialize<P: Packet, R: Read>(r: &mut R,
+                                              state: &ClientState,
+                                              ids: Option<&IdMap>)
+                                              -> Result<P> {
+    match ids {
+        Some(ids) => {
+            let id = ids.inbound(state, r)?;
+            P::deserialize(&mut Cursor::new(id).chain(r), state)
+        },
+        None => P::deserialize(r, state),
+    }
+}
+
+/* Frame a serialized packet: prefix it with its length, compressing it if
+ * compression is enabled and it's at least threshold bytes long */
+pub(crate) fn frame(tmp: &[u8], compression: Option<usize>, level: Compression) -> Result<Vec<u8>> {
+    let uncompressed_length = tmp.len();
+    let mut out = Vec::with_capacity(uncompressed_length);
+    match compression {
+        /* Compression is enabled, and the packet length is over the
+         * threshold, thus compress the packet */
+        Some(threshold) if uncompressed_length >= threshold => {
+            /* We have to copy all the data again, because we need
+             * to prefix the packet with length of the compressed data */
+            let mut compressed = Vec::new();
+            write_varint(&(uncompressed_length as i32), &mut compressed)?;
+            let mut compressor = ZlibEncoder::new(compressed, level);
+            compressor.write_all(tmp)?;
+            let compressed = compressor.finish()?;
+
+            write_varint(&(compressed.len() as i32), &mut out)?;
+            out.write_all(&compressed)?;
+        },
+        /* Compression is enabled, but the packet length is not over the
+         * threshold, thus we don't compress the packet */
+        Some(_) => {
+            /* Add 1 to the uncompressed length for the 1 byte it takes
+             * to specify no compression */
+            write_varint(&((uncompressed_length + 1) as i32), &mut out)?;
+            write_varint(&0, &mut out)?;
+            out.write_all(tmp)?;
+        },
+        /* Compression is not enabled */
+        None => {
+            write_varint(&(uncompressed_length as i32), &mut out)?;
+            out.write_all(tmp)?;
+        },
+    }
+    Ok(out)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use {PROTOCOL_VERSION, Server};
+    use clientbound::{self, ClientboundPacket};
+    use http;
+    use read::SmallString;
+    use serverbound::{self, ServerboundPacket};
+    use utils;
+    use transport::MemoryStream;
+    use std::io;
+    use std::sync::Arc;
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::thread;
+
+    /* Read until a packet is available, for at most 5 seconds */
+    fn read_until<I: Packet, O: Packet>(conn: &mut Connection<I, O>) -> I {
+        let start = time::Instant::now();
+        loop {
+            conn.update_inbuf().unwrap();
+            if let Some(packet) = conn.read_packet().unwrap() {
+                return packet;
+            }
+            assert!(start.elapsed() < time::Duration::from_secs(5), "Timed out");
+            thread::sleep(time::Duration::from_millis(5));
+        }
+    }
+
+    #[test]
+    fn offline_login() {
+        let (a, b) = MemoryStream::pair();
+        let mut client: Connection<ClientboundPacket, ServerboundPacket> =
+            Connection::from_transport(Box::new(a));
+        let mut server = Server::from_transport(b).unwrap();
+        client.set_clientstate(ClientState::Login);
+        server.set_clientstate(ClientState::Login);
+        let mut options = ::LoginOptions {
+            offline_mode: false,
+            compression_threshold: Some(64),
+            session_outage: ::SessionOutage::Fail,
+        };
+
+        let _: usize = client.send(&serverbound::LoginStart::new("Notch".to_string())).unwrap();
+        let start = time::Instant::now();
+        while server.read().unwrap().is_empty() {
+            assert!(start.elapsed() < time::Duration::from_secs(5), "Timed out");
+            thread::sleep(time::Duration::from_millis(5));
+        }
+        assert!(server.login_offline(&options).is_err());
+        options.offline_mode = true;
+        let (uuid, username) = server.login_offline(&options).unwrap();
+        assert_eq!((uuid.to_string().as_str(), username.as_str()),
+                   ("b50ad385-829d-3141-a216-7e7d7539ba7f", "Notch"));
+        assert_eq!(server.clientstate(), &ClientState::Play);
+
+        match read_until(&mut client) {
+            ClientboundPacket::SetCompression(..) => client.enable_compression(64),
+            x => panic!("Expected a SetCompression, got {:?}", x),
+        }
+        match read_until(&mut client) {
+            ClientboundPacket::LoginSuccess(ref p) => assert_eq!(*p.get_uuid(), uuid.as_u128()),
+            x => panic!("Expected a LoginSuccess, got {:?}", x),
+        }
+    }
+
+    #[test]
+    fn partial_reads() {
+        let (mut peer, stream) = MemoryStream::pair();
+        let mut server = Server::from_transport(stream).unwrap();
+
+        let handshake = serverbound::Handshake::new(PROTOCOL_VERSION, "localhost".to_string(), 25565, 2);
+        let data = handshake.to_u8().unwrap();
+        let mut frame = Vec::new();
+        write_varint(&(data.len() as i32), &mut frame).unwrap();
+        frame.extend_from_slice(&data);
+
+        assert_eq!(server.try_read_packet().unwrap(), ReadStatus::NeedMore);
+        peer.write_all(&frame[..3]).unwrap();
+        assert_eq!(server.try_read_packet().unwrap(), ReadStatus::NeedMore);
+        peer.write_all(&frame[3..]).unwrap();
+        match server.try_read_packet().unwrap() {
+            ReadStatus::Ready(ServerboundPacket::Handshake(..)) => (),
+            x => panic!("Expected a Handshake, got {:?}", x),
+        }
+
+        drop(peer);
+        assert!(server.try_read_packet().is_err());
+    }
+
+    #[test]
+    fn custom_frames() {
+        let (a, b) = MemoryStream::pair();
+        let mut client: Connection<ClientboundPacket, ServerboundPacket> =
+            Connection::from_transport(Box::new(a));
+        let mut server: Connection<ServerboundPacket, ClientboundPacket> =
+            Connection::from_transport(Box::new(b));
+        client.set_clientstate(ClientState::Play);
+        server.set_clientstate(ClientState::Play);
+        client.enable_compression(64);
+        server.enable_compression(64);
+        client.register_custom(ClientState::Play, 0x60, "Region");
+
+        /* The server sends KeepAlive as 0x60, which isn't the custom packet */
+        let ids = ::protocol_data::ProtocolData::parse(r#"{
+            "protocol": 9999,
+            "packets": {"clientbound": {"Play": {"KeepAlive": 96}}}
+        }"#)
+            .unwrap()
+            .clientbound_ids();
+        client.set_packet_ids(Some(ids.clone()), None);
+        server.set_packet_ids(None, Some(ids));
+        let _: usize = server.send(&clientbound::KeepAlive::new(5)).unwrap();
+        assert_eq!(read_until(&mut client), clientbound::KeepAlive::new(5));
+        assert!(client.read_custom().is_none());
+
+        /* A custom packet inflating to more than its uncompressed length */
+        client.set_packet_ids(None, None);
+        let mut data = Vec::new();
+        write_varint(&0x60, &mut data).unwrap();
+        data.extend_from_slice(&[0; 10000]);
+        let mut packet = Vec::new();
+        write_varint(&100, &mut packet).unwrap();
+        let mut compressor = ZlibEncoder::new(packet, Compression::default());
+        compressor.write_all(&data).unwrap();
+        let packet = compressor.finish().unwrap();
+        let mut frame = Vec::new();
+        write_varint(&(packet.len() as i32), &mut frame).unwrap();
+        frame.extend_from_slice(&packet);
+        client.receive(&frame).unwrap();
+        assert!(client.read_packet().is_err());
+        assert!(client.read_custom().is_none());
+    }
+
+    #[test]
+    fn deferred_decode() {
+        let (a, b) = MemoryStream::pair();
+        let mut client: Connection<ClientboundPacket, ServerboundPacket> =
+            Connection::from_transport(Box::new(a));
+        let mut server: Connection<ServerboundPacket, ClientboundPacket> =
+            Connection::from_transport(Box::new(b));
+        client.set_clientstate(ClientState::Play);
+        server.set_clientstate(ClientState::Play);
+        client.enable_compression(64);
+        server.enable_compression(64);
+        let id = ClientboundPacket::id_by_name(&ClientState::Play, "ChunkData").unwrap();
+        client.register_deferred(ClientState::Play, id, "ChunkData");
+
+        /* Only the compressed ChunkData is deferred */
+        let chunk = clientbound::ChunkData::new(vec![7; 4096]);
+        let _: usize = server.send(&chunk).unwrap();
+        let _: usize = server.send(&clientbound::ChunkData::new(vec![1; 8])).unwrap();
+        let _: usize = server.send(&clientbound::KeepAlive::new(5)).unwrap();
+        match read_until(&mut client) {
+            ClientboundPacket::ChunkData(ref p) => assert_eq!(p.get_data(), &vec![1; 8]),
+            x => panic!("Expected a ChunkData, got {:?}", x),
+        }
+        match read_until(&mut client) {
+            ClientboundPacket::KeepAlive(..) => (),
+            x => panic!("Expected a KeepAlive, got {:?}", x),
+        }
+
+        let deferred = client.read_deferred().unwrap();
+        assert_eq!(deferred.name(), "ChunkData");
+        assert!(deferred.compressed_len() < deferred.uncompressed_len());
+        assert_eq!(deferred.decode().unwrap(), chunk);
+        assert!(client.read_deferred().is_none());
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn unix_transport() {
+        use std::os::unix::net::UnixStream;
+
+        let (a, b) = UnixStream::pair().unwrap();
+        a.set_nonblocking(true).unwrap();
+        b.set_nonblocking(true).unwrap();
+        let mut client = ::Client::from_transport(a).unwrap();
+        let mut server = Server::from_transport(b).unwrap();
+        assert!(server.peer_addr().is_none());
+        assert!(server.raw_fd().is_some());
+        assert!(Server::from_transport(MemoryStream::pair().0).unwrap().raw_fd().is_none());
+
+        let handshake = serverbound::Handshake::new(PROTOCOL_VERSION, "localhost".to_string(), 25565, 2);
+        let _: usize = client.send(&handshake).unwrap();
+        let start = time::Instant::now();
+        let packets = loop {
+            let packets = server.read().unwrap();
+            if !packets.is_empty() {
+                break packets;
+            }
+            assert!(start.elapsed() < time::Duration::from_secs(5), "Timed out");
+            thread::sleep(time::Duration::from_millis(5));
+        };
+        assert_eq!(packets, vec![handshake]);
+
+        server.close().unwrap();
+        thread::sleep(time::Duration::from_millis(50));
+        assert!(client.read().is_err());
+    }
+
+    #[test]
+    fn server_compression() {
+        let (_peer, stream) = MemoryStream::pair();
+        let mut server = Server::from_transport(stream).unwrap();
+
+        assert!(server.enable_compression(256).is_err());
+        server.set_clientstate(ClientState::Login);
+        assert!(server.enable_compression(256).is_ok());
+        assert!(server.enable_compression(256).is_err());
+        assert!(server.set_compression_level(10).is_err());
+        assert!(server.set_compression_level(1).is_ok());
+    }
+
+    /* A MemoryStream that can't be written to while stalled */
+    struct Stalled(MemoryStream, Arc<AtomicBool>);
+    impl Read for Stalled {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            self.0.read(buf)
+        }
+    }
+    impl Write for Stalled {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            if self.1.load(Ordering::SeqCst) {
+                return Err(io::ErrorKind::WouldBlock.into());
+            }
+            self.0.write(buf)
+        }
+        fn flush(&mut self) -> io::Result<()> {
+            self.0.flush()
+        }
+    }
+    impl Transport for Stalled {}
+
+    #[test]
+    fn queued_before_compression() {
+        let (peer, stream) = MemoryStream::pair();
+        let stalled = Arc::new(AtomicBool::new(true));
+        let mut server = Server::from_transport(Stalled(stream, stalled.clone())).unwrap();
+        let mut client = ::Client::from_transport(peer).unwrap();
+        server.set_clientstate(ClientState::Login);
+        client.set_clientstate(ClientState::Login);
+        client.set_auto_handle(true);
+        server.set_send_queue(Some(0)).unwrap();
+
+        /* The buffer can't drain, so the second request would be queued, and
+         * framed with compression once it's moved to the buffer */
+        let request =
+            clientbound::LoginPluginRequest::new(1, "ozelot:test".to_string(), vec![0; 300]);
+        assert!(server.send(&request).unwrap() > 0);
+        assert!(server.send(&request).unwrap() > 0);
+        assert_eq!(server.queued(), 0);
+        let _: usize = server.enable_compression(256).unwrap();
+        let success = clientbound::LoginSuccess::new(0, "test".to_string());
+        let _: usize = server.send(&success).unwrap();
+
+        stalled.store(false, Ordering::SeqCst);
+        while server.pending_write() > 0 {
+            let _: usize = server.write().unwrap();
+        }
+        let mut packets = Vec::new();
+        let start = time::Instant::now();
+        while packets.len() < 4 {
+            packets.extend(client.read().unwrap());
+            assert!(start.elapsed() < time::Duration::from_secs(5), "Timed out");
+        }
+        let names: Vec<_> = packets.iter().map(|x| x.get_packet_name()).collect();
+        assert_eq!(names,
+                   vec!["LoginPluginRequest",
+                        "LoginPluginRequest",
+                        "SetCompression",
+                        "LoginSuccess"]);
+    }
+
+    #[test]
+    fn aes_cfb8_vector() {
+        /* NIST SP 800-38A F.3.7, CFB8-AES128.Encrypt */
+        let key = [0x2b, 0x7e, 0x15, 0x16, 0x28, 0xae, 0xd2, 0xa6, 0xab, 0xf7, 0x15, 0x88, 0x09,
+                   0xcf, 0x4f, 0x3c];
+        let iv = [0x00, 0x01, 0x02, 0x03, 0x04, 0x05, 0x06, 0x07, 0x08, 0x09, 0x0a, 0x0b, 0x0c,
+                  0x0d, 0x0e, 0x0f];
+        let plaintext = [0x6b, 0xc1, 0xbe, 0xe2, 0x2e, 0x40, 0x9f, 0x96, 0xe9, 0x3d, 0x7e, 0x11,
+                         0x73, 0x93, 0x17, 0x2a, 0xae, 0x2d];
+        let ciphertext = [0x3b, 0x79, 0x42, 0x4c, 0x9c, 0x0d, 0xd4, 0x36, 0xba, 0xce, 0x9e, 0x0e,
+                          0xd4, 0x58, 0x6a, 0x4f, 0x32, 0xb9];
+        let mut crypter = symm::Crypter::new(symm::Cipher::aes_128_cfb8(),
+                                             symm::Mode::Encrypt,
+                                             &key,
+                                             Some(&iv))
+                .unwrap();
+        let mut out = vec![0; plaintext.len() + 16];
+        let n = crypter.update(&plaintext, &mut out).unwrap();
+        assert_eq!(&out[..n], &ciphertext[..]);
+    }
+
+    #[test]
+    fn encrypted_framing() {
+        /* The shared secret is used as both key and IV, and each direction
+         * is a separate stream starting from it */
+        let key = [0x00, 0x01, 0x02, 0x03, 0x04, 0x05, 0x06, 0x07, 0x08, 0x09, 0x0a, 0x0b, 0x0c,
+                   0x0d, 0x0e, 0x0f];
+        let (mut peer, stream) = MemoryStream::pair();
+        let mut conn: Connection<ServerboundPacket, ClientboundPacket> =
+            Connection::from_transport(Box::new(stream));
+        conn.enable_encryption(&key);
+
+        /* Handshake(404, "localhost", 25565, 2), encrypted */
+        peer.write_all(&[0x1a, 0xa4, 0x41, 0xf8, 0x5a, 0x3a, 0x13, 0xb7, 0xbe, 0xfe, 0x1c,
+                          0xc9, 0xdb, 0xe9, 0xfa, 0x93, 0xce])
+            .unwrap();
+        match read_until(&mut conn) {
+            ServerboundPacket::Handshake(ref p) => {
+                assert_eq!(*p.get_protocol_version(), 404);
+                assert_eq!(p.get_server_address(), "localhost");
+                assert_eq!(*p.get_server_port(), 25565);
+            },
+            x => panic!("Expected a Handshake, got {:?}", x),
+        }
+
+        /* StatusPong(1), encrypted */
+        let _: usize = conn.send(&clientbound::StatusPong::new(1)).unwrap();
+        let mut out = [0; 10];
+        peer.read_exact(&mut out).unwrap();
+        assert_eq!(out, [0x03, 0xbd, 0xad, 0x01, 0x37, 0x00, 0x93, 0x33, 0x53, 0xdd]);
+    }
+
+    /* Deliver the stream to a new connection in pieces of the given lengths,
+     * as a slow network might, and return the packets read. Checks that each
+     * packet is read as soon as its last byte has arrived, and not before. */
+    fn deliver(stream: &[u8],
+               frame_ends: &[usize],
+               compression: Option<usize>,
+               key: Option<&[u8; 16]>,
+               pieces: &[usize])
+               -> Vec<ServerboundPacket> {
+        let (_peer, socket) = MemoryStream::pair();
+        let mut conn: Connection<ServerboundPacket, ClientboundPacket> =
+            Connection::from_transport(Box::new(socket));
+        conn.set_clientstate(ClientState::Play);
+        if let Some(threshold) = compression {
+            conn.enable_compression(threshold);
+        }
+        if let Some(key) = key {
+            conn.enable_encryption(key);
+        }
+
+        let mut ret = Vec::new();
+        let mut delivered = 0;
+        for &len in pieces {
+            conn.receive(&stream[delivered..delivered + len]).unwrap();
+            delivered += len;
+            while let Some(packet) = conn.read_packet().unwrap() {
+                ret.push(packet);
+            }
+            let complete = frame_ends.iter().filter(|&&end| end <= delivered).count();
+            assert_eq!(ret.len(), complete, "After {} of {} bytes", delivered, stream.len());
+        }
+        assert_eq!(delivered, stream.len());
+        ret
+    }
+
+    #[test]
+    fn fragmented_frames() {
+        let packets = vec![serverbound::ChatMessage::new("hi".to_string()),
+                           serverbound::PluginMessage::new(SmallString::from("minecraft:brand"),
+                                                           vec![3; 3000]),
+                           serverbound::ChatMessage::new("x".repeat(200)),
+                           serverbound::KeepAlive::new(-1)];
+        let key = [7; 16];
+
+        for &compression in &[None, Some(64)] {
+            for &encrypted in &[false, true] {
+                let mut stream = Vec::new();
+                let mut frame_ends = Vec::new();
+                for packet in &packets {
+                    stream.extend(frame(&packet.to_u8().unwrap(),
+                                        compression,
+                                        Compression::default())
+                                          .unwrap());
+                    frame_ends.push(stream.len());
+                }
+                if encrypted {
+                    let mut enc = symm::Crypter::new(symm::Cipher::aes_128_cfb8(),
+                                                     symm::Mode::Encrypt,
+                                                     &key,
+                                                     Some(&key))
+                        .unwrap();
+                    let mut tmp = vec![0; stream.len() + 16];
+                    let n = enc.update(&stream, &mut tmp).unwrap();
+                    tmp.truncate(n);
+                    stream = tmp;
+                }
+                let key = if encrypted { Some(&key) } else { None };
+
+                /* One byte at a time */
+                let pieces = vec![1; stream.len()];
+                assert_eq!(deliver(&stream, &frame_ends, compression, key, &pieces), packets);
+
+                /* Split once around each frame boundary, including within the
+                 * length header of the next frame */
+                for &end in &frame_ends {
+                    for split in end.saturating_sub(2)..end + 3 {
+                        let split = split.min(stream.len());
+                        let pieces = [split, stream.len() - split];
+                        assert_eq!(deliver(&stream, &frame_ends, compression, key, &pieces),
+                                   packets);
+                    }
+                }
+
+                /* Irregular pieces of up to 7 bytes */
+                let mut pieces = Vec::new();
+                let mut left = stream.len();
+                let mut seed: u32 = 12345;
+                while left > 0 {
+                    seed = seed.wrapping_mul(1103515245).wrapping_add(12345);
+                    let len = ((seed >> 16) as usize % 7 + 1).min(left);
+                    pieces.push(len);
+                    left -= len;
+                }
+                assert_eq!(deliver(&stream, &frame_ends, compression, key, &pieces), packets);
+            }
+        }
+    }
+
+    thread_local! {
+        static SESSION_DOWN: ::std::cell::Cell<bool> = const { ::std::cell::Cell::new(false) };
+    }
+
+    /* Fails the requests made on threads with SESSION_DOWN set, as if the
+     * session servers were unreachable */
+    struct SessionDown;
+    impl http::Interceptor for SessionDown {
+        fn before_request(&self, _: &mut http::Request) -> Result<()> {
+            if SESSION_DOWN.with(|x| x.get()) {
+                return Err(io::Error::new(io::ErrorKind::TimedOut, "Session servers down").into());
+            }
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn session_outage_login() {
+        let (a, b) = MemoryStream::pair();
+        let mut client: Connection<ClientboundPacket, ServerboundPacket> =
+            Connection::from_transport(Box::new(a));
+        let mut server = Server::from_transport(b).unwrap();
+        client.set_clientstate(ClientState::Login);
+        server.set_clientstate(ClientState::Login);
+        server.set_fallback_verifier(Some(Arc::new(|username, _| match username {
+            "Notch" => Ok(::uuid::Uuid(5)),
+            _ => bail!("Unknown player"),
+        })));
+        let options = ::LoginOptions {
+            offline_mode: false,
+            compression_threshold: None,
+            session_outage: ::SessionOutage::Fallback,
+        };
+
+        let rsa = utils::generate_rsa_key();
+        let shared_secret = utils::create_shared_secret();
+        let response = serverbound::EncryptionResponse::new_unencrypted(&utils::rsa_key_binary(&rsa),
+                                                                        &shared_secret,
+                                                                        &[1, 2, 3, 4])
+                .unwrap();
+        let _: usize = client.send(&serverbound::LoginStart::new("Notch".to_string())).unwrap();
+        let _: usize = client.send(&response).unwrap();
+        let mut packets = Vec::new();
+        let start = time::Instant::now();
+        while packets.len() < 2 {
+            packets.extend(server.read().unwrap());
+            assert!(start.elapsed() < time::Duration::from_secs(5), "Timed out");
+            thread::sleep(time::Duration::from_millis(5));
+        }
+        let response = match packets[1] {
+            ServerboundPacket::EncryptionResponse(ref p) => p.clone(),
+            ref x => panic!("Expected an EncryptionResponse, got {:?}", x),
+        };
+
+        let interceptor: Arc<dyn http::Interceptor> = Arc::new(SessionDown);
+        http::add_interceptor(interceptor.clone());
+        SESSION_DOWN.with(|x| x.set(true));
+        let login = server.login_online(&response, &rsa, &[1, 2, 3, 4], "", &options);
+        SESSION_DOWN.with(|x| x.set(false));
+        http::remove_interceptor(&interceptor);
+        assert_eq!(login.unwrap().0, ::uuid::Uuid(5));
+        assert_eq!(server.clientstate(), &ClientState::Play);
+    }
+
+    #[test]
+    fn loopback_login() {
+        let (a, b) = MemoryStream::pair();
+        let mut client: Connection<ClientboundPacket, ServerboundPacket> =
+            Connection::from_transport(Box::new(a));
+        let mut server: Connection<ServerboundPacket, ClientboundPacket> =
+            Connection::from_transport(Box::new(b));
+        client.set_clientstate(ClientState::Login);
+        server.set_clientstate(ClientState::Login);
+
+        /* The RSA handshake, as a vanilla server does it */
+        let rsa = utils::generate_rsa_key();
+        let verify_token = vec![1, 2, 3, 4];
+        let request = clientbound::EncryptionRequest::new(String::new(),
+                                                          utils::rsa_key_binary(&rsa),
+                                                          verify_token.clone());
+        let _: usize = server.send(&request).unwrap();
+        let public_key = match read_until(&mut client) {
+            ClientboundPacket::EncryptionRequest(ref p) => p.get_public_key().clone(),
+            x => panic!("Expected an EncryptionRequest, got {:?}", x),
+        };
+        let shared_secret = utils::create_shared_secret();
+        let response = serverbound::EncryptionResponse::new_unencrypted(&public_key,
+                                                                        &shared_secret,
+                                                                        &verify_token)
+                .unwrap();
+        let _: usize = client.send(&response).unwrap();
+        client.enable_encryption(&shared_secret);
+        match read_until(&mut server) {
+            ServerboundPacket::EncryptionResponse(ref p) => {
+                let secret = p.verify(&rsa, &verify_token).unwrap();
+                assert_eq!(secret, shared_secret);
+                server.enable_encryption(&shared_secret);
+            },
+            x => panic!("Expected an EncryptionResponse, got {:?}", x),
+        }
+
+        /* Compression, then packets under and over the threshold in both
+         * directions */
+        let _: usize = server.send(&clientbound::SetCompression::new(64)).unwrap();
+        server.enable_compression(64);
+        match read_until(&mut client) {
+            ClientboundPacket::SetCompression(..) => client.enable_compression(64),
+            x => panic!("Expected a SetCompression, got {:?}", x),
+        }
+        let _: usize = server.send(&clientbound::LoginSuccess::new(1, "Bob".to_string())).unwrap();
+        match read_until(&mut client) {
+            ClientboundPacket::LoginSuccess(ref p) => assert_eq!(p.get_username(), "Bob"),
+            x => panic!("Expected a LoginSuccess, got {:?}", x),
+        }
+        client.set_clientstate(ClientState::Play);
+        server.set_clientstate(ClientState::Play);
+
+        let message = "x".repeat(200);
+        for &len in &[10, 200] {
+            let _: usize = server.send(&clientbound::ClientboundPluginMessage::new(SmallString::from("test"),
+                                                                        vec![7; len]))
+                    .unwrap();
+            match read_until(&mut client) {
+                ClientboundPacket::ClientboundPluginMessage(ref p) => assert_eq!(p.get_data().len(), len),
+                x => panic!("Expected a ClientboundPluginMessage, got {:?}", x),
+            }
+            let _: usize = client.send(&serverbound::ChatMessage::new(message[..len].to_string()))
+                .unwrap();
+            match read_until(&mut server) {
+                ServerboundPacket::ChatMessage(ref p) => assert_eq!(p.get_message().len(), len),
+                x => panic!("Expected a ChatMessage, got {:?}", x),
+            }
+        }
+    }
+
+    #[test]
+    fn encryption_response_validation() {
+        use errors::{Error, ErrorKind};
+
+        let rsa = utils::generate_rsa_key();
+        let public_key = utils::rsa_key_binary(&rsa);
+        let check = |secret: &[u8], token: &[u8]| -> Result<[u8; 16]> {
+            match serverbound::EncryptionResponse::new_unencrypted(&public_key, secret, token)? {
+                ServerboundPacket::EncryptionResponse(ref p) => p.verify(&rsa, &[1, 2, 3, 4]),
+                _ => unreachable!(),
+            }
+        };
+        assert!(check(&[7; 16], &[1, 2, 3, 4]).is_ok());
+        match check(&[7; 16], &[4, 3, 2, 1]) {
+            Err(Error(ErrorKind::VerifyTokenMismatch, _)) => (),
+            x => panic!("Expected a VerifyTokenMismatch, got {:?}", x),
+        }
+        match check(&[7; 15], &[1, 2, 3, 4]) {
+            Err(Error(ErrorKind::BadSharedSecret(15), _)) => (),
+            x => panic!("Expected a BadSharedSecret, got {:?}", x),
+        }
+    }
+}
@@ -0,0 +1,100 @@
+//! Limits of the protocol and protocol version numbers
+//!
+//! The limits are those enforced by the vanilla client and server of the
+//! supported version, PROTOCOL_VERSION. ozelot checks incoming and outgoing
+//! data against them where it validates something, and they're available
+//! for validating your own data before sending it.
+
+/// The maximum length of a packet, i.e. the largest 3 byte varint
+pub const MAX_PACKET_SIZE: usize = (1 << 21) - 1;
+/// The maximum length of a packet once decompressed
+pub const MAX_UNCOMPRESSED_SIZE: usize = 1 << 21;
+/// The maximum length in bytes of a string read by ozelot. The protocol
+/// limits strings to 32767 characters, and most fields to much less.
+pub const MAX_STRING_BYTES: usize = 1 << 16;
+
+/// The maximum length of a username
+pub const MAX_USERNAME_LENGTH: usize = 16;
+/// The maximum length of a chat message sent by a client
+pub const MAX_CHAT_LENGTH: usize = 256;
+/// The maximum length of a namespaced identifier, e.g. an advancement tab
+pub const MAX_IDENTIFIER_LENGTH: usize = 32767;
+/// The maximum length of the server address in a Handshake
+pub const MAX_SERVER_ADDRESS_LENGTH: usize = 255;
+/// The maximum length of a team or objective name
+pub const MAX_TEAM_NAME_LENGTH: usize = 16;
+/// The maximum length of the name of a score holder, e.g. a player or
+/// entity UUID
+pub const MAX_SCORE_HOLDER_LENGTH: usize = 40;
+/// The maximum length of chat json, e.g. a display name
+pub const MAX_CHAT_JSON_LENGTH: usize = 32767;
+/// The maximum length of a page of a book
+pub const MAX_BOOK_PAGE_LENGTH: usize = 256;
+/// The maximum amount of pages of a book
+pub const MAX_BOOK_PAGES: usize = 50;
+/// The maximum length of the title of a signed book
+pub const MAX_BOOK_TITLE_LENGTH: usize = 16;
+/// The maximum length of an item name set in an anvil
+pub const MAX_ITEM_NAME_LENGTH: usize = 35;
+/// The maximum size of the data of a clientbound plugin message
+pub const MAX_CLIENTBOUND_PLUGIN_MESSAGE: usize = 1048576;
+/// The maximum size of the data of a serverbound plugin message
+pub const MAX_SERVERBOUND_PLUGIN_MESSAGE: usize = 32767;
+
+/// The minimum view distance in chunks the client can request
+pub const MIN_VIEW_DISTANCE: u8 = 2;
+/// The maximum view distance in chunks the client can request
+pub const MAX_VIEW_DISTANCE: u8 = 32;
+
+/* The protocol versions of releases. Note that ozelot only speaks
+ * PROTOCOL_VERSION, these are for identifying peers. */
+pub const PROTOCOL_1_12_2: i32 = 340;
+pub const PROTOCOL_1_13: i32 = 393;
+pub const PROTOCOL_1_13_1: i32 = 401;
+pub const PROTOCOL_1_13_2: i32 = 404;
+pub const PROTOCOL_1_14: i32 = 477;
+pub const PROTOCOL_1_14_4: i32 = 498;
+pub const PROTOCOL_1_15_2: i32 = 578;
+pub const PROTOCOL_1_16_5: i32 = 754;
+pub const PROTOCOL_1_17_1: i32 = 756;
+pub const PROTOCOL_1_18_2: i32 = 758;
+pub const PROTOCOL_1_19_4: i32 = 762;
+pub const PROTOCOL_1_20_4: i32 = 765;
+
+/// Releases and their protocol versions, oldest first
+pub const RELEASES: &[(&str, i32)] = &[("1.12.2", PROTOCOL_1_12_2),
+                                       ("1.13", PROTOCOL_1_13),
+                                       ("1.13.1", PROTOCOL_1_13_1),
+                                       ("1.13.2", PROTOCOL_1_13_2),
+                                       ("1.14", PROTOCOL_1_14),
+                                       ("1.14.4", PROTOCOL_1_14_4),
+                                       ("1.15.2", PROTOCOL_1_15_2),
+                                       ("1.16.5", PROTOCOL_1_16_5),
+                                       ("1.17.1", PROTOCOL_1_17_1),
+                                       ("1.18.2", PROTOCOL_1_18_2),
+                                       ("1.19.4", PROTOCOL_1_19_4),
+                                       ("1.20.4", PROTOCOL_1_20_4)];
+
+/// Get the protocol version of the given release, e.g. "1.13.2"
+pub fn protocol_of(release: &str) -> Option<i32> {
+    RELEASES.iter().find(|x| x.0 == release).map(|x| x.1)
+}
+
+/// Get the release with the given protocol version
+pub fn release_of(protocol: i32) -> Option<&'static str> {
+    RELEASES.iter().find(|x| x.1 == protocol).map(|x| x.0)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use PROTOCOL_VERSION;
+
+    #[test]
+    fn releases() {
+        assert_eq!(release_of(PROTOCOL_VERSION), Some("1.13.2"));
+        assert_eq!(protocol_of("1.12.2"), Some(340));
+        assert_eq!(protocol_of("1.0"), None);
+        assert!(RELEASES.windows(2).all(|x| x[0].1 < x[1].1));
+    }
+}
@@ -0,0 +1,569 @@
+//! File-backed storage of authentication tokens
+//!
+//! Access tokens are effectively account passwords, so the store can be
+//! encrypted with a passphrase. The key is derived using PBKDF2-HMAC-SHA256,
+//! and the accounts are encrypted with AES-256-GCM.
+//!
+//! # Examples
+//!
+//! ```rust,no_run
+//! use ozelot::credentials::CredentialStore;
+//! use ozelot::mojang::Authenticate;
+//!
+//! let mut store = CredentialStore::open_encrypted("accounts.json",
+//!                                                 "my passphrase").unwrap();
+//! if store.get("my_email@example.com").is_none() {
+//!     let auth = Authenticate::new("my_email@example.com".to_string(),
+//!                                  "my_password".to_string())
+//!         .perform().unwrap();
+//!     let _ = store.insert("my_email@example.com", auth);
+//!     store.save().unwrap();
+//! }
+//! ```
+//!
+//! A TokenStore additionally keeps the tokens valid, validating them with
+//! the authserver and refreshing them when they've expired:
+//!
+//! ```rust,no_run
+//! use ozelot::credentials::TokenStore;
+//! use ozelot::Client;
+//!
+//! let mut store = TokenStore::open("accounts.json").unwrap();
+//! let auth = store.get("my_email@example.com").unwrap().unwrap();
+//! let mut client = Client::connect_authenticated("minecraft.example.com",
+//!                                                25565,
+//!                                                &auth).unwrap();
+//! ```
+
+use errors::{Error, ErrorKind, Result, ResultExt};
+use json::AuthenticationResponse;
+use mojang::{AuthenticateRefresh, AuthenticateValidate};
+
+use openssl::base64;
+use openssl::hash::MessageDigest;
+use openssl::pkcs5;
+use openssl::rand;
+use openssl::symm::{self, Cipher};
+
+use serde::Serialize;
+use serde::de::DeserializeOwned;
+use serde_json;
+
+use std::collections::BTreeMap;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Read, Write};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+const KDF: &str = "pbkdf2-hmac-sha256";
+const KDF_ITERATIONS: usize = 100_000;
+/// How long TokenStore trusts a validated token before validating it again
+pub const REVALIDATE_AFTER: Duration = Duration::from_secs(5 * 60);
+
+type Accounts = BTreeMap<String, AuthenticationResponse>;
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct StoreFile {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    accounts: Option<Accounts>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    encrypted: Option<EncryptedAccounts>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub(crate) struct EncryptedAccounts {
+    kdf: String,
+    iterations: usize,
+    /* The following are all base64 encoded */
+    salt: String,
+    nonce: String,
+    tag: String,
+    ciphertext: String,
+}
+
+/// A file of access tokens, optionally encrypted with a passphrase.
+///
+/// Accounts are keyed by any name you like, e.g. the email address used to
+/// log in.
+#[derive(Debug)]
+pub struct CredentialStore {
+    path: PathBuf,
+    passphrase: Option<String>,
+    accounts: Accounts,
+}
+impl CredentialStore {
+    /// Open the unencrypted store at the given path. If the file doesn't
+    /// exist, the store starts out empty.
+    ///
+    /// Returns an error if the file is encrypted.
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Self> {
+        CredentialStore::open_inner(path.as_ref(), None)
+    }
+
+    /// Open the store at the given path, encrypted with the given
+    /// passphrase. If the file doesn't exist, the store starts out empty.
+    ///
+    /// If the file exists but isn't encrypted, it'll be encrypted the next
+    /// time the store is saved.
+    pub fn open_encrypted<P: AsRef<Path>>(path: P, passphrase: &str) -> Result<Self> {
+        CredentialStore::open_inner(path.as_ref(), Some(passphrase))
+    }
+
+    fn open_inner(path: &Path, passphrase: Option<&str>) -> Result<Self> {
+        let file = match File::open(path) {
+            Ok(mut f) => {
+                let mut contents = String::new();
+                let _: usize = f.read_to_string(&mut contents)?;
+                serde_json::from_str(&contents)
+                    .chain_err(|| "Unable to parse credential store")?
+            },
+            Err(ref e) if e.kind() == io::ErrorKind::NotFound => {
+                StoreFile::default()
+            },
+            Err(e) => return Err(e.into()),
+        };
+
+        let accounts = match (file.encrypted, passphrase) {
+            (Some(ref encrypted), Some(passphrase)) => {
+                decrypt(encrypted, passphrase)?
+            },
+            (Some(_), None) => {
+                bail!("The credential store is encrypted, but no passphrase was given");
+            },
+            (None, _) => file.accounts.unwrap_or_default(),
+        };
+
+        Ok(CredentialStore {
+            path: path.to_path_buf(),
+            passphrase: passphrase.map(|x| x.to_string()),
+            accounts,
+        })
+    }
+
+    /// Whether the store is saved encrypted
+    pub fn is_encrypted(&self) -> bool {
+        self.passphrase.is_some()
+    }
+
+    /// Change the passphrase, or with None stop encrypting the store. Takes
+    /// effect the next time the store is saved.
+    pub fn set_passphrase(&mut self, passphrase: Option<&str>) {
+        self.passphrase = passphrase.map(|x| x.to_string());
+    }
+
+    /// Get the tokens of the given account
+    pub fn get(&self, account: &str) -> Option<&AuthenticationResponse> {
+        self.accounts.get(account)
+    }
+
+    /// Store the tokens of the given account, returning the old ones if any
+    pub fn insert(&mut self,
+                  account: &str,
+                  auth: AuthenticationResponse)
+                  -> Option<AuthenticationResponse> {
+        self.accounts.insert(account.to_string(), auth)
+    }
+
+    /// Remove the given account, returning its tokens if it existed
+    pub fn remove(&mut self, account: &str) -> Option<AuthenticationResponse> {
+        self.accounts.remove(account)
+    }
+
+    /// Get the names of all the stored accounts
+    pub fn accounts(&self) -> Vec<&str> {
+        self.accounts.keys().map(|x| x.as_str()).collect()
+    }
+
+    /// Write the store to disk.
+    ///
+    /// The file is replaced atomically, and on unix it's only readable by
+    /// the owner.
+    pub fn save(&self) -> Result<()> {
+        let file = match self.passphrase {
+            Some(ref passphrase) => StoreFile {
+                accounts: None,
+                encrypted: Some(encrypt(&self.accounts, passphrase)?),
+            },
+            None => StoreFile {
+                accounts: Some(self.accounts.clone()),
+                encrypted: None,
+            },
+        };
+        let contents = serde_json::to_string_pretty(&file)?;
+        write_private(&self.path, &contents)
+    }
+}
+
+/// A CredentialStore that keeps its tokens valid.
+///
+/// The tokens are validated with the authserver when the store is opened,
+/// and again when they're retrieved if they haven't been for
+/// REVALIDATE_AFTER. Invalid tokens are refreshed with their client token,
+/// and the store is saved whenever tokens are refreshed, inserted or
+/// removed.
+#[derive(Debug)]
+pub struct TokenStore {
+    store: CredentialStore,
+    /* When the tokens of each account were last known to be valid */
+    validated: BTreeMap<String, Instant>,
+}
+impl TokenStore {
+    /// Open the unencrypted store at the given path and validate its tokens,
+    /// see CredentialStore::open() and validate_all()
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Self> {
+        TokenStore::validated(CredentialStore::open(path)?)
+    }
+
+    /// Open the store encrypted with the given passphrase and validate its
+    /// tokens, see CredentialStore::open_encrypted() and validate_all()
+    pub fn open_encrypted<P: AsRef<Path>>(path: P, passphrase: &str) -> Result<Self> {
+        TokenStore::validated(CredentialStore::open_encrypted(path, passphrase)?)
+    }
+
+    /// Use the given store, without validating its tokens until they're
+    /// retrieved
+    pub fn from_store(store: CredentialStore) -> Self {
+        TokenStore {
+            store,
+            validated: BTreeMap::new(),
+        }
+    }
+
+    /* Tokens that can't be validated now are tried again when retrieved */
+    fn validated(store: CredentialStore) -> Result<Self> {
+        let mut ret = TokenStore::from_store(store);
+        let _: Vec<(String, Error)> = ret.validate_all()?;
+        Ok(ret)
+    }
+
+    /// Validate the tokens of all accounts, refreshing the invalid ones.
+    /// Returns the accounts whose tokens couldn't be validated or refreshed,
+    /// with the errors, or an error if the store couldn't be saved.
+    pub fn validate_all(&mut self) -> Result<Vec<(String, Error)>> {
+        let accounts: Vec<String> = self.store.accounts.keys().cloned().collect();
+        let mut failed = Vec::new();
+        let mut refreshed = false;
+        for account in accounts {
+            match self.validate(&account) {
+                Ok(x) => refreshed |= x,
+                Err(e) => failed.push((account, e)),
+            }
+        }
+        if refreshed {
+            self.store.save()?;
+        }
+        Ok(failed)
+    }
+
+    /* Validate the tokens of the account, refreshing them if invalid.
+     * Returns whether they were refreshed. */
+    fn validate(&mut self, account: &str) -> Result<bool> {
+        let refreshed = match self.store.accounts.get_mut(account) {
+            Some(auth) => ensure_valid(auth)
+                .chain_err(|| format!("Unable to validate the tokens of {}", account))?,
+            None => return Ok(false),
+        };
+        let _: Option<Instant> = self.validated.insert(account.to_string(), Instant::now());
+        Ok(refreshed)
+    }
+
+    /// Get valid tokens of the given account, validating and refreshing
+    /// them if needed
+    pub fn get(&mut self, account: &str) -> Result<Option<AuthenticationResponse>> {
+        let fresh = self.validated
+            .get(account)
+            .is_some_and(|x| x.elapsed() < REVALIDATE_AFTER);
+        if !fresh && self.validate(account)? {
+            self.store.save()?;
+        }
+        Ok(self.store.get(account).cloned())
+    }
+
+    /// Store newly obtained tokens of the given account, e.g. from
+    /// Authenticate, and save the store. Returns the old tokens if any.
+    pub fn insert(&mut self,
+                  account: &str,
+                  auth: AuthenticationResponse)
+                  -> Result<Option<AuthenticationResponse>> {
+        let ret = self.store.insert(account, auth);
+        let _: Option<Instant> = self.validated.insert(account.to_string(), Instant::now());
+        self.store.save()?;
+        Ok(ret)
+    }
+
+    /// Remove the given account and save the store, returning its tokens if
+    /// it existed
+    pub fn remove(&mut self, account: &str) -> Result<Option<AuthenticationResponse>> {
+        let ret = self.store.remove(account);
+        let _: Option<Instant> = self.validated.remove(account);
+        self.store.save()?;
+        Ok(ret)
+    }
+
+    /// Get the names of all the stored accounts
+    pub fn accounts(&self) -> Vec<&str> {
+        self.store.accounts()
+    }
+
+    /// Get the underlying store
+    pub fn store(&self) -> &CredentialStore {
+        &self.store
+    }
+
+    /// Give back the underlying store, dropping the record of when each
+    /// token was last validated
+    pub fn into_store(self) -> CredentialStore {
+        self.store
+    }
+}
+
+/* Validate Mojang tokens with the authserver, refreshing them if they're
+ * invalid. Returns whether they were refreshed. Other errors, e.g. the
+ * authserver being unreachable, don't say anything about the tokens, and
+ * refreshing would only invalidate them for nothing. */
+pub(crate) fn ensure_valid(auth: &mut AuthenticationResponse) -> Result<bool> {
+    let validate = AuthenticateValidate::new(auth.accessToken.clone(),
+                                             auth.clientToken.clone());
+    match validate.perform() {
+        Ok(()) => return Ok(false),
+        Err(Error(ErrorKind::InvalidToken(_), _)) => (),
+        Err(e) => return Err(e),
+    }
+    *auth = refresh(auth)?;
+    Ok(true)
+}
+
+/* Refresh Mojang tokens, which requires the client token */
+pub(crate) fn refresh(auth: &AuthenticationResponse) -> Result<AuthenticationResponse> {
+    let client_token = match auth.clientToken {
+        Some(ref x) => x.clone(),
+        None => bail!("Unable to refresh a Mojang token without a client token"),
+    };
+    AuthenticateRefresh::new(auth.accessToken.clone(), client_token, false)
+        .perform()
+        .chain_err(|| "Unable to refresh the Mojang token")
+}
+
+/* Atomically replace the file at path with contents, only readable by the
+ * owner on unix */
+pub(crate) fn write_private(path: &Path, contents: &str) -> Result<()> {
+    let mut tmp = path.to_path_buf().into_os_string();
+    tmp.push(".tmp");
+    let tmp = PathBuf::from(tmp);
+    {
+        let mut options = OpenOptions::new();
+        let _: &mut OpenOptions = options.write(true)
+            .create(true)
+            .truncate(true);
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::OpenOptionsExt;
+            let _: &mut OpenOptions = options.mode(0o600);
+        }
+        let mut f = options.open(&tmp)?;
+        f.write_all(contents.as_bytes())?;
+        f.sync_all()?;
+    }
+    fs::rename(&tmp, path)?;
+    Ok(())
+}
+
+fn derive_key(passphrase: &str, salt: &[u8], iterations: usize) -> Result<[u8; 32]> {
+    let mut key = [0; 32];
+    pkcs5::pbkdf2_hmac(passphrase.as_bytes(),
+                       salt,
+                       iterations,
+                       MessageDigest::sha256(),
+                       &mut key)?;
+    Ok(key)
+}
+
+/* Also used by the accounts module, so that both files are encrypted the
+ * same way */
+pub(crate) fn encrypt<T: Serialize>(accounts: &T, passphrase: &str) -> Result<EncryptedAccounts> {
+    let mut salt = [0; 16];
+    let mut nonce = [0; 12];
+    rand::rand_bytes(&mut salt)?;
+    rand::rand_bytes(&mut nonce)?;
+    let key = derive_key(passphrase, &salt, KDF_ITERATIONS)?;
+
+    let plaintext = serde_json::to_vec(accounts)?;
+    let mut tag = [0; 16];
+    let ciphertext = symm::encrypt_aead(Cipher::aes_256_gcm(),
+                                        &key,
+                                        Some(&nonce),
+                                        &[],
+                                        &plaintext,
+                                        &mut tag)?;
+    Ok(EncryptedAccounts {
+        kdf: KDF.to_string(),
+        iterations: KDF_ITERATIONS,
+        salt: base64::encode_block(&salt),
+        nonce: base64::encode_block(&nonce),
+        tag: base64::encode_block(&tag),
+        ciphertext: base64::encode_block(&ciphertext),
+    })
+}
+
+pub(crate) fn decrypt<T: DeserializeOwned>(encrypted: &EncryptedAccounts,
+                                          passphrase: &str)
+                                          -> Result<T> {
+    if encrypted.kdf != KDF {
+        bail!("Unsupported credential store key derivation function {}",
+              encrypted.kdf);
+    }
+    let salt = base64::decode_block(&encrypted.salt)?;
+    let nonce = base64::decode_block(&encrypted.nonce)?;
+    let tag = base64::decode_block(&encrypted.tag)?;
+    let ciphertext = base64::decode_block(&encrypted.ciphertext)?;
+    let key = derive_key(passphrase, &salt, encrypted.iterations)?;
+
+    let plaintext = symm::decrypt_aead(Cipher::aes_256_gcm(),
+                                       &key,
+                                       Some(&nonce),
+                                       &[],
+                                       &ciphertext,
+                                       &tag)
+            .chain_err(|| "Unable to decrypt the credential store, wrong passphrase?")?;
+    Ok(serde_json::from_slice(&plaintext)?)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use http;
+    use json::NameUUID;
+    use std::collections::VecDeque;
+    use std::env;
+    use std::sync::{Arc, Mutex};
+
+    fn auth() -> AuthenticationResponse {
+        AuthenticationResponse {
+            accessToken: "secret access token".to_string(),
+            clientToken: Some("client token".to_string()),
+            availableProfiles: None,
+            selectedProfile: NameUUID {
+                id: "069a79f444e94726a5befca90e38aaf5".to_string(),
+                name: "Notch".to_string(),
+                legacy: false,
+                demo: false,
+            },
+        }
+    }
+
+    #[test]
+    fn credential_store() {
+        let path = env::temp_dir().join(format!("ozelot-credentials-{}.json",
+                                                ::std::process::id()));
+
+        let mut store = CredentialStore::open(&path).unwrap();
+        assert!(store.accounts().is_empty());
+        assert!(store.insert("notch", auth()).is_none());
+        store.save().unwrap();
+        let store = CredentialStore::open(&path).unwrap();
+        assert_eq!(store.get("notch").unwrap().accessToken,
+                   "secret access token");
+
+        /* Opening a plain store with a passphrase encrypts it on save */
+        let store = CredentialStore::open_encrypted(&path, "hunter2").unwrap();
+        store.save().unwrap();
+        let mut contents = String::new();
+        let _: usize = File::open(&path).unwrap()
+            .read_to_string(&mut contents)
+            .unwrap();
+        assert!(!contents.contains("secret access token"));
+
+        assert!(CredentialStore::open(&path).is_err());
+        assert!(CredentialStore::open_encrypted(&path, "hunter3").is_err());
+        let store = CredentialStore::open_encrypted(&path, "hunter2").unwrap();
+        assert_eq!(store.get("notch").unwrap().accessToken,
+                   "secret access token");
+
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn token_store() {
+        let path = env::temp_dir().join(format!("ozelot-tokens-{}.json",
+                                                ::std::process::id()));
+
+        /* Inserted tokens count as validated, so no requests are made */
+        let mut store = TokenStore::from_store(CredentialStore::open(&path).unwrap());
+        assert!(store.get("notch").unwrap().is_none());
+        assert!(store.insert("notch", auth()).unwrap().is_none());
+        assert_eq!(store.get("notch").unwrap().unwrap().accessToken,
+                   "secret access token");
+        assert_eq!(store.accounts(), vec!["notch"]);
+
+        /* Changes are saved right away */
+        let store = CredentialStore::open(&path).unwrap();
+        assert!(store.get("notch").is_some());
+        let mut store = TokenStore::from_store(store);
+        assert!(store.remove("notch").unwrap().is_some());
+        assert!(CredentialStore::open(&path).unwrap().get("notch").is_none());
+
+        fs::remove_file(&path).unwrap();
+    }
+
+    /* Answers validate requests with the queued responses, None being a
+     * network failure, and counts refresh requests */
+    struct AuthStub {
+        validate: Mutex<VecDeque<Option<(u32, &'static str)>>>,
+        refreshes: Mutex<usize>,
+    }
+    impl http::Backend for AuthStub {
+        fn send(&self, request: &http::Request) -> Result<http::Response> {
+            if request.url.ends_with("/refresh") {
+                *self.refreshes.lock().unwrap() += 1;
+                let mut auth = auth();
+                auth.accessToken = "new access token".to_string();
+                return Ok(http::Response {
+                              status: 200,
+                              headers: Vec::new(),
+                              body: serde_json::to_vec(&auth)?,
+                          });
+            }
+            assert!(request.url.ends_with("/validate"), "Unexpected {}", request.url);
+            match self.validate.lock().unwrap().pop_front().expect("Unexpected validate") {
+                Some((status, body)) => {
+                    Ok(http::Response {
+                           status,
+                           /* Don't hold up other tests with a cooldown */
+                           headers: vec![("Retry-After".to_string(), "0".to_string())],
+                           body: body.as_bytes().to_vec(),
+                       })
+                },
+                None => Err(io::Error::new(io::ErrorKind::ConnectionRefused, "stub").into()),
+            }
+        }
+    }
+
+    #[test]
+    fn refresh_only_invalid() {
+        let _lock = http::BACKEND_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+        let invalid_token = r#"{"error":"ForbiddenOperationException","errorMessage":"Invalid token."}"#;
+        let responses = vec![None, Some((429, "")), Some((403, invalid_token)), Some((204, ""))];
+        let stub = Arc::new(AuthStub {
+                                validate: Mutex::new(responses.into_iter().collect()),
+                                refreshes: Mutex::new(0),
+                            });
+        let backend: Arc<dyn http::Backend> = stub.clone();
+        http::set_backend(Some(backend));
+        let mut tokens = auth();
+        let unreachable = ensure_valid(&mut tokens);
+        let rate_limited = ensure_valid(&mut tokens);
+        let invalid = ensure_valid(&mut tokens);
+        let valid = ensure_valid(&mut tokens);
+        http::set_backend(None);
+
+        assert!(unreachable.is_err());
+        match rate_limited {
+            Err(Error(ErrorKind::MojangApi(429, ..), _)) => (),
+            x => panic!("{:?}", x),
+        }
+        assert!(invalid.unwrap());
+        assert!(!valid.unwrap());
+        assert_eq!(*stub.refreshes.lock().unwrap(), 1);
+        assert_eq!(tokens.accessToken, "new access token");
+    }
+}
@@ -0,0 +1,362 @@
+//! Culling of outgoing packets the player can't see or hear
+//!
+//! On busy servers, most entity movement, particle and sound packets are
+//! about things far away from a given player, or behind them. Give a
+//! Server CullingOptions with server.set_culling(), and server.send() drops
+//! those outside the radius around the player, and, for entities and
+//! particles, outside the player's field of view:
+//!
+//! - Particles and sounds are dropped, they're gone by the time the player
+//!   would see or hear them anyway
+//! - Entity movement is deferred: the Culler keeps track of where each
+//!   entity is, and once a culled entity is visible again, its next movement
+//!   is sent as an EntityTeleport to where it is by then
+//!
+//! Spawning and destroying entities is never culled. The position of the
+//! player is taken from its movement packets and from server.teleport().
+//! Until it's known, nothing is culled.
+
+use clientbound::{self, ClientboundPacket};
+use entity::Location;
+use serverbound::ServerboundPacket;
+
+use std::collections::HashMap;
+
+/// What a Server culls, see the module documentation
+#[derive(Debug, Clone, PartialEq)]
+pub struct CullingOptions {
+    /// The distance in blocks beyond which packets are culled
+    pub radius: f64,
+    /// The distance in blocks within which nothing is culled, since the
+    /// player may turn around at any moment
+    pub near: f64,
+    /// The horizontal and vertical field of view in degrees, outside of which
+    /// entity movement and particles are culled, or None to only cull by
+    /// distance. Should be wider than the client's, e.g. 120 for the default
+    /// of 70.
+    pub view_angle: Option<f64>,
+    pub cull_entities: bool,
+    pub cull_particles: bool,
+    pub cull_sounds: bool,
+}
+impl Default for CullingOptions {
+    fn default() -> Self {
+        CullingOptions {
+            radius: 64.0,
+            near: 12.0,
+            view_angle: Some(120.0),
+            cull_entities: true,
+            cull_particles: true,
+            cull_sounds: true,
+        }
+    }
+}
+
+/// What to do with an outgoing packet
+#[derive(Debug, Clone, PartialEq)]
+pub enum Cull {
+    Send,
+    Drop,
+    /// Send the given packet instead
+    Replace(ClientboundPacket),
+}
+
+/* The last known location of an entity, in protocol units for the angles */
+#[derive(Debug, Clone, Copy)]
+struct Tracked {
+    x: f64,
+    y: f64,
+    z: f64,
+    yaw: i8,
+    pitch: i8,
+    on_ground: bool,
+    /* Whether movement was culled since the entity was last sent */
+    stale: bool,
+}
+
+/// Decides which packets to cull for a single player
+#[derive(Debug, Clone)]
+pub struct Culler {
+    options: CullingOptions,
+    player: Option<Location>,
+    entities: HashMap<i32, Tracked>,
+    culled: u64,
+}
+impl Culler {
+    pub fn new(options: CullingOptions) -> Self {
+        Culler {
+            options,
+            player: None,
+            entities: HashMap::new(),
+            culled: 0,
+        }
+    }
+
+    pub fn options(&self) -> &CullingOptions {
+        &self.options
+    }
+
+    /// The location of the player, if known
+    pub fn player_location(&self) -> Option<&Location> {
+        self.player.as_ref()
+    }
+
+    /// Set the location of the player, e.g. when teleporting it
+    pub fn set_player_location(&mut self, location: Location) {
+        self.player = Some(location);
+    }
+
+    /// The amount of packets culled so far
+    pub fn culled(&self) -> u64 {
+        self.culled
+    }
+
+    /// Update the location of the player from its movement packets
+    pub fn observe(&mut self, packet: &ServerboundPacket) {
+        let mut location = self.player.unwrap_or_default();
+        match *packet {
+            ServerboundPacket::PlayerPosition(ref p) => {
+                location.x = *p.get_x();
+                location.y = *p.get_y();
+                location.z = *p.get_z();
+            },
+            ServerboundPacket::PlayerPositionAndLook(ref p) => {
+                location.x = *p.get_x();
+                location.y = *p.get_y();
+                location.z = *p.get_z();
+                location.yaw = *p.get_yaw();
+                location.pitch = *p.get_pitch();
+            },
+            /* Looking around before the position is known says nothing */
+            ServerboundPacket::PlayerLook(ref p) if self.player.is_some() => {
+                location.yaw = *p.get_yaw();
+                location.pitch = *p.get_pitch();
+            },
+            _ => return,
+        }
+        self.player = Some(location);
+    }
+
+    /// Decide what to do with an outgoing packet
+    pub fn filter(&mut self, packet: &ClientboundPacket) -> Cull {
+        let ret = self.decide(packet);
+        if ret == Cull::Drop {
+            self.culled += 1;
+        }
+        ret
+    }
+
+    fn decide(&mut self, packet: &ClientboundPacket) -> Cull {
+        match *packet {
+            ClientboundPacket::SpawnObject(ref p) => {
+                self.spawn(*p.get_entity_id(), *p.get_x(), *p.get_y(), *p.get_z());
+            },
+            ClientboundPacket::SpawnExperienceOrb(ref p) => {
+                self.spawn(*p.get_entity_id(), *p.get_x(), *p.get_y(), *p.get_z());
+            },
+            ClientboundPacket::SpawnMob(ref p) => {
+                self.spawn(*p.get_entity_id(), *p.get_x(), *p.get_y(), *p.get_z());
+            },
+            ClientboundPacket::SpawnPlayer(ref p) => {
+                self.spawn(*p.get_entity_id(), *p.get_x(), *p.get_y(), *p.get_z());
+            },
+            ClientboundPacket::DestroyEntities(ref p) => {
+                for id in p.get_entity_ids() {
+                    let _: Option<Tracked> = self.entities.remove(id);
+                }
+            },
+            /* The client forgets all entities when respawning */
+            ClientboundPacket::Respawn(..) => self.entities.clear(),
+            ClientboundPacket::EntityTeleport(ref p) => {
+                return self.moved(*p.get_entity_id(), |x| {
+                    x.x = *p.get_x();
+                    x.y = *p.get_y();
+                    x.z = *p.get_z();
+                    x.yaw = *p.get_yaw();
+                    x.pitch = *p.get_pitch();
+                    x.on_ground = *p.get_on_ground();
+                });
+            },
+            ClientboundPacket::EntityRelativeMove(ref p) => {
+                return self.moved(*p.get_entity_id(), |x| {
+                    x.x += *p.get_x() as f64 / 4096.0;
+                    x.y += *p.get_y() as f64 / 4096.0;
+                    x.z += *p.get_z() as f64 / 4096.0;
+                    x.on_ground = *p.get_on_ground();
+                });
+            },
+            ClientboundPacket::EntityLookRelativeMove(ref p) => {
+                return self.moved(*p.get_entity_id(), |x| {
+                    x.x += *p.get_x() as f64 / 4096.0;
+                    x.y += *p.get_y() as f64 / 4096.0;
+                    x.z += *p.get_z() as f64 / 4096.0;
+                    x.yaw = *p.get_yaw();
+                    x.pitch = *p.get_pitch();
+                    x.on_ground = *p.get_on_ground();
+                });
+            },
+            ClientboundPacket::EntityLook(ref p) => {
+                return self.moved(*p.get_entity_id(), |x| {
+                    x.yaw = *p.get_yaw();
+                    x.pitch = *p.get_pitch();
+                    x.on_ground = *p.get_on_ground();
+                });
+            },
+            ClientboundPacket::EntityHeadLook(ref p) => {
+                return self.entity_detail(*p.get_entity_id());
+            },
+            ClientboundPacket::EntityVelocity(ref p) => {
+                return self.entity_detail(*p.get_entity_id());
+            },
+            ClientboundPacket::ClientboundAnimation(ref p) => {
+                return self.entity_detail(*p.get_entity_id());
+            },
+            ClientboundPacket::Particle(ref p) if self.options.cull_particles &&
+                                                  !*p.get_use_long_distance() => {
+                let (x, y, z) = (*p.get_x() as f64, *p.get_y() as f64, *p.get_z() as f64);
+                if !self.is_visible(x, y, z, true) {
+                    return Cull::Drop;
+                }
+            },
+            ClientboundPacket::SoundEffect(ref p) if self.options.cull_sounds => {
+                return self.sound(*p.get_x(), *p.get_y(), *p.get_z());
+            },
+            ClientboundPacket::NamedSoundEffect(ref p) if self.options.cull_sounds => {
+                return self.sound(*p.get_x(), *p.get_y(), *p.get_z());
+            },
+            _ => (),
+        }
+        Cull::Send
+    }
+
+    fn spawn(&mut self, entity_id: i32, x: f64, y: f64, z: f64) {
+        let _: Option<Tracked> = self.entities.insert(entity_id,
+                                                      Tracked {
+                                                          x,
+                                                          y,
+                                                          z,
+                                                          yaw: 0,
+                                                          pitch: 0,
+                                                          on_ground: false,
+                                                          stale: false,
+                                                      });
+    }
+
+    /* Update the tracked location of an entity, and decide whether to send
+     * the movement, or a teleport if earlier movement was culled */
+    fn moved<F: FnOnce(&mut Tracked)>(&mut self, entity_id: i32, f: F) -> Cull {
+        let mut tracked = match self.entities.get(&entity_id) {
+            Some(x) => *x,
+            None => return Cull::Send,
+        };
+        f(&mut tracked);
+        let visible = !self.options.cull_entities ||
+                      self.is_visible(tracked.x, tracked.y, tracked.z, true);
+        let ret = if !visible {
+            tracked.stale = true;
+            Cull::Drop
+        } else if tracked.stale {
+            tracked.stale = false;
+            Cull::Replace(clientbound::EntityTeleport::new(entity_id,
+                                                           tracked.x,
+                                                           tracked.y,
+                                                           tracked.z,
+                                                           tracked.yaw,
+                                                           tracked.pitch,
+                                                           tracked.on_ground))
+        } else {
+            Cull::Send
+        };
+        let _: Option<Tracked> = self.entities.insert(entity_id, tracked);
+        ret
+    }
+
+    /* Packets about an entity that only matter while it's visible */
+    fn entity_detail(&self, entity_id: i32) -> Cull {
+        match self.entities.get(&entity_id) {
+            Some(x) if self.options.cull_entities && !self.is_visible(x.x, x.y, x.z, true) => {
+                Cull::Drop
+            },
+            _ => Cull::Send,
+        }
+    }
+
+    /* Sounds, with their position in eighths of a block */
+    fn sound(&self, x: i32, y: i32, z: i32) -> Cull {
+        if self.is_visible(x as f64 / 8.0, y as f64 / 8.0, z as f64 / 8.0, false) {
+            Cull::Send
+        } else {
+            Cull::Drop
+        }
+    }
+
+    /* Whether the position is within the radius, and if in_view, within the
+     * field of view */
+    fn is_visible(&self, x: f64, y: f64, z: f64, in_view: bool) -> bool {
+        let player = match self.player {
+            Some(ref x) => x,
+            None => return true,
+        };
+        /* From the eyes of the player */
+        let (dx, dy, dz) = (x - player.x, y - player.y - 1.62, z - player.z);
+        let distance = (dx * dx + dy * dy + dz * dz).sqrt();
+        if distance <= self.options.near {
+            return true;
+        }
+        if distance > self.options.radius {
+            return false;
+        }
+        match self.options.view_angle {
+            Some(angle) if in_view => {
+                let yaw = (player.yaw as f64).to_radians();
+                let pitch = (player.pitch as f64).to_radians();
+                let look = (-yaw.sin() * pitch.cos(), -pitch.sin(), yaw.cos() * pitch.cos());
+                let cos = (look.0 * dx + look.1 * dy + look.2 * dz) / distance;
+                cos >= (angle / 2.0).to_radians().cos()
+            },
+            _ => true,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use clientbound::{EntityRelativeMove, EntityTeleport, Particle, SoundEffect, SpawnPlayer};
+    use serverbound::PlayerPositionAndLook;
+
+    #[test]
+    fn culling() {
+        let mut culler = Culler::new(CullingOptions {
+                                         near: 0.0,
+                                         ..CullingOptions::default()
+                                     });
+        let particle = |x: f32, z: f32| {
+            Particle::new_raw(0, false, x, 64.0, z, 0.0, 0.0, 0.0, 0.0, 1, Vec::new())
+        };
+        /* Nothing is culled before the player's location is known */
+        assert_eq!(culler.filter(&particle(1000.0, 0.0)), Cull::Send);
+
+        /* Looking south, towards +z */
+        culler.observe(&PlayerPositionAndLook::new(0.0, 62.38, 0.0, 0.0, 0.0, true));
+        assert_eq!(culler.filter(&particle(0.0, 30.0)), Cull::Send);
+        assert_eq!(culler.filter(&particle(0.0, -30.0)), Cull::Drop);
+        assert_eq!(culler.filter(&particle(0.0, 100.0)), Cull::Drop);
+        assert_eq!(culler.filter(&SoundEffect::new(0, 0, 0, 512, -240, 1.0, 1.0)), Cull::Send);
+        assert_eq!(culler.filter(&SoundEffect::new(0, 0, 0, 512, -800, 1.0, 1.0)), Cull::Drop);
+
+        /* An entity walking behind the player and back */
+        let spawn = SpawnPlayer::new(5, 0, 0.0, 64.0, 20.0, 0, 0, Vec::new());
+        assert_eq!(culler.filter(&spawn), Cull::Send);
+        let step = |z: i16| EntityRelativeMove::new(5, 0, 0, z * 4096, true);
+        assert_eq!(culler.filter(&step(-7)), Cull::Send);
+        assert_eq!(culler.filter(&step(-7)), Cull::Send);
+        assert_eq!(culler.filter(&step(-7)), Cull::Drop);
+        assert_eq!(culler.filter(&step(-7)), Cull::Drop);
+        assert_eq!(culler.filter(&step(7)), Cull::Drop);
+        assert_eq!(culler.filter(&step(7)),
+                   Cull::Replace(EntityTeleport::new(5, 0.0, 64.0, 6.0, 0, 0, true)));
+        assert_eq!(culler.filter(&step(1)), Cull::Send);
+        assert_eq!(culler.culled(), 6);
+    }
+}
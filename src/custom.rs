@@ -0,0 +1,244 @@
+//! User-defined packets
+//!
+//! Modded and custom servers sometimes use packets that aren't part of the
+//! vanilla protocol. These can be defined with the define_packet! macro,
+//! which generates the struct and both directions of the codec from the list
+//! of fields. Each field type must implement Field, which is implemented
+//! for the protocol's datatypes.
+//!
+//! Custom packets are sent with client.send_custom() or server.send_custom().
+//! To receive them, register their ID with register_custom(), after which
+//! incoming packets with that ID are not decoded as vanilla packets, but
+//! kept for read_custom() instead.
+//!
+//! # Examples
+//!
+//! ```rust,no_run
+//! #[macro_use]
+//! extern crate ozelot;
+//!
+//! use ozelot::Client;
+//! use ozelot::custom::VarInt;
+//!
+//! define_packet! {
+//!     /// Sent by the server when the player enters a region
+//!     #[derive(Debug, Clone, PartialEq)]
+//!     pub struct RegionEnter: Play = 0x60 {
+//!         pub entity_id: VarInt,
+//!         pub region: String,
+//!     }
+//! }
+//!
+//! # fn main() {
+//! let mut client = Client::connect_unauthenticated("localhost", 25565, "Bob").unwrap();
+//! client.register_custom::<RegionEnter>();
+//! loop {
+//!     let _ = client.read().unwrap();
+//!     while let Some(raw) = client.read_custom() {
+//!         if let Some(packet) = raw.decode::<RegionEnter>().unwrap() {
+//!             println!("Entered {}", packet.region);
+//!         }
+//!     }
+//! }
+//! # }
+//! ```
+
+use ClientState;
+use errors::Result;
+use read::*;
+use write::*;
+
+use std::io::{Cursor, Read};
+
+/// A datatype that can be a field of a custom packet
+pub trait Field: Sized {
+    fn read_field<R: Read>(r: &mut R) -> Result<Self>;
+    fn write_field(&self, w: &mut Vec<u8>) -> Result<()>;
+}
+
+macro_rules! impl_field {
+    ($($ty:ty => $read:ident, $write:ident;)*) => {
+        $(
+            impl Field for $ty {
+                fn read_field<R: Read>(r: &mut R) -> Result<Self> {
+                    $read(r)
+                }
+                fn write_field(&self, w: &mut Vec<u8>) -> Result<()> {
+                    $write(self, w)
+                }
+            }
+        )*
+    }
+}
+
+impl_field! {
+    bool => read_bool, write_bool;
+    u8 => read_u8, write_u8;
+    i8 => read_i8, write_i8;
+    u16 => read_u16, write_u16;
+    i16 => read_i16, write_i16;
+    i32 => read_i32, write_i32;
+    i64 => read_i64, write_i64;
+    u64 => read_u64, write_u64;
+    u128 => read_u128, write_u128;
+    f32 => read_f32, write_f32;
+    f64 => read_f64, write_f64;
+    String => read_String, write_String;
+    Vec<u8> => read_prefixed_bytearray, write_prefixed_bytearray;
+}
+
+/// An i32 sent as a varint
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub struct VarInt(pub i32);
+impl Field for VarInt {
+    fn read_field<R: Read>(r: &mut R) -> Result<Self> {
+        Ok(VarInt(read_varint(r)?))
+    }
+    fn write_field(&self, w: &mut Vec<u8>) -> Result<()> {
+        write_varint(&self.0, w)
+    }
+}
+
+/// An i64 sent as a varlong
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub struct VarLong(pub i64);
+impl Field for VarLong {
+    fn read_field<R: Read>(r: &mut R) -> Result<Self> {
+        Ok(VarLong(read_varlong(r)?))
+    }
+    fn write_field(&self, w: &mut Vec<u8>) -> Result<()> {
+        write_varlong(&self.0, w)
+    }
+}
+
+/// The rest of the packet as raw bytes, must be the last field
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Default)]
+pub struct Rest(pub Vec<u8>);
+impl Field for Rest {
+    fn read_field<R: Read>(r: &mut R) -> Result<Self> {
+        Ok(Rest(read_bytearray_to_end(r)?))
+    }
+    fn write_field(&self, w: &mut Vec<u8>) -> Result<()> {
+        write_bytearray_to_end(&self.0, w)
+    }
+}
+
+/// A user-defined packet, usually implemented with define_packet!
+pub trait CustomPacket: Sized {
+    /// The packet ID
+    const ID: i32;
+    /// The connection state in which the packet is sent
+    const STATE: ClientState;
+    /// The packet name, e.g. for packet statistics
+    const NAME: &'static str;
+
+    /// Serialize the fields, without the packet ID
+    fn write_fields(&self, w: &mut Vec<u8>) -> Result<()>;
+    /// Deserialize the fields, without the packet ID
+    fn read_fields<R: Read>(r: &mut R) -> Result<Self>;
+
+    /// Serialize the packet, including the packet ID
+    fn to_u8(&self) -> Result<Vec<u8>> {
+        let mut ret = Vec::new();
+        write_varint(&Self::ID, &mut ret)?;
+        self.write_fields(&mut ret)?;
+        Ok(ret)
+    }
+}
+
+/// A received packet with a registered custom ID, not yet decoded
+#[derive(Debug, Clone, PartialEq)]
+pub struct RawPacket {
+    /// The connection state it was received in
+    pub state: ClientState,
+    pub id: i32,
+    /// The packet, including the packet ID
+    pub data: Vec<u8>,
+}
+impl RawPacket {
+    /// Decode the packet as P, or None if it has another ID or state
+    pub fn decode<P: CustomPacket>(&self) -> Result<Option<P>> {
+        if self.id != P::ID || self.state != P::STATE {
+            return Ok(None);
+        }
+        let mut r = Cursor::new(&self.data);
+        let _: i32 = read_varint(&mut r)?;
+        Ok(Some(P::read_fields(&mut r)?))
+    }
+}
+
+/// Define a custom packet, see the custom module
+///
+/// The struct is followed by the connection state (Handshake, Status, Login
+/// or Play) and the packet ID. Every field type must implement
+/// ozelot::custom::Field.
+#[macro_export]
+macro_rules! define_packet {
+    (
+        $(#[$meta:meta])*
+        $vis:vis struct $name:ident : $state:ident = $id:tt {
+            $($(#[$fmeta:meta])* $fvis:vis $field:ident : $ty:ty),* $(,)*
+        }
+    ) => {
+        $(#[$meta])*
+        $vis struct $name {
+            $($(#[$fmeta])* $fvis $field: $ty,)*
+        }
+        impl $crate::custom::CustomPacket for $name {
+            const ID: i32 = $id;
+            const STATE: $crate::ClientState = $crate::ClientState::$state;
+            const NAME: &'static str = stringify!($name);
+
+            #[allow(unused_variables)]
+            fn write_fields(&self, w: &mut Vec<u8>) -> $crate::errors::Result<()> {
+                $($crate::custom::Field::write_field(&self.$field, w)?;)*
+                Ok(())
+            }
+
+            #[allow(unused_variables)]
+            fn read_fields<R: ::std::io::Read>(r: &mut R) -> $crate::errors::Result<Self> {
+                Ok($name {
+                    $($field: $crate::custom::Field::read_field(r)?,)*
+                })
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    define_packet! {
+        #[derive(Debug, Clone, PartialEq)]
+        struct Region: Play = 0x60 {
+            entity_id: VarInt,
+            name: String,
+            flags: u8,
+            extra: Rest,
+        }
+    }
+
+    #[test]
+    fn roundtrip() {
+        let packet = Region {
+            entity_id: VarInt(300),
+            name: "spawn".to_string(),
+            flags: 3,
+            extra: Rest(vec![1, 2, 3]),
+        };
+        let data = packet.to_u8().unwrap();
+        assert_eq!(&data[..5], &[0x60, 0xac, 0x02, 5, b's']);
+        let raw = RawPacket {
+            state: ClientState::Play,
+            id: 0x60,
+            data,
+        };
+        assert_eq!(raw.decode::<Region>().unwrap(), Some(packet));
+        let raw = RawPacket {
+            state: ClientState::Login,
+            ..raw
+        };
+        assert_eq!(raw.decode::<Region>().unwrap(), None);
+    }
+}
@@ -0,0 +1,101 @@
+//! Deferred decoding of large packets
+//!
+//! When joining a server, dozens of ChunkData packets arrive at once, each
+//! of which is several times larger decompressed than as sent. Decoding all
+//! of them as they are read means holding all of them decompressed at the
+//! same time, even if they're then handled one by one.
+//!
+//! With client.set_deferred_decode(true), compressed ChunkData and
+//! DeclareRecipes packets are instead kept as received, and returned by
+//! client.read_deferred() rather than client.read(). Each is only
+//! decompressed when decode() is called on it, so only the packet being
+//! handled is held decompressed.
+//!
+//! Note that the deferred packets are taken out of the stream of packets
+//! returned by client.read(), so a packet sent after a deferred one may be
+//! returned first. Handle the deferred packets after each call to
+//! client.read(), and keep in mind that e.g. an UnloadChunk returned by
+//! client.read() may be for a chunk whose ChunkData is still queued.
+//!
+//! # Examples
+//!
+//! ```rust,no_run
+//! use ozelot::Client;
+//! use ozelot::clientbound::ClientboundPacket;
+//!
+//! let mut client = Client::connect_unauthenticated("localhost", 25565, "Bob").unwrap();
+//! client.set_deferred_decode(true);
+//! loop {
+//!     let _ = client.read().unwrap();
+//!     while let Some(deferred) = client.read_deferred() {
+//!         if let ClientboundPacket::ChunkData(ref p) = deferred.decode().unwrap() {
+//!             println!("Got {} bytes of chunk data", p.get_data().len());
+//!         }
+//!     }
+//! }
+//! ```
+
+use ClientState;
+use clientbound::ClientboundPacket;
+use connection::deserialize;
+use errors::Result;
+use protocol_data::IdMap;
+
+use std::io::Read;
+use std::sync::Arc;
+
+use flate2::read::ZlibDecoder;
+
+/// The clientbound packets whose decoding can be deferred, all in the Play
+/// state
+pub const DEFERRED_PACKETS: &[&str] = &["ChunkData", "DeclareRecipes"];
+
+/// A received packet still compressed, see the module documentation
+#[derive(Debug, Clone)]
+pub struct DeferredPacket {
+    state: ClientState,
+    name: &'static str,
+    /* The zlib compressed packet, including the packet ID */
+    data: Vec<u8>,
+    uncompressed_len: usize,
+    /* The packet IDs to translate from, if the server uses another
+     * protocol version */
+    ids: Option<Arc<IdMap>>,
+}
+impl DeferredPacket {
+    pub(crate) fn new(state: ClientState,
+                      name: &'static str,
+                      data: Vec<u8>,
+                      uncompressed_len: usize,
+                      ids: Option<Arc<IdMap>>)
+                      -> Self {
+        DeferredPacket {
+            state,
+            name,
+            data,
+            uncompressed_len,
+            ids,
+        }
+    }
+
+    /// Get the name of the packet, e.g. ChunkData
+    pub fn name(&self) -> &str {
+        self.name
+    }
+
+    /// Get the length of the packet as received
+    pub fn compressed_len(&self) -> usize {
+        self.data.len()
+    }
+
+    /// Get the length of the packet once decompressed
+    pub fn uncompressed_len(&self) -> usize {
+        self.uncompressed_len
+    }
+
+    /// Decompress and decode the packet
+    pub fn decode(&self) -> Result<ClientboundPacket> {
+        let mut r = ZlibDecoder::new(&self.data[..]).take(self.uncompressed_len as u64);
+        deserialize(&mut r, &self.state, self.ids.as_deref())
+    }
+}
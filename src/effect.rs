@@ -0,0 +1,207 @@
+//! Status effects of the player, e.g. from potions
+//!
+//! The server sends an EntityEffect packet when an effect is applied and a
+//! RemoveEntityEffect packet when it's removed early, but not when it runs
+//! out, so Effects works out from the duration when each one ends. The
+//! Client keeps the effects of the player when auto_handle is true, see
+//! client.effects().
+
+use clientbound::ClientboundPacket;
+
+use std::collections::BTreeMap;
+use std::time::{Duration, Instant};
+
+/// Durations at least this long are shown as "**:**", i.e. are practically
+/// infinite
+pub const INFINITE_TICKS: i32 = 32767;
+
+/// The name of an effect ID, without the minecraft: prefix
+pub fn effect_name(id: u8) -> Option<&'static str> {
+    Some(match id {
+             1 => "speed",
+             2 => "slowness",
+             3 => "haste",
+             4 => "mining_fatigue",
+             5 => "strength",
+             6 => "instant_health",
+             7 => "instant_damage",
+             8 => "jump_boost",
+             9 => "nausea",
+             10 => "regeneration",
+             11 => "resistance",
+             12 => "fire_resistance",
+             13 => "water_breathing",
+             14 => "invisibility",
+             15 => "blindness",
+             16 => "night_vision",
+             17 => "hunger",
+             18 => "weakness",
+             19 => "poison",
+             20 => "wither",
+             21 => "health_boost",
+             22 => "absorption",
+             23 => "saturation",
+             24 => "glowing",
+             25 => "levitation",
+             26 => "luck",
+             27 => "unluck",
+             28 => "slow_falling",
+             29 => "conduit_power",
+             30 => "dolphins_grace",
+             _ => return None,
+         })
+}
+
+/// An active effect
+#[derive(Debug, Clone, PartialEq)]
+pub struct Effect {
+    pub id: u8,
+    /// The level minus one, e.g. 1 for Speed II
+    pub amplifier: i8,
+    /// The duration in ticks, counted from received
+    pub duration: i32,
+    /// 0x01 ambient (from a beacon), 0x02 show particles, 0x04 show icon
+    pub flags: u8,
+    /// When the effect was applied
+    pub received: Instant,
+}
+impl Effect {
+    /// The name of the effect, see effect_name()
+    pub fn name(&self) -> Option<&'static str> {
+        effect_name(self.id)
+    }
+
+    /// The level, e.g. 2 for Speed II
+    pub fn level(&self) -> i32 {
+        self.amplifier as i32 + 1
+    }
+
+    pub fn is_infinite(&self) -> bool {
+        self.duration >= INFINITE_TICKS
+    }
+
+    /// Whether the effect comes from a beacon or conduit
+    pub fn is_ambient(&self) -> bool {
+        self.flags & 0x01 != 0
+    }
+
+    /// How much longer the effect lasts at the given moment, assuming 20
+    /// ticks per second, or None if it's infinite
+    pub fn remaining_at(&self, now: Instant) -> Option<Duration> {
+        if self.is_infinite() {
+            return None;
+        }
+        let total = Duration::from_millis(self.duration.max(0) as u64 * 50);
+        Some(total.saturating_sub(now.saturating_duration_since(self.received)))
+    }
+
+    /// How much longer the effect lasts, or None if it's infinite
+    pub fn remaining(&self) -> Option<Duration> {
+        self.remaining_at(Instant::now())
+    }
+
+    fn is_active_at(&self, now: Instant) -> bool {
+        self.remaining_at(now) != Some(Duration::from_secs(0))
+    }
+}
+
+/// An effect being added or removed, see ClientEvent::Effect
+#[derive(Debug, Clone, PartialEq)]
+pub enum EffectChange {
+    /// An effect was applied, or changed if the entity already had it
+    Added(Effect),
+    /// The effect with the given ID was removed before it ran out
+    Removed(u8),
+}
+
+/// The effects of an entity, by effect ID
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct Effects {
+    effects: BTreeMap<u8, Effect>,
+}
+impl Effects {
+    /// Get an active effect
+    pub fn get(&self, id: u8) -> Option<&Effect> {
+        let now = Instant::now();
+        self.effects.get(&id).filter(|x| x.is_active_at(now))
+    }
+
+    /// Whether the effect is active
+    pub fn has(&self, id: u8) -> bool {
+        self.get(id).is_some()
+    }
+
+    /// Get all active effects
+    pub fn active(&self) -> Vec<&Effect> {
+        let now = Instant::now();
+        self.effects
+            .values()
+            .filter(|x| x.is_active_at(now))
+            .collect()
+    }
+
+    /// Remove all effects, e.g. after dying
+    pub fn clear(&mut self) {
+        self.effects.clear();
+    }
+
+    /* Apply an EntityEffect or RemoveEntityEffect packet if it's about the
+     * given entity */
+    pub(crate) fn handle(&mut self,
+                         packet: &ClientboundPacket,
+                         entity_id: i32)
+                         -> Option<EffectChange> {
+        match *packet {
+            ClientboundPacket::EntityEffect(ref p) if *p.get_entity_id() == entity_id => {
+                let effect = Effect {
+                    id: *p.get_effect_id(),
+                    amplifier: *p.get_amplifier(),
+                    duration: *p.get_duration(),
+                    flags: *p.get_flags(),
+                    received: Instant::now(),
+                };
+                let _ = self.effects.insert(effect.id, effect.clone());
+                Some(EffectChange::Added(effect))
+            },
+            ClientboundPacket::RemoveEntityEffect(ref p) if *p.get_entity_id() == entity_id => {
+                let _ = self.effects.remove(p.get_effect_id());
+                Some(EffectChange::Removed(*p.get_effect_id()))
+            },
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use clientbound::{EntityEffect, RemoveEntityEffect};
+
+    #[test]
+    fn effects() {
+        let mut effects = Effects::default();
+        assert!(effects.handle(&EntityEffect::new(2, 1, 0, 600, 0x06), 1).is_none());
+        assert!(!effects.has(1));
+
+        let speed = effects.handle(&EntityEffect::new(1, 1, 1, 600, 0x06), 1);
+        let speed = match speed {
+            Some(EffectChange::Added(x)) => x,
+            x => panic!("Unexpected change {:?}", x),
+        };
+        assert_eq!((speed.name(), speed.level()), (Some("speed"), 2));
+        let remaining = speed.remaining_at(speed.received + Duration::from_secs(10));
+        assert_eq!(remaining, Some(Duration::from_secs(20)));
+        assert_eq!(speed.remaining_at(speed.received + Duration::from_secs(60)),
+                   Some(Duration::from_secs(0)));
+
+        let _ = effects.handle(&EntityEffect::new(1, 13, 0, INFINITE_TICKS, 0x01), 1);
+        let _ = effects.handle(&EntityEffect::new(1, 16, 0, 0, 0x06), 1);
+        assert_eq!(effects.active().iter().map(|x| x.id).collect::<Vec<_>>(), vec![1, 13]);
+        assert_eq!(effects.get(13).unwrap().remaining(), None);
+
+        assert_eq!(effects.handle(&RemoveEntityEffect::new(1, 1), 1), Some(EffectChange::Removed(1)));
+        assert!(!effects.has(1));
+        effects.clear();
+        assert!(effects.active().is_empty());
+    }
+}
@@ -0,0 +1,436 @@
+//! Entity bookkeeping for servers
+//!
+//! Every server needs to hand out entity IDs, keep track of which players can
+//! see which entities, and send the right spawn/destroy/movement packets when
+//! things move around. The EntityRegistry does exactly that, leaving the
+//! actual sending of the packets to the consumer.
+
+use clientbound::{ClientboundPacket, DestroyEntities, EntityHeadLook,
+                  EntityLook, EntityLookRelativeMove, EntityRelativeMove,
+                  EntityTeleport};
+
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::fmt;
+use std::hash::Hash;
+
+/// The location and rotation of an entity
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct Location {
+    pub x: f64,
+    pub y: f64,
+    pub z: f64,
+    /// The yaw in degrees
+    pub yaw: f32,
+    /// The pitch in degrees
+    pub pitch: f32,
+    pub on_ground: bool,
+}
+impl Location {
+    pub fn new(x: f64, y: f64, z: f64) -> Self {
+        Location {
+            x,
+            y,
+            z,
+            yaw: 0.0,
+            pitch: 0.0,
+            on_ground: true,
+        }
+    }
+
+    /// Get the (horizontal) distance to the given coordinates, ignoring Y
+    pub fn horizontal_distance(&self, x: f64, z: f64) -> f64 {
+        ((self.x - x).powi(2) + (self.z - z).powi(2)).sqrt()
+    }
+
+    /// Apply the values of a clientbound PlayerPositionAndLook packet to this
+    /// location, honoring the relative flags (see the RELATIVE_* constants.)
+    pub fn teleport(&self,
+                    x: f64,
+                    y: f64,
+                    z: f64,
+                    yaw: f32,
+                    pitch: f32,
+                    flags: u8)
+                    -> Location {
+        let relative = |flag: u8| flags & flag != 0;
+        Location {
+            x: if relative(RELATIVE_X) { self.x + x } else { x },
+            y: if relative(RELATIVE_Y) { self.y + y } else { y },
+            z: if relative(RELATIVE_Z) { self.z + z } else { z },
+            yaw: if relative(RELATIVE_YAW) { self.yaw + yaw } else { yaw },
+            pitch: if relative(RELATIVE_PITCH) {
+                self.pitch + pitch
+            } else {
+                pitch
+            },
+            on_ground: self.on_ground,
+        }
+    }
+}
+
+/// PlayerPositionAndLook flag: the X coordinate is relative
+pub const RELATIVE_X: u8 = 0x01;
+/// PlayerPositionAndLook flag: the Y coordinate is relative
+pub const RELATIVE_Y: u8 = 0x02;
+/// PlayerPositionAndLook flag: the Z coordinate is relative
+pub const RELATIVE_Z: u8 = 0x04;
+/// PlayerPositionAndLook flag: the yaw is relative
+pub const RELATIVE_YAW: u8 = 0x08;
+/// PlayerPositionAndLook flag: the pitch is relative
+pub const RELATIVE_PITCH: u8 = 0x10;
+
+/// Convert an angle in degrees to the 1/256th of a turn used by the protocol
+pub fn angle_to_byte(degrees: f32) -> i8 {
+    ((degrees / 360.0 * 256.0).round() as i32 & 0xff) as u8 as i8
+}
+
+/// Creates the packet(s) used to spawn an entity for a player, given the
+/// entity ID and the entity's current location.
+pub type SpawnFn = Box<dyn Fn(i32, &Location) -> Vec<ClientboundPacket>>;
+
+struct TrackedEntity<P> {
+    location: Location,
+    spawn: SpawnFn,
+    viewers: HashSet<P>,
+}
+
+struct Viewer {
+    location: Location,
+    /* The player's own entity, which should never be spawned for them */
+    entity_id: Option<i32>,
+}
+impl Viewer {
+    fn can_see(&self, entity_id: i32, location: &Location, distance: f64) -> bool {
+        Some(entity_id) != self.entity_id &&
+        self.location.horizontal_distance(location.x, location.z) <= distance
+    }
+}
+
+/// Allocates entity IDs, and tracks which players can see which entities.
+///
+/// Entities are visible to a player if they're within the view distance
+/// (measured horizontally, in blocks) of the player. All methods that change
+/// the world return the packets that should be sent to each player, as
+/// (player, packet) pairs.
+///
+/// Players are identified by any key type, e.g. their UUID.
+pub struct EntityRegistry<P: Hash + Eq + Clone> {
+    next_id: i32,
+    view_distance: f64,
+    entities: BTreeMap<i32, TrackedEntity<P>>,
+    players: HashMap<P, Viewer>,
+}
+impl<P: Hash + Eq + Clone> EntityRegistry<P> {
+    /// Create a new registry where entities are visible within the given
+    /// amount of blocks.
+    pub fn new(view_distance: f64) -> Self {
+        EntityRegistry {
+            next_id: 1,
+            view_distance,
+            entities: BTreeMap::new(),
+            players: HashMap::new(),
+        }
+    }
+
+    /// Allocate a new entity ID without tracking any entity for it, e.g. for
+    /// use in the JoinGame packet.
+    pub fn allocate_id(&mut self) -> i32 {
+        let ret = self.next_id;
+        self.next_id = self.next_id.wrapping_add(1);
+        ret
+    }
+
+    /// Get the amount of tracked entities
+    pub fn len(&self) -> usize {
+        self.entities.len()
+    }
+
+    /// Whether no entities are tracked
+    pub fn is_empty(&self) -> bool {
+        self.entities.is_empty()
+    }
+
+    /// Get the current location of the given entity
+    pub fn location(&self, entity_id: i32) -> Option<&Location> {
+        self.entities.get(&entity_id).map(|x| &x.location)
+    }
+
+    /// Get the players that can currently see the given entity
+    pub fn viewers(&self, entity_id: i32) -> Vec<P> {
+        self.entities
+            .get(&entity_id)
+            .map(|x| x.viewers.iter().cloned().collect())
+            .unwrap_or_default()
+    }
+
+    /// Spawn a new entity at the given location, returning its new ID and the
+    /// packets needed to show it to nearby players.
+    ///
+    /// The spawn function creates the spawn packet(s) for the entity, e.g.
+    /// SpawnMob or SpawnPlayer followed by EntityMetadata. It is called
+    /// whenever the entity comes into view of a player.
+    pub fn spawn<F>(&mut self,
+                    location: Location,
+                    spawn: F)
+                    -> (i32, Vec<(P, ClientboundPacket)>)
+        where F: Fn(i32, &Location) -> Vec<ClientboundPacket> + 'static
+    {
+        let entity_id = self.allocate_id();
+        let _ = self.entities.insert(entity_id,
+                                     TrackedEntity {
+                                         location,
+                                         spawn: Box::new(spawn),
+                                         viewers: HashSet::new(),
+                                     });
+        let packets = self.update_entity_viewers(entity_id);
+        (entity_id, packets)
+    }
+
+    /// Remove the given entity, returning the packets needed to destroy it
+    /// for everybody that could see it.
+    pub fn despawn(&mut self, entity_id: i32) -> Vec<(P, ClientboundPacket)> {
+        match self.entities.remove(&entity_id) {
+            Some(entity) => {
+                entity.viewers
+                    .into_iter()
+                    .map(|p| (p, DestroyEntities::new(vec![entity_id])))
+                    .collect()
+            },
+            None => Vec::new(),
+        }
+    }
+
+    /// Move the given entity, returning the movement packets for the players
+    /// that can still see it, and spawn/destroy packets for the players that
+    /// it moved into or out of view of.
+    pub fn move_entity(&mut self,
+                       entity_id: i32,
+                       location: Location)
+                       -> Vec<(P, ClientboundPacket)> {
+        let movement = match self.entities.get_mut(&entity_id) {
+            Some(entity) => {
+                let old = entity.location;
+                entity.location = location;
+                movement_packets(entity_id, &old, &location)
+            },
+            None => return Vec::new(),
+        };
+
+        let before = self.viewers(entity_id);
+        let mut ret = self.update_entity_viewers(entity_id);
+        let after = &self.entities[&entity_id].viewers;
+        for player in before.into_iter().filter(|p| after.contains(p)) {
+            for packet in &movement {
+                ret.push((player.clone(), packet.clone()));
+            }
+        }
+        ret
+    }
+
+    /// Set the location of the given player, returning the packets needed to
+    /// spawn entities that came into view, and destroy those that left it.
+    ///
+    /// If the player is a new player, they start out seeing no entities.
+    ///
+    /// The entity ID is the player's own entity, which is never spawned for
+    /// themselves.
+    pub fn update_player(&mut self,
+                         player: P,
+                         entity_id: Option<i32>,
+                         location: Location)
+                         -> Vec<(P, ClientboundPacket)> {
+        let _ = self.players.insert(player.clone(),
+                                    Viewer {
+                                        location,
+                                        entity_id,
+                                    });
+
+        let viewer = &self.players[&player];
+        let mut ret = Vec::new();
+        let mut destroyed = Vec::new();
+        for (&id, entity) in &mut self.entities {
+            let visible =
+                viewer.can_see(id, &entity.location, self.view_distance);
+            let seen = entity.viewers.contains(&player);
+            if visible && !seen {
+                let _: bool = entity.viewers.insert(player.clone());
+                for packet in (entity.spawn)(id, &entity.location) {
+                    ret.push((player.clone(), packet));
+                }
+            } else if !visible && seen {
+                let _: bool = entity.viewers.remove(&player);
+                destroyed.push(id);
+            }
+        }
+        if !destroyed.is_empty() {
+            ret.push((player, DestroyEntities::new(destroyed)));
+        }
+        ret
+    }
+
+    /// Stop tracking the given player, e.g. because they disconnected.
+    ///
+    /// No packets are generated.
+    pub fn remove_player(&mut self, player: &P) {
+        let _ = self.players.remove(player);
+        for entity in self.entities.values_mut() {
+            let _: bool = entity.viewers.remove(player);
+        }
+    }
+
+    /// Recompute which players can see the given entity
+    fn update_entity_viewers(&mut self,
+                             entity_id: i32)
+                             -> Vec<(P, ClientboundPacket)> {
+        let mut ret = Vec::new();
+        let entity = match self.entities.get_mut(&entity_id) {
+            Some(x) => x,
+            None => return ret,
+        };
+        for (player, viewer) in &self.players {
+            let visible = viewer.can_see(entity_id,
+                                         &entity.location,
+                                         self.view_distance);
+            let seen = entity.viewers.contains(player);
+            if visible && !seen {
+                let _: bool = entity.viewers.insert(player.clone());
+                for packet in (entity.spawn)(entity_id, &entity.location) {
+                    ret.push((player.clone(), packet));
+                }
+            } else if !visible && seen {
+                let _: bool = entity.viewers.remove(player);
+                ret.push((player.clone(), DestroyEntities::new(vec![entity_id])));
+            }
+        }
+        ret
+    }
+}
+impl<P: Hash + Eq + Clone> fmt::Debug for EntityRegistry<P> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("EntityRegistry")
+            .field("next_id", &self.next_id)
+            .field("view_distance", &self.view_distance)
+            .field("entities", &self.entities.len())
+            .field("players", &self.players.len())
+            .finish()
+    }
+}
+
+/// Get the packets needed to move an entity from old to new, for a player
+/// that has already seen the entity at old.
+///
+/// Uses relative moves if the entity moved less than 8 blocks along every
+/// axis, else a teleport.
+pub fn movement_packets(entity_id: i32,
+                        old: &Location,
+                        new: &Location)
+                        -> Vec<ClientboundPacket> {
+    let mut ret = Vec::new();
+    let moved = old.x != new.x || old.y != new.y || old.z != new.z;
+    let rotated = old.yaw != new.yaw || old.pitch != new.pitch;
+    let yaw = angle_to_byte(new.yaw);
+    let pitch = angle_to_byte(new.pitch);
+
+    /* Relative moves are given in 1/4096ths of a block */
+    let delta = |a: f64, b: f64| (b * 4096.0).round() - (a * 4096.0).round();
+    let (dx, dy, dz) = (delta(old.x, new.x),
+                        delta(old.y, new.y),
+                        delta(old.z, new.z));
+    let fits = |d: f64| d >= i16::MIN as f64 && d <= i16::MAX as f64;
+
+    if moved && !(fits(dx) && fits(dy) && fits(dz)) {
+        ret.push(EntityTeleport::new(entity_id,
+                                     new.x,
+                                     new.y,
+                                     new.z,
+                                     yaw,
+                                     pitch,
+                                     new.on_ground));
+    } else if moved && rotated {
+        ret.push(EntityLookRelativeMove::new(entity_id,
+                                             dx as i16,
+                                             dy as i16,
+                                             dz as i16,
+                                             yaw,
+                                             pitch,
+                                             new.on_ground));
+    } else if moved {
+        ret.push(EntityRelativeMove::new(entity_id,
+                                         dx as i16,
+                                         dy as i16,
+                                         dz as i16,
+                                         new.on_ground));
+    } else if rotated {
+        ret.push(EntityLook::new(entity_id, yaw, pitch, new.on_ground));
+    }
+
+    if old.yaw != new.yaw {
+        ret.push(EntityHeadLook::new(entity_id, yaw));
+    }
+
+    ret
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use clientbound::SpawnGlobalEntity;
+
+    fn spawn(id: i32, l: &Location) -> Vec<ClientboundPacket> {
+        vec![SpawnGlobalEntity::new(id, 1, l.x, l.y, l.z)]
+    }
+
+    #[test]
+    fn entity_registry() {
+        let mut reg = EntityRegistry::new(32.0);
+        let own_id = reg.allocate_id();
+        assert!(reg.update_player("a", Some(own_id), Location::new(0.0, 64.0, 0.0))
+                    .is_empty());
+
+        let (id, packets) = reg.spawn(Location::new(10.0, 64.0, 0.0), spawn);
+        assert_eq!(packets,
+                   vec![("a", SpawnGlobalEntity::new(id, 1, 10.0, 64.0, 0.0))]);
+
+        let packets = reg.move_entity(id, Location::new(11.0, 64.0, 0.0));
+        assert_eq!(packets,
+                   vec![("a", EntityRelativeMove::new(id, 4096, 0, 0, true))]);
+
+        let packets = reg.move_entity(id, Location::new(100.0, 64.0, 0.0));
+        assert_eq!(packets, vec![("a", DestroyEntities::new(vec![id]))]);
+
+        let packets = reg.update_player("a",
+                                        Some(own_id),
+                                        Location::new(90.0, 64.0, 0.0));
+        assert_eq!(packets,
+                   vec![("a", SpawnGlobalEntity::new(id, 1, 100.0, 64.0, 0.0))]);
+
+        assert_eq!(reg.despawn(id), vec![("a", DestroyEntities::new(vec![id]))]);
+        assert!(reg.is_empty());
+    }
+
+    #[test]
+    fn movement_packets() {
+        let old = Location::new(0.0, 0.0, 0.0);
+        let mut new = Location::new(0.0, 0.0, 20.0);
+        assert_eq!(super::movement_packets(1, &old, &new),
+                   vec![EntityTeleport::new(1, 0.0, 0.0, 20.0, 0, 0, true)]);
+        new.z = 0.5;
+        new.yaw = 90.0;
+        assert_eq!(super::movement_packets(1, &old, &new),
+                   vec![EntityLookRelativeMove::new(1, 0, 0, 2048, 64, 0, true),
+                        EntityHeadLook::new(1, 64)]);
+    }
+
+    #[test]
+    fn teleport() {
+        let mut old = Location::new(10.0, 64.0, 10.0);
+        old.yaw = 90.0;
+        let new = old.teleport(1.0, 70.0, -1.0, 10.0, 45.0,
+                               RELATIVE_X | RELATIVE_Z | RELATIVE_YAW);
+        assert_eq!(new.x, 11.0);
+        assert_eq!(new.y, 70.0);
+        assert_eq!(new.z, 9.0);
+        assert_eq!(new.yaw, 100.0);
+        assert_eq!(new.pitch, 45.0);
+    }
+}
@@ -7,5 +7,90 @@ error_chain! {
         Openssl(::openssl::error::ErrorStack);
         Serde(::serde_json::Error);
         FromUtf8(::std::string::FromUtf8Error);
+        Utf8(::std::str::Utf8Error);
+    }
+
+    errors {
+        /// The Mojang account has been migrated to a Microsoft account, and
+        /// must log in with the msa module instead
+        MigratedToMicrosoft(message: String) {
+            description("account migrated to a Microsoft account")
+            display("The account has been migrated to a Microsoft account, log in with Microsoft instead ({})", message)
+        }
+        /// The legacy account has been migrated to a Mojang account, and
+        /// must log in with its email address instead of the username
+        UseEmail(message: String) {
+            description("account migrated, use email as username")
+            display("The account has been migrated, log in with the email address instead of the username ({})", message)
+        }
+        /// The account is a legacy (unmigrated) account, which can no
+        /// longer log in until it's migrated
+        LegacyAccount(message: String) {
+            description("legacy account")
+            display("The account is a legacy account, migrate it to be able to log in ({})", message)
+        }
+        /// Mojang wants the security questions of the account answered
+        /// before allowing it to log in from this location
+        SecurityChallengeRequired(message: String) {
+            description("security questions must be answered")
+            display("The account's security questions must be answered before logging in from this location ({})", message)
+        }
+        /// Wrong username or password
+        InvalidCredentials(message: String) {
+            description("invalid credentials")
+            display("Invalid username or password ({})", message)
+        }
+        /// The access token is invalid or expired, and must be refreshed or
+        /// a new one obtained by logging in again
+        InvalidToken(message: String) {
+            description("invalid token")
+            display("The access token is invalid or expired ({})", message)
+        }
+        /// The account isn't allowed to join online servers, e.g. because
+        /// multiplayer is disabled for it
+        InsufficientPrivileges(message: String) {
+            description("insufficient privileges")
+            display("The account isn't allowed to join servers ({})", message)
+        }
+        /// The shared secret in an EncryptionResponse didn't decrypt to 16
+        /// bytes
+        BadSharedSecret(len: usize) {
+            description("bad shared secret")
+            display("The decrypted shared secret was {} bytes long, not 16", len)
+        }
+        /// The verify token in an EncryptionResponse didn't match the one
+        /// sent in the EncryptionRequest
+        VerifyTokenMismatch {
+            description("verify token mismatch")
+            display("The verify token sent by the client doesn't match the one sent to it")
+        }
+        /// A scoreboard name is empty, see the scoreboard module
+        EmptyName(kind: ::scoreboard::NameKind) {
+            description("empty name")
+            display("The {} is empty", kind)
+        }
+        /// A scoreboard name is longer than the client accepts, in UTF-16
+        /// code units
+        NameTooLong(kind: ::scoreboard::NameKind, length: usize, max: usize) {
+            description("name too long")
+            display("The {} is {} characters long, the maximum is {}", kind, length, max)
+        }
+        /// A scoreboard name contains a character vanilla doesn't allow in it
+        InvalidNameCharacter(kind: ::scoreboard::NameKind, c: char) {
+            description("invalid character in name")
+            display("The {} contains the invalid character {:?}", kind, c)
+        }
+        /// Mojang answered an earlier request with 429 Too Many Requests, and
+        /// the cooldown it asked for has this much longer to go
+        RateLimited(remaining: ::std::time::Duration) {
+            description("rate limited")
+            display("Rate limited by Mojang, retry in {} seconds", remaining.as_secs() + 1)
+        }
+        /// Any other error response from a Mojang API, with the HTTP status,
+        /// and the error, errorMessage and cause given by the API
+        MojangApi(status: u32, error: String, message: String, cause: Option<String>) {
+            description("Mojang API error")
+            display("Mojang API returned {}: {}: {}", status, error, message)
+        }
     }
 }
@@ -0,0 +1,72 @@
+//! Converting between experience points and levels
+//!
+//! These are the vanilla formulas since 1.8. The SetExperience packet, and
+//! client.experience(), give the level, the progress towards the next level
+//! and the total experience, but the total isn't reset when the player
+//! spends levels, so use these to work out e.g. how many points a bot has
+//! to collect to afford an enchantment.
+
+/// The points needed to get from the given level to the next
+pub fn points_to_next_level(level: i32) -> i32 {
+    match level {
+        x if x <= 0 => 7,
+        x if x <= 15 => 2 * x + 7,
+        x if x <= 30 => 5 * x - 38,
+        x => 9 * x - 158,
+    }
+}
+
+/// The total points needed to reach the given level from 0
+pub fn points_for_level(level: i32) -> i64 {
+    let l = level.max(0) as i64;
+    if l <= 16 {
+        l * l + 6 * l
+    } else if l <= 31 {
+        (5 * l * l - 81 * l + 720) / 2
+    } else {
+        (9 * l * l - 325 * l + 4440) / 2
+    }
+}
+
+/// The points of a player at the given level with the given progress
+/// towards the next, from 0 to 1, e.g. from client.experience()
+pub fn points(level: i32, progress: f32) -> i64 {
+    let progress = progress.clamp(0.0, 1.0) as f64;
+    points_for_level(level) + (points_to_next_level(level) as f64 * progress).round() as i64
+}
+
+/// The level reached with the given total points, and the progress towards
+/// the next level from 0 to 1
+pub fn level_for_points(points: i64) -> (i32, f32) {
+    let mut level = 0;
+    let mut remaining = points.max(0);
+    loop {
+        let next = points_to_next_level(level) as i64;
+        if remaining < next {
+            return (level, remaining as f32 / next as f32);
+        }
+        remaining -= next;
+        level += 1;
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn levels() {
+        assert_eq!(points_for_level(0), 0);
+        assert_eq!(points_for_level(16), 352);
+        assert_eq!(points_for_level(30), 1395);
+        assert_eq!(points_for_level(31), 1507);
+        assert_eq!(points_for_level(32), 1628);
+        for level in 0..100 {
+            assert_eq!(points_for_level(level + 1) - points_for_level(level),
+                       points_to_next_level(level) as i64);
+            assert_eq!(level_for_points(points_for_level(level)), (level, 0.0));
+        }
+        assert_eq!(level_for_points(1400), (30, 5.0 / 112.0));
+        assert_eq!(points(30, 5.0 / 112.0), 1400);
+    }
+}
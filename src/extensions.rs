@@ -0,0 +1,125 @@
+//! Per-connection state attached by user code
+//!
+//! Programs usually need to keep some state per connection, e.g. the
+//! player's data or permissions, and middleware may need its own. Rather
+//! than keeping it in maps keyed by socket address, it can be stored in
+//! the connection itself, see server.extensions() and client.extensions().
+//!
+//! Extensions holds at most one value of each type, so give each kind of
+//! state its own type, e.g. a newtype around a String rather than a String.
+//!
+//! # Examples
+//!
+//! ```rust
+//! use ozelot::extensions::Extensions;
+//!
+//! struct Permissions(Vec<String>);
+//!
+//! let mut extensions = Extensions::new();
+//! let _ = extensions.insert(Permissions(vec!["kick".to_string()]));
+//! if let Some(permissions) = extensions.get_mut::<Permissions>() {
+//!     permissions.0.push("ban".to_string());
+//! }
+//! assert_eq!(extensions.get::<Permissions>().unwrap().0.len(), 2);
+//! ```
+
+use std::any::{Any, TypeId};
+use std::collections::HashMap;
+use std::fmt;
+
+/// A map holding one value of each type, see the module documentation
+#[derive(Default)]
+pub struct Extensions {
+    map: HashMap<TypeId, Box<dyn Any + Send>>,
+}
+impl Extensions {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Insert the value, returning the previous value of the type if any
+    pub fn insert<T: Any + Send>(&mut self, value: T) -> Option<T> {
+        self.map
+            .insert(TypeId::of::<T>(), Box::new(value))
+            .and_then(|x| x.downcast().ok())
+            .map(|x| *x)
+    }
+
+    /// Get the value of the type, if there is one
+    pub fn get<T: Any + Send>(&self) -> Option<&T> {
+        self.map.get(&TypeId::of::<T>()).and_then(|x| x.downcast_ref())
+    }
+
+    /// Get the value of the type mutably, if there is one
+    pub fn get_mut<T: Any + Send>(&mut self) -> Option<&mut T> {
+        self.map.get_mut(&TypeId::of::<T>()).and_then(|x| x.downcast_mut())
+    }
+
+    /// Get the value of the type, inserting the one returned by f first if
+    /// there is none
+    pub fn get_or_insert_with<T: Any + Send, F: FnOnce() -> T>(&mut self, f: F) -> &mut T {
+        self.map
+            .entry(TypeId::of::<T>())
+            .or_insert_with(|| Box::new(f()))
+            .downcast_mut()
+            .expect("Extension stored under the wrong type")
+    }
+
+    /// Remove the value of the type, returning it if there was one
+    pub fn remove<T: Any + Send>(&mut self) -> Option<T> {
+        self.map
+            .remove(&TypeId::of::<T>())
+            .and_then(|x| x.downcast().ok())
+            .map(|x| *x)
+    }
+
+    /// Whether there is a value of the type
+    pub fn contains<T: Any + Send>(&self) -> bool {
+        self.map.contains_key(&TypeId::of::<T>())
+    }
+
+    /// The amount of values
+    pub fn len(&self) -> usize {
+        self.map.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.map.is_empty()
+    }
+
+    /// Remove all values
+    pub fn clear(&mut self) {
+        self.map.clear()
+    }
+}
+impl fmt::Debug for Extensions {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Extensions").field("len", &self.map.len()).finish()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[derive(Debug, PartialEq)]
+    struct Rank(u8);
+
+    #[test]
+    fn extensions() {
+        let mut extensions = Extensions::new();
+        assert!(extensions.insert(Rank(1)).is_none());
+        assert_eq!(extensions.insert(Rank(2)), Some(Rank(1)));
+        assert_eq!(extensions.insert("name"), None);
+        assert_eq!(extensions.len(), 2);
+
+        extensions.get_or_insert_with(|| Rank(5)).0 += 1;
+        assert_eq!(extensions.get::<Rank>(), Some(&Rank(3)));
+        assert_eq!(*extensions.get_or_insert_with(|| 7u32), 7);
+        assert_eq!(extensions.remove::<Rank>(), Some(Rank(3)));
+        assert!(!extensions.contains::<Rank>());
+        assert_eq!(extensions.get::<&str>(), Some(&"name"));
+        extensions.clear();
+        assert!(extensions.is_empty());
+    }
+}
@@ -0,0 +1,386 @@
+//! A C API for the Mojang and status APIs
+//!
+//! Lets server software written in other languages use ozelot for looking
+//! up UUIDs, authenticating and pinging servers. Requires the ffi feature,
+//! and is built as a shared library with
+//! cargo rustc --release --features ffi --crate-type cdylib
+//!
+//! Functions return OZELOT_OK (0) on success or a negative error code, with
+//! the message of the last error on the calling thread available from
+//! ozelot_last_error(). Strings returned through out parameters must be
+//! freed with ozelot_string_free(), and handles with their own free
+//! function. Strings returned directly belong to the handle or thread they
+//! came from and must not be freed.
+//!
+//! The declarations are
+//!
+//! ```c
+//! #define OZELOT_OK 0
+//! #define OZELOT_ERROR_INVALID_ARGUMENT -1
+//! #define OZELOT_ERROR_NETWORK -2
+//! #define OZELOT_ERROR_AUTH -3
+//! #define OZELOT_ERROR_OTHER -4
+//!
+//! typedef struct OzelotSession OzelotSession;
+//! typedef struct OzelotStatus OzelotStatus;
+//!
+//! const char *ozelot_last_error(void);
+//! void ozelot_string_free(char *s);
+//!
+//! int ozelot_lookup_uuid(const char *username, char **uuid);
+//!
+//! int ozelot_authenticate(const char *username, const char *password, OzelotSession **session);
+//! const char *ozelot_session_access_token(const OzelotSession *session);
+//! const char *ozelot_session_name(const OzelotSession *session);
+//! const char *ozelot_session_uuid(const OzelotSession *session);
+//! void ozelot_session_free(OzelotSession *session);
+//!
+//! int ozelot_ping(const char *host, uint16_t port, uint32_t timeout_ms, OzelotStatus **status);
+//! const char *ozelot_status_version_name(const OzelotStatus *status);
+//! int32_t ozelot_status_protocol(const OzelotStatus *status);
+//! int32_t ozelot_status_players_online(const OzelotStatus *status);
+//! int32_t ozelot_status_players_max(const OzelotStatus *status);
+//! const char *ozelot_status_description(const OzelotStatus *status);
+//! uint32_t ozelot_status_latency_ms(const OzelotStatus *status);
+//! void ozelot_status_free(OzelotStatus *status);
+//! ```
+
+use errors::{Error, ErrorKind, Result};
+use mojang::{self, Authenticate};
+use status;
+
+use std::cell::RefCell;
+use std::ffi::{CStr, CString};
+use std::os::raw::{c_char, c_int};
+use std::panic::{self, AssertUnwindSafe};
+use std::ptr;
+use std::time::Duration;
+
+/// Success
+pub const OZELOT_OK: c_int = 0;
+/// A null pointer or invalid string was given
+pub const OZELOT_ERROR_INVALID_ARGUMENT: c_int = -1;
+/// The connection or request failed
+pub const OZELOT_ERROR_NETWORK: c_int = -2;
+/// The credentials or token were rejected
+pub const OZELOT_ERROR_AUTH: c_int = -3;
+/// Any other error, see ozelot_last_error()
+pub const OZELOT_ERROR_OTHER: c_int = -4;
+
+thread_local! {
+    static LAST_ERROR: RefCell<Option<CString>> = const { RefCell::new(None) };
+}
+
+/// An authenticated Mojang session
+#[derive(Debug)]
+pub struct OzelotSession {
+    access_token: CString,
+    name: CString,
+    uuid: CString,
+}
+
+/// The status of a server
+#[derive(Debug)]
+pub struct OzelotStatus {
+    version_name: CString,
+    protocol: i32,
+    players_online: i32,
+    players_max: i32,
+    description: CString,
+    latency_ms: u32,
+}
+
+/* Get the error code of an error */
+fn error_code(e: &Error) -> c_int {
+    match *e.kind() {
+        ErrorKind::InvalidCredentials(..) |
+        ErrorKind::InvalidToken(..) |
+        ErrorKind::MigratedToMicrosoft(..) |
+        ErrorKind::UseEmail(..) |
+        ErrorKind::LegacyAccount(..) |
+        ErrorKind::SecurityChallengeRequired(..) => OZELOT_ERROR_AUTH,
+        ErrorKind::Curl(..) | ErrorKind::Io(..) => OZELOT_ERROR_NETWORK,
+        _ => OZELOT_ERROR_OTHER,
+    }
+}
+
+fn set_last_error(msg: &str) {
+    let msg = CString::new(msg.replace('\0', "")).unwrap_or_default();
+    LAST_ERROR.with(|x| *x.borrow_mut() = Some(msg));
+}
+
+/* Run f, turning errors and panics into error codes */
+fn guard<F: FnOnce() -> Result<()>>(f: F) -> c_int {
+    match panic::catch_unwind(AssertUnwindSafe(f)) {
+        Ok(Ok(())) => OZELOT_OK,
+        Ok(Err(e)) => {
+            set_last_error(&e.to_string());
+            error_code(&e)
+        },
+        Err(_) => {
+            set_last_error("ozelot panicked");
+            OZELOT_ERROR_OTHER
+        },
+    }
+}
+
+/* Read a string argument, setting the error if invalid */
+unsafe fn arg<'a>(s: *const c_char) -> Option<&'a str> {
+    if s.is_null() {
+        set_last_error("Null string argument");
+        return None;
+    }
+    let ret = CStr::from_ptr(s).to_str().ok();
+    if ret.is_none() {
+        set_last_error("String argument is not valid UTF-8");
+    }
+    ret
+}
+
+fn c_string(s: &str) -> CString {
+    CString::new(s.replace('\0', "")).unwrap_or_default()
+}
+
+/* Check an out parameter, setting the error if invalid */
+fn check_out<T>(out: *mut T) -> bool {
+    if out.is_null() {
+        set_last_error("Null out parameter");
+    }
+    !out.is_null()
+}
+
+/// Get the message of the last error on this thread, or null if there was
+/// none. The string is valid until the next call on this thread.
+#[no_mangle]
+pub extern "C" fn ozelot_last_error() -> *const c_char {
+    LAST_ERROR.with(|x| match *x.borrow() {
+        Some(ref x) => x.as_ptr(),
+        None => ptr::null(),
+    })
+}
+
+/// Free a string returned through an out parameter
+///
+/// # Safety
+///
+/// s must be null or a string returned by ozelot, not freed before.
+#[no_mangle]
+pub unsafe extern "C" fn ozelot_string_free(s: *mut c_char) {
+    if !s.is_null() {
+        drop(CString::from_raw(s));
+    }
+}
+
+/// Look up the UUID of a player, written to uuid with hyphens
+///
+/// # Safety
+///
+/// username must be null or a valid C string, and uuid null or valid for writes.
+#[no_mangle]
+pub unsafe extern "C" fn ozelot_lookup_uuid(username: *const c_char,
+                                            uuid: *mut *mut c_char)
+                                            -> c_int {
+    let username = match arg(username) {
+        Some(x) if check_out(uuid) => x,
+        _ => return OZELOT_ERROR_INVALID_ARGUMENT,
+    };
+    guard(|| {
+        let found = mojang::lookup_uuid(username)?;
+        *uuid = c_string(&found.to_string()).into_raw();
+        Ok(())
+    })
+}
+
+/// Authenticate with a Mojang account, the session must be freed with
+/// ozelot_session_free()
+///
+/// # Safety
+///
+/// username and password must be null or valid C strings, and session null
+/// or valid for writes.
+#[no_mangle]
+pub unsafe extern "C" fn ozelot_authenticate(username: *const c_char,
+                                             password: *const c_char,
+                                             session: *mut *mut OzelotSession)
+                                             -> c_int {
+    let (username, password) = match (arg(username), arg(password)) {
+        (Some(x), Some(y)) if check_out(session) => (x, y),
+        _ => return OZELOT_ERROR_INVALID_ARGUMENT,
+    };
+    guard(|| {
+        let auth = Authenticate::new(username.to_string(), password.to_string()).perform()?;
+        let ret = OzelotSession {
+            access_token: c_string(&auth.accessToken),
+            name: c_string(&auth.selectedProfile.name),
+            uuid: c_string(&auth.selectedProfile.id),
+        };
+        *session = Box::into_raw(Box::new(ret));
+        Ok(())
+    })
+}
+
+/// Get the access token of the session
+///
+/// # Safety
+///
+/// The handle must be null or a live handle returned by ozelot.
+#[no_mangle]
+pub unsafe extern "C" fn ozelot_session_access_token(session: *const OzelotSession) -> *const c_char {
+    session.as_ref().map_or(ptr::null(), |x| x.access_token.as_ptr())
+}
+
+/// Get the player name of the session
+///
+/// # Safety
+///
+/// The handle must be null or a live handle returned by ozelot.
+#[no_mangle]
+pub unsafe extern "C" fn ozelot_session_name(session: *const OzelotSession) -> *const c_char {
+    session.as_ref().map_or(ptr::null(), |x| x.name.as_ptr())
+}
+
+/// Get the player UUID of the session, without hyphens
+///
+/// # Safety
+///
+/// The handle must be null or a live handle returned by ozelot.
+#[no_mangle]
+pub unsafe extern "C" fn ozelot_session_uuid(session: *const OzelotSession) -> *const c_char {
+    session.as_ref().map_or(ptr::null(), |x| x.uuid.as_ptr())
+}
+
+/// Free a session
+///
+/// # Safety
+///
+/// The handle must be null or returned by ozelot, and not freed before.
+#[no_mangle]
+pub unsafe extern "C" fn ozelot_session_free(session: *mut OzelotSession) {
+    if !session.is_null() {
+        drop(Box::from_raw(session));
+    }
+}
+
+/// Ping a server for its status, which must be freed with
+/// ozelot_status_free()
+///
+/// # Safety
+///
+/// host must be null or a valid C string, and status null or valid for writes.
+#[no_mangle]
+pub unsafe extern "C" fn ozelot_ping(host: *const c_char,
+                                     port: u16,
+                                     timeout_ms: u32,
+                                     status: *mut *mut OzelotStatus)
+                                     -> c_int {
+    let host = match arg(host) {
+        Some(x) if check_out(status) => x,
+        _ => return OZELOT_ERROR_INVALID_ARGUMENT,
+    };
+    guard(|| {
+        let timeout = Duration::from_millis(u64::from(timeout_ms));
+        let x = status::ping(host, port, timeout)?;
+        let ret = OzelotStatus {
+            version_name: c_string(&x.version_name),
+            protocol: x.protocol,
+            players_online: x.players_online,
+            players_max: x.players_max,
+            description: c_string(&x.description),
+            latency_ms: x.latency.as_millis() as u32,
+        };
+        *status = Box::into_raw(Box::new(ret));
+        Ok(())
+    })
+}
+
+/// Get the version name of the server, e.g. 1.13.2
+///
+/// # Safety
+///
+/// The handle must be null or a live handle returned by ozelot.
+#[no_mangle]
+pub unsafe extern "C" fn ozelot_status_version_name(status: *const OzelotStatus) -> *const c_char {
+    status.as_ref().map_or(ptr::null(), |x| x.version_name.as_ptr())
+}
+
+/// Get the protocol version of the server
+///
+/// # Safety
+///
+/// The handle must be null or a live handle returned by ozelot.
+#[no_mangle]
+pub unsafe extern "C" fn ozelot_status_protocol(status: *const OzelotStatus) -> i32 {
+    status.as_ref().map_or(0, |x| x.protocol)
+}
+
+/// Get the number of players online
+///
+/// # Safety
+///
+/// The handle must be null or a live handle returned by ozelot.
+#[no_mangle]
+pub unsafe extern "C" fn ozelot_status_players_online(status: *const OzelotStatus) -> i32 {
+    status.as_ref().map_or(0, |x| x.players_online)
+}
+
+/// Get the maximum number of players
+///
+/// # Safety
+///
+/// The handle must be null or a live handle returned by ozelot.
+#[no_mangle]
+pub unsafe extern "C" fn ozelot_status_players_max(status: *const OzelotStatus) -> i32 {
+    status.as_ref().map_or(0, |x| x.players_max)
+}
+
+/// Get the MOTD as raw chat json
+///
+/// # Safety
+///
+/// The handle must be null or a live handle returned by ozelot.
+#[no_mangle]
+pub unsafe extern "C" fn ozelot_status_description(status: *const OzelotStatus) -> *const c_char {
+    status.as_ref().map_or(ptr::null(), |x| x.description.as_ptr())
+}
+
+/// Get the latency of the ping in milliseconds
+///
+/// # Safety
+///
+/// The handle must be null or a live handle returned by ozelot.
+#[no_mangle]
+pub unsafe extern "C" fn ozelot_status_latency_ms(status: *const OzelotStatus) -> u32 {
+    status.as_ref().map_or(0, |x| x.latency_ms)
+}
+
+/// Free a status
+///
+/// # Safety
+///
+/// The handle must be null or returned by ozelot, and not freed before.
+#[no_mangle]
+pub unsafe extern "C" fn ozelot_status_free(status: *mut OzelotStatus) {
+    if !status.is_null() {
+        drop(Box::from_raw(status));
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn errors() {
+        let mut uuid = ptr::null_mut();
+        let code = unsafe { ozelot_lookup_uuid(ptr::null(), &mut uuid) };
+        assert_eq!(code, OZELOT_ERROR_INVALID_ARGUMENT);
+        assert!(uuid.is_null());
+        let msg = unsafe { CStr::from_ptr(ozelot_last_error()) };
+        assert_eq!(msg.to_str().unwrap(), "Null string argument");
+
+        let e: Error = ErrorKind::InvalidCredentials("".to_string()).into();
+        assert_eq!(error_code(&e), OZELOT_ERROR_AUTH);
+        assert_eq!(guard(|| bail!("failed")), OZELOT_ERROR_OTHER);
+        assert_eq!(unsafe { ozelot_status_protocol(ptr::null()) }, 0);
+    }
+}
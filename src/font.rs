@@ -0,0 +1,122 @@
+//! Estimating how wide text is rendered by the vanilla client
+//!
+//! Servers aligning text in chat or the MOTD need to know how many pixels it
+//! takes up, since the default font isn't monospaced. The widths here are
+//! those of the vanilla ASCII font at GUI scale 1, including the pixel of
+//! space after each character. Other characters are rendered with the
+//! unicode font, whose widths vary per glyph, so they are estimated: 9
+//! pixels for CJK characters and 6 for everything else.
+//!
+//! Legacy formatting codes (section sign codes) take up no space, but bold
+//! text is a pixel wider per character.
+//!
+//! # Examples
+//!
+//! ```rust
+//! use ozelot::font;
+//!
+//! let line = font::center("\u{a7}lWelcome!", font::CHAT_WIDTH);
+//! assert!(line.starts_with("      "));
+//! ```
+
+/// The width of the chat window with the default settings
+pub const CHAT_WIDTH: u32 = 320;
+/// The approximate width available to each line of the MOTD in the server
+/// list
+pub const MOTD_WIDTH: u32 = 270;
+
+/* The widths of the printable ASCII characters, starting with the space */
+const ASCII_WIDTHS: [u8; 95] = [
+    /*   ! " # $ % & ' ( ) * + , - . / */
+    4, 2, 4, 6, 6, 6, 6, 2, 5, 5, 5, 6, 2, 6, 2, 6,
+    /* 0 1 2 3 4 5 6 7 8 9 : ; < = > ? */
+    6, 6, 6, 6, 6, 6, 6, 6, 6, 6, 2, 2, 5, 6, 5, 6,
+    /* @ A B C D E F G H I J K L M N O */
+    7, 6, 6, 6, 6, 6, 6, 6, 6, 4, 6, 6, 6, 6, 6, 6,
+    /* P Q R S T U V W X Y Z [ \ ] ^ _ */
+    6, 6, 6, 6, 6, 6, 6, 6, 6, 6, 6, 4, 6, 4, 6, 6,
+    /* ` a b c d e f g h i j k l m n o */
+    3, 6, 6, 6, 6, 6, 5, 6, 6, 2, 6, 5, 3, 6, 6, 6,
+    /* p q r s t u v w x y z { | } ~ */
+    6, 6, 6, 6, 4, 6, 6, 6, 6, 6, 6, 5, 2, 5, 6,
+];
+
+/// Get the width of a character in pixels, see the module documentation
+pub fn char_width(c: char, bold: bool) -> u32 {
+    let width = match c as u32 {
+        x @ 0x20..=0x7e => u32::from(ASCII_WIDTHS[(x - 0x20) as usize]),
+        x if x < 0x20 || x == 0x7f => return 0,
+        _ if is_wide(c) => 9,
+        _ => 6,
+    };
+    if bold { width + 1 } else { width }
+}
+
+/// Get the width of the text in pixels, applying the legacy formatting
+/// codes in it
+pub fn text_width(text: &str) -> u32 {
+    let mut width = 0;
+    let mut bold = false;
+    let mut chars = text.chars();
+    while let Some(c) = chars.next() {
+        if c != '\u{a7}' {
+            width += char_width(c, bold);
+            continue;
+        }
+        match chars.next().map(|x| x.to_ascii_lowercase()) {
+            Some('l') => bold = true,
+            /* Color codes and reset also reset the formatting */
+            Some('0'..='9') | Some('a'..='f') | Some('r') => bold = false,
+            Some(_) => (),
+            None => break,
+        }
+    }
+    width
+}
+
+/// Pad the text with spaces on the left so that it is centered within the
+/// given width. Text that is too wide is returned as is.
+pub fn center(text: &str, width: u32) -> String {
+    let free = width.saturating_sub(text_width(text)) / 2;
+    let mut ret = spaces(free);
+    ret.push_str(text);
+    ret
+}
+
+/// Pad the text with spaces on the right, to at most the given width, e.g.
+/// to align columns. Text that is too wide is returned as is.
+pub fn pad_right(text: &str, width: u32) -> String {
+    let free = width.saturating_sub(text_width(text));
+    let mut ret = text.to_string();
+    ret.push_str(&spaces(free));
+    ret
+}
+
+/* As many spaces as fit within the given width. Since spaces are 4 pixels
+ * wide, this may be up to 3 pixels short. */
+fn spaces(width: u32) -> String {
+    " ".repeat((width / char_width(' ', false)) as usize)
+}
+
+/* Whether the character is rendered double width by the unicode font */
+fn is_wide(c: char) -> bool {
+    matches!(c as u32,
+             0x1100..=0x115f | 0x2e80..=0xa4cf | 0xac00..=0xd7a3 | 0xf900..=0xfaff |
+             0xfe30..=0xfe4f | 0xff00..=0xff60 | 0xffe0..=0xffe6)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn width() {
+        assert_eq!(text_width("Hello"), 6 + 6 + 3 + 3 + 6);
+        assert_eq!(text_width("\u{a7}cHi"), 6 + 2);
+        assert_eq!(text_width("\u{a7}lHi\u{a7}r!"), 7 + 3 + 2);
+        assert_eq!(text_width("日本"), 18);
+        assert_eq!(center("Hi", 48), "     Hi");
+        assert_eq!(center(&"W".repeat(100), CHAT_WIDTH), "W".repeat(100));
+        assert_eq!(pad_right("ab", 20), "ab  ");
+    }
+}
@@ -0,0 +1,239 @@
+//! Connecting directly to backend servers behind a proxy
+//!
+//! Servers behind a BungeeCord or Velocity proxy run in offline mode, and
+//! trust the proxy to tell them who the player is and where they connect
+//! from. Network-internal tools, e.g. for diagnostics, can connect to a
+//! backend directly by forwarding that information themselves:
+//!
+//! - BungeeCord's legacy forwarding appends the address, UUID and
+//!   properties to the host in the Handshake, see bungee_host(). The
+//!   BungeeGuard plugin also requires a token among the properties.
+//! - Velocity's modern forwarding answers a LoginPluginRequest on the
+//!   velocity:player_info channel with the player's information, signed
+//!   with the secret shared by the proxy and its backends, see
+//!   velocity_response().
+//!
+//! Both need the backend to be reachable and the token or secret to be
+//! known, i.e. you must be authorized to bypass the proxy. Set
+//! ConnectOptions::forwarding to have the Client do this when connecting
+//! with connect_unauthenticated_with().
+//!
+//! # Examples
+//!
+//! ```rust,no_run
+//! use ozelot::{Client, ConnectOptions};
+//! use ozelot::forwarding::{ForwardedPlayer, Forwarding, ForwardingMode};
+//! use ozelot::uuid::Uuid;
+//!
+//! let player = ForwardedPlayer {
+//!     address: "127.0.0.1".parse().unwrap(),
+//!     uuid: Uuid::offline("Diagnostics").unwrap(),
+//!     username: "Diagnostics".to_string(),
+//!     properties: Vec::new(),
+//! };
+//! let mut options = ConnectOptions::default();
+//! options.forwarding = Some(Forwarding {
+//!     player,
+//!     mode: ForwardingMode::Velocity { secret: b"the secret".to_vec() },
+//! });
+//! let client = Client::connect_unauthenticated_with("backend.internal",
+//!                                                   25566,
+//!                                                   "Diagnostics",
+//!                                                   &options).unwrap();
+//! ```
+
+use clientbound::LoginPluginRequest;
+use errors::Result;
+use serverbound::{self, ServerboundPacket};
+use uuid::Uuid;
+use write::{write_String, write_bool, write_u128, write_varint};
+
+use openssl::hash::MessageDigest;
+use openssl::pkey::PKey;
+use openssl::sign::Signer;
+use serde_json;
+
+use std::net::IpAddr;
+
+/// The plugin channel of Velocity's modern forwarding
+pub const VELOCITY_CHANNEL: &str = "velocity:player_info";
+/// The version of Velocity's modern forwarding that is supported
+pub const VELOCITY_VERSION: i32 = 1;
+/// The name of the property holding the BungeeGuard token
+pub const BUNGEEGUARD_PROPERTY: &str = "bungeeguard-token";
+
+/// A property of a player's profile, e.g. the textures
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Property {
+    pub name: String,
+    pub value: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub signature: Option<String>,
+}
+
+/// The player information forwarded to the backend
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ForwardedPlayer {
+    /// The address the player connects from
+    pub address: IpAddr,
+    pub uuid: Uuid,
+    pub username: String,
+    pub properties: Vec<Property>,
+}
+
+/// How the player information is forwarded
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ForwardingMode {
+    /// BungeeCord's legacy forwarding
+    Legacy,
+    /// BungeeCord's legacy forwarding, with the token of the BungeeGuard
+    /// plugin
+    BungeeGuard { token: String },
+    /// Velocity's modern forwarding, with the forwarding secret
+    Velocity { secret: Vec<u8> },
+}
+
+/// Forwarding of the given player, see ConnectOptions::forwarding
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Forwarding {
+    pub player: ForwardedPlayer,
+    pub mode: ForwardingMode,
+}
+impl Forwarding {
+    /// Get the host to send in the Handshake
+    pub fn handshake_host(&self, host: &str) -> Result<String> {
+        match self.mode {
+            ForwardingMode::Legacy => bungee_host(host, &self.player),
+            ForwardingMode::BungeeGuard { ref token } => {
+                let mut player = self.player.clone();
+                player.properties.push(Property {
+                                           name: BUNGEEGUARD_PROPERTY.to_string(),
+                                           value: token.clone(),
+                                           signature: None,
+                                       });
+                bungee_host(host, &player)
+            },
+            ForwardingMode::Velocity { .. } => Ok(host.to_string()),
+        }
+    }
+
+    /// Answer a LoginPluginRequest, with the player information if it's
+    /// for Velocity's modern forwarding, and as not understood otherwise
+    pub fn answer(&self, request: &LoginPluginRequest) -> Result<ServerboundPacket> {
+        match self.mode {
+            ForwardingMode::Velocity { ref secret } => {
+                velocity_response(secret, request, &self.player)
+            },
+            _ => Ok(not_understood(request)),
+        }
+    }
+}
+
+/// Get the host field of a Handshake with BungeeCord's legacy forwarding
+pub fn bungee_host(host: &str, player: &ForwardedPlayer) -> Result<String> {
+    Ok(format!("{}\0{}\0{}\0{}",
+               host,
+               player.address,
+               player.uuid.to_simple(),
+               serde_json::to_string(&player.properties)?))
+}
+
+/// Answer a velocity:player_info LoginPluginRequest, signing the player
+/// information with the given secret. Requests on other channels are
+/// answered as not understood, like the vanilla client does.
+pub fn velocity_response(secret: &[u8],
+                         request: &LoginPluginRequest,
+                         player: &ForwardedPlayer)
+                         -> Result<ServerboundPacket> {
+    if request.get_identifier() != VELOCITY_CHANNEL {
+        return Ok(not_understood(request));
+    }
+    /* The request may contain the highest version the proxy supports */
+    if let Some(&max) = request.get_data().first() {
+        if (max as i32) < VELOCITY_VERSION {
+            bail!("The backend only supports forwarding version {}", max);
+        }
+    }
+
+    let mut info = Vec::new();
+    write_varint(&VELOCITY_VERSION, &mut info)?;
+    write_String(&player.address.to_string(), &mut info)?;
+    write_u128(&player.uuid.as_u128(), &mut info)?;
+    write_String(&player.username, &mut info)?;
+    write_varint(&(player.properties.len() as i32), &mut info)?;
+    for property in &player.properties {
+        write_String(&property.name, &mut info)?;
+        write_String(&property.value, &mut info)?;
+        write_bool(&property.signature.is_some(), &mut info)?;
+        if let Some(ref signature) = property.signature {
+            write_String(signature, &mut info)?;
+        }
+    }
+
+    let key = PKey::hmac(secret)?;
+    let mut signer = Signer::new(MessageDigest::sha256(), &key)?;
+    signer.update(&info)?;
+    let mut data = signer.sign_to_vec()?;
+    data.extend(info);
+    Ok(serverbound::LoginPluginResponse::new(*request.get_id(), true, data))
+}
+
+/// Answer a LoginPluginRequest as not understood, like the vanilla client
+pub fn not_understood(request: &LoginPluginRequest) -> ServerboundPacket {
+    serverbound::LoginPluginResponse::new(*request.get_id(), false, Vec::new())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use clientbound::ClientboundPacket;
+    use read::{read_String, read_u128, read_varint};
+    use std::io::Cursor;
+
+    fn request(identifier: &str) -> LoginPluginRequest {
+        match LoginPluginRequest::new(7, identifier.to_string(), Vec::new()) {
+            ClientboundPacket::LoginPluginRequest(x) => x,
+            _ => unreachable!(),
+        }
+    }
+
+    #[test]
+    fn forwarding() {
+        let player = ForwardedPlayer {
+            address: "10.0.0.2".parse().unwrap(),
+            uuid: Uuid(1),
+            username: "Bob".to_string(),
+            properties: Vec::new(),
+        };
+        let mut forwarding = Forwarding {
+            player,
+            mode: ForwardingMode::BungeeGuard { token: "abc".to_string() },
+        };
+        assert_eq!(forwarding.handshake_host("backend").unwrap(),
+                   "backend\u{0}10.0.0.2\u{0}00000000000000000000000000000001\u{0}\
+                    [{\"name\":\"bungeeguard-token\",\"value\":\"abc\"}]");
+
+        forwarding.mode = ForwardingMode::Velocity { secret: b"secret".to_vec() };
+        assert_eq!(forwarding.handshake_host("backend").unwrap(), "backend");
+        let data = match forwarding.answer(&request(VELOCITY_CHANNEL)).unwrap() {
+            ServerboundPacket::LoginPluginResponse(ref p) => {
+                assert!(*p.get_successful());
+                assert_eq!(*p.get_message_id(), 7);
+                p.get_data().clone()
+            },
+            _ => unreachable!(),
+        };
+        let key = PKey::hmac(b"secret").unwrap();
+        let mut signer = Signer::new(MessageDigest::sha256(), &key).unwrap();
+        signer.update(&data[32..]).unwrap();
+        assert_eq!(&data[..32], &signer.sign_to_vec().unwrap()[..]);
+        let mut r = Cursor::new(&data[32..]);
+        assert_eq!(read_varint(&mut r).unwrap(), VELOCITY_VERSION);
+        assert_eq!(read_String(&mut r).unwrap(), "10.0.0.2");
+        assert_eq!(read_u128(&mut r).unwrap(), 1);
+        assert_eq!(read_String(&mut r).unwrap(), "Bob");
+
+        assert_eq!(forwarding.answer(&request("other:channel")).unwrap(),
+                   serverbound::LoginPluginResponse::new(7, false, Vec::new()));
+    }
+}
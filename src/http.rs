@@ -0,0 +1,837 @@
+//! The HTTP client used for the Mojang and Microsoft APIs
+//!
+//! All requests made by the mojang and msa modules go through perform() in
+//! this module. Interceptors added with add_interceptor() see every request
+//! before it's sent and every response before it's parsed, so they can add
+//! headers (e.g. an API key for a mirror), record metrics, or log the
+//! traffic for debugging.
+//!
+//! Every request identifies ozelot in its User-Agent header, as Mojang asks
+//! API consumers to do. Set your own with set_user_agent(), or for some
+//! requests only with with_user_agent(). A User-Agent header already on a
+//! Request is left as is.
+//!
+//! Large files, e.g. textures and assets, can be streamed to disk with
+//! download(), which reports the progress as it goes.
+//!
+//! Requests are sent with libcurl by default. Programs that already use
+//! another HTTP client, or want to stub out the network in tests, can send
+//! them with it instead by implementing Backend and calling set_backend().
+//! Downloads go through the backend too, and are only streamed to disk if
+//! it implements Backend::download().
+//!
+//! # Examples
+//!
+//! ```rust,no_run
+//! use ozelot::errors::Result;
+//! use ozelot::http::{self, Interceptor, Request, Response};
+//! use std::sync::Arc;
+//! use std::time::Duration;
+//!
+//! struct Logger;
+//! impl Interceptor for Logger {
+//!     fn after_response(&self,
+//!                       request: &Request,
+//!                       response: &mut Response,
+//!                       elapsed: Duration)
+//!                       -> Result<()> {
+//!         println!("{} {} in {:?}", response.status, request.url, elapsed);
+//!         Ok(())
+//!     }
+//! }
+//!
+//! http::add_interceptor(Arc::new(Logger));
+//! ```
+
+use errors::Result;
+
+use curl::easy::{Easy, List};
+use openssl::base64;
+use openssl::hash::{self, MessageDigest};
+use serde_json;
+
+use std::cell::{Cell, RefCell};
+use std::fs;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::str;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+/// The method of a Request
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Method {
+    Get,
+    Post,
+    Put,
+    Delete,
+}
+
+/// An HTTP request
+#[derive(Debug, Clone, PartialEq)]
+pub struct Request {
+    pub method: Method,
+    pub url: String,
+    /// The headers as (name, value)
+    pub headers: Vec<(String, String)>,
+    pub body: Option<Vec<u8>>,
+}
+impl Request {
+    /// Create a request with the given method and body
+    pub fn new(method: Method, url: &str, body: Option<Vec<u8>>) -> Self {
+        Request {
+            method,
+            url: url.to_string(),
+            headers: Vec::new(),
+            body,
+        }
+    }
+
+    /// Create a GET request
+    pub fn get(url: &str) -> Self {
+        Request::new(Method::Get, url, None)
+    }
+
+    /// Create a POST request with the given body
+    pub fn post(url: &str, body: &str) -> Self {
+        Request::new(Method::Post, url, Some(body.as_bytes().to_vec()))
+    }
+
+    /// Create a DELETE request
+    pub fn delete(url: &str) -> Self {
+        Request::new(Method::Delete, url, None)
+    }
+
+    /// Add a header
+    pub fn header(mut self, name: &str, value: &str) -> Self {
+        self.headers.push((name.to_string(), value.to_string()));
+        self
+    }
+
+    /// Get the value of the header with the given name, ignoring case
+    pub fn get_header(&self, name: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|x| x.0.eq_ignore_ascii_case(name))
+            .map(|x| x.1.as_str())
+    }
+
+    /// Get the body as a string, if there is one and it's valid utf-8
+    pub fn body_str(&self) -> Option<&str> {
+        self.body.as_ref().and_then(|x| str::from_utf8(x).ok())
+    }
+}
+
+/// The response to a Request
+#[derive(Debug, Clone, PartialEq)]
+pub struct Response {
+    /// The HTTP status code
+    pub status: u32,
+    /// The headers as (name, value)
+    pub headers: Vec<(String, String)>,
+    pub body: Vec<u8>,
+}
+impl Response {
+    /// Get the body as a string, failing if it isn't valid utf-8
+    pub fn text(&self) -> Result<&str> {
+        Ok(str::from_utf8(&self.body)?)
+    }
+
+    /// Turn the response into its body as a string, failing if it isn't
+    /// valid utf-8
+    pub fn into_text(self) -> Result<String> {
+        Ok(String::from_utf8(self.body)?)
+    }
+
+    /// Get the value of the header with the given name, ignoring case
+    pub fn get_header(&self, name: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|x| x.0.eq_ignore_ascii_case(name))
+            .map(|x| x.1.as_str())
+    }
+}
+
+/// Sees all requests and responses, see the module documentation
+///
+/// Returning an error from either function fails the request with that
+/// error.
+pub trait Interceptor: Send + Sync {
+    /// Called before the request is sent, and may modify it
+    fn before_request(&self, _request: &mut Request) -> Result<()> {
+        Ok(())
+    }
+
+    /// Called after the response is received, and may modify it
+    fn after_response(&self,
+                      _request: &Request,
+                      _response: &mut Response,
+                      _elapsed: Duration)
+                      -> Result<()> {
+        Ok(())
+    }
+}
+
+static INTERCEPTORS: Mutex<Vec<Arc<dyn Interceptor>>> = Mutex::new(Vec::new());
+
+/// Sends requests over the network, see the module documentation
+pub trait Backend: Send + Sync {
+    /// Send the request and return the response. HTTP error statuses are
+    /// returned as a Response, not as an error.
+    fn send(&self, request: &Request) -> Result<Response>;
+
+    /// Download the response body into the sink, calling progress whenever
+    /// data has been written to it, and cancelling the download with an
+    /// error if it returns false, see download(). Redirects are followed,
+    /// and the bodies of error statuses aren't written to the sink. The body
+    /// of the returned Response is ignored.
+    ///
+    /// Defaults to sending the request with send(), and then writing the
+    /// whole body to the sink.
+    fn download(&self,
+                request: &Request,
+                sink: &mut dyn Write,
+                progress: &mut dyn FnMut(&Progress) -> bool)
+                -> Result<Response> {
+        let start = Instant::now();
+        let response = self.send(request)?;
+        if response.status >= 400 {
+            return Ok(response);
+        }
+        sink.write_all(&response.body)?;
+        let bytes = response.body.len() as u64;
+        let elapsed = start.elapsed().as_secs_f64();
+        let p = Progress {
+            bytes,
+            total: Some(bytes),
+            rate: if elapsed > 0.0 { bytes as f64 / elapsed } else { 0.0 },
+        };
+        if !progress(&p) {
+            bail!("Download of {} cancelled", request.url);
+        }
+        Ok(response)
+    }
+}
+
+/// The default Backend, sending requests with libcurl
+#[derive(Debug, Clone, Copy, Default)]
+pub struct CurlBackend;
+impl Backend for CurlBackend {
+    fn send(&self, request: &Request) -> Result<Response> {
+        let mut handle = Easy::new();
+        handle.url(&request.url)?;
+        let mut list = List::new();
+        for header in &request.headers {
+            list.append(&format!("{}: {}", header.0, header.1))?;
+        }
+        handle.http_headers(list)?;
+        match request.method {
+            Method::Get => (),
+            Method::Post => handle.post(true)?,
+            Method::Put => handle.custom_request("PUT")?,
+            Method::Delete => handle.custom_request("DELETE")?,
+        }
+        if let Some(ref body) = request.body {
+            handle.post_fields_copy(body)?;
+        } else if request.method == Method::Post {
+            handle.post_fields_copy(&[])?;
+        }
+        let mut headers = Vec::new();
+        let mut body = Vec::new();
+        {
+            let mut transfer = handle.transfer();
+            transfer
+                .header_function(|line| {
+                    let line = String::from_utf8_lossy(line);
+                    if line.starts_with("HTTP/") {
+                        headers.clear();
+                    } else if let Some(i) = line.find(':') {
+                        headers.push((line[..i].trim().to_string(),
+                                      line[i + 1..].trim().to_string()));
+                    }
+                    true
+                })?;
+            transfer
+                .write_function(|data| {
+                                    body.extend_from_slice(data);
+                                    Ok(data.len())
+                                })?;
+            transfer.perform()?;
+        }
+        Ok(Response {
+               status: handle.response_code()?,
+               headers,
+               body,
+           })
+    }
+
+    /* Streams the download to the sink instead of keeping it in memory */
+    fn download(&self,
+                request: &Request,
+                sink: &mut dyn Write,
+                progress: &mut dyn FnMut(&Progress) -> bool)
+                -> Result<Response> {
+        let mut handle = Easy::new();
+        handle.url(&request.url)?;
+        handle.follow_location(true)?;
+        let mut list = List::new();
+        for header in &request.headers {
+            list.append(&format!("{}: {}", header.0, header.1))?;
+        }
+        handle.http_headers(list)?;
+
+        let start = Instant::now();
+        /* Shared between the header and write callbacks */
+        let status = Cell::new(0);
+        let total = Cell::new(None);
+        let headers = RefCell::new(Vec::new());
+        let mut bytes = 0;
+        let mut cancelled = false;
+        let mut write_error = None;
+        let res = {
+            let mut transfer = handle.transfer();
+            transfer
+                .header_function(|line| {
+                    let line = String::from_utf8_lossy(line);
+                    if line.starts_with("HTTP/") {
+                        /* A new response, e.g. after a redirect */
+                        status.set(line.split_whitespace()
+                                       .nth(1)
+                                       .and_then(|x| x.parse().ok())
+                                       .unwrap_or(0));
+                        total.set(None);
+                        headers.borrow_mut().clear();
+                    } else if let Some(i) = line.find(':') {
+                        if line[..i].eq_ignore_ascii_case("content-length") {
+                            total.set(line[i + 1..].trim().parse().ok());
+                        }
+                        headers.borrow_mut().push((line[..i].trim().to_string(),
+                                                   line[i + 1..].trim().to_string()));
+                    }
+                    true
+                })?;
+            transfer
+                .write_function(|data| {
+                    if status.get() >= 400 {
+                        /* Don't write error pages to the sink */
+                        return Ok(data.len());
+                    }
+                    if let Err(e) = sink.write_all(data) {
+                        write_error = Some(e);
+                        return Ok(0);
+                    }
+                    bytes += data.len() as u64;
+                    let elapsed = start.elapsed().as_secs_f64();
+                    let rate = if elapsed > 0.0 { bytes as f64 / elapsed } else { 0.0 };
+                    let p = Progress {
+                        bytes,
+                        total: total.get(),
+                        rate,
+                    };
+                    if !progress(&p) {
+                        cancelled = true;
+                        return Ok(0);
+                    }
+                    Ok(data.len())
+                })?;
+            transfer.perform()
+        };
+        if cancelled {
+            bail!("Download of {} cancelled", request.url);
+        }
+        if let Some(e) = write_error {
+            return Err(e.into());
+        }
+        res?;
+        Ok(Response {
+               status: handle.response_code()?,
+               headers: headers.into_inner(),
+               body: Vec::new(),
+           })
+    }
+}
+
+static BACKEND: Mutex<Option<Arc<dyn Backend>>> = Mutex::new(None);
+/* Held by tests setting the backend, so they don't replace each other's */
+#[cfg(test)]
+pub(crate) static BACKEND_LOCK: Mutex<()> = Mutex::new(());
+
+/// Send all requests with the given backend from now on, or with libcurl
+/// again if None
+pub fn set_backend(backend: Option<Arc<dyn Backend>>) {
+    if let Ok(mut x) = BACKEND.lock() {
+        *x = backend;
+    }
+}
+
+/* The backend, cloned so that the lock isn't held during requests */
+fn backend() -> Arc<dyn Backend> {
+    match BACKEND.lock().ok().and_then(|x| x.clone()) {
+        Some(x) => x,
+        None => Arc::new(CurlBackend),
+    }
+}
+
+/// The User-Agent sent unless another one is set, with the crate version
+pub const DEFAULT_USER_AGENT: &str = concat!("ozelot/",
+                                             env!("CARGO_PKG_VERSION"),
+                                             " (+https://github.com/C4K3/ozelot)");
+
+static USER_AGENT: Mutex<Option<String>> = Mutex::new(None);
+
+thread_local! {
+    /* Set by with_user_agent(), takes precedence over USER_AGENT */
+    static SCOPED_USER_AGENT: RefCell<Option<String>> = const { RefCell::new(None) };
+}
+
+/// Add an interceptor, called after those added before it
+pub fn add_interceptor(interceptor: Arc<dyn Interceptor>) {
+    if let Ok(mut interceptors) = INTERCEPTORS.lock() {
+        interceptors.push(interceptor);
+    }
+}
+
+/// Remove a previously added interceptor
+pub fn remove_interceptor(interceptor: &Arc<dyn Interceptor>) {
+    if let Ok(mut interceptors) = INTERCEPTORS.lock() {
+        interceptors.retain(|x| !Arc::ptr_eq(x, interceptor));
+    }
+}
+
+/// Remove all interceptors
+pub fn clear_interceptors() {
+    if let Ok(mut interceptors) = INTERCEPTORS.lock() {
+        interceptors.clear();
+    }
+}
+
+/// Set the User-Agent of all requests, or with None go back to
+/// DEFAULT_USER_AGENT
+pub fn set_user_agent(user_agent: Option<&str>) {
+    if let Ok(mut x) = USER_AGENT.lock() {
+        *x = user_agent.map(|x| x.to_string());
+    }
+}
+
+/// Get the User-Agent requests made from this thread are sent with
+pub fn user_agent() -> String {
+    if let Some(x) = SCOPED_USER_AGENT.with(|x| x.borrow().clone()) {
+        return x;
+    }
+    USER_AGENT.lock()
+        .ok()
+        .and_then(|x| x.clone())
+        .unwrap_or_else(|| DEFAULT_USER_AGENT.to_string())
+}
+
+/// Call f, sending the requests it makes from this thread with the given
+/// User-Agent, e.g. to identify the requests of a single client
+pub fn with_user_agent<T, F: FnOnce() -> T>(user_agent: &str, f: F) -> T {
+    struct Restore(Option<String>);
+    impl Drop for Restore {
+        fn drop(&mut self) {
+            let old = self.0.take();
+            SCOPED_USER_AGENT.with(|x| *x.borrow_mut() = old);
+        }
+    }
+    let old = SCOPED_USER_AGENT.with(|x| x.replace(Some(user_agent.to_string())));
+    let _restore = Restore(old);
+    f()
+}
+
+/* Add the User-Agent header, unless the request already has one */
+fn set_user_agent_header(request: &mut Request) {
+    if request.get_header("User-Agent").is_none() {
+        request.headers.push(("User-Agent".to_string(), user_agent()));
+    }
+}
+
+/// Perform the request, passing it and the response through the
+/// interceptors
+///
+/// HTTP error statuses are returned as a Response, not as an error.
+pub fn perform(mut request: Request) -> Result<Response> {
+    set_user_agent_header(&mut request);
+    let interceptors = interceptors();
+    for interceptor in &interceptors {
+        interceptor.before_request(&mut request)?;
+    }
+    let start = Instant::now();
+    let mut response = backend().send(&request)?;
+    let elapsed = start.elapsed();
+    for interceptor in &interceptors {
+        interceptor.after_response(&request, &mut response, elapsed)?;
+    }
+    Ok(response)
+}
+
+/// The progress of a download
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Progress {
+    /// The amount of bytes downloaded so far
+    pub bytes: u64,
+    /// The size of the download, if known
+    pub total: Option<u64>,
+    /// The average download rate so far, in bytes per second
+    pub rate: f64,
+}
+
+/// Download the URL into the sink, without keeping it in memory, returning
+/// the amount of bytes downloaded
+///
+/// This is meant for large downloads, e.g. textures, client jars and
+/// assets. Redirects are followed. The progress callback is called whenever
+/// data has been received, return false from it to cancel the download, in
+/// which case an error is returned. The sink may have received part of the
+/// download if an error is returned.
+///
+/// The request passes through the interceptors like any other, the
+/// response is given to them with an empty body.
+pub fn download<W, F>(url: &str, sink: &mut W, mut progress: F) -> Result<u64>
+    where W: Write,
+          F: FnMut(&Progress) -> bool
+{
+    let interceptors = interceptors();
+    let mut request = Request::get(url);
+    set_user_agent_header(&mut request);
+    for interceptor in &interceptors {
+        interceptor.before_request(&mut request)?;
+    }
+
+    let start = Instant::now();
+    let mut sink = Counter {
+        inner: sink,
+        bytes: 0,
+    };
+    let mut response = backend().download(&request, &mut sink, &mut progress)?;
+    if response.status >= 400 {
+        bail!("HTTP {} downloading {}", response.status, url);
+    }
+    response.body.clear();
+    let elapsed = start.elapsed();
+    for interceptor in &interceptors {
+        interceptor.after_response(&request, &mut response, elapsed)?;
+    }
+    Ok(sink.bytes)
+}
+
+/* Counts the bytes written to the sink of a download */
+struct Counter<'a, W: 'a + Write> {
+    inner: &'a mut W,
+    bytes: u64,
+}
+impl<'a, W: Write> Write for Counter<'a, W> {
+    fn write(&mut self, data: &[u8]) -> io::Result<usize> {
+        let n = self.inner.write(data)?;
+        self.bytes += n as u64;
+        Ok(n)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+/// URL encode the given string, e.g. for a form body
+///
+/// Everything but letters, digits and -._~ is percent-encoded.
+pub fn url_encode(s: &str) -> String {
+    let mut ret = String::with_capacity(s.len());
+    for b in s.bytes() {
+        match b {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' => {
+                ret.push(b as char)
+            },
+            _ => ret.push_str(&format!("%{:02X}", b)),
+        }
+    }
+    ret
+}
+
+/* The interceptors, cloned so that the lock isn't held during requests */
+fn interceptors() -> Vec<Arc<dyn Interceptor>> {
+    match INTERCEPTORS.lock() {
+        Ok(x) => x.clone(),
+        Err(_) => Vec::new(),
+    }
+}
+
+/// A disk cache for GET requests, using conditional requests
+///
+/// Responses with an ETag or Last-Modified header are stored in the
+/// directory. Requesting the same URL again sends If-None-Match or
+/// If-Modified-Since, and if the server answers 304 Not Modified the stored
+/// response is returned instead, saving the download. Responses without
+/// either header are never stored.
+#[derive(Debug, Clone)]
+pub struct DiskCache {
+    dir: PathBuf,
+}
+
+#[derive(Serialize, Deserialize)]
+struct CacheEntry {
+    url: String,
+    etag: Option<String>,
+    last_modified: Option<String>,
+    headers: Vec<(String, String)>,
+    /* Base64 encoded, since the body may not be utf-8 */
+    body: String,
+}
+
+impl DiskCache {
+    /// Use the given directory for the cache, creating it if needed
+    pub fn open<P: AsRef<Path>>(dir: P) -> Result<Self> {
+        fs::create_dir_all(dir.as_ref())?;
+        Ok(DiskCache { dir: dir.as_ref().to_path_buf() })
+    }
+
+    /// Perform a GET request to the URL, using the cache if possible
+    pub fn get(&self, url: &str) -> Result<Response> {
+        let path = self.path(url);
+        let entry = self.load(&path, url);
+        let mut request = Request::get(url);
+        if let Some(ref entry) = entry {
+            if let Some(ref etag) = entry.etag {
+                request = request.header("If-None-Match", etag);
+            }
+            if let Some(ref last_modified) = entry.last_modified {
+                request = request.header("If-Modified-Since", last_modified);
+            }
+        }
+        let response = perform(request)?;
+        match entry {
+            Some(entry) if response.status == 304 => {
+                return Ok(Response {
+                              status: 200,
+                              headers: entry.headers,
+                              body: base64::decode_block(&entry.body)?,
+                          });
+            },
+            _ => (),
+        }
+        let etag = response.get_header("ETag").map(|x| x.to_string());
+        let last_modified = response.get_header("Last-Modified").map(|x| x.to_string());
+        if response.status == 200 && (etag.is_some() || last_modified.is_some()) {
+            let entry = CacheEntry {
+                url: url.to_string(),
+                etag,
+                last_modified,
+                headers: response.headers.clone(),
+                body: base64::encode_block(&response.body),
+            };
+            /* Write to a temporary file first so that readers never see a
+             * partial entry */
+            let tmp = path.with_extension("tmp");
+            fs::write(&tmp, serde_json::to_vec(&entry)?)?;
+            fs::rename(&tmp, &path)?;
+        }
+        Ok(response)
+    }
+
+    /// Remove everything from the cache
+    pub fn clear(&self) -> Result<()> {
+        for entry in fs::read_dir(&self.dir)? {
+            let path = entry?.path();
+            if path.extension().is_some_and(|x| x == "json") {
+                fs::remove_file(path)?;
+            }
+        }
+        Ok(())
+    }
+
+    fn path(&self, url: &str) -> PathBuf {
+        let digest = hash::hash(MessageDigest::sha1(), url.as_bytes())
+            .map(|x| x.iter().map(|b| format!("{:02x}", b)).collect::<String>())
+            .unwrap_or_else(|_| url.bytes().map(|b| format!("{:02x}", b)).collect());
+        self.dir.join(format!("{}.json", digest))
+    }
+
+    /* Load the entry, ignoring broken entries and hash collisions */
+    fn load(&self, path: &Path, url: &str) -> Option<CacheEntry> {
+        let data = fs::read(path).ok()?;
+        let entry: CacheEntry = serde_json::from_slice(&data).ok()?;
+        if entry.url == url { Some(entry) } else { None }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    struct Block;
+    impl Interceptor for Block {
+        fn before_request(&self, request: &mut Request) -> Result<()> {
+            if request.url.starts_with("https://blocked.invalid/") {
+                bail!("Blocked with key {:?}", request.get_header("x-api-key"));
+            }
+            Ok(())
+        }
+    }
+
+    struct AddKey;
+    impl Interceptor for AddKey {
+        fn before_request(&self, request: &mut Request) -> Result<()> {
+            request.headers.push(("X-Api-Key".to_string(), "secret".to_string()));
+            Ok(())
+        }
+    }
+
+    /* Serve a single HTTP response with the given body */
+    fn serve(body: Vec<u8>) -> String {
+        use std::io::Read;
+        use std::net::TcpListener;
+        use std::thread;
+
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/file", listener.local_addr().unwrap());
+        let _ = thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut request = [0; 1024];
+            let _: usize = stream.read(&mut request).unwrap();
+            let header = format!("HTTP/1.1 200 OK\r\nContent-Length: {}\r\n\r\n", body.len());
+            let _ = stream.write_all(header.as_bytes());
+            let _ = stream.write_all(&body);
+        });
+        url
+    }
+
+    #[test]
+    fn download_progress() {
+        let url = serve(vec![7; 100_000]);
+        let mut sink = Vec::new();
+        let mut last = None;
+        let bytes = download(&url, &mut sink, |p| {
+            last = Some(*p);
+            true
+        })
+                .unwrap();
+        assert_eq!(bytes, 100_000);
+        assert_eq!(sink, vec![7; 100_000]);
+        let last = last.unwrap();
+        assert_eq!(last.bytes, 100_000);
+        assert_eq!(last.total, Some(100_000));
+
+        let url = serve(vec![7; 100_000]);
+        let mut sink = Vec::new();
+        assert!(download(&url, &mut sink, |_| false).is_err());
+        assert!(sink.len() < 100_000);
+    }
+
+    #[test]
+    fn disk_cache() {
+        use std::io::Read;
+        use std::net::TcpListener;
+        use std::thread;
+
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/manifest", listener.local_addr().unwrap());
+        let server = thread::spawn(move || {
+            let mut requests = Vec::new();
+            /* The body isn't valid utf-8, like e.g. a texture */
+            let ok = b"HTTP/1.1 200 OK\r\nETag: \"abc\"\r\nContent-Length: 5\r\n\r\n\x89PNG\xff";
+            let not_modified = b"HTTP/1.1 304 Not Modified\r\nContent-Length: 0\r\n\r\n";
+            for response in &[&ok[..], &not_modified[..]] {
+                let (mut stream, _) = listener.accept().unwrap();
+                let mut request = [0; 1024];
+                let n = stream.read(&mut request).unwrap();
+                requests.push(String::from_utf8_lossy(&request[..n]).into_owned());
+                stream.write_all(response).unwrap();
+            }
+            requests
+        });
+
+        let dir = ::std::env::temp_dir().join(format!("ozelot-cache-test-{}", ::std::process::id()));
+        let cache = DiskCache::open(&dir).unwrap();
+        assert_eq!(cache.get(&url).unwrap().body, b"\x89PNG\xff");
+        let cached = cache.get(&url).unwrap();
+        assert_eq!(cached.status, 200);
+        assert_eq!(cached.body, b"\x89PNG\xff");
+        assert!(cached.text().is_err());
+        let requests = server.join().unwrap();
+        assert!(requests[1].contains("If-None-Match: \"abc\""));
+        cache.clear().unwrap();
+        fs::remove_dir(&dir).unwrap();
+    }
+
+    #[test]
+    fn user_agent_header() {
+        use std::io::Read;
+        use std::net::TcpListener;
+        use std::thread;
+
+        /* Responds with the request headers as the body */
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/", listener.local_addr().unwrap());
+        let _ = thread::spawn(move || for stream in listener.incoming().take(3) {
+            let mut stream = stream.unwrap();
+            let mut request = [0; 1024];
+            let n = stream.read(&mut request).unwrap();
+            let header = format!("HTTP/1.1 200 OK\r\nContent-Length: {}\r\n\r\n", n);
+            let _ = stream.write_all(header.as_bytes());
+            let _ = stream.write_all(&request[..n]);
+        });
+
+        let res = perform(Request::get(&url)).unwrap().into_text().unwrap();
+        assert!(res.contains(&format!("User-Agent: {}\r\n", user_agent())));
+        let res = with_user_agent("bot/1.0", || perform(Request::get(&url))).unwrap();
+        assert!(res.text().unwrap().contains("User-Agent: bot/1.0\r\n"));
+        assert_ne!(user_agent(), "bot/1.0");
+        let res = perform(Request::get(&url).header("User-Agent", "other")).unwrap();
+        let res = res.into_text().unwrap();
+        assert!(res.contains("User-Agent: other\r\n"));
+        assert_eq!(res.matches("User-Agent").count(), 1);
+    }
+
+    #[test]
+    fn interceptors() {
+        let add_key: Arc<dyn Interceptor> = Arc::new(AddKey);
+        let block: Arc<dyn Interceptor> = Arc::new(Block);
+        add_interceptor(add_key.clone());
+        add_interceptor(block.clone());
+        let err = perform(Request::get("https://blocked.invalid/profile")).unwrap_err();
+        remove_interceptor(&add_key);
+        remove_interceptor(&block);
+        assert_eq!(err.to_string(), "Blocked with key Some(\"secret\")");
+    }
+
+    /* Answers the requests to stub.invalid, and sends the rest with curl */
+    struct Stub;
+    impl Backend for Stub {
+        fn send(&self, request: &Request) -> Result<Response> {
+            if !request.url.starts_with("https://stub.invalid/") {
+                return CurlBackend.send(request);
+            }
+            Ok(Response {
+                   status: 200,
+                   headers: Vec::new(),
+                   body: format!("{} {:?}", request.url, request.get_header("User-Agent"))
+                       .into_bytes(),
+               })
+        }
+    }
+
+    #[test]
+    fn backend() {
+        let _lock = BACKEND_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+        set_backend(Some(Arc::new(Stub)));
+        let response = perform(Request::get("https://stub.invalid/a"));
+        let mut sink = Vec::new();
+        let mut calls = 0;
+        let downloaded = download("https://stub.invalid/b", &mut sink, |_| {
+            calls += 1;
+            true
+        });
+        set_backend(None);
+        assert_eq!(response.unwrap().into_text().unwrap(),
+                   format!("https://stub.invalid/a Some({:?})", user_agent()));
+        assert_eq!(downloaded.unwrap(), sink.len() as u64);
+        assert!(sink.starts_with(b"https://stub.invalid/b "));
+        assert_eq!(calls, 1);
+    }
+
+    #[test]
+    fn url_encoding() {
+        assert_eq!(url_encode("a-Z_0.~"), "a-Z_0.~");
+        assert_eq!(url_encode("a b&c=/é"), "a%20b%26c%3D%2F%C3%A9");
+    }
+}
@@ -0,0 +1,180 @@
+//! Conversions to and from the types of other Minecraft crates
+//!
+//! Projects moving to or from ozelot, or using it alongside other crates,
+//! often need to pass the same data between them. The Interop trait converts
+//! ozelot's types to and from their equivalents, which are enabled with a
+//! feature per crate:
+//!
+//! - azalea: ChatComponent and azalea_chat::FormattedText
+//! - valence: ChatComponent and Text, Uuid and uuid::Uuid,
+//!   ProfileProperties and Property, block positions and BlockPos, and
+//!   block::Hand and BlockFace and their counterparts Hand and Direction,
+//!   from valence_protocol
+//!
+//! Chat components are converted through their json, so formatting the
+//! other side doesn't support is lost.
+//!
+//! # Examples
+//!
+//! ```rust,ignore
+//! use ozelot::chat::ChatComponent;
+//! use ozelot::interop::Interop;
+//! use valence_protocol::Text;
+//!
+//! let chat = ChatComponent::parse(r#"{"text": "Hello", "color": "red"}"#).unwrap();
+//! let text: Text = chat.to_foreign().unwrap();
+//! assert_eq!(ChatComponent::from_foreign(&text).unwrap(), chat);
+//! ```
+
+use errors::Result;
+
+/// Conversion between an ozelot type and its equivalent T in another crate
+pub trait Interop<T>: Sized {
+    /// Convert from the other crate's type
+    fn from_foreign(x: &T) -> Result<Self>;
+    /// Convert to the other crate's type
+    fn to_foreign(&self) -> Result<T>;
+}
+
+#[cfg(feature = "azalea")]
+mod azalea {
+    use super::Interop;
+    use chat::ChatComponent;
+    use errors::Result;
+
+    use azalea_chat::FormattedText;
+    use serde_json;
+
+    impl Interop<FormattedText> for ChatComponent {
+        fn from_foreign(x: &FormattedText) -> Result<Self> {
+            ChatComponent::parse(&serde_json::to_string(x)?)
+        }
+
+        fn to_foreign(&self) -> Result<FormattedText> {
+            Ok(serde_json::from_str(&self.to_json())?)
+        }
+    }
+}
+
+#[cfg(feature = "valence")]
+mod valence {
+    use super::Interop;
+    use block::{BlockFace, Hand};
+    use chat::ChatComponent;
+    use errors::Result;
+    use json::ProfileProperties;
+    use uuid::Uuid;
+
+    use serde_json;
+    use valence_protocol::{self, BlockPos, Direction, Property, Text};
+
+    impl Interop<Text> for ChatComponent {
+        fn from_foreign(x: &Text) -> Result<Self> {
+            ChatComponent::parse(&serde_json::to_string(x)?)
+        }
+
+        fn to_foreign(&self) -> Result<Text> {
+            Ok(serde_json::from_str(&self.to_json())?)
+        }
+    }
+
+    impl Interop<valence_protocol::uuid::Uuid> for Uuid {
+        fn from_foreign(x: &valence_protocol::uuid::Uuid) -> Result<Self> {
+            Ok(Uuid(x.as_u128()))
+        }
+
+        fn to_foreign(&self) -> Result<valence_protocol::uuid::Uuid> {
+            Ok(valence_protocol::uuid::Uuid::from_u128(self.0))
+        }
+    }
+
+    impl Interop<Property> for ProfileProperties {
+        fn from_foreign(x: &Property) -> Result<Self> {
+            Ok(ProfileProperties {
+                   name: x.name.clone(),
+                   value: x.value.clone(),
+                   signature: x.signature.clone(),
+               })
+        }
+
+        fn to_foreign(&self) -> Result<Property> {
+            Ok(Property {
+                   name: self.name.clone(),
+                   value: self.value.clone(),
+                   signature: self.signature.clone(),
+               })
+        }
+    }
+
+    /// Block positions, as used by e.g. client.dig_block()
+    impl Interop<BlockPos> for (i32, i32, i32) {
+        fn from_foreign(x: &BlockPos) -> Result<Self> {
+            Ok((x.x, x.y, x.z))
+        }
+
+        fn to_foreign(&self) -> Result<BlockPos> {
+            Ok(BlockPos::new(self.0, self.1, self.2))
+        }
+    }
+
+    impl Interop<valence_protocol::Hand> for Hand {
+        fn from_foreign(x: &valence_protocol::Hand) -> Result<Self> {
+            Ok(match *x {
+                   valence_protocol::Hand::Main => Hand::Main,
+                   valence_protocol::Hand::Off => Hand::Off,
+               })
+        }
+
+        fn to_foreign(&self) -> Result<valence_protocol::Hand> {
+            Ok(match *self {
+                   Hand::Main => valence_protocol::Hand::Main,
+                   Hand::Off => valence_protocol::Hand::Off,
+               })
+        }
+    }
+
+    impl Interop<Direction> for BlockFace {
+        fn from_foreign(x: &Direction) -> Result<Self> {
+            Ok(match *x {
+                   Direction::Down => BlockFace::Bottom,
+                   Direction::Up => BlockFace::Top,
+                   Direction::North => BlockFace::North,
+                   Direction::South => BlockFace::South,
+                   Direction::West => BlockFace::West,
+                   Direction::East => BlockFace::East,
+               })
+        }
+
+        fn to_foreign(&self) -> Result<Direction> {
+            Ok(match *self {
+                   BlockFace::Bottom => Direction::Down,
+                   BlockFace::Top => Direction::Up,
+                   BlockFace::North => Direction::North,
+                   BlockFace::South => Direction::South,
+                   BlockFace::West => Direction::West,
+                   BlockFace::East => Direction::East,
+               })
+        }
+    }
+
+    #[cfg(test)]
+    mod test {
+        use super::*;
+
+        #[test]
+        fn convert() {
+            let chat = ChatComponent::parse(r#"{"text": "Hello ", "color": "red",
+                "extra": [{"text": "world", "bold": true}]}"#).unwrap();
+            let text: Text = chat.to_foreign().unwrap();
+            assert_eq!(ChatComponent::from_foreign(&text).unwrap(), chat);
+
+            let uuid: Uuid = "069a79f4-44e9-4726-a5be-fca90e38aaf5".parse().unwrap();
+            let foreign: valence_protocol::uuid::Uuid = uuid.to_foreign().unwrap();
+            assert_eq!(foreign.to_string(), uuid.to_string());
+
+            let pos: BlockPos = (1, -2, 3).to_foreign().unwrap();
+            assert_eq!(pos, BlockPos::new(1, -2, 3));
+            assert_eq!(BlockFace::from_foreign(&Direction::Up).unwrap(), BlockFace::Top);
+        }
+    }
+}
@@ -1,8 +1,40 @@
 //! This module contains json serializable structs for use for interaction with
 //! the various Mojang APIs.
+//!
+//! Every model implements JsonModel, so responses can be saved, e.g. to a
+//! cache on disk, and parsed again later without repeating the request.
+
+use errors;
+use utils;
+
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use serde::de::DeserializeOwned;
+use serde_json::{self, Map, Value};
+
+use std::collections::BTreeMap;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+#[cfg(feature = "chrono")]
+use chrono::{DateTime, Utc};
+
+/// Converting a model to and from json as given by the API
+pub trait JsonModel: Serialize + DeserializeOwned {
+    /// Parse the model from json, e.g. a response saved earlier
+    fn from_json_str(json: &str) -> errors::Result<Self> {
+        Ok(serde_json::from_str(json)?)
+    }
+
+    /// Serialize the model to json in the form the API gives it
+    fn to_json_string(&self) -> errors::Result<String> {
+        Ok(serde_json::to_string(self)?)
+    }
+}
 
 /// Contains the status about each of the Mojang APIs
-#[derive(Debug, Deserialize, Clone)]
+///
+/// Note that APIStatus reshapes the response before parsing it, so this is
+/// serialized as an object of service to status, not as the raw response.
+#[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct APIStatusResponse {
     #[serde(rename="minecraft.net")]
     pub minecraftnet: String,
@@ -29,55 +61,169 @@ pub struct APIStatusResponse {
 /// Represents a single username - UUID mapping.
 ///
 /// This struct is used in both PlayernamesToUUIDs and NameToUUID.
-#[derive(Debug, Deserialize, Clone)]
+#[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct NameUUID {
     /// The uuid in hex without dashes
     pub id: String,
     /// Name of the player at the present point in time
     pub name: String,
-    #[serde(default="always_false")]
+    #[serde(default="always_false", skip_serializing_if="is_false")]
     pub legacy: bool,
-    #[serde(default="always_false")]
+    #[serde(default="always_false", skip_serializing_if="is_false")]
     pub demo: bool,
 }
 
 /// Represents a player Profile, as returned in a UUIDToProfile lookup
-#[derive(Debug, Deserialize, Clone)]
+#[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct Profile {
     pub id: String,
     pub name: String,
     pub properties: Vec<ProfileProperties>,
+    /// Actions the player must take, e.g. "FORCED_NAME_CHANGE" or
+    /// "USING_BANNED_SKIN"
+    #[serde(default)]
+    pub profileActions: Vec<String>,
+    /// Any fields not known to ozelot, kept so they aren't lost when the
+    /// profile is serialized again
+    #[serde(flatten)]
+    pub extra: Map<String, Value>,
 }
 /// Represents the properties part of a Profile response
 ///
 /// Used in the UUIDToProfile and SessionHasJoined requests.
-#[derive(Debug, Deserialize, Clone)]
+#[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct ProfileProperties {
     pub name: String,
     pub value: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub signature: Option<String>,
 }
 
+/// The skin and cape of a player, as given base64 encoded in the textures
+/// property of their profile, see Profile::textures()
+#[derive(Debug, Serialize, Deserialize, Clone, Default, PartialEq)]
+pub struct Textures {
+    #[serde(default, rename = "SKIN", skip_serializing_if = "Option::is_none")]
+    pub skin: Option<Texture>,
+    #[serde(default, rename = "CAPE", skip_serializing_if = "Option::is_none")]
+    pub cape: Option<Texture>,
+}
+
+/// A texture in Textures
+#[derive(Debug, Serialize, Deserialize, Clone, Default, PartialEq)]
+pub struct Texture {
+    pub url: String,
+    /// E.g. "model": "slim" for skins with slim arms
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub metadata: BTreeMap<String, String>,
+}
+impl Texture {
+    /// Whether this is a skin with slim (3 pixel wide) arms
+    pub fn is_slim(&self) -> bool {
+        self.metadata.get("model").map(|x| x.as_str()) == Some("slim")
+    }
+}
+
+/// The settings and privileges of an account, as returned by
+/// msa::player_attributes()
+#[derive(Debug, Serialize, Deserialize, Clone, Default, PartialEq)]
+pub struct PlayerAttributes {
+    /// The privileges of the account by name, e.g. "onlineChat",
+    /// "multiplayerServer", "multiplayerRealms" and "telemetry"
+    #[serde(default)]
+    pub privileges: BTreeMap<String, Privilege>,
+    #[serde(default)]
+    pub profanityFilterPreferences: ProfanityFilterPreferences,
+    /// Any fields not known to ozelot, e.g. banStatus, kept so they aren't
+    /// lost when the attributes are serialized again
+    #[serde(flatten)]
+    pub extra: Map<String, Value>,
+}
+impl PlayerAttributes {
+    /// Whether the account has the privilege with the given name. Unknown
+    /// privileges are treated as not granted.
+    pub fn has_privilege(&self, name: &str) -> bool {
+        self.privileges.get(name).is_some_and(|x| x.enabled)
+    }
+
+    /// Whether the player wants chat filtered for profanity
+    pub fn profanity_filter(&self) -> bool {
+        self.profanityFilterPreferences.profanityFilterOn
+    }
+}
+
+/// A privilege in PlayerAttributes
+#[derive(Debug, Serialize, Deserialize, Clone, Default, PartialEq)]
+pub struct Privilege {
+    #[serde(default)]
+    pub enabled: bool,
+    /// Any fields not known to ozelot
+    #[serde(flatten)]
+    pub extra: Map<String, Value>,
+}
+
+/// The profanity filter preferences in PlayerAttributes
+#[derive(Debug, Serialize, Deserialize, Clone, Default, PartialEq)]
+pub struct ProfanityFilterPreferences {
+    #[serde(default)]
+    pub profanityFilterOn: bool,
+    /// Any fields not known to ozelot
+    #[serde(flatten)]
+    pub extra: Map<String, Value>,
+}
+
 /// The requested statistics
-#[derive(Debug, Deserialize, Clone)]
+#[derive(Debug, Serialize, Deserialize, Clone)]
 #[allow(non_snake_case)]
 pub struct StatisticsResponse {
+    #[serde(default)]
     pub total: u64,
+    #[serde(default)]
     pub last24h: u64,
+    #[serde(default)]
     pub saleVelocityPerSeconds: f64,
+    /// Any fields not known to ozelot
+    #[serde(flatten)]
+    pub extra: Map<String, Value>,
 }
 
 /// Represents a single historic name for a given account. Used in the
 /// UUIDToHistory request.
-#[derive(Debug, Deserialize, Clone)]
+#[derive(Debug, Serialize, Deserialize, Clone)]
 #[allow(non_snake_case)]
 pub struct NameHistory {
     pub name: String,
-    pub changedToAt: Option<u64>,
+    /// When the account changed to this name, None for the original name
+    #[serde(default,
+            deserialize_with = "deserialize_epoch_millis",
+            serialize_with = "serialize_epoch_millis",
+            skip_serializing_if = "Option::is_none")]
+    pub changedToAt: Option<SystemTime>,
+}
+#[cfg(feature = "chrono")]
+impl NameHistory {
+    /// Get changedToAt as a chrono DateTime
+    pub fn changed_to_at_utc(&self) -> Option<DateTime<Utc>> {
+        self.changedToAt.map(DateTime::from)
+    }
+}
+
+/* The API gives times as milliseconds since the epoch */
+fn deserialize_epoch_millis<'de, D>(d: D) -> Result<Option<SystemTime>, D::Error>
+    where D: Deserializer<'de>
+{
+    Ok(Option::<u64>::deserialize(d)?.map(utils::from_epoch_millis))
+}
+
+fn serialize_epoch_millis<S>(time: &Option<SystemTime>, s: S) -> Result<S::Ok, S::Error>
+    where S: Serializer
+{
+    time.map(|x| x.duration_since(UNIX_EPOCH).unwrap_or_default().as_millis() as u64)
+        .serialize(s)
 }
 
 /// Represents a response to a successful authentication
-#[derive(Debug, Deserialize, Clone)]
+#[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct AuthenticationResponse {
     pub accessToken: String,
     pub clientToken: Option<String>,
@@ -86,19 +232,66 @@ pub struct AuthenticationResponse {
 }
 
 /// Response about whether the client has posted a join to Mojang
-#[derive(Debug, Deserialize, Clone)]
+#[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct SessionHasJoinedResponse {
     /// The uuid
-    id: String,
-    name: String,
-    properties: Vec<ProfileProperties>,
+    pub id: String,
+    pub name: String,
+    pub properties: Vec<ProfileProperties>,
+    /// Actions the player must take, see Profile::profileActions
+    #[serde(default)]
+    pub profileActions: Vec<String>,
+    /// Any fields not known to ozelot
+    #[serde(flatten)]
+    pub extra: Map<String, Value>,
 }
 
+/// A security question set on an account, as given by SecurityChallenges
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct SecurityChallenge {
+    /// The ID to answer the question with, see SecurityAnswer
+    pub answer: SecurityAnswerId,
+    pub question: SecurityQuestion,
+}
 
+/// The ID of the answer to a security question
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq)]
+pub struct SecurityAnswerId {
+    pub id: i64,
+}
 
+/// A security question, e.g. "What is your favorite pet's name?"
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct SecurityQuestion {
+    pub id: i64,
+    pub question: String,
+}
 
+impl JsonModel for APIStatusResponse {}
+impl JsonModel for NameUUID {}
+impl JsonModel for Profile {}
+impl JsonModel for ProfileProperties {}
+impl JsonModel for Textures {}
+impl JsonModel for Texture {}
+impl JsonModel for PlayerAttributes {}
+impl JsonModel for Privilege {}
+impl JsonModel for ProfanityFilterPreferences {}
+impl JsonModel for StatisticsResponse {}
+impl JsonModel for NameHistory {}
+impl JsonModel for AuthenticationResponse {}
+impl JsonModel for SessionHasJoinedResponse {}
+impl JsonModel for SecurityChallenge {}
+impl JsonModel for SecurityAnswerId {}
+impl JsonModel for SecurityQuestion {}
+/* Responses that are lists, e.g. of UUIDToHistory and PlayernamesToUUIDs */
+impl<T: JsonModel> JsonModel for Vec<T> {}
 
 /// For use with Serde default values
 fn always_false() -> bool {
     false
 }
+
+/* For leaving out flags that are false, as the API does */
+fn is_false(x: &bool) -> bool {
+    !*x
+}
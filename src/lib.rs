@@ -10,7 +10,8 @@
 //! their complex fields serialized by this library, but instead handing the
 //! raw binary data to consumers of this library to parse however they wish.
 //! One example of this is packets that contain NBT data, the packets are read
-//! but the NBT data is not in any way parsed by this library. You'll probably
+//! but the NBT data is left for you to parse, e.g. with the nbt and slot
+//! modules. You'll probably
 //! want to see what the meanings of each of the packets are, which is
 //! documented on [wiki.vg](http://wiki.vg/Main_Page). The [protocol
 //! documentation](http://wiki.vg/Protocol) in particular is likely to be a
@@ -98,15 +99,34 @@
         unreachable_pub,
         )]
 
+#[cfg(feature = "azalea")]
+extern crate azalea_chat;
 extern crate byteorder;
+/* The code generated by pyo3 refers to ::core, which isn't in scope in the
+ * 2015 edition without this */
+#[cfg(feature = "python")]
+extern crate core;
+#[cfg(feature = "chrono")]
+extern crate chrono;
+#[cfg(feature = "inline-strings")]
+extern crate compact_str;
 extern crate curl;
 extern crate flate2;
+#[cfg(feature = "image")]
+extern crate image;
+#[cfg(feature = "mio")]
+extern crate mio;
 extern crate netbuf;
 extern crate openssl;
+#[cfg(feature = "python")]
+extern crate pyo3;
+extern crate serde;
 #[macro_use]
 extern crate serde_derive;
 #[macro_use]
 extern crate serde_json;
+#[cfg(feature = "valence")]
+extern crate valence_protocol;
 #[macro_use]
 extern crate error_chain;
 
@@ -115,29 +135,89 @@ mod connection;
 #[allow(non_snake_case)]
 mod json;
 mod server;
+pub mod accounts;
+pub mod audit;
+pub mod bedrock;
+pub mod block;
+pub mod broadcast;
+pub mod chat;
+pub mod chunk;
 pub mod clientbound;
+pub mod command;
+pub mod compat;
+pub mod consts;
+pub mod credentials;
+pub mod culling;
+pub mod custom;
+pub mod deferred;
+pub mod effect;
+pub mod entity;
 pub mod errors;
+pub mod experience;
+pub mod extensions;
+#[cfg(feature = "ffi")]
+pub mod ffi;
+pub mod font;
+pub mod forwarding;
+pub mod http;
+pub mod interop;
+pub mod lifecycle;
+pub mod listener;
+#[cfg(feature = "test-util")]
+pub mod mock;
 #[allow(non_snake_case)]
 pub mod mojang;
+pub mod movement;
+pub mod msa;
+pub mod nbt;
+pub mod placeholder;
+pub mod prepared;
+pub mod priority;
+pub mod profiles;
+pub mod protocol_data;
+#[cfg(feature = "python")]
+pub mod python;
 pub mod read;
+#[cfg(all(feature = "mio", unix))]
+pub mod readiness;
+pub mod recorder;
+pub mod scoreboard;
 pub mod serverbound;
+pub mod slot;
+pub mod spectator;
+pub mod stats;
+pub mod status;
+pub mod text;
+pub mod throttle;
+pub mod tick;
+pub mod trade;
+pub mod transition;
+pub mod transport;
 pub mod utils;
+pub mod uuid;
+pub mod vehicle;
+pub mod vhost;
+pub mod watchdog;
+pub mod window;
+pub mod world;
 pub mod write;
 #[cfg(test)]
 mod tests;
 
-pub use client::Client;
-pub use server::Server;
-pub use connection::Packet;
+pub use client::{Client, ClientEvent, ClientSettings, ConnectOptions, MAX_PAUSED_PACKETS,
+                 PausedClient};
+pub use server::{FallbackVerifier, LoginOptions, Server, SessionOutage};
+pub use connection::{Packet, ReadStatus};
+pub use mojang::{lookup_profile, lookup_uuid};
 
 use std::fmt;
 
 /// The protocol version supported by this version of ozelot
-pub const PROTOCOL_VERSION: i32 = 404;
+pub const PROTOCOL_VERSION: i32 = consts::PROTOCOL_1_13_2;
 
 /// This tracks which state of play the client is in. The value of this changes
 /// the meaning of the different packet ids.
-#[derive(Debug, Clone, PartialEq, Eq)]
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
 pub enum ClientState {
     Handshake,
     Status,
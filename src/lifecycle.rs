@@ -0,0 +1,169 @@
+//! Connection lifecycle events for supervisors
+//!
+//! Code watching over many connections, e.g. to restart bots or show a
+//! dashboard, usually doesn't care about the packets, only whether each
+//! connection is up and how far it has got. Give a Client or Server a
+//! channel with set_lifecycle_sender(), or all new connections one with
+//! set_default_sender(), and it sends a LifecycleRecord when it's
+//! connected, changes state, enables encryption or compression, and when
+//! it's closed. Sending never blocks, and a dropped receiver is ignored.
+//!
+//! # Examples
+//!
+//! ```rust,no_run
+//! use ozelot::Client;
+//! use ozelot::lifecycle::{self, LifecycleEvent};
+//! use std::sync::mpsc;
+//! use std::thread;
+//!
+//! let (tx, rx) = mpsc::channel();
+//! lifecycle::set_default_sender(Some(tx));
+//! for name in &["bot1", "bot2"] {
+//!     let name = name.to_string();
+//!     let _ = thread::spawn(move || {
+//!         let mut client = Client::connect_unauthenticated("localhost", 25565, &name).unwrap();
+//!         loop {
+//!             let _ = client.read().unwrap();
+//!         }
+//!     });
+//! }
+//! for record in rx {
+//!     if let LifecycleEvent::Closed { ref reason } = record.event {
+//!         println!("Connection {} closed: {}", record.connection, reason);
+//!     }
+//! }
+//! ```
+
+use ClientState;
+
+use std::net::SocketAddr;
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::mpsc::Sender;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// A change in the lifecycle of a connection
+#[derive(Debug, Clone, PartialEq)]
+pub enum LifecycleEvent {
+    /// The connection was established. Also sent first when a sender is set
+    /// on an existing connection, along with its current state.
+    Connected { state: ClientState },
+    StateChanged { from: ClientState, to: ClientState },
+    EncryptionEnabled,
+    CompressionEnabled { threshold: usize },
+    /// The connection was closed or dropped, or failed. This is the last
+    /// event of a connection.
+    Closed { reason: String },
+}
+
+/// A LifecycleEvent with the connection it happened on
+#[derive(Debug, Clone, PartialEq)]
+pub struct LifecycleRecord {
+    /// The ID of the connection, unique within the process, see
+    /// client.connection_id()
+    pub connection: u64,
+    /// The address of the peer, if known
+    pub peer: Option<SocketAddr>,
+    /// Milliseconds since the unix epoch
+    pub timestamp: u64,
+    pub event: LifecycleEvent,
+}
+
+static NEXT_ID: AtomicU64 = AtomicU64::new(1);
+static DEFAULT_SENDER: Mutex<Option<Sender<LifecycleRecord>>> = Mutex::new(None);
+
+/// Set the sender that connections created from now on send their events
+/// to, or None to not send them anywhere by default
+pub fn set_default_sender(sender: Option<Sender<LifecycleRecord>>) {
+    if let Ok(mut x) = DEFAULT_SENDER.lock() {
+        *x = sender;
+    }
+}
+
+/* The lifecycle state of a single connection */
+#[derive(Debug)]
+pub(crate) struct Lifecycle {
+    id: u64,
+    peer: Option<SocketAddr>,
+    sender: Option<Sender<LifecycleRecord>>,
+    closed: bool,
+}
+impl Lifecycle {
+    pub(crate) fn new(peer: Option<SocketAddr>, state: &ClientState) -> Self {
+        let sender = DEFAULT_SENDER.lock().ok().and_then(|x| x.clone());
+        let ret = Lifecycle {
+            id: NEXT_ID.fetch_add(1, Ordering::Relaxed),
+            peer,
+            sender,
+            closed: false,
+        };
+        ret.emit(LifecycleEvent::Connected { state: state.clone() });
+        ret
+    }
+
+    pub(crate) fn id(&self) -> u64 {
+        self.id
+    }
+
+    pub(crate) fn set_sender(&mut self,
+                             sender: Option<Sender<LifecycleRecord>>,
+                             state: &ClientState) {
+        self.sender = sender;
+        if !self.closed {
+            self.emit(LifecycleEvent::Connected { state: state.clone() });
+        }
+    }
+
+    pub(crate) fn emit(&self, event: LifecycleEvent) {
+        if let Some(ref sender) = self.sender {
+            let timestamp = SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map(|d| d.as_millis() as u64)
+                .unwrap_or(0);
+            let _ = sender.send(LifecycleRecord {
+                                    connection: self.id,
+                                    peer: self.peer,
+                                    timestamp,
+                                    event,
+                                });
+        }
+    }
+
+    /* Send the Closed event, unless it has already been sent */
+    pub(crate) fn closed(&mut self, reason: &str) {
+        if !self.closed {
+            self.closed = true;
+            self.emit(LifecycleEvent::Closed { reason: reason.to_string() });
+        }
+    }
+}
+impl Drop for Lifecycle {
+    fn drop(&mut self) {
+        self.closed("Dropped");
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::sync::mpsc;
+
+    #[test]
+    fn events() {
+        let (tx, rx) = mpsc::channel();
+        let mut lifecycle = Lifecycle::new(None, &ClientState::Handshake);
+        lifecycle.set_sender(Some(tx), &ClientState::Login);
+        lifecycle.emit(LifecycleEvent::CompressionEnabled { threshold: 256 });
+        lifecycle.closed("Read timeout");
+        let id = lifecycle.id();
+        drop(lifecycle);
+
+        let events: Vec<_> = rx.iter().collect();
+        assert!(events.iter().all(|x| x.connection == id));
+        let events: Vec<_> = events.into_iter().map(|x| x.event).collect();
+        assert_eq!(events,
+                   vec![LifecycleEvent::Connected { state: ClientState::Login },
+                        LifecycleEvent::CompressionEnabled { threshold: 256 },
+                        LifecycleEvent::Closed { reason: "Read timeout".to_string() }]);
+    }
+}
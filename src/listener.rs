@@ -0,0 +1,496 @@
+//! Accepting client connections on one or more addresses
+//!
+//! A ServerListener does the accept loop above the per connection Server:
+//! it binds the given addresses, accepts connections, and rejects those over
+//! the global or per IP connection limits, or from IPs connecting too often,
+//! before any packet is read. Accepted connections are handed out as
+//! ServerConnections, which free their slot when dropped.
+//!
+//! The listener also keeps track of which players are logged in, to handle
+//! a second login with the same UUID according to the DuplicateLogin
+//! policy, see conn.login(). By default the old session is kicked, like
+//! vanilla does. Login attempts can be throttled per IP and per account
+//! with the login_throttle option, see the throttle module.
+//!
+//! # Examples
+//!
+//! ```rust,no_run
+//! use ozelot::listener::{ListenerOptions, ServerListener};
+//! use std::thread;
+//!
+//! let mut options = ListenerOptions::default();
+//! options.max_per_ip = Some(3);
+//! let mut listener = ServerListener::bind(&["0.0.0.0:25565", "[::]:25565"],
+//!                                         options).unwrap();
+//! listener.run(|mut conn| {
+//!     let _ = thread::spawn(move || {
+//!         let packets = conn.read();
+//!         /* ... */
+//!     });
+//! }).unwrap();
+//! ```
+
+use {ClientState, Server};
+use clientbound::{self, ClientboundPacket};
+use audit::AuditEvent;
+use errors::Result;
+use serverbound::ServerboundPacket;
+use throttle::{LoginThrottle, THROTTLED_REASON, ThrottleLimits, ThrottleStore};
+use uuid::Uuid;
+
+use std::collections::{HashMap, VecDeque};
+use std::fmt;
+use std::io;
+use std::net::{IpAddr, SocketAddr, TcpListener, ToSocketAddrs};
+use std::ops::{Deref, DerefMut};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// Limits applied by a ServerListener
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct ListenerOptions {
+    /// The maximum amount of open connections
+    pub max_connections: Option<usize>,
+    /// The maximum amount of open connections from a single IP
+    pub max_per_ip: Option<usize>,
+    /// The maximum amount of new connections from a single IP within the
+    /// given time, e.g. (3, 4 seconds)
+    pub rate_limit: Option<(usize, Duration)>,
+    /// What to do when a player logs in while already logged in
+    pub duplicate_login: DuplicateLogin,
+    /// The limits of login attempts per IP and per account
+    pub login_throttle: ThrottleLimits,
+}
+
+/// What to do with a second login with the same UUID
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum DuplicateLogin {
+    /// Disconnect the old session, like vanilla
+    #[default]
+    KickOld,
+    /// Disconnect the new connection, like BungeeCord
+    RejectNew,
+    /// Let both sessions be logged in
+    Allow,
+}
+
+/// Decides what to do with a duplicate login, given the UUID and the
+/// username of the new login. See listener.set_duplicate_login_handler().
+pub type DuplicateLoginHandler = Arc<dyn Fn(&Uuid, &str) -> DuplicateLogin + Send + Sync>;
+
+/// The reason sent to a session replaced by a newer login
+pub const DUPLICATE_LOGIN_REASON: &str = r#"{"translate":"multiplayer.disconnect.duplicate_login"}"#;
+/// The reason sent to a login rejected because the player is already
+/// logged in
+pub const ALREADY_CONNECTED_REASON: &str = r#"{"text":"You are already connected to this server"}"#;
+
+/* The logged in players, shared with the ServerConnections */
+struct Sessions {
+    policy: DuplicateLogin,
+    handler: Option<DuplicateLoginHandler>,
+    throttle: LoginThrottle,
+    next_id: u64,
+    /* The session ID and the replaced flag of each logged in player */
+    players: HashMap<Uuid, (u64, Arc<AtomicBool>)>,
+}
+
+/* Open connections, shared with the ServerConnections to free their slots */
+#[derive(Debug, Default)]
+struct Slots {
+    total: usize,
+    per_ip: HashMap<IpAddr, usize>,
+}
+
+/// Accepts connections, see the module documentation
+#[derive(Debug)]
+pub struct ServerListener {
+    listeners: Vec<TcpListener>,
+    options: ListenerOptions,
+    slots: Arc<Mutex<Slots>>,
+    sessions: Arc<Mutex<Sessions>>,
+    /* When each IP last connected, for the rate limit */
+    recent: HashMap<IpAddr, VecDeque<Instant>>,
+}
+impl fmt::Debug for Sessions {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Sessions")
+            .field("policy", &self.policy)
+            .field("throttle", &self.throttle)
+            .field("players", &self.players.len())
+            .finish()
+    }
+}
+impl ServerListener {
+    /// Bind all of the given addresses
+    pub fn bind<A: ToSocketAddrs>(addrs: &[A], options: ListenerOptions) -> Result<Self> {
+        let mut listeners = Vec::new();
+        for addr in addrs {
+            let listener = TcpListener::bind(addr)?;
+            listener.set_nonblocking(true)?;
+            listeners.push(listener);
+        }
+        if listeners.is_empty() {
+            bail!("ServerListener needs at least one address");
+        }
+        let sessions = Sessions {
+            policy: options.duplicate_login,
+            handler: None,
+            throttle: LoginThrottle::new(options.login_throttle),
+            next_id: 0,
+            players: HashMap::new(),
+        };
+        Ok(ServerListener {
+            listeners,
+            options,
+            slots: Arc::new(Mutex::new(Slots::default())),
+            sessions: Arc::new(Mutex::new(sessions)),
+            recent: HashMap::new(),
+        })
+    }
+
+    /// Get the addresses listened on
+    pub fn local_addrs(&self) -> Result<Vec<SocketAddr>> {
+        let mut ret = Vec::new();
+        for listener in &self.listeners {
+            ret.push(listener.local_addr()?);
+        }
+        Ok(ret)
+    }
+
+    /* The listening sockets, for registering them in a poll loop */
+    #[cfg(all(feature = "mio", unix))]
+    pub(crate) fn listeners(&self) -> &[TcpListener] {
+        &self.listeners
+    }
+
+    /// Get the amount of open connections
+    pub fn connections(&self) -> usize {
+        self.slots.lock().map(|x| x.total).unwrap_or(0)
+    }
+
+    /// Get the amount of logged in players, see conn.login()
+    pub fn logged_in(&self) -> usize {
+        self.sessions.lock().map(|x| x.players.len()).unwrap_or(0)
+    }
+
+    /// Decide what to do with each duplicate login with the given function
+    /// instead of the duplicate_login option, or go back to the option if
+    /// None. The function is called on the thread calling conn.login().
+    pub fn set_duplicate_login_handler(&mut self, handler: Option<DuplicateLoginHandler>) {
+        if let Ok(mut sessions) = self.sessions.lock() {
+            sessions.handler = handler;
+        }
+    }
+
+    /// Count login attempts in the given store instead of in memory, e.g.
+    /// to share the counts between servers
+    pub fn set_throttle_store(&mut self, store: Arc<dyn ThrottleStore>) {
+        if let Ok(mut sessions) = self.sessions.lock() {
+            sessions.throttle = LoginThrottle::with_store(self.options.login_throttle, store);
+        }
+    }
+
+    /// Accept the connections waiting on any of the addresses, closing those
+    /// over the limits.
+    ///
+    /// Failing to accept a connection, e.g. because the process is out of
+    /// file descriptors, is printed to stderr and doesn't fail this, so
+    /// that it doesn't stop run(). The connections accepted before are
+    /// still returned.
+    ///
+    /// This function is nonblocking.
+    pub fn accept(&mut self) -> Result<Vec<ServerConnection>> {
+        let mut ret = Vec::new();
+        for i in 0..self.listeners.len() {
+            loop {
+                let (stream, addr) = match self.listeners[i].accept() {
+                    Ok(x) => x,
+                    Err(ref e) if e.kind() == io::ErrorKind::WouldBlock => break,
+                    Err(e) => {
+                        /* E.g. a connection reset before it was accepted, or
+                         * running out of file descriptors, which won't stop
+                         * until some connections are closed, so move on to
+                         * the next address instead of retrying */
+                        eprintln!("ozelot: failed to accept a connection: {}", e);
+                        break;
+                    },
+                };
+                let ip = addr.ip();
+                if !self.check_rate(ip) || !self.take_slot(ip) {
+                    /* Dropping the stream closes it */
+                    continue;
+                }
+                let slot = Slot {
+                    slots: self.slots.clone(),
+                    ip,
+                };
+                /* A connection failing to set up only affects itself */
+                if let Ok(server) = Server::from_tcpstream(stream) {
+                    ret.push(ServerConnection {
+                                 server,
+                                 _slot: slot,
+                                 sessions: self.sessions.clone(),
+                                 session: None,
+                                 replaced: Arc::new(AtomicBool::new(false)),
+                             });
+                }
+            }
+        }
+        Ok(ret)
+    }
+
+    /// Accept connections forever, calling f with each of them
+    pub fn run<F: FnMut(ServerConnection)>(&mut self, mut f: F) -> Result<()> {
+        loop {
+            let conns = self.accept()?;
+            if conns.is_empty() {
+                thread::sleep(Duration::from_millis(10));
+            }
+            for conn in conns {
+                f(conn);
+            }
+        }
+    }
+
+    /* Record a connection from the IP, and whether it's within the rate
+     * limit */
+    fn check_rate(&mut self, ip: IpAddr) -> bool {
+        let (max, window) = match self.options.rate_limit {
+            Some(x) => x,
+            None => return true,
+        };
+        let now = Instant::now();
+        self.recent.retain(|_, times| {
+            while times.front().is_some_and(|&x| now - x > window) {
+                let _: Option<Instant> = times.pop_front();
+            }
+            !times.is_empty()
+        });
+        let times = self.recent.entry(ip).or_default();
+        times.push_back(now);
+        times.len() <= max
+    }
+
+    /* Take a connection slot for the IP, if within the limits */
+    fn take_slot(&self, ip: IpAddr) -> bool {
+        let mut slots = match self.slots.lock() {
+            Ok(x) => x,
+            Err(_) => return false,
+        };
+        let per_ip = slots.per_ip.get(&ip).cloned().unwrap_or(0);
+        if self.options.max_connections.is_some_and(|x| slots.total >= x) ||
+           self.options.max_per_ip.is_some_and(|x| per_ip >= x) {
+            return false;
+        }
+        slots.total += 1;
+        let _: Option<usize> = slots.per_ip.insert(ip, per_ip + 1);
+        true
+    }
+}
+
+/* Frees a connection slot when dropped */
+#[derive(Debug)]
+struct Slot {
+    slots: Arc<Mutex<Slots>>,
+    ip: IpAddr,
+}
+impl Drop for Slot {
+    fn drop(&mut self) {
+        if let Ok(mut slots) = self.slots.lock() {
+            slots.total -= 1;
+            let remove = match slots.per_ip.get_mut(&self.ip) {
+                Some(x) => {
+                    *x -= 1;
+                    *x == 0
+                },
+                None => false,
+            };
+            if remove {
+                let _: Option<usize> = slots.per_ip.remove(&self.ip);
+            }
+        }
+    }
+}
+
+/// A connection accepted by a ServerListener. Derefs to the Server, and
+/// frees its slot in the listener's connection limits when dropped.
+pub struct ServerConnection {
+    server: Server,
+    _slot: Slot,
+    sessions: Arc<Mutex<Sessions>>,
+    /* The UUID and session ID, once logged in */
+    session: Option<(Uuid, u64)>,
+    replaced: Arc<AtomicBool>,
+}
+impl ServerConnection {
+    /// Log in the player with the given UUID, applying the duplicate login
+    /// policy. Call this before sending LoginSuccess.
+    ///
+    /// If the player is already logged in and the policy is to reject the
+    /// new login, this connection is disconnected and an error is returned.
+    /// If it's to kick the old session, that session is disconnected the
+    /// next time conn.read() is called on it.
+    pub fn login(&mut self, uuid: Uuid) -> Result<()> {
+        let username = self.username().unwrap_or("").to_string();
+        let decision = {
+            let sessions = self.sessions.lock().map_err(|_| "Poisoned session lock")?;
+            match sessions.players.get(&uuid) {
+                Some(&(id, _)) if Some((uuid, id)) == self.session => return Ok(()),
+                Some(_) => Some((sessions.policy, sessions.handler.clone())),
+                None => None,
+            }
+        };
+        /* The handler is called without the lock held */
+        let decision = decision.map(|(policy, handler)| match handler {
+                                        Some(handler) => handler(&uuid, &username),
+                                        None => policy,
+                                    });
+        if decision == Some(DuplicateLogin::RejectNew) {
+            self.disconnect(ALREADY_CONNECTED_REASON)?;
+            bail!("Rejected the duplicate login of {}", uuid);
+        }
+
+        let mut sessions = self.sessions.lock().map_err(|_| "Poisoned session lock")?;
+        let id = sessions.next_id;
+        sessions.next_id += 1;
+        let old = sessions.players.insert(uuid, (id, self.replaced.clone()));
+        match old {
+            Some((_, replaced)) if decision == Some(DuplicateLogin::KickOld) => {
+                replaced.store(true, Ordering::SeqCst);
+            },
+            /* Allowed duplicates stay logged in, but aren't tracked */
+            _ => (),
+        }
+        self.session = Some((uuid, id));
+        Ok(())
+    }
+
+    /// Whether this session has been replaced by a newer login of the same
+    /// player
+    pub fn is_replaced(&self) -> bool {
+        self.replaced.load(Ordering::SeqCst)
+    }
+
+    /// Like server.read(), but if this session has been replaced by a newer
+    /// login, the client is disconnected and an error is returned.
+    ///
+    /// Login attempts are also checked against the login throttle, and if
+    /// over the limit, the client is disconnected, a ThrottleRejected audit
+    /// event is recorded and an error is returned.
+    pub fn read(&mut self) -> Result<Vec<ServerboundPacket>> {
+        if self.is_replaced() {
+            self.disconnect(DUPLICATE_LOGIN_REASON)?;
+            bail!("The session was replaced by a newer login");
+        }
+        let packets = self.server.read()?;
+        let username = packets.iter()
+            .filter_map(|x| match *x {
+                            ServerboundPacket::LoginStart(ref p) => Some(p.get_name().clone()),
+                            _ => None,
+                        })
+            .next();
+        if let Some(username) = username {
+            let throttle = self.sessions
+                .lock()
+                .map_err(|_| "Poisoned session lock")?
+                .throttle
+                .clone();
+            let ip = self.server.peer_addr().map(|x| x.ip());
+            if let Some((key, attempts)) = throttle.check(ip, &username) {
+                self.server.audit(AuditEvent::ThrottleRejected {
+                                      key: key.to_string(),
+                                      attempts,
+                                  });
+                self.disconnect(THROTTLED_REASON)?;
+                bail!("Throttled the login attempt of {}", username);
+            }
+        }
+        Ok(packets)
+    }
+
+    /* Send a disconnect with the given json reason and close the connection */
+    fn disconnect(&mut self, reason: &str) -> Result<()> {
+        let packet: ClientboundPacket = match *self.server.clientstate() {
+            ClientState::Play => clientbound::PlayDisconnect::new(reason.to_string()),
+            _ => clientbound::LoginDisconnect::new(reason.to_string()),
+        };
+        let _: usize = self.server.send(packet)?;
+        let _: usize = self.server.write()?;
+        self.server.close()
+    }
+}
+impl Drop for ServerConnection {
+    fn drop(&mut self) {
+        if let (Some((uuid, id)), Ok(mut sessions)) = (self.session, self.sessions.lock()) {
+            if sessions.players.get(&uuid).is_some_and(|x| x.0 == id) {
+                let _ = sessions.players.remove(&uuid);
+            }
+        }
+    }
+}
+impl Deref for ServerConnection {
+    type Target = Server;
+    fn deref(&self) -> &Server {
+        &self.server
+    }
+}
+impl DerefMut for ServerConnection {
+    fn deref_mut(&mut self) -> &mut Server {
+        &mut self.server
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::net::TcpStream;
+
+    #[test]
+    fn limits() {
+        let options = ListenerOptions {
+            max_connections: None,
+            max_per_ip: Some(1),
+            rate_limit: None,
+            duplicate_login: DuplicateLogin::KickOld,
+            login_throttle: ThrottleLimits::default(),
+        };
+        let mut listener = ServerListener::bind(&["127.0.0.1:0"], options).unwrap();
+        let addr = listener.local_addrs().unwrap()[0];
+        let _a = TcpStream::connect(addr).unwrap();
+        let _b = TcpStream::connect(addr).unwrap();
+        thread::sleep(Duration::from_millis(100));
+        let conns = listener.accept().unwrap();
+        assert_eq!(conns.len(), 1);
+        assert_eq!(listener.connections(), 1);
+        drop(conns);
+        assert_eq!(listener.connections(), 0);
+    }
+
+    #[test]
+    fn duplicate_login() {
+        let mut listener = ServerListener::bind(&["127.0.0.1:0"], ListenerOptions::default())
+            .unwrap();
+        let addr = listener.local_addrs().unwrap()[0];
+        let _streams: Vec<_> = (0..3).map(|_| TcpStream::connect(addr).unwrap()).collect();
+        thread::sleep(Duration::from_millis(100));
+        let mut conns = listener.accept().unwrap();
+        assert_eq!(conns.len(), 3);
+
+        let uuid = Uuid(1);
+        conns[0].login(uuid).unwrap();
+        conns[1].login(uuid).unwrap();
+        assert!(conns[0].is_replaced());
+        assert!(!conns[1].is_replaced());
+        assert!(conns[0].read().is_err());
+        assert_eq!(listener.logged_in(), 1);
+
+        listener.set_duplicate_login_handler(Some(Arc::new(|_, _| DuplicateLogin::RejectNew)));
+        assert!(conns[2].login(uuid).is_err());
+        assert!(!conns[1].is_replaced());
+
+        drop(conns);
+        assert_eq!(listener.logged_in(), 0);
+    }
+}
@@ -0,0 +1,435 @@
+//! Scripted mock connections for integration tests
+//!
+//! Enabled with the test-util feature. A MockServer listens on a local port
+//! and plays a Script: sending packets, expecting packets from the client
+//! and switching states, in order. It fails at the first packet not
+//! matching what the script expects, so clients can be tested hermetically
+//! without a real Minecraft server.
+//!
+//! Symmetrically, a MockClient connects to a server and plays a script, to
+//! test server implementations. It can log in offline, or answer an
+//! EncryptionRequest from a server using a test keypair (without
+//! authenticating with Mojang).
+//!
+//! SetCompression packets are handled by both sides, enabling compression
+//! as they're sent or received.
+//!
+//! # Examples
+//!
+//! ```rust,no_run
+//! use ozelot::Client;
+//! use ozelot::mock::{MockServer, ServerScript};
+//!
+//! let script = ServerScript::new()
+//!     .offline_login("bot")
+//!     .expect("ChatMessage");
+//! let server = MockServer::start(script).unwrap();
+//! let mut client = Client::connect_unauthenticated("127.0.0.1",
+//!                                                  server.port(),
+//!                                                  "bot").unwrap();
+//! /* ... */
+//! server.finish().unwrap();
+//! ```
+
+use {Client, ClientState, PROTOCOL_VERSION, Packet, Server};
+use clientbound::{self, ClientboundPacket};
+use errors::Result;
+use read::SmallString;
+use serverbound::{self, ServerboundPacket};
+use utils;
+
+use std::net::{SocketAddr, TcpListener};
+use std::thread;
+use std::time::{Duration, Instant};
+
+/* A connection a script can be played on */
+trait Endpoint {
+    type In: Packet;
+    type Out: Packet;
+    fn send_packet(&mut self, packet: Self::Out) -> Result<usize>;
+    fn read_one(&mut self) -> Result<Option<Self::In>>;
+    fn set_state(&mut self, state: ClientState);
+    /* Answer the packet if it's an EncryptionRequest, and enable encryption */
+    fn answer_encryption(&mut self, packet: &Self::In) -> Result<bool>;
+}
+impl Endpoint for Server {
+    type In = ServerboundPacket;
+    type Out = ClientboundPacket;
+    fn send_packet(&mut self, packet: ClientboundPacket) -> Result<usize> {
+        match packet {
+            ClientboundPacket::SetCompression(ref p) => {
+                self.enable_compression(*p.get_threshold() as usize)
+            },
+            _ => self.send(&packet),
+        }
+    }
+    fn read_one(&mut self) -> Result<Option<ServerboundPacket>> {
+        self.update_inbuf()?;
+        self.read_packet()
+    }
+    fn set_state(&mut self, state: ClientState) {
+        self.set_clientstate(state)
+    }
+    fn answer_encryption(&mut self, _: &ServerboundPacket) -> Result<bool> {
+        Ok(false)
+    }
+}
+impl Endpoint for Client {
+    type In = ClientboundPacket;
+    type Out = ServerboundPacket;
+    fn send_packet(&mut self, packet: ServerboundPacket) -> Result<usize> {
+        self.send(packet)
+    }
+    fn read_one(&mut self) -> Result<Option<ClientboundPacket>> {
+        self.update_inbuf()?;
+        let packet = self.read_packet()?;
+        if let Some(ClientboundPacket::SetCompression(ref p)) = packet {
+            self.enable_compression(*p.get_threshold() as usize);
+        }
+        Ok(packet)
+    }
+    fn set_state(&mut self, state: ClientState) {
+        self.set_clientstate(state)
+    }
+    fn answer_encryption(&mut self, packet: &ClientboundPacket) -> Result<bool> {
+        let p = match *packet {
+            ClientboundPacket::EncryptionRequest(ref p) => p,
+            _ => return Ok(false),
+        };
+        let shared_secret = utils::create_shared_secret();
+        let response = serverbound::EncryptionResponse::new_unencrypted(p.get_public_key(),
+                                                                        &shared_secret,
+                                                                        p.get_verify_token())?;
+        let _: usize = self.send(response)?;
+        self.enable_encryption(&shared_secret);
+        Ok(true)
+    }
+}
+
+enum Step<I, O> {
+    Send(O),
+    Expect(String, Box<dyn Fn(&I) -> bool + Send>),
+    SkipUntil(String),
+    Encrypt,
+    SetState(ClientState),
+    Sleep(Duration),
+}
+
+/// A sequence of steps played by a mock connection, receiving packets of
+/// type I and sending packets of type O
+pub struct Script<I, O> {
+    steps: Vec<Step<I, O>>,
+    timeout: Duration,
+}
+
+/// A script played by a MockServer
+pub type ServerScript = Script<ServerboundPacket, ClientboundPacket>;
+/// A script played by a MockClient
+pub type ClientScript = Script<ClientboundPacket, ServerboundPacket>;
+
+impl<I: Packet + 'static, O: Packet> Script<I, O> {
+    /// Create an empty script. Each expected packet must arrive within 5
+    /// seconds.
+    pub fn new() -> Self {
+        Script {
+            steps: Vec::new(),
+            timeout: Duration::from_secs(5),
+        }
+    }
+
+    /// Set how long to wait for each expected packet
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// Send the given packet
+    pub fn send(mut self, packet: O) -> Self {
+        self.steps.push(Step::Send(packet));
+        self
+    }
+
+    /// Expect the next packet to be of the given type, e.g. "LoginStart"
+    pub fn expect(self, name: &str) -> Self {
+        let owned = name.to_string();
+        self.expect_where(name, move |p: &I| p.get_packet_name() == owned)
+    }
+
+    /// Expect the next packet to match the given predicate, described by
+    /// description in the failure message
+    pub fn expect_where<F>(mut self, description: &str, predicate: F) -> Self
+        where F: Fn(&I) -> bool + Send + 'static
+    {
+        self.steps.push(Step::Expect(description.to_string(), Box::new(predicate)));
+        self
+    }
+
+    /// Ignore packets until one of the given type arrives
+    pub fn skip_until(mut self, name: &str) -> Self {
+        self.steps.push(Step::SkipUntil(name.to_string()));
+        self
+    }
+
+    /// Switch the connection to the given state
+    pub fn set_state(mut self, state: ClientState) -> Self {
+        self.steps.push(Step::SetState(state));
+        self
+    }
+
+    /// Wait for the given time, e.g. to let the other side read everything
+    /// before the connection is closed
+    pub fn sleep(mut self, duration: Duration) -> Self {
+        self.steps.push(Step::Sleep(duration));
+        self
+    }
+
+    /* Play the script, returning all packets received */
+    fn play<E>(self, conn: &mut E) -> Result<Vec<I>>
+        where E: Endpoint<In = I, Out = O>
+    {
+        let mut received = Vec::new();
+        for (i, step) in self.steps.into_iter().enumerate() {
+            match step {
+                Step::Send(packet) => {
+                    let _: usize = conn.send_packet(packet)?;
+                },
+                Step::Expect(description, predicate) => {
+                    let packet = read_timeout(conn, self.timeout, &description)?;
+                    if !predicate(&packet) {
+                        bail!("Step {}: expected {}, got {}",
+                              i,
+                              description,
+                              packet.get_packet_name());
+                    }
+                    received.push(packet);
+                },
+                Step::SkipUntil(name) => {
+                    loop {
+                        let packet = read_timeout(conn, self.timeout, &name)?;
+                        let done = packet.get_packet_name() == name;
+                        received.push(packet);
+                        if done {
+                            break;
+                        }
+                    }
+                },
+                Step::Encrypt => {
+                    let packet = read_timeout(conn, self.timeout, "EncryptionRequest")?;
+                    if !conn.answer_encryption(&packet)? {
+                        bail!("Step {}: expected EncryptionRequest, got {}",
+                              i,
+                              packet.get_packet_name());
+                    }
+                    received.push(packet);
+                },
+                Step::SetState(state) => conn.set_state(state),
+                Step::Sleep(duration) => thread::sleep(duration),
+            }
+        }
+        Ok(received)
+    }
+}
+impl ServerScript {
+    /// Add the steps of an offline mode login: expect the Handshake and
+    /// LoginStart, and send LoginSuccess, JoinGame and PlayerAbilities, after
+    /// which Client::connect_unauthenticated() returns.
+    pub fn offline_login(self, username: &str) -> Self {
+        let owned = username.to_string();
+        self.expect("Handshake")
+            .set_state(ClientState::Login)
+            .expect_where("LoginStart", move |p| match *p {
+                ServerboundPacket::LoginStart(ref p) => *p.get_name() == owned,
+                _ => false,
+            })
+            .send(clientbound::LoginSuccess::new(0, username.to_string()))
+            .set_state(ClientState::Play)
+            .send(clientbound::JoinGame::new(1, 0, 0, 0, 20, SmallString::from("default"), false))
+            .send(clientbound::PlayerAbilities::new(0, 0.05, 0.1))
+    }
+}
+impl ClientScript {
+    /// Add the steps of an offline mode login: send the Handshake and
+    /// LoginStart, and wait for LoginSuccess
+    pub fn offline_login(self, username: &str) -> Self {
+        self.start_login(username)
+            .skip_until("LoginSuccess")
+            .set_state(ClientState::Play)
+    }
+
+    /// Add the steps of an online mode login, without authenticating with
+    /// Mojang: send the Handshake and LoginStart, answer the
+    /// EncryptionRequest with a new shared secret, and wait for LoginSuccess
+    pub fn encrypted_login(self, username: &str) -> Self {
+        self.start_login(username)
+            .encrypt()
+            .skip_until("LoginSuccess")
+            .set_state(ClientState::Play)
+    }
+
+    fn start_login(self, username: &str) -> Self {
+        self.send(serverbound::Handshake::new(PROTOCOL_VERSION,
+                                              "localhost".to_string(),
+                                              25565,
+                                              2))
+            .set_state(ClientState::Login)
+            .send(serverbound::LoginStart::new(username.to_string()))
+    }
+
+    /// Expect an EncryptionRequest, answering it with a new shared secret
+    /// and enabling encryption
+    pub fn encrypt(mut self) -> Self {
+        self.steps.push(Step::Encrypt);
+        self
+    }
+}
+impl<I: Packet + 'static, O: Packet> Default for Script<I, O> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+fn read_timeout<E: Endpoint>(conn: &mut E,
+                             timeout: Duration,
+                             description: &str)
+                             -> Result<E::In> {
+    let start = Instant::now();
+    loop {
+        if let Some(packet) = conn.read_one()? {
+            return Ok(packet);
+        }
+        if start.elapsed() > timeout {
+            bail!("Timed out waiting for {}", description);
+        }
+        thread::sleep(Duration::from_millis(5));
+    }
+}
+
+/// A mock server playing a script to the first client connecting to it
+pub struct MockServer {
+    addr: SocketAddr,
+    thread: thread::JoinHandle<Result<Vec<ServerboundPacket>>>,
+}
+impl MockServer {
+    /// Listen on a free local port, and play the script on a background
+    /// thread once a client connects
+    pub fn start(script: ServerScript) -> Result<Self> {
+        let listener = TcpListener::bind("127.0.0.1:0")?;
+        let addr = listener.local_addr()?;
+        let thread = thread::spawn(move || {
+            let (stream, _) = listener.accept()?;
+            let mut server = Server::from_tcpstream(stream)?;
+            script.play(&mut server)
+        });
+        Ok(MockServer { addr, thread })
+    }
+
+    /// The address listened on
+    pub fn addr(&self) -> SocketAddr {
+        self.addr
+    }
+
+    /// The port listened on
+    pub fn port(&self) -> u16 {
+        self.addr.port()
+    }
+
+    /// Wait for the script to finish, returning the packets received, or
+    /// the reason it failed
+    pub fn finish(self) -> Result<Vec<ServerboundPacket>> {
+        match self.thread.join() {
+            Ok(x) => x,
+            Err(_) => bail!("The mock server panicked"),
+        }
+    }
+}
+
+/// A mock client playing a script on a connection to a server
+pub struct MockClient {
+    thread: thread::JoinHandle<Result<Vec<ClientboundPacket>>>,
+}
+impl MockClient {
+    /// Connect to the given server, and play the script on a background
+    /// thread
+    pub fn start(host: &str, port: u16, script: ClientScript) -> Result<Self> {
+        let mut client = Client::connect_tcp(host, port)?;
+        let thread = thread::spawn(move || script.play(&mut client));
+        Ok(MockClient { thread })
+    }
+
+    /// Wait for the script to finish, returning the packets received, or
+    /// the reason it failed
+    pub fn finish(self) -> Result<Vec<ClientboundPacket>> {
+        match self.thread.join() {
+            Ok(x) => x,
+            Err(_) => bail!("The mock client panicked"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use Client;
+    use serverbound;
+
+    #[test]
+    fn mock_server() {
+        let script = ServerScript::new()
+            .offline_login("bot")
+            .skip_until("ChatMessage")
+            .sleep(Duration::from_millis(50));
+        let server = MockServer::start(script).unwrap();
+        let mut client = Client::connect_unauthenticated("127.0.0.1", server.port(), "bot")
+            .unwrap();
+        let _: usize = client.send(serverbound::ChatMessage::new("hi".to_string())).unwrap();
+        let received = server.finish().unwrap();
+        assert_eq!(received.last().unwrap().get_packet_name(), "ChatMessage");
+
+        let script = ServerScript::new().expect("LoginStart");
+        let server = MockServer::start(script).unwrap();
+        let mut client = Client::connect_tcp("127.0.0.1", server.port()).unwrap();
+        let handshake = serverbound::Handshake::new(404, "127.0.0.1".to_string(), 0, 2);
+        let _: usize = client.send(handshake).unwrap();
+        assert!(server.finish().is_err());
+    }
+
+    #[test]
+    fn mock_client() {
+        let script = ServerScript::new().offline_login("bot").expect("ChatMessage");
+        let server = MockServer::start(script).unwrap();
+        let script = ClientScript::new()
+            .offline_login("bot")
+            .skip_until("PlayerAbilities")
+            .send(serverbound::ChatMessage::new("hi".to_string()));
+        let client = MockClient::start("127.0.0.1", server.port(), script).unwrap();
+        assert_eq!(client.finish().unwrap().last().unwrap().get_packet_name(),
+                   "PlayerAbilities");
+        assert!(server.finish().is_ok());
+
+        /* Encrypted login against a Server doing the encryption manually */
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let script = ClientScript::new().encrypted_login("bot");
+        let client = MockClient::start("127.0.0.1", port, script).unwrap();
+        let mut server = Server::from_tcpstream(listener.accept().unwrap().0).unwrap();
+        let key = utils::generate_rsa_key();
+        let script = ServerScript::new()
+            .expect("Handshake")
+            .set_state(ClientState::Login)
+            .expect("LoginStart")
+            .send(clientbound::EncryptionRequest::new(String::new(),
+                                                      utils::rsa_key_binary(&key),
+                                                      vec![1, 2, 3, 4]));
+        let _ = script.play(&mut server).unwrap();
+        let packet = read_timeout(&mut server, Duration::from_secs(5), "response").unwrap();
+        match packet {
+            ServerboundPacket::EncryptionResponse(ref p) => {
+                let _: [u8; 16] = server.accept_encryption(p, &key, &[1, 2, 3, 4]).unwrap();
+            },
+            _ => panic!("Expected EncryptionResponse"),
+        }
+        let _: usize = server.send(clientbound::LoginSuccess::new(0, "bot".to_string()))
+            .unwrap();
+        assert_eq!(client.finish().unwrap().len(), 2);
+    }
+}
@@ -12,15 +12,89 @@
 //! requests and their responses.
 //!
 //! Also contains some helper functions used for authentication.
+//!
+//! Large networks proxying the Mojang APIs can have requests sent to their
+//! own mirrors first, see set_mirrors().
+//!
+//! To keep programs from getting their IP blocked by sending many requests
+//! at once, e.g. looking up a whole player list from a thread pool, only a
+//! few requests to each family of endpoints are sent at a time, and the
+//! rest wait for them to finish. The defaults are those of
+//! EndpointFamily::default_limit(), and can be changed with
+//! set_concurrency_limit().
+//!
+//! When Mojang answers a request with 429 Too Many Requests, all requests
+//! wait until the cooldown it asks for has passed, plus a random delay so
+//! they don't all retry at once. Requests that would have to wait longer
+//! than set_max_cooldown_wait() fail with ErrorKind::RateLimited instead.
+//!
+//! The requests block until the response arrives. Async programs can
+//! perform them from a thread meant for blocking work, e.g. with tokio's
+//! spawn_blocking().
 
 pub use json::*;
-use errors::Result;
-use utils;
-
-use curl::easy::{Easy, List};
+use errors::{Error, ErrorKind, Result};
+use {http, utils};
+use uuid::Uuid;
 
 use serde_json;
 
+use openssl::base64;
+
+use std::sync::{Arc, Condvar, Mutex};
+use std::thread;
+use std::time::{Duration, Instant, SystemTime};
+
+#[cfg(feature = "chrono")]
+use chrono::{DateTime, Utc};
+
+/// A request to one of the Mojang APIs
+///
+/// All the request types in this module implement this, so that code can be
+/// written generically over them.
+pub trait Request {
+    /// The parsed response
+    type Response;
+    /// Perform the request, blocking until the response arrives
+    fn perform(&self) -> Result<Self::Response>;
+}
+
+macro_rules! impl_request {
+    ($($name:ident => $response:ty,)*) => {
+        $(
+            impl Request for $name {
+                type Response = $response;
+                fn perform(&self) -> Result<$response> {
+                    $name::perform(self)
+                }
+            }
+        )*
+    }
+}
+
+impl_request! {
+    APIStatus => APIStatusResponse,
+    NameToUUID => NameUUID,
+    UUIDToHistory => Vec<NameHistory>,
+    PlayernamesToUUIDs => Vec<NameUUID>,
+    UUIDToProfile => Profile,
+    BlockedServers => Vec<String>,
+    Statistics => StatisticsResponse,
+    Authenticate => AuthenticationResponse,
+    AuthenticateRefresh => AuthenticationResponse,
+    AuthenticateValidate => (),
+    AuthenticateSignout => (),
+    AuthenticateInvalidate => (),
+    SessionJoin => (),
+    SessionHasJoined => SessionHasJoinedResponse,
+    ChangeSkin => (),
+    UploadSkin => (),
+    ResetSkin => (),
+    SecurityLocationCheck => bool,
+    SecurityChallenges => Vec<SecurityChallenge>,
+    SecurityAnswer => (),
+}
+
 /// Make a request to check the status of the Mojang APIs
 #[derive(Debug, Clone)]
 pub struct APIStatus();
@@ -50,18 +124,19 @@ impl APIStatus {
 /// Make a Username -> UUID (at time) request
 ///
 /// Returns information about which account had the given name at the point in
-/// time, where the time is specified as epoch time. If at is not specified, it
-/// will default to the current time.
+/// time. If at is not specified, it will default to the current time.
 ///
 /// If unable to find the player at the given point in time, will return an
-/// error.
+/// error. Invalid usernames return an error without sending the request.
 #[derive(Debug, Clone)]
 pub struct NameToUUID {
     username: String,
+    /* Seconds since the epoch */
     at: Option<i64>,
 }
 impl NameToUUID {
     pub fn perform(&self) -> Result<NameUUID> {
+        utils::validate_username(&self.username)?;
         let url = match self.at {
             Some(x) => {
                 format!("https://api.mojang.com/users/profiles/minecraft/{}?at={}",
@@ -76,12 +151,17 @@ impl NameToUUID {
         let res = get_request(&url)?;
         Ok(serde_json::from_str(&res)?)
     }
-    pub fn new(username: String, at: Option<i64>) -> Self {
+    pub fn new(username: String, at: Option<SystemTime>) -> Self {
         NameToUUID {
             username: username,
-            at: at,
+            at: at.map(utils::to_epoch_seconds),
         }
     }
+    /// Create the request with the time as a chrono DateTime
+    #[cfg(feature = "chrono")]
+    pub fn new_utc(username: String, at: Option<DateTime<Utc>>) -> Self {
+        NameToUUID::new(username, at.map(SystemTime::from))
+    }
 }
 
 /// A UUID -> Username history request
@@ -118,7 +198,6 @@ impl PlayernamesToUUIDs {
     }
     pub fn perform(&self) -> Result<Vec<NameUUID>> {
         let body = serde_json::to_string(&self.usernames)?;
-        println!("body: {}", body);
         let res = post_request(&Self::get_endpoint(), &body)?;
         Ok(serde_json::from_str(&res)?)
     }
@@ -155,7 +234,6 @@ impl UUIDToProfile {
                     self.uuid)
         };
         let res = get_request(&url)?;
-        println!("res: {}", res);
         Ok(serde_json::from_str(&res)?)
     }
     pub fn new(uuid: String, signed: bool) -> Self {
@@ -166,6 +244,116 @@ impl UUIDToProfile {
     }
 }
 
+/// Look up the UUID of the player currently using the given username
+///
+/// A shorthand for NameToUUID, use that to look up who had the username at
+/// some point in time.
+pub fn lookup_uuid(username: &str) -> Result<Uuid> {
+    NameToUUID::new(username.to_string(), None).perform()?.uuid()
+}
+
+/// Look up the profile (current username, skin and cape) of the player with
+/// the given UUID, with or without hyphens
+///
+/// A shorthand for an unsigned UUIDToProfile request.
+pub fn lookup_profile(uuid: &str) -> Result<Profile> {
+    let uuid: Uuid = uuid.parse()?;
+    UUIDToProfile::new(uuid.to_simple(), false).perform()
+}
+
+impl Profile {
+    /// Decode the skin and cape from the textures property, if the profile
+    /// has one
+    pub fn textures(&self) -> Result<Option<Textures>> {
+        #[derive(Deserialize)]
+        struct TexturesProperty {
+            textures: Textures,
+        }
+        let property = match self.properties.iter().find(|x| x.name == "textures") {
+            Some(x) => x,
+            None => return Ok(None),
+        };
+        let json = String::from_utf8(base64::decode_block(&property.value)?)?;
+        Ok(Some(serde_json::from_str::<TexturesProperty>(&json)?.textures))
+    }
+}
+
+/// Everything known about a player from the public APIs, see
+/// PlayerReport::fetch()
+#[derive(Debug, Clone)]
+pub struct PlayerReport {
+    pub uuid: Uuid,
+    /// The current username
+    pub name: String,
+    /// The skin and cape, if the player has a textures property
+    pub textures: Option<Textures>,
+    /// Actions the player must take, see Profile::profileActions
+    pub profile_actions: Vec<String>,
+    /// The profile the report was made from
+    pub profile: Profile,
+    /// When the report was made
+    pub fetched_at: SystemTime,
+}
+impl PlayerReport {
+    /// Look up the player with the given username or UUID, e.g. for a
+    /// moderation tool's whois command
+    ///
+    /// Usernames are resolved with NameToUUID, then the profile is fetched
+    /// with UUIDToProfile and its textures decoded. The requests are subject
+    /// to the concurrency limits and cooldowns like any other. Reports are
+    /// cached for REPORT_CACHE_TIME, since the session server only allows
+    /// fetching the same profile about once a minute.
+    pub fn fetch(name_or_uuid: &str) -> Result<PlayerReport> {
+        if let Some(x) = cached_report(name_or_uuid) {
+            return Ok(x);
+        }
+        let uuid = match name_or_uuid.parse::<Uuid>() {
+            Ok(x) => x,
+            Err(_) => lookup_uuid(name_or_uuid)?,
+        };
+        if let Some(x) = cached_report(&uuid.to_string()) {
+            return Ok(x);
+        }
+        let profile = UUIDToProfile::new(uuid.to_simple(), false).perform()?;
+        let report = PlayerReport::from_profile(profile)?;
+        if let Ok(mut cache) = REPORTS.lock() {
+            cache.retain(|x| x.uuid != report.uuid);
+            cache.push(report.clone());
+        }
+        Ok(report)
+    }
+
+    /// Make a report from an already fetched profile
+    pub fn from_profile(profile: Profile) -> Result<PlayerReport> {
+        Ok(PlayerReport {
+               uuid: profile.uuid()?,
+               name: profile.name.clone(),
+               textures: profile.textures()?,
+               profile_actions: profile.profileActions.clone(),
+               profile,
+               fetched_at: SystemTime::now(),
+           })
+    }
+}
+
+/// How long PlayerReport::fetch() caches reports
+pub const REPORT_CACHE_TIME: Duration = Duration::from_secs(60);
+
+static REPORTS: Mutex<Vec<PlayerReport>> = Mutex::new(Vec::new());
+
+/* Get a report fetched recently for the username or UUID */
+fn cached_report(name_or_uuid: &str) -> Option<PlayerReport> {
+    let uuid = name_or_uuid.parse::<Uuid>().ok();
+    let mut cache = REPORTS.lock().ok()?;
+    cache.retain(|x| x.fetched_at.elapsed().is_ok_and(|x| x < REPORT_CACHE_TIME));
+    cache.iter()
+        .find(|x| match uuid {
+                  Some(uuid) => x.uuid == uuid,
+                  None => x.name.eq_ignore_ascii_case(name_or_uuid),
+              })
+        .cloned()
+}
+
 /// Get the blocked server's hashes
 #[derive(Debug, Clone)]
 pub struct BlockedServers();
@@ -188,81 +376,106 @@ impl BlockedServers {
     }
 }
 
+/// A type of sale counted by the Statistics request
+#[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum MetricKey {
+    ItemSoldMinecraft,
+    PrepaidCardRedeemedMinecraft,
+    ItemSoldCobalt,
+    PrepaidCardRedeemedCobalt,
+    ItemSoldScrolls,
+    ItemSoldDungeons,
+    /// A key not known to ozelot, as sent to the API
+    Other(String),
+}
+impl MetricKey {
+    /// All the keys known to ozelot
+    pub fn known() -> Vec<MetricKey> {
+        vec![MetricKey::ItemSoldMinecraft,
+             MetricKey::PrepaidCardRedeemedMinecraft,
+             MetricKey::ItemSoldCobalt,
+             MetricKey::PrepaidCardRedeemedCobalt,
+             MetricKey::ItemSoldScrolls,
+             MetricKey::ItemSoldDungeons]
+    }
+
+    /// Get the key as sent to the API, e.g. "item_sold_minecraft"
+    pub fn as_str(&self) -> &str {
+        match *self {
+            MetricKey::ItemSoldMinecraft => "item_sold_minecraft",
+            MetricKey::PrepaidCardRedeemedMinecraft => "prepaid_card_redeemed_minecraft",
+            MetricKey::ItemSoldCobalt => "item_sold_cobalt",
+            MetricKey::PrepaidCardRedeemedCobalt => "prepaid_card_redeemed_cobalt",
+            MetricKey::ItemSoldScrolls => "item_sold_scrolls",
+            MetricKey::ItemSoldDungeons => "item_sold_dungeons",
+            MetricKey::Other(ref x) => x,
+        }
+    }
+}
+impl<'a> From<&'a str> for MetricKey {
+    fn from(x: &'a str) -> Self {
+        MetricKey::known()
+            .into_iter()
+            .find(|key| key.as_str() == x)
+            .unwrap_or_else(|| MetricKey::Other(x.to_string()))
+    }
+}
+
 /// Get the orders statistics
 ///
 /// The API will respond with the sum of sales for the selected types. E.g. by
-/// setting item_sold_minecraft and prepaid_card_redeemed_minecraft to true,
-/// you'll get the sum of sales for those two types.
+/// selecting MetricKey::ItemSoldMinecraft and
+/// MetricKey::PrepaidCardRedeemedMinecraft, you'll get the sum of sales for
+/// those two types.
 #[derive(Debug, Clone)]
 pub struct Statistics {
-    item_sold_minecraft: bool,
-    prepaid_card_redeemed_minecraft: bool,
-    item_sold_cobalt: bool,
-    item_sold_scrolls: bool,
+    keys: Vec<MetricKey>,
 }
 impl Statistics {
     fn get_endpoint() -> String {
         "https://api.mojang.com/orders/statistics".to_string()
     }
+    /// Perform the request. Returns an error without sending the request if
+    /// no keys are selected.
     pub fn perform(&self) -> Result<StatisticsResponse> {
-        let mut query: Vec<&str> = Vec::new();
-        if self.item_sold_minecraft {
-            query.push("item_sold_minecraft");
-        }
-        if self.prepaid_card_redeemed_minecraft {
-            query.push("prepaid_card_redeemed_minecraft");
-        }
-        if self.item_sold_cobalt {
-            query.push("item_sold_cobalt");
-        }
-        if self.item_sold_scrolls {
-            query.push("item_sold_scrolls");
+        if self.keys.is_empty() {
+            bail!("You must specify at least one type of sale in the Statistics request");
         }
+        let keys: Vec<&str> = self.keys.iter().map(|x| x.as_str()).collect();
         let payload = json!({
-                                "metricKeys": query
+                                "metricKeys": keys
                             });
         let res = post_request(&Self::get_endpoint(), &payload.to_string())?;
         Ok(serde_json::from_str(&res)?)
     }
-    /// Create a new request for requesting the sum of sales of the specified
-    /// types.
-    ///
-    /// # Panics
-    ///
-    /// Panics if not at least one of the values is true.
-    pub fn new(item_sold_minecraft: bool,
-               prepaid_card_redeemed_minecraft: bool,
-               item_sold_cobalt: bool,
-               item_sold_scrolls: bool)
-               -> Self {
-        if !(item_sold_minecraft | prepaid_card_redeemed_minecraft |
-             item_sold_cobalt | item_sold_scrolls) {
-            panic!("You must specify at least one type of sale in the Statistics request");
+    /// Create a new request for requesting the sum of sales of the given
+    /// types. More can be added with key().
+    pub fn new<I: IntoIterator<Item = MetricKey>>(keys: I) -> Self {
+        let mut ret = Statistics { keys: Vec::new() };
+        for key in keys {
+            ret = ret.key(key);
         }
-        Statistics {
-            item_sold_minecraft: item_sold_minecraft,
-            prepaid_card_redeemed_minecraft: prepaid_card_redeemed_minecraft,
-            item_sold_cobalt: item_sold_cobalt,
-            item_sold_scrolls: item_sold_scrolls,
+        ret
+    }
+    /// Also count the sales of the given type
+    pub fn key(mut self, key: MetricKey) -> Self {
+        if !self.keys.contains(&key) {
+            self.keys.push(key);
         }
+        self
+    }
+    /// Get the selected types of sales
+    pub fn keys(&self) -> &[MetricKey] {
+        &self.keys
     }
-    /// Get the sum of everything
+    /// Get the sum of everything known to ozelot
     pub fn all() -> Self {
-        Statistics {
-            item_sold_minecraft: true,
-            prepaid_card_redeemed_minecraft: true,
-            item_sold_cobalt: true,
-            item_sold_scrolls: true,
-        }
+        Self::new(MetricKey::known())
     }
     /// Get just the amount of Minecraft sales
     pub fn minecraft() -> Self {
-        Statistics {
-            item_sold_minecraft: true,
-            prepaid_card_redeemed_minecraft: true,
-            item_sold_cobalt: false,
-            item_sold_scrolls: false,
-        }
+        Self::new(vec![MetricKey::ItemSoldMinecraft,
+                       MetricKey::PrepaidCardRedeemedMinecraft])
     }
 }
 
@@ -403,6 +616,13 @@ impl AuthenticateInvalidate {
 
 /// Send a session join message to Mojang, used by clients when connecting to
 /// online servers
+///
+/// Joining sometimes fails spuriously, e.g. with an invalid token right after
+/// logging in, before the token has reached the session servers, or when
+/// their clocks disagree with the authserver's. perform() retries after such
+/// failures a couple of times, with short delays so the server doesn't time
+/// out the login meanwhile. Accounts not allowed to play online fail right
+/// away with ErrorKind::InsufficientPrivileges.
 #[derive(Debug, Serialize, Clone)]
 pub struct SessionJoin {
     accessToken: String,
@@ -416,8 +636,20 @@ impl SessionJoin {
     }
     pub fn perform(&self) -> Result<()> {
         let payload = serde_json::to_string(self)?;
-        let _ = post_request(&Self::get_endpoint(), &payload)?;
-        Ok(())
+        let mut delays = SESSION_JOIN_RETRY_DELAYS.iter();
+        loop {
+            let e = match post_request(&Self::get_endpoint(), &payload) {
+                Ok(_) => return Ok(()),
+                Err(e) => e,
+            };
+            match delays.next() {
+                Some(&delay) if retry_join(&e) => {
+                    let delay = Duration::from_millis(delay);
+                    thread::sleep(delay + utils::jitter(delay / 2));
+                },
+                _ => return Err(e),
+            }
+        }
     }
     pub fn new(access_token: String,
                uuid: String,
@@ -435,8 +667,36 @@ impl SessionJoin {
     }
 }
 
+/* The delays in milliseconds before retrying a failed SessionJoin, none
+ * in tests so they don't have to sleep through them */
+#[cfg(not(test))]
+const SESSION_JOIN_RETRY_DELAYS: [u64; 2] = [250, 1000];
+#[cfg(test)]
+const SESSION_JOIN_RETRY_DELAYS: [u64; 2] = [0, 0];
+
+/* Whether a failed SessionJoin may succeed if retried */
+fn retry_join(e: &Error) -> bool {
+    match *e.kind() {
+        ErrorKind::InvalidToken(_) => true,
+        _ => is_unreachable(e),
+    }
+}
+
+/// Whether the error means that the Mojang API couldn't be reached or is
+/// down, as opposed to it answering the request with an error
+pub fn is_unreachable(e: &Error) -> bool {
+    match *e.kind() {
+        ErrorKind::Curl(_) |
+        ErrorKind::Io(_) => true,
+        ErrorKind::MojangApi(status, ..) => status >= 500,
+        _ => false,
+    }
+}
+
 /// Check whether a client has posted a SessionJoin to Mojang, used by servers
 /// for authenticating connecting clients.
+///
+/// Returns an error if the client hasn't joined.
 #[derive(Debug, Clone)]
 pub struct SessionHasJoined {
     username: String,
@@ -446,7 +706,10 @@ impl SessionHasJoined {
     pub fn perform(&self) -> Result<SessionHasJoinedResponse> {
         let url = format!("https://sessionserver.mojang.com/session/minecraft/hasJoined?username={}&serverId={}", self.username, self.serverId);
         let res = get_request(&url)?;
-        println!("session has joined response: {}", &res);
+        /* Mojang answers 204 No Content if the client hasn't joined */
+        if res.is_empty() {
+            bail!("{} hasn't joined the server with Mojang", self.username);
+        }
         Ok(serde_json::from_str(&res)?)
     }
     pub fn new(username: String,
@@ -462,45 +725,954 @@ impl SessionHasJoined {
     }
 }
 
+/* Here begins the skin requests */
+
+const SKINS_URL: &str = "https://api.minecraftservices.com/minecraft/profile/skins";
+const ACTIVE_SKIN_URL: &str = "https://api.minecraftservices.com/minecraft/profile/skins/active";
+/* The first bytes of every PNG file */
+const PNG_SIGNATURE: &[u8] = b"\x89PNG\r\n\x1a\n";
+
+/// The player model a skin is made for
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SkinModel {
+    /// The model with 4 pixel wide arms, "Steve"
+    Classic,
+    /// The model with 3 pixel wide arms, "Alex"
+    Slim,
+}
+impl SkinModel {
+    /// Get the name the API uses for the model
+    pub fn as_str(&self) -> &'static str {
+        match *self {
+            SkinModel::Classic => "classic",
+            SkinModel::Slim => "slim",
+        }
+    }
+}
+
+/// Change the skin of the account with the given access token to the one at
+/// the given URL
+#[derive(Debug, Clone)]
+pub struct ChangeSkin {
+    access_token: String,
+    url: String,
+    model: SkinModel,
+}
+impl ChangeSkin {
+    pub fn perform(&self) -> Result<()> {
+        let payload = json!({
+            "variant": self.model.as_str(),
+            "url": self.url,
+        });
+        let request = http::Request::post(SKINS_URL, &payload.to_string())
+            .header("Content-Type", "application/json")
+            .header("Authorization", &format!("Bearer {}", self.access_token));
+        let _ = check_response(perform(request)?)?;
+        Ok(())
+    }
+    pub fn new(access_token: String, url: String, model: SkinModel) -> Self {
+        ChangeSkin {
+            access_token,
+            url,
+            model,
+        }
+    }
+}
+
+/// Upload a PNG file as the skin of the account with the given access token
+///
+/// The image must be 64x64 pixels, or 64x32 for skins in the old format.
+#[derive(Debug, Clone)]
+pub struct UploadSkin {
+    access_token: String,
+    png: Vec<u8>,
+    model: SkinModel,
+}
+impl UploadSkin {
+    pub fn perform(&self) -> Result<()> {
+        check_skin(&self.png)?;
+        let mut boundary = [0; 16];
+        ::openssl::rand::rand_bytes(&mut boundary)?;
+        let boundary: String = boundary.iter().map(|x| format!("{:02x}", x)).collect();
+
+        let mut body = Vec::new();
+        body.extend_from_slice(format!("--{}\r\n\
+                                        Content-Disposition: form-data; name=\"variant\"\r\n\r\n\
+                                        {}\r\n\
+                                        --{}\r\n\
+                                        Content-Disposition: form-data; name=\"file\"; \
+                                        filename=\"skin.png\"\r\n\
+                                        Content-Type: image/png\r\n\r\n",
+                                       boundary,
+                                       self.model.as_str(),
+                                       boundary)
+                                   .as_bytes());
+        body.extend_from_slice(&self.png);
+        body.extend_from_slice(format!("\r\n--{}--\r\n", boundary).as_bytes());
+
+        let request = http::Request::new(http::Method::Post, SKINS_URL, Some(body))
+            .header("Content-Type",
+                    &format!("multipart/form-data; boundary={}", boundary))
+            .header("Authorization", &format!("Bearer {}", self.access_token));
+        let _ = check_response(perform(request)?)?;
+        Ok(())
+    }
+    pub fn new(access_token: String, png: Vec<u8>, model: SkinModel) -> Self {
+        UploadSkin {
+            access_token,
+            png,
+            model,
+        }
+    }
+}
+
+/* Check that the data is a PNG image of a size Minecraft accepts as skin */
+fn check_skin(png: &[u8]) -> Result<()> {
+    /* The signature is followed by the IHDR chunk, which starts with the
+     * width and height */
+    if png.len() < 24 || !png.starts_with(PNG_SIGNATURE) || &png[12..16] != b"IHDR" {
+        bail!("The skin isn't a PNG image");
+    }
+    let width = u32::from_be_bytes([png[16], png[17], png[18], png[19]]);
+    let height = u32::from_be_bytes([png[20], png[21], png[22], png[23]]);
+    if width != 64 || (height != 64 && height != 32) {
+        bail!("The skin is {}x{} pixels, it must be 64x64 or 64x32", width, height);
+    }
+    Ok(())
+}
+
+/// Reset the skin of the account with the given access token to the default
+#[derive(Debug, Clone)]
+pub struct ResetSkin {
+    access_token: String,
+}
+impl ResetSkin {
+    pub fn perform(&self) -> Result<()> {
+        let request = http::Request::delete(ACTIVE_SKIN_URL)
+            .header("Authorization", &format!("Bearer {}", self.access_token));
+        let _ = check_response(perform(request)?)?;
+        Ok(())
+    }
+    pub fn new(access_token: String) -> Self {
+        ResetSkin { access_token }
+    }
+}
+
+/* Here begins the security question requests */
+
+const SECURITY_LOCATION_URL: &str = "https://api.mojang.com/user/security/location";
+const SECURITY_CHALLENGES_URL: &str = "https://api.mojang.com/user/security/challenges";
+
+/// Check whether the account with the given access token is trusted to be
+/// used from this IP address
+///
+/// Some requests fail when sent from an IP address the account hasn't been
+/// used from before, until the account's security questions are answered.
+/// Returns false if they must be answered first, see SecurityChallenges and
+/// SecurityAnswer.
+#[derive(Debug, Clone)]
+pub struct SecurityLocationCheck {
+    access_token: String,
+}
+impl SecurityLocationCheck {
+    pub fn perform(&self) -> Result<bool> {
+        let request = http::Request::get(SECURITY_LOCATION_URL)
+            .header("Authorization", &format!("Bearer {}", self.access_token));
+        let response = perform(request)?;
+        if response.status == 403 {
+            return Ok(false);
+        }
+        let _ = check_response(response)?;
+        Ok(true)
+    }
+    pub fn new(access_token: String) -> Self {
+        SecurityLocationCheck { access_token }
+    }
+}
+
+/// Get the security questions of the account with the given access token
+///
+/// The list is empty if the account has no security questions.
+#[derive(Debug, Clone)]
+pub struct SecurityChallenges {
+    access_token: String,
+}
+impl SecurityChallenges {
+    pub fn perform(&self) -> Result<Vec<SecurityChallenge>> {
+        let request = http::Request::get(SECURITY_CHALLENGES_URL)
+            .header("Authorization", &format!("Bearer {}", self.access_token));
+        let res = check_response(perform(request)?)?;
+        Ok(serde_json::from_str(&res)?)
+    }
+    pub fn new(access_token: String) -> Self {
+        SecurityChallenges { access_token }
+    }
+}
+
+/// Answer the security questions of the account with the given access token,
+/// trusting the IP address the request is sent from
+///
+/// Takes the answer ID of each question given by SecurityChallenges, with
+/// the answer to it. Returns an error if any answer is wrong.
+#[derive(Debug, Clone)]
+pub struct SecurityAnswer {
+    access_token: String,
+    answers: Vec<(i64, String)>,
+}
+impl SecurityAnswer {
+    pub fn perform(&self) -> Result<()> {
+        let payload: Vec<_> = self.answers
+            .iter()
+            .map(|&(id, ref answer)| json!({ "id": id, "answer": answer }))
+            .collect();
+        let request = http::Request::post(SECURITY_LOCATION_URL,
+                                          &serde_json::to_string(&payload)?)
+            .header("Content-Type", "application/json")
+            .header("Authorization", &format!("Bearer {}", self.access_token));
+        let _ = check_response(perform(request)?)?;
+        Ok(())
+    }
+    pub fn new(access_token: String, answers: Vec<(i64, String)>) -> Self {
+        SecurityAnswer {
+            access_token,
+            answers,
+        }
+    }
+    /// Answer the questions given by SecurityChallenges, in the same order
+    pub fn from_challenges(access_token: String,
+                           challenges: &[SecurityChallenge],
+                           answers: Vec<String>)
+                           -> Self {
+        let ids = challenges.iter().map(|x| x.answer.id);
+        SecurityAnswer::new(access_token, ids.zip(answers).collect())
+    }
+}
+
+/// The Mojang web services, each of which can be given mirrors with
+/// set_mirrors()
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Service {
+    /// api.mojang.com
+    Api,
+    /// sessionserver.mojang.com
+    SessionServer,
+    /// authserver.mojang.com
+    AuthServer,
+    /// status.mojang.com
+    Status,
+}
+impl Service {
+    /// Get the base URL of the service at Mojang
+    pub fn mojang_url(&self) -> &'static str {
+        match *self {
+            Service::Api => "https://api.mojang.com",
+            Service::SessionServer => "https://sessionserver.mojang.com",
+            Service::AuthServer => "https://authserver.mojang.com",
+            Service::Status => "https://status.mojang.com",
+        }
+    }
+
+    fn all() -> [Service; 4] {
+        [Service::Api, Service::SessionServer, Service::AuthServer, Service::Status]
+    }
+}
+
+static MIRRORS: Mutex<Vec<(Service, Vec<String>)>> = Mutex::new(Vec::new());
+
+/// Set the base URLs requests to the service are sent to, tried in order.
+///
+/// If a request to one of them fails to connect, or gets a 5xx response, the
+/// next one is tried. E.g. to use a caching mirror and fall back to Mojang:
+/// vec!["https://mojang-mirror.internal".to_string(),
+/// Service::Api.mojang_url().to_string()]. An empty list resets the service
+/// to only using Mojang.
+pub fn set_mirrors(service: Service, base_urls: Vec<String>) {
+    if let Ok(mut mirrors) = MIRRORS.lock() {
+        mirrors.retain(|x| x.0 != service);
+        if !base_urls.is_empty() {
+            let base_urls = base_urls.into_iter()
+                .map(|x| x.trim_end_matches('/').to_string())
+                .collect();
+            mirrors.push((service, base_urls));
+        }
+    }
+}
+
+/// Get the base URLs requests to the service are sent to, see set_mirrors()
+pub fn mirrors(service: Service) -> Vec<String> {
+    let mirrors = MIRRORS.lock().ok().and_then(|mirrors| {
+        mirrors.iter().find(|x| x.0 == service).map(|x| x.1.clone())
+    });
+    mirrors.unwrap_or_else(|| vec![service.mojang_url().to_string()])
+}
+
+/* The URLs to try for the given Mojang URL, in order */
+fn candidate_urls(url: &str) -> Vec<String> {
+    for service in &Service::all() {
+        if let Some(path) = url.strip_prefix(service.mojang_url()) {
+            return mirrors(*service).iter().map(|x| format!("{}{}", x, path)).collect();
+        }
+    }
+    vec![url.to_string()]
+}
+
+static CACHE: Mutex<Option<Arc<http::DiskCache>>> = Mutex::new(None);
+
+/// Set a disk cache for GET requests, e.g. for the blocked servers list, or
+/// None to not use one
+///
+/// Only responses with an ETag or Last-Modified header are cached, and are
+/// revalidated with the API on each request, see http::DiskCache.
+pub fn set_cache(cache: Option<Arc<http::DiskCache>>) {
+    if let Ok(mut x) = CACHE.lock() {
+        *x = cache;
+    }
+}
+
+/// The families of Mojang endpoints, each limited to a number of requests
+/// in flight at once
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum EndpointFamily {
+    /// Looking up a profile by UUID, e.g. UUIDToProfile, which is limited
+    /// the most strictly by Mojang
+    Profile,
+    /// Looking up a single name or the name history, e.g. NameToUUID
+    NameLookup,
+    /// Looking up many names at once, i.e. PlayernamesToUUIDs
+    BulkNameLookup,
+    /// The authserver, e.g. Authenticate and AuthenticateRefresh
+    Auth,
+    /// Joining and checking joins, i.e. SessionJoin and SessionHasJoined
+    Session,
+    /// Everything else, e.g. APIStatus and BlockedServers
+    Other,
+}
+impl EndpointFamily {
+    /// Get the family of a Mojang API URL
+    pub fn of_url(url: &str) -> Self {
+        let path = match url.find("://") {
+            Some(i) => &url[i + 3..],
+            None => url,
+        };
+        let (host, path) = match path.find('/') {
+            Some(i) => path.split_at(i),
+            None => (path, ""),
+        };
+        if host.starts_with("authserver.") {
+            EndpointFamily::Auth
+        } else if path.starts_with("/session/minecraft/profile/") {
+            EndpointFamily::Profile
+        } else if path.starts_with("/session/minecraft/") {
+            EndpointFamily::Session
+        } else if path.starts_with("/users/profiles/minecraft/") ||
+                  (path.starts_with("/user/profiles/") && path.ends_with("/names")) {
+            EndpointFamily::NameLookup
+        } else if path.starts_with("/profiles/minecraft") {
+            EndpointFamily::BulkNameLookup
+        } else {
+            EndpointFamily::Other
+        }
+    }
+
+    /// Get the number of requests sent at once by default, None for no
+    /// limit
+    ///
+    /// Servers verify every joining player with SessionHasJoined, so
+    /// Session allows the most.
+    pub fn default_limit(&self) -> Option<usize> {
+        Some(match *self {
+                 EndpointFamily::Profile => 1,
+                 EndpointFamily::NameLookup => 2,
+                 EndpointFamily::BulkNameLookup => 2,
+                 EndpointFamily::Auth => 1,
+                 EndpointFamily::Session => 8,
+                 EndpointFamily::Other => 4,
+             })
+    }
+}
+
+/* Limits the number of requests in flight per endpoint family */
+struct Limiter {
+    /* The family, its limit, and how many requests are in flight */
+    state: Mutex<Vec<(EndpointFamily, Option<usize>, usize)>>,
+    freed: Condvar,
+}
+impl Limiter {
+    const fn new() -> Self {
+        Limiter {
+            state: Mutex::new(Vec::new()),
+            freed: Condvar::new(),
+        }
+    }
+
+    fn entry(state: &mut Vec<(EndpointFamily, Option<usize>, usize)>,
+             family: EndpointFamily)
+             -> &mut (EndpointFamily, Option<usize>, usize) {
+        match state.iter().position(|x| x.0 == family) {
+            Some(i) => &mut state[i],
+            None => {
+                state.push((family, family.default_limit(), 0));
+                state.last_mut().expect("Limiter entry was just pushed")
+            },
+        }
+    }
+
+    fn set_limit(&self, family: EndpointFamily, limit: Option<usize>) {
+        if let Ok(mut state) = self.state.lock() {
+            Limiter::entry(&mut state, family).1 = limit.map(|x| x.max(1));
+        }
+        self.freed.notify_all();
+    }
+
+    fn limit(&self, family: EndpointFamily) -> Option<usize> {
+        match self.state.lock() {
+            Ok(mut state) => Limiter::entry(&mut state, family).1,
+            Err(_) => family.default_limit(),
+        }
+    }
+
+    /* Wait until a request to the family may be sent. The returned permit
+     * must be kept until the request is done. */
+    fn acquire(&self, family: EndpointFamily) -> Permit<'_> {
+        if let Ok(mut state) = self.state.lock() {
+            loop {
+                let entry = Limiter::entry(&mut state, family);
+                if entry.1.is_none_or(|x| entry.2 < x) {
+                    entry.2 += 1;
+                    break;
+                }
+                state = match self.freed.wait(state) {
+                    Ok(x) => x,
+                    Err(_) => break,
+                };
+            }
+        }
+        Permit {
+            limiter: self,
+            family,
+        }
+    }
+
+    #[cfg(test)]
+    fn try_acquire(&self, family: EndpointFamily) -> Option<Permit<'_>> {
+        let mut state = self.state.lock().ok()?;
+        let entry = Limiter::entry(&mut state, family);
+        if entry.1.is_some_and(|x| entry.2 >= x) {
+            return None;
+        }
+        entry.2 += 1;
+        Some(Permit {
+                 limiter: self,
+                 family,
+             })
+    }
+}
+
+struct Permit<'a> {
+    limiter: &'a Limiter,
+    family: EndpointFamily,
+}
+impl<'a> Drop for Permit<'a> {
+    fn drop(&mut self) {
+        if let Ok(mut state) = self.limiter.state.lock() {
+            let entry = Limiter::entry(&mut state, self.family);
+            entry.2 = entry.2.saturating_sub(1);
+        }
+        self.limiter.freed.notify_all();
+    }
+}
+
+static LIMITER: Limiter = Limiter::new();
+
+/// Set how many requests to the family of endpoints are sent at once, or
+/// None for no limit. Other requests wait for them to finish.
+///
+/// The limit applies to all threads of the process, and to requests sent to
+/// mirrors too, see set_mirrors().
+pub fn set_concurrency_limit(family: EndpointFamily, limit: Option<usize>) {
+    LIMITER.set_limit(family, limit)
+}
+
+/// Get how many requests to the family of endpoints are sent at once, see
+/// set_concurrency_limit()
+pub fn concurrency_limit(family: EndpointFamily) -> Option<usize> {
+    LIMITER.limit(family)
+}
+
+/// How long requests wait after a 429 response without a Retry-After header
+pub const DEFAULT_COOLDOWN: Duration = Duration::from_secs(10);
+
+/* The cooldown after a 429 response, shared by all requests */
+struct Cooldown {
+    until: Mutex<Option<Instant>>,
+    max_wait: Mutex<Duration>,
+}
+impl Cooldown {
+    const fn new() -> Self {
+        Cooldown {
+            until: Mutex::new(None),
+            max_wait: Mutex::new(Duration::from_secs(60)),
+        }
+    }
+
+    fn remaining(&self) -> Option<Duration> {
+        let until = self.until.lock().ok().and_then(|x| *x)?;
+        until.checked_duration_since(Instant::now()).filter(|x| *x > Duration::from_secs(0))
+    }
+
+    /* Start the cooldown asked for by a 429 response, unless a longer one
+     * is already running */
+    fn start(&self, response: &http::Response) {
+        let duration = response.get_header("Retry-After")
+            .and_then(|x| x.trim().parse().ok())
+            .map(Duration::from_secs)
+            .unwrap_or(DEFAULT_COOLDOWN);
+        let until = Instant::now() + duration;
+        if let Ok(mut x) = self.until.lock() {
+            if x.is_none_or(|x| x < until) {
+                *x = Some(until);
+            }
+        }
+    }
+
+    /* Wait for the cooldown to pass, with some jitter, or fail if it's
+     * longer than the maximum wait */
+    fn wait(&self) -> Result<()> {
+        let remaining = match self.remaining() {
+            Some(x) => x,
+            None => return Ok(()),
+        };
+        let max_wait = self.max_wait.lock().map(|x| *x).unwrap_or_default();
+        if remaining > max_wait {
+            bail!(ErrorKind::RateLimited(remaining));
+        }
+        thread::sleep(remaining + utils::jitter(remaining / 4 + Duration::from_millis(500)));
+        Ok(())
+    }
+}
+
+static COOLDOWN: Cooldown = Cooldown::new();
+
+/// Get how much longer requests wait because of a 429 response, if at all
+pub fn cooldown_remaining() -> Option<Duration> {
+    COOLDOWN.remaining()
+}
+
+/// Set the longest a request waits for a cooldown after a 429 response,
+/// failing with ErrorKind::RateLimited if it would have to wait longer. The
+/// default is a minute.
+pub fn set_max_cooldown_wait(max_wait: Duration) {
+    if let Ok(mut x) = COOLDOWN.max_wait.lock() {
+        *x = max_wait;
+    }
+}
+
+/* Perform the request, trying the mirrors of the service in order */
+fn perform(request: http::Request) -> Result<http::Response> {
+    COOLDOWN.wait()?;
+    let _permit = LIMITER.acquire(EndpointFamily::of_url(&request.url));
+    let mut last = None;
+    for url in candidate_urls(&request.url) {
+        let cache = CACHE.lock().ok().and_then(|x| x.clone());
+        let res = match cache {
+            /* Responses to authorized requests depend on the account, but
+             * the cache only goes by the URL */
+            Some(ref cache) if request.method == http::Method::Get &&
+                               request.get_header("Authorization").is_none() => {
+                cache.get(&url)
+            },
+            _ => {
+                let mut request = request.clone();
+                request.url = url;
+                http::perform(request)
+            },
+        };
+        if let Ok(ref x) = res {
+            if x.status == 429 {
+                COOLDOWN.start(x);
+            }
+        }
+        match res {
+            Ok(ref x) if x.status < 500 => return res,
+            _ => last = Some(res),
+        }
+    }
+    match last {
+        Some(x) => x,
+        None => bail!("No URLs to send the request to"),
+    }
+}
+
 /// Helper function for performing a GET request to the given URL, returning
 /// the response content
 fn get_request(url: &str) -> Result<String> {
-    let mut handle = Easy::new();
-    handle.url(url)?;
-    handle.fail_on_error(true)?;
-    let mut response = Vec::new();
-    {
-        let mut transfer = handle.transfer();
-        transfer
-            .write_function(|data| {
-                                response.extend_from_slice(data);
-                                Ok(data.len())
-                            })?;
-        transfer.perform()?;
-    }
-    Ok(String::from_utf8(response)?)
+    check_response(perform(http::Request::get(url))?)
 }
 
 /// Helper function for performing a POST request to the given URL,
 /// posting the given data to it, and returning the response content.
 fn post_request(url: &str, post: &str) -> Result<String> {
-    let mut handle = Easy::new();
-    handle.url(url)?;
-    handle.fail_on_error(true)?;
-    let mut headers = List::new();
-    headers.append("Content-Type: application/json")?;
-    handle.http_headers(headers)?;
-    handle.post_fields_copy(post.as_bytes())?;
-    handle.post(true)?;
-    let mut response = Vec::new();
-    {
-        let mut transfer = handle.transfer();
-        transfer
-            .write_function(|data| {
-                                response.extend_from_slice(data);
-                                Ok(data.len())
-                            })?;
-        transfer.perform()?;
-    }
-    Ok(String::from_utf8(response)?)
+    let request = http::Request::post(url, post).header("Content-Type", "application/json");
+    check_response(perform(request)?)
+}
+
+/* Turn HTTP error statuses into errors */
+fn check_response(response: http::Response) -> Result<String> {
+    if response.status >= 400 {
+        return Err(api_error(response.status, &String::from_utf8_lossy(&response.body)));
+    }
+    response.into_text()
+}
+
+/// Turn an error response from a Mojang API into an Error.
+///
+/// The authserver tells apart some situations needing a different login
+/// path only in its error messages, which are recognized here and turned
+/// into their own ErrorKinds, e.g. ErrorKind::MigratedToMicrosoft.
+fn api_error(status: u32, body: &str) -> Error {
+    #[derive(Deserialize)]
+    struct ErrorResponse {
+        #[serde(default)]
+        error: String,
+        #[serde(default, rename = "errorMessage")]
+        error_message: String,
+        cause: Option<String>,
+    }
+    let res = match serde_json::from_str::<ErrorResponse>(body) {
+        Ok(x) => x,
+        Err(_) => {
+            return ErrorKind::MojangApi(status, String::new(), body.to_string(), None).into()
+        },
+    };
+    let message = res.error_message.to_lowercase();
+    let message_has = |x: &[&str]| x.iter().any(|x| message.contains(x));
+    let forbidden = res.error == "ForbiddenOperationException";
+    let kind = if res.error == "InsufficientPrivilegesException" {
+        ErrorKind::InsufficientPrivileges(res.error_message)
+    } else if message_has(&["microsoft"]) {
+        ErrorKind::MigratedToMicrosoft(res.error_message)
+    } else if res.cause.as_deref() == Some("UserMigratedException") ||
+              message_has(&["use email"]) {
+        ErrorKind::UseEmail(res.error_message)
+    } else if message_has(&["legacy"]) {
+        ErrorKind::LegacyAccount(res.error_message)
+    } else if message_has(&["security", "challenge"]) {
+        ErrorKind::SecurityChallengeRequired(res.error_message)
+    } else if forbidden && message_has(&["invalid token"]) {
+        ErrorKind::InvalidToken(res.error_message)
+    } else if forbidden && message_has(&["invalid credentials"]) {
+        ErrorKind::InvalidCredentials(res.error_message)
+    } else {
+        ErrorKind::MojangApi(status, res.error, res.error_message, res.cause)
+    };
+    kind.into()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::cell::RefCell;
+    use std::collections::VecDeque;
+
+    thread_local! {
+        static RECORDED: RefCell<Option<Vec<http::Request>>> = const { RefCell::new(None) };
+    }
+
+    /* Records and blocks the requests made on threads recording them */
+    struct Recorder;
+    impl http::Interceptor for Recorder {
+        fn before_request(&self, request: &mut http::Request) -> Result<()> {
+            RECORDED.with(|x| match *x.borrow_mut() {
+                Some(ref mut x) => {
+                    x.push(request.clone());
+                    bail!("Blocked by the test")
+                },
+                None => Ok(()),
+            })
+        }
+    }
+
+    /* Get the HTTP requests made by performing the request, without sending
+     * them. New ways of performing requests should be checked against this. */
+    fn requests_made<R: Request>(request: &R) -> Vec<http::Request> {
+        let recorder: Arc<dyn http::Interceptor> = Arc::new(Recorder);
+        http::add_interceptor(recorder.clone());
+        RECORDED.with(|x| *x.borrow_mut() = Some(Vec::new()));
+        assert!(request.perform().is_err());
+        http::remove_interceptor(&recorder);
+        RECORDED.with(|x| x.borrow_mut().take().unwrap_or_default())
+    }
+
+    #[test]
+    fn skin_requests() {
+        let requests = requests_made(&ChangeSkin::new("token".to_string(),
+                                                      "https://example.com/skin.png".to_string(),
+                                                      SkinModel::Slim));
+        assert_eq!((requests[0].method, requests[0].url.as_str()), (http::Method::Post, SKINS_URL));
+        assert_eq!(requests[0].get_header("Authorization"), Some("Bearer token"));
+        assert!(requests[0].body_str().unwrap().contains(r#""variant":"slim""#));
+
+        let requests = requests_made(&ResetSkin::new("token".to_string()));
+        assert_eq!((requests[0].method, requests[0].url.as_str()),
+                   (http::Method::Delete, ACTIVE_SKIN_URL));
+
+        /* The signature and the start of an IHDR chunk of a 64x32 image */
+        let mut png = PNG_SIGNATURE.to_vec();
+        png.extend_from_slice(&[0, 0, 0, 13]);
+        png.extend_from_slice(b"IHDR");
+        png.extend_from_slice(&[0, 0, 0, 64, 0, 0, 0, 32]);
+        let requests = requests_made(&UploadSkin::new("token".to_string(),
+                                                      png.clone(),
+                                                      SkinModel::Classic));
+        let content_type = requests[0].get_header("Content-Type").unwrap();
+        let boundary = &content_type[content_type.find("boundary=").unwrap() + 9..];
+        let body = requests[0].body.as_ref().unwrap();
+        assert!(body.starts_with(format!("--{}\r\n", boundary).as_bytes()));
+        assert!(body.ends_with(format!("\r\n--{}--\r\n", boundary).as_bytes()));
+        assert!(body.windows(png.len()).any(|x| x == &png[..]));
+        assert!(String::from_utf8_lossy(body).contains("name=\"variant\"\r\n\r\nclassic\r\n"));
+
+        png[23] = 16;
+        assert!(check_skin(&png).is_err());
+        assert!(check_skin(b"GIF89a").is_err());
+    }
+
+    #[test]
+    fn security_requests() {
+        let requests = requests_made(&SecurityLocationCheck::new("token".to_string()));
+        assert_eq!((requests[0].method, requests[0].url.as_str()),
+                   (http::Method::Get, SECURITY_LOCATION_URL));
+        assert_eq!(requests[0].get_header("Authorization"), Some("Bearer token"));
+
+        let challenges: Vec<SecurityChallenge> = serde_json::from_str(
+            r#"[{"answer":{"id":123},"question":{"id":1,"question":"Your pet's name?"}},
+                {"answer":{"id":456},"question":{"id":2,"question":"Favorite movie?"}}]"#)
+            .unwrap();
+        assert_eq!(challenges[1].question.question, "Favorite movie?");
+        let answer = SecurityAnswer::from_challenges("token".to_string(),
+                                                     &challenges,
+                                                     vec!["Rex".to_string(), "Up".to_string()]);
+        let requests = requests_made(&answer);
+        assert_eq!((requests[0].method, requests[0].url.as_str()),
+                   (http::Method::Post, SECURITY_LOCATION_URL));
+        assert_eq!(requests[0].body_str(),
+                   Some(r#"[{"answer":"Rex","id":123},{"answer":"Up","id":456}]"#));
+    }
+
+    #[test]
+    fn request_urls() {
+        let check = |requests: Vec<http::Request>, url: &str, post: bool| {
+            assert_eq!(requests.len(), 1);
+            assert_eq!(requests[0].url, url);
+            assert_eq!(requests[0].body.is_some(), post);
+        };
+        /* Not APIStatus, mirror_urls changes the status service mirrors */
+        check(requests_made(&BlockedServers::new()),
+              "https://sessionserver.mojang.com/blockedservers",
+              false);
+        check(requests_made(&NameToUUID::new("Notch".to_string(), None)),
+              "https://api.mojang.com/users/profiles/minecraft/Notch",
+              false);
+        check(requests_made(&UUIDToProfile::new("069a79f444e94726a5befca90e38aaf5".to_string(),
+                                                false)),
+              "https://sessionserver.mojang.com/session/minecraft/profile/069a79f444e94726a5befca90e38aaf5",
+              false);
+        check(requests_made(&Authenticate::new("bob".to_string(), "hunter2".to_string())),
+              "https://authserver.mojang.com/authenticate",
+              true);
+        check(requests_made(&AuthenticateValidate::new("token".to_string(), None)),
+              "https://authserver.mojang.com/validate",
+              true);
+        /* Invalid usernames fail before making a request */
+        assert!(requests_made(&NameToUUID::new("N".to_string(), None)).is_empty());
+    }
+
+    #[test]
+    fn mirror_urls() {
+        let url = "https://status.mojang.com/check";
+        assert_eq!(candidate_urls(url), vec![url.to_string()]);
+        set_mirrors(Service::Status,
+                    vec!["http://mirror.invalid/mojang/".to_string(),
+                         Service::Status.mojang_url().to_string()]);
+        assert_eq!(candidate_urls(url),
+                   vec!["http://mirror.invalid/mojang/check".to_string(), url.to_string()]);
+        set_mirrors(Service::Status, Vec::new());
+        assert_eq!(mirrors(Service::Status), vec![Service::Status.mojang_url().to_string()]);
+        assert_eq!(candidate_urls("https://example.com/x"), vec!["https://example.com/x".to_string()]);
+    }
+
+    #[test]
+    fn concurrency_limits() {
+        assert_eq!(EndpointFamily::of_url("https://sessionserver.mojang.com/session/minecraft/profile/069a79f444e94726a5befca90e38aaf5"),
+                   EndpointFamily::Profile);
+        assert_eq!(EndpointFamily::of_url("https://sessionserver.mojang.com/session/minecraft/hasJoined?username=a"),
+                   EndpointFamily::Session);
+        assert_eq!(EndpointFamily::of_url("https://api.mojang.com/profiles/minecraft"),
+                   EndpointFamily::BulkNameLookup);
+        assert_eq!(EndpointFamily::of_url("https://authserver.mojang.com/validate"),
+                   EndpointFamily::Auth);
+        assert_eq!(EndpointFamily::of_url("https://status.mojang.com/check"),
+                   EndpointFamily::Other);
+
+        let limiter = Limiter::new();
+        let first = limiter.acquire(EndpointFamily::Profile);
+        assert!(limiter.try_acquire(EndpointFamily::Profile).is_none());
+        assert!(limiter.try_acquire(EndpointFamily::Session).is_some());
+        drop(first);
+        let _second = limiter.try_acquire(EndpointFamily::Profile).unwrap();
+        limiter.set_limit(EndpointFamily::Profile, None);
+        assert!(limiter.try_acquire(EndpointFamily::Profile).is_some());
+        assert_eq!(limiter.limit(EndpointFamily::Profile), None);
+    }
+
+    #[test]
+    fn cooldown() {
+        let cooldown = Cooldown::new();
+        assert_eq!(cooldown.remaining(), None);
+        assert!(cooldown.wait().is_ok());
+        cooldown.start(&http::Response {
+                           status: 429,
+                           headers: vec![("Retry-After".to_string(), "120".to_string())],
+                           body: Vec::new(),
+                       });
+        assert!(cooldown.remaining().unwrap() > Duration::from_secs(110));
+        match cooldown.wait() {
+            Err(Error(ErrorKind::RateLimited(_), _)) => (),
+            x => panic!("{:?}", x),
+        }
+    }
+
+    #[test]
+    fn lookup_invalid_uuid() {
+        /* Fails before sending any request */
+        assert!(lookup_profile("Notch").is_err());
+    }
+
+    #[test]
+    fn api_errors() {
+        let kind = |body| api_error(403, body).0;
+        match kind(r#"{"error":"ForbiddenOperationException","errorMessage":"Invalid credentials. Account migrated, use email as username.","cause":"UserMigratedException"}"#) {
+            ErrorKind::UseEmail(_) => (),
+            x => panic!("{:?}", x),
+        }
+        match kind(r#"{"error":"ForbiddenOperationException","errorMessage":"Invalid credentials. Invalid username or password."}"#) {
+            ErrorKind::InvalidCredentials(_) => (),
+            x => panic!("{:?}", x),
+        }
+        match kind(r#"{"error":"ForbiddenOperationException","errorMessage":"Invalid token."}"#) {
+            ErrorKind::InvalidToken(_) => (),
+            x => panic!("{:?}", x),
+        }
+        match kind(r#"{"error":"ForbiddenOperationException","errorMessage":"This account has been migrated to a Microsoft account."}"#) {
+            ErrorKind::MigratedToMicrosoft(_) => (),
+            x => panic!("{:?}", x),
+        }
+        match kind(r#"{"error":"InsufficientPrivilegesException","errorMessage":"Invalid token."}"#) {
+            ErrorKind::InsufficientPrivileges(_) => (),
+            x => panic!("{:?}", x),
+        }
+        match kind("Not found") {
+            ErrorKind::MojangApi(403, _, _, None) => (),
+            x => panic!("{:?}", x),
+        }
+    }
+
+    /* Answers session joins with the given responses in order, and sends
+     * the rest with curl */
+    struct JoinStub(Mutex<VecDeque<(u32, &'static str)>>);
+    impl http::Backend for JoinStub {
+        fn send(&self, request: &http::Request) -> Result<http::Response> {
+            if request.url != SessionJoin::get_endpoint() {
+                return http::CurlBackend.send(request);
+            }
+            let (status, body) = self.0.lock().unwrap().pop_front().expect("Unexpected join");
+            Ok(http::Response {
+                   status,
+                   headers: Vec::new(),
+                   body: body.as_bytes().to_vec(),
+               })
+        }
+    }
+
+    #[test]
+    fn session_join_retries() {
+        let _lock = http::BACKEND_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+        let join = SessionJoin::new("token".to_string(), "uuid".to_string(), "", &[], &[]);
+        let invalid_token = r#"{"error":"ForbiddenOperationException","errorMessage":"Invalid token."}"#;
+        let forbidden = r#"{"error":"InsufficientPrivilegesException","errorMessage":"Forbidden"}"#;
+
+        let stub = Arc::new(JoinStub(Mutex::new(vec![(403, invalid_token), (503, ""), (204, "")]
+                                                    .into_iter()
+                                                    .collect())));
+        let backend: Arc<dyn http::Backend> = stub.clone();
+        http::set_backend(Some(backend));
+        let retried = join.perform();
+        stub.0.lock().unwrap().push_back((403, forbidden));
+        stub.0.lock().unwrap().push_back((204, ""));
+        let forbidden = join.perform();
+        http::set_backend(None);
+
+        assert!(retried.is_ok());
+        match forbidden.map_err(|e| e.0) {
+            Err(ErrorKind::InsufficientPrivileges(_)) => (),
+            x => panic!("{:?}", x),
+        }
+        /* Accounts without privileges aren't retried */
+        assert_eq!(stub.0.lock().unwrap().len(), 1);
+    }
+
+    #[test]
+    fn json_models() {
+        let json = r#"[{"name":"Gold"},{"name":"Diamond","changedToAt":1414059749000}]"#;
+        let history = Vec::<NameHistory>::from_json_str(json).unwrap();
+        assert_eq!(history[1].changedToAt,
+                   Some(::utils::from_epoch_millis(1_414_059_749_000)));
+        assert_eq!(history.to_json_string().unwrap(), json);
+
+        let json = r#"{"id":"069a79f444e94726a5befca90e38aaf5","name":"Notch","properties":[{"name":"textures","value":"e30="}],"profileActions":[],"legacyProfile":false}"#;
+        let profile = Profile::from_json_str(json).unwrap();
+        assert_eq!(profile.extra["legacyProfile"], false);
+        assert_eq!(profile.to_json_string().unwrap(), json);
+        assert_eq!(NameUUID::from_json_str(r#"{"id":"a","name":"b"}"#).unwrap()
+                       .to_json_string().unwrap(),
+                   r#"{"id":"a","name":"b"}"#);
+    }
+
+    #[test]
+    fn statistics() {
+        let request = Statistics::minecraft().key(MetricKey::from("item_sold_dungeons"))
+            .key("item_sold_legends".into())
+            .key(MetricKey::ItemSoldMinecraft);
+        assert_eq!(request.keys(),
+                   &[MetricKey::ItemSoldMinecraft,
+                     MetricKey::PrepaidCardRedeemedMinecraft,
+                     MetricKey::ItemSoldDungeons,
+                     MetricKey::Other("item_sold_legends".to_string())]);
+        let requests = requests_made(&request);
+        assert!(requests[0].body_str().unwrap().contains(r#""item_sold_legends""#));
+        assert!(Statistics::new(vec![]).perform().is_err());
+
+        let res = StatisticsResponse::from_json_str(r#"{"total":10,"last24h":1,"saleVelocityPerSeconds":0.5,"new":true}"#)
+            .unwrap();
+        assert_eq!(res.total, 10);
+        assert_eq!(res.extra["new"], true);
+    }
+
+    #[test]
+    fn player_report() {
+        let textures = r#"{"timestamp":1,"profileId":"069a79f444e94726a5befca90e38aaf5","profileName":"Notch","textures":{"SKIN":{"url":"http://textures.minecraft.net/texture/1","metadata":{"model":"slim"}}}}"#;
+        let json = format!(r#"{{"id":"069a79f444e94726a5befca90e38aaf5","name":"Notch","properties":[{{"name":"textures","value":"{}"}}]}}"#,
+                           base64::encode_block(textures.as_bytes()));
+        let report = PlayerReport::from_profile(Profile::from_json_str(&json).unwrap()).unwrap();
+        let textures = report.textures.clone().unwrap();
+        assert!(textures.skin.unwrap().is_slim());
+        assert!(textures.cape.is_none());
+
+        REPORTS.lock().unwrap().push(report);
+        /* Served from the cache, without any requests */
+        assert_eq!(PlayerReport::fetch("notch").unwrap().uuid.to_simple(),
+                   "069a79f444e94726a5befca90e38aaf5");
+        assert_eq!(PlayerReport::fetch("069a79f4-44e9-4726-a5be-fca90e38aaf5").unwrap().name,
+                   "Notch");
+    }
 }
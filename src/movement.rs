@@ -0,0 +1,184 @@
+//! Basic validation of the movement of players
+//!
+//! Give a Server MovementLimits with server.set_movement_limits(), and it
+//! checks the PlayerPosition and PlayerPositionAndLook packets of the
+//! client against them. Nothing is done about a violation apart from
+//! reporting it with server.poll_violations(), so that what happens to
+//! cheaters is up to the server, e.g. teleporting them back, kicking them
+//! after a number of violations or just logging them.
+//!
+//! The checks are deliberately simple, and don't know about the world, so
+//! e.g. falling, knockback, elytra and potions can make honest players
+//! exceed low limits. The defaults are lenient.
+
+use entity::Location;
+use serverbound::ServerboundPacket;
+
+use std::time::{Duration, Instant};
+
+/// The limits the movement of a player is checked against. Set a limit to
+/// None to not check it.
+#[derive(Debug, Clone, PartialEq)]
+pub struct MovementLimits {
+    /// The maximum horizontal speed in blocks per second. Sprint jumping is
+    /// about 7.
+    pub max_speed: Option<f64>,
+    /// The maximum distance of a single move, like vanilla's "moved too
+    /// quickly" check
+    pub max_move_distance: Option<f64>,
+    /// The maximum amount of consecutive moves in the air without falling,
+    /// like vanilla's "flying is not enabled" check. Should be None for
+    /// players that are allowed to fly.
+    pub max_air_moves: Option<u32>,
+    /// The maximum absolute x and z coordinate
+    pub world_border: f64,
+    /// The range of valid y coordinates
+    pub min_y: f64,
+    pub max_y: f64,
+}
+impl Default for MovementLimits {
+    fn default() -> Self {
+        MovementLimits {
+            max_speed: Some(20.0),
+            max_move_distance: Some(10.0),
+            max_air_moves: Some(80),
+            world_border: 30000000.0,
+            min_y: -512.0,
+            max_y: 4096.0,
+        }
+    }
+}
+
+/// A move that broke the limits
+#[derive(Debug, Clone, PartialEq)]
+pub enum Violation {
+    /// The position isn't a finite number, or is outside the world
+    InvalidPosition { x: f64, y: f64, z: f64 },
+    /// The player moved faster than max_speed, in blocks per second
+    TooFast { speed: f64, max: f64 },
+    /// A single move was longer than max_move_distance
+    TooFar { distance: f64, max: f64 },
+    /// The player has been in the air without falling for more than
+    /// max_air_moves moves
+    Flying { moves: u32 },
+}
+
+/// Checks the moves of a player, see the module documentation
+#[derive(Debug, Clone)]
+pub struct MovementValidator {
+    limits: MovementLimits,
+    /* The last valid position and when it was received */
+    last: Option<(Location, Instant)>,
+    air_moves: u32,
+}
+impl MovementValidator {
+    pub fn new(limits: MovementLimits) -> Self {
+        MovementValidator {
+            limits,
+            last: None,
+            air_moves: 0,
+        }
+    }
+
+    pub fn limits(&self) -> &MovementLimits {
+        &self.limits
+    }
+
+    /// Set the position of the player, e.g. after teleporting it, so the
+    /// next move isn't seen as too far
+    pub fn reset(&mut self, location: Option<Location>) {
+        self.last = location.map(|x| (x, Instant::now()));
+        self.air_moves = 0;
+    }
+
+    /// Check a packet from the client, returning the violations
+    pub fn check(&mut self, packet: &ServerboundPacket) -> Vec<Violation> {
+        self.check_at(packet, Instant::now())
+    }
+
+    fn check_at(&mut self, packet: &ServerboundPacket, now: Instant) -> Vec<Violation> {
+        let (x, y, z, on_ground) = match *packet {
+            ServerboundPacket::PlayerPosition(ref p) => {
+                (*p.get_x(), *p.get_y(), *p.get_z(), *p.get_on_ground())
+            },
+            ServerboundPacket::PlayerPositionAndLook(ref p) => {
+                (*p.get_x(), *p.get_y(), *p.get_z(), *p.get_on_ground())
+            },
+            _ => return Vec::new(),
+        };
+        let limits = &self.limits;
+        if !x.is_finite() || !y.is_finite() || !z.is_finite() ||
+           x.abs() > limits.world_border || z.abs() > limits.world_border ||
+           y < limits.min_y || y > limits.max_y {
+            return vec![Violation::InvalidPosition { x, y, z }];
+        }
+
+        let mut ret = Vec::new();
+        let mut falling = false;
+        if let Some((last, at)) = self.last {
+            let (dx, dy, dz) = (x - last.x, y - last.y, z - last.z);
+            falling = dy < 0.0;
+            let distance = (dx * dx + dy * dy + dz * dz).sqrt();
+            if let Some(max) = limits.max_move_distance.filter(|&max| distance > max) {
+                ret.push(Violation::TooFar { distance, max });
+            }
+            /* At least a tick, so that packets arriving in a burst after
+             * lag don't count as fast */
+            let elapsed = now.saturating_duration_since(at).max(Duration::from_millis(50));
+            let speed = (dx * dx + dz * dz).sqrt() / elapsed.as_secs_f64();
+            if let Some(max) = limits.max_speed.filter(|&max| speed > max) {
+                ret.push(Violation::TooFast { speed, max });
+            }
+        }
+
+        if on_ground || falling {
+            self.air_moves = 0;
+        } else {
+            self.air_moves += 1;
+            if limits.max_air_moves == Some(self.air_moves - 1) {
+                ret.push(Violation::Flying { moves: self.air_moves });
+            }
+        }
+
+        let mut location = self.last.map(|x| x.0).unwrap_or_default();
+        location.x = x;
+        location.y = y;
+        location.z = z;
+        location.on_ground = on_ground;
+        self.last = Some((location, now));
+        ret
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use serverbound::PlayerPosition;
+
+    #[test]
+    fn violations() {
+        let mut v = MovementValidator::new(MovementLimits {
+                                               max_air_moves: Some(2),
+                                               ..MovementLimits::default()
+                                           });
+        let start = Instant::now();
+        let at = |ticks: u32| start + Duration::from_millis(50) * ticks;
+        assert_eq!(v.check_at(&PlayerPosition::new(0.0, 64.0, 0.0, true), at(0)), vec![]);
+        assert_eq!(v.check_at(&PlayerPosition::new(0.2, 64.0, 0.0, true), at(1)), vec![]);
+        assert_eq!(v.check_at(&PlayerPosition::new(3.2, 64.0, 0.0, true), at(2)),
+                   vec![Violation::TooFast { speed: 60.0, max: 20.0 }]);
+        assert_eq!(v.check_at(&PlayerPosition::new(20.0, 64.0, 0.0, true), at(1000)),
+                   vec![Violation::TooFar { distance: 16.8, max: 10.0 }]);
+
+        assert_eq!(v.check_at(&PlayerPosition::new(20.0, 65.0, 0.0, false), at(1001)), vec![]);
+        assert_eq!(v.check_at(&PlayerPosition::new(20.0, 65.0, 0.0, false), at(1002)), vec![]);
+        assert_eq!(v.check_at(&PlayerPosition::new(20.0, 65.0, 0.0, false), at(1003)),
+                   vec![Violation::Flying { moves: 3 }]);
+        assert_eq!(v.check_at(&PlayerPosition::new(20.0, 65.0, 0.0, false), at(1004)), vec![]);
+        assert_eq!(v.check_at(&PlayerPosition::new(20.0, 64.5, 0.0, false), at(1005)), vec![]);
+
+        assert_eq!(v.check_at(&PlayerPosition::new(f64::NAN, 64.0, 0.0, true), at(1006)).len(), 1);
+        assert_eq!(v.check_at(&PlayerPosition::new(4e7, 64.0, 0.0, true), at(1007)),
+                   vec![Violation::InvalidPosition { x: 4e7, y: 64.0, z: 0.0 }]);
+    }
+}
@@ -0,0 +1,850 @@
+//! Microsoft account (MSA) authentication
+//!
+//! Microsoft accounts log in to Minecraft through a chain of token
+//! exchanges: the Microsoft OAuth refresh token gets a Microsoft access
+//! token, which gets an Xbox Live token, which gets an XSTS token, which
+//! finally gets a Minecraft access token from minecraftservices.com. The
+//! Minecraft access token expires after 24 hours, so long-running clients
+//! need to redo the chain from time to time, which MsaSession takes care of.
+//!
+//! Getting the initial refresh token requires the user to log in with a
+//! browser, using the client ID of an Azure application with Xbox Live
+//! access. device_code_login() does this with the device code flow, where
+//! the user is shown a code to enter on a Microsoft web page. How the code is
+//! shown is up to the AuthPrompt passed to it, or DeviceCodeAuth can be
+//! used to show the code and poll for the login yourself. Programs that can open a
+//! browser can instead use the authorization code flow, and pass the code
+//! received by their redirect URI to MsaSession::from_authorization_code().
+//!
+//! The resulting access token is used like one from the Mojang Authenticate
+//! endpoint, e.g. with mojang::SessionJoin or Client::connect_authenticated().
+//! If you already have a Microsoft access token, minecraft_token() does the
+//! rest of the exchange.
+//!
+//! # Examples
+//!
+//! ```rust,no_run
+//! use ozelot::msa::MsaSession;
+//! use ozelot::Client;
+//!
+//! let mut session = MsaSession::new("my azure client id".to_string(),
+//!                                   "my refresh token".to_string());
+//! session.ensure_fresh().unwrap();
+//! let auth = session.to_authentication_response().unwrap();
+//! let mut client = Client::connect_authenticated("minecraft.example.com",
+//!                                                25565,
+//!                                                &auth).unwrap();
+//! /* Store session.refresh_token() somewhere, since it changes over time */
+//! ```
+
+use errors::{Result, ResultExt};
+use http;
+use json::{AuthenticationResponse, NameUUID, PlayerAttributes};
+
+use serde_json;
+
+use std::cmp;
+use std::io::{self, Write};
+use std::sync::{Arc, Mutex};
+use std::sync::mpsc::Sender;
+use std::thread;
+use std::time::{Duration, Instant, SystemTime};
+
+const DEVICE_CODE_URL: &str = "https://login.microsoftonline.com/consumers/oauth2/v2.0/devicecode";
+const TOKEN_URL: &str = "https://login.microsoftonline.com/consumers/oauth2/v2.0/token";
+const XBL_URL: &str = "https://user.auth.xboxlive.com/user/authenticate";
+const XSTS_URL: &str = "https://xsts.auth.xboxlive.com/xsts/authorize";
+const LOGIN_URL: &str = "https://api.minecraftservices.com/authentication/login_with_xbox";
+const PROFILE_URL: &str = "https://api.minecraftservices.com/minecraft/profile";
+const ATTRIBUTES_URL: &str = "https://api.minecraftservices.com/player/attributes";
+/* The shortest time between two refreshes by spawn_refresher() */
+const MIN_REFRESH_INTERVAL: Duration = Duration::from_secs(60);
+/// The OAuth scope needed for logging in to Xbox Live
+pub const SCOPE: &str = "XboxLive.signin offline_access";
+
+/// A Minecraft access token obtained through MSA
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MinecraftToken {
+    pub access_token: String,
+    /// When the token expires
+    pub expires_at: SystemTime,
+    /// The Minecraft profile (UUID and name) of the account
+    pub profile: NameUUID,
+}
+
+/// A Microsoft account session, see the module documentation
+///
+/// The session can be serialized with serde to persist it between runs.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MsaSession {
+    client_id: String,
+    refresh_token: String,
+    token: Option<MinecraftToken>,
+    /// How long before the token expires to refresh it
+    refresh_margin: Duration,
+}
+impl MsaSession {
+    /// Create a new session from the client ID of the Azure application and
+    /// a Microsoft OAuth refresh token.
+    pub fn new(client_id: String, refresh_token: String) -> Self {
+        MsaSession {
+            client_id,
+            refresh_token,
+            token: None,
+            refresh_margin: Duration::from_secs(10 * 60),
+        }
+    }
+
+    /// Set how long before the token expires that ensure_fresh() refreshes
+    /// it. Default 10 minutes.
+    pub fn set_refresh_margin(&mut self, margin: Duration) {
+        self.refresh_margin = margin;
+    }
+
+    /// Get the current Microsoft refresh token.
+    ///
+    /// Microsoft may hand out a new refresh token on each refresh, so this
+    /// is what you want to persist.
+    pub fn refresh_token(&self) -> &str {
+        &self.refresh_token
+    }
+
+    /// Get the current Minecraft token, if any. It may have expired.
+    pub fn token(&self) -> Option<&MinecraftToken> {
+        self.token.as_ref()
+    }
+
+    /// Whether the Minecraft token is missing or expires within the refresh
+    /// margin of the given time
+    pub fn needs_refresh(&self, now: SystemTime) -> bool {
+        match self.token {
+            Some(ref token) => now + self.refresh_margin >= token.expires_at,
+            None => true,
+        }
+    }
+
+    /// Refresh the Minecraft token if needed, see needs_refresh(). Returns
+    /// whether it was refreshed.
+    pub fn ensure_fresh(&mut self) -> Result<bool> {
+        if self.needs_refresh(SystemTime::now()) {
+            self.refresh()?;
+            Ok(true)
+        } else {
+            Ok(false)
+        }
+    }
+
+    /// Unconditionally redo the whole token exchange
+    pub fn refresh(&mut self) -> Result<()> {
+        let ms = refresh_microsoft_token(&self.client_id, &self.refresh_token)
+            .chain_err(|| "Unable to refresh the Microsoft token")?;
+        self.exchange(ms)
+    }
+
+    /// Create a session from an OAuth authorization code, as received by the
+    /// redirect URI after the user logged in with a browser. The redirect URI
+    /// must be the one the code was requested with.
+    pub fn from_authorization_code(client_id: String,
+                                   code: &str,
+                                   redirect_uri: &str)
+                                   -> Result<Self> {
+        let ms = redeem_authorization_code(&client_id, code, redirect_uri)
+            .chain_err(|| "Unable to redeem the authorization code")?;
+        let refresh_token = match ms.refresh_token {
+            Some(ref x) => x.clone(),
+            None => bail!("Microsoft did not return a refresh token"),
+        };
+        let mut session = MsaSession::new(client_id, refresh_token);
+        session.exchange(ms)?;
+        Ok(session)
+    }
+
+    /* Exchange the Microsoft token for a Minecraft token */
+    fn exchange(&mut self, ms: MicrosoftToken) -> Result<()> {
+        self.token = Some(minecraft_token(&ms.access_token)?);
+        if let Some(refresh_token) = ms.refresh_token {
+            self.refresh_token = refresh_token;
+        }
+        Ok(())
+    }
+
+    /// Get the token as an AuthenticationResponse, for use with
+    /// Client::connect_authenticated()
+    pub fn to_authentication_response(&self) -> Option<AuthenticationResponse> {
+        self.token.as_ref().map(|token| {
+            AuthenticationResponse {
+                accessToken: token.access_token.clone(),
+                clientToken: None,
+                availableProfiles: None,
+                selectedProfile: token.profile.clone(),
+            }
+        })
+    }
+
+    /// Spawn a thread that keeps the shared session fresh in the background.
+    ///
+    /// The thread sleeps until the token needs refreshing, refreshes it
+    /// (retrying every minute on failure), and exits once it holds the last
+    /// reference to the session. The session isn't locked during the
+    /// refresh, only to swap in the new token. The thread waits at least a
+    /// minute between refreshes, even if the refresh margin is longer than
+    /// the tokens last.
+    pub fn spawn_refresher(session: Arc<Mutex<MsaSession>>) -> thread::JoinHandle<()> {
+        thread::spawn(move || {
+            loop {
+                if Arc::strong_count(&session) == 1 {
+                    return;
+                }
+                let stale = match session.lock() {
+                    Ok(x) if x.needs_refresh(SystemTime::now()) => Some(x.clone()),
+                    Ok(_) => None,
+                    Err(_) => return,
+                };
+                let refreshed = stale.map(|mut x| x.refresh().map(|_| x));
+                let wait = {
+                    let mut session = match session.lock() {
+                        Ok(x) => x,
+                        Err(_) => return,
+                    };
+                    match refreshed {
+                        Some(Ok(x)) => {
+                            session.token = x.token;
+                            session.refresh_token = x.refresh_token;
+                            session.time_until_refresh(SystemTime::now())
+                        },
+                        Some(Err(_)) => Duration::from_secs(60),
+                        None => session.time_until_refresh(SystemTime::now()),
+                    }
+                };
+                let wait = cmp::max(wait, MIN_REFRESH_INTERVAL);
+                /* Sleep in short intervals so we notice being dropped */
+                let mut waited = Duration::from_secs(0);
+                while waited < wait && Arc::strong_count(&session) > 1 {
+                    let step = Duration::from_secs(1);
+                    thread::sleep(step);
+                    waited += step;
+                }
+            }
+        })
+    }
+
+    fn time_until_refresh(&self, now: SystemTime) -> Duration {
+        match self.token {
+            Some(ref token) => {
+                (token.expires_at - self.refresh_margin)
+                    .duration_since(now)
+                    .unwrap_or_else(|_| Duration::from_secs(0))
+            },
+            None => Duration::from_secs(0),
+        }
+    }
+}
+
+/// Exchange a Microsoft access token with the Xbox Live scope for a
+/// Minecraft token, going through Xbox Live and XSTS.
+///
+/// Use this if you get the Microsoft token some other way, e.g. from a
+/// launcher. The Microsoft token can't be refreshed without its refresh
+/// token, so prefer MsaSession where possible.
+pub fn minecraft_token(ms_access_token: &str) -> Result<MinecraftToken> {
+    let started = SystemTime::now();
+    let (xbl, _) = xbox_live_token(ms_access_token)
+        .chain_err(|| "Unable to get the Xbox Live token")?;
+    let (xsts, user_hash) = xsts_token(&xbl)
+        .chain_err(|| "Unable to get the XSTS token")?;
+    let login = minecraft_login(&user_hash, &xsts)
+        .chain_err(|| "Unable to log in to Minecraft with the XSTS token")?;
+    let profile = minecraft_profile(&login.access_token)
+        .chain_err(|| "Unable to get the Minecraft profile, does the account own Minecraft?")?;
+
+    Ok(MinecraftToken {
+           access_token: login.access_token,
+           expires_at: started + Duration::from_secs(login.expires_in),
+           profile: NameUUID {
+               id: profile.id,
+               name: profile.name,
+               legacy: false,
+               demo: false,
+           },
+       })
+}
+
+/// A device code for the user to enter, see device_code_login() and
+/// DeviceCodeAuth
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct DeviceCode {
+    /// The code the user must enter
+    pub user_code: String,
+    /// The URL of the page where the user enters the code
+    pub verification_uri: String,
+    /// How many seconds the code is valid for
+    pub expires_in: u64,
+    /// Instructions for the user, localized by Microsoft
+    pub message: String,
+    device_code: String,
+    interval: u64,
+}
+
+/// Presents the device code to the user during device_code_login()
+///
+/// This is implemented for closures taking a &DeviceCode.
+pub trait AuthPrompt {
+    /// Show the code to the user
+    fn show_code(&mut self, code: &DeviceCode);
+
+    /// Called between each check of whether the user has logged in. Return
+    /// true to cancel the login.
+    fn is_cancelled(&mut self) -> bool {
+        false
+    }
+
+    /// Called when the login is over, successfully or not
+    fn finished(&mut self, _success: bool) {}
+}
+impl<F: FnMut(&DeviceCode)> AuthPrompt for F {
+    fn show_code(&mut self, code: &DeviceCode) {
+        self(code)
+    }
+}
+
+/// An AuthPrompt that prints the instructions to stdout
+#[derive(Debug, Clone, Default)]
+pub struct TerminalPrompt();
+impl TerminalPrompt {
+    pub fn new() -> Self {
+        TerminalPrompt()
+    }
+}
+impl AuthPrompt for TerminalPrompt {
+    fn show_code(&mut self, code: &DeviceCode) {
+        println!("{}", code.message);
+        let _ = io::stdout().flush();
+    }
+}
+
+/// The messages sent by a ChannelPrompt
+#[derive(Debug, Clone, PartialEq)]
+pub enum PromptMessage {
+    /// Show this code to the user
+    ShowCode(DeviceCode),
+    /// The login is over, successfully (true) or not (false)
+    Finished(bool),
+}
+
+/// An AuthPrompt that sends the code over a channel, e.g. to a GUI thread.
+///
+/// The login is cancelled if the receiving end of the channel is dropped.
+#[derive(Debug, Clone)]
+pub struct ChannelPrompt {
+    sender: Sender<PromptMessage>,
+    cancelled: bool,
+}
+impl ChannelPrompt {
+    pub fn new(sender: Sender<PromptMessage>) -> Self {
+        ChannelPrompt {
+            sender,
+            cancelled: false,
+        }
+    }
+}
+impl AuthPrompt for ChannelPrompt {
+    fn show_code(&mut self, code: &DeviceCode) {
+        if self.sender.send(PromptMessage::ShowCode(code.clone())).is_err() {
+            self.cancelled = true;
+        }
+    }
+
+    fn is_cancelled(&mut self) -> bool {
+        self.cancelled
+    }
+
+    fn finished(&mut self, success: bool) {
+        let _ = self.sender.send(PromptMessage::Finished(success));
+    }
+}
+
+/// Log in with the device code flow, blocking until the user has entered
+/// the code shown by the prompt (or the code expires.)
+///
+/// client_id is the client ID of an Azure application with Xbox Live access
+/// and public client flows enabled.
+///
+/// # Examples
+///
+/// ```rust,no_run
+/// use ozelot::msa::{self, TerminalPrompt};
+///
+/// let session = msa::device_code_login("my azure client id".to_string(),
+///                                      &mut TerminalPrompt::new()).unwrap();
+/// ```
+pub fn device_code_login<P: AuthPrompt>(client_id: String,
+                                        prompt: &mut P)
+                                        -> Result<MsaSession> {
+    let res = device_code_login_inner(client_id, prompt);
+    prompt.finished(res.is_ok());
+    res
+}
+
+fn device_code_login_inner<P: AuthPrompt>(client_id: String,
+                                          prompt: &mut P)
+                                          -> Result<MsaSession> {
+    let mut auth = DeviceCodeAuth::start(client_id)?;
+    prompt.show_code(auth.device_code());
+    loop {
+        if prompt.is_cancelled() {
+            bail!("The device code login was cancelled");
+        }
+        thread::sleep(auth.time_until_poll());
+        if let Some(session) = auth.poll()? {
+            return Ok(session);
+        }
+    }
+}
+
+/// A device code login in progress, for programs that can't block in
+/// device_code_login(), e.g. bots polling from their main loop.
+///
+/// # Examples
+///
+/// ```rust,no_run
+/// use ozelot::msa::DeviceCodeAuth;
+///
+/// let mut auth = DeviceCodeAuth::start("my azure client id".to_string()).unwrap();
+/// println!("Go to {} and enter {}", auth.verification_uri(), auth.user_code());
+/// let session = loop {
+///     if let Some(session) = auth.poll().unwrap() {
+///         break session;
+///     }
+///     /* Do something else in the meantime */
+///     std::thread::sleep(std::time::Duration::from_millis(500));
+/// };
+/// println!("Access token {}", session.token().unwrap().access_token);
+/// ```
+#[derive(Debug, Clone)]
+pub struct DeviceCodeAuth {
+    client_id: String,
+    code: DeviceCode,
+    started: Instant,
+    last_poll: Instant,
+    /* How long to wait between polls, as asked by Microsoft */
+    interval: Duration,
+}
+impl DeviceCodeAuth {
+    /// Request a device code. client_id is the client ID of an Azure
+    /// application with Xbox Live access and public client flows enabled.
+    pub fn start(client_id: String) -> Result<Self> {
+        let code = request_device_code(&client_id)
+            .chain_err(|| "Unable to get a device code")?;
+        Ok(DeviceCodeAuth::new(client_id, code, Instant::now()))
+    }
+
+    fn new(client_id: String, code: DeviceCode, started: Instant) -> Self {
+        let interval = Duration::from_secs(code.interval.max(1));
+        DeviceCodeAuth {
+            client_id,
+            code,
+            started,
+            last_poll: started,
+            interval,
+        }
+    }
+
+    /// Get the device code, to show to the user
+    pub fn device_code(&self) -> &DeviceCode {
+        &self.code
+    }
+
+    /// Get the code the user must enter
+    pub fn user_code(&self) -> &str {
+        &self.code.user_code
+    }
+
+    /// Get the URL of the page where the user enters the code
+    pub fn verification_uri(&self) -> &str {
+        &self.code.verification_uri
+    }
+
+    /// Whether the code has expired without the user logging in
+    pub fn is_expired(&self) -> bool {
+        self.started.elapsed() > Duration::from_secs(self.code.expires_in)
+    }
+
+    /// Get how long until poll() will next check with Microsoft
+    pub fn time_until_poll(&self) -> Duration {
+        self.interval.saturating_sub(self.last_poll.elapsed())
+    }
+
+    /// Check whether the user has logged in, returning the session with the
+    /// Minecraft token if so.
+    ///
+    /// Microsoft is only asked once per polling interval, calling this more
+    /// often just returns None. Fails once the code has expired.
+    pub fn poll(&mut self) -> Result<Option<MsaSession>> {
+        if self.is_expired() {
+            bail!("The device code expired before the user logged in");
+        }
+        if self.time_until_poll() > Duration::from_secs(0) {
+            return Ok(None);
+        }
+        self.last_poll = Instant::now();
+
+        match poll_device_code(&self.client_id, &self.code.device_code)? {
+            DevicePoll::Pending => Ok(None),
+            DevicePoll::SlowDown => {
+                self.interval += Duration::from_secs(5);
+                Ok(None)
+            },
+            DevicePoll::Done(ms) => {
+                let refresh_token = match ms.refresh_token {
+                    Some(ref x) => x.clone(),
+                    None => bail!("Microsoft did not return a refresh token"),
+                };
+                let mut session = MsaSession::new(self.client_id.clone(), refresh_token);
+                session.exchange(ms)?;
+                Ok(Some(session))
+            },
+        }
+    }
+
+    /// Block until the user has logged in or the code expires
+    pub fn wait(mut self) -> Result<MsaSession> {
+        loop {
+            thread::sleep(self.time_until_poll());
+            if let Some(session) = self.poll()? {
+                return Ok(session);
+            }
+        }
+    }
+}
+
+enum DevicePoll {
+    Pending,
+    SlowDown,
+    Done(MicrosoftToken),
+}
+
+#[derive(Deserialize)]
+struct OAuthError {
+    error: String,
+    error_description: Option<String>,
+}
+
+fn request_device_code(client_id: &str) -> Result<DeviceCode> {
+    let body = format!("client_id={}&scope={}",
+                       http::url_encode(client_id),
+                       http::url_encode(SCOPE));
+    let res = request(DEVICE_CODE_URL,
+                      &["Content-Type: application/x-www-form-urlencoded"],
+                      Some(&body))?;
+    Ok(serde_json::from_str(&res)?)
+}
+
+fn poll_device_code(client_id: &str, device_code: &str) -> Result<DevicePoll> {
+    let body = format!("client_id={}&device_code={}&grant_type={}",
+                       http::url_encode(client_id),
+                       http::url_encode(device_code),
+                       http::url_encode("urn:ietf:params:oauth:grant-type:device_code"));
+    let (status, res) =
+        request_raw(TOKEN_URL,
+                    &["Content-Type: application/x-www-form-urlencoded"],
+                    Some(&body))?;
+    if status == 200 {
+        return Ok(DevicePoll::Done(serde_json::from_str(&res)?));
+    }
+    let err: OAuthError = serde_json::from_str(&res)
+        .chain_err(|| format!("Device code poll failed with HTTP {}", status))?;
+    match err.error.as_str() {
+        "authorization_pending" => Ok(DevicePoll::Pending),
+        "slow_down" => Ok(DevicePoll::SlowDown),
+        _ => {
+            bail!("Device code login failed: {} ({})",
+                  err.error,
+                  err.error_description.unwrap_or_default())
+        },
+    }
+}
+
+#[derive(Deserialize)]
+struct MicrosoftToken {
+    access_token: String,
+    refresh_token: Option<String>,
+}
+
+#[derive(Deserialize)]
+#[allow(non_snake_case)]
+struct XboxResponse {
+    Token: String,
+    DisplayClaims: XboxDisplayClaims,
+}
+
+#[derive(Deserialize)]
+#[allow(non_snake_case)]
+struct XboxError {
+    XErr: u64,
+}
+
+#[derive(Deserialize)]
+struct XboxDisplayClaims {
+    xui: Vec<XboxUserInfo>,
+}
+
+#[derive(Deserialize)]
+struct XboxUserInfo {
+    uhs: String,
+}
+
+#[derive(Deserialize)]
+struct MinecraftLogin {
+    access_token: String,
+    expires_in: u64,
+}
+
+#[derive(Deserialize)]
+struct MinecraftProfile {
+    id: String,
+    name: String,
+}
+
+fn redeem_authorization_code(client_id: &str,
+                             code: &str,
+                             redirect_uri: &str)
+                             -> Result<MicrosoftToken> {
+    let body = format!("client_id={}&code={}&grant_type=authorization_code\
+                        &redirect_uri={}&scope={}",
+                       http::url_encode(client_id),
+                       http::url_encode(code),
+                       http::url_encode(redirect_uri),
+                       http::url_encode(SCOPE));
+    let res = request(TOKEN_URL,
+                      &["Content-Type: application/x-www-form-urlencoded"],
+                      Some(&body))?;
+    Ok(serde_json::from_str(&res)?)
+}
+
+fn refresh_microsoft_token(client_id: &str, refresh_token: &str) -> Result<MicrosoftToken> {
+    let body = format!("client_id={}&refresh_token={}&grant_type=refresh_token&scope={}",
+                       http::url_encode(client_id),
+                       http::url_encode(refresh_token),
+                       http::url_encode(SCOPE));
+    let res = request(TOKEN_URL,
+                      &["Content-Type: application/x-www-form-urlencoded"],
+                      Some(&body))?;
+    Ok(serde_json::from_str(&res)?)
+}
+
+/* Returns the token and the user hash */
+fn xbox_live_token(ms_access_token: &str) -> Result<(String, String)> {
+    let body = json!({
+        "Properties": {
+            "AuthMethod": "RPS",
+            "SiteName": "user.auth.xboxlive.com",
+            "RpsTicket": format!("d={}", ms_access_token),
+        },
+        "RelyingParty": "http://auth.xboxlive.com",
+        "TokenType": "JWT",
+    });
+    xbox_request(XBL_URL, &body.to_string())
+}
+
+/* Returns the token and the user hash */
+fn xsts_token(xbl_token: &str) -> Result<(String, String)> {
+    let body = json!({
+        "Properties": {
+            "SandboxId": "RETAIL",
+            "UserTokens": [xbl_token],
+        },
+        "RelyingParty": "rap://api.minecraftservices.com/",
+        "TokenType": "JWT",
+    });
+    xbox_request(XSTS_URL, &body.to_string())
+}
+
+fn xbox_request(url: &str, body: &str) -> Result<(String, String)> {
+    let (status, res) = request_raw(url,
+                                    &["Content-Type: application/json",
+                                      "Accept: application/json"],
+                                    Some(body))?;
+    if status >= 400 {
+        match serde_json::from_str::<XboxError>(&res) {
+            Ok(err) => bail!("{}", xbox_error(err.XErr)),
+            Err(_) => bail!("HTTP {} from {}: {}", status, url, res),
+        }
+    }
+    let res: XboxResponse = serde_json::from_str(&res)?;
+    let uhs = match res.DisplayClaims.xui.into_iter().next() {
+        Some(x) => x.uhs,
+        None => bail!("Xbox Live response did not include a user hash"),
+    };
+    Ok((res.Token, uhs))
+}
+
+/* Describe the XErr code of a failed Xbox Live request */
+fn xbox_error(code: u64) -> String {
+    let reason = match code {
+        2148916227 => "The account is banned from Xbox Live",
+        2148916233 => "The account has no Xbox profile, log in to xbox.com to create one",
+        2148916235 => "Xbox Live is not available in the account's country",
+        2148916236 | 2148916237 => "The account needs adult verification on xbox.com",
+        2148916238 => "The account belongs to a child and must be added to a family by an adult",
+        _ => return format!("Xbox Live error {}", code),
+    };
+    format!("{} (Xbox Live error {})", reason, code)
+}
+
+fn minecraft_login(user_hash: &str, xsts_token: &str) -> Result<MinecraftLogin> {
+    let body = json!({
+        "identityToken": format!("XBL3.0 x={};{}", user_hash, xsts_token),
+    });
+    let res = request(LOGIN_URL,
+                      &["Content-Type: application/json",
+                        "Accept: application/json"],
+                      Some(&body.to_string()))?;
+    Ok(serde_json::from_str(&res)?)
+}
+
+fn minecraft_profile(access_token: &str) -> Result<MinecraftProfile> {
+    let authorization = format!("Authorization: Bearer {}", access_token);
+    let res = request(PROFILE_URL, &[&authorization], None)?;
+    Ok(serde_json::from_str(&res)?)
+}
+
+/// Get the settings and privileges of the account with the given Minecraft
+/// access token, e.g. whether it may play online and its profanity filter
+/// preference
+pub fn player_attributes(access_token: &str) -> Result<PlayerAttributes> {
+    let authorization = format!("Authorization: Bearer {}", access_token);
+    let res = request(ATTRIBUTES_URL, &[&authorization], None)?;
+    Ok(serde_json::from_str(&res)?)
+}
+
+/* Perform a GET request, or a POST request if there's a body, failing on
+ * HTTP errors */
+fn request(url: &str, headers: &[&str], body: Option<&str>) -> Result<String> {
+    let (status, res) = request_raw(url, headers, body)?;
+    if status >= 400 {
+        bail!("HTTP {} from {}: {}", status, url, res);
+    }
+    Ok(res)
+}
+
+/* Like request, but returns the HTTP status code instead of failing. The
+ * headers are given as "Name: value". */
+fn request_raw(url: &str, headers: &[&str], body: Option<&str>) -> Result<(u32, String)> {
+    let mut request = match body {
+        Some(body) => http::Request::post(url, body),
+        None => http::Request::get(url),
+    };
+    for header in headers {
+        let mut parts = header.splitn(2, ':');
+        let name = parts.next().unwrap_or_default();
+        let value = parts.next().unwrap_or_default().trim();
+        request = request.header(name, value);
+    }
+    let response = http::perform(request)?;
+    let status = response.status;
+    Ok((status, response.into_text()?))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn needs_refresh() {
+        let now = SystemTime::now();
+        let mut session = MsaSession::new("client".to_string(),
+                                          "refresh".to_string());
+        assert!(session.needs_refresh(now));
+
+        session.token = Some(MinecraftToken {
+                                 access_token: "token".to_string(),
+                                 expires_at: now + Duration::from_secs(3600),
+                                 profile: NameUUID {
+                                     id: "id".to_string(),
+                                     name: "name".to_string(),
+                                     legacy: false,
+                                     demo: false,
+                                 },
+                             });
+        assert!(!session.needs_refresh(now));
+        assert_eq!(session.time_until_refresh(now), Duration::from_secs(3000));
+        assert!(session.needs_refresh(now + Duration::from_secs(3000)));
+        assert_eq!(session.time_until_refresh(now + Duration::from_secs(4000)),
+                   Duration::from_secs(0));
+        assert_eq!(session.to_authentication_response().unwrap().accessToken,
+                   "token");
+    }
+
+    #[test]
+    fn device_code_auth() {
+        let code: DeviceCode = serde_json::from_str(r#"{
+            "user_code": "ABCD1234",
+            "device_code": "secret",
+            "verification_uri": "https://microsoft.com/devicelogin",
+            "expires_in": 900,
+            "interval": 5,
+            "message": "Enter ABCD1234"}"#).unwrap();
+
+        let mut auth = DeviceCodeAuth::new("client".to_string(), code.clone(), Instant::now());
+        assert_eq!(auth.user_code(), "ABCD1234");
+        assert!(!auth.is_expired());
+        assert!(auth.time_until_poll() > Duration::from_secs(4));
+        /* Too early to ask Microsoft */
+        assert!(auth.poll().unwrap().is_none());
+
+        let code = DeviceCode { expires_in: 0, ..code };
+        let mut auth = DeviceCodeAuth::new("client".to_string(), code, Instant::now());
+        thread::sleep(Duration::from_millis(5));
+        assert!(auth.is_expired());
+        assert!(auth.poll().is_err());
+    }
+
+    #[test]
+    fn xbox_errors() {
+        let err: XboxError = serde_json::from_str(r#"{"Identity":"0","XErr":2148916233,"Message":"","Redirect":"https://start.ui.xboxlive.com/CreateAccount"}"#).unwrap();
+        assert_eq!(xbox_error(err.XErr),
+                   "The account has no Xbox profile, log in to xbox.com to create one \
+                    (Xbox Live error 2148916233)");
+        assert_eq!(xbox_error(1), "Xbox Live error 1");
+    }
+
+    #[test]
+    fn channel_prompt() {
+        use std::sync::mpsc::channel;
+
+        let code: DeviceCode = serde_json::from_str(r#"{
+            "user_code": "ABCD1234",
+            "device_code": "secret",
+            "verification_uri": "https://microsoft.com/devicelogin",
+            "expires_in": 900,
+            "interval": 5,
+            "message": "Enter ABCD1234"}"#).unwrap();
+
+        let (tx, rx) = channel();
+        let mut prompt = ChannelPrompt::new(tx);
+        prompt.show_code(&code);
+        assert!(!prompt.is_cancelled());
+        prompt.finished(true);
+        assert_eq!(rx.recv().unwrap(), PromptMessage::ShowCode(code.clone()));
+        assert_eq!(rx.recv().unwrap(), PromptMessage::Finished(true));
+
+        drop(rx);
+        prompt.show_code(&code);
+        assert!(prompt.is_cancelled());
+    }
+
+    #[test]
+    fn player_attributes() {
+        let json = r#"{"privileges":{"onlineChat":{"enabled":true},"multiplayerServer":{"enabled":false}},"profanityFilterPreferences":{"profanityFilterOn":true},"banStatus":{"bannedScopes":{}}}"#;
+        let attributes: PlayerAttributes = serde_json::from_str(json).unwrap();
+        assert!(attributes.has_privilege("onlineChat"));
+        assert!(!attributes.has_privilege("multiplayerServer"));
+        assert!(!attributes.has_privilege("telemetry"));
+        assert!(attributes.profanity_filter());
+        /* Unknown fields survive a round trip */
+        let value: serde_json::Value = serde_json::from_str(json).unwrap();
+        assert_eq!(serde_json::to_value(&attributes).unwrap(), value);
+        assert_eq!(serde_json::from_str::<PlayerAttributes>("{}").unwrap(),
+                   PlayerAttributes::default());
+    }
+}
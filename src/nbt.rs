@@ -0,0 +1,263 @@
+//! Reading and writing NBT, the binary format of item and block entity data
+//!
+//! Only the uncompressed network format is supported, which is how NBT is
+//! sent in slots and block entity packets. Strings are read as UTF-8, which
+//! only differs from Java's modified UTF-8 in how NUL and characters outside
+//! the BMP are encoded.
+
+use errors::Result;
+use read::{read_f32, read_f64, read_i16, read_i32, read_i64, read_i8, read_u16, read_u8};
+use write::{write_f32, write_f64, write_i16, write_i32, write_i64, write_i8, write_u16,
+            write_u8};
+
+use std::collections::BTreeMap;
+use std::io::{Read, Write};
+
+/// How deeply lists and compounds may be nested, like in vanilla
+pub const MAX_DEPTH: usize = 512;
+
+/// An NBT tag
+#[derive(Debug, Clone, PartialEq)]
+pub enum Tag {
+    Byte(i8),
+    Short(i16),
+    Int(i32),
+    Long(i64),
+    Float(f32),
+    Double(f64),
+    ByteArray(Vec<i8>),
+    String(String),
+    List(Vec<Tag>),
+    Compound(BTreeMap<String, Tag>),
+    IntArray(Vec<i32>),
+    LongArray(Vec<i64>),
+}
+impl Tag {
+    /// The type ID of the tag
+    pub fn id(&self) -> u8 {
+        match *self {
+            Tag::Byte(_) => 1,
+            Tag::Short(_) => 2,
+            Tag::Int(_) => 3,
+            Tag::Long(_) => 4,
+            Tag::Float(_) => 5,
+            Tag::Double(_) => 6,
+            Tag::ByteArray(_) => 7,
+            Tag::String(_) => 8,
+            Tag::List(_) => 9,
+            Tag::Compound(_) => 10,
+            Tag::IntArray(_) => 11,
+            Tag::LongArray(_) => 12,
+        }
+    }
+
+    /// Get an entry of a compound tag
+    pub fn get(&self, name: &str) -> Option<&Tag> {
+        match *self {
+            Tag::Compound(ref x) => x.get(name),
+            _ => None,
+        }
+    }
+
+    /// The value of a numeric tag as an i64, e.g. for Short enchantment
+    /// levels that are sometimes sent as Int
+    pub fn as_i64(&self) -> Option<i64> {
+        match *self {
+            Tag::Byte(x) => Some(x as i64),
+            Tag::Short(x) => Some(x as i64),
+            Tag::Int(x) => Some(x as i64),
+            Tag::Long(x) => Some(x),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> Option<&str> {
+        match *self {
+            Tag::String(ref x) => Some(x),
+            _ => None,
+        }
+    }
+
+    pub fn as_list(&self) -> Option<&[Tag]> {
+        match *self {
+            Tag::List(ref x) => Some(x),
+            _ => None,
+        }
+    }
+}
+
+/// Read a named root tag, or None if it's just a TAG_End, which is how an
+/// absent tag is sent
+pub fn read_nbt<R: Read>(r: &mut R) -> Result<Option<(String, Tag)>> {
+    let id = read_u8(r)?;
+    if id == 0 {
+        return Ok(None);
+    }
+    let name = read_string(r)?;
+    Ok(Some((name, read_payload(r, id, 0)?)))
+}
+
+/// Write a named root tag, or a TAG_End if None
+pub fn write_nbt<W: Write>(val: Option<(&str, &Tag)>, w: &mut W) -> Result<()> {
+    match val {
+        None => write_u8(&0, w),
+        Some((name, tag)) => {
+            write_u8(&tag.id(), w)?;
+            write_string(name, w)?;
+            write_payload(tag, w)
+        },
+    }
+}
+
+fn read_string<R: Read>(r: &mut R) -> Result<String> {
+    let len = read_u16(r)?;
+    let mut buf = vec![0; len as usize];
+    r.read_exact(&mut buf)?;
+    Ok(String::from_utf8_lossy(&buf).into_owned())
+}
+
+fn write_string<W: Write>(val: &str, w: &mut W) -> Result<()> {
+    if val.len() > u16::MAX as usize {
+        bail!("NBT string is too long: {} bytes", val.len());
+    }
+    write_u16(&(val.len() as u16), w)?;
+    Ok(w.write_all(val.as_bytes())?)
+}
+
+fn read_len<R: Read>(r: &mut R) -> Result<usize> {
+    let len = read_i32(r)?;
+    if len < 0 {
+        bail!("Negative NBT array length {}", len);
+    }
+    Ok(len as usize)
+}
+
+/* Read up to len elements, without trusting len for the allocation */
+fn read_array<R: Read, T, F>(r: &mut R, len: usize, mut f: F) -> Result<Vec<T>>
+    where F: FnMut(&mut R) -> Result<T>
+{
+    let mut ret = Vec::with_capacity(len.min(1024));
+    for _ in 0..len {
+        ret.push(f(r)?);
+    }
+    Ok(ret)
+}
+
+fn read_payload<R: Read>(r: &mut R, id: u8, depth: usize) -> Result<Tag> {
+    if depth > MAX_DEPTH {
+        bail!("NBT is nested too deeply");
+    }
+    Ok(match id {
+           1 => Tag::Byte(read_i8(r)?),
+           2 => Tag::Short(read_i16(r)?),
+           3 => Tag::Int(read_i32(r)?),
+           4 => Tag::Long(read_i64(r)?),
+           5 => Tag::Float(read_f32(r)?),
+           6 => Tag::Double(read_f64(r)?),
+           7 => {
+               let len = read_len(r)?;
+               Tag::ByteArray(read_array(r, len, read_i8)?)
+           },
+           8 => Tag::String(read_string(r)?),
+           9 => {
+               let element = read_u8(r)?;
+               let len = read_len(r)?;
+               if element == 0 && len > 0 {
+                   bail!("NBT list of TAG_End with {} elements", len);
+               }
+               Tag::List(read_array(r, len, |r| read_payload(r, element, depth + 1))?)
+           },
+           10 => {
+               let mut ret = BTreeMap::new();
+               loop {
+                   let id = read_u8(r)?;
+                   if id == 0 {
+                       break;
+                   }
+                   let name = read_string(r)?;
+                   let _ = ret.insert(name, read_payload(r, id, depth + 1)?);
+               }
+               Tag::Compound(ret)
+           },
+           11 => {
+               let len = read_len(r)?;
+               Tag::IntArray(read_array(r, len, read_i32)?)
+           },
+           12 => {
+               let len = read_len(r)?;
+               Tag::LongArray(read_array(r, len, read_i64)?)
+           },
+           _ => bail!("Unknown NBT tag type {}", id),
+       })
+}
+
+fn write_payload<W: Write>(tag: &Tag, w: &mut W) -> Result<()> {
+    match *tag {
+        Tag::Byte(ref x) => write_i8(x, w),
+        Tag::Short(ref x) => write_i16(x, w),
+        Tag::Int(ref x) => write_i32(x, w),
+        Tag::Long(ref x) => write_i64(x, w),
+        Tag::Float(ref x) => write_f32(x, w),
+        Tag::Double(ref x) => write_f64(x, w),
+        Tag::ByteArray(ref x) => {
+            write_i32(&(x.len() as i32), w)?;
+            x.iter().try_for_each(|x| write_i8(x, w))
+        },
+        Tag::String(ref x) => write_string(x, w),
+        Tag::List(ref x) => {
+            let element = x.first().map(|x| x.id()).unwrap_or(0);
+            if x.iter().any(|x| x.id() != element) {
+                bail!("NBT list elements must all have the same type");
+            }
+            write_u8(&element, w)?;
+            write_i32(&(x.len() as i32), w)?;
+            x.iter().try_for_each(|x| write_payload(x, w))
+        },
+        Tag::Compound(ref x) => {
+            for (name, tag) in x {
+                write_u8(&tag.id(), w)?;
+                write_string(name, w)?;
+                write_payload(tag, w)?;
+            }
+            write_u8(&0, w)
+        },
+        Tag::IntArray(ref x) => {
+            write_i32(&(x.len() as i32), w)?;
+            x.iter().try_for_each(|x| write_i32(x, w))
+        },
+        Tag::LongArray(ref x) => {
+            write_i32(&(x.len() as i32), w)?;
+            x.iter().try_for_each(|x| write_i64(x, w))
+        },
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::io::Cursor;
+
+    #[test]
+    fn roundtrip() {
+        let mut compound = BTreeMap::new();
+        let _ = compound.insert("lvl".to_string(), Tag::Short(3));
+        let _ = compound.insert("id".to_string(), Tag::String("minecraft:sharpness".to_string()));
+        let mut root = BTreeMap::new();
+        let _ = root.insert("Enchantments".to_string(), Tag::List(vec![Tag::Compound(compound)]));
+        let _ = root.insert("Data".to_string(), Tag::LongArray(vec![1, -2]));
+        let root = Tag::Compound(root);
+
+        let mut buf = Vec::new();
+        write_nbt(Some(("", &root)), &mut buf).unwrap();
+        let (name, tag) = read_nbt(&mut Cursor::new(&buf)).unwrap().unwrap();
+        assert_eq!(name, "");
+        assert_eq!(tag, root);
+        let enchantment = &tag.get("Enchantments").and_then(|x| x.as_list()).unwrap()[0];
+        assert_eq!(enchantment.get("lvl").and_then(|x| x.as_i64()), Some(3));
+
+        assert_eq!(read_nbt(&mut Cursor::new(&[0])).unwrap(), None);
+        assert!(read_nbt(&mut Cursor::new(&buf[..buf.len() - 1])).is_err());
+        /* A huge claimed length mustn't be allocated up front */
+        assert!(read_nbt(&mut Cursor::new(&[7, 0, 0, 0x7f, 0xff, 0xff, 0xff])).is_err());
+    }
+}
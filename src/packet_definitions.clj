@@ -46,7 +46,7 @@
       :fields [{:name "action" :type "i32" :read "varint" :getter "Get the action ID varint enum"}]}
      {:name "ClientSettings"
       :id 4
-      :fields [{:name "locale" :type "String" :getter "Get the client's locale"}
+      :fields [{:name "locale" :type "SmallString" :getter "Get the client's locale"}
                {:name "view_distance" :type "u8" :getter "Get the client's view distance in chunks"}
                {:name "chat_mode" :type "i32" :read "varint" :getter "Get the client's chat mode as varint enum"}
                {:name "chat_colors" :type "bool" :getter "Get whether the player has chat colors enabled"}
@@ -78,7 +78,7 @@
       :fields [{:name "window_id" :type "u8" :getter "Get the window id"}]}
      {:name "PluginMessage"
       :id 10
-      :fields [{:name "channel" :type "String" :getter "Get the channel"}
+      :fields [{:name "channel" :type "SmallString" :getter "Get the channel"}
                {:name "data" :type "Vec<u8>" :read "bytearray" :getter "Get the data"}]}
      {:name "EditBook"
       :id 11
@@ -422,11 +422,11 @@
           {:name "cooldown" :type "i32" :getter "Get the cooldown on the item specified in ticks" :read "varint"}]}
 {:name "ClientboundPluginMessage"
  :id 25
- :fields [{:name "channel" :type "String" :getter "Get the plugin channel"}
+ :fields [{:name "channel" :type "SmallString" :getter "Get the plugin channel"}
           {:name "data" :type "Vec<u8>" :getter "Get the raw data" :read "bytearray_to_end"}]}
 {:name "NamedSoundEffect"
  :id 26
- :fields [{:name "sound_name" :type "String" :getter "Get the name of the sound"}
+ :fields [{:name "sound_name" :type "SmallString" :getter "Get the name of the sound"}
           {:name "sound_category" :type "i32" :getter "Get the category of the sound" :read "varint"}
           {:name "x" :type "i32" :getter "Get the X coordinate multiplied by 8"}
           {:name "y" :type "i32" :getter "Get the Y coordinate multiplied by 8"}
@@ -496,7 +496,7 @@
           {:name "dimension" :type "i32" :getter "Get the dimension the player is in. Not the specific world (in case of servers with multiworld), but the kind of world"}
           {:name "difficulty" :type "u8" :getter "Get the difficulty"}
           {:name "max_players" :type "u8"}; Apparently this field is no longer used but for some reason hasn't been removed yet
-          {:name "level_type" :type "String" :getter "Get the level type of the world the player joined in"}
+          {:name "level_type" :type "SmallString" :getter "Get the level type of the world the player joined in"}
           {:name "reduced_debug" :type "bool" :getter "Get whether to show reduced debug info"}]}
 {:name "Map"
  :id 38
@@ -531,8 +531,8 @@
  :fields [{:name "x" :type "f64" :getter "Get the X coordinate"}
           {:name "y" :type "f64" :getter "Get the Y coordinate"}
           {:name "z" :type "f64" :getter "Get the Z coordinate"}
-          {:name "yaw" :type "i8" :getter "Get the (absolute) angle"}
-          {:name "pitch" :type "i8" :getter "Get the (absolute) pitch"}]}
+          {:name "yaw" :type "f32" :getter "Get the (absolute) angle"}
+          {:name "pitch" :type "f32" :getter "Get the (absolute) pitch"}]}
 {:name "OpenSignEditor"
  :id 44
  :fields [{:name "position" :type "(i32, i32, i32)" :getter "Get the (x, y, z) position" :read "position"}]}
@@ -605,7 +605,7 @@
  :fields  [{:name "dimension" :type "i32" :getter "Get the integer value for the dimension the player is spawning in"}
            {:name "difficulty" :type "u8" :getter "Get the integer value for the difficulty"}
            {:name "gamemode" :type "u8" :getter "Get the integer value for the gamemode"}
-           {:name "level_type" :type "String" :getter "Get the level type"}]}
+           {:name "level_type" :type "SmallString" :getter "Get the level type"}]}
 {:name "EntityHeadLook"
  :id 57
  :fields [{:name "entity_id" :type "i32" :getter "Get the entity ID" :read "varint"}
@@ -626,7 +626,7 @@
 {:name "DisplayScoreboard"
  :id 62
  :fields [{:name "position" :type "u8" :getter "Get the raw integer representing the scoreboard's position"}
-          {:name "name" :type "String" :getter "Get the name of the scoreboard"}]}
+          {:name "name" :type "SmallString" :getter "Get the name of the scoreboard"}]}
 {:name "EntityMetadata"
  :id 63
  :fields [{:name "entity_id" :type "i32" :getter "Get the entity ID of the entity which metadata is being updated" :read "varint"}
@@ -673,9 +673,9 @@
 {:name "UpdateScore"
  :id 72
  :automatic-serialize false
- :fields [{:name "name" :type "String" :getter "Get the name of the score to be updated"}
+ :fields [{:name "name" :type "SmallString" :getter "Get the name of the score to be updated"}
           {:name "action" :type "u8" :getter "Get the action being performed"}
-          {:name "objective_name" :type "String" :getter "Get the name of the objective the score belongs to"}
+          {:name "objective_name" :type "SmallString" :getter "Get the name of the objective the score belongs to"}
           {:name "value" :type "Option<i32>" :getter "Get the score to be displayed if this packet is updating a score, else `None`"}]}
 {:name "SpawnPosition"
  :id 73
@@ -137,6 +137,18 @@
             "        }"
             "    }"))
 
+;; Create the id_by_name function for the Packet trait for the given packets
+(defn enum-fn-id-by-name [packets]
+  (long-str "    fn id_by_name(state: &ClientState, name: &str) -> Option<i32> {"
+            "        match (state, name) {"
+            (apply str
+                   (for [{name :name id :id state :state} packets]
+                     (format "        (&ClientState::%s, \"%s\") => Some(%s),\n"
+                             state name id)))
+            "        _ => None,"
+            "        }"
+            "    }"))
+
 ;; Create the to_u8 function for the Packet trait for the given packets
 (defn enum-fn-to-u8 [packets packet-type]
   (long-str "    fn to_u8(&self) -> Result<Vec<u8>> {"
@@ -155,6 +167,7 @@
               (enum-fn-get-packet-name packets packet-type)
               (enum-fn-get-state packets packet-type)
               (enum-fn-get-id packets packet-type)
+              (enum-fn-id-by-name packets)
               (enum-fn-to-u8 packets packet-type)
               "}"
               "impl fmt::Display for %s {"
@@ -0,0 +1,146 @@
+//! A placeholder server answering status pings and kicking players
+//!
+//! While the real server is starting, or down for maintenance, a Placeholder
+//! can take its place on the listener: it shows a MOTD in the server list,
+//! and disconnects players trying to log in with a message, e.g. "Server
+//! starting...".
+//!
+//! # Examples
+//!
+//! ```rust,no_run
+//! use ozelot::broadcast;
+//! use ozelot::listener::{ListenerOptions, ServerListener};
+//! use ozelot::placeholder::Placeholder;
+//!
+//! let mut listener = ServerListener::bind(&["0.0.0.0:25565"],
+//!                                         ListenerOptions::default()).unwrap();
+//! let placeholder = Placeholder::new(broadcast::text("Starting up"),
+//!                                    broadcast::text("Server starting..."));
+//! placeholder.serve(&mut listener).unwrap();
+//! ```
+
+use {PROTOCOL_VERSION, Server};
+use clientbound;
+use errors::Result;
+use listener::ServerListener;
+use serverbound::ServerboundPacket;
+use vhost;
+
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// What a placeholder server shows, see the module documentation
+#[derive(Debug, Clone, PartialEq)]
+pub struct Placeholder {
+    /// The MOTD as raw chat json
+    pub motd: String,
+    /// The message players trying to log in are disconnected with, as raw
+    /// chat json
+    pub kick_message: String,
+    /// The version name shown in the server list
+    pub version_name: String,
+    /// The protocol version reported. Clients show a version mismatch if it
+    /// differs from theirs.
+    pub protocol: i32,
+    pub max_players: i32,
+    /// The server icon as a data URI of a PNG, see
+    /// status::favicon_from_png()
+    pub favicon: Option<String>,
+    /// How long to wait for a client before giving up on it
+    pub timeout: Duration,
+}
+impl Placeholder {
+    /// Create a new placeholder with the given MOTD and kick message
+    pub fn new(motd: String, kick_message: String) -> Self {
+        Placeholder {
+            motd,
+            kick_message,
+            version_name: "1.13.2".to_string(),
+            protocol: PROTOCOL_VERSION,
+            max_players: 0,
+            favicon: None,
+            timeout: Duration::from_secs(10),
+        }
+    }
+
+    /// Get the json of the StatusResponse
+    pub fn status_json(&self) -> String {
+        let motd: ::serde_json::Value = ::serde_json::from_str(&self.motd)
+            .unwrap_or_else(|_| json!({ "text": self.motd }));
+        let mut status = json!({
+            "version": { "name": self.version_name, "protocol": self.protocol },
+            "players": { "max": self.max_players, "online": 0 },
+            "description": motd,
+        });
+        if let Some(ref favicon) = self.favicon {
+            status["favicon"] = json!(favicon);
+        }
+        status.to_string()
+    }
+
+    /// Answer a newly accepted connection, from the Handshake until it is
+    /// closed
+    pub fn handle(&self, server: &mut Server) -> Result<()> {
+        let start = Instant::now();
+        let _: vhost::VirtualHost = vhost::read_handshake(server, self.timeout)?;
+        loop {
+            if start.elapsed() > self.timeout {
+                bail!("Timed out waiting for the client");
+            }
+            server.update_inbuf()?;
+            match server.read_packet()? {
+                Some(ServerboundPacket::StatusRequest(..)) => {
+                    let _: usize = server.send(clientbound::StatusResponse::new(self.status_json()))?;
+                },
+                Some(ServerboundPacket::StatusPing(ref p)) => {
+                    let remaining = server.send(clientbound::StatusPong::new(*p.get_id()))?;
+                    return finish(server, remaining);
+                },
+                Some(ServerboundPacket::LoginStart(..)) => {
+                    let disconnect = clientbound::LoginDisconnect::new(self.kick_message.clone());
+                    let remaining = server.send(disconnect)?;
+                    return finish(server, remaining);
+                },
+                Some(_) => (),
+                None => thread::sleep(Duration::from_millis(10)),
+            }
+        }
+    }
+
+    /// Answer all connections on the listener forever, each on its own thread
+    pub fn serve(&self, listener: &mut ServerListener) -> Result<()> {
+        listener.run(|mut conn| {
+            let placeholder = self.clone();
+            let _ = thread::spawn(move || placeholder.handle(&mut conn));
+        })
+    }
+}
+
+/* Send the remaining bytes of the outgoing buffer and close the
+ * connection */
+fn finish(server: &mut Server, mut remaining: usize) -> Result<()> {
+    let start = Instant::now();
+    while remaining > 0 && start.elapsed() < Duration::from_secs(5) {
+        thread::sleep(Duration::from_millis(10));
+        match server.write() {
+            Ok(x) => remaining = remaining.saturating_sub(x),
+            Err(_) => break,
+        }
+    }
+    server.close()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use status::parse_status;
+
+    #[test]
+    fn status() {
+        let placeholder = Placeholder::new("Starting".to_string(),
+                                           "{\"text\":\"Server starting...\"}".to_string());
+        let status = parse_status(&placeholder.status_json()).unwrap();
+        assert_eq!(status.protocol, PROTOCOL_VERSION);
+        assert_eq!(status.description, "{\"text\":\"Starting\"}");
+    }
+}
@@ -0,0 +1,105 @@
+//! Packets serialized once and sent to many connections
+//!
+//! Serializing and compressing a ChunkData packet takes far more time than
+//! sending it, and servers usually send the same chunk to every player
+//! nearby. A PreparedPacket is serialized when it's created, and compressed
+//! the first time it's sent with a given compression threshold and level,
+//! after which every connection sending it shares the result. Send it with
+//! server.send_prepared(), and see chunk::ChunkCache for keeping the
+//! prepared chunks up to date with block changes.
+//!
+//! Only encryption is still done per connection, as it must be.
+
+use Packet;
+use clientbound::ClientboundPacket;
+use connection;
+use errors::Result;
+use priority::Priority;
+
+use flate2::Compression;
+
+use std::fmt;
+use std::sync::{Arc, Mutex};
+
+/* The framed data for a compression threshold and level */
+type Framed = (Option<usize>, Compression, Arc<Vec<u8>>);
+
+/// A serialized packet, cheap to clone and share between threads
+#[derive(Clone)]
+pub struct PreparedPacket {
+    name: Arc<str>,
+    priority: Priority,
+    data: Arc<Vec<u8>>,
+    framed: Arc<Mutex<Option<Framed>>>,
+}
+impl PreparedPacket {
+    /// Serialize the packet
+    pub fn new(packet: &ClientboundPacket) -> Result<Self> {
+        Ok(PreparedPacket {
+               name: packet.get_packet_name().into(),
+               priority: Priority::of(packet),
+               data: Arc::new(packet.to_u8()?),
+               framed: Arc::new(Mutex::new(None)),
+           })
+    }
+
+    /// The name of the packet, e.g. "ChunkData"
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn priority(&self) -> Priority {
+        self.priority
+    }
+
+    /// The serialized packet, including the packet ID
+    pub fn data(&self) -> &[u8] {
+        &self.data
+    }
+
+    /* Get the packet framed for the given compression, reusing the last
+     * framing if it was for the same compression */
+    pub(crate) fn framed(&self,
+                         compression: Option<usize>,
+                         level: Compression)
+                         -> Result<Arc<Vec<u8>>> {
+        let mut framed = self.framed.lock().map_err(|_| "Poisoned prepared packet lock")?;
+        match *framed {
+            Some((c, l, ref data)) if c == compression && l == level => return Ok(data.clone()),
+            _ => (),
+        }
+        let data = Arc::new(connection::frame(&self.data, compression, level)?);
+        *framed = Some((compression, level, data.clone()));
+        Ok(data)
+    }
+}
+impl fmt::Debug for PreparedPacket {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("PreparedPacket")
+            .field("name", &self.name)
+            .field("priority", &self.priority)
+            .field("length", &self.data.len())
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use clientbound::ChunkData;
+
+    #[test]
+    fn prepared() {
+        let packet = PreparedPacket::new(&ChunkData::new(vec![0; 1000])).unwrap();
+        assert_eq!(packet.name(), "ChunkData");
+        assert_eq!(packet.priority(), Priority::Bulk);
+        assert_eq!(packet.data(), &ChunkData::new(vec![0; 1000]).to_u8().unwrap()[..]);
+
+        let a = packet.framed(Some(256), Compression::default()).unwrap();
+        let b = packet.clone().framed(Some(256), Compression::default()).unwrap();
+        assert!(Arc::ptr_eq(&a, &b));
+        assert!(a.len() < 100);
+        let c = packet.framed(None, Compression::default()).unwrap();
+        assert_eq!(c.len(), packet.data().len() + 2);
+    }
+}
@@ -0,0 +1,160 @@
+//! Priority classes for outgoing packets
+//!
+//! When the connection to a client can't keep up, e.g. while it's loading
+//! chunks over a slow link, the outgoing buffer grows, and packets sent
+//! after the chunks wait behind them. If that includes a KeepAlive, the
+//! client times out for no good reason.
+//!
+//! With server.set_send_queue(), packets are put in a SendQueue instead of
+//! the outgoing buffer while the buffer is over a limit, and moved to the
+//! buffer by server.write() as it drains, by priority:
+//!
+//! - Critical packets, i.e. KeepAlives, disconnects and all packets sent
+//!   before the Play state, are never queued
+//! - Normal packets are moved before bulk packets
+//! - Bulk packets, i.e. chunks and block changes, are moved last
+//!
+//! Packets of the same priority stay in order, but normal packets can
+//! overtake chunks, so e.g. an entity can be spawned before its chunk is
+//! loaded, which the vanilla client handles fine. Block changes are bulk so
+//! that they stay behind the chunk they change, which would otherwise
+//! overwrite them when it arrives.
+//!
+//! Queued packets are framed when they're moved to the buffer, so packets
+//! sent during Login, where server.enable_compression() changes the framing
+//! of the following packets, must not wait in the queue.
+
+use clientbound::ClientboundPacket;
+use connection::Packet;
+use prepared::PreparedPacket;
+use ClientState;
+
+use std::collections::VecDeque;
+
+/// The priority class of an outgoing packet
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Priority {
+    Critical,
+    Normal,
+    Bulk,
+}
+impl Priority {
+    /// Get the priority class of the packet
+    pub fn of(packet: &ClientboundPacket) -> Self {
+        if packet.get_clientstate() != ClientState::Play {
+            return Priority::Critical;
+        }
+        match *packet {
+            ClientboundPacket::KeepAlive(..) |
+            ClientboundPacket::PlayDisconnect(..) => Priority::Critical,
+            ClientboundPacket::ChunkData(..) |
+            ClientboundPacket::UnloadChunk(..) |
+            ClientboundPacket::BlockChange(..) |
+            ClientboundPacket::MultiBlockChange(..) |
+            ClientboundPacket::UpdateBlockEntity(..) => Priority::Bulk,
+            _ => Priority::Normal,
+        }
+    }
+}
+
+/// Packets waiting to be moved to the outgoing buffer, see the module
+/// documentation. Packets are queued serialized, as PreparedPackets.
+#[derive(Debug, Clone)]
+pub struct SendQueue {
+    limit: usize,
+    normal: VecDeque<PreparedPacket>,
+    bulk: VecDeque<PreparedPacket>,
+}
+impl SendQueue {
+    /// Create a queue for when the outgoing buffer is over limit bytes
+    pub fn new(limit: usize) -> Self {
+        SendQueue {
+            limit,
+            normal: VecDeque::new(),
+            bulk: VecDeque::new(),
+        }
+    }
+
+    /// The amount of bytes the outgoing buffer may hold before packets are
+    /// queued
+    pub fn limit(&self) -> usize {
+        self.limit
+    }
+
+    /// The amount of queued packets
+    pub fn len(&self) -> usize {
+        self.normal.len() + self.bulk.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Queue the packet if needed, given the length of the outgoing buffer,
+    /// or give it back if it can be sent right away
+    pub fn push(&mut self, packet: PreparedPacket, pending: usize) -> Option<PreparedPacket> {
+        let over = pending > self.limit;
+        /* A packet can't overtake queued packets of its own priority */
+        match packet.priority() {
+            Priority::Critical => Some(packet),
+            Priority::Normal if over || !self.normal.is_empty() => {
+                self.normal.push_back(packet);
+                None
+            },
+            Priority::Bulk if over || !self.is_empty() => {
+                self.bulk.push_back(packet);
+                None
+            },
+            _ => Some(packet),
+        }
+    }
+
+    /// Take the next packet to send, if the outgoing buffer has room for it
+    pub fn pop(&mut self, pending: usize) -> Option<PreparedPacket> {
+        if pending > self.limit {
+            return None;
+        }
+        self.normal.pop_front().or_else(|| self.bulk.pop_front())
+    }
+
+    /// Take all queued packets, e.g. when disabling the queue
+    pub fn drain(&mut self) -> Vec<PreparedPacket> {
+        self.normal.drain(..).chain(self.bulk.drain(..)).collect()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use clientbound::{BlockChange, ChunkData, KeepAlive, LoginSuccess, SetCompression,
+                      TimeUpdate};
+
+    #[test]
+    fn priorities() {
+        let prepare = |x: ClientboundPacket| PreparedPacket::new(&x).unwrap();
+        let chunk = prepare(ChunkData::new(vec![0; 64]));
+        let keep_alive = prepare(KeepAlive::new(1));
+        let time = prepare(TimeUpdate::new(0, 0));
+        assert_eq!(chunk.priority(), Priority::Bulk);
+        assert_eq!(keep_alive.priority(), Priority::Critical);
+        assert_eq!(Priority::of(&BlockChange::new((0, 0, 0), 1)), Priority::Bulk);
+        assert_eq!(Priority::of(&SetCompression::new(256)), Priority::Critical);
+        assert_eq!(Priority::of(&LoginSuccess::new(0, "a".to_string())), Priority::Critical);
+
+        let mut queue = SendQueue::new(100);
+        let name = |x: Option<PreparedPacket>| x.map(|x| x.name().to_string());
+        assert_eq!(name(queue.push(chunk.clone(), 0)), Some("ChunkData".to_string()));
+        assert!(queue.push(chunk.clone(), 200).is_none());
+        assert!(queue.push(time.clone(), 200).is_none());
+        assert_eq!(name(queue.push(keep_alive, 200)), Some("KeepAlive".to_string()));
+        /* Bulk packets wait behind the queued ones even with room */
+        assert!(queue.push(chunk, 0).is_none());
+        assert_eq!(queue.len(), 3);
+
+        assert!(queue.pop(200).is_none());
+        assert_eq!(name(queue.pop(0)), Some("TimeUpdate".to_string()));
+        assert_eq!(name(queue.pop(0)), Some("ChunkData".to_string()));
+        assert_eq!(queue.drain().len(), 1);
+        assert!(queue.is_empty());
+    }
+}
@@ -0,0 +1,257 @@
+//! Resolving many UUIDs to profiles without hitting the rate limit
+//!
+//! The session server only allows looking up the same profile about once a
+//! minute, and limits the total amount of requests too. A ProfileBatcher
+//! queues lookups and performs them on a background thread, spacing the
+//! requests according to a configurable budget. Lookups of a UUID that is
+//! already queued are merged, and results are cached for a while, so asking
+//! for the same profile often is cheap.
+//!
+//! # Examples
+//!
+//! ```rust,no_run
+//! use ozelot::profiles::ProfileBatcher;
+//!
+//! let batcher = ProfileBatcher::new();
+//! batcher.lookup("069a79f444e94726a5befca90e38aaf5", |res| match res {
+//!     Ok(profile) => println!("Got {}", profile.name),
+//!     Err(e) => println!("Lookup failed: {}", e),
+//! }).unwrap();
+//! let profile = batcher.get("853c80ef3c3749fdaa49938b674adae6").unwrap()
+//!     .recv().unwrap().unwrap();
+//! ```
+
+use errors::{ErrorKind, Result};
+use mojang::{Profile, UUIDToProfile};
+use uuid::Uuid;
+
+use std::collections::{HashMap, VecDeque};
+use std::sync::{Arc, Condvar, Mutex};
+use std::sync::mpsc::{self, Receiver};
+use std::thread;
+use std::time::{Duration, Instant};
+
+type Callback = Box<dyn FnOnce(Result<Profile>) + Send>;
+type Fetcher = Box<dyn Fn(&str) -> Result<Profile> + Send>;
+
+struct State {
+    /* UUIDs (in simple form) waiting to be looked up, in order */
+    queue: VecDeque<String>,
+    /* Callbacks of the lookups queued or in flight, by UUID */
+    waiting: HashMap<String, Vec<Callback>>,
+    cache: HashMap<String, (Instant, Profile)>,
+    cache_ttl: Duration,
+    interval: Duration,
+    shutdown: bool,
+}
+
+/// Looks up profiles by UUID on a background thread, see the module
+/// documentation
+pub struct ProfileBatcher {
+    state: Arc<(Mutex<State>, Condvar)>,
+}
+impl ProfileBatcher {
+    /// Create a new batcher, doing at most one request per second and
+    /// caching profiles for a minute
+    pub fn new() -> Self {
+        Self::with_fetcher(Box::new(|uuid| UUIDToProfile::new(uuid.to_string(), false).perform()))
+    }
+
+    fn with_fetcher(fetch: Fetcher) -> Self {
+        let state = State {
+            queue: VecDeque::new(),
+            waiting: HashMap::new(),
+            cache: HashMap::new(),
+            cache_ttl: Duration::from_secs(60),
+            interval: Duration::from_secs(1),
+            shutdown: false,
+        };
+        let state = Arc::new((Mutex::new(state), Condvar::new()));
+        let thread_state = state.clone();
+        let _: thread::JoinHandle<()> = thread::spawn(move || run(&thread_state, fetch));
+        ProfileBatcher { state }
+    }
+
+    /// Set the request budget: at most the given amount of requests in the
+    /// given time. Requests are spread out evenly.
+    pub fn set_budget(&self, requests: u32, per: Duration) {
+        let interval = per / requests.max(1);
+        self.with_state(|state| state.interval = interval);
+    }
+
+    /// Set how long profiles are cached for
+    pub fn set_cache_ttl(&self, ttl: Duration) {
+        self.with_state(|state| state.cache_ttl = ttl);
+    }
+
+    /// Get the profile of the given UUID if it is cached
+    pub fn cached(&self, uuid: &str) -> Option<Profile> {
+        let uuid = uuid.parse::<Uuid>().ok()?.to_simple();
+        self.with_state(|state| {
+            match state.cache.get(&uuid) {
+                Some(&(at, ref profile)) if at.elapsed() < state.cache_ttl => {
+                    Some(profile.clone())
+                },
+                _ => None,
+            }
+        })
+    }
+
+    /// Look up the profile of the given UUID, calling callback with the
+    /// result once it's done.
+    ///
+    /// The callback is called right away if the profile is cached, and
+    /// otherwise on the background thread. Fails if the UUID is invalid.
+    pub fn lookup<F>(&self, uuid: &str, callback: F) -> Result<()>
+        where F: FnOnce(Result<Profile>) + Send + 'static
+    {
+        if let Some(profile) = self.cached(uuid) {
+            callback(Ok(profile));
+            return Ok(());
+        }
+        let uuid = uuid.parse::<Uuid>()?.to_simple();
+        self.with_state(move |state| {
+            if !state.waiting.contains_key(&uuid) {
+                state.queue.push_back(uuid.clone());
+            }
+            state.waiting.entry(uuid).or_default().push(Box::new(callback));
+        });
+        Ok(())
+    }
+
+    /// Like lookup(), but returns a channel receiving the result
+    pub fn get(&self, uuid: &str) -> Result<Receiver<Result<Profile>>> {
+        let (sender, receiver) = mpsc::channel();
+        self.lookup(uuid, move |res| {
+            let _ = sender.send(res);
+        })?;
+        Ok(receiver)
+    }
+
+    /// The amount of UUIDs waiting to be looked up
+    pub fn queued(&self) -> usize {
+        self.with_state(|state| state.queue.len())
+    }
+
+    fn with_state<T, F: FnOnce(&mut State) -> T>(&self, f: F) -> T {
+        let (ref lock, ref condvar) = *self.state;
+        let mut state = lock.lock().expect("ProfileBatcher lock poisoned");
+        let ret = f(&mut state);
+        condvar.notify_all();
+        ret
+    }
+}
+impl Default for ProfileBatcher {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+impl Drop for ProfileBatcher {
+    /// Stop the background thread. Lookups still queued are dropped without
+    /// calling their callbacks.
+    fn drop(&mut self) {
+        self.with_state(|state| state.shutdown = true);
+    }
+}
+
+/* The background thread */
+fn run(state: &(Mutex<State>, Condvar), fetch: Fetcher) {
+    let (ref lock, ref condvar) = *state;
+    let mut last_request: Option<Instant> = None;
+    loop {
+        let uuid = {
+            let mut state = match lock.lock() {
+                Ok(x) => x,
+                Err(_) => return,
+            };
+            loop {
+                if state.shutdown {
+                    return;
+                }
+                let wait = last_request
+                    .and_then(|x| (x + state.interval).checked_duration_since(Instant::now()))
+                    .unwrap_or_default();
+                if state.queue.is_empty() {
+                    state = match condvar.wait(state) {
+                        Ok(x) => x,
+                        Err(_) => return,
+                    };
+                } else if wait > Duration::from_secs(0) {
+                    state = match condvar.wait_timeout(state, wait) {
+                        Ok(x) => x.0,
+                        Err(_) => return,
+                    };
+                } else {
+                    break state.queue.pop_front().expect("unreachable empty queue");
+                }
+            }
+        };
+
+        last_request = Some(Instant::now());
+        let res = fetch(&uuid);
+
+        let callbacks = {
+            let mut state = match lock.lock() {
+                Ok(x) => x,
+                Err(_) => return,
+            };
+            if let Ok(ref profile) = res {
+                let _: Option<(Instant, Profile)> =
+                    state.cache.insert(uuid.clone(), (Instant::now(), profile.clone()));
+            }
+            let ttl = state.cache_ttl;
+            state.cache.retain(|_, &mut (at, _)| at.elapsed() < ttl);
+            state.waiting.remove(&uuid).unwrap_or_default()
+        };
+        /* Errors can't be cloned, so only the first callback gets the
+         * original one */
+        let (profile, mut error, message) = match res {
+            Ok(x) => (Some(x), None, String::new()),
+            Err(e) => {
+                let message = e.to_string();
+                (None, Some(e), message)
+            },
+        };
+        for callback in callbacks {
+            match profile {
+                Some(ref x) => callback(Ok(x.clone())),
+                None => {
+                    let e = error.take().unwrap_or_else(|| ErrorKind::Msg(message.clone()).into());
+                    callback(Err(e));
+                },
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    #[test]
+    fn batcher() {
+        let requests = Arc::new(AtomicUsize::new(0));
+        let counter = requests.clone();
+        let batcher = ProfileBatcher::with_fetcher(Box::new(move |uuid| {
+            let _: usize = counter.fetch_add(1, Ordering::SeqCst);
+            Ok(Profile {
+                id: uuid.to_string(),
+                name: "Notch".to_string(),
+                properties: Vec::new(),
+                profileActions: Vec::new(),
+                extra: Default::default(),
+            })
+        }));
+        batcher.set_budget(100, Duration::from_secs(1));
+        let uuid = "069a79f4-44e9-4726-a5be-fca90e38aaf5";
+        let a = batcher.get(uuid).unwrap();
+        let b = batcher.get("069a79f444e94726a5befca90e38aaf5").unwrap();
+        assert_eq!(a.recv().unwrap().unwrap().name, "Notch");
+        assert_eq!(b.recv().unwrap().unwrap().id, "069a79f444e94726a5befca90e38aaf5");
+        assert!(batcher.cached(uuid).is_some());
+        let _ = batcher.get(uuid).unwrap().recv().unwrap();
+        assert_eq!(requests.load(Ordering::SeqCst), 1);
+        assert!(batcher.get("nope").is_err());
+    }
+}
@@ -0,0 +1,342 @@
+//! Packet IDs and registries of other protocol versions, loaded at runtime
+//!
+//! ozelot is compiled for PROTOCOL_VERSION, but a new Minecraft release
+//! often only renumbers the packets and registry entries. Rather than
+//! waiting for a new ozelot and recompiling, a deployed proxy can support
+//! such a release by loading a data file for it. The packets still need to
+//! have the same fields as in the compiled version.
+//!
+//! A data file is json of the form
+//!
+//! ```json
+//! {
+//!     "version_name": "1.13.3",
+//!     "protocol": 405,
+//!     "packets": {
+//!         "clientbound": {"Play": {"ChatMessage": 15, "KeepAlive": 34}},
+//!         "serverbound": {"Play": {"ChatMessage": 3}}
+//!     },
+//!     "registries": {
+//!         "blocks": {"minecraft:stone": 1}
+//!     }
+//! }
+//! ```
+//!
+//! where the packets are named as in the clientbound and serverbound modules,
+//! and are given their IDs in that version. Packets that aren't listed keep
+//! the IDs they have in PROTOCOL_VERSION. Old names of renamed packets, see
+//! the compat module, are accepted too.
+//!
+//! Give a ProtocolData to client.set_protocol_data() or
+//! server.set_protocol_data() and the connection translates the packet IDs
+//! when sending and receiving, so the packets are used as usual. Loaded data
+//! can also be registered by protocol version, e.g. to look up the data for
+//! the version in a Handshake. Registering data for a version again replaces
+//! it for connections set up from then on, so a proxy can reload its data
+//! files without restarting.
+//!
+//! # Examples
+//!
+//! ```rust,no_run
+//! use ozelot::{Server, protocol_data};
+//! use ozelot::serverbound::ServerboundPacket;
+//! use std::net::TcpListener;
+//! use std::path::Path;
+//!
+//! let _ = protocol_data::load_dir(Path::new("protocols")).unwrap();
+//! let listener = TcpListener::bind("0.0.0.0:25565").unwrap();
+//! let (stream, _) = listener.accept().unwrap();
+//! let mut server = Server::from_tcpstream(stream).unwrap();
+//! 'wait: loop {
+//!     for packet in server.read().unwrap() {
+//!         if let ServerboundPacket::Handshake(ref p) = packet {
+//!             server.set_protocol_data(protocol_data::get(*p.get_protocol_version()));
+//!             break 'wait;
+//!         }
+//!     }
+//! }
+//! ```
+
+use ClientState;
+use clientbound::ClientboundPacket;
+use compat;
+use connection::Packet;
+use errors::{Result, ResultExt};
+use read::read_varint;
+use serverbound::ServerboundPacket;
+use write::write_varint;
+
+use std::collections::HashMap;
+use std::fs::{self, File};
+use std::io::{Cursor, Read};
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+
+use serde_json::{self, Map, Value};
+
+/// The packet IDs and registries of a protocol version
+#[derive(Debug, Clone, PartialEq)]
+pub struct ProtocolData {
+    version_name: String,
+    protocol: i32,
+    clientbound: Arc<IdMap>,
+    serverbound: Arc<IdMap>,
+    registries: HashMap<String, Registry>,
+}
+impl ProtocolData {
+    /// Parse a data file, see the module documentation for the format
+    pub fn parse(json: &str) -> Result<Self> {
+        let json: Value = serde_json::from_str(json)?;
+        let protocol = match json["protocol"].as_i64() {
+            Some(x) => x as i32,
+            None => bail!("Protocol data without a protocol version"),
+        };
+        let version_name = json["version_name"]
+            .as_str()
+            .unwrap_or("")
+            .to_string();
+
+        let packets = &json["packets"];
+        let clientbound = IdMap::parse::<ClientboundPacket>(&packets["clientbound"])
+            .chain_err(|| "Invalid clientbound packets")?;
+        let serverbound = IdMap::parse::<ServerboundPacket>(&packets["serverbound"])
+            .chain_err(|| "Invalid serverbound packets")?;
+
+        let mut registries = HashMap::new();
+        for (name, entries) in object(&json["registries"])? {
+            let mut registry = Registry::default();
+            for (entry, id) in object(&entries)? {
+                let id = match id.as_i64() {
+                    Some(x) => x as i32,
+                    None => bail!("Invalid ID of {} in registry {}", entry, name),
+                };
+                let _: Option<i32> = registry.ids.insert(entry.clone(), id);
+                let _: Option<String> = registry.names.insert(id, entry.clone());
+            }
+            let _ = registries.insert(name.clone(), registry);
+        }
+
+        Ok(ProtocolData {
+               version_name,
+               protocol,
+               clientbound: Arc::new(clientbound),
+               serverbound: Arc::new(serverbound),
+               registries,
+           })
+    }
+
+    /// Read and parse a data file
+    pub fn load(path: &Path) -> Result<Self> {
+        let mut s = String::new();
+        let _: usize = File::open(path)?.read_to_string(&mut s)?;
+        ProtocolData::parse(&s).chain_err(|| format!("Error loading {}", path.display()))
+    }
+
+    /// Get the protocol version
+    pub fn protocol(&self) -> i32 {
+        self.protocol
+    }
+
+    /// Get the name of the Minecraft version, e.g. 1.13.2
+    pub fn version_name(&self) -> &str {
+        &self.version_name
+    }
+
+    /// Get the ID a clientbound packet has in this version
+    pub fn clientbound_id(&self, state: &ClientState, name: &str) -> Option<i32> {
+        ClientboundPacket::id_by_name(state, name).map(|id| self.clientbound.wire_id(state, id))
+    }
+
+    /// Get the ID a serverbound packet has in this version
+    pub fn serverbound_id(&self, state: &ClientState, name: &str) -> Option<i32> {
+        ServerboundPacket::id_by_name(state, name).map(|id| self.serverbound.wire_id(state, id))
+    }
+
+    /// Get the ID of an entry of a registry, e.g. ("blocks", "minecraft:stone")
+    pub fn registry_id(&self, registry: &str, name: &str) -> Option<i32> {
+        self.registries
+            .get(registry)
+            .and_then(|x| x.ids.get(name))
+            .cloned()
+    }
+
+    /// Get the name of the entry with the given ID in a registry
+    pub fn registry_name(&self, registry: &str, id: i32) -> Option<&str> {
+        self.registries
+            .get(registry)
+            .and_then(|x| x.names.get(&id))
+            .map(|x| x.as_str())
+    }
+
+    pub(crate) fn clientbound_ids(&self) -> Arc<IdMap> {
+        self.clientbound.clone()
+    }
+
+    pub(crate) fn serverbound_ids(&self) -> Arc<IdMap> {
+        self.serverbound.clone()
+    }
+}
+
+/* A registry, as name to ID and ID to name */
+#[derive(Debug, Clone, PartialEq, Default)]
+struct Registry {
+    ids: HashMap<String, i32>,
+    names: HashMap<i32, String>,
+}
+
+/* The packet IDs of one direction, mapped between those of PROTOCOL_VERSION
+ * and those sent over the wire */
+#[derive(Debug, Clone, PartialEq, Default)]
+pub(crate) struct IdMap {
+    to_wire: HashMap<(ClientState, i32), i32>,
+    from_wire: HashMap<(ClientState, i32), i32>,
+}
+impl IdMap {
+    fn parse<P: Packet>(json: &Value) -> Result<Self> {
+        let mut ret = IdMap::default();
+        for (state_name, packets) in object(json)? {
+            let state = match state_name.as_str() {
+                "Handshake" => ClientState::Handshake,
+                "Status" => ClientState::Status,
+                "Login" => ClientState::Login,
+                "Play" => ClientState::Play,
+                _ => bail!("Unknown state {}", state_name),
+            };
+            for (name, wire) in object(&packets)? {
+                let id = match P::id_by_name(&state, compat::packet_name(&name)) {
+                    Some(x) => x,
+                    None => bail!("Unknown packet {} in state {}", name, state),
+                };
+                let wire = match wire.as_i64() {
+                    Some(x) => x as i32,
+                    None => bail!("Invalid ID of packet {}", name),
+                };
+                let _: Option<i32> = ret.to_wire.insert((state.clone(), id), wire);
+                let _: Option<i32> = ret.from_wire.insert((state.clone(), wire), id);
+            }
+        }
+        Ok(ret)
+    }
+
+    fn wire_id(&self, state: &ClientState, id: i32) -> i32 {
+        *self.to_wire.get(&(state.clone(), id)).unwrap_or(&id)
+    }
+
+    fn local_id(&self, state: &ClientState, wire: i32) -> i32 {
+        *self.from_wire.get(&(state.clone(), wire)).unwrap_or(&wire)
+    }
+
+    /// Replace the packet ID at the start of a serialized packet with the
+    /// one sent over the wire
+    pub(crate) fn outbound(&self, state: &ClientState, data: Vec<u8>) -> Result<Vec<u8>> {
+        let (id, len) = {
+            let mut r = Cursor::new(&data);
+            (read_varint(&mut r)?, r.position() as usize)
+        };
+        let wire = self.wire_id(state, id);
+        if wire == id {
+            return Ok(data);
+        }
+        let mut ret = Vec::with_capacity(data.len() + 5);
+        write_varint(&wire, &mut ret)?;
+        ret.extend_from_slice(&data[len..]);
+        Ok(ret)
+    }
+
+    /// Read the packet ID sent over the wire, and return the packet ID of
+    /// PROTOCOL_VERSION serialized, to be read before the rest of the packet
+    pub(crate) fn inbound<R: Read>(&self, state: &ClientState, r: &mut R) -> Result<Vec<u8>> {
+        let wire = read_varint(r)?;
+        let mut ret = Vec::new();
+        write_varint(&self.local_id(state, wire), &mut ret)?;
+        Ok(ret)
+    }
+}
+
+/* Get a json object, treating a missing one as empty */
+fn object(json: &Value) -> Result<Map<String, Value>> {
+    match *json {
+        Value::Object(ref x) => Ok(x.clone()),
+        Value::Null => Ok(Map::new()),
+        _ => bail!("Expected a json object, got {}", json),
+    }
+}
+
+static LOADED: Mutex<Vec<Arc<ProtocolData>>> = Mutex::new(Vec::new());
+
+/// Register the data for its protocol version, replacing any data
+/// registered for it before. Connections already using the old data keep
+/// using it.
+pub fn register(data: ProtocolData) -> Arc<ProtocolData> {
+    let data = Arc::new(data);
+    if let Ok(mut loaded) = LOADED.lock() {
+        loaded.retain(|x| x.protocol != data.protocol);
+        loaded.push(data.clone());
+    }
+    data
+}
+
+/// Get the data registered for a protocol version
+pub fn get(protocol: i32) -> Option<Arc<ProtocolData>> {
+    LOADED.lock()
+        .ok()
+        .and_then(|x| x.iter().find(|x| x.protocol == protocol).cloned())
+}
+
+/// Get the protocol versions data is registered for
+pub fn registered() -> Vec<i32> {
+    LOADED.lock()
+        .map(|x| x.iter().map(|x| x.protocol).collect())
+        .unwrap_or_default()
+}
+
+/// Load and register every .json file in the directory, returning how many
+/// were loaded. Call it again to reload the files.
+pub fn load_dir(dir: &Path) -> Result<usize> {
+    let mut count = 0;
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path.extension().and_then(|x| x.to_str()) == Some("json") {
+            let _ = register(ProtocolData::load(&path)?);
+            count += 1;
+        }
+    }
+    Ok(count)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn remap() {
+        let data = ProtocolData::parse(r#"{
+            "version_name": "test",
+            "protocol": 9999,
+            "packets": {"clientbound": {"Play": {"KeepAlive": 100}}},
+            "registries": {"blocks": {"minecraft:stone": 1}}
+        }"#).unwrap();
+        assert_eq!(data.protocol(), 9999);
+        assert_eq!(data.registry_id("blocks", "minecraft:stone"), Some(1));
+        assert_eq!(data.registry_name("blocks", 1), Some("minecraft:stone"));
+        assert_eq!(data.registry_name("items", 1), None);
+
+        let id = ClientboundPacket::id_by_name(&ClientState::Play, "KeepAlive").unwrap();
+        assert_eq!(data.clientbound_id(&ClientState::Play, "KeepAlive"), Some(100));
+        assert_eq!(data.clientbound_id(&ClientState::Play, "ChatMessage"),
+                   ClientboundPacket::id_by_name(&ClientState::Play, "ChatMessage"));
+
+        let ids = data.clientbound_ids();
+        let sent = ids.outbound(&ClientState::Play, vec![id as u8, 1, 2]).unwrap();
+        assert_eq!(sent, vec![100, 1, 2]);
+        let mut r = Cursor::new(&sent);
+        assert_eq!(ids.inbound(&ClientState::Play, &mut r).unwrap(), vec![id as u8]);
+        assert_eq!(ids.outbound(&ClientState::Login, vec![id as u8]).unwrap(), vec![id as u8]);
+
+        assert!(ProtocolData::parse(r#"{"protocol": 1, "packets": {"clientbound": {"Play": {"NoSuchPacket": 1}}}}"#).is_err());
+
+        let _ = register(data);
+        assert_eq!(get(9999).map(|x| x.version_name().to_string()), Some("test".to_string()));
+        assert!(registered().contains(&9999));
+    }
+}
@@ -0,0 +1,189 @@
+//! Python bindings
+//!
+//! Exposes the lookups, authentication, server pings and chat parsing as a
+//! Python module named ozelot. Requires the python feature, and is built as
+//! an extension module with maturin, e.g.
+//! maturin build --release --features python
+//! The module uses the stable ABI, so it works with Python 3.8 and later.
+//!
+//! ```python
+//! import ozelot
+//!
+//! print(ozelot.lookup_uuid("Notch"))
+//! status = ozelot.ping("localhost", 25565)
+//! print(status.players_online, ozelot.ChatComponent(status.description).to_plain())
+//! ```
+//!
+//! Errors are raised as ozelot.OzelotError. The network calls release the
+//! GIL while waiting.
+
+use chat;
+use errors::Error;
+use mojang::{self, Authenticate};
+use status;
+
+use std::time::Duration;
+
+use pyo3::create_exception;
+use pyo3::exceptions::PyException;
+use pyo3::prelude::*;
+
+create_exception!(ozelot, OzelotError, PyException, "An error from ozelot");
+
+fn to_pyerr(e: Error) -> PyErr {
+    OzelotError::new_err(e.to_string())
+}
+
+/// An authenticated Mojang session
+#[pyclass(module = "ozelot", get_all, frozen, skip_from_py_object)]
+#[derive(Debug, Clone)]
+pub struct Session {
+    access_token: String,
+    client_token: Option<String>,
+    name: String,
+    uuid: String,
+}
+
+/// The status of a server
+#[pyclass(module = "ozelot", get_all, frozen, skip_from_py_object)]
+#[derive(Debug, Clone)]
+pub struct Status {
+    version_name: String,
+    protocol: i32,
+    players_online: i32,
+    players_max: i32,
+    /// A sample of the players online, as (name, UUID)
+    sample: Vec<(String, String)>,
+    /// The MOTD as raw chat json
+    description: String,
+    favicon: Option<String>,
+    /// The latency in seconds
+    latency: f64,
+}
+
+/// A parsed chat component
+#[pyclass(module = "ozelot", name = "ChatComponent", frozen, skip_from_py_object)]
+#[derive(Debug, Clone)]
+pub struct PyChatComponent(chat::ChatComponent);
+
+#[pymethods]
+impl PyChatComponent {
+    /// Parse chat json
+    #[new]
+    fn new(json: &str) -> PyResult<Self> {
+        chat::ChatComponent::parse(json)
+            .map(PyChatComponent)
+            .map_err(to_pyerr)
+    }
+
+    /// The visible text without formatting
+    fn to_plain(&self) -> String {
+        self.0.to_plain()
+    }
+
+    /// The text with ANSI escape codes
+    fn to_ansi(&self) -> String {
+        self.0.to_ansi()
+    }
+
+    fn to_json(&self) -> String {
+        self.0.to_json()
+    }
+
+    /// The estimated rendered width in pixels
+    fn width(&self) -> u32 {
+        self.0.width()
+    }
+
+    fn __str__(&self) -> String {
+        self.0.to_plain()
+    }
+
+    fn __repr__(&self) -> String {
+        format!("ChatComponent({:?})", self.0.to_json())
+    }
+}
+
+/// Look up the UUID of a player, with hyphens
+#[pyfunction]
+fn lookup_uuid(py: Python<'_>, username: &str) -> PyResult<String> {
+    py.detach(|| mojang::lookup_uuid(username))
+        .map(|x| x.to_string())
+        .map_err(to_pyerr)
+}
+
+/// Look up the current name of a player by UUID
+#[pyfunction]
+fn lookup_name(py: Python<'_>, uuid: &str) -> PyResult<String> {
+    py.detach(|| mojang::lookup_profile(uuid))
+        .map(|x| x.name)
+        .map_err(to_pyerr)
+}
+
+/// Authenticate with a Mojang account
+#[pyfunction]
+fn authenticate(py: Python<'_>, username: &str, password: &str) -> PyResult<Session> {
+    let auth = py.detach(|| Authenticate::new(username.to_string(), password.to_string()).perform())
+        .map_err(to_pyerr)?;
+    Ok(Session {
+           access_token: auth.accessToken,
+           client_token: auth.clientToken,
+           name: auth.selectedProfile.name,
+           uuid: auth.selectedProfile.id,
+       })
+}
+
+/// Ping a server for its status, waiting at most timeout seconds
+#[pyfunction]
+#[pyo3(signature = (host, port = 25565, timeout = 10.0))]
+fn ping(py: Python<'_>, host: &str, port: u16, timeout: f64) -> PyResult<Status> {
+    let timeout = Duration::from_millis((timeout.max(0.0) * 1000.0) as u64);
+    let x = py.detach(|| status::ping(host, port, timeout))
+        .map_err(to_pyerr)?;
+    Ok(Status {
+           version_name: x.version_name,
+           protocol: x.protocol,
+           players_online: x.players_online,
+           players_max: x.players_max,
+           sample: x.sample,
+           description: x.description,
+           favicon: x.favicon,
+           latency: x.latency.as_secs_f64(),
+       })
+}
+
+/// Get the visible text of chat json, without formatting
+#[pyfunction]
+fn chat_to_plain(json: &str) -> PyResult<String> {
+    chat::ChatComponent::parse(json)
+        .map(|x| x.to_plain())
+        .map_err(to_pyerr)
+}
+
+#[pymodule]
+fn ozelot(m: &Bound<'_, PyModule>) -> PyResult<()> {
+    m.add("OzelotError", m.py().get_type::<OzelotError>())?;
+    m.add_class::<Session>()?;
+    m.add_class::<Status>()?;
+    m.add_class::<PyChatComponent>()?;
+    m.add_function(wrap_pyfunction!(self::lookup_uuid, m)?)?;
+    m.add_function(wrap_pyfunction!(self::lookup_name, m)?)?;
+    m.add_function(wrap_pyfunction!(self::authenticate, m)?)?;
+    m.add_function(wrap_pyfunction!(self::ping, m)?)?;
+    m.add_function(wrap_pyfunction!(self::chat_to_plain, m)?)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn chat() {
+        assert_eq!(chat_to_plain(r#"{"text": "Hello ", "extra": ["world"]}"#).unwrap(),
+                   "Hello world");
+        let chat = PyChatComponent::new(r#"{"text": "Hi", "color": "red"}"#).unwrap();
+        assert_eq!(chat.to_json(), r#"{"color":"red","text":"Hi"}"#);
+        assert!(chat_to_plain("{").is_err());
+    }
+}
@@ -1,9 +1,14 @@
 //! Functions for deserializing datatypes used by the protocol
+use consts::MAX_STRING_BYTES;
 use errors::{Result, ResultExt};
 
 use std::io::Read;
+#[cfg(feature = "inline-strings")]
+use std::str;
 
 use byteorder::{BigEndian, ReadBytesExt};
+#[cfg(feature = "inline-strings")]
+use compact_str::CompactString;
 
 /// Read a single bool from the Reader
 pub fn read_bool<R: Read>(reader: &mut R) -> Result<bool> {
@@ -76,8 +81,11 @@ pub fn read_f64<R: Read>(reader: &mut R) -> Result<f64> {
 #[allow(non_snake_case)]
 pub fn read_String<R: Read>(reader: &mut R) -> Result<String> {
     let length = read_varint(reader)? as usize;
+    read_string_body(reader, length)
+}
 
-    if length > (1 << 16) {
+fn read_string_body<R: Read>(reader: &mut R, length: usize) -> Result<String> {
+    if length > MAX_STRING_BYTES {
         bail!("read_string refusing to read string due to its length");
     }
 
@@ -91,6 +99,52 @@ pub fn read_String<R: Read>(reader: &mut R) -> Result<String> {
     Ok(ret)
 }
 
+/// A string field that is usually short, e.g. an identifier or plugin
+/// channel.
+///
+/// This is a String, unless the inline-strings feature is enabled, in which
+/// case it is a CompactString from the compact_str crate. CompactString
+/// stores strings of up to 24 bytes inline rather than allocating them,
+/// which saves an allocation per such field of e.g. every PluginMessage and
+/// NamedSoundEffect received.
+#[cfg(not(feature = "inline-strings"))]
+pub type SmallString = String;
+/// A string field that is usually short, e.g. an identifier or plugin
+/// channel.
+///
+/// This is a String, unless the inline-strings feature is enabled, in which
+/// case it is a CompactString from the compact_str crate. CompactString
+/// stores strings of up to 24 bytes inline rather than allocating them,
+/// which saves an allocation per such field of e.g. every PluginMessage and
+/// NamedSoundEffect received.
+#[cfg(feature = "inline-strings")]
+pub type SmallString = CompactString;
+
+/// Read a length-prefixed utf-8 SmallString from the Reader
+#[cfg(not(feature = "inline-strings"))]
+#[allow(non_snake_case)]
+pub fn read_SmallString<R: Read>(reader: &mut R) -> Result<SmallString> {
+    read_String(reader)
+}
+
+/// Read a length-prefixed utf-8 SmallString from the Reader, without
+/// allocating if it fits inline
+#[cfg(feature = "inline-strings")]
+#[allow(non_snake_case)]
+pub fn read_SmallString<R: Read>(reader: &mut R) -> Result<SmallString> {
+    let length = read_varint(reader)? as usize;
+    let mut buf = [0; 24];
+    if length > buf.len() {
+        return Ok(SmallString::from(read_string_body(reader, length)?));
+    }
+    reader.read_exact(&mut buf[..length])
+        .chain_err(|| format!("read_SmallString expected a string with length {}", length))?;
+    match str::from_utf8(&buf[..length]) {
+        Ok(x) => Ok(SmallString::from(x)),
+        Err(_) => bail!("read_SmallString read a string that isn't valid utf-8"),
+    }
+}
+
 /// Read a Minecraft-style varint, which currently fits into an i32
 pub fn read_varint<R: Read>(reader: &mut R) -> Result<i32> {
     let mut result = 0;
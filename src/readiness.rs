@@ -0,0 +1,147 @@
+//! Registering connections in a mio poll loop
+//!
+//! With the mio feature enabled, Client, Server and ServerListener implement
+//! mio's Source trait, so they can be registered directly with a mio Poll
+//! instead of being polled in a loop with sleeps. This is only available on
+//! unix.
+//!
+//! The connections are always nonblocking. When a Client or Server is
+//! readable, call try_read_packet() until it returns NeedMore. When
+//! pending_write() is greater than 0 after a send(), register for writable
+//! events as well, and call write() when writable.
+//!
+//! # Examples
+//!
+//! ```rust,no_run
+//! extern crate mio;
+//! extern crate ozelot;
+//!
+//! use mio::{Events, Interest, Poll, Token};
+//! use ozelot::{Client, Packet, ReadStatus};
+//!
+//! # fn main() {
+//! let mut client = Client::connect_unauthenticated("localhost", 25565, "Bob").unwrap();
+//! let mut poll = Poll::new().unwrap();
+//! poll.registry().register(&mut client, Token(0), Interest::READABLE).unwrap();
+//!
+//! let mut events = Events::with_capacity(16);
+//! loop {
+//!     poll.poll(&mut events, None).unwrap();
+//!     for event in &events {
+//!         if event.is_readable() {
+//!             while let ReadStatus::Ready(packet) = client.try_read_packet().unwrap() {
+//!                 println!("{}", packet.get_packet_name());
+//!             }
+//!         }
+//!     }
+//! }
+//! # }
+//! ```
+
+use {Client, Server};
+use listener::ServerListener;
+
+use std::io;
+use std::os::unix::io::{AsRawFd, RawFd};
+
+use mio::{Interest, Registry, Token};
+use mio::event::Source;
+use mio::unix::SourceFd;
+
+macro_rules! impl_source {
+    ($name:ident) => {
+        impl Source for $name {
+            fn register(&mut self,
+                        registry: &Registry,
+                        token: Token,
+                        interests: Interest)
+                        -> io::Result<()> {
+                SourceFd(&fd(self.raw_fd())?).register(registry, token, interests)
+            }
+
+            fn reregister(&mut self,
+                          registry: &Registry,
+                          token: Token,
+                          interests: Interest)
+                          -> io::Result<()> {
+                SourceFd(&fd(self.raw_fd())?).reregister(registry, token, interests)
+            }
+
+            fn deregister(&mut self, registry: &Registry) -> io::Result<()> {
+                SourceFd(&fd(self.raw_fd())?).deregister(registry)
+            }
+        }
+    }
+}
+
+/* Connections over transports without a file descriptor can't be polled */
+fn fd(fd: Option<RawFd>) -> io::Result<RawFd> {
+    fd.ok_or_else(|| {
+                      io::Error::new(io::ErrorKind::InvalidInput,
+                                     "The transport has no file descriptor to poll")
+                  })
+}
+
+impl_source!(Client);
+impl_source!(Server);
+
+/// All the addresses of the listener are registered with the same token,
+/// call accept() when any of them is readable.
+impl Source for ServerListener {
+    fn register(&mut self,
+                registry: &Registry,
+                token: Token,
+                interests: Interest)
+                -> io::Result<()> {
+        for listener in self.listeners() {
+            SourceFd(&listener.as_raw_fd()).register(registry, token, interests)?;
+        }
+        Ok(())
+    }
+
+    fn reregister(&mut self,
+                  registry: &Registry,
+                  token: Token,
+                  interests: Interest)
+                  -> io::Result<()> {
+        for listener in self.listeners() {
+            SourceFd(&listener.as_raw_fd()).reregister(registry, token, interests)?;
+        }
+        Ok(())
+    }
+
+    fn deregister(&mut self, registry: &Registry) -> io::Result<()> {
+        for listener in self.listeners() {
+            SourceFd(&listener.as_raw_fd()).deregister(registry)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use mio::{Events, Poll};
+    use listener::ListenerOptions;
+    use std::net::TcpStream;
+    use std::time::Duration;
+
+    #[test]
+    fn listener_readiness() {
+        let mut listener = ServerListener::bind(&["127.0.0.1:0"], ListenerOptions::default()).unwrap();
+        let addr = listener.local_addrs().unwrap()[0];
+        let mut poll = Poll::new().unwrap();
+        poll.registry().register(&mut listener, Token(1), Interest::READABLE).unwrap();
+
+        let _stream = TcpStream::connect(addr).unwrap();
+        let mut events = Events::with_capacity(4);
+        poll.poll(&mut events, Some(Duration::from_secs(5))).unwrap();
+        assert!(events.iter().any(|e| e.token() == Token(1) && e.is_readable()));
+
+        let mut conns = listener.accept().unwrap();
+        assert_eq!(conns.len(), 1);
+        let server: &mut Server = &mut conns[0];
+        poll.registry().register(server, Token(2), Interest::READABLE).unwrap();
+        poll.registry().deregister(server).unwrap();
+    }
+}
@@ -0,0 +1,410 @@
+//! Recording the packets of a connection to a file
+//!
+//! Give a Client or Server a Recorder with set_recorder(), and every packet
+//! sent and received is appended to the file, to be replayed or inspected
+//! later with read_records().
+//!
+//! To keep long sessions manageable, a Filter selects which packets are
+//! recorded, and a Rotation caps the size of the file by moving it aside
+//! (to file.1, file.2, ...) once it's too large, keeping only a few old
+//! files.
+//!
+//! Filters are written as space separated terms, all of which must match.
+//! Each term is a key and a comma separated list of values, any of which may
+//! match, and may be negated with a leading "!":
+//!
+//! - state=handshake,status,login,play
+//! - dir=in,out
+//! - type=<packet names>, e.g. type=ChunkData,KeepAlive
+//! - entity=<entity IDs>, matching only packets about one of the entities
+//!
+//! E.g. "state=play !type=KeepAlive,ChunkData entity=42".
+//!
+//! Recording must never take down the connection, so errors writing the
+//! file are ignored by the connection.
+//!
+//! # Examples
+//!
+//! ```rust,no_run
+//! use ozelot::Client;
+//! use ozelot::recorder::{Filter, Recorder, Rotation};
+//!
+//! let mut recorder = Recorder::open("session.rec").unwrap();
+//! recorder.set_filter("state=play !type=ChunkData".parse::<Filter>().unwrap());
+//! recorder.set_rotation(Some(Rotation { max_size: 64 << 20, max_files: 4 }));
+//! let mut client = Client::connect_unauthenticated("localhost", 25565, "bot").unwrap();
+//! let _ = client.set_recorder(Some(recorder));
+//! ```
+
+use ClientState;
+use errors::{Error, Result};
+use read::{read_String, read_i32, read_prefixed_bytearray, read_u64, read_u8, read_varint};
+use stats::Direction;
+use write::{write_String, write_prefixed_bytearray, write_u64, write_u8};
+
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, BufWriter, Cursor, Read, Write};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Clientbound packets starting with a varint entity ID
+const VARINT_ENTITY_PACKETS: &[&str] = &["SpawnObject",
+                                         "SpawnExperienceOrb",
+                                         "SpawnGlobalEntity",
+                                         "SpawnMob",
+                                         "SpawnPainting",
+                                         "SpawnPlayer",
+                                         "ClientboundAnimation",
+                                         "BlockBreakAnimation",
+                                         "Entity",
+                                         "EntityRelativeMove",
+                                         "EntityLookRelativeMove",
+                                         "EntityLook",
+                                         "UseBed",
+                                         "RemoveEntityEffect",
+                                         "EntityHeadLook",
+                                         "Camera",
+                                         "EntityMetadata",
+                                         "EntityVelocity",
+                                         "EntityEquipment",
+                                         "SetPassengers",
+                                         "CollectItem",
+                                         "EntityTeleport",
+                                         "EntityProperties",
+                                         "EntityEffect",
+                                         "EntityAction"];
+/// Packets starting with an i32 entity ID
+const I32_ENTITY_PACKETS: &[&str] = &["EntityStatus", "JoinGame", "AttachEntity"];
+
+/// A recorded packet
+#[derive(Debug, Clone, PartialEq)]
+pub struct Record {
+    /// Milliseconds since the unix epoch
+    pub timestamp: u64,
+    pub direction: Direction,
+    pub state: ClientState,
+    /// The packet name, e.g. "ChunkData"
+    pub name: String,
+    /// The packet ID followed by its fields, uncompressed
+    pub data: Vec<u8>,
+}
+impl Record {
+    /// Get the ID of the entity the packet is about, for the packets whose
+    /// first field is an entity ID
+    pub fn entity_id(&self) -> Option<i32> {
+        let mut r = Cursor::new(&self.data);
+        let _: i32 = read_varint(&mut r).ok()?;
+        if VARINT_ENTITY_PACKETS.contains(&self.name.as_str()) {
+            read_varint(&mut r).ok()
+        } else if I32_ENTITY_PACKETS.contains(&self.name.as_str()) {
+            read_i32(&mut r).ok()
+        } else {
+            None
+        }
+    }
+
+    fn write<W: Write>(&self, w: &mut W) -> Result<()> {
+        write_u64(&self.timestamp, w)?;
+        write_u8(&match self.direction {
+                     Direction::Inbound => 0,
+                     Direction::Outbound => 1,
+                 },
+                 w)?;
+        write_u8(&match self.state {
+                     ClientState::Handshake => 0,
+                     ClientState::Status => 1,
+                     ClientState::Login => 2,
+                     ClientState::Play => 3,
+                 },
+                 w)?;
+        write_String(&self.name, w)?;
+        write_prefixed_bytearray(&self.data, w)
+    }
+
+    fn read<R: Read>(r: &mut R) -> Result<Self> {
+        let timestamp = read_u64(r)?;
+        let direction = match read_u8(r)? {
+            0 => Direction::Inbound,
+            1 => Direction::Outbound,
+            x => bail!("Invalid direction in record: {}", x),
+        };
+        let state = match read_u8(r)? {
+            0 => ClientState::Handshake,
+            1 => ClientState::Status,
+            2 => ClientState::Login,
+            3 => ClientState::Play,
+            x => bail!("Invalid state in record: {}", x),
+        };
+        Ok(Record {
+               timestamp,
+               direction,
+               state,
+               name: read_String(r)?,
+               data: read_prefixed_bytearray(r)?,
+           })
+    }
+}
+
+/// Read all the records from a recording
+pub fn read_records<R: Read>(mut r: R) -> Result<Vec<Record>> {
+    let mut data = Vec::new();
+    let _: usize = r.read_to_end(&mut data)?;
+    let mut r = Cursor::new(&data);
+    let mut ret = Vec::new();
+    while (r.position() as usize) < data.len() {
+        ret.push(Record::read(&mut r)?);
+    }
+    Ok(ret)
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Condition {
+    State(Vec<ClientState>),
+    Direction(Vec<Direction>),
+    Type(Vec<String>),
+    Entity(Vec<i32>),
+}
+
+/// Selects which packets are recorded, see the module documentation
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct Filter {
+    /* The conditions, and whether they're negated */
+    terms: Vec<(Condition, bool)>,
+}
+impl Filter {
+    /// A filter matching everything
+    pub fn all() -> Self {
+        Filter::default()
+    }
+
+    /// Whether the record matches this filter
+    pub fn matches(&self, record: &Record) -> bool {
+        self.terms.iter().all(|&(ref condition, negated)| {
+            let matches = match *condition {
+                Condition::State(ref x) => x.contains(&record.state),
+                Condition::Direction(ref x) => x.contains(&record.direction),
+                Condition::Type(ref x) => x.contains(&record.name),
+                Condition::Entity(ref x) => {
+                    record.entity_id().is_some_and(|id| x.contains(&id))
+                },
+            };
+            matches != negated
+        })
+    }
+}
+impl FromStr for Filter {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let mut terms = Vec::new();
+        for term in s.split_whitespace() {
+            let (negated, term) = match term.strip_prefix('!') {
+                Some(x) => (true, x),
+                None => (false, term),
+            };
+            let (key, values) = match term.find('=') {
+                Some(i) => (&term[..i], term[i + 1..].split(',')),
+                None => bail!("Filter term without a value: {:?}", term),
+            };
+            let condition = match key {
+                "state" => Condition::State(parse_values(values, parse_state)?),
+                "dir" => Condition::Direction(parse_values(values, parse_direction)?),
+                "type" => Condition::Type(values.map(|x| x.to_string()).collect()),
+                "entity" => Condition::Entity(parse_values(values, |x| x.parse().ok())?),
+                _ => bail!("Unknown filter key: {:?}", key),
+            };
+            terms.push((condition, negated));
+        }
+        Ok(Filter { terms })
+    }
+}
+
+fn parse_values<'a, I, T, F>(values: I, f: F) -> Result<Vec<T>>
+    where I: Iterator<Item = &'a str>,
+          F: Fn(&str) -> Option<T>
+{
+    values
+        .map(|x| match f(x) {
+                 Some(x) => Ok(x),
+                 None => bail!("Invalid value in filter: {:?}", x),
+             })
+        .collect()
+}
+
+fn parse_state(s: &str) -> Option<ClientState> {
+    match s.to_lowercase().as_str() {
+        "handshake" => Some(ClientState::Handshake),
+        "status" => Some(ClientState::Status),
+        "login" => Some(ClientState::Login),
+        "play" => Some(ClientState::Play),
+        _ => None,
+    }
+}
+
+fn parse_direction(s: &str) -> Option<Direction> {
+    match s {
+        "in" => Some(Direction::Inbound),
+        "out" => Some(Direction::Outbound),
+        _ => None,
+    }
+}
+
+/// When to move a recording aside and start a new file
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Rotation {
+    /// The maximum size of a file in bytes
+    pub max_size: u64,
+    /// How many old files to keep, as file.1 (the newest) to file.N
+    pub max_files: usize,
+}
+
+/// Records packets to a file, see the module documentation
+#[derive(Debug)]
+pub struct Recorder {
+    path: PathBuf,
+    file: BufWriter<File>,
+    size: u64,
+    filter: Filter,
+    rotation: Option<Rotation>,
+}
+impl Recorder {
+    /// Open the given file for appending, creating it if needed
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let path = path.as_ref().to_path_buf();
+        let file = OpenOptions::new().append(true).create(true).open(&path)?;
+        let size = file.metadata()?.len();
+        Ok(Recorder {
+               path,
+               file: BufWriter::new(file),
+               size,
+               filter: Filter::all(),
+               rotation: None,
+           })
+    }
+
+    /// Set which packets are recorded
+    pub fn set_filter(&mut self, filter: Filter) {
+        self.filter = filter;
+    }
+
+    /// Set when to rotate the file, or None to let it grow forever
+    pub fn set_rotation(&mut self, rotation: Option<Rotation>) {
+        self.rotation = rotation;
+    }
+
+    /// Record a packet given by its serialized data (ID and fields), if it
+    /// matches the filter
+    pub fn record(&mut self,
+                  direction: Direction,
+                  state: &ClientState,
+                  name: &str,
+                  data: Vec<u8>)
+                  -> Result<()> {
+        let record = Record {
+            timestamp: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map(|d| d.as_millis() as u64)
+                .unwrap_or(0),
+            direction,
+            state: state.clone(),
+            name: name.to_string(),
+            data,
+        };
+        if !self.filter.matches(&record) {
+            return Ok(());
+        }
+        let mut buf = Vec::new();
+        record.write(&mut buf)?;
+        if let Some(rotation) = self.rotation {
+            if self.size > 0 && self.size + buf.len() as u64 > rotation.max_size {
+                self.rotate(rotation.max_files)?;
+            }
+        }
+        self.file.write_all(&buf)?;
+        self.size += buf.len() as u64;
+        Ok(())
+    }
+
+    /// Flush the buffered records to the file
+    pub fn flush(&mut self) -> Result<()> {
+        Ok(self.file.flush()?)
+    }
+
+    fn rotated_path(&self, i: usize) -> PathBuf {
+        let mut name = self.path.clone().into_os_string();
+        name.push(format!(".{}", i));
+        PathBuf::from(name)
+    }
+
+    /* Move file.N-1 to file.N, ..., file to file.1, and start a new file */
+    fn rotate(&mut self, max_files: usize) -> Result<()> {
+        self.file.flush()?;
+        if max_files == 0 {
+            fs::remove_file(&self.path)?;
+        } else {
+            for i in (1..max_files).rev() {
+                match fs::rename(self.rotated_path(i), self.rotated_path(i + 1)) {
+                    Err(ref e) if e.kind() == io::ErrorKind::NotFound => (),
+                    x => x?,
+                }
+            }
+            fs::rename(&self.path, self.rotated_path(1))?;
+        }
+        let file = OpenOptions::new().append(true).create(true).open(&self.path)?;
+        self.file = BufWriter::new(file);
+        self.size = 0;
+        Ok(())
+    }
+}
+impl Drop for Recorder {
+    fn drop(&mut self) {
+        let _ = self.file.flush();
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::env;
+
+    fn record(name: &str, data: Vec<u8>) -> Record {
+        Record {
+            timestamp: 0,
+            direction: Direction::Inbound,
+            state: ClientState::Play,
+            name: name.to_string(),
+            data,
+        }
+    }
+
+    #[test]
+    fn filter() {
+        let filter: Filter = "state=play !type=KeepAlive entity=42".parse().unwrap();
+        /* Packet ID 0x28 (EntityRelativeMove), entity 42 */
+        assert!(filter.matches(&record("EntityRelativeMove", vec![0x28, 42])));
+        assert!(!filter.matches(&record("EntityRelativeMove", vec![0x28, 43])));
+        assert!(!filter.matches(&record("KeepAlive", vec![0x21, 42])));
+        assert!("dir=sideways".parse::<Filter>().is_err());
+        assert!(Filter::all().matches(&record("KeepAlive", vec![])));
+    }
+
+    #[test]
+    fn rotation() {
+        let path = env::temp_dir().join(format!("ozelot-recorder-{}", ::std::process::id()));
+        let mut recorder = Recorder::open(&path).unwrap();
+        recorder.set_rotation(Some(Rotation { max_size: 40, max_files: 1 }));
+        for _ in 0..3 {
+            recorder.record(Direction::Outbound, &ClientState::Play, "KeepAlive", vec![0; 20])
+                .unwrap();
+        }
+        recorder.flush().unwrap();
+        let records = read_records(File::open(&path).unwrap()).unwrap();
+        assert_eq!(records.len(), 1);
+        assert_eq!(records[0].name, "KeepAlive");
+        let old = recorder.rotated_path(1);
+        assert_eq!(read_records(File::open(&old).unwrap()).unwrap().len(), 1);
+        let _ = fs::remove_file(&path);
+        let _ = fs::remove_file(&old);
+    }
+}
@@ -0,0 +1,205 @@
+//! Validating the names used by teams and scoreboards
+//!
+//! The client disconnects when a Teams, ScoreboardObjective or UpdateScore
+//! packet has a name longer than the protocol allows, so sending one by
+//! mistake kicks every player who sees it. The validate_* functions check
+//! names against the vanilla limits and return an EmptyName, NameTooLong or
+//! InvalidNameCharacter error, and the sanitize_* functions turn arbitrary
+//! input into a name that passes.
+//!
+//! Team and objective names are further limited to the characters vanilla
+//! commands accept without quotes (letters, digits and _ - . +), so the
+//! teams and objectives can also be managed with commands.
+//!
+//! Lengths are counted in UTF-16 code units, like Java does.
+
+use chat::ChatComponent;
+use consts;
+use errors::{ErrorKind, Result};
+
+use serde_json;
+
+use std::fmt;
+
+/// The maximum length of team names
+pub const MAX_TEAM_NAME: usize = consts::MAX_TEAM_NAME_LENGTH;
+/// The maximum length of objective names
+pub const MAX_OBJECTIVE_NAME: usize = consts::MAX_TEAM_NAME_LENGTH;
+/// The maximum length of score holders, i.e. player names or entity UUIDs
+/// in teams and scores
+pub const MAX_SCORE_HOLDER: usize = consts::MAX_SCORE_HOLDER_LENGTH;
+/// The maximum length of display names, prefixes and suffixes, as chat json
+pub const MAX_DISPLAY_NAME: usize = consts::MAX_CHAT_JSON_LENGTH;
+
+/// What a name is used as
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NameKind {
+    Team,
+    Objective,
+    ScoreHolder,
+    /// A display name, prefix or suffix
+    DisplayName,
+}
+impl NameKind {
+    /// The maximum length of this kind of name
+    pub fn max_length(&self) -> usize {
+        match *self {
+            NameKind::Team => MAX_TEAM_NAME,
+            NameKind::Objective => MAX_OBJECTIVE_NAME,
+            NameKind::ScoreHolder => MAX_SCORE_HOLDER,
+            NameKind::DisplayName => MAX_DISPLAY_NAME,
+        }
+    }
+
+    /// Whether the character is allowed in this kind of name
+    pub fn is_allowed_character(&self, c: char) -> bool {
+        match *self {
+            NameKind::Team | NameKind::Objective => {
+                c.is_ascii_alphanumeric() || "_-.+".contains(c)
+            },
+            NameKind::ScoreHolder => !c.is_control() && !c.is_whitespace(),
+            NameKind::DisplayName => true,
+        }
+    }
+}
+impl fmt::Display for NameKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match *self {
+                        NameKind::Team => "team name",
+                        NameKind::Objective => "objective name",
+                        NameKind::ScoreHolder => "score holder",
+                        NameKind::DisplayName => "display name",
+                    })
+    }
+}
+
+fn java_length(s: &str) -> usize {
+    s.encode_utf16().count()
+}
+
+fn validate(kind: NameKind, name: &str) -> Result<()> {
+    if name.is_empty() {
+        bail!(ErrorKind::EmptyName(kind));
+    }
+    let length = java_length(name);
+    if length > kind.max_length() {
+        bail!(ErrorKind::NameTooLong(kind, length, kind.max_length()));
+    }
+    if let Some(c) = name.chars().find(|&c| !kind.is_allowed_character(c)) {
+        bail!(ErrorKind::InvalidNameCharacter(kind, c));
+    }
+    Ok(())
+}
+
+fn sanitize(kind: NameKind, name: &str) -> Option<String> {
+    let mut ret = String::new();
+    let mut length = 0;
+    for c in name.chars().filter(|&c| kind.is_allowed_character(c)) {
+        length += c.len_utf16();
+        if length > kind.max_length() {
+            break;
+        }
+        ret.push(c);
+    }
+    if ret.is_empty() { None } else { Some(ret) }
+}
+
+/// Check a team name, see the module documentation
+pub fn validate_team_name(name: &str) -> Result<()> {
+    validate(NameKind::Team, name)
+}
+
+/// Check an objective name, see the module documentation
+pub fn validate_objective_name(name: &str) -> Result<()> {
+    validate(NameKind::Objective, name)
+}
+
+/// Check a score holder, i.e. a player name or entity UUID
+pub fn validate_score_holder(name: &str) -> Result<()> {
+    validate(NameKind::ScoreHolder, name)
+}
+
+/// Check a display name, prefix or suffix, given as chat json
+pub fn validate_display_name(json: &str) -> Result<()> {
+    validate(NameKind::DisplayName, json)?;
+    let _: ChatComponent = ChatComponent::parse(json)?;
+    Ok(())
+}
+
+/// Remove the characters not allowed in team names and cut the name to the
+/// maximum length, returning None if nothing is left
+pub fn sanitize_team_name(name: &str) -> Option<String> {
+    sanitize(NameKind::Team, name)
+}
+
+/// Like sanitize_team_name(), for objective names
+pub fn sanitize_objective_name(name: &str) -> Option<String> {
+    sanitize(NameKind::Objective, name)
+}
+
+/// Like sanitize_team_name(), for score holders
+pub fn sanitize_score_holder(name: &str) -> Option<String> {
+    sanitize(NameKind::ScoreHolder, name)
+}
+
+/// Create a display name from plain text, cutting the text so that the
+/// chat json fits within the maximum length
+pub fn sanitize_display_name(text: &str) -> String {
+    let json = |text: &str| json!({ "text": text }).to_string();
+    let mut ret = json(text);
+    if java_length(&ret) <= MAX_DISPLAY_NAME {
+        return ret;
+    }
+    /* Escaping can make the json longer than the text, so cut it until it
+     * fits */
+    let mut chars: Vec<char> = text.chars().collect();
+    while java_length(&ret) > MAX_DISPLAY_NAME {
+        let excess = java_length(&ret) - MAX_DISPLAY_NAME;
+        let keep = chars.len().saturating_sub(excess.max(1));
+        chars.truncate(keep);
+        ret = json(&chars.iter().collect::<String>());
+    }
+    debug_assert!(serde_json::from_str::<serde_json::Value>(&ret).is_ok());
+    ret
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use errors::Error;
+
+    fn kind(res: Result<()>) -> ErrorKind {
+        match res {
+            Err(Error(kind, _)) => kind,
+            Ok(()) => panic!("Expected an error"),
+        }
+    }
+
+    #[test]
+    fn names() {
+        validate_team_name("red_team").unwrap();
+        match kind(validate_team_name("a_very_long_team_name")) {
+            ErrorKind::NameTooLong(NameKind::Team, 21, 16) => (),
+            x => panic!("Unexpected error {}", x),
+        }
+        match kind(validate_objective_name("kills ")) {
+            ErrorKind::InvalidNameCharacter(NameKind::Objective, ' ') => (),
+            x => panic!("Unexpected error {}", x),
+        }
+        match kind(validate_score_holder("")) {
+            ErrorKind::EmptyName(NameKind::ScoreHolder) => (),
+            x => panic!("Unexpected error {}", x),
+        }
+        validate_score_holder("069a79f4-44e9-4726-a5be-fca90e38aaf5").unwrap();
+        validate_display_name(r#"{"text": "Red"}"#).unwrap();
+        assert!(validate_display_name("{").is_err());
+
+        assert_eq!(sanitize_team_name("Red Team \u{a7}c!"), Some("RedTeamc".to_string()));
+        assert_eq!(sanitize_objective_name("abcdefghijklmnopqrstuvwxyz"),
+                   Some("abcdefghijklmnop".to_string()));
+        assert_eq!(sanitize_team_name("!!"), None);
+        let long = sanitize_display_name(&"\"".repeat(MAX_DISPLAY_NAME));
+        assert!(java_length(&long) <= MAX_DISPLAY_NAME);
+        validate_display_name(&long).unwrap();
+    }
+}
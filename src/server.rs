@@ -1,22 +1,127 @@
 use ClientState;
-use clientbound::ClientboundPacket;
-use connection::Connection;
-use errors::Result;
-use serverbound::ServerboundPacket;
+use audit::{AuditEvent, AuditRecord, AuditSink};
+use chat::{ChatPolicy, ChatViolation};
+use clientbound::{self, ClientboundPacket};
+use connection::{Connection, ReadStatus};
+use culling::{Cull, Culler, CullingOptions};
+use custom::{CustomPacket, RawPacket};
+use entity::Location;
+use errors::{Error, Result};
+use extensions::Extensions;
+use lifecycle::LifecycleRecord;
+use mojang;
+use movement::{MovementLimits, MovementValidator, Violation};
+use prepared::PreparedPacket;
+use priority::{Priority, SendQueue};
+use protocol_data::ProtocolData;
+use serverbound::{self, ServerboundPacket};
+use recorder::Recorder;
+use stats::PacketStats;
+use transition::TransitionLog;
+use transport::Transport;
+use uuid::Uuid;
+use vhost::VirtualHost;
 
-use std::net::TcpStream;
+use std::net::{SocketAddr, TcpStream};
 use std::borrow::Borrow;
+use std::sync::Arc;
+use std::sync::mpsc::Sender;
+use std::{mem, time};
+#[cfg(unix)]
+use std::os::unix::io::RawFd;
+
+use openssl::pkey::Private;
+use openssl::rsa::Rsa;
+
+/// How server.login_offline() and server.login_online() log in the client
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct LoginOptions {
+    /// Log in without encryption or authenticating with Mojang, giving the
+    /// client its offline UUID. This must be set explicitly, since anyone
+    /// can then log in as anyone, so it's only suitable for test servers and
+    /// for backends behind a proxy that authenticates the players.
+    pub offline_mode: bool,
+    /// Enable compression with the given threshold before logging in
+    pub compression_threshold: Option<usize>,
+    /// What server.login_online() does when the session servers can't be
+    /// reached to authenticate the client
+    pub session_outage: SessionOutage,
+}
+
+/// What to do when the session servers are down while logging in a client.
+///
+/// Large proxies often keep letting players join during an outage, rather
+/// than locking everyone out. Note that the client is then logged in
+/// without being authenticated, so anyone can log in as anyone.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum SessionOutage {
+    /// Refuse the login, returning the error
+    #[default]
+    Fail,
+    /// Log in the client with its offline UUID
+    AllowOffline,
+    /// Ask the verifier given to server.set_fallback_verifier() for the UUID
+    /// to log in with. Fails like Fail if there's no verifier.
+    Fallback,
+}
+
+/// Called with the username and address of a client when the session
+/// servers are down, see SessionOutage::Fallback. Returns the UUID to log in
+/// the client with, e.g. from a cache of earlier logins, or an error to
+/// refuse the login.
+pub type FallbackVerifier = Arc<dyn Fn(&str, Option<SocketAddr>) -> Result<Uuid> + Send + Sync>;
 
 /// Represents a single client connection, from the point of view of a server
 pub struct Server {
     conn: Connection<ServerboundPacket, ClientboundPacket>,
+    next_teleport_id: i32,
+    awaiting_teleport: Option<i32>,
+    peer: Option<SocketAddr>,
+    username: Option<String>,
+    audit: Option<Arc<dyn AuditSink>>,
+    locale: Option<String>,
+    chat_mode: i32,
+    chat_policy: ChatPolicy,
+    virtual_host: Option<VirtualHost>,
+    movement: Option<MovementValidator>,
+    violations: Vec<Violation>,
+    queue: Option<SendQueue>,
+    culler: Option<Culler>,
+    fallback_verifier: Option<FallbackVerifier>,
+    extensions: Extensions,
 }
 impl Server {
     /// Create a new connection from an existing TcpStream
     pub fn from_tcpstream(stream: TcpStream) -> Result<Self> {
-        Ok(Server {
-               conn: Connection::from_tcpstream(stream)?,
-           })
+        Ok(Server::from_connection(Connection::from_tcpstream(stream)?))
+    }
+
+    /// Create a new connection over a nonblocking transport other than a
+    /// TcpStream, e.g. a unix socket, see the transport module
+    pub fn from_transport<T: Transport + 'static>(transport: T) -> Result<Self> {
+        Ok(Server::from_connection(Connection::from_transport(Box::new(transport))))
+    }
+
+    fn from_connection(conn: Connection<ServerboundPacket, ClientboundPacket>) -> Self {
+        let peer = conn.peer_addr();
+        Server {
+               conn,
+               next_teleport_id: 0,
+               awaiting_teleport: None,
+               peer,
+               username: None,
+               audit: None,
+               locale: None,
+               chat_mode: 0,
+               chat_policy: ChatPolicy::default(),
+               virtual_host: None,
+               movement: None,
+               violations: Vec::new(),
+               queue: None,
+               culler: None,
+               fallback_verifier: None,
+               extensions: Extensions::new(),
+           }
     }
 
     /// Try to read some packets from the client.
@@ -42,14 +147,279 @@ impl Server {
     /// possible. Returns the length of the outgoing buffer. If this is greater
     /// than 0, you will need to call write() to send the remaining data.
     pub fn send<T: Borrow<ClientboundPacket>>(&mut self, packet: T) -> Result<usize> {
-        self.conn.send(packet.borrow())
+        let packet = packet.borrow();
+        let replaced;
+        let packet = match self.culler.as_mut().map(|x| x.filter(packet)) {
+            Some(Cull::Drop) => return Ok(self.conn.pending_write()),
+            Some(Cull::Replace(x)) => {
+                replaced = x;
+                &replaced
+            },
+            _ => packet,
+        };
+        if self.audit.is_some() {
+            match *packet {
+                ClientboundPacket::LoginSuccess(_) => {
+                    self.audit(AuditEvent::AuthResult {
+                                   success: true,
+                                   reason: None,
+                               });
+                },
+                ClientboundPacket::LoginDisconnect(ref p) => {
+                    self.audit(AuditEvent::Kick { reason: p.get_raw_chat().clone() });
+                },
+                ClientboundPacket::PlayDisconnect(ref p) => {
+                    self.audit(AuditEvent::Kick { reason: p.get_reason().clone() });
+                },
+                _ => (),
+            }
+        }
+        if self.queue.is_some() && Priority::of(packet) != Priority::Critical {
+            return self.send_prepared(&PreparedPacket::new(packet)?);
+        }
+        let _: usize = self.conn.send(packet)?;
+        self.flush_queue()
     }
 
-    /// Write from the outgoing buffer to the TcpStream
+    /// Send a packet serialized in advance, e.g. a chunk sent to many
+    /// clients, see the prepared module. Returns the length of the outgoing
+    /// buffer, like server.send().
+    pub fn send_prepared(&mut self, packet: &PreparedPacket) -> Result<usize> {
+        if let Some(ref mut queue) = self.queue {
+            let pending = self.conn.pending_write();
+            if let Some(packet) = queue.push(packet.clone(), pending) {
+                let _: usize = self.conn.send_prepared(&packet)?;
+            }
+        } else {
+            let _: usize = self.conn.send_prepared(packet)?;
+        }
+        self.flush_queue()
+    }
+
+    /// Write from the outgoing buffer to the TcpStream, moving queued
+    /// packets to the buffer as it drains if the send queue is enabled
     ///
-    /// Returns the amount of bytes written.
+    /// Returns the amount of bytes written, which is 0 if the socket can't
+    /// take any more data right now.
     pub fn write(&mut self) -> Result<usize> {
-        self.conn.write()
+        let ret = self.conn.write()?;
+        let _: usize = self.flush_queue()?;
+        Ok(ret)
+    }
+
+    /// Queue outgoing packets by priority while the outgoing buffer holds
+    /// more than the given amount of bytes, so that chunks don't hold up
+    /// KeepAlives, or send them right away if None. See the priority module.
+    ///
+    /// Disabling the queue moves the queued packets to the buffer.
+    pub fn set_send_queue(&mut self, limit: Option<usize>) -> Result<()> {
+        let old = mem::replace(&mut self.queue, limit.map(SendQueue::new));
+        for packet in old.map(|mut x| x.drain()).unwrap_or_default() {
+            let _: usize = self.send_prepared(&packet)?;
+        }
+        Ok(())
+    }
+
+    /// Get the amount of packets in the send queue. Like with
+    /// pending_write(), call write() until this is 0.
+    pub fn queued(&self) -> usize {
+        self.queue.as_ref().map_or(0, |x| x.len())
+    }
+
+    /* Move packets from the send queue to the outgoing buffer while it has
+     * room, returning the length of the buffer */
+    fn flush_queue(&mut self) -> Result<usize> {
+        if let Some(ref mut queue) = self.queue {
+            while let Some(packet) = queue.pop(self.conn.pending_write()) {
+                let _: usize = self.conn.send_prepared(&packet)?;
+            }
+        }
+        Ok(self.conn.pending_write())
+    }
+
+    /// Get the amount of bytes in the outgoing buffer not yet written to the
+    /// TcpStream
+    pub fn pending_write(&self) -> usize {
+        self.conn.pending_write()
+    }
+
+    /// Set how long to wait for data before the connection is closed with a
+    /// "Read timeout" error, or None to never time out. Defaults to 30
+    /// seconds.
+    pub fn set_read_timeout(&mut self, timeout: Option<time::Duration>) {
+        self.conn.set_read_timeout(timeout)
+    }
+
+    /// Get the ID of this connection, as used in lifecycle events
+    pub fn connection_id(&self) -> u64 {
+        self.conn.connection_id()
+    }
+
+    /// Set the channel lifecycle events of this connection are sent to, see
+    /// the lifecycle module
+    pub fn set_lifecycle_sender(&mut self, sender: Option<Sender<LifecycleRecord>>) {
+        self.conn.set_lifecycle_sender(sender)
+    }
+
+    /// Translate the packet IDs to and from those of another protocol
+    /// version, or None to use those of PROTOCOL_VERSION, see the
+    /// protocol_data module
+    pub fn set_protocol_data(&mut self, data: Option<Arc<ProtocolData>>) {
+        match data {
+            Some(data) => self.conn.set_packet_ids(Some(data.serverbound_ids()),
+                                                   Some(data.clientbound_ids())),
+            None => self.conn.set_packet_ids(None, None),
+        }
+    }
+
+    /// Register a custom packet, so that received packets with its ID are
+    /// kept for read_custom() instead of being decoded, see the custom module
+    pub fn register_custom<P: CustomPacket>(&mut self) {
+        self.conn.register_custom(P::STATE, P::ID, P::NAME)
+    }
+
+    /// Send a custom packet, see the custom module
+    pub fn send_custom<P: CustomPacket>(&mut self, packet: &P) -> Result<usize> {
+        self.conn.send_raw(packet.to_u8()?, P::NAME)
+    }
+
+    /// Get the next received packet with a registered custom ID, if any
+    pub fn read_custom(&mut self) -> Option<RawPacket> {
+        self.conn.read_custom()
+    }
+
+    /// Set the sink receiving audit records about this connection, see the
+    /// audit module
+    pub fn set_audit_sink(&mut self, sink: Option<Arc<dyn AuditSink>>) {
+        self.audit = sink;
+    }
+
+    /// Record an event in the audit sink, if there is one
+    pub fn audit(&self, event: AuditEvent) {
+        if let Some(ref sink) = self.audit {
+            sink.record(&AuditRecord::new(self.peer, self.username.clone(), event));
+        }
+    }
+
+    /// Get the username from the client's LoginStart, if it has been received
+    pub fn username(&self) -> Option<&str> {
+        self.username.as_deref()
+    }
+
+    /// Get the client's locale (e.g. "en_us") from its ClientSettings, if
+    /// it has sent them
+    pub fn locale(&self) -> Option<&str> {
+        self.locale.as_deref()
+    }
+
+    /// Get the client's chat mode from its ClientSettings: 0 for everything,
+    /// 1 for only system messages, 2 for hidden. Defaults to 0.
+    pub fn chat_mode(&self) -> i32 {
+        self.chat_mode
+    }
+
+    /// Set how chat messages from the client are validated, see the chat
+    /// module
+    pub fn set_chat_policy(&mut self, policy: ChatPolicy) {
+        self.chat_policy = policy;
+    }
+
+    /// Get the chat validation policy
+    pub fn chat_policy(&self) -> ChatPolicy {
+        self.chat_policy
+    }
+
+    /// Get the host the client connected to, from its Handshake, if it has
+    /// been received. See the vhost module.
+    pub fn virtual_host(&self) -> Option<&VirtualHost> {
+        self.virtual_host.as_ref()
+    }
+
+    /// Check the movement packets of the client against the given limits, or
+    /// stop checking them if None. See the movement module.
+    pub fn set_movement_limits(&mut self, limits: Option<MovementLimits>) {
+        self.movement = limits.map(MovementValidator::new);
+        self.violations.clear();
+    }
+
+    /// Get the limits movement packets are checked against, if any
+    pub fn movement_limits(&self) -> Option<&MovementLimits> {
+        self.movement.as_ref().map(|x| x.limits())
+    }
+
+    /// Get the movement violations found since the last call
+    pub fn poll_violations(&mut self) -> Vec<Violation> {
+        ::std::mem::take(&mut self.violations)
+    }
+
+    /// Cull the entity movement, particle and sound packets sent with
+    /// server.send() that the player can't see or hear, or send everything
+    /// if None. See the culling module.
+    pub fn set_culling(&mut self, options: Option<CullingOptions>) {
+        self.culler = options.map(|options| {
+            let mut culler = Culler::new(options);
+            if let Some(location) = self.culler.as_ref().and_then(|x| x.player_location()) {
+                culler.set_player_location(*location);
+            }
+            culler
+        });
+    }
+
+    /// Get the culler, if culling is enabled
+    pub fn culler(&self) -> Option<&Culler> {
+        self.culler.as_ref()
+    }
+
+    /// Get the state attached to this connection, see the extensions module
+    pub fn extensions(&self) -> &Extensions {
+        &self.extensions
+    }
+
+    /// Get the state attached to this connection mutably
+    pub fn extensions_mut(&mut self) -> &mut Extensions {
+        &mut self.extensions
+    }
+
+    /// Get the address of the client, if known
+    pub fn peer_addr(&self) -> Option<SocketAddr> {
+        self.peer
+    }
+
+    /// Teleport the player to the given location by sending a
+    /// PlayerPositionAndLook packet with a new teleport ID.
+    ///
+    /// Until the client confirms the teleport, server.awaiting_teleport()
+    /// returns its ID. Like the vanilla server, you should ignore any movement
+    /// packets from the client while awaiting a teleport confirmation, since
+    /// they were sent before the client knew about the teleport.
+    ///
+    /// The movement validation, if enabled, is skipped until the teleport is
+    /// confirmed, and continues from the new location.
+    ///
+    /// Returns the length of the outgoing buffer, like server.send()
+    pub fn teleport(&mut self, location: &Location) -> Result<usize> {
+        if let Some(ref mut movement) = self.movement {
+            movement.reset(Some(*location));
+        }
+        if let Some(ref mut culler) = self.culler {
+            culler.set_player_location(*location);
+        }
+        let id = self.next_teleport_id;
+        self.next_teleport_id = self.next_teleport_id.wrapping_add(1);
+        self.awaiting_teleport = Some(id);
+        let packet = clientbound::PlayerPositionAndLook::new(location.x,
+                                                             location.y,
+                                                             location.z,
+                                                             location.yaw,
+                                                             location.pitch,
+                                                             0,
+                                                             id);
+        self.send(packet)
+    }
+
+    /// Get the ID of the teleport that the client has yet to confirm, if any
+    pub fn awaiting_teleport(&self) -> Option<i32> {
+        self.awaiting_teleport
     }
 
     /// Attempt to close this connection, disconnecting the client
@@ -59,6 +429,34 @@ impl Server {
         self.conn.close()
     }
 
+    /// Enable or disable collecting statistics about the packets sent and
+    /// received, see the stats module. Disabling clears the statistics.
+    pub fn enable_packet_stats(&mut self, enable: bool) {
+        self.conn.enable_packet_stats(enable)
+    }
+
+    /// Get the packet statistics, if enabled
+    pub fn packet_stats(&self) -> Option<&PacketStats> {
+        self.conn.packet_stats()
+    }
+
+    /// Record the packets sent and received with the given recorder, see
+    /// the recorder module. Returns the previous recorder, if any.
+    pub fn set_recorder(&mut self, recorder: Option<Recorder>) -> Option<Recorder> {
+        self.conn.set_recorder(recorder)
+    }
+
+    /// Get the current client state of this connection
+    pub fn clientstate(&self) -> &ClientState {
+        self.conn.clientstate()
+    }
+
+    /// Get the log of the state transitions of this connection, see the
+    /// transition module
+    pub fn transition_log(&mut self) -> &mut TransitionLog {
+        self.conn.transitions()
+    }
+
     /// Change the client state of this connection
     pub fn set_clientstate(&mut self, new_state: ClientState) {
         self.conn.set_clientstate(new_state)
@@ -72,12 +470,150 @@ impl Server {
         self.conn.enable_encryption(key)
     }
 
-    /// Enable compression.
+    /// Verify the client's EncryptionResponse and enable encryption with the
+    /// shared secret it contains
+    ///
+    /// key and verify_token are the server's key and the verify token sent in
+    /// the EncryptionRequest. Encryption is only enabled if the verify token
+    /// matches and the shared secret is valid, otherwise an
+    /// ErrorKind::VerifyTokenMismatch or ErrorKind::BadSharedSecret error is
+    /// returned, and the client should be disconnected.
+    ///
+    /// Returns the shared secret, which is needed for authenticating the
+    /// client with Mojang.
+    pub fn accept_encryption(&mut self,
+                             response: &serverbound::EncryptionResponse,
+                             key: &Rsa<Private>,
+                             verify_token: &[u8])
+                             -> Result<[u8; 16]> {
+        let shared_secret = response.verify(key, verify_token)?;
+        self.enable_encryption(&shared_secret);
+        Ok(shared_secret)
+    }
+
+    /// Enable compression, sending the SetCompression packet to the client.
+    ///
+    /// Packets of at least threshold bytes are compressed. The
+    /// SetCompression packet itself is sent uncompressed, and every packet
+    /// sent after it is compressed, as the client expects. This must be
+    /// called during Login, i.e. after the LoginStart and before the
+    /// LoginSuccess, and only once, otherwise an error is returned and
+    /// nothing is sent.
+    ///
+    /// Returns the length of the outgoing buffer, like send().
+    pub fn enable_compression(&mut self, threshold: usize) -> Result<usize> {
+        if self.conn.clientstate() != &ClientState::Login {
+            bail!("Compression can only be enabled during Login, not {}",
+                  self.conn.clientstate());
+        }
+        if self.conn.compression().is_some() {
+            bail!("Compression is already enabled");
+        }
+        if threshold > i32::MAX as usize {
+            bail!("Compression threshold {} is too large", threshold);
+        }
+        let ret = self.send(clientbound::SetCompression::new(threshold as i32))?;
+        self.conn.enable_compression(threshold);
+        Ok(ret)
+    }
+
+    /// Log in the client without encryption, after its LoginStart has been
+    /// read: enable compression if wanted, send the LoginSuccess with the
+    /// offline UUID of the username, and switch to the Play state.
+    ///
+    /// options.offline_mode must be true, and the connection must be in the
+    /// Login state, otherwise an error is returned and nothing is sent.
+    ///
+    /// Returns the UUID and username of the player.
+    pub fn login_offline(&mut self, options: &LoginOptions) -> Result<(Uuid, String)> {
+        if !options.offline_mode {
+            bail!("Offline mode must be enabled explicitly with LoginOptions::offline_mode");
+        }
+        if self.conn.clientstate() != &ClientState::Login {
+            bail!("Can only log in during Login, not {}", self.conn.clientstate());
+        }
+        let username = match self.username {
+            Some(ref x) => x.clone(),
+            None => bail!("Can't log in before receiving the LoginStart"),
+        };
+        let uuid = Uuid::offline(&username)?;
+        if let Some(threshold) = options.compression_threshold {
+            let _: usize = self.enable_compression(threshold)?;
+        }
+        let _: usize = self.send(clientbound::LoginSuccess::new(uuid.as_u128(), username.clone()))?;
+        self.set_clientstate(ClientState::Play);
+        Ok((uuid, username))
+    }
+
+    /// Log in the client with encryption, authenticating it with Mojang,
+    /// after its EncryptionResponse has been read: enable encryption, check
+    /// that the client has joined with SessionHasJoined, enable compression
+    /// if wanted, send the LoginSuccess and switch to the Play state.
+    ///
+    /// key, verify_token and server_id are those sent in the
+    /// EncryptionRequest. If the session servers can't be reached, the
+    /// client is logged in according to options.session_outage.
+    ///
+    /// Returns the UUID and username of the player. If an error is returned,
+    /// the client should be disconnected.
+    pub fn login_online(&mut self,
+                        response: &serverbound::EncryptionResponse,
+                        key: &Rsa<Private>,
+                        verify_token: &[u8],
+                        server_id: &str,
+                        options: &LoginOptions)
+                        -> Result<(Uuid, String)> {
+        if self.conn.clientstate() != &ClientState::Login {
+            bail!("Can only log in during Login, not {}", self.conn.clientstate());
+        }
+        let username = match self.username {
+            Some(ref x) => x.clone(),
+            None => bail!("Can't log in before receiving the LoginStart"),
+        };
+        let shared_secret = self.accept_encryption(response, key, verify_token)?;
+        let public_key = key.public_key_to_der()?;
+        let joined = mojang::SessionHasJoined::new(username.clone(),
+                                                   server_id,
+                                                   &shared_secret,
+                                                   &public_key)
+                .perform();
+        let uuid = match joined {
+            Ok(x) => x.uuid()?,
+            Err(e) => self.session_outage(e, &username, options.session_outage)?,
+        };
+        if let Some(threshold) = options.compression_threshold {
+            let _: usize = self.enable_compression(threshold)?;
+        }
+        let _: usize = self.send(clientbound::LoginSuccess::new(uuid.as_u128(), username.clone()))?;
+        self.set_clientstate(ClientState::Play);
+        Ok((uuid, username))
+    }
+
+    /* Get the UUID to log in with after SessionHasJoined failed */
+    fn session_outage(&self, e: Error, username: &str, policy: SessionOutage) -> Result<Uuid> {
+        if !mojang::is_unreachable(&e) {
+            return Err(e);
+        }
+        match (policy, self.fallback_verifier.as_ref()) {
+            (SessionOutage::AllowOffline, _) => Uuid::offline(username),
+            (SessionOutage::Fallback, Some(verifier)) => verifier(username, self.peer),
+            _ => Err(e),
+        }
+    }
+
+    /// Set the verifier used with SessionOutage::Fallback
+    pub fn set_fallback_verifier(&mut self, verifier: Option<FallbackVerifier>) {
+        self.fallback_verifier = verifier;
+    }
+
+    /// Set the zlib compression level used for outgoing packets once
+    /// compression is enabled, from 0 (fastest) to 9 (smallest). Defaults
+    /// to 6.
     ///
-    /// It is generally an error to enable compression if compression has
-    /// already been enabled.
-    pub fn enable_compression(&mut self, threshold: usize) {
-        self.conn.enable_compression(threshold)
+    /// Lower levels trade bandwidth for CPU time, which is usually worth it
+    /// when sending a lot of chunk data.
+    pub fn set_compression_level(&mut self, level: u32) -> Result<()> {
+        self.conn.set_compression_level(level)
     }
 
     /// Read from the TcpStream and update the incoming buffer.
@@ -92,6 +628,24 @@ impl Server {
         self.conn.update_inbuf()
     }
 
+    /// Read a single packet, reading from the TcpStream only if the internal
+    /// buffer doesn't contain a complete one.
+    ///
+    /// This is meant for poll loops (e.g. mio) with nonblocking sockets: call
+    /// it whenever the socket is readable until it returns NeedMore. Partial
+    /// packets are kept in the internal buffer until the rest arrives. An
+    /// error is returned once the client has closed the connection.
+    pub fn try_read_packet(&mut self) -> Result<ReadStatus<ServerboundPacket>> {
+        if let Some(packet) = self.read_packet()? {
+            return Ok(ReadStatus::Ready(packet));
+        }
+        self.update_inbuf()?;
+        Ok(match self.read_packet()? {
+               Some(packet) => ReadStatus::Ready(packet),
+               None => ReadStatus::NeedMore,
+           })
+    }
+
     /// Read a single packet from the internal buffer.
     ///
     /// This is only really useful if you want finegrained control over the
@@ -102,7 +656,79 @@ impl Server {
     /// You MUST be sure that server.update_inbuf() has been called before this,
     /// this function will not attempt to read from the TcpStream, only from the
     /// internal buffer.
+    ///
+    /// TeleportConfirm packets matching the teleport sent by server.teleport()
+    /// are tracked here, confirmations with any other ID are ignored.
+    ///
+    /// ChatMessage packets are validated according to the chat policy
+    /// before being returned. Rejected messages are skipped, and if the
+    /// policy is to kick the client, it is disconnected and an error is
+    /// returned.
     pub fn read_packet(&mut self) -> Result<Option<ServerboundPacket>> {
-        self.conn.read_packet()
+        let mut packet = self.conn.read_packet()?;
+        while let Some(ServerboundPacket::ChatMessage(ref p)) = packet {
+            let reason = match self.chat_policy.kick_reason(p.get_message()) {
+                Some(x) => x,
+                None => break,
+            };
+            match self.chat_policy.apply(p.get_message()) {
+                Some(message) => {
+                    packet = Some(serverbound::ChatMessage::new(message));
+                    break;
+                },
+                None if self.chat_policy.on_violation == ChatViolation::Kick => {
+                    let _: usize = self.send(clientbound::PlayDisconnect::new(reason.to_string()))?;
+                    let _: usize = self.write()?;
+                    self.close()?;
+                    bail!("Kicked the client for an invalid chat message");
+                },
+                None => packet = self.conn.read_packet()?,
+            }
+        }
+        match packet {
+            Some(ServerboundPacket::Handshake(ref p)) => {
+                self.virtual_host = Some(VirtualHost::parse(p.get_server_address(),
+                                                            *p.get_server_port()));
+            },
+            Some(ServerboundPacket::TeleportConfirm(ref p))
+                if self.awaiting_teleport == Some(*p.get_id()) => {
+                self.awaiting_teleport = None;
+            },
+            Some(ServerboundPacket::LoginStart(ref p)) => {
+                self.username = Some(p.get_name().clone());
+                self.audit(AuditEvent::LoginAttempt { username: p.get_name().clone() });
+            },
+            Some(ServerboundPacket::ClientSettings(ref p)) => {
+                self.locale = Some(p.get_locale().as_str().to_lowercase());
+                self.chat_mode = *p.get_chat_mode();
+            },
+            Some(ServerboundPacket::ChatMessage(ref p)) if self.audit.is_some() => {
+                let message = p.get_message().clone();
+                if message.starts_with('/') {
+                    self.audit(AuditEvent::Command { command: message });
+                } else {
+                    self.audit(AuditEvent::Chat { message });
+                }
+            },
+            Some(ref p) if self.awaiting_teleport.is_none() => {
+                if let Some(ref mut movement) = self.movement {
+                    self.violations.extend(movement.check(p));
+                }
+                if let Some(ref mut culler) = self.culler {
+                    culler.observe(p);
+                }
+            },
+            _ => (),
+        }
+        Ok(packet)
+    }
+}
+
+#[cfg(unix)]
+impl Server {
+    /// Get the file descriptor of the connection to poll for readiness, or
+    /// None if its transport has none, see the transport module
+    pub fn raw_fd(&self) -> Option<RawFd> {
+        self.conn.raw_fd()
     }
 }
@@ -4,7 +4,7 @@
 //! about each of the packets.
 
 use connection::Packet;
-use errors::Result;
+use errors::{ErrorKind, Result};
 use read::*;
 use write::*;
 use {ClientState, utils};
@@ -19,6 +19,10 @@ use openssl::pkey::Private;
 include!("./.serverbound-enum.generated.rs");
 include!("./.serverbound-packets.generated.rs");
 
+/* Old names of renamed packets, see the compat module */
+#[deprecated(since = "0.8.0", note = "renamed to RecipeBookData in Minecraft 1.13")]
+pub type CraftingBookData = RecipeBookData;
+
 /* Now come to the manual definitions of packets that don't fit into the
  * code generation */
 
@@ -34,20 +38,33 @@ impl Handshake {
 }
 
 impl EncryptionResponse {
+    /// Decrypt the shared secret with the server's key
+    ///
+    /// Returns an ErrorKind::BadSharedSecret error if it's not 16 bytes long.
     pub fn get_decrypted_shared_secret(&self, key: &Rsa<Private>) -> Result<[u8; 16]> {
         let tmp = utils::rsa_decrypt(key, &self.shared_secret)?;
         if tmp.len() != 16 {
-            bail!("Decrypted shared secret was not 16 bytes long");
+            bail!(ErrorKind::BadSharedSecret(tmp.len()));
         }
         let mut ret = [0; 16];
-        for i in 0..16 {
-            ret[i] = tmp[i];
-        }
+        ret.copy_from_slice(&tmp);
         Ok(ret)
     }
     pub fn get_decrypted_verify_token(&self, key: &Rsa<Private>) -> Result<Vec<u8>> {
         utils::rsa_decrypt(key, &self.verify_token)
     }
+    /// Check that the verify token matches the one sent in the
+    /// EncryptionRequest, and decrypt the shared secret
+    ///
+    /// Returns an ErrorKind::VerifyTokenMismatch error if the verify token
+    /// doesn't match, and an ErrorKind::BadSharedSecret error if the shared
+    /// secret isn't 16 bytes long.
+    pub fn verify(&self, key: &Rsa<Private>, verify_token: &[u8]) -> Result<[u8; 16]> {
+        if self.get_decrypted_verify_token(key)? != verify_token {
+            bail!(ErrorKind::VerifyTokenMismatch);
+        }
+        self.get_decrypted_shared_secret(key)
+    }
     /// Create the EncryptionResponse packet from the unencrypted shared secret
     /// and verify token, and the server's public key in DER format.
     pub fn new_unencrypted(key: &[u8],
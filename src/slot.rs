@@ -0,0 +1,188 @@
+//! Parsing the item stacks of inventory slots
+//!
+//! Packets like SetSlot and ClickWindow carry the slot data in raw form,
+//! parse it with Slot::parse(). Items are identified by their numeric ID,
+//! and any enchantments, name, damage etc. are in the NBT.
+//!
+//! # Examples
+//!
+//! ```rust,no_run
+//! use ozelot::clientbound::ClientboundPacket;
+//! use ozelot::slot::Slot;
+//! # fn handle(packet: ClientboundPacket) {
+//! if let ClientboundPacket::SetSlot(ref p) = packet {
+//!     if let Ok(Some(slot)) = Slot::parse(p.get_slot_data()) {
+//!         for enchantment in slot.enchantments(None) {
+//!             println!("{:?} {}", enchantment.name, enchantment.level);
+//!         }
+//!     }
+//! }
+//! # }
+//! ```
+
+use errors::Result;
+use nbt::{self, Tag};
+use protocol_data::ProtocolData;
+use read::{read_bool, read_i8, read_varint};
+use write::{write_bool, write_i8, write_varint};
+
+use std::io::{Cursor, Read, Write};
+
+/// The registry that numeric enchantment IDs are looked up in
+pub const ENCHANTMENT_REGISTRY: &str = "enchantments";
+
+/// A stack of items in a slot
+#[derive(Debug, Clone, PartialEq)]
+pub struct Slot {
+    pub item_id: i32,
+    pub count: i8,
+    /// The NBT of the item, if any
+    pub nbt: Option<Tag>,
+}
+
+/// An enchantment of an item
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Enchantment {
+    /// The name, e.g. "minecraft:sharpness", or None if the item has a
+    /// numeric ID that isn't in the registry
+    pub name: Option<String>,
+    /// The numeric ID, if the item has one or the registry has the name
+    pub id: Option<i32>,
+    pub level: i32,
+}
+
+impl Slot {
+    /// Read a slot, or None if it's empty
+    pub fn read<R: Read>(r: &mut R) -> Result<Option<Slot>> {
+        if !read_bool(r)? {
+            return Ok(None);
+        }
+        let item_id = read_varint(r)?;
+        let count = read_i8(r)?;
+        let nbt = nbt::read_nbt(r)?.map(|(_, tag)| tag);
+        Ok(Some(Slot {
+                    item_id,
+                    count,
+                    nbt,
+                }))
+    }
+
+    /// Parse the raw slot data from a packet, or None if the slot is empty
+    pub fn parse(data: &[u8]) -> Result<Option<Slot>> {
+        Slot::read(&mut Cursor::new(data))
+    }
+
+    /// Write a slot, or an empty slot if None
+    pub fn write<W: Write>(slot: Option<&Slot>, w: &mut W) -> Result<()> {
+        match slot {
+            None => write_bool(&false, w),
+            Some(slot) => {
+                write_bool(&true, w)?;
+                write_varint(&slot.item_id, w)?;
+                write_i8(&slot.count, w)?;
+                nbt::write_nbt(slot.nbt.as_ref().map(|x| ("", x)), w)
+            },
+        }
+    }
+
+    /// Serialize the slot to the raw format used in packets
+    pub fn to_u8(&self) -> Result<Vec<u8>> {
+        let mut ret = Vec::new();
+        Slot::write(Some(self), &mut ret)?;
+        Ok(ret)
+    }
+
+    /// Get the enchantments of the item, including those stored in
+    /// enchanted books.
+    ///
+    /// Enchantments are named in the NBT since 1.13, and numbered before
+    /// that. Give the ProtocolData of the connection to translate between
+    /// the two using its "enchantments" registry. Malformed entries are
+    /// skipped.
+    pub fn enchantments(&self, data: Option<&ProtocolData>) -> Vec<Enchantment> {
+        let nbt = match self.nbt {
+            Some(ref x) => x,
+            None => return Vec::new(),
+        };
+        ["Enchantments", "StoredEnchantments", "ench"]
+            .iter()
+            .filter_map(|x| nbt.get(x).and_then(|x| x.as_list()))
+            .flat_map(|x| x.iter())
+            .filter_map(|x| enchantment(x, data))
+            .collect()
+    }
+
+    /// Get the level of the named enchantment, or 0 if the item doesn't
+    /// have it
+    pub fn enchantment_level(&self, name: &str, data: Option<&ProtocolData>) -> i32 {
+        self.enchantments(data)
+            .into_iter()
+            .find(|x| x.name.as_deref() == Some(name))
+            .map(|x| x.level)
+            .unwrap_or(0)
+    }
+}
+
+fn enchantment(tag: &Tag, data: Option<&ProtocolData>) -> Option<Enchantment> {
+    let level = tag.get("lvl").and_then(|x| x.as_i64())? as i32;
+    let (name, id) = match *tag.get("id")? {
+        Tag::String(ref name) => {
+            let id = data.and_then(|x| x.registry_id(ENCHANTMENT_REGISTRY, name));
+            (Some(name.clone()), id)
+        },
+        ref x => {
+            let id = x.as_i64()? as i32;
+            let name = data.and_then(|x| x.registry_name(ENCHANTMENT_REGISTRY, id));
+            (name.map(|x| x.to_string()), Some(id))
+        },
+    };
+    Some(Enchantment { name, id, level })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::collections::BTreeMap;
+
+    fn enchantment_tag(id: Tag, level: i16) -> Tag {
+        let mut x = BTreeMap::new();
+        let _ = x.insert("id".to_string(), id);
+        let _ = x.insert("lvl".to_string(), Tag::Short(level));
+        Tag::Compound(x)
+    }
+
+    #[test]
+    fn enchantments() {
+        let mut nbt = BTreeMap::new();
+        let _ = nbt.insert("Enchantments".to_string(),
+                           Tag::List(vec![enchantment_tag(Tag::String("minecraft:sharpness".to_string()), 5),
+                                          enchantment_tag(Tag::Short(34), 3)]));
+        let slot = Slot {
+            item_id: 792,
+            count: 1,
+            nbt: Some(Tag::Compound(nbt)),
+        };
+        let parsed = Slot::parse(&slot.to_u8().unwrap()).unwrap().unwrap();
+        assert_eq!(parsed, slot);
+        assert_eq!(Slot::parse(&[0]).unwrap(), None);
+
+        let data = ProtocolData::parse(r#"{"version_name": "1.12.2", "protocol": 340,
+            "registries": {"enchantments": {"minecraft:unbreaking": 34,
+                                            "minecraft:sharpness": 16}}}"#)
+                .unwrap();
+        assert_eq!(slot.enchantments(Some(&data)),
+                   vec![Enchantment {
+                            name: Some("minecraft:sharpness".to_string()),
+                            id: Some(16),
+                            level: 5,
+                        },
+                        Enchantment {
+                            name: Some("minecraft:unbreaking".to_string()),
+                            id: Some(34),
+                            level: 3,
+                        }]);
+        assert_eq!(slot.enchantments(None)[1].name, None);
+        assert_eq!(slot.enchantment_level("minecraft:sharpness", None), 5);
+        assert_eq!(slot.enchantment_level("minecraft:mending", None), 0);
+    }
+}
@@ -0,0 +1,139 @@
+//! The game mode of the player and spectating other entities
+//!
+//! In spectator mode, a player can look through the eyes of another entity
+//! by clicking it, or teleport to a player with a Spectate packet. The
+//! server tells the client whose view to show with a Camera packet, and the
+//! player leaves the view by sneaking. The Client keeps track of the game
+//! mode and camera when auto_handle is true, see client.game_mode(),
+//! client.camera() and client.spectate().
+
+use clientbound::ClientboundPacket;
+
+/// A game mode
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum GameMode {
+    Survival,
+    Creative,
+    Adventure,
+    Spectator,
+}
+impl GameMode {
+    /// Get the game mode with the given protocol ID, ignoring the hardcore
+    /// flag
+    pub fn from_id(id: u8) -> Option<Self> {
+        match id & 0x7 {
+            0 => Some(GameMode::Survival),
+            1 => Some(GameMode::Creative),
+            2 => Some(GameMode::Adventure),
+            3 => Some(GameMode::Spectator),
+            _ => None,
+        }
+    }
+
+    /// Get the protocol ID of this game mode
+    pub fn to_id(&self) -> u8 {
+        match *self {
+            GameMode::Survival => 0,
+            GameMode::Creative => 1,
+            GameMode::Adventure => 2,
+            GameMode::Spectator => 3,
+        }
+    }
+}
+
+/// A change of the game mode or camera, see ClientEvent::Spectator
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SpectatorChange {
+    GameModeChanged(GameMode),
+    /// The camera moved to the entity with the given ID, or back to the
+    /// player if None
+    CameraChanged(Option<i32>),
+}
+
+/// The game mode and camera of the player
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct SpectatorState {
+    game_mode: Option<GameMode>,
+    hardcore: bool,
+    camera: Option<i32>,
+}
+impl SpectatorState {
+    /// The game mode, or None before joining the game
+    pub fn game_mode(&self) -> Option<GameMode> {
+        self.game_mode
+    }
+
+    pub fn is_hardcore(&self) -> bool {
+        self.hardcore
+    }
+
+    /// The entity whose view is shown, or None if it's the player's own
+    pub fn camera(&self) -> Option<i32> {
+        self.camera
+    }
+
+    /* Handle a packet about the player with the given entity ID */
+    pub(crate) fn handle(&mut self,
+                         packet: &ClientboundPacket,
+                         player: i32)
+                         -> Vec<SpectatorChange> {
+        let mut ret = Vec::new();
+        let game_mode = match *packet {
+            ClientboundPacket::JoinGame(ref p) => {
+                self.hardcore = p.get_gamemode() & 0x8 != 0;
+                GameMode::from_id(*p.get_gamemode())
+            },
+            ClientboundPacket::Respawn(ref p) => GameMode::from_id(*p.get_gamemode()),
+            ClientboundPacket::ChangeGameState(ref p) if *p.get_action() == 3 => {
+                GameMode::from_id(*p.get_value() as u8)
+            },
+            ClientboundPacket::Camera(ref p) => {
+                let camera = Some(*p.get_entity_id()).filter(|&x| x != player);
+                if camera != self.camera {
+                    self.camera = camera;
+                    ret.push(SpectatorChange::CameraChanged(camera));
+                }
+                None
+            },
+            _ => None,
+        };
+        if let Some(game_mode) = game_mode {
+            if Some(game_mode) != self.game_mode {
+                self.game_mode = Some(game_mode);
+                ret.push(SpectatorChange::GameModeChanged(game_mode));
+            }
+            /* Only spectators can look through other entities */
+            if game_mode != GameMode::Spectator && self.camera.take().is_some() {
+                ret.push(SpectatorChange::CameraChanged(None));
+            }
+        }
+        ret
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use clientbound::{Camera, ChangeGameState, JoinGame};
+    use read::SmallString;
+
+    #[test]
+    fn spectator() {
+        let mut state = SpectatorState::default();
+        let join = JoinGame::new(1, 0x8 | 3, 0, 0, 20, SmallString::from("default"), false);
+        assert_eq!(state.handle(&join, 1),
+                   vec![SpectatorChange::GameModeChanged(GameMode::Spectator)]);
+        assert!(state.is_hardcore());
+
+        assert_eq!(state.handle(&Camera::new(5), 1),
+                   vec![SpectatorChange::CameraChanged(Some(5))]);
+        assert_eq!(state.camera(), Some(5));
+        assert_eq!(state.handle(&Camera::new(5), 1), vec![]);
+
+        assert_eq!(state.handle(&ChangeGameState::new(3, 1.0), 1),
+                   vec![SpectatorChange::GameModeChanged(GameMode::Creative),
+                        SpectatorChange::CameraChanged(None)]);
+        assert_eq!(state.game_mode(), Some(GameMode::Creative));
+        assert_eq!(state.handle(&Camera::new(1), 1), vec![]);
+    }
+}
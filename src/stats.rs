@@ -0,0 +1,274 @@
+//! Per packet type statistics
+//!
+//! When enabled with client.enable_packet_stats() (or the Server
+//! equivalent), the connection counts the packets sent and received of each
+//! type, and keeps histograms of their sizes on the wire and of how long they
+//! took to serialize/deserialize. This helps find out which packets dominate
+//! the bandwidth.
+//!
+//! The statistics can be exported to any metrics system with
+//! PacketStats::export().
+
+use std::collections::BTreeMap;
+use std::time::Duration;
+
+/// Default histogram bucket bounds for packet sizes, in bytes
+pub const SIZE_BUCKETS: &[u64] = &[16, 64, 256, 1024, 4096, 16384, 65536, 262144];
+/// Default histogram bucket bounds for processing times, in microseconds
+pub const TIME_BUCKETS: &[u64] = &[1, 5, 10, 50, 100, 500, 1000, 5000];
+
+/// A histogram with fixed bucket bounds
+#[derive(Debug, Clone, PartialEq)]
+pub struct Histogram {
+    /* Inclusive upper bounds of the buckets, in increasing order. There's
+     * one more bucket for values above the last bound. */
+    bounds: Vec<u64>,
+    counts: Vec<u64>,
+    count: u64,
+    sum: u64,
+    min: u64,
+    max: u64,
+}
+impl Histogram {
+    /// Create a new histogram with the given bucket upper bounds, which must
+    /// be in increasing order
+    pub fn new(bounds: &[u64]) -> Self {
+        Histogram {
+            bounds: bounds.to_vec(),
+            counts: vec![0; bounds.len() + 1],
+            count: 0,
+            sum: 0,
+            min: u64::MAX,
+            max: 0,
+        }
+    }
+
+    /// Record a value
+    pub fn record(&mut self, value: u64) {
+        let bucket = self.bounds
+            .iter()
+            .position(|&x| value <= x)
+            .unwrap_or(self.bounds.len());
+        self.counts[bucket] += 1;
+        self.count += 1;
+        self.sum += value;
+        self.min = self.min.min(value);
+        self.max = self.max.max(value);
+    }
+
+    /// The amount of values recorded
+    pub fn count(&self) -> u64 {
+        self.count
+    }
+
+    /// The sum of all the values recorded
+    pub fn sum(&self) -> u64 {
+        self.sum
+    }
+
+    /// The smallest value recorded, if any
+    pub fn min(&self) -> Option<u64> {
+        if self.count == 0 { None } else { Some(self.min) }
+    }
+
+    /// The largest value recorded, if any
+    pub fn max(&self) -> Option<u64> {
+        if self.count == 0 { None } else { Some(self.max) }
+    }
+
+    /// The mean of the values recorded
+    pub fn mean(&self) -> f64 {
+        if self.count == 0 {
+            0.0
+        } else {
+            self.sum as f64 / self.count as f64
+        }
+    }
+
+    /// Get the buckets as (inclusive upper bound, count), where the last
+    /// bucket has no upper bound
+    pub fn buckets(&self) -> Vec<(Option<u64>, u64)> {
+        self.bounds
+            .iter()
+            .map(|&x| Some(x))
+            .chain(Some(None))
+            .zip(self.counts.iter().cloned())
+            .collect()
+    }
+
+    /// Estimate the given quantile (0 to 1) as the upper bound of the bucket
+    /// it falls in, or the max for the last bucket
+    pub fn quantile(&self, q: f64) -> Option<u64> {
+        if self.count == 0 {
+            return None;
+        }
+        let target = (q * self.count as f64).ceil().max(1.0) as u64;
+        let mut seen = 0;
+        for (bound, count) in self.buckets() {
+            seen += count;
+            if seen >= target {
+                return Some(bound.unwrap_or(self.max).min(self.max));
+            }
+        }
+        Some(self.max)
+    }
+}
+
+/// Statistics of a single packet type in a single direction
+#[derive(Debug, Clone, PartialEq)]
+pub struct PacketTypeStats {
+    /// The amount of packets
+    pub count: u64,
+    /// The total size on the wire, before encryption
+    pub bytes: u64,
+    /// Packet sizes in bytes
+    pub sizes: Histogram,
+    /// Serialization/deserialization time in microseconds
+    pub times: Histogram,
+}
+impl Default for PacketTypeStats {
+    fn default() -> Self {
+        PacketTypeStats {
+            count: 0,
+            bytes: 0,
+            sizes: Histogram::new(SIZE_BUCKETS),
+            times: Histogram::new(TIME_BUCKETS),
+        }
+    }
+}
+
+/// Whether a packet was sent or received
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Direction {
+    Inbound,
+    Outbound,
+}
+impl Direction {
+    fn as_str(&self) -> &'static str {
+        match *self {
+            Direction::Inbound => "inbound",
+            Direction::Outbound => "outbound",
+        }
+    }
+}
+
+/// Packet statistics of a connection, by packet name
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct PacketStats {
+    inbound: BTreeMap<String, PacketTypeStats>,
+    outbound: BTreeMap<String, PacketTypeStats>,
+}
+impl PacketStats {
+    pub fn new() -> Self {
+        PacketStats::default()
+    }
+
+    fn map(&self, direction: Direction) -> &BTreeMap<String, PacketTypeStats> {
+        match direction {
+            Direction::Inbound => &self.inbound,
+            Direction::Outbound => &self.outbound,
+        }
+    }
+
+    /// Record a packet of the given name and size, which took the given time
+    /// to process
+    pub fn record(&mut self, direction: Direction, name: &str, bytes: usize, time: Duration) {
+        let map = match direction {
+            Direction::Inbound => &mut self.inbound,
+            Direction::Outbound => &mut self.outbound,
+        };
+        if !map.contains_key(name) {
+            let _: Option<PacketTypeStats> = map.insert(name.to_string(),
+                                                        PacketTypeStats::default());
+        }
+        let stats = map.get_mut(name).expect("unreachable missing packet stats");
+        stats.count += 1;
+        stats.bytes += bytes as u64;
+        stats.sizes.record(bytes as u64);
+        stats.times.record(time.as_micros() as u64);
+    }
+
+    /// Get the statistics of the given packet type
+    pub fn get(&self, direction: Direction, name: &str) -> Option<&PacketTypeStats> {
+        self.map(direction).get(name)
+    }
+
+    /// Iterate over the statistics of all packet types seen, by name
+    pub fn iter(&self, direction: Direction) -> impl Iterator<Item = (&str, &PacketTypeStats)> {
+        self.map(direction).iter().map(|(k, v)| (k.as_str(), v))
+    }
+
+    /// Get the total amount of bytes
+    pub fn total_bytes(&self, direction: Direction) -> u64 {
+        self.map(direction).values().map(|x| x.bytes).sum()
+    }
+
+    /// Get the n packet types using the most bandwidth, with the amount of
+    /// bytes used, in decreasing order
+    pub fn top_by_bytes(&self, direction: Direction, n: usize) -> Vec<(&str, u64)> {
+        let mut ret: Vec<(&str, u64)> = self.iter(direction).map(|(k, v)| (k, v.bytes)).collect();
+        ret.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(b.0)));
+        ret.truncate(n);
+        ret
+    }
+
+    /// Clear all statistics
+    pub fn reset(&mut self) {
+        self.inbound.clear();
+        self.outbound.clear();
+    }
+
+    /// Export the statistics to a metrics system.
+    ///
+    /// The callback is called with a metric name, its labels ("direction"
+    /// and "packet") and its value, for each of the metrics
+    /// ozelot_packets_total, ozelot_packet_bytes_total,
+    /// ozelot_packet_bytes_mean and ozelot_packet_micros_mean.
+    pub fn export<F: FnMut(&str, &[(&str, &str)], f64)>(&self, mut f: F) {
+        for &direction in &[Direction::Inbound, Direction::Outbound] {
+            for (name, stats) in self.iter(direction) {
+                let labels = [("direction", direction.as_str()), ("packet", name)];
+                f("ozelot_packets_total", &labels, stats.count as f64);
+                f("ozelot_packet_bytes_total", &labels, stats.bytes as f64);
+                f("ozelot_packet_bytes_mean", &labels, stats.sizes.mean());
+                f("ozelot_packet_micros_mean", &labels, stats.times.mean());
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn histogram() {
+        let mut h = Histogram::new(&[10, 100]);
+        assert_eq!(h.quantile(0.5), None);
+        for x in &[1, 5, 10, 50, 1000] {
+            h.record(*x);
+        }
+        assert_eq!(h.buckets(), vec![(Some(10), 3), (Some(100), 1), (None, 1)]);
+        assert_eq!((h.min(), h.max(), h.sum()), (Some(1), Some(1000), 1066));
+        assert_eq!(h.quantile(0.5), Some(10));
+        assert_eq!(h.quantile(0.8), Some(100));
+        assert_eq!(h.quantile(1.0), Some(1000));
+    }
+
+    #[test]
+    fn packet_stats() {
+        let mut s = PacketStats::new();
+        let t = Duration::from_micros(3);
+        s.record(Direction::Inbound, "ChunkData", 5000, t);
+        s.record(Direction::Inbound, "KeepAlive", 10, t);
+        s.record(Direction::Inbound, "KeepAlive", 10, t);
+        s.record(Direction::Outbound, "KeepAlive", 10, t);
+        assert_eq!(s.get(Direction::Inbound, "KeepAlive").unwrap().count, 2);
+        assert_eq!(s.total_bytes(Direction::Inbound), 5020);
+        assert_eq!(s.top_by_bytes(Direction::Inbound, 1), vec![("ChunkData", 5000)]);
+
+        let mut exported = 0;
+        s.export(|_, _, _| exported += 1);
+        assert_eq!(exported, 3 * 4);
+    }
+}
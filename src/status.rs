@@ -0,0 +1,636 @@
+//! Pinging servers for their status, as shown in the server list
+//!
+//! # Examples
+//!
+//! ```rust,no_run
+//! use ozelot::status;
+//! use std::time::Duration;
+//!
+//! let status = status::ping("minecraft.example.com", 25565,
+//!                           Duration::from_secs(5)).unwrap();
+//! println!("{} ({}/{} players)", status.version_name, status.players_online,
+//!          status.players_max);
+//! ```
+//!
+//! Many third-party servers send slightly malformed status json, e.g. player
+//! counts as strings. parse_status() accepts the common deviations, while
+//! parse_status_with() and ParseMode::Strict reject them, e.g. to validate
+//! a server's own status. Fields ozelot doesn't know are kept in
+//! ServerStatus::extra, and sent again by ServerStatus::to_json().
+//!
+//! For dashboards, a Monitor pings a set of servers periodically, keeps
+//! their last status and a history of their uptime and latency, and reports
+//! when they go offline or their player count crosses a threshold.
+//!
+//! ```rust,no_run
+//! use ozelot::status::{Monitor, MonitorOptions};
+//! use std::thread;
+//!
+//! let mut monitor = Monitor::new(MonitorOptions {
+//!     player_thresholds: vec![50],
+//!     ..MonitorOptions::default()
+//! });
+//! monitor.add("minecraft.example.com", 25565);
+//! loop {
+//!     for event in monitor.poll() {
+//!         println!("{:?}", event);
+//!     }
+//!     thread::sleep(monitor.time_until_next_poll());
+//! }
+//! ```
+//!
+//! The other way around, servers need the favicon they show as a data URI
+//! of a 64x64 PNG. favicon_from_png() creates it from such a PNG, and with
+//! the image feature favicon_from_image() and favicon_from_file() first
+//! convert an arbitrary image.
+
+use {Client, ClientState, PROTOCOL_VERSION, serverbound, utils};
+use clientbound::ClientboundPacket;
+use errors::{Result, ResultExt};
+
+use openssl::base64;
+use serde_json::{self, Map, Value};
+
+#[cfg(feature = "image")]
+use image::{self, DynamicImage, ImageFormat};
+#[cfg(feature = "image")]
+use image::imageops::FilterType;
+
+use std::collections::VecDeque;
+use std::thread;
+use std::time::{Duration, Instant, SystemTime};
+
+#[cfg(feature = "image")]
+use std::io::Cursor;
+#[cfg(feature = "image")]
+use std::path::Path;
+
+/// The width and height of a server icon
+pub const FAVICON_SIZE: u32 = 64;
+const PNG_SIGNATURE: [u8; 8] = [0x89, b'P', b'N', b'G', b'\r', b'\n', 0x1a, b'\n'];
+
+/// The status of a server, from its StatusResponse
+#[derive(Debug, Clone, PartialEq)]
+pub struct ServerStatus {
+    /// The version name, e.g. "1.13.2"
+    pub version_name: String,
+    /// The protocol version the server uses
+    pub protocol: i32,
+    pub players_online: i32,
+    pub players_max: i32,
+    /// A sample of the players online, as (name, UUID)
+    pub sample: Vec<(String, String)>,
+    /// The MOTD as raw chat json
+    pub description: String,
+    /// The server icon as a data URI of a PNG, if any
+    pub favicon: Option<String>,
+    /// The time between sending the StatusPing and receiving the StatusPong,
+    /// or 0 if the status was only parsed
+    pub latency: Duration,
+    /// The top-level fields not parsed into the other fields, e.g.
+    /// modinfo sent by Forge servers
+    pub extra: Map<String, Value>,
+}
+impl ServerStatus {
+    /// Serialize the status into the json of a StatusResponse, including the
+    /// extra fields
+    pub fn to_json(&self) -> String {
+        let mut players = json!({
+            "max": self.players_max,
+            "online": self.players_online,
+        });
+        if !self.sample.is_empty() {
+            let sample: Vec<Value> = self.sample
+                .iter()
+                .map(|x| json!({ "name": x.0, "id": x.1 }))
+                .collect();
+            players["sample"] = Value::Array(sample);
+        }
+        let mut ret = self.extra.clone();
+        let _ = ret.insert("version".to_string(),
+                           json!({ "name": self.version_name, "protocol": self.protocol }));
+        let _ = ret.insert("players".to_string(), players);
+        let description = serde_json::from_str(&self.description)
+            .unwrap_or_else(|_| json!({ "text": self.description }));
+        let _ = ret.insert("description".to_string(), description);
+        if let Some(ref x) = self.favicon {
+            let _ = ret.insert("favicon".to_string(), Value::from(x.clone()));
+        }
+        Value::Object(ret).to_string()
+    }
+}
+
+/// How strictly to parse status json, see the module documentation
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ParseMode {
+    /// Reject anything vanilla wouldn't send, e.g. to validate a status
+    Strict,
+    /// Accept and coerce the common deviations: numbers as strings or
+    /// floats, missing players or sample, and invalid sample entries
+    Lenient,
+}
+
+/* The fields parsed into ServerStatus, everything else goes in extra */
+const KNOWN_FIELDS: [&str; 4] = ["version", "players", "description", "favicon"];
+
+/// Ping the server at the given host and port, waiting at most timeout for
+/// the whole exchange
+pub fn ping(host: &str, port: u16, timeout: Duration) -> Result<ServerStatus> {
+    ping_as(host, port, host, port, timeout)
+}
+
+/// Like ping(), but declaring the given host and port in the Handshake
+/// instead of the ones connected to, e.g. to test virtual host routing
+pub fn ping_as(host: &str,
+               port: u16,
+               handshake_host: &str,
+               handshake_port: u16,
+               timeout: Duration)
+               -> Result<ServerStatus> {
+    let start = Instant::now();
+    let mut client = Client::connect_tcp(host, port)?;
+    let handshake = serverbound::Handshake::new(PROTOCOL_VERSION,
+                                                handshake_host.to_string(),
+                                                handshake_port,
+                                                1);
+    let _: usize = client.send(handshake)?;
+    client.set_clientstate(ClientState::Status);
+    let _: usize = client.send(serverbound::StatusRequest::new_raw())?;
+
+    let mut status = None;
+    let mut ping_sent = start;
+    loop {
+        if start.elapsed() > timeout {
+            bail!("Timed out waiting for the status of {}:{}", host, port);
+        }
+        client.update_inbuf()?;
+        match client.read_packet()? {
+            Some(ClientboundPacket::StatusResponse(ref p)) => {
+                status = Some(parse_status(p.get_json())?);
+                ping_sent = Instant::now();
+                let _: usize = client.send(serverbound::StatusPing::new(0))?;
+            },
+            Some(ClientboundPacket::StatusPong(..)) => {
+                if let Some(mut status) = status {
+                    status.latency = ping_sent.elapsed();
+                    let _ = client.close();
+                    return Ok(status);
+                }
+            },
+            Some(_) => (),
+            None => thread::sleep(Duration::from_millis(10)),
+        }
+    }
+}
+
+/// Parse the json of a StatusResponse leniently, see ParseMode. The latency
+/// is set to 0.
+pub fn parse_status(json: &str) -> Result<ServerStatus> {
+    parse_status_with(json, ParseMode::Lenient)
+}
+
+/// Parse the json of a StatusResponse with the given mode. The latency is
+/// set to 0.
+pub fn parse_status_with(json: &str, mode: ParseMode) -> Result<ServerStatus> {
+    let strict = mode == ParseMode::Strict;
+    let value: Value = serde_json::from_str(json)
+        .chain_err(|| "Invalid json in the status response")?;
+    let object = match value.as_object() {
+        Some(x) => x,
+        None => bail!("The status response is not a json object"),
+    };
+    let int = |value: &Value, name: &str| -> Result<i32> {
+        let ret = match *value {
+            Value::Number(ref x) if x.is_i64() => x.as_i64(),
+            Value::Number(ref x) if !strict => x.as_f64().map(|x| x as i64),
+            Value::String(ref x) if !strict => x.trim().parse().ok(),
+            Value::Null if !strict && name != "version.protocol" => Some(0),
+            _ => None,
+        };
+        match ret {
+            Some(x) => Ok(x as i32),
+            None if value.is_null() => bail!("The status response has no {}", name),
+            None => bail!("The {} of the status response is not an integer: {}", name, value),
+        }
+    };
+
+    let players = &value["players"];
+    if strict && !players.is_object() {
+        bail!("The status response has no players");
+    }
+    let mut sample = Vec::new();
+    match players["sample"] {
+        Value::Array(ref x) => {
+            for p in x {
+                match (p["name"].as_str(), p["id"].as_str()) {
+                    (Some(name), Some(id)) => sample.push((name.to_string(), id.to_string())),
+                    _ if strict => bail!("Invalid player in the status sample: {}", p),
+                    _ => (),
+                }
+            }
+        },
+        Value::Null => (),
+        ref x if strict => bail!("The status sample is not an array: {}", x),
+        _ => (),
+    }
+
+    let description = match value["description"] {
+        Value::String(ref x) => json!({ "text": x }).to_string(),
+        Value::Null if strict => bail!("The status response has no description"),
+        ref x => x.to_string(),
+    };
+    let version_name = match value["version"]["name"] {
+        Value::String(ref x) => x.clone(),
+        ref x if strict => bail!("The status response has no version name: {}", x),
+        Value::Null => String::new(),
+        ref x => x.to_string(),
+    };
+    let favicon = match value["favicon"] {
+        Value::String(ref x) => Some(x.clone()),
+        Value::Null => None,
+        ref x if strict => bail!("The status favicon is not a string: {}", x),
+        _ => None,
+    };
+
+    Ok(ServerStatus {
+        version_name,
+        protocol: int(&value["version"]["protocol"], "version.protocol")?,
+        players_online: int(&players["online"], "players.online")?,
+        players_max: int(&players["max"], "players.max")?,
+        sample,
+        description,
+        favicon,
+        latency: Duration::from_secs(0),
+        extra: object.iter()
+            .filter(|x| !KNOWN_FIELDS.contains(&x.0.as_str()))
+            .map(|(k, v)| (k.clone(), v.clone()))
+            .collect(),
+    })
+}
+
+/// Create the favicon data URI from a 64x64 PNG
+///
+/// Returns an error if it's not a PNG, or not 64x64, since the client
+/// doesn't show other sizes.
+pub fn favicon_from_png(png: &[u8]) -> Result<String> {
+    /* The IHDR chunk always comes first, with the width and height */
+    if png.len() < 24 || png[..8] != PNG_SIGNATURE || &png[12..16] != b"IHDR" {
+        bail!("The favicon is not a PNG");
+    }
+    let width = u32::from_be_bytes([png[16], png[17], png[18], png[19]]);
+    let height = u32::from_be_bytes([png[20], png[21], png[22], png[23]]);
+    if (width, height) != (FAVICON_SIZE, FAVICON_SIZE) {
+        bail!("The favicon must be {}x{}, not {}x{}",
+              FAVICON_SIZE,
+              FAVICON_SIZE,
+              width,
+              height);
+    }
+    Ok(format!("data:image/png;base64,{}", base64::encode_block(png)))
+}
+
+/// Create the favicon data URI from an image of any size. Images that
+/// aren't square are cropped around the center.
+#[cfg(feature = "image")]
+pub fn favicon_from_image(image: &DynamicImage) -> Result<String> {
+    let icon = image.resize_to_fill(FAVICON_SIZE, FAVICON_SIZE, FilterType::Lanczos3);
+    let mut png = Cursor::new(Vec::new());
+    icon.write_to(&mut png, ImageFormat::Png)
+        .chain_err(|| "Unable to encode the favicon")?;
+    favicon_from_png(png.get_ref())
+}
+
+/// Create the favicon data URI from an image file of any size and format
+/// supported by the image crate, see favicon_from_image()
+#[cfg(feature = "image")]
+pub fn favicon_from_file<P: AsRef<Path>>(path: P) -> Result<String> {
+    let image = image::open(path.as_ref())
+        .chain_err(|| format!("Unable to open {}", path.as_ref().display()))?;
+    favicon_from_image(&image)
+}
+
+/// The options of a Monitor
+#[derive(Debug, Clone, PartialEq)]
+pub struct MonitorOptions {
+    /// How often each server is pinged
+    pub interval: Duration,
+    /// At most how much later than the interval a ping is sent, chosen
+    /// randomly, so that the servers aren't all pinged at once
+    pub jitter: Duration,
+    /// How long to wait for each ping
+    pub timeout: Duration,
+    /// How many samples of the history to keep per server
+    pub history: usize,
+    /// The player counts to send an event for when crossed
+    pub player_thresholds: Vec<i32>,
+}
+impl Default for MonitorOptions {
+    fn default() -> Self {
+        MonitorOptions {
+            interval: Duration::from_secs(60),
+            jitter: Duration::from_secs(5),
+            timeout: Duration::from_secs(10),
+            history: 1440,
+            player_thresholds: Vec::new(),
+        }
+    }
+}
+
+/// A change in the status of a server watched by a Monitor, the address
+/// being host:port
+#[derive(Debug, Clone, PartialEq)]
+pub enum MonitorEvent {
+    /// The server answered a ping, after not answering the last one or on
+    /// the first ping
+    WentOnline { address: String, status: ServerStatus },
+    /// The server didn't answer a ping, after answering the last one or on
+    /// the first ping
+    WentOffline { address: String, error: String },
+    /// The number of players online reached the threshold
+    PlayersAbove { address: String, threshold: i32, online: i32 },
+    /// The number of players online dropped below the threshold
+    PlayersBelow { address: String, threshold: i32, online: i32 },
+}
+
+/// The result of a single ping by a Monitor
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Sample {
+    pub time: SystemTime,
+    /// The latency, or None if the server didn't answer
+    pub latency: Option<Duration>,
+    /// The number of players online, or None if the server didn't answer
+    pub players_online: Option<i32>,
+}
+
+/* A server watched by a Monitor */
+#[derive(Debug)]
+struct Monitored {
+    host: String,
+    port: u16,
+    next_poll: Instant,
+    /* The last status, or the error of the last ping if it failed */
+    last: Option<::std::result::Result<ServerStatus, String>>,
+    history: VecDeque<Sample>,
+}
+impl Monitored {
+    fn address(&self) -> String {
+        format!("{}:{}", self.host, self.port)
+    }
+}
+
+/// Pings servers periodically, see the module documentation
+#[derive(Debug)]
+pub struct Monitor {
+    options: MonitorOptions,
+    servers: Vec<Monitored>,
+}
+impl Monitor {
+    pub fn new(options: MonitorOptions) -> Self {
+        Monitor {
+            options,
+            servers: Vec::new(),
+        }
+    }
+
+    /// Start watching a server, which is pinged on the next poll()
+    pub fn add(&mut self, host: &str, port: u16) {
+        if self.find(host, port).is_none() {
+            self.servers.push(Monitored {
+                                  host: host.to_string(),
+                                  port,
+                                  next_poll: Instant::now(),
+                                  last: None,
+                                  history: VecDeque::new(),
+                              });
+        }
+    }
+
+    /// Stop watching a server
+    pub fn remove(&mut self, host: &str, port: u16) {
+        self.servers.retain(|x| x.host != host || x.port != port);
+    }
+
+    fn find(&self, host: &str, port: u16) -> Option<&Monitored> {
+        self.servers.iter().find(|x| x.host == host && x.port == port)
+    }
+
+    /// Ping the servers that are due, returning the changes seen. Blocks
+    /// while pinging.
+    pub fn poll(&mut self) -> Vec<MonitorEvent> {
+        let mut ret = Vec::new();
+        for i in 0..self.servers.len() {
+            if self.servers[i].next_poll > Instant::now() {
+                continue;
+            }
+            let res = ping(&self.servers[i].host, self.servers[i].port, self.options.timeout);
+            ret.extend(self.record(i, res.map_err(|e| e.to_string())));
+        }
+        ret
+    }
+
+    /* Record the result of pinging the server with the given index, and
+     * schedule the next ping */
+    fn record(&mut self,
+              index: usize,
+              res: ::std::result::Result<ServerStatus, String>)
+              -> Vec<MonitorEvent> {
+        let options = &self.options;
+        let server = &mut self.servers[index];
+        let address = server.address();
+        server.next_poll = Instant::now() + options.interval + utils::jitter(options.jitter);
+
+        server.history.push_back(Sample {
+                                     time: SystemTime::now(),
+                                     latency: res.as_ref().ok().map(|x| x.latency),
+                                     players_online: res.as_ref().ok().map(|x| x.players_online),
+                                 });
+        while server.history.len() > options.history {
+            let _ = server.history.pop_front();
+        }
+
+        let mut ret = Vec::new();
+        match (server.last.as_ref(), res.as_ref()) {
+            (Some(Ok(old)), Ok(new)) => {
+                for &threshold in &options.player_thresholds {
+                    if old.players_online < threshold && new.players_online >= threshold {
+                        ret.push(MonitorEvent::PlayersAbove {
+                                     address: address.clone(),
+                                     threshold,
+                                     online: new.players_online,
+                                 });
+                    } else if old.players_online >= threshold && new.players_online < threshold {
+                        ret.push(MonitorEvent::PlayersBelow {
+                                     address: address.clone(),
+                                     threshold,
+                                     online: new.players_online,
+                                 });
+                    }
+                }
+            },
+            (Some(Err(_)), Err(_)) => (),
+            (_, Ok(new)) => {
+                ret.push(MonitorEvent::WentOnline {
+                             address,
+                             status: new.clone(),
+                         })
+            },
+            (_, Err(e)) => {
+                ret.push(MonitorEvent::WentOffline {
+                             address,
+                             error: e.clone(),
+                         })
+            },
+        }
+        server.last = Some(res);
+        ret
+    }
+
+    /// Get how long until the next server is due to be pinged
+    pub fn time_until_next_poll(&self) -> Duration {
+        let now = Instant::now();
+        self.servers
+            .iter()
+            .map(|x| x.next_poll.saturating_duration_since(now))
+            .min()
+            .unwrap_or(self.options.interval)
+    }
+
+    /// Get the last status of a server, or None if it didn't answer the last
+    /// ping or hasn't been pinged yet
+    pub fn status(&self, host: &str, port: u16) -> Option<&ServerStatus> {
+        match self.find(host, port)?.last {
+            Some(Ok(ref x)) => Some(x),
+            _ => None,
+        }
+    }
+
+    /// Get the kept history of a server, oldest first
+    pub fn history(&self, host: &str, port: u16) -> Vec<Sample> {
+        self.find(host, port)
+            .map(|x| x.history.iter().cloned().collect())
+            .unwrap_or_default()
+    }
+
+    /// Get the share of the kept history the server was online, between 0
+    /// and 1, or None if it hasn't been pinged yet
+    pub fn uptime(&self, host: &str, port: u16) -> Option<f64> {
+        let history = &self.find(host, port)?.history;
+        if history.is_empty() {
+            return None;
+        }
+        let online = history.iter().filter(|x| x.latency.is_some()).count();
+        Some(online as f64 / history.len() as f64)
+    }
+
+    /// Get the average latency over the kept history, of the pings the
+    /// server answered
+    pub fn average_latency(&self, host: &str, port: u16) -> Option<Duration> {
+        let latencies: Vec<Duration> = self.find(host, port)?
+            .history
+            .iter()
+            .filter_map(|x| x.latency)
+            .collect();
+        if latencies.is_empty() {
+            return None;
+        }
+        Some(latencies.iter().sum::<Duration>() / latencies.len() as u32)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn status() {
+        let status = parse_status(r#"{
+            "version": {"name": "1.13.2", "protocol": 404},
+            "players": {"max": 100, "online": 1,
+                        "sample": [{"name": "Notch", "id": "069a79f4-44e9-4726-a5be-fca90e38aaf5"}]},
+            "description": "A Minecraft Server"
+        }"#).unwrap();
+        assert_eq!(status.protocol, 404);
+        assert_eq!((status.players_online, status.players_max), (1, 100));
+        assert_eq!(status.sample[0].0, "Notch");
+        assert_eq!(status.description, r#"{"text":"A Minecraft Server"}"#);
+        assert_eq!(status.favicon, None);
+        assert!(parse_status("{}").is_err());
+        assert!(parse_status_with(&status.to_json(), ParseMode::Strict).is_ok());
+
+        let json = r#"{
+            "version": {"name": "Paper 1.13.2", "protocol": "404"},
+            "players": {"max": 100.0, "online": "7", "sample": [{"name": "Notch"}]},
+            "description": {"text": "Hi"},
+            "modinfo": {"type": "FML", "modList": []}
+        }"#;
+        let status = parse_status(json).unwrap();
+        assert_eq!((status.protocol, status.players_online, status.players_max), (404, 7, 100));
+        assert!(status.sample.is_empty());
+        assert_eq!(status.extra["modinfo"]["type"], "FML");
+        assert!(parse_status_with(json, ParseMode::Strict).is_err());
+
+        let status = parse_status(&status.to_json()).unwrap();
+        assert_eq!(status.extra["modinfo"]["type"], "FML");
+        assert_eq!(status.description, r#"{"text":"Hi"}"#);
+    }
+
+    #[test]
+    fn favicon() {
+        /* Only the signature and IHDR matter */
+        let mut png = PNG_SIGNATURE.to_vec();
+        png.extend_from_slice(&[0, 0, 0, 13]);
+        png.extend_from_slice(b"IHDR");
+        png.extend_from_slice(&[0, 0, 0, 64, 0, 0, 0, 64, 8, 6, 0, 0, 0]);
+        assert_eq!(favicon_from_png(&png).unwrap(),
+                   format!("data:image/png;base64,{}", base64::encode_block(&png)));
+        png[19] = 32;
+        assert!(favicon_from_png(&png).is_err());
+        assert!(favicon_from_png(b"GIF89a").is_err());
+
+        #[cfg(feature = "image")]
+        {
+            let image = DynamicImage::new_rgba8(200, 100);
+            let uri = favicon_from_image(&image).unwrap();
+            let png = base64::decode_block(&uri["data:image/png;base64,".len()..]).unwrap();
+            assert_eq!(image::load_from_memory(&png).unwrap().width(), FAVICON_SIZE);
+        }
+    }
+
+    #[test]
+    fn monitor() {
+        let mut monitor = Monitor::new(MonitorOptions {
+                                           player_thresholds: vec![10],
+                                           ..MonitorOptions::default()
+                                       });
+        monitor.add("localhost", 25565);
+        let status = |online| {
+            let mut ret = parse_status(r#"{"version": {"name": "1.13.2", "protocol": 404}}"#).unwrap();
+            ret.players_online = online;
+            ret.latency = Duration::from_millis(20);
+            ret
+        };
+
+        match monitor.record(0, Ok(status(5)))[..] {
+            [MonitorEvent::WentOnline { ref address, .. }] => assert_eq!(address, "localhost:25565"),
+            ref x => panic!("{:?}", x),
+        }
+        assert!(monitor.time_until_next_poll() > Duration::from_secs(59));
+        assert_eq!(monitor.record(0, Ok(status(12))),
+                   vec![MonitorEvent::PlayersAbove {
+                            address: "localhost:25565".to_string(),
+                            threshold: 10,
+                            online: 12,
+                        }]);
+        assert!(monitor.record(0, Ok(status(11))).is_empty());
+        match monitor.record(0, Err("Connection refused".to_string()))[..] {
+            [MonitorEvent::WentOffline { .. }] => (),
+            ref x => panic!("{:?}", x),
+        }
+        assert!(monitor.record(0, Err("Connection refused".to_string())).is_empty());
+
+        assert_eq!(monitor.status("localhost", 25565), None);
+        assert_eq!(monitor.history("localhost", 25565).len(), 5);
+        assert_eq!(monitor.uptime("localhost", 25565), Some(0.6));
+        assert_eq!(monitor.average_latency("localhost", 25565), Some(Duration::from_millis(20)));
+    }
+}
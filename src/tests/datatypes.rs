@@ -60,6 +60,22 @@ fn string() {
                     write_String);
 }
 
+#[test]
+fn small_string() {
+    read_and_write!(SmallString::from("ozelot"),
+                    &[6, b'o', b'z', b'e', b'l', b'o', b't'],
+                    read_SmallString,
+                    write_SmallString);
+    /* Longer than fits inline */
+    let long = "minecraft:entity.experience_orb.pickup";
+    let mut binary = vec![long.len() as u8];
+    binary.extend_from_slice(long.as_bytes());
+    read_and_write!(SmallString::from(long), &binary[..], read_SmallString, write_SmallString);
+
+    assert!(read_SmallString(&mut Cursor::new(&[2, 0xff, 0xfe])).is_err());
+    assert!(read_SmallString(&mut Cursor::new(&[6, b'o', b'z'])).is_err());
+}
+
 #[test]
 fn varint() {
     /* Test some special values */
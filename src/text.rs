@@ -0,0 +1,203 @@
+//! Reading and writing the text of signs, books and anvils
+//!
+//! The server drops or cuts text that doesn't fit the limits of the vanilla
+//! client, so the functions building the UpdateSign, EditBook and NameItem
+//! packets here check the text first and return an error instead:
+//!
+//! - Sign lines must fit in SIGN_LINE_WIDTH pixels, see the font module
+//! - Books have at most MAX_BOOK_PAGES pages of MAX_PAGE_LENGTH characters,
+//!   and a title of at most MAX_TITLE_LENGTH characters when signed
+//! - Item names have at most MAX_ITEM_NAME characters
+//!
+//! None of them may contain section signs or control characters, see
+//! chat::is_allowed_character(). Sign text is sent by the server as chat
+//! json in UpdateBlockEntity packets, which sign_text() parses.
+
+use block::Hand;
+use chat::{self, ChatComponent};
+use clientbound::ClientboundPacket;
+use consts;
+use errors::Result;
+use font;
+use nbt::{self, Tag};
+use serverbound::{self, ServerboundPacket};
+use slot::Slot;
+use write::{write_bool, write_varint};
+
+use std::io::Cursor;
+
+/// The width in pixels of a sign line
+pub const SIGN_LINE_WIDTH: u32 = 90;
+/// The number of lines on a sign
+pub const SIGN_LINES: usize = 4;
+pub const MAX_BOOK_PAGES: usize = consts::MAX_BOOK_PAGES;
+pub const MAX_PAGE_LENGTH: usize = consts::MAX_BOOK_PAGE_LENGTH;
+pub const MAX_TITLE_LENGTH: usize = consts::MAX_BOOK_TITLE_LENGTH;
+/// The maximum length of an item name set in an anvil
+pub const MAX_ITEM_NAME: usize = consts::MAX_ITEM_NAME_LENGTH;
+/// The UpdateBlockEntity action setting the text of a sign
+pub const SIGN_ACTION: u8 = 9;
+
+fn check_characters(what: &str, text: &str) -> Result<()> {
+    if let Some(c) = text.chars().find(|&c| !chat::is_allowed_character(c)) {
+        bail!("The {} contains the invalid character {:?}", what, c);
+    }
+    Ok(())
+}
+
+fn check_length(what: &str, text: &str, max: usize) -> Result<()> {
+    let length = text.chars().count();
+    if length > max {
+        bail!("The {} is {} characters long, the maximum is {}", what, length, max);
+    }
+    check_characters(what, text)
+}
+
+/// Get the lines of a sign from its block entity NBT
+pub fn sign_lines(nbt: &Tag) -> Result<Vec<ChatComponent>> {
+    (1..SIGN_LINES + 1)
+        .map(|i| match nbt.get(&format!("Text{}", i)).and_then(|x| x.as_str()) {
+                 Some(json) => ChatComponent::parse(json),
+                 None => bail!("Sign without line {}", i),
+             })
+        .collect()
+}
+
+/// The text of a sign, see sign_text()
+#[derive(Debug, Clone, PartialEq)]
+pub struct SignText {
+    pub position: (i32, i32, i32),
+    pub lines: Vec<ChatComponent>,
+}
+
+/// Get the text of a sign, if the packet is an UpdateBlockEntity setting the
+/// text of one
+pub fn sign_text(packet: &ClientboundPacket) -> Option<Result<SignText>> {
+    match *packet {
+        ClientboundPacket::UpdateBlockEntity(ref p) if *p.get_action() == SIGN_ACTION => {
+            let lines = nbt::read_nbt(&mut Cursor::new(p.get_nbt()))
+                .and_then(|x| match x {
+                              Some((_, tag)) => sign_lines(&tag),
+                              None => bail!("Sign without NBT"),
+                          });
+            Some(lines.map(|lines| {
+                               SignText {
+                                   position: *p.get_position(),
+                                   lines,
+                               }
+                           }))
+        },
+        _ => None,
+    }
+}
+
+/// Build an UpdateSign packet setting the text of the sign at the given
+/// position, with missing lines left empty
+pub fn update_sign(position: (i32, i32, i32), lines: &[&str]) -> Result<ServerboundPacket> {
+    if lines.len() > SIGN_LINES {
+        bail!("A sign has {} lines, got {}", SIGN_LINES, lines.len());
+    }
+    for line in lines {
+        check_characters("sign line", line)?;
+        let width = font::text_width(line);
+        if width > SIGN_LINE_WIDTH {
+            bail!("The sign line {:?} is {} pixels wide, the maximum is {}",
+                  line,
+                  width,
+                  SIGN_LINE_WIDTH);
+        }
+    }
+    let line = |i: usize| lines.get(i).cloned().unwrap_or("").to_string();
+    Ok(serverbound::UpdateSign::new(position, line(0), line(1), line(2), line(3)))
+}
+
+/// Build an EditBook packet writing the given pages into the book and quill
+/// in the given hand, and signing it with the title if there is one. The
+/// server sets the author itself.
+pub fn edit_book(book: &Slot,
+                 pages: &[&str],
+                 title: Option<&str>,
+                 hand: Hand)
+                 -> Result<ServerboundPacket> {
+    if pages.len() > MAX_BOOK_PAGES {
+        bail!("A book has at most {} pages, got {}", MAX_BOOK_PAGES, pages.len());
+    }
+    for page in pages {
+        check_length("book page", page, MAX_PAGE_LENGTH)?;
+    }
+    let mut book = book.clone();
+    let mut tag = match book.nbt.take() {
+        Some(Tag::Compound(x)) => x,
+        _ => Default::default(),
+    };
+    let pages = pages.iter().map(|x| Tag::String(x.to_string())).collect();
+    let _ = tag.insert("pages".to_string(), Tag::List(pages));
+    if let Some(title) = title {
+        if title.trim().is_empty() {
+            bail!("The book title is empty");
+        }
+        check_length("book title", title, MAX_TITLE_LENGTH)?;
+        let _ = tag.insert("title".to_string(), Tag::String(title.to_string()));
+    }
+    book.nbt = Some(Tag::Compound(tag));
+
+    let mut data = book.to_u8()?;
+    write_bool(&title.is_some(), &mut data)?;
+    write_varint(&hand.to_id(), &mut data)?;
+    Ok(serverbound::EditBook::new(data))
+}
+
+/// Build a NameItem packet setting the name of the item in an open anvil
+pub fn rename_item(name: &str) -> Result<ServerboundPacket> {
+    check_length("item name", name, MAX_ITEM_NAME)?;
+    Ok(serverbound::NameItem::new(name.to_string()))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use clientbound::UpdateBlockEntity;
+    use std::collections::BTreeMap;
+
+    #[test]
+    fn text() {
+        let mut tag = BTreeMap::new();
+        for i in 1..5 {
+            let json = format!(r#"{{"text": "Line {}"}}"#, i);
+            let _ = tag.insert(format!("Text{}", i), Tag::String(json));
+        }
+        let mut data = Vec::new();
+        nbt::write_nbt(Some(("", &Tag::Compound(tag))), &mut data).unwrap();
+        let packet = UpdateBlockEntity::new((1, 64, -3), SIGN_ACTION, data);
+        let sign = sign_text(&packet).unwrap().unwrap();
+        assert_eq!(sign.position, (1, 64, -3));
+        assert_eq!(sign.lines.iter().map(|x| x.to_plain()).collect::<Vec<_>>(),
+                   vec!["Line 1", "Line 2", "Line 3", "Line 4"]);
+
+        assert!(update_sign((0, 0, 0), &["Hello", "world"]).is_ok());
+        assert!(update_sign((0, 0, 0), &["This line is far too wide"]).is_err());
+        assert!(update_sign((0, 0, 0), &["\u{a7}cRed"]).is_err());
+        assert!(update_sign((0, 0, 0), &["", "", "", "", ""]).is_err());
+
+        let book = Slot {
+            item_id: 1,
+            count: 1,
+            nbt: None,
+        };
+        let packet = edit_book(&book, &["Page 1", "Page 2"], Some("Title"), Hand::Main).unwrap();
+        let data = match packet {
+            ServerboundPacket::EditBook(ref p) => p.get_data().clone(),
+            _ => unreachable!(),
+        };
+        let mut r = Cursor::new(&data[..]);
+        let slot = Slot::read(&mut r).unwrap().unwrap();
+        let nbt = slot.nbt.unwrap();
+        assert_eq!(nbt.get("title").and_then(|x| x.as_str()), Some("Title"));
+        assert_eq!(nbt.get("pages").and_then(|x| x.as_list()).map(|x| x.len()), Some(2));
+        assert_eq!(&data[r.position() as usize..], &[1, 0]);
+        assert!(edit_book(&book, &[], Some("A title far too long"), Hand::Main).is_err());
+
+        assert!(rename_item("Sword").is_ok());
+        assert!(rename_item(&"x".repeat(36)).is_err());
+    }
+}
@@ -0,0 +1,164 @@
+//! Throttling of login attempts per IP and per account
+//!
+//! A LoginThrottle counts the login attempts (LoginStart packets) of each
+//! IP and username within a time window, and rejects those over the limit,
+//! like the connection-throttle setting of Bukkit servers. The counts are
+//! kept in a ThrottleStore, which is in memory by default, but can be shared
+//! between the servers of a cluster by implementing ThrottleStore on top of
+//! e.g. Redis.
+//!
+//! A ServerListener applies the ThrottleLimits in its options to the
+//! connections it accepts, see conn.read().
+
+use std::collections::{HashMap, VecDeque};
+use std::fmt;
+use std::net::IpAddr;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+/// The reason sent to a client whose login attempt was throttled
+pub const THROTTLED_REASON: &str = r#"{"text":"Connection throttled! Please wait before reconnecting."}"#;
+
+/// What login attempts are counted by
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum ThrottleKey {
+    Ip(IpAddr),
+    /// The lowercase username
+    Account(String),
+}
+impl fmt::Display for ThrottleKey {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            ThrottleKey::Ip(ref ip) => write!(f, "ip:{}", ip),
+            ThrottleKey::Account(ref name) => write!(f, "account:{}", name),
+        }
+    }
+}
+
+/// Counts login attempts. Stores are shared between connections, and thus
+/// between threads.
+pub trait ThrottleStore: Send + Sync {
+    /// Record an attempt with the given key, and return the amount of
+    /// attempts with it within the last window, including this one
+    fn hit(&self, key: &ThrottleKey, window: Duration) -> usize;
+}
+
+/// A ThrottleStore keeping the counts in memory
+#[derive(Debug, Default)]
+pub struct MemoryThrottleStore {
+    /* The window and the attempts within it of each key */
+    attempts: Mutex<HashMap<ThrottleKey, (Duration, VecDeque<Instant>)>>,
+}
+impl MemoryThrottleStore {
+    pub fn new() -> Self {
+        Default::default()
+    }
+}
+impl ThrottleStore for MemoryThrottleStore {
+    fn hit(&self, key: &ThrottleKey, window: Duration) -> usize {
+        let mut attempts = match self.attempts.lock() {
+            Ok(x) => x,
+            Err(_) => return 0,
+        };
+        let now = Instant::now();
+        /* Forget old attempts of all keys, so the map doesn't grow forever */
+        attempts.retain(|_, &mut (window, ref mut times)| {
+            while times.front().is_some_and(|&x| now - x > window) {
+                let _: Option<Instant> = times.pop_front();
+            }
+            !times.is_empty()
+        });
+        let entry = attempts.entry(key.clone()).or_default();
+        entry.0 = window;
+        entry.1.push_back(now);
+        entry.1.len()
+    }
+}
+
+/// The maximum amount of login attempts within the given time, e.g.
+/// (1, 4 seconds) for Bukkit's default connection throttle. Set a limit to
+/// None to not check it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct ThrottleLimits {
+    pub per_ip: Option<(usize, Duration)>,
+    pub per_account: Option<(usize, Duration)>,
+}
+
+/// Checks login attempts against ThrottleLimits
+#[derive(Clone)]
+pub struct LoginThrottle {
+    limits: ThrottleLimits,
+    store: Arc<dyn ThrottleStore>,
+}
+impl LoginThrottle {
+    /// Create a throttle keeping the counts in memory
+    pub fn new(limits: ThrottleLimits) -> Self {
+        LoginThrottle::with_store(limits, Arc::new(MemoryThrottleStore::new()))
+    }
+
+    /// Create a throttle keeping the counts in the given store
+    pub fn with_store(limits: ThrottleLimits, store: Arc<dyn ThrottleStore>) -> Self {
+        LoginThrottle { limits, store }
+    }
+
+    pub fn limits(&self) -> &ThrottleLimits {
+        &self.limits
+    }
+
+    /// Record a login attempt, returning the key and the amount of attempts
+    /// if it's over the limits
+    pub fn check(&self, ip: Option<IpAddr>, username: &str) -> Option<(ThrottleKey, usize)> {
+        let mut keys = Vec::new();
+        if let (Some(ip), Some(limit)) = (ip, self.limits.per_ip) {
+            keys.push((ThrottleKey::Ip(ip), limit));
+        }
+        if let Some(limit) = self.limits.per_account {
+            keys.push((ThrottleKey::Account(username.to_lowercase()), limit));
+        }
+        /* Every key is hit, so an attempt counts against all of them */
+        let mut ret = None;
+        for (key, (max, window)) in keys {
+            let attempts = self.store.hit(&key, window);
+            if attempts > max && ret.is_none() {
+                ret = Some((key, attempts));
+            }
+        }
+        ret
+    }
+}
+impl fmt::Debug for LoginThrottle {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("LoginThrottle")
+            .field("limits", &self.limits)
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn throttle() {
+        let limits = ThrottleLimits {
+            per_ip: Some((2, Duration::from_secs(60))),
+            per_account: Some((1, Duration::from_secs(60))),
+        };
+        let throttle = LoginThrottle::new(limits);
+        let ip: IpAddr = "10.0.0.1".parse().unwrap();
+        assert_eq!(throttle.check(Some(ip), "Bob"), None);
+        assert_eq!(throttle.check(Some(ip), "bob"),
+                   Some((ThrottleKey::Account("bob".to_string()), 2)));
+        assert_eq!(throttle.check(Some(ip), "Alice"),
+                   Some((ThrottleKey::Ip(ip), 3)));
+        assert_eq!(throttle.check(None, "Carol"), None);
+        assert_eq!(ThrottleKey::Ip(ip).to_string(), "ip:10.0.0.1");
+
+        let store = MemoryThrottleStore::new();
+        let key = ThrottleKey::Account("bob".to_string());
+        assert_eq!(store.hit(&key, Duration::from_secs(60)), 1);
+        assert_eq!(store.hit(&key, Duration::from_secs(60)), 2);
+        assert_eq!(store.hit(&ThrottleKey::Ip(ip), Duration::from_secs(0)), 1);
+        assert_eq!(store.hit(&key, Duration::from_secs(60)), 3);
+    }
+}
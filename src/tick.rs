@@ -0,0 +1,255 @@
+//! A fixed timestep scheduler for driving server ticks
+//!
+//! Minecraft servers run their world updates at 20 ticks per second. The
+//! TickScheduler keeps track of when the next tick is due, what to do when
+//! the server falls behind, and how long ticks are taking.
+//!
+//! # Examples
+//!
+//! ```rust,no_run
+//! use ozelot::tick::TickScheduler;
+//!
+//! let mut scheduler = TickScheduler::new();
+//! loop {
+//!     let tick = scheduler.wait();
+//!     /* Update the world, send keepalives every 20 seconds, etc. */
+//!     if tick % 400 == 0 {
+//!         println!("{:?}", scheduler.metrics());
+//!     }
+//! }
+//! ```
+
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// The vanilla amount of ticks per second
+pub const TICKS_PER_SECOND: u32 = 20;
+
+/// What to do when the scheduler falls behind, i.e. when one or more ticks
+/// were due before the consumer got around to running them.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LagPolicy {
+    /// Run the missed ticks back to back without waiting, but never more than
+    /// the given amount. Any ticks beyond that are skipped. This is what
+    /// vanilla does (with a limit corresponding to 2 seconds.)
+    CatchUp(u32),
+    /// Skip all missed ticks, and continue on schedule from now
+    Skip,
+}
+
+/// Timing information about the ticks run so far
+#[derive(Debug, Clone, Default)]
+pub struct TickMetrics {
+    /// The amount of ticks run
+    pub ticks: u64,
+    /// The amount of ticks skipped due to lag
+    pub skipped: u64,
+    /// The amount of ticks that took longer than the tick period
+    pub overloaded: u64,
+    /// How long the last tick took
+    pub last_duration: Duration,
+    /// The longest any tick has taken
+    pub max_duration: Duration,
+    /// Exponential moving average of the tick duration
+    pub average_duration: Duration,
+}
+impl TickMetrics {
+    /// Get the average milliseconds per tick (MSPT)
+    pub fn mspt(&self) -> f64 {
+        duration_to_secs(self.average_duration) * 1000.0
+    }
+}
+
+/// Fixed timestep tick scheduler, see the module documentation
+#[derive(Debug, Clone)]
+pub struct TickScheduler {
+    period: Duration,
+    policy: LagPolicy,
+    next_tick: Option<Instant>,
+    /* When the tick that is currently being run started */
+    tick_started: Option<Instant>,
+    metrics: TickMetrics,
+}
+impl Default for TickScheduler {
+    fn default() -> Self {
+        TickScheduler::new()
+    }
+}
+impl TickScheduler {
+    /// Create a new scheduler running at 20 ticks per second, catching up at
+    /// most 40 ticks.
+    pub fn new() -> Self {
+        TickScheduler::with_rate(TICKS_PER_SECOND)
+    }
+
+    /// Create a new scheduler with the given amount of ticks per second
+    ///
+    /// # Panics
+    ///
+    /// Panics if ticks_per_second is 0.
+    pub fn with_rate(ticks_per_second: u32) -> Self {
+        if ticks_per_second == 0 {
+            panic!("TickScheduler ticks_per_second must be greater than 0");
+        }
+        TickScheduler {
+            period: Duration::from_secs(1) / ticks_per_second,
+            policy: LagPolicy::CatchUp(2 * ticks_per_second),
+            next_tick: None,
+            tick_started: None,
+            metrics: TickMetrics::default(),
+        }
+    }
+
+    /// Set the policy for handling missed ticks
+    pub fn set_policy(&mut self, policy: LagPolicy) {
+        self.policy = policy;
+    }
+
+    /// Get the time between each tick
+    pub fn period(&self) -> Duration {
+        self.period
+    }
+
+    /// Get the timing metrics
+    pub fn metrics(&self) -> &TickMetrics {
+        &self.metrics
+    }
+
+    /// Check whether a tick is due, and if so return its tick number.
+    ///
+    /// The time between a tick being returned and the next call to poll or
+    /// wait is counted as the duration of that tick.
+    ///
+    /// This function is nonblocking.
+    pub fn poll(&mut self) -> Option<u64> {
+        self.poll_at(Instant::now())
+    }
+
+    /// Block until the next tick is due, then return its tick number.
+    pub fn wait(&mut self) -> u64 {
+        loop {
+            let now = Instant::now();
+            if let Some(tick) = self.poll_at(now) {
+                return tick;
+            }
+            if let Some(next) = self.next_tick {
+                if next > now {
+                    thread::sleep(next - now);
+                }
+            }
+        }
+    }
+
+    /// Run the given function once per tick, with the tick number, until it
+    /// returns false.
+    pub fn run<F: FnMut(u64) -> bool>(&mut self, mut f: F) {
+        loop {
+            let tick = self.wait();
+            if !f(tick) {
+                self.finish_tick(Instant::now());
+                return;
+            }
+        }
+    }
+
+    fn poll_at(&mut self, now: Instant) -> Option<u64> {
+        self.finish_tick(now);
+
+        let next = match self.next_tick {
+            Some(x) => x,
+            /* The first tick is due immediately */
+            None => now,
+        };
+        if next > now {
+            return None;
+        }
+
+        let behind = duration_to_secs(now - next) /
+                     duration_to_secs(self.period);
+        let behind = behind as u64;
+        let next = match self.policy {
+            LagPolicy::Skip if behind > 0 => {
+                self.metrics.skipped += behind;
+                now
+            },
+            LagPolicy::CatchUp(max) if behind > max as u64 => {
+                self.metrics.skipped += behind - max as u64;
+                now.checked_sub(self.period * max).unwrap_or(now)
+            },
+            _ => next,
+        };
+
+        self.next_tick = Some(next + self.period);
+        self.tick_started = Some(now);
+        let tick = self.metrics.ticks;
+        self.metrics.ticks += 1;
+        Some(tick)
+    }
+
+    /* Record the duration of the currently running tick, if any */
+    fn finish_tick(&mut self, now: Instant) {
+        if let Some(started) = self.tick_started.take() {
+            let duration = now - started;
+            let m = &mut self.metrics;
+            if duration > self.period {
+                m.overloaded += 1;
+            }
+            if duration > m.max_duration {
+                m.max_duration = duration;
+            }
+            m.last_duration = duration;
+            m.average_duration = if m.ticks <= 1 {
+                duration
+            } else {
+                m.average_duration.mul_f64(0.95) + duration.mul_f64(0.05)
+            };
+        }
+    }
+}
+
+fn duration_to_secs(d: Duration) -> f64 {
+    d.as_secs() as f64 + d.subsec_nanos() as f64 * 1e-9
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn catch_up() {
+        let start = Instant::now();
+        let ms = Duration::from_millis;
+        let mut s = TickScheduler::new();
+        s.set_policy(LagPolicy::CatchUp(2));
+
+        assert_eq!(s.poll_at(start), Some(0));
+        assert_eq!(s.poll_at(start + ms(10)), None);
+        assert_eq!(s.poll_at(start + ms(50)), Some(1));
+        assert_eq!(s.metrics().last_duration, ms(10));
+        assert_eq!(s.metrics().overloaded, 0);
+
+        /* Tick 2 was due at 100 ms, so at 260 ms we're 3 ticks behind, of
+         * which 1 is skipped */
+        assert_eq!(s.poll_at(start + ms(260)), Some(2));
+        assert_eq!(s.metrics().overloaded, 1);
+        assert_eq!(s.metrics().skipped, 1);
+        assert_eq!(s.poll_at(start + ms(260)), Some(3));
+        assert_eq!(s.poll_at(start + ms(260)), Some(4));
+        assert_eq!(s.poll_at(start + ms(260)), None);
+        assert_eq!(s.poll_at(start + ms(310)), Some(5));
+    }
+
+    #[test]
+    fn skip() {
+        let start = Instant::now();
+        let ms = Duration::from_millis;
+        let mut s = TickScheduler::new();
+        s.set_policy(LagPolicy::Skip);
+
+        assert_eq!(s.poll_at(start), Some(0));
+        assert_eq!(s.poll_at(start + ms(175)), Some(1));
+        assert_eq!(s.metrics().skipped, 2);
+        assert_eq!(s.poll_at(start + ms(200)), None);
+        assert_eq!(s.poll_at(start + ms(225)), Some(2));
+    }
+}
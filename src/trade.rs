@@ -0,0 +1,224 @@
+//! Villager trading
+//!
+//! When a villager's trading window is opened, the server sends its trades
+//! as a plugin message on the minecraft:trader_list channel, which
+//! TradeList::from_packet() parses. To trade, select the trade with a
+//! SelectTrade packet, put the inputs into the two input slots of the
+//! window and take the result from the output slot. TradeList::select() and
+//! TradeList::clicks() build those, and client.trade() sends them through
+//! the window transactions, see the window module.
+//!
+//! The protocol doesn't include the experience of trades before 1.14, so it
+//! isn't available here.
+//!
+//! # Examples
+//!
+//! ```rust,no_run
+//! use ozelot::Client;
+//! use ozelot::slot::Slot;
+//! use ozelot::trade::TradeList;
+//!
+//! let mut client = Client::connect_unauthenticated("localhost", 25565, "Bob").unwrap();
+//! loop {
+//!     for packet in client.read().unwrap() {
+//!         if let Some(Ok(list)) = TradeList::from_packet(&packet) {
+//!             for (i, trade) in list.trades.iter().enumerate() {
+//!                 println!("{}: {} of item {}", i, trade.output.count, trade.output.item_id);
+//!             }
+//!             /* Pay with the 32 emeralds in slot 30 of the window */
+//!             let emeralds = Slot { item_id: 684, count: 32, nbt: None };
+//!             client.trade(&list, 0, &[(30, emeralds)]).unwrap();
+//!         }
+//!     }
+//! }
+//! ```
+
+use clientbound::ClientboundPacket;
+use errors::Result;
+use read::{read_bool, read_i32, read_i8};
+use serverbound::{self, ServerboundPacket};
+use slot::Slot;
+use window::Click;
+
+use std::io::Cursor;
+
+/// The plugin channel the trades are sent on
+pub const TRADER_LIST_CHANNEL: &str = "minecraft:trader_list";
+
+/// The slots of the trading window
+pub const INPUT_SLOTS: [i16; 2] = [0, 1];
+pub const OUTPUT_SLOT: i16 = 2;
+
+/// A trade offered by a villager
+#[derive(Debug, Clone, PartialEq)]
+pub struct Trade {
+    pub input: Slot,
+    pub second_input: Option<Slot>,
+    pub output: Slot,
+    /// Whether the villager has run out of this trade
+    pub disabled: bool,
+    /// How many times the trade has been used
+    pub uses: i32,
+    /// How many times the trade can be used before it's disabled
+    pub max_uses: i32,
+}
+impl Trade {
+    /// The inputs of the trade
+    pub fn inputs(&self) -> Vec<&Slot> {
+        let mut ret = vec![&self.input];
+        ret.extend(self.second_input.as_ref());
+        ret
+    }
+}
+
+/// The trades of an open trading window
+#[derive(Debug, Clone, PartialEq)]
+pub struct TradeList {
+    pub window_id: u8,
+    pub trades: Vec<Trade>,
+}
+impl TradeList {
+    /// Parse the data of a trader_list plugin message
+    pub fn parse(data: &[u8]) -> Result<Self> {
+        let mut r = Cursor::new(data);
+        let window_id = read_i32(&mut r)? as u8;
+        let count = read_i8(&mut r)?;
+        let mut trades = Vec::new();
+        for _ in 0..count {
+            let input = slot(&mut r)?;
+            let output = slot(&mut r)?;
+            let second_input = if read_bool(&mut r)? {
+                Some(slot(&mut r)?)
+            } else {
+                None
+            };
+            trades.push(Trade {
+                            input,
+                            second_input,
+                            output,
+                            disabled: read_bool(&mut r)?,
+                            uses: read_i32(&mut r)?,
+                            max_uses: read_i32(&mut r)?,
+                        });
+        }
+        Ok(TradeList { window_id, trades })
+    }
+
+    /// Parse the trades if the packet is a trader_list plugin message
+    pub fn from_packet(packet: &ClientboundPacket) -> Option<Result<Self>> {
+        match *packet {
+            ClientboundPacket::ClientboundPluginMessage(ref p)
+                if p.get_channel() == TRADER_LIST_CHANNEL => Some(TradeList::parse(p.get_data())),
+            _ => None,
+        }
+    }
+
+    /// Get the trade with the given index, if it exists and isn't disabled
+    pub fn get(&self, index: usize) -> Result<&Trade> {
+        match self.trades.get(index) {
+            Some(x) if x.disabled => bail!("Trade {} is disabled", index),
+            Some(x) => Ok(x),
+            None => bail!("No trade {}, only {} trades", index, self.trades.len()),
+        }
+    }
+
+    /// The packet selecting the trade with the given index
+    pub fn select(&self, index: usize) -> Result<ServerboundPacket> {
+        let _: &Trade = self.get(index)?;
+        Ok(serverbound::SelectTrade::new(index as i32))
+    }
+
+    /// The clicks that do the trade with the given index, after it's been
+    /// selected: moving each of the inputs from the given window slots, with
+    /// the items they contain, to the input slots, and shift-clicking the
+    /// output into the inventory.
+    ///
+    /// The inputs must be given in the same order as in the trade.
+    pub fn clicks(&self, index: usize, inputs: &[(i16, Slot)]) -> Result<Vec<Click>> {
+        let trade = self.get(index)?;
+        let needed = trade.inputs();
+        if inputs.len() != needed.len() {
+            bail!("Trade {} needs {} inputs, got {}", index, needed.len(), inputs.len());
+        }
+        let mut empty = Vec::new();
+        Slot::write(None, &mut empty)?;
+        let mut ret = Vec::new();
+        for (&(from, ref item), (&to, wanted)) in
+            inputs.iter().zip(INPUT_SLOTS.iter().zip(needed)) {
+            if item.item_id != wanted.item_id || item.count < wanted.count {
+                bail!("Slot {} doesn't have {} of item {}", from, wanted.count, wanted.item_id);
+            }
+            ret.push(self.click(from, 0, item.to_u8()?));
+            ret.push(self.click(to, 0, empty.clone()));
+        }
+        ret.push(self.click(OUTPUT_SLOT, 1, trade.output.to_u8()?));
+        Ok(ret)
+    }
+
+    /* A left click in the given mode, 0 for a normal click and 1 for shift */
+    fn click(&self, slot: i16, mode: i32, clicked_item: Vec<u8>) -> Click {
+        Click {
+            window_id: self.window_id,
+            slot,
+            button: 0,
+            mode,
+            clicked_item,
+        }
+    }
+}
+
+fn slot(r: &mut Cursor<&[u8]>) -> Result<Slot> {
+    match Slot::read(r)? {
+        Some(x) => Ok(x),
+        None => bail!("Trade with an empty item"),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use clientbound::ClientboundPluginMessage;
+    use read::SmallString;
+    use write::{write_bool, write_i32, write_i8};
+
+    fn item(item_id: i32, count: i8) -> Slot {
+        Slot {
+            item_id,
+            count,
+            nbt: None,
+        }
+    }
+
+    #[test]
+    fn trades() {
+        let mut data = Vec::new();
+        write_i32(&3, &mut data).unwrap();
+        write_i8(&1, &mut data).unwrap();
+        data.extend(item(684, 12).to_u8().unwrap());
+        data.extend(item(1, 8).to_u8().unwrap());
+        write_bool(&false, &mut data).unwrap();
+        write_bool(&false, &mut data).unwrap();
+        write_i32(&2, &mut data).unwrap();
+        write_i32(&7, &mut data).unwrap();
+        let packet = ClientboundPluginMessage::new(SmallString::from(TRADER_LIST_CHANNEL), data);
+        let list = TradeList::from_packet(&packet).unwrap().unwrap();
+        assert_eq!(list.window_id, 3);
+        assert_eq!(list.trades,
+                   vec![Trade {
+                            input: item(684, 12),
+                            second_input: None,
+                            output: item(1, 8),
+                            disabled: false,
+                            uses: 2,
+                            max_uses: 7,
+                        }]);
+
+        assert_eq!(list.select(0).unwrap(), serverbound::SelectTrade::new(0));
+        assert!(list.select(1).is_err());
+        let clicks = list.clicks(0, &[(30, item(684, 20))]).unwrap();
+        assert_eq!(clicks.iter().map(|x| (x.slot, x.mode)).collect::<Vec<_>>(),
+                   vec![(30, 0), (0, 0), (2, 1)]);
+        assert!(list.clicks(0, &[(30, item(684, 2))]).is_err());
+        assert!(list.clicks(0, &[]).is_err());
+    }
+}
@@ -0,0 +1,164 @@
+//! Tracking the state transitions of a connection
+//!
+//! Every Client and Server keeps a TransitionLog of the protocol states it
+//! has gone through (Handshake, Status, Login, Play), when, and which packet
+//! was the last one sent or received before the transition, i.e. usually
+//! the one that caused it. This helps debugging logins that hang between
+//! states: client.transition_log().time_in_state() shows how long the
+//! connection has been stuck, and the last packet shows where.
+//!
+//! The log can be exported as json, or as a Graphviz graph with to_dot().
+
+use ClientState;
+use stats::Direction;
+
+use std::time::{Duration, Instant};
+
+/// A change of the state of a connection
+#[derive(Debug, Clone, PartialEq)]
+pub struct Transition {
+    pub from: ClientState,
+    pub to: ClientState,
+    /// The time since the connection was created
+    pub at: Duration,
+    /// The last packet sent or received before the transition, as its
+    /// direction and name, if any
+    pub cause: Option<(Direction, String)>,
+}
+
+/// The state transitions of a connection, see the module documentation
+#[derive(Debug, Clone)]
+pub struct TransitionLog {
+    created: Instant,
+    last_change: Instant,
+    history: Vec<Transition>,
+    /* Index in history of the first transition not yet polled */
+    polled: usize,
+    last_packet: Option<(Direction, String)>,
+}
+impl TransitionLog {
+    pub(crate) fn new() -> Self {
+        let now = Instant::now();
+        TransitionLog {
+            created: now,
+            last_change: now,
+            history: Vec::new(),
+            polled: 0,
+            last_packet: None,
+        }
+    }
+
+    pub(crate) fn packet(&mut self, direction: Direction, name: &str) {
+        match self.last_packet {
+            Some((ref mut d, ref mut n)) => {
+                *d = direction;
+                n.clear();
+                n.push_str(name);
+            },
+            None => self.last_packet = Some((direction, name.to_string())),
+        }
+    }
+
+    pub(crate) fn transition(&mut self, from: &ClientState, to: &ClientState) {
+        if from == to {
+            return;
+        }
+        self.last_change = Instant::now();
+        self.history.push(Transition {
+                              from: from.clone(),
+                              to: to.clone(),
+                              at: self.created.elapsed(),
+                              cause: self.last_packet.clone(),
+                          });
+    }
+
+    /// Get all the transitions so far
+    pub fn history(&self) -> &[Transition] {
+        &self.history
+    }
+
+    /// Get the transitions that have happened since the last call to this
+    /// function
+    pub fn poll(&mut self) -> Vec<Transition> {
+        let ret = self.history[self.polled..].to_vec();
+        self.polled = self.history.len();
+        ret
+    }
+
+    /// How long the connection has been in its current state
+    pub fn time_in_state(&self) -> Duration {
+        self.last_change.elapsed()
+    }
+
+    /// The last packet sent or received, as its direction and name
+    pub fn last_packet(&self) -> Option<(Direction, &str)> {
+        self.last_packet.as_ref().map(|&(d, ref n)| (d, n.as_str()))
+    }
+
+    /// Export the transitions as a json array
+    pub fn to_json(&self) -> String {
+        let transitions: Vec<_> = self.history
+            .iter()
+            .map(|t| {
+                json!({
+                    "from": t.from.to_string(),
+                    "to": t.to.to_string(),
+                    "at_ms": t.at.as_millis() as u64,
+                    "cause": t.cause.as_ref().map(|&(d, ref n)| json!({
+                        "direction": direction_name(d),
+                        "packet": n,
+                    })),
+                })
+            })
+            .collect();
+        json!(transitions).to_string()
+    }
+
+    /// Export the transitions as a Graphviz graph, with the states as nodes
+    /// and each transition as an edge labelled with its time and cause
+    pub fn to_dot(&self) -> String {
+        let mut ret = "digraph connection {\n".to_string();
+        for t in &self.history {
+            let cause = match t.cause {
+                Some((d, ref n)) => format!(" ({} {})", direction_name(d), n),
+                None => String::new(),
+            };
+            ret.push_str(&format!("    {} -> {} [label=\"{}ms{}\"];\n",
+                                  t.from,
+                                  t.to,
+                                  t.at.as_millis(),
+                                  cause));
+        }
+        ret.push_str("}\n");
+        ret
+    }
+}
+
+fn direction_name(direction: Direction) -> &'static str {
+    match direction {
+        Direction::Inbound => "received",
+        Direction::Outbound => "sent",
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn log() {
+        let mut log = TransitionLog::new();
+        log.packet(Direction::Outbound, "Handshake");
+        log.transition(&ClientState::Handshake, &ClientState::Login);
+        log.transition(&ClientState::Login, &ClientState::Login);
+        assert_eq!(log.poll().len(), 1);
+        log.packet(Direction::Inbound, "LoginSuccess");
+        log.transition(&ClientState::Login, &ClientState::Play);
+        let polled = log.poll();
+        assert_eq!(polled.len(), 1);
+        assert_eq!(polled[0].cause, Some((Direction::Inbound, "LoginSuccess".to_string())));
+        assert_eq!(log.history().len(), 2);
+        assert!(log.to_dot().contains("Login -> Play"));
+        assert!(log.to_json().contains("\"packet\":\"Handshake\""));
+    }
+}
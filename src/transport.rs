@@ -0,0 +1,302 @@
+//! Connections over streams other than TCP
+//!
+//! Client and Server usually talk over a TcpStream, but the protocol can be
+//! carried by any byte stream, e.g. a unix socket, a TLS tunnel or an
+//! in-memory pipe in tests. Implement Transport for the stream and pass it
+//! to Client::from_transport() or Server::from_transport().
+//!
+//! Like the rest of ozelot, transports are nonblocking. read() must fail
+//! with io::ErrorKind::WouldBlock when no data is available instead of
+//! waiting for it, and read 0 bytes only once the peer has closed the
+//! stream. write() must likewise fail with WouldBlock when the stream can't
+//! take any more data right now.
+//!
+//! MemoryStream is a transport connected to another in memory, for tests
+//! that shouldn't depend on the network or timing of the machine they run
+//! on. It can also delay the data written to it, to test how a program
+//! handles a slow connection.
+//!
+//! There's no support for async streams (AsyncRead and AsyncWrite), since
+//! ozelot doesn't depend on an async runtime. These can be adapted by
+//! implementing Transport over a pair of channels to a task copying to and
+//! from the stream.
+//!
+//! # Examples
+//!
+//! ```rust,no_run
+//! # #[cfg(unix)]
+//! # fn main() {
+//! use ozelot::Client;
+//! use std::os::unix::net::UnixStream;
+//!
+//! let stream = UnixStream::connect("/run/minecraft.sock").unwrap();
+//! stream.set_nonblocking(true).unwrap();
+//! let mut client = Client::from_transport(stream).unwrap();
+//! # }
+//! # #[cfg(not(unix))]
+//! # fn main() {}
+//! ```
+//!
+//! Connecting a client and a server in memory
+//!
+//! ```rust
+//! use ozelot::{Client, Server};
+//! use ozelot::transport::MemoryStream;
+//!
+//! let (a, b) = MemoryStream::pair();
+//! let mut client = Client::from_transport(a).unwrap();
+//! let mut server = Server::from_transport(b).unwrap();
+//! ```
+
+use std::cmp;
+use std::collections::VecDeque;
+use std::io::{self, Read, Write};
+use std::net::{Shutdown, SocketAddr, TcpStream};
+#[cfg(unix)]
+use std::os::unix::io::{AsRawFd, RawFd};
+#[cfg(unix)]
+use std::os::unix::net::UnixStream;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+/// A nonblocking byte stream carrying a connection, see the module
+/// documentation
+pub trait Transport: Read + Write + Send {
+    /// Get the address of the peer, if it has one
+    fn peer_addr(&self) -> Option<SocketAddr> {
+        None
+    }
+
+    /// Close both directions of the stream
+    fn close(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+
+    /// Get the file descriptor to poll for readiness, if there is one, see
+    /// the readiness module
+    #[cfg(unix)]
+    fn raw_fd(&self) -> Option<RawFd> {
+        None
+    }
+}
+
+impl Transport for TcpStream {
+    fn peer_addr(&self) -> Option<SocketAddr> {
+        TcpStream::peer_addr(self).ok()
+    }
+
+    fn close(&mut self) -> io::Result<()> {
+        self.shutdown(Shutdown::Both)
+    }
+
+    #[cfg(unix)]
+    fn raw_fd(&self) -> Option<RawFd> {
+        Some(self.as_raw_fd())
+    }
+}
+
+/// The stream must be set to nonblocking with set_nonblocking(true)
+#[cfg(unix)]
+impl Transport for UnixStream {
+    fn close(&mut self) -> io::Result<()> {
+        self.shutdown(Shutdown::Both)
+    }
+
+    fn raw_fd(&self) -> Option<RawFd> {
+        Some(self.as_raw_fd())
+    }
+}
+
+/* One direction of a pair of MemoryStreams */
+#[derive(Debug, Default)]
+struct Pipe {
+    /* The data written and not yet read, with the time it arrives */
+    chunks: VecDeque<(Instant, Vec<u8>)>,
+    /* Whether either end has been closed or dropped */
+    closed: bool,
+}
+
+/// One end of a pair of streams connected in memory, see the module
+/// documentation
+///
+/// Data written to one end can be read from the other, after the latency
+/// given to pair_with_latency(). Once either end is closed or dropped,
+/// writing to either fails, and reading returns 0 bytes once everything
+/// written before has been read.
+#[derive(Debug)]
+pub struct MemoryStream {
+    incoming: Arc<Mutex<Pipe>>,
+    outgoing: Arc<Mutex<Pipe>>,
+    latency: Duration,
+    jitter: Duration,
+    /* The state of the random number generator for the jitter */
+    seed: u64,
+}
+impl MemoryStream {
+    /// Create a pair of streams connected to each other, where data arrives
+    /// as soon as it's written
+    pub fn pair() -> (MemoryStream, MemoryStream) {
+        MemoryStream::pair_with_latency(Duration::from_secs(0), Duration::from_secs(0))
+    }
+
+    /// Create a pair of streams connected to each other, where data arrives
+    /// after the given latency, plus a random delay of up to jitter
+    ///
+    /// The delays are random, but the same each time the program is run.
+    /// Data still arrives in the order it was written, data written during
+    /// the delay of earlier data can arrive together with it.
+    pub fn pair_with_latency(latency: Duration, jitter: Duration) -> (MemoryStream, MemoryStream) {
+        let a = Arc::new(Mutex::new(Pipe::default()));
+        let b = Arc::new(Mutex::new(Pipe::default()));
+        (MemoryStream {
+             incoming: a.clone(),
+             outgoing: b.clone(),
+             latency,
+             jitter,
+             seed: 1,
+         },
+         MemoryStream {
+             incoming: b,
+             outgoing: a,
+             latency,
+             jitter,
+             seed: 2,
+         })
+    }
+
+    /* The delay before data written now arrives */
+    fn delay(&mut self) -> Duration {
+        let jitter = self.jitter.as_nanos() as u64;
+        if jitter == 0 {
+            return self.latency;
+        }
+        /* xorshift64 */
+        self.seed ^= self.seed << 13;
+        self.seed ^= self.seed >> 7;
+        self.seed ^= self.seed << 17;
+        self.latency + Duration::from_nanos(self.seed % (jitter + 1))
+    }
+}
+impl Read for MemoryStream {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let mut pipe = self.incoming.lock().expect("MemoryStream lock poisoned");
+        let now = Instant::now();
+        let mut read = 0;
+        while read < buf.len() {
+            let (arrival, ref mut chunk) = match pipe.chunks.front_mut() {
+                Some(x) => (x.0, &mut x.1),
+                None => break,
+            };
+            if arrival > now {
+                break;
+            }
+            let n = cmp::min(chunk.len(), buf.len() - read);
+            buf[read..read + n].copy_from_slice(&chunk[..n]);
+            let _: Vec<u8> = chunk.drain(..n).collect();
+            read += n;
+            if chunk.is_empty() {
+                let _: Option<(Instant, Vec<u8>)> = pipe.chunks.pop_front();
+            }
+        }
+        if read == 0 && !buf.is_empty() && !(pipe.closed && pipe.chunks.is_empty()) {
+            return Err(io::ErrorKind::WouldBlock.into());
+        }
+        Ok(read)
+    }
+}
+impl Write for MemoryStream {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let delay = self.delay();
+        let mut pipe = self.outgoing.lock().expect("MemoryStream lock poisoned");
+        if pipe.closed {
+            return Err(io::ErrorKind::BrokenPipe.into());
+        }
+        /* Don't let the jitter reorder the data */
+        let mut arrival = Instant::now() + delay;
+        if let Some(last) = pipe.chunks.back() {
+            arrival = cmp::max(arrival, last.0);
+        }
+        pipe.chunks.push_back((arrival, buf.to_vec()));
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+impl Transport for MemoryStream {
+    fn close(&mut self) -> io::Result<()> {
+        self.incoming.lock().expect("MemoryStream lock poisoned").closed = true;
+        self.outgoing.lock().expect("MemoryStream lock poisoned").closed = true;
+        Ok(())
+    }
+}
+impl Drop for MemoryStream {
+    fn drop(&mut self) {
+        /* Don't panic while panicking if the lock is poisoned */
+        for pipe in &[&self.incoming, &self.outgoing] {
+            if let Ok(mut pipe) = pipe.lock() {
+                pipe.closed = true;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::thread;
+
+    #[test]
+    fn memory_stream() {
+        let (mut a, mut b) = MemoryStream::pair();
+        let mut buf = [0; 4];
+        assert_eq!(b.read(&mut buf).unwrap_err().kind(), io::ErrorKind::WouldBlock);
+        a.write_all(&[1, 2, 3]).unwrap();
+        a.write_all(&[4, 5]).unwrap();
+        assert_eq!(b.read(&mut buf).unwrap(), 4);
+        assert_eq!(buf, [1, 2, 3, 4]);
+        b.write_all(&[6]).unwrap();
+        assert_eq!(a.read(&mut buf).unwrap(), 1);
+        assert_eq!(buf[0], 6);
+
+        /* Data written before closing can still be read */
+        a.close().unwrap();
+        assert_eq!(b.write(&[7]).unwrap_err().kind(), io::ErrorKind::BrokenPipe);
+        assert_eq!(b.read(&mut buf).unwrap(), 1);
+        assert_eq!(buf[0], 5);
+        assert_eq!(b.read(&mut buf).unwrap(), 0);
+
+        let (a, mut b) = MemoryStream::pair();
+        drop(a);
+        assert_eq!(b.read(&mut buf).unwrap(), 0);
+    }
+
+    #[test]
+    fn memory_stream_latency() {
+        let latency = Duration::from_millis(30);
+        let (mut a, mut b) = MemoryStream::pair_with_latency(latency, Duration::from_millis(20));
+        let start = Instant::now();
+        let data: Vec<u8> = (0..100).collect();
+        for x in data.chunks(10) {
+            a.write_all(x).unwrap();
+        }
+
+        let mut received = Vec::new();
+        while received.len() < data.len() {
+            let mut buf = [0; 64];
+            match b.read(&mut buf) {
+                Ok(n) => {
+                    assert!(start.elapsed() >= latency);
+                    received.extend_from_slice(&buf[..n]);
+                },
+                Err(ref e) if e.kind() == io::ErrorKind::WouldBlock => {
+                    assert!(start.elapsed() < Duration::from_secs(5), "Timed out");
+                    thread::sleep(Duration::from_millis(1));
+                },
+                Err(e) => panic!("{}", e),
+            }
+        }
+        assert_eq!(received, data);
+    }
+}
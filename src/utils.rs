@@ -3,6 +3,7 @@
 use errors::{Result, ResultExt};
 
 use std::fmt::Write;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
 
 use openssl::hash::{self, MessageDigest};
 use openssl::rand;
@@ -43,6 +44,47 @@ fn chat_to_str_parse_json(json: &Value, ret: &mut String) {
     }
 }
 
+/// Convert a time given as milliseconds since the epoch, as in the Mojang API
+/// responses, to a SystemTime
+pub fn from_epoch_millis(millis: u64) -> SystemTime {
+    UNIX_EPOCH + Duration::from_millis(millis)
+}
+
+/// Convert a SystemTime to whole seconds since the epoch, as used in the
+/// Mojang API requests. Times before the epoch are negative.
+pub fn to_epoch_seconds(time: SystemTime) -> i64 {
+    match time.duration_since(UNIX_EPOCH) {
+        Ok(x) => x.as_secs() as i64,
+        Err(e) => -(e.duration().as_secs() as i64),
+    }
+}
+
+/// Whether the given string is a valid Minecraft username, i.e. 3 to 16
+/// characters, all of which are ASCII letters, digits or underscores
+pub fn is_valid_username(username: &str) -> bool {
+    validate_username(username).is_ok()
+}
+
+/// Check that the given string is a valid Minecraft username, returning an
+/// error describing the problem if it's not
+///
+/// Use this before sending a username to the Mojang APIs, an invalid one is
+/// certain to fail anyway and would only use up the rate limit.
+pub fn validate_username(username: &str) -> Result<()> {
+    let len = username.chars().count();
+    if !(3..=16).contains(&len) {
+        bail!("Invalid username {:?}, must be 3 to 16 characters long, not {}",
+              username,
+              len);
+    }
+    if let Some(c) = username.chars().find(|&c| !c.is_ascii_alphanumeric() && c != '_') {
+        bail!("Invalid username {:?}, {:?} is not allowed, only letters, digits and underscores",
+              username,
+              c);
+    }
+    Ok(())
+}
+
 /// Create a shared secret as used for protocol encryption
 ///
 /// # Panics
@@ -59,6 +101,13 @@ pub fn create_shared_secret() -> [u8; 16] {
     ret
 }
 
+/* A random duration of at most max, to spread out retries */
+pub(crate) fn jitter(max: Duration) -> Duration {
+    let mut bytes = [0; 4];
+    let _ = rand::rand_bytes(&mut bytes);
+    max.mul_f64(f64::from(u32::from_be_bytes(bytes)) / f64::from(u32::MAX))
+}
+
 /// Generate an RSA key as used by MC.
 ///
 /// Call rsa_key_binary to convert it to the format used in EncryptionRequest.
@@ -191,6 +240,38 @@ mod test {
                    "-da0143edc7918223fcc86951a195a5212c77c3f");
     }
 
+    #[test]
+    fn epoch_times() {
+        use std::time::{Duration, UNIX_EPOCH};
+        let time = super::from_epoch_millis(1_423_059_891_000);
+        assert_eq!(time, UNIX_EPOCH + Duration::from_secs(1_423_059_891));
+        assert_eq!(super::to_epoch_seconds(time), 1_423_059_891);
+        assert_eq!(super::to_epoch_seconds(UNIX_EPOCH - Duration::from_secs(5)), -5);
+    }
+
+    #[test]
+    fn username() {
+        assert!(super::is_valid_username("Notch"));
+        assert!(super::is_valid_username("jeb_"));
+        assert!(super::is_valid_username("a_1_B_2_c_3_D_4_"));
+        assert!(!super::is_valid_username("ab"));
+        assert!(!super::is_valid_username("a_1_B_2_c_3_D_4_e"));
+        assert!(!super::is_valid_username("Not ch"));
+        assert!(!super::is_valid_username("Nötch"));
+        assert!(super::validate_username("bob-").is_err());
+    }
+
+    #[test]
+    fn vanilla_sha1() {
+        /* The server hash examples given for the vanilla implementation */
+        assert_eq!(super::sha1("Notch".as_bytes()),
+                   "4ed1f46bbe04bc756bcb17c0c7ce3e4632f06a48");
+        assert_eq!(super::sha1("jeb_".as_bytes()),
+                   "-7c9d5b0044c130109a5d7b5fb5c317c02b4e28c1");
+        assert_eq!(super::sha1("simon".as_bytes()),
+                   "88e16a1019277b15d58faf0541e11910eb756f6");
+    }
+
     #[test]
     fn rsa() {
         use openssl::rsa::Rsa;
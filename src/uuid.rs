@@ -0,0 +1,170 @@
+//! UUID parsing, formatting and comparison
+//!
+//! The Mojang APIs and the protocol use a mix of UUID formats: with and
+//! without hyphens, upper and lower case, and as raw u128s in packets.
+//! Comparing them as strings is a common source of bugs, so parse them into
+//! a Uuid instead.
+
+use errors::{Error, Result, ResultExt};
+use json::{NameUUID, Profile, SessionHasJoinedResponse};
+
+use openssl::hash::{self, MessageDigest};
+
+use std::fmt;
+use std::str::FromStr;
+
+/// A UUID, as used for player and entity IDs
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Default)]
+pub struct Uuid(pub u128);
+impl Uuid {
+    /// Get the UUID of a player in offline mode.
+    ///
+    /// Offline mode servers derive the UUID from the username, as a version
+    /// 3 (name-based, MD5) UUID of "OfflinePlayer:<username>".
+    pub fn offline(username: &str) -> Result<Self> {
+        let name = format!("OfflinePlayer:{}", username);
+        let digest = hash::hash(MessageDigest::md5(), name.as_bytes())?;
+        let mut bytes = [0; 16];
+        bytes.copy_from_slice(&digest);
+        bytes[6] = (bytes[6] & 0x0f) | 0x30;
+        bytes[8] = (bytes[8] & 0x3f) | 0x80;
+        Ok(Uuid(u128::from_be_bytes(bytes)))
+    }
+
+    /// Get the UUID as a u128, as used in packets
+    pub fn as_u128(&self) -> u128 {
+        self.0
+    }
+
+    /// Get the UUID version, e.g. 4 for online mode players and 3 for offline
+    /// mode players
+    pub fn version(&self) -> u8 {
+        ((self.0 >> 76) & 0xf) as u8
+    }
+
+    /// Format the UUID without hyphens, as used by the Mojang APIs
+    pub fn to_simple(&self) -> String {
+        format!("{:032x}", self.0)
+    }
+}
+impl From<u128> for Uuid {
+    fn from(x: u128) -> Self {
+        Uuid(x)
+    }
+}
+impl From<Uuid> for u128 {
+    fn from(x: Uuid) -> Self {
+        x.0
+    }
+}
+impl FromStr for Uuid {
+    type Err = Error;
+
+    /// Parse a UUID with or without hyphens, in any case
+    fn from_str(s: &str) -> Result<Self> {
+        let hex: String = s.chars().filter(|&c| c != '-').collect();
+        /* from_str_radix would accept a sign too */
+        if hex.len() != 32 || !hex.chars().all(|c| c.is_ascii_hexdigit()) {
+            bail!("Invalid UUID {:?}, expected 32 hex digits", s);
+        }
+        let hyphens: Vec<usize> = s.match_indices('-').map(|(i, _)| i).collect();
+        if !hyphens.is_empty() && hyphens != [8, 13, 18, 23] {
+            bail!("Invalid UUID {:?}, hyphens in the wrong places", s);
+        }
+        let x = u128::from_str_radix(&hex, 16)
+            .chain_err(|| format!("Invalid UUID {:?}, hex string could not be parsed", s))?;
+        Ok(Uuid(x))
+    }
+}
+impl fmt::Display for Uuid {
+    /// Format the UUID with hyphens, in lower case
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let s = self.to_simple();
+        write!(f,
+               "{}-{}-{}-{}-{}",
+               &s[0..8],
+               &s[8..12],
+               &s[12..16],
+               &s[16..20],
+               &s[20..32])
+    }
+}
+
+/// Canonicalize a UUID string, i.e. to lower case with hyphens
+pub fn canonicalize(uuid: &str) -> Result<String> {
+    Ok(uuid.parse::<Uuid>()?.to_string())
+}
+
+/// Whether the two strings are the same UUID, regardless of case and
+/// hyphens. Returns false if either isn't a valid UUID.
+pub fn same(a: &str, b: &str) -> bool {
+    match (a.parse::<Uuid>(), b.parse::<Uuid>()) {
+        (Ok(a), Ok(b)) => a == b,
+        _ => false,
+    }
+}
+
+impl NameUUID {
+    /// Parse the id of this profile
+    pub fn uuid(&self) -> Result<Uuid> {
+        self.id.parse()
+    }
+
+    /// Whether this is the same player as the other, comparing by UUID (names
+    /// can change)
+    pub fn same_player(&self, other: &NameUUID) -> bool {
+        same(&self.id, &other.id)
+    }
+}
+
+impl SessionHasJoinedResponse {
+    /// Parse the id of the player
+    pub fn uuid(&self) -> Result<Uuid> {
+        self.id.parse()
+    }
+}
+
+impl Profile {
+    /// Parse the id of this profile
+    pub fn uuid(&self) -> Result<Uuid> {
+        self.id.parse()
+    }
+
+    /// Whether this is the same player as the other, comparing by UUID (names
+    /// can change)
+    pub fn same_player(&self, other: &Profile) -> bool {
+        same(&self.id, &other.id)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn parse() {
+        let a: Uuid = "069a79f444e94726a5befca90e38aaf5".parse().unwrap();
+        let b: Uuid = "069A79F4-44E9-4726-A5BE-FCA90E38AAF5".parse().unwrap();
+        assert_eq!(a, b);
+        assert_eq!(a.to_string(), "069a79f4-44e9-4726-a5be-fca90e38aaf5");
+        assert_eq!(a.to_simple(), "069a79f444e94726a5befca90e38aaf5");
+        assert_eq!(a.version(), 4);
+
+        assert!("069a79f4-44e94726a5befca90e38aaf5".parse::<Uuid>().is_err());
+        assert!("069a79f444e94726a5befca90e38aaf".parse::<Uuid>().is_err());
+        assert!("069a79f444e94726a5befca90e38aafg".parse::<Uuid>().is_err());
+        assert!("+69a79f444e94726a5befca90e38aaf5".parse::<Uuid>().is_err());
+        assert!("+69a79f4-44e9-4726-a5be-fca90e38aaf5".parse::<Uuid>().is_err());
+
+        assert!(same("069a79f444e94726a5befca90e38aaf5",
+                     "069a79f4-44e9-4726-a5be-fca90e38aaf5"));
+        assert!(!same("069a79f444e94726a5befca90e38aaf5", "Notch"));
+    }
+
+    #[test]
+    fn offline() {
+        let uuid = Uuid::offline("Notch").unwrap();
+        assert_eq!(uuid.to_string(), "b50ad385-829d-3141-a216-7e7d7539ba7f");
+        assert_eq!(uuid.version(), 3);
+    }
+}
@@ -0,0 +1,175 @@
+//! Riding and steering boats, minecarts, horses and other vehicles
+//!
+//! A player mounts a vehicle by interacting with it, and the server then
+//! lists the player among the vehicle's passengers in a SetPassengers
+//! packet. While riding, the player steers with SteerVehicle (and the
+//! paddles of boats with SteerBoat), and for vehicles the client controls,
+//! i.e. boats and saddled horses and pigs, also sends the vehicle's position
+//! with VehicleMove. The server corrects it with its own VehicleMove.
+//!
+//! The functions here build those packets, and the Client uses Riding to
+//! keep track of what the player is riding, see client.vehicle(),
+//! client.mount() and client.steer().
+
+use block::Hand;
+use clientbound::ClientboundPacket;
+use entity::Location;
+use serverbound::{self, ServerboundPacket};
+
+/// The vehicle the player is riding
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Vehicle {
+    pub entity_id: i32,
+    /// The location of the vehicle, if sent by the server or set with
+    /// client.move_vehicle()
+    pub location: Option<Location>,
+}
+
+/// The player mounting or dismounting a vehicle, see ClientEvent::Vehicle
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum VehicleChange {
+    /// The player started riding the vehicle with the given entity ID
+    Mounted(i32),
+    /// The player stopped riding the vehicle with the given entity ID
+    Dismounted(i32),
+}
+
+/// Keeps track of the vehicle the player is riding
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct Riding {
+    vehicle: Option<Vehicle>,
+}
+impl Riding {
+    /// The vehicle, or None if the player isn't riding anything
+    pub fn vehicle(&self) -> Option<&Vehicle> {
+        self.vehicle.as_ref()
+    }
+
+    /// Set the location of the vehicle, as sent with a VehicleMove
+    pub fn set_location(&mut self, location: Location) {
+        if let Some(ref mut vehicle) = self.vehicle {
+            vehicle.location = Some(location);
+        }
+    }
+
+    /// Forget the vehicle, e.g. when respawning
+    pub fn clear(&mut self) -> Option<VehicleChange> {
+        self.vehicle
+            .take()
+            .map(|x| VehicleChange::Dismounted(x.entity_id))
+    }
+
+    /* Handle a packet about the player with the given entity ID */
+    pub(crate) fn handle(&mut self,
+                         packet: &ClientboundPacket,
+                         player: i32)
+                         -> Option<VehicleChange> {
+        let current = self.vehicle.map(|x| x.entity_id);
+        match *packet {
+            ClientboundPacket::SetPassengers(ref p) => {
+                let vehicle = *p.get_entity_id();
+                let riding = p.get_passengers().contains(&player);
+                if riding && current != Some(vehicle) {
+                    self.vehicle = Some(Vehicle {
+                                            entity_id: vehicle,
+                                            location: None,
+                                        });
+                    Some(VehicleChange::Mounted(vehicle))
+                } else if !riding && current == Some(vehicle) {
+                    self.clear()
+                } else {
+                    None
+                }
+            },
+            ClientboundPacket::DestroyEntities(ref p) => {
+                if current.is_some_and(|x| p.get_entity_ids().contains(&x)) {
+                    self.clear()
+                } else {
+                    None
+                }
+            },
+            ClientboundPacket::ClientboundVehicleMove(ref p) => {
+                self.set_location(Location {
+                                      x: *p.get_x(),
+                                      y: *p.get_y(),
+                                      z: *p.get_z(),
+                                      yaw: *p.get_yaw(),
+                                      pitch: *p.get_pitch(),
+                                      on_ground: false,
+                                  });
+                None
+            },
+            _ => None,
+        }
+    }
+}
+
+/// Interact with the entity to mount it
+pub fn mount(entity_id: i32, hand: Hand) -> ServerboundPacket {
+    serverbound::UseEntity::new_raw(entity_id, 0, None, Some(hand.to_id()))
+}
+
+/// Steer the vehicle. Forward and sideways are from -0.98 to 0.98 like
+/// vanilla, positive being forward and left. Jump makes horses jump, and
+/// unmount dismounts.
+pub fn steer(forward: f32, sideways: f32, jump: bool, unmount: bool) -> ServerboundPacket {
+    let forward = forward.clamp(-0.98, 0.98);
+    let sideways = sideways.clamp(-0.98, 0.98);
+    let flags = (jump as u8) | (unmount as u8) << 1;
+    serverbound::SteerVehicle::new(sideways, forward, flags)
+}
+
+/// Dismount the vehicle
+pub fn dismount() -> ServerboundPacket {
+    steer(0.0, 0.0, false, true)
+}
+
+/// Turn the paddles of a boat, which is only for the animation; boats are
+/// moved with VehicleMove
+pub fn steer_boat(left: bool, right: bool) -> ServerboundPacket {
+    serverbound::SteerBoat::new(right, left)
+}
+
+/// Move the vehicle, for vehicles the client controls
+pub fn move_vehicle(location: &Location) -> ServerboundPacket {
+    serverbound::VehicleMove::new(location.x,
+                                  location.y,
+                                  location.z,
+                                  location.yaw,
+                                  location.pitch)
+}
+
+/// Make the ridden horse jump, with a strength from 0 to 100
+pub fn horse_jump(player: i32, strength: i32) -> ServerboundPacket {
+    serverbound::EntityAction::new(player, 5, strength.clamp(0, 100))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use clientbound::{ClientboundVehicleMove, DestroyEntities, SetPassengers};
+
+    #[test]
+    fn riding() {
+        let mut riding = Riding::default();
+        assert_eq!(riding.handle(&SetPassengers::new(10, vec![2]), 1), None);
+        assert_eq!(riding.handle(&SetPassengers::new(10, vec![2, 1]), 1),
+                   Some(VehicleChange::Mounted(10)));
+        assert_eq!(riding.handle(&SetPassengers::new(10, vec![1]), 1), None);
+
+        let _ = riding.handle(&ClientboundVehicleMove::new(1.0, 2.0, 3.0, 90.0, 0.0), 1);
+        let vehicle = riding.vehicle().unwrap();
+        assert_eq!(vehicle.entity_id, 10);
+        assert_eq!(vehicle.location.map(|x| (x.x, x.yaw)), Some((1.0, 90.0)));
+
+        assert_eq!(riding.handle(&SetPassengers::new(10, vec![]), 1),
+                   Some(VehicleChange::Dismounted(10)));
+        let _ = riding.handle(&SetPassengers::new(11, vec![1]), 1);
+        assert_eq!(riding.handle(&DestroyEntities::new(vec![11]), 1),
+                   Some(VehicleChange::Dismounted(11)));
+        assert!(riding.vehicle().is_none());
+
+        assert_eq!(steer(2.0, -0.5, true, true),
+                   serverbound::SteerVehicle::new(-0.5, 0.98, 3));
+    }
+}
@@ -0,0 +1,186 @@
+//! Virtual host routing of incoming connections
+//!
+//! Clients send the hostname they connected to in the Handshake, so one
+//! listener can serve several hostnames, e.g. lobby.example.com and
+//! survival.example.com, by dispatching connections to different backends
+//! or worlds with a Router.
+//!
+//! The host field may have extensions appended, separated by null
+//! characters: BungeeCord's IP forwarding appends the client's address, UUID
+//! and properties, and Forge clients append a marker such as "FML2". These
+//! are split off by VirtualHost::parse().
+//!
+//! # Examples
+//!
+//! ```rust,no_run
+//! use ozelot::Server;
+//! use ozelot::vhost::Router;
+//! use std::net::TcpListener;
+//! use std::time::Duration;
+//!
+//! let mut router = Router::new();
+//! router.route("lobby.example.com", "lobby");
+//! router.route("*.example.com", "survival");
+//! router.set_default("lobby");
+//!
+//! let listener = TcpListener::bind("0.0.0.0:25565").unwrap();
+//! for stream in listener.incoming() {
+//!     let mut server = Server::from_tcpstream(stream.unwrap()).unwrap();
+//!     match router.dispatch(&mut server, Duration::from_secs(5)) {
+//!         Ok(Some(backend)) => println!("Sending to {}", backend),
+//!         _ => { let _ = server.close(); },
+//!     }
+//! }
+//! ```
+
+use {ClientState, Server};
+use errors::Result;
+use serverbound::ServerboundPacket;
+
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// The host declared by a client in its Handshake
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct VirtualHost {
+    /// The hostname, in lower case and without a trailing dot
+    pub hostname: String,
+    pub port: u16,
+    /// The Forge marker, e.g. "FML" or "FML2", if the client is modded
+    pub fml: Option<String>,
+    /// Any other null separated extensions, e.g. the forwarded address,
+    /// UUID and properties from BungeeCord
+    pub extensions: Vec<String>,
+    /// The host field as sent by the client
+    pub raw: String,
+}
+impl VirtualHost {
+    /// Parse the host field of a Handshake
+    pub fn parse(host: &str, port: u16) -> Self {
+        let mut parts = host.split('\0');
+        let hostname = parts.next()
+            .unwrap_or_default()
+            .trim_end_matches('.')
+            .to_lowercase();
+        let mut fml = None;
+        let mut extensions = Vec::new();
+        for part in parts.filter(|x| !x.is_empty()) {
+            if fml.is_none() && part.starts_with("FML") {
+                fml = Some(part.to_string());
+            } else {
+                extensions.push(part.to_string());
+            }
+        }
+        VirtualHost {
+            hostname,
+            port,
+            fml,
+            extensions,
+            raw: host.to_string(),
+        }
+    }
+}
+
+/// Maps hostnames to backends, see the module documentation
+#[derive(Debug, Clone)]
+pub struct Router<T> {
+    routes: Vec<(String, T)>,
+    default: Option<T>,
+}
+impl<T> Router<T> {
+    pub fn new() -> Self {
+        Router {
+            routes: Vec::new(),
+            default: None,
+        }
+    }
+
+    /// Route the given hostname to the given backend. The hostname may start
+    /// with "*." to match any subdomain. Routes are tried in the order they
+    /// were added.
+    pub fn route(&mut self, hostname: &str, backend: T) {
+        self.routes.push((hostname.to_lowercase(), backend));
+    }
+
+    /// Set the backend of hostnames not matching any route
+    pub fn set_default(&mut self, backend: T) {
+        self.default = Some(backend);
+    }
+
+    /// Get the backend for the given host, if any
+    pub fn resolve(&self, host: &VirtualHost) -> Option<&T> {
+        self.routes
+            .iter()
+            .find(|x| matches(&x.0, &host.hostname))
+            .map(|x| &x.1)
+            .or(self.default.as_ref())
+    }
+
+    /// Read the Handshake of a newly accepted connection with
+    /// read_handshake(), and get the backend for the requested host
+    pub fn dispatch(&self, server: &mut Server, timeout: Duration) -> Result<Option<&T>> {
+        let host = read_handshake(server, timeout)?;
+        Ok(self.resolve(&host))
+    }
+}
+impl<T> Default for Router<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Wait at most timeout for the Handshake of a newly accepted connection,
+/// and switch to the state it requests. Returns the requested host.
+pub fn read_handshake(server: &mut Server, timeout: Duration) -> Result<VirtualHost> {
+    let start = Instant::now();
+    loop {
+        if start.elapsed() > timeout {
+            bail!("Timed out waiting for the Handshake");
+        }
+        server.update_inbuf()?;
+        match server.read_packet()? {
+            Some(ServerboundPacket::Handshake(ref p)) => {
+                let state = match *p.get_next_state() {
+                    1 => ClientState::Status,
+                    2 => ClientState::Login,
+                    x => bail!("Invalid next state in the Handshake: {}", x),
+                };
+                server.set_clientstate(state);
+                return Ok(VirtualHost::parse(p.get_server_address(), *p.get_server_port()));
+            },
+            Some(_) => bail!("Expected a Handshake"),
+            None => thread::sleep(Duration::from_millis(10)),
+        }
+    }
+}
+
+fn matches(pattern: &str, hostname: &str) -> bool {
+    if pattern.starts_with("*.") {
+        hostname.ends_with(&pattern[1..])
+    } else {
+        pattern == hostname
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn routing() {
+        let host = VirtualHost::parse("Play.Example.com.\0FML2\0", 25565);
+        assert_eq!(host.hostname, "play.example.com");
+        assert_eq!(host.fml, Some("FML2".to_string()));
+        let bungee = VirtualHost::parse("lobby.example.com\x00127.0.0.1\x00abcd\x00[]", 25565);
+        assert_eq!(bungee.extensions, vec!["127.0.0.1", "abcd", "[]"]);
+
+        let mut router = Router::new();
+        router.route("lobby.example.com", 1);
+        router.route("*.example.com", 2);
+        assert_eq!(router.resolve(&bungee), Some(&1));
+        assert_eq!(router.resolve(&host), Some(&2));
+        assert_eq!(router.resolve(&VirtualHost::parse("example.org", 25565)), None);
+        router.set_default(3);
+        assert_eq!(router.resolve(&VirtualHost::parse("example.org", 25565)), Some(&3));
+    }
+}
@@ -0,0 +1,175 @@
+//! Connection health watchdog for clients
+//!
+//! The vanilla server disconnects clients it hasn't heard from in 30 seconds,
+//! and the server sends a KeepAlive at least every 15 seconds. So if the
+//! client hasn't received anything for a while, the connection has most
+//! likely stalled. The Watchdog notices that, and can also keep the player
+//! from being kicked for idling by sending harmless actions now and then.
+//!
+//! Enable it with client.set_watchdog().
+
+use serverbound::ServerboundPacket;
+
+use std::time::{Duration, Instant};
+
+/// Harmless actions for keeping the player from idling
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AntiIdleAction {
+    /// Swing the main arm
+    SwingArm,
+    /// Turn the head back and forth slightly (only when the position is
+    /// known)
+    Rotate,
+}
+
+/// Watchdog configuration
+#[derive(Debug, Clone, PartialEq)]
+pub struct WatchdogOptions {
+    /// How long without receiving any packets until the connection is
+    /// considered unhealthy. Default 20 seconds.
+    pub stall_timeout: Duration,
+    /// How long without sending any player action until an anti-idle action
+    /// is performed, if any. Default None.
+    ///
+    /// Packets the client sends by itself, e.g. KeepAlive replies, aren't
+    /// player actions, see is_player_action().
+    pub anti_idle_interval: Option<Duration>,
+    /// The anti-idle actions to perform. Default arm swing.
+    pub anti_idle_actions: Vec<AntiIdleAction>,
+}
+impl Default for WatchdogOptions {
+    fn default() -> Self {
+        WatchdogOptions {
+            stall_timeout: Duration::from_secs(20),
+            anti_idle_interval: None,
+            anti_idle_actions: vec![AntiIdleAction::SwingArm],
+        }
+    }
+}
+
+/// What the watchdog wants done, see Watchdog::check
+#[derive(Debug, Clone, PartialEq)]
+pub enum WatchdogAction {
+    /// Nothing received for the given duration, reported once per stall
+    Unhealthy(Duration),
+    /// Perform the given anti-idle actions
+    AntiIdle(Vec<AntiIdleAction>),
+}
+
+/// Keeps track of connection activity, see the module documentation
+#[derive(Debug, Clone)]
+pub struct Watchdog {
+    options: WatchdogOptions,
+    last_received: Instant,
+    last_sent: Instant,
+    stalled: bool,
+}
+impl Watchdog {
+    /// Create a new watchdog, counting from now
+    pub fn new(options: WatchdogOptions) -> Self {
+        let now = Instant::now();
+        Watchdog {
+            options,
+            last_received: now,
+            last_sent: now,
+            stalled: false,
+        }
+    }
+
+    /// Get the options
+    pub fn options(&self) -> &WatchdogOptions {
+        &self.options
+    }
+
+    /// Record that a packet was received
+    pub fn received(&mut self, now: Instant) {
+        self.last_received = now;
+        self.stalled = false;
+    }
+
+    /// Record that a player action was sent, see is_player_action()
+    pub fn sent(&mut self, now: Instant) {
+        self.last_sent = now;
+    }
+
+    /// Get how long it's been since the last packet was received
+    pub fn since_received(&self, now: Instant) -> Duration {
+        now.saturating_duration_since(self.last_received)
+    }
+
+    /// Check the connection, returning what should be done
+    pub fn check(&mut self, now: Instant) -> Vec<WatchdogAction> {
+        let mut ret = Vec::new();
+        let silent = self.since_received(now);
+        if !self.stalled && silent >= self.options.stall_timeout {
+            self.stalled = true;
+            ret.push(WatchdogAction::Unhealthy(silent));
+        }
+        if let Some(interval) = self.options.anti_idle_interval {
+            if now.saturating_duration_since(self.last_sent) >= interval &&
+               !self.options.anti_idle_actions.is_empty() {
+                self.last_sent = now;
+                let actions = self.options.anti_idle_actions.clone();
+                ret.push(WatchdogAction::AntiIdle(actions));
+            }
+        }
+        ret
+    }
+}
+
+/// Whether the packet is an action of the player, which keeps the server
+/// from kicking the player for idling, as opposed to e.g. a KeepAlive or
+/// TeleportConfirm reply
+pub fn is_player_action(packet: &ServerboundPacket) -> bool {
+    matches!(*packet,
+             ServerboundPacket::ChatMessage(..) |
+             ServerboundPacket::ClickWindow(..) |
+             ServerboundPacket::EnchantItem(..) |
+             ServerboundPacket::UseEntity(..) |
+             ServerboundPacket::Player(..) |
+             ServerboundPacket::PlayerPosition(..) |
+             ServerboundPacket::PlayerPositionAndLook(..) |
+             ServerboundPacket::PlayerLook(..) |
+             ServerboundPacket::VehicleMove(..) |
+             ServerboundPacket::SteerBoat(..) |
+             ServerboundPacket::PickItem(..) |
+             ServerboundPacket::CraftRecipeRequest(..) |
+             ServerboundPacket::PlayerDigging(..) |
+             ServerboundPacket::EntityAction(..) |
+             ServerboundPacket::SteerVehicle(..) |
+             ServerboundPacket::HeldItemChange(..) |
+             ServerboundPacket::CreativeInventoryAction(..) |
+             ServerboundPacket::Animation(..) |
+             ServerboundPacket::PlayerBlockPlacement(..) |
+             ServerboundPacket::UseItem(..))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn watchdog() {
+        let secs = Duration::from_secs;
+        let mut w = Watchdog::new(WatchdogOptions {
+                                      anti_idle_interval: Some(secs(60)),
+                                      ..WatchdogOptions::default()
+                                  });
+        let start = w.last_received;
+
+        assert_eq!(w.check(start + secs(10)), vec![]);
+        assert_eq!(w.check(start + secs(21)),
+                   vec![WatchdogAction::Unhealthy(secs(21))]);
+        /* Only reported once per stall */
+        assert_eq!(w.check(start + secs(25)), vec![]);
+        w.received(start + secs(26));
+        w.sent(start + secs(26));
+        assert_eq!(w.check(start + secs(50)),
+                   vec![WatchdogAction::Unhealthy(secs(24))]);
+
+        w.received(start + secs(80));
+        assert_eq!(w.check(start + secs(86)),
+                   vec![WatchdogAction::AntiIdle(vec![AntiIdleAction::SwingArm])]);
+        assert_eq!(w.check(start + secs(90)), vec![]);
+    }
+}
@@ -0,0 +1,261 @@
+//! Inventory window click transactions
+//!
+//! Every ClickWindow packet carries an action number which the server
+//! answers with a ConfirmTransaction packet, telling whether the click was
+//! accepted. If it wasn't, the client must "apologize" by sending the
+//! ConfirmTransaction back, or the server ignores all further clicks in that
+//! window.
+//!
+//! WindowTransactions handles all of that. It only has one click in flight
+//! at a time and queues the rest, so that clicks from different parts of a
+//! bot can't interleave and act on an inventory state that's already been
+//! rejected. Rejected clicks are retried a configurable amount of times.
+
+use clientbound::ClientboundPacket;
+use serverbound::{self, ServerboundPacket};
+
+use std::collections::VecDeque;
+use std::mem;
+
+/// A click in an inventory window, see the ClickWindow packet
+#[derive(Debug, Clone, PartialEq)]
+pub struct Click {
+    pub window_id: u8,
+    pub slot: i16,
+    pub button: i8,
+    pub mode: i32,
+    /// The raw slot data of the clicked item
+    pub clicked_item: Vec<u8>,
+}
+impl Click {
+    fn to_packet(&self, action: i16) -> ServerboundPacket {
+        serverbound::ClickWindow::new(self.window_id,
+                                      self.slot,
+                                      self.button,
+                                      action,
+                                      self.mode,
+                                      self.clicked_item.clone())
+    }
+}
+
+/// The outcome of a click
+#[derive(Debug, Clone, PartialEq)]
+pub enum WindowEvent {
+    /// The server accepted the click with the given action number
+    Accepted(Click, i16),
+    /// The server rejected the click, and it was sent again with the given
+    /// new action number
+    Retried(Click, i16),
+    /// The server rejected the click, and it was given up on. Any clicks
+    /// queued for the same window are dropped as well.
+    Rejected(Click),
+    /// The window was closed before the click was confirmed
+    Cancelled(Click),
+}
+
+/// Tracks window click transactions, see the module documentation
+#[derive(Debug, Clone)]
+pub struct WindowTransactions {
+    next_action: i16,
+    max_retries: u32,
+    /* The click awaiting confirmation, its action number and retry count */
+    pending: Option<(Click, i16, u32)>,
+    queue: VecDeque<Click>,
+    events: Vec<WindowEvent>,
+}
+impl Default for WindowTransactions {
+    fn default() -> Self {
+        WindowTransactions::new(1)
+    }
+}
+impl WindowTransactions {
+    /// Create a new transaction tracker, retrying rejected clicks at most
+    /// max_retries times.
+    pub fn new(max_retries: u32) -> Self {
+        WindowTransactions {
+            next_action: 1,
+            max_retries,
+            pending: None,
+            queue: VecDeque::new(),
+            events: Vec::new(),
+        }
+    }
+
+    /// Set how many times rejected clicks are retried, including the clicks
+    /// already sent or queued
+    pub fn set_max_retries(&mut self, max_retries: u32) {
+        self.max_retries = max_retries;
+    }
+
+    /// Whether there are no clicks waiting to be confirmed or sent
+    pub fn is_idle(&self) -> bool {
+        self.pending.is_none() && self.queue.is_empty()
+    }
+
+    /// Queue a click. Returns the packets that should be sent now, which is
+    /// nothing if another click is still awaiting confirmation.
+    pub fn click(&mut self, click: Click) -> Vec<ServerboundPacket> {
+        self.queue.push_back(click);
+        self.send_next()
+    }
+
+    /// Handle a packet from the server, returning the packets that should
+    /// be sent in response.
+    pub fn handle(&mut self, packet: &ClientboundPacket) -> Vec<ServerboundPacket> {
+        match *packet {
+            ClientboundPacket::ClientboundConfirmTransaction(ref p) => {
+                self.confirm(*p.get_window_id(),
+                             *p.get_action_id(),
+                             *p.get_accepted())
+            },
+            ClientboundPacket::ClientboundCloseWindow(ref p) => {
+                self.close(*p.get_window_id())
+            },
+            _ => Vec::new(),
+        }
+    }
+
+    /// Get all the events that have happened since the last call to this
+    /// function.
+    pub fn poll_events(&mut self) -> Vec<WindowEvent> {
+        mem::take(&mut self.events)
+    }
+
+    fn confirm(&mut self, window_id: u8, action: i16, accepted: bool) -> Vec<ServerboundPacket> {
+        let mut ret = Vec::new();
+        if !accepted {
+            /* The apology, without which the server won't accept any more
+             * clicks in this window */
+            ret.push(serverbound::ConfirmTransaction::new(window_id,
+                                                          action,
+                                                          true));
+        }
+
+        let matches = match self.pending {
+            Some((ref click, id, _)) => click.window_id == window_id && id == action,
+            None => false,
+        };
+        if !matches {
+            return ret;
+        }
+        let (click, _, retries) = self.pending.take().unwrap();
+
+        if accepted {
+            self.events.push(WindowEvent::Accepted(click, action));
+        } else if retries < self.max_retries {
+            let action = self.allocate_action();
+            ret.push(click.to_packet(action));
+            self.events.push(WindowEvent::Retried(click.clone(), action));
+            self.pending = Some((click, action, retries + 1));
+            return ret;
+        } else {
+            self.queue.retain(|c| c.window_id != window_id);
+            self.events.push(WindowEvent::Rejected(click));
+        }
+        ret.extend(self.send_next());
+        ret
+    }
+
+    fn close(&mut self, window_id: u8) -> Vec<ServerboundPacket> {
+        let cancelled = match self.pending {
+            Some((ref click, _, _)) => click.window_id == window_id,
+            None => false,
+        };
+        if cancelled {
+            let (click, _, _) = self.pending.take().unwrap();
+            self.events.push(WindowEvent::Cancelled(click));
+        }
+        let (closed, queue): (VecDeque<Click>, VecDeque<Click>) =
+            self.queue.drain(..).partition(|c| c.window_id == window_id);
+        self.queue = queue;
+        self.events.extend(closed.into_iter().map(WindowEvent::Cancelled));
+        self.send_next()
+    }
+
+    fn send_next(&mut self) -> Vec<ServerboundPacket> {
+        if self.pending.is_some() {
+            return Vec::new();
+        }
+        match self.queue.pop_front() {
+            Some(click) => {
+                let action = self.allocate_action();
+                let packet = click.to_packet(action);
+                self.pending = Some((click, action, 0));
+                vec![packet]
+            },
+            None => Vec::new(),
+        }
+    }
+
+    fn allocate_action(&mut self) -> i16 {
+        let action = self.next_action;
+        self.next_action = match self.next_action.wrapping_add(1) {
+            0 => 1,
+            x => x,
+        };
+        action
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use clientbound::{ClientboundCloseWindow, ClientboundConfirmTransaction};
+
+    fn click(window_id: u8, slot: i16) -> Click {
+        Click {
+            window_id,
+            slot,
+            button: 0,
+            mode: 0,
+            clicked_item: vec![0xff, 0xff],
+        }
+    }
+
+    #[test]
+    fn transactions() {
+        let mut t = WindowTransactions::new(1);
+        assert_eq!(t.click(click(1, 10)), vec![click(1, 10).to_packet(1)]);
+        /* Queued, since the first one hasn't been confirmed yet */
+        assert_eq!(t.click(click(1, 11)), vec![]);
+
+        let accept = ClientboundConfirmTransaction::new(1, 1, true);
+        assert_eq!(t.handle(&accept), vec![click(1, 11).to_packet(2)]);
+        assert_eq!(t.poll_events(),
+                   vec![WindowEvent::Accepted(click(1, 10), 1)]);
+
+        let reject = ClientboundConfirmTransaction::new(1, 2, false);
+        assert_eq!(t.handle(&reject),
+                   vec![serverbound::ConfirmTransaction::new(1, 2, true),
+                        click(1, 11).to_packet(3)]);
+        let reject = ClientboundConfirmTransaction::new(1, 3, false);
+        assert_eq!(t.handle(&reject),
+                   vec![serverbound::ConfirmTransaction::new(1, 3, true)]);
+        assert_eq!(t.poll_events(),
+                   vec![WindowEvent::Retried(click(1, 11), 3),
+                        WindowEvent::Rejected(click(1, 11))]);
+        assert!(t.is_idle());
+
+        let _ = t.click(click(2, 0));
+        let _ = t.click(click(2, 1));
+        assert_eq!(t.handle(&ClientboundCloseWindow::new(2)), vec![]);
+        assert_eq!(t.poll_events(),
+                   vec![WindowEvent::Cancelled(click(2, 0)),
+                        WindowEvent::Cancelled(click(2, 1))]);
+        assert!(t.is_idle());
+
+        /* Changing the retries keeps the clicks in flight */
+        let _ = t.click(click(3, 0));
+        let _ = t.click(click(3, 1));
+        t.set_max_retries(0);
+        assert!(!t.is_idle());
+        let accept = ClientboundConfirmTransaction::new(3, 5, true);
+        assert_eq!(t.handle(&accept), vec![click(3, 1).to_packet(6)]);
+        let reject = ClientboundConfirmTransaction::new(3, 6, false);
+        assert_eq!(t.handle(&reject),
+                   vec![serverbound::ConfirmTransaction::new(3, 6, true)]);
+        assert_eq!(t.poll_events(),
+                   vec![WindowEvent::Accepted(click(3, 0), 5),
+                        WindowEvent::Rejected(click(3, 1))]);
+    }
+}
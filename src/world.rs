@@ -0,0 +1,281 @@
+//! Tracking the time, weather and world border of the world
+//!
+//! The server sends the time of day in TimeUpdate packets about once a
+//! second, the weather in ChangeGameState packets and the world border in
+//! WorldBorder packets. WorldState keeps the latest values of all of them,
+//! and is updated by the Client when auto_handle is true, see
+//! client.world(), client.time_of_day() and client.is_raining().
+//!
+//! The time of day is in ticks, 0 being sunrise, 6000 noon, 12000 sunset
+//! and 18000 midnight, and counts up forever, so use time_of_day() % 24000
+//! for the time within the current day.
+
+use clientbound::ClientboundPacket;
+use errors::Result;
+use read::{read_f64, read_varint, read_varlong};
+
+use std::io::Cursor;
+use std::time::{Duration, Instant};
+
+/// The length of a Minecraft day in ticks
+pub const DAY_LENGTH: i64 = 24000;
+
+/// The world border, see the WorldBorder packet
+#[derive(Debug, Clone, PartialEq)]
+pub struct WorldBorder {
+    pub center_x: f64,
+    pub center_z: f64,
+    /// The diameter when the border started moving
+    pub old_diameter: f64,
+    /// The diameter the border is moving towards, or its diameter if it's
+    /// not moving
+    pub new_diameter: f64,
+    /// How long the border takes to move from old_diameter to new_diameter
+    pub speed: Duration,
+    /// When the border started moving
+    pub since: Instant,
+    /// The distance from the origin portals may teleport to, usually
+    /// 29999984
+    pub portal_teleport_boundary: i32,
+    /// How many seconds before the border reaches a player the warning
+    /// starts
+    pub warning_time: i32,
+    /// How many blocks from the border the warning starts
+    pub warning_blocks: i32,
+}
+impl Default for WorldBorder {
+    fn default() -> Self {
+        WorldBorder {
+            center_x: 0.0,
+            center_z: 0.0,
+            old_diameter: 60000000.0,
+            new_diameter: 60000000.0,
+            speed: Duration::from_secs(0),
+            since: Instant::now(),
+            portal_teleport_boundary: 29999984,
+            warning_time: 15,
+            warning_blocks: 5,
+        }
+    }
+}
+impl WorldBorder {
+    /// The diameter at the given moment, taking a moving border into account
+    pub fn diameter_at(&self, now: Instant) -> f64 {
+        let elapsed = now.saturating_duration_since(self.since);
+        if elapsed >= self.speed {
+            return self.new_diameter;
+        }
+        let progress = elapsed.as_secs_f64() / self.speed.as_secs_f64();
+        self.old_diameter + (self.new_diameter - self.old_diameter) * progress
+    }
+
+    /// The current diameter
+    pub fn diameter(&self) -> f64 {
+        self.diameter_at(Instant::now())
+    }
+
+    /// Whether the border is currently moving
+    pub fn is_moving(&self) -> bool {
+        self.since.elapsed() < self.speed
+    }
+
+    /// The distance from the given position to the border, negative if the
+    /// position is outside it
+    pub fn distance(&self, x: f64, z: f64) -> f64 {
+        let radius = self.diameter() / 2.0;
+        let dx = radius - (x - self.center_x).abs();
+        let dz = radius - (z - self.center_z).abs();
+        dx.min(dz)
+    }
+
+    /// Whether the given position is inside the border
+    pub fn contains(&self, x: f64, z: f64) -> bool {
+        self.distance(x, z) >= 0.0
+    }
+
+    /* Apply the data of a WorldBorder packet received at the given moment */
+    fn apply(&mut self, data: &[u8], now: Instant) -> Result<()> {
+        let mut r = Cursor::new(data);
+        match read_varint(&mut r)? {
+            0 => {
+                self.new_diameter = read_f64(&mut r)?;
+                self.old_diameter = self.new_diameter;
+                self.speed = Duration::from_secs(0);
+            },
+            1 => {
+                self.old_diameter = read_f64(&mut r)?;
+                self.new_diameter = read_f64(&mut r)?;
+                self.speed = Duration::from_millis(read_varlong(&mut r)?.max(0) as u64);
+                self.since = now;
+            },
+            2 => {
+                self.center_x = read_f64(&mut r)?;
+                self.center_z = read_f64(&mut r)?;
+            },
+            3 => {
+                self.center_x = read_f64(&mut r)?;
+                self.center_z = read_f64(&mut r)?;
+                self.old_diameter = read_f64(&mut r)?;
+                self.new_diameter = read_f64(&mut r)?;
+                self.speed = Duration::from_millis(read_varlong(&mut r)?.max(0) as u64);
+                self.since = now;
+                self.portal_teleport_boundary = read_varint(&mut r)?;
+                self.warning_time = read_varint(&mut r)?;
+                self.warning_blocks = read_varint(&mut r)?;
+            },
+            4 => self.warning_time = read_varint(&mut r)?,
+            5 => self.warning_blocks = read_varint(&mut r)?,
+            action => bail!("Unknown world border action {}", action),
+        }
+        Ok(())
+    }
+}
+
+/// The time, weather and world border as last sent by the server
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct WorldState {
+    world_age: i64,
+    time_of_day: i64,
+    daylight_cycle: bool,
+    raining: bool,
+    rain_level: f32,
+    thunder_level: f32,
+    border: Option<WorldBorder>,
+}
+impl WorldState {
+    /// The age of the world in ticks
+    pub fn world_age(&self) -> i64 {
+        self.world_age
+    }
+
+    /// The time of day in ticks, see the module documentation
+    pub fn time_of_day(&self) -> i64 {
+        self.time_of_day
+    }
+
+    /// Whether the time of day advances, i.e. the doDaylightCycle gamerule
+    pub fn daylight_cycle(&self) -> bool {
+        self.daylight_cycle
+    }
+
+    /// Whether it's day, i.e. between sunrise and sunset
+    pub fn is_day(&self) -> bool {
+        self.time_of_day.rem_euclid(DAY_LENGTH) < DAY_LENGTH / 2
+    }
+
+    pub fn is_raining(&self) -> bool {
+        self.raining
+    }
+
+    /// Whether it's thundering, which requires rain
+    pub fn is_thundering(&self) -> bool {
+        self.raining && self.thunder_level > 0.9
+    }
+
+    /// How strong the rain is, from 0 to 1
+    pub fn rain_level(&self) -> f32 {
+        self.rain_level
+    }
+
+    /// How strong the thunder is, from 0 to 1
+    pub fn thunder_level(&self) -> f32 {
+        self.thunder_level
+    }
+
+    /// The world border, or None if the server hasn't sent it yet
+    pub fn world_border(&self) -> Option<&WorldBorder> {
+        self.border.as_ref()
+    }
+
+    /// Update the state from a packet, ignoring packets that aren't about
+    /// the time, weather or world border
+    pub fn handle(&mut self, packet: &ClientboundPacket) -> Result<()> {
+        self.handle_at(packet, Instant::now())
+    }
+
+    fn handle_at(&mut self, packet: &ClientboundPacket, now: Instant) -> Result<()> {
+        match *packet {
+            ClientboundPacket::TimeUpdate(ref p) => {
+                self.world_age = *p.get_world_age();
+                /* A negative time means the time doesn't advance */
+                self.time_of_day = p.get_time_of_day().abs();
+                self.daylight_cycle = *p.get_time_of_day() >= 0;
+            },
+            /* The IDs of "begin raining" and "end raining" are swapped in
+             * vanilla, so 1 is what starts the rain */
+            ClientboundPacket::ChangeGameState(ref p) => match *p.get_action() {
+                1 => {
+                    self.raining = true;
+                    self.rain_level = 0.0;
+                },
+                2 => {
+                    self.raining = false;
+                    self.rain_level = 1.0;
+                },
+                7 => self.rain_level = p.get_value().clamp(0.0, 1.0),
+                8 => self.thunder_level = p.get_value().clamp(0.0, 1.0),
+                _ => (),
+            },
+            ClientboundPacket::WorldBorder(ref p) => {
+                self.border
+                    .get_or_insert_with(WorldBorder::default)
+                    .apply(p.get_data(), now)?;
+            },
+            /* The world changed, so forget the weather and border */
+            ClientboundPacket::Respawn(..) => {
+                self.raining = false;
+                self.rain_level = 0.0;
+                self.thunder_level = 0.0;
+                self.border = None;
+            },
+            _ => (),
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use clientbound::{ChangeGameState, TimeUpdate, WorldBorder};
+    use write::{write_f64, write_varint, write_varlong};
+
+    #[test]
+    fn state() {
+        let mut world = WorldState::default();
+        world.handle(&TimeUpdate::new(100, -42000)).unwrap();
+        assert_eq!(world.world_age(), 100);
+        assert_eq!(world.time_of_day(), 42000);
+        assert!(!world.daylight_cycle());
+        assert!(!world.is_day());
+
+        world.handle(&ChangeGameState::new(1, 0.0)).unwrap();
+        world.handle(&ChangeGameState::new(7, 0.5)).unwrap();
+        world.handle(&ChangeGameState::new(8, 1.0)).unwrap();
+        assert!(world.is_raining());
+        assert_eq!(world.rain_level(), 0.5);
+        assert!(world.is_thundering());
+
+        let mut data = Vec::new();
+        write_varint(&3, &mut data).unwrap();
+        write_f64(&10.0, &mut data).unwrap();
+        write_f64(&-20.0, &mut data).unwrap();
+        write_f64(&100.0, &mut data).unwrap();
+        write_f64(&200.0, &mut data).unwrap();
+        write_varlong(&10000, &mut data).unwrap();
+        write_varint(&29999984, &mut data).unwrap();
+        write_varint(&15, &mut data).unwrap();
+        write_varint(&5, &mut data).unwrap();
+        let now = Instant::now();
+        let packet = WorldBorder::new(data);
+        world.handle_at(&packet, now).unwrap();
+        let border = world.world_border().unwrap();
+        assert_eq!((border.center_x, border.center_z), (10.0, -20.0));
+        assert_eq!(border.diameter_at(now + Duration::from_secs(5)), 150.0);
+        assert_eq!(border.diameter_at(now + Duration::from_secs(20)), 200.0);
+        assert!(border.contains(10.0, -20.0));
+        assert!(!border.contains(200.0, 0.0));
+
+        assert!(world.handle(&WorldBorder::new(vec![9])).is_err());
+    }
+}
@@ -127,6 +127,12 @@ pub fn write_String<W: Write>(val: &str, writer: &mut W) -> Result<()> {
     Ok(writer.write_all(string)?)
 }
 
+/// Write a SmallString to the Writer, ensuring to properly length-prefix it
+#[allow(non_snake_case)]
+pub fn write_SmallString<W: Write>(val: &str, writer: &mut W) -> Result<()> {
+    write_String(val, writer)
+}
+
 /// Write a length-prefixed bytearray, where the length is given as a varint
 pub fn write_prefixed_bytearray<W: Write>(val: &[u8],
                                           writer: &mut W)